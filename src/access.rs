@@ -1,9 +1,12 @@
+use std::cmp::Ordering;
+
 use indextree::NodeEdge as IndexTreeNodeEdge;
 
+use crate::doctype::Doctype;
 use crate::error::Error;
-use crate::levelorder::{level_order_traverse, LevelOrder};
+use crate::levelorder::{breadth_first_traverse, level_order_traverse, LevelOrder};
 use crate::nodemap::{category_predicate, Attributes, Namespaces};
-use crate::output::NamespaceDeclarations;
+use crate::output::{self, NamespaceDeclarations};
 use crate::xmlvalue::{Value, ValueCategory, ValueType};
 use crate::xotdata::{Node, Xot};
 use crate::{NameId, NamespaceId, PrefixId, Prefixes};
@@ -49,6 +52,39 @@ pub enum Axis {
     AncestorOrSelf,
 }
 
+impl Axis {
+    /// Whether this is a reverse axis.
+    ///
+    /// The reverse axes are [`Axis::Ancestor`], [`Axis::AncestorOrSelf`],
+    /// [`Axis::Preceding`] and [`Axis::PrecedingSibling`]. [`Xot::axis`]
+    /// gives their nodes in reverse document order, starting with the node
+    /// nearest to the context node. All other axes give their nodes in
+    /// document order.
+    ///
+    /// This is what positions count along in XPath, so `preceding::p[1]` is
+    /// the nearest preceding `p`.
+    ///
+    /// ```rust
+    /// use xot::{Axis, Xot};
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse("<p><a/><b/><c/></p>").unwrap();
+    /// let p = xot.document_element(root).unwrap();
+    /// let c = xot.last_child(p).unwrap();
+    ///
+    /// assert!(Axis::PrecedingSibling.is_reverse());
+    /// let b = xot.axis(Axis::PrecedingSibling, c).next().unwrap();
+    /// assert_eq!(xot.next_sibling(b), Some(c));
+    /// assert!(!Axis::FollowingSibling.is_reverse());
+    /// ```
+    pub fn is_reverse(self) -> bool {
+        matches!(
+            self,
+            Axis::Ancestor | Axis::AncestorOrSelf | Axis::Preceding | Axis::PrecedingSibling
+        )
+    }
+}
+
 /// Node edges.
 ///
 /// Used by [`Xot::traverse`] and [`Xot::reverse_traverse`].
@@ -199,6 +235,32 @@ impl Xot {
         Err(Error::NoElementAtTopLevel)
     }
 
+    /// The prolog of a document: the comments and processing instructions
+    /// before the document element, in document order.
+    ///
+    /// If this is not supplied with a document node, this produces a
+    /// [`Error::NotDocument`] error.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse("<?xml-stylesheet href=\"a.xsl\"?><!--a comment--><doc/><!--after-->")?;
+    /// let prolog = xot.prolog(root)?.collect::<Vec<_>>();
+    /// assert_eq!(prolog.len(), 2);
+    /// assert!(xot.is_processing_instruction(prolog[0]));
+    /// assert!(xot.is_comment(prolog[1]));
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn prolog(&self, node: Node) -> Result<impl Iterator<Item = Node> + '_, Error> {
+        if self.value_type(node) != ValueType::Document {
+            return Err(Error::NotDocument(node));
+        }
+        Ok(self
+            .children(node)
+            .take_while(|child| !self.is_element(*child)))
+    }
+
     /// Given a node indicating a document, check if it is a well-formed
     /// document.
     ///
@@ -231,7 +293,9 @@ impl Xot {
             match self.value(child) {
                 Value::Element(_) => element_count += 1,
                 // no text nodes at the top level
-                Value::Text(_) => return Err(Error::TextAtTopLevel(child)),
+                Value::Text(_) | Value::Cdata(_) | Value::EntityReference(_) => {
+                    return Err(Error::TextAtTopLevel(child))
+                }
                 Value::Comment(_) | Value::ProcessingInstruction(_) => {
                     // these we can have as many as we like
                 }
@@ -512,8 +576,129 @@ impl Xot {
         Some(Node::new(self.arena[node.get()].previous_sibling()?))
     }
 
+    /// Whether `ancestor` is an ancestor of `node`.
+    ///
+    /// A node isn't its own ancestor. The element a namespace or attribute
+    /// node belongs to is its ancestor.
+    ///
+    /// ```rust
+    /// let mut xot = xot::Xot::new();
+    /// let root = xot.parse("<a><b><c/></b></a>").unwrap();
+    /// let a = xot.document_element(root).unwrap();
+    /// let b = xot.first_child(a).unwrap();
+    /// let c = xot.first_child(b).unwrap();
+    ///
+    /// assert!(xot.is_ancestor(a, c));
+    /// assert!(xot.is_ancestor(root, b));
+    /// assert!(!xot.is_ancestor(c, a));
+    /// assert!(!xot.is_ancestor(b, b));
+    /// ```
+    pub fn is_ancestor(&self, ancestor: Node, node: Node) -> bool {
+        ancestor != node && self.ancestors(node).any(|n| n == ancestor)
+    }
+
+    /// The nearest node that both `a` and `b` are in.
+    ///
+    /// If one node is an ancestor of the other, that's the common ancestor,
+    /// and the common ancestor of a node and itself is that node. Returns
+    /// [`None`] if the nodes are in different trees.
+    ///
+    /// ```rust
+    /// let mut xot = xot::Xot::new();
+    /// let root = xot.parse("<a><b><c/><d/></b><e/></a>").unwrap();
+    /// let a = xot.document_element(root).unwrap();
+    /// let b = xot.first_child(a).unwrap();
+    /// let c = xot.first_child(b).unwrap();
+    /// let d = xot.next_sibling(c).unwrap();
+    /// let e = xot.next_sibling(b).unwrap();
+    ///
+    /// assert_eq!(xot.common_ancestor(c, d), Some(b));
+    /// assert_eq!(xot.common_ancestor(c, e), Some(a));
+    /// assert_eq!(xot.common_ancestor(b, d), Some(b));
+    ///
+    /// let other = xot.new_element(xot.name("a").unwrap());
+    /// assert_eq!(xot.common_ancestor(c, other), None);
+    /// ```
+    pub fn common_ancestor(&self, a: Node, b: Node) -> Option<Node> {
+        let a_ancestors = self.ancestors(a).collect::<Vec<_>>();
+        let b_ancestors = self.ancestors(b).collect::<Vec<_>>();
+        // walk down from the root for as long as the ancestors are the same
+        a_ancestors
+            .iter()
+            .rev()
+            .zip(b_ancestors.iter().rev())
+            .take_while(|(a, b)| a == b)
+            .last()
+            .map(|(a, _)| *a)
+    }
+
+    /// Compare the position of two nodes in document order.
+    ///
+    /// A node comes before its descendants, and namespace and attribute
+    /// nodes come after the element they belong to and before its children.
+    ///
+    /// Returns [`Error::DifferentTrees`] if the nodes aren't in the same
+    /// tree.
+    ///
+    /// ```rust
+    /// use std::cmp::Ordering;
+    ///
+    /// let mut xot = xot::Xot::new();
+    /// let root = xot.parse("<a><b><c/></b><d/></a>").unwrap();
+    /// let a = xot.document_element(root).unwrap();
+    /// let b = xot.first_child(a).unwrap();
+    /// let c = xot.first_child(b).unwrap();
+    /// let d = xot.next_sibling(b).unwrap();
+    ///
+    /// assert_eq!(xot.compare_document_order(c, d)?, Ordering::Less);
+    /// assert_eq!(xot.compare_document_order(c, a)?, Ordering::Greater);
+    ///
+    /// // sort nodes collected from several places, and remove duplicates
+    /// let mut nodes = vec![d, c, a, d];
+    /// nodes.sort_by(|x, y| xot.compare_document_order(*x, *y).unwrap());
+    /// nodes.dedup();
+    /// assert_eq!(nodes, vec![a, c, d]);
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn compare_document_order(&self, a: Node, b: Node) -> Result<Ordering, Error> {
+        if a == b {
+            return Ok(Ordering::Equal);
+        }
+        let a_ancestors = self.ancestors(a).collect::<Vec<_>>();
+        let b_ancestors = self.ancestors(b).collect::<Vec<_>>();
+        if a_ancestors.last() != b_ancestors.last() {
+            return Err(Error::DifferentTrees(a, b));
+        }
+        // the number of ancestors the nodes have in common, from the root
+        let common = a_ancestors
+            .iter()
+            .rev()
+            .zip(b_ancestors.iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        if common == a_ancestors.len() {
+            return Ok(Ordering::Less);
+        }
+        if common == b_ancestors.len() {
+            return Ok(Ordering::Greater);
+        }
+        // these are siblings in the arena, which is in document order
+        let a_sibling = a_ancestors[a_ancestors.len() - common - 1];
+        let b_sibling = b_ancestors[b_ancestors.len() - common - 1];
+        let mut current = a_sibling;
+        while let Some(next) = self.internal_next_sibling(current) {
+            if next == b_sibling {
+                return Ok(Ordering::Less);
+            }
+            current = next;
+        }
+        Ok(Ordering::Greater)
+    }
+
     /// Iterator over ancestor nodes, including this one.
     ///
+    /// This is the XPath `ancestor-or-self` axis, [`Axis::AncestorOrSelf`].
+    ///
     /// Namespace and attribute node have ancestors, even though
     /// they aren't the child of the element they are in.
     ///
@@ -551,6 +736,67 @@ impl Xot {
         self.normal_children(node)
     }
 
+    /// Iterator over the child elements of this node.
+    ///
+    /// ```rust
+    /// let mut xot = xot::Xot::new();
+    /// let root = xot.parse("<p><a/>text<!--comment--><b/></p>").unwrap();
+    /// let p = xot.document_element(root).unwrap();
+    /// let a = xot.first_child(p).unwrap();
+    /// let b = xot.last_child(p).unwrap();
+    ///
+    /// let elements = xot.child_elements(p).collect::<Vec<_>>();
+    /// assert_eq!(elements, vec![a, b]);
+    /// assert_eq!(xot.child_element_count(p), 2);
+    /// ```
+    pub fn child_elements(&self, node: Node) -> impl Iterator<Item = Node> + '_ {
+        self.children(node).filter(|node| self.is_element(*node))
+    }
+
+    /// The number of child elements of this node.
+    pub fn child_element_count(&self, node: Node) -> usize {
+        self.child_elements(node).count()
+    }
+
+    /// Iterator over the text of the child text nodes of this node.
+    ///
+    /// This only includes text nodes, like [`Xot::text_str`], so not text
+    /// inside child elements. Use [`Xot::text_content_str`] to get the text
+    /// of an element that only contains text.
+    ///
+    /// ```rust
+    /// let mut xot = xot::Xot::new();
+    /// let root = xot.parse("<p>one<b>two</b>three</p>").unwrap();
+    /// let p = xot.document_element(root).unwrap();
+    ///
+    /// let texts = xot.child_texts(p).collect::<Vec<_>>();
+    /// assert_eq!(texts, vec!["one", "three"]);
+    /// ```
+    pub fn child_texts(&self, node: Node) -> impl Iterator<Item = &str> + '_ {
+        self.children(node).filter_map(|node| self.text_str(node))
+    }
+
+    /// Iterator over the child elements of this node with the given name.
+    ///
+    /// To look up the name by its local name and namespace, use
+    /// [`Xot::name_ns`]. If that returns [`None`], no element can have that
+    /// name.
+    ///
+    /// ```rust
+    /// let mut xot = xot::Xot::new();
+    /// let root = xot.parse("<p><item/><other/><item/></p>").unwrap();
+    /// let p = xot.document_element(root).unwrap();
+    /// let item = xot.name("item").unwrap();
+    /// let first = xot.first_child(p).unwrap();
+    /// let last = xot.last_child(p).unwrap();
+    ///
+    /// let items = xot.children_named(p, item).collect::<Vec<_>>();
+    /// assert_eq!(items, vec![first, last]);
+    /// ```
+    pub fn children_named(&self, node: Node, name: NameId) -> impl Iterator<Item = Node> + '_ {
+        self.children(node).filter(self.element_named_filter(name))
+    }
+
     /// Get index of child.
     ///
     /// Returns [`None`] if the node is not a child of this node, so
@@ -626,6 +872,74 @@ impl Xot {
             .map(Node::new)
     }
 
+    /// Iterator over the descendant elements of this node with the given
+    /// name, in document order.
+    ///
+    /// Unlike [`Xot::descendants`], this doesn't include this node itself.
+    /// To look up the name by its local name and namespace, use
+    /// [`Xot::name_ns`].
+    ///
+    /// ```rust
+    /// let mut xot = xot::Xot::new();
+    /// let root = xot.parse("<item><a><item/></a><item><item/></item></item>").unwrap();
+    /// let outer = xot.document_element(root).unwrap();
+    /// let item = xot.name("item").unwrap();
+    ///
+    /// let items = xot.descendants_named(outer, item).collect::<Vec<_>>();
+    /// assert_eq!(items.len(), 3);
+    /// assert!(!items.contains(&outer));
+    /// ```
+    pub fn descendants_named(&self, node: Node, name: NameId) -> impl Iterator<Item = Node> + '_ {
+        self.descendants(node)
+            .skip(1)
+            .filter(self.element_named_filter(name))
+    }
+
+    fn element_named_filter(&self, name: NameId) -> impl Fn(&Node) -> bool + '_ {
+        move |node| match self.arena[node.get()].get() {
+            Value::Element(element) => element.name() == name,
+            _ => false,
+        }
+    }
+
+    /// Iterator over the descendants of this node, including this one, in
+    /// reverse document order.
+    ///
+    /// This starts with the last descendant and ends with this node, so it
+    /// gives the same nodes as [`Xot::descendants`] in reverse. It doesn't
+    /// allocate, so it's an efficient way to find the last descendant that
+    /// matches something.
+    ///
+    /// Namespace and attribute nodes aren't included.
+    ///
+    /// ```rust
+    /// let mut xot = xot::Xot::new();
+    /// let root = xot.parse("<a><b><c/></b><d/></a>").unwrap();
+    /// let a = xot.document_element(root).unwrap();
+    /// let b = xot.first_child(a).unwrap();
+    /// let c = xot.first_child(b).unwrap();
+    /// let d = xot.next_sibling(b).unwrap();
+    ///
+    /// let descendants = xot.reverse_descendants(a).collect::<Vec<_>>();
+    /// assert_eq!(descendants, vec![d, c, b, a]);
+    /// ```
+    pub fn reverse_descendants(&self, node: Node) -> impl Iterator<Item = Node> + '_ {
+        let mut last = node;
+        while let Some(last_child) = self.internal_last_child(last) {
+            last = last_child;
+        }
+        // reverse preorder from the last descendant reaches this node after
+        // all its descendants
+        let mut done = false;
+        ReversePreorder::new(self, last, |_| true)
+            .take_while(move |current| {
+                let take = !done;
+                done = *current == node;
+                take
+            })
+            .filter(|node| self.arena[node.get()].get().is_normal())
+    }
+
     /// All the descendants of this node.
     ///
     /// This includes this one, and namespace and attribute nodes,
@@ -723,10 +1037,12 @@ impl Xot {
         Following::new(self, node, |_| true)
     }
 
-    /// Preceding nodes in document order
+    /// Preceding nodes in reverse document order
     ///
     /// These are nodes that come before this node in document order,
-    /// without that node itself, its ancestors, or its descendants.
+    /// without that node itself or its ancestors. They're given in reverse
+    /// document order, starting with the nearest node, like the XPath
+    /// `preceding` axis.
     ///
     /// Does not include namespace or attribute nodes.
     ///
@@ -748,23 +1064,15 @@ impl Xot {
     /// assert_eq!(siblings, vec![g, e, d, c, b, a]);
     /// ```
     pub fn preceding(&self, node: Node) -> impl Iterator<Item = Node> + '_ {
-        // start with an empty iterator
-        let mut joined_iterator: Box<dyn Iterator<Item = Node>> = Box::new(std::iter::empty());
-        let mut current_parent = Some(node);
-        while let Some(parent) = current_parent {
-            let mut current_sibling = parent;
-            while let Some(current) = self.previous_sibling(current_sibling) {
-                // add descendants of previous sibling, reversed
-                // this unfortunately requires an extra allocation, as descendants
-                // is not a double iterator.
-                let descendants = Box::new(self.descendants(current).collect::<Vec<_>>());
-                let reverse_descendants = descendants.into_iter().rev();
-                joined_iterator = Box::new(joined_iterator.chain(Box::new(reverse_descendants)));
-                current_sibling = current;
-            }
-            current_parent = self.parent(parent);
-        }
-        joined_iterator
+        Preceding::new(self, node, self.normal_node_filter())
+    }
+
+    /// Preceding nodes in reverse document order.
+    ///
+    /// Like [`Xot::preceding`] but includes namespace and
+    /// attribute nodes too.
+    pub fn all_preceding(&self, node: Node) -> impl Iterator<Item = Node> + '_ {
+        Preceding::new(self, node, |_| true)
     }
 
     /// Traverse over node edges.
@@ -887,6 +1195,33 @@ impl Xot {
         level_order_traverse(self, node)
     }
 
+    /// Breadth-first traversal.
+    ///
+    /// This gives this node and its descendants in level order: first the
+    /// node itself, then its children, then its grandchildren, and so on.
+    /// Each node comes with its depth, relative to this node, which has
+    /// depth 0.
+    ///
+    /// Unlike [`Xot::level_order`], this doesn't mark where a sequence of
+    /// siblings ends.
+    ///
+    /// Namespace and attribute nodes aren't included.
+    ///
+    /// ```rust
+    /// let mut xot = xot::Xot::new();
+    /// let root = xot.parse("<a><b><d/></b><c/></a>").unwrap();
+    /// let a = xot.document_element(root).unwrap();
+    /// let b = xot.first_child(a).unwrap();
+    /// let c = xot.next_sibling(b).unwrap();
+    /// let d = xot.first_child(b).unwrap();
+    ///
+    /// let nodes = xot.breadth_first(a).collect::<Vec<_>>();
+    /// assert_eq!(nodes, vec![(a, 0), (b, 1), (c, 1), (d, 2)]);
+    /// ```
+    pub fn breadth_first(&self, node: Node) -> impl Iterator<Item = (Node, usize)> + '_ {
+        breadth_first_traverse(self, node)
+    }
+
     /// Axis-based traversal.
     ///
     /// Use an [`crate::Axis`] to traverse the tree in a way defined by
@@ -947,10 +1282,170 @@ impl Xot {
     /// given value.
     ///
     /// If that id does not exist, returns [`None`].
+    ///
+    /// This only knows the ids the document had when it was parsed. Use
+    /// [`Xot::element_by_id`] for ids that follow changes to the document.
     pub fn xml_id_node(&self, document_node: Node, value: &str) -> Option<Node> {
         let value_nodes = self.id_nodes_map.get(&document_node.get())?;
         value_nodes.get(value).map(|node_id| Node::new(*node_id))
     }
+
+    /// Get the name of the encoding a document node was decoded from.
+    ///
+    /// This is only known for documents parsed from bytes, such as with
+    /// [`Xot::parse_bytes`], [`Xot::parse_read`] or `Xot::parse_html_bytes`. The name is the canonical name of the
+    /// encoding as defined by the [Encoding
+    /// Standard](https://encoding.spec.whatwg.org/), such as `UTF-8` or
+    /// `windows-1252`.
+    ///
+    /// If the encoding is not known, returns [`None`].
+    pub fn encoding(&self, document_node: Node) -> Option<&'static str> {
+        self.encodings
+            .get(&document_node.get())
+            .map(|encoding| encoding.name())
+    }
+
+    /// Whether a document node was parsed from data that starts with a byte
+    /// order mark.
+    ///
+    /// The byte order mark isn't part of the tree, so you can use this to
+    /// write one again when you serialize the document.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let document = xot.parse_bytes(b"\xEF\xBB\xBF<doc/>")?;
+    /// assert!(xot.has_bom(document));
+    /// let document = xot.parse("<doc/>")?;
+    /// assert!(!xot.has_bom(document));
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn has_bom(&self, document_node: Node) -> bool {
+        self.boms.contains(&document_node.get())
+    }
+
+    /// The positions of the invalid characters that were replaced when a
+    /// document node was parsed.
+    ///
+    /// These are the byte positions in the source text, in order. When you
+    /// parse bytes, they're positions in the decoded text, like
+    /// [`Span`](crate::Span)s are. Characters are only replaced if you parse
+    /// with
+    /// [`ParseOptions::replace_invalid_characters`](crate::ParseOptions::replace_invalid_characters).
+    ///
+    /// ```rust
+    /// use xot::{ParseOptions, Xot};
+    ///
+    /// let mut xot = Xot::new();
+    /// let options = ParseOptions {
+    ///     replace_invalid_characters: true,
+    ///     ..Default::default()
+    /// };
+    /// let document = xot.parse_with_options("<doc>a\u{1}b</doc>", &options)?;
+    /// assert_eq!(xot.replaced_characters(document), &[6]);
+    /// let doc_el = xot.document_element(document)?;
+    /// assert_eq!(xot.text_content_str(doc_el), Some("a\u{FFFD}b"));
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn replaced_characters(&self, document_node: Node) -> &[usize] {
+        self.replaced_characters
+            .get(&document_node.get())
+            .map(|positions| positions.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Get the doctype of a document node.
+    ///
+    /// This is set when parsing XML or HTML with a `<!DOCTYPE>`, or with
+    /// [`Xot::set_doctype`]. For XML, this includes the entities and
+    /// notations declared in the internal subset.
+    ///
+    /// ```rust
+    /// use xot::{EntityDefinition, Xot};
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<!DOCTYPE doc [<!ENTITY company "ACME">]><doc/>"#)?;
+    /// let doctype = xot.doctype(root).unwrap();
+    /// assert_eq!(doctype.name, "doc");
+    /// assert_eq!(doctype.entities[0].name, "company");
+    /// assert_eq!(
+    ///     doctype.entities[0].definition,
+    ///     EntityDefinition::Internal("ACME".to_string())
+    /// );
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    ///
+    /// If there is no doctype, returns [`None`].
+    pub fn doctype(&self, document_node: Node) -> Option<&Doctype> {
+        self.doctypes.get(&document_node.get())
+    }
+
+    /// Get the XML declaration a document node was parsed with.
+    ///
+    /// Serialization doesn't write this declaration by itself; what you pass
+    /// in [`output::xml::Parameters::declaration`]
+    /// is written. You can pass this declaration to keep it, or change it
+    /// first.
+    ///
+    /// ```rust
+    /// use xot::{Xot, output};
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><doc/>"#)?;
+    /// let declaration = xot.xml_declaration(root).unwrap();
+    /// assert_eq!(declaration.encoding.as_deref(), Some("UTF-8"));
+    /// assert_eq!(declaration.standalone, Some(true));
+    ///
+    /// let xml = xot.serialize_xml_string(output::xml::Parameters {
+    ///     declaration: Some(output::xml::Declaration {
+    ///         standalone: None,
+    ///         ..declaration.clone()
+    ///     }),
+    ///     ..Default::default()
+    /// }, root)?;
+    /// assert_eq!(xml, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<doc/>");
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    ///
+    /// If the document had no XML declaration, returns [`None`].
+    pub fn xml_declaration(&self, document_node: Node) -> Option<&output::xml::Declaration> {
+        self.declarations.get(&document_node.get())
+    }
+
+    /// Whether an attribute of an element was added because the doctype
+    /// declares a default for it.
+    ///
+    /// This only happens when you parse with
+    /// [`ParseOptions::apply_attribute_defaults`](crate::ParseOptions::apply_attribute_defaults).
+    /// If you set the attribute, it's no longer defaulted. Returns `false` if
+    /// the element doesn't have the attribute.
+    ///
+    /// ```rust
+    /// use xot::{ParseOptions, Xot};
+    ///
+    /// let mut xot = Xot::new();
+    /// let options = ParseOptions {
+    ///     apply_attribute_defaults: true,
+    ///     ..Default::default()
+    /// };
+    /// let root = xot.parse_with_options(
+    ///     r#"<!DOCTYPE doc [<!ATTLIST doc a CDATA "x" b CDATA "y">]><doc b="z"/>"#,
+    ///     &options,
+    /// )?;
+    /// let doc = xot.document_element(root)?;
+    /// let a = xot.add_name("a");
+    /// let b = xot.add_name("b");
+    /// assert_eq!(xot.get_attribute(doc, a), Some("x"));
+    /// assert!(xot.is_defaulted_attribute(doc, a));
+    /// assert!(!xot.is_defaulted_attribute(doc, b));
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn is_defaulted_attribute(&self, element: Node, name: NameId) -> bool {
+        self.attributes(element)
+            .get_node(name)
+            .is_some_and(|attribute| self.defaulted_attributes.contains(&attribute.get()))
+    }
 }
 
 struct ReversePreorder<'a, F: Fn(Node) -> bool> {
@@ -997,6 +1492,43 @@ impl<F: Fn(Node) -> bool> Iterator for ReversePreorder<'_, F> {
     }
 }
 
+// reverse preorder, skipping the node itself and its ancestors
+struct Preceding<'a, F> {
+    nodes: ReversePreorder<'a, fn(Node) -> bool>,
+    ancestor: Option<Node>,
+    filter: F,
+}
+
+impl<'a, F: Fn(Node) -> bool> Preceding<'a, F> {
+    fn new(xot: &'a Xot, current: Node, filter: F) -> Self {
+        let mut nodes = ReversePreorder::new(xot, current, (|_| true) as fn(Node) -> bool);
+        nodes.next();
+        Self {
+            nodes,
+            ancestor: xot.parent(current),
+            filter,
+        }
+    }
+}
+
+impl<F: Fn(Node) -> bool> Iterator for Preceding<'_, F> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.nodes.next()?;
+            // reverse preorder reaches the ancestors as it goes up
+            if Some(node) == self.ancestor {
+                self.ancestor = self.nodes.xot.parent(node);
+                continue;
+            }
+            if (self.filter)(node) {
+                return Some(node);
+            }
+        }
+    }
+}
+
 struct Following<'a, F> {
     xot: &'a Xot,
     current: Option<Node>,
@@ -0,0 +1,111 @@
+use crate::error::Error;
+use crate::output::{
+    gen_outputs, NoopNormalizer, Output, OutputToken, Pretty, TokenSerializeParameters,
+    XmlSerializer,
+};
+use crate::xotdata::{Node, Xot};
+
+const RESET: &str = "\x1b[0m";
+const TAG: &str = "\x1b[34m";
+const NAME: &str = "\x1b[36m";
+const VALUE: &str = "\x1b[32m";
+const TEXT: &str = "\x1b[33m";
+const COMMENT: &str = "\x1b[90m";
+
+fn push_colored(out: &mut String, color: &str, text: &str) {
+    out.push_str(color);
+    out.push_str(text);
+    out.push_str(RESET);
+}
+
+// attributes and namespace declarations look like `name="value"`, and a
+// name can't contain `=`
+fn push_attribute(out: &mut String, text: &str) {
+    match text.split_once('=') {
+        Some((name, value)) => {
+            push_colored(out, NAME, name);
+            out.push('=');
+            push_colored(out, VALUE, value);
+        }
+        None => push_colored(out, NAME, text),
+    }
+}
+
+fn push_token(out: &mut String, output: &Output, token: &OutputToken) {
+    if token.text.is_empty() {
+        return;
+    }
+    if token.space {
+        out.push(' ');
+    }
+    match output {
+        Output::StartTagOpen(_) | Output::StartTagClose | Output::EndTag(_) => {
+            push_colored(out, TAG, &token.text)
+        }
+        Output::Prefix(..) | Output::Attribute(..) => push_attribute(out, &token.text),
+        Output::Text(_) | Output::Cdata(_) | Output::EntityReference(_) => {
+            push_colored(out, TEXT, &token.text)
+        }
+        Output::Comment(_) | Output::ProcessingInstruction(..) => {
+            push_colored(out, COMMENT, &token.text)
+        }
+    }
+}
+
+pub(crate) fn colored_string(xot: &Xot, node: Node) -> Result<String, Error> {
+    let mut serializer = XmlSerializer::new(
+        xot,
+        node,
+        TokenSerializeParameters::default(),
+        NoopNormalizer,
+    );
+    let mut pretty = Pretty::new(xot, |_| false, |_| false, None);
+    let mut out = String::new();
+    let mut newline = false;
+    for (node, output) in gen_outputs(xot, node) {
+        let (indentation, next_newline) = pretty.prettify(node, &output);
+        if newline {
+            out.push('\n');
+        }
+        out.push_str(&"  ".repeat(indentation));
+        let token = serializer.render_output(node, &output)?;
+        push_token(&mut out, &output, &token);
+        newline = next_newline;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colored_string() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<doc xmlns:x="urn:x"><a x:b="B">text &amp; more</a><!--c--></doc>"#)
+            .unwrap();
+        assert_eq!(
+            colored_string(&xot, root).unwrap(),
+            concat!(
+                "\x1b[34m<doc\x1b[0m \x1b[36mxmlns:x\x1b[0m=\x1b[32m\"urn:x\"\x1b[0m\x1b[34m>\x1b[0m\n",
+                "  \x1b[34m<a\x1b[0m \x1b[36mx:b\x1b[0m=\x1b[32m\"B\"\x1b[0m\x1b[34m>\x1b[0m",
+                "\x1b[33mtext &amp; more\x1b[0m\x1b[34m</a>\x1b[0m\n",
+                "  \x1b[90m<!--c-->\x1b[0m\n",
+                "\x1b[34m</doc>\x1b[0m"
+            )
+        );
+    }
+
+    #[test]
+    fn test_colored_string_missing_prefix() {
+        let mut xot = Xot::new();
+        let ns = xot.add_namespace("urn:x");
+        let name = xot.add_name_ns("a", ns);
+        let a = xot.new_element(name);
+        assert!(matches!(
+            colored_string(&xot, a),
+            Err(Error::MissingPrefix(_))
+        ));
+    }
+}
@@ -1,6 +1,8 @@
 use crate::error::Error;
 use crate::id::NameId;
-use crate::xmlvalue::{Attribute, Comment, Element, Namespace, ProcessingInstruction, Text, Value};
+use crate::xmlvalue::{
+    Attribute, Comment, Element, EntityReference, Namespace, ProcessingInstruction, Text, Value,
+};
 use crate::xotdata::{Node, Xot};
 use crate::{NamespaceId, PrefixId};
 
@@ -164,6 +166,47 @@ impl Xot {
         self.new_node(text)
     }
 
+    /// Create a new, unattached CDATA section.
+    ///
+    /// Unlike text nodes, CDATA sections aren't consolidated with adjacent
+    /// text.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<doc/>"#)?;
+    /// let doc_el = xot.document_element(root)?;
+    /// let cdata = xot.new_cdata("a < b");
+    /// xot.append(doc_el, cdata)?;
+    /// assert_eq!(xot.to_string(root)?, "<doc><![CDATA[a < b]]></doc>");
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn new_cdata(&mut self, text: &str) -> Node {
+        let cdata = Value::Cdata(Text::new(text.to_string()));
+        self.new_node(cdata)
+    }
+
+    /// Create a new, unattached entity reference.
+    ///
+    /// `name` is the name of the entity, without `&` and `;`.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<doc/>"#)?;
+    /// let doc_el = xot.document_element(root)?;
+    /// let entity_reference = xot.new_entity_reference("chapter1");
+    /// xot.append(doc_el, entity_reference)?;
+    /// assert_eq!(xot.to_string(root)?, "<doc>&chapter1;</doc>");
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn new_entity_reference(&mut self, name: &str) -> Node {
+        let entity_reference = Value::EntityReference(EntityReference::new(name.to_string()));
+        self.new_node(entity_reference)
+    }
+
     /// Create a new, unattached comment node given comment text.
     ///
     /// ```rust
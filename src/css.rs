@@ -0,0 +1,848 @@
+//! CSS selectors.
+//!
+//! This module implements matching of nodes against [CSS
+//! selectors](https://www.w3.org/TR/selectors-4/). You usually use it
+//! through [`Xot::select`] and [`Xot::select_first`], but you can also
+//! parse a [`Selector`] once and use it many times.
+//!
+//! The following is supported:
+//!
+//! * Type selectors (`p`) and the universal selector (`*`). These match
+//!   elements by local name in any namespace. For elements in the XHTML
+//!   namespace the match is ASCII case-insensitive.
+//!
+//! * ID selectors (`#main`) and class selectors (`.article`), which look at
+//!   the `id` and `class` attributes.
+//!
+//! * Attribute selectors: `[attr]`, `[attr=value]`, `[attr~=value]`,
+//!   `[attr|=value]`, `[attr^=value]`, `[attr$=value]` and `[attr*=value]`,
+//!   with an optional `i` or `s` flag for case sensitivity. These only match
+//!   attributes without a namespace.
+//!
+//! * The combinators: descendant (whitespace), child (`>`), next sibling
+//!   (`+`) and subsequent sibling (`~`).
+//!
+//! * Selector lists (`h1, h2`).
+//!
+//! * The pseudo-classes `:root`, `:empty`, `:first-child`, `:last-child`,
+//!   `:only-child`, `:first-of-type`, `:last-of-type`, `:only-of-type`,
+//!   `:nth-child()`, `:nth-last-child()`, `:nth-of-type()`,
+//!   `:nth-last-of-type()`, `:not()` and `:is()`.
+//!
+//! Pseudo-elements and pseudo-classes which depend on user interaction or
+//! rendering (such as `:hover`) are not supported.
+
+use std::num::IntErrorKind;
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::xotdata::{Node, Xot};
+
+const XHTML_NS: &str = "http://www.w3.org/1999/xhtml";
+
+/// A parsed CSS selector (list).
+///
+/// ```rust
+/// use xot::Xot;
+/// use xot::css::Selector;
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse(r#"<div><p class="intro">A</p><p>B</p></div>"#)?;
+/// let selector = Selector::parse("p.intro")?;
+/// let matching = xot
+///     .descendants(root)
+///     .filter(|node| selector.matches(&xot, *node))
+///     .collect::<Vec<_>>();
+/// assert_eq!(matching.len(), 1);
+/// # Ok::<(), xot::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    complex_selectors: Vec<ComplexSelector>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ComplexSelector {
+    // the first compound has no combinator
+    compounds: Vec<(Combinator, Compound)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Combinator {
+    Descendant,
+    Child,
+    NextSibling,
+    SubsequentSibling,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct Compound {
+    simple_selectors: Vec<SimpleSelector>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SimpleSelector {
+    Type(String),
+    Universal,
+    Id(String),
+    Class(String),
+    Attribute {
+        name: String,
+        matcher: Option<(AttributeOperator, String, bool)>,
+    },
+    Root,
+    Empty,
+    NthChild(Nth),
+    NthLastChild(Nth),
+    NthOfType(Nth),
+    NthLastOfType(Nth),
+    Not(Selector),
+    Is(Selector),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AttributeOperator {
+    Equals,
+    Includes,
+    DashMatch,
+    Prefix,
+    Suffix,
+    Substring,
+}
+
+// an + b
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Nth {
+    a: i64,
+    b: i64,
+}
+
+impl Nth {
+    const FIRST: Nth = Nth { a: 0, b: 1 };
+
+    fn matches(&self, position: i64) -> bool {
+        if self.a == 0 {
+            position == self.b
+        } else {
+            // `a` and `b` can be as large as an `i64` goes, so this can't
+            // overflow in an `i128`
+            let a = i128::from(self.a);
+            let n = i128::from(position) - i128::from(self.b);
+            n % a == 0 && n / a >= 0
+        }
+    }
+}
+
+// Parse an integer in an an+b expression. Like browsers, we clamp an integer
+// that's out of range rather than reject it.
+fn nth_integer(text: &str) -> Option<i64> {
+    match text.parse::<i64>() {
+        Ok(value) => Some(value),
+        Err(error) => match error.kind() {
+            IntErrorKind::PosOverflow => Some(i64::MAX),
+            IntErrorKind::NegOverflow => Some(i64::MIN),
+            _ => None,
+        },
+    }
+}
+
+impl Selector {
+    /// Parse a CSS selector list.
+    ///
+    /// Returns [`Error::InvalidSelector`] if the selector cannot be parsed
+    /// or uses unsupported features.
+    pub fn parse(selector: &str) -> Result<Self, Error> {
+        let mut parser = Parser {
+            input: selector,
+            pos: 0,
+        };
+        let selector = parser.selector_list()?;
+        parser.skip_whitespace();
+        if let Some(c) = parser.peek() {
+            return Err(parser.error(&format!("unexpected character '{}'", c)));
+        }
+        Ok(selector)
+    }
+
+    /// Does this selector match the node?
+    ///
+    /// Only element nodes can match.
+    pub fn matches(&self, xot: &Xot, node: Node) -> bool {
+        xot.is_element(node)
+            && self
+                .complex_selectors
+                .iter()
+                .any(|complex| complex.matches(xot, node, complex.compounds.len() - 1))
+    }
+}
+
+impl FromStr for Selector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Selector::parse(s)
+    }
+}
+
+impl ComplexSelector {
+    fn matches(&self, xot: &Xot, node: Node, index: usize) -> bool {
+        let (combinator, compound) = &self.compounds[index];
+        if !compound.matches(xot, node) {
+            return false;
+        }
+        if index == 0 {
+            return true;
+        }
+        match combinator {
+            Combinator::Descendant => {
+                let mut current = parent_element(xot, node);
+                while let Some(ancestor) = current {
+                    if self.matches(xot, ancestor, index - 1) {
+                        return true;
+                    }
+                    current = parent_element(xot, ancestor);
+                }
+                false
+            }
+            Combinator::Child => {
+                parent_element(xot, node).is_some_and(|parent| self.matches(xot, parent, index - 1))
+            }
+            Combinator::NextSibling => previous_element_siblings(xot, node)
+                .next()
+                .is_some_and(|sibling| self.matches(xot, sibling, index - 1)),
+            Combinator::SubsequentSibling => previous_element_siblings(xot, node)
+                .any(|sibling| self.matches(xot, sibling, index - 1)),
+        }
+    }
+}
+
+impl Compound {
+    fn matches(&self, xot: &Xot, node: Node) -> bool {
+        self.simple_selectors
+            .iter()
+            .all(|simple| simple.matches(xot, node))
+    }
+}
+
+impl SimpleSelector {
+    fn matches(&self, xot: &Xot, node: Node) -> bool {
+        match self {
+            SimpleSelector::Type(name) => {
+                let element_name = xot.element(node).unwrap().name();
+                let local_name = xot.local_name_str(element_name);
+                if is_html(xot, node) {
+                    local_name.eq_ignore_ascii_case(name)
+                } else {
+                    local_name == name
+                }
+            }
+            SimpleSelector::Universal => true,
+            SimpleSelector::Id(id) => attribute_value(xot, node, "id") == Some(id),
+            SimpleSelector::Class(class) => attribute_value(xot, node, "class")
+                .is_some_and(|value| value.split_ascii_whitespace().any(|c| c == class)),
+            SimpleSelector::Attribute { name, matcher } => {
+                let Some(value) = attribute_value(xot, node, name) else {
+                    return false;
+                };
+                let Some((operator, expected, case_insensitive)) = matcher else {
+                    return true;
+                };
+                let (value, expected) = if *case_insensitive {
+                    (value.to_lowercase(), expected.to_lowercase())
+                } else {
+                    (value.to_string(), expected.clone())
+                };
+                match operator {
+                    AttributeOperator::Equals => value == expected,
+                    AttributeOperator::Includes => {
+                        value.split_ascii_whitespace().any(|v| v == expected)
+                    }
+                    AttributeOperator::DashMatch => {
+                        value == expected
+                            || value
+                                .strip_prefix(&expected)
+                                .is_some_and(|rest| rest.starts_with('-'))
+                    }
+                    AttributeOperator::Prefix => {
+                        !expected.is_empty() && value.starts_with(&expected)
+                    }
+                    AttributeOperator::Suffix => !expected.is_empty() && value.ends_with(&expected),
+                    AttributeOperator::Substring => {
+                        !expected.is_empty() && value.contains(&expected)
+                    }
+                }
+            }
+            SimpleSelector::Root => xot.parent(node).is_some_and(|p| xot.is_document(p)),
+            SimpleSelector::Empty => xot.children(node).all(|child| {
+                !(xot.is_element(child)
+                    || xot.is_text(child)
+                    || xot.is_cdata(child)
+                    || xot.is_entity_reference(child))
+            }),
+            SimpleSelector::NthChild(nth) => {
+                nth.matches(previous_element_siblings(xot, node).count() as i64 + 1)
+            }
+            SimpleSelector::NthLastChild(nth) => {
+                nth.matches(next_element_siblings(xot, node).count() as i64 + 1)
+            }
+            SimpleSelector::NthOfType(nth) => {
+                let name = xot.element(node).unwrap().name();
+                nth.matches(
+                    previous_element_siblings(xot, node)
+                        .filter(|sibling| xot.element(*sibling).unwrap().name() == name)
+                        .count() as i64
+                        + 1,
+                )
+            }
+            SimpleSelector::NthLastOfType(nth) => {
+                let name = xot.element(node).unwrap().name();
+                nth.matches(
+                    next_element_siblings(xot, node)
+                        .filter(|sibling| xot.element(*sibling).unwrap().name() == name)
+                        .count() as i64
+                        + 1,
+                )
+            }
+            SimpleSelector::Not(selector) => !selector.matches(xot, node),
+            SimpleSelector::Is(selector) => selector.matches(xot, node),
+        }
+    }
+}
+
+fn is_html(xot: &Xot, node: Node) -> bool {
+    let name = xot.element(node).unwrap().name();
+    xot.namespace_str(xot.namespace_for_name(name)) == XHTML_NS
+}
+
+fn attribute_value<'a>(xot: &'a Xot, node: Node, name: &str) -> Option<&'a str> {
+    let html = is_html(xot, node);
+    xot.attributes(node).iter().find_map(|(name_id, value)| {
+        let (local_name, namespace) = xot.name_ns_str(name_id);
+        let matches = if html {
+            local_name.eq_ignore_ascii_case(name)
+        } else {
+            local_name == name
+        };
+        (matches && namespace.is_empty()).then_some(value.as_str())
+    })
+}
+
+fn parent_element(xot: &Xot, node: Node) -> Option<Node> {
+    xot.parent(node).filter(|parent| xot.is_element(*parent))
+}
+
+fn previous_element_siblings(xot: &Xot, node: Node) -> impl Iterator<Item = Node> + '_ {
+    xot.preceding_siblings(node)
+        .skip(1)
+        .filter(|sibling| xot.is_element(*sibling))
+}
+
+fn next_element_siblings(xot: &Xot, node: Node) -> impl Iterator<Item = Node> + '_ {
+    xot.following_siblings(node)
+        .skip(1)
+        .filter(|sibling| xot.is_element(*sibling))
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> Error {
+        Error::InvalidSelector(format!("{} at position {}", message, self.pos))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.input[self.pos..].chars().nth(offset)
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek().is_some_and(is_css_whitespace) {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn selector_list(&mut self) -> Result<Selector, Error> {
+        let mut complex_selectors = vec![self.complex_selector()?];
+        loop {
+            self.skip_whitespace();
+            if !self.eat(',') {
+                break;
+            }
+            complex_selectors.push(self.complex_selector()?);
+        }
+        Ok(Selector { complex_selectors })
+    }
+
+    fn complex_selector(&mut self) -> Result<ComplexSelector, Error> {
+        self.skip_whitespace();
+        let mut compounds = vec![(Combinator::Descendant, self.compound()?)];
+        loop {
+            let had_whitespace = self.skip_whitespace();
+            let combinator = match self.peek() {
+                Some('>') => Combinator::Child,
+                Some('+') => Combinator::NextSibling,
+                Some('~') => Combinator::SubsequentSibling,
+                Some(',') | Some(')') | None => break,
+                _ if had_whitespace => Combinator::Descendant,
+                Some(c) => return Err(self.error(&format!("unexpected character '{}'", c))),
+            };
+            if combinator != Combinator::Descendant {
+                self.advance();
+                self.skip_whitespace();
+            }
+            compounds.push((combinator, self.compound()?));
+        }
+        Ok(ComplexSelector { compounds })
+    }
+
+    fn compound(&mut self) -> Result<Compound, Error> {
+        let mut simple_selectors = Vec::new();
+        if self.eat('*') {
+            simple_selectors.push(SimpleSelector::Universal);
+        } else if self.starts_identifier() {
+            simple_selectors.push(SimpleSelector::Type(self.identifier()?));
+        }
+        if self.peek() == Some('|') {
+            return Err(self.error("namespace prefixes are not supported"));
+        }
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.advance();
+                    simple_selectors.push(SimpleSelector::Id(self.identifier()?));
+                }
+                Some('.') => {
+                    self.advance();
+                    simple_selectors.push(SimpleSelector::Class(self.identifier()?));
+                }
+                Some('[') => {
+                    self.advance();
+                    simple_selectors.push(self.attribute()?);
+                }
+                Some(':') => {
+                    self.advance();
+                    simple_selectors.push(self.pseudo_class()?);
+                }
+                _ => break,
+            }
+        }
+        if simple_selectors.is_empty() {
+            return Err(match self.peek() {
+                Some(c) => self.error(&format!("unexpected character '{}'", c)),
+                None => self.error("unexpected end of selector"),
+            });
+        }
+        Ok(Compound { simple_selectors })
+    }
+
+    fn attribute(&mut self) -> Result<SimpleSelector, Error> {
+        self.skip_whitespace();
+        let name = self.identifier()?;
+        if self.peek() == Some('|') && self.peek_at(1) != Some('=') {
+            return Err(self.error("namespace prefixes are not supported"));
+        }
+        self.skip_whitespace();
+        if self.eat(']') {
+            return Ok(SimpleSelector::Attribute {
+                name,
+                matcher: None,
+            });
+        }
+        let operator = match self.advance() {
+            Some('=') => AttributeOperator::Equals,
+            Some(c @ ('~' | '|' | '^' | '$' | '*')) => {
+                self.expect('=')?;
+                match c {
+                    '~' => AttributeOperator::Includes,
+                    '|' => AttributeOperator::DashMatch,
+                    '^' => AttributeOperator::Prefix,
+                    '$' => AttributeOperator::Suffix,
+                    _ => AttributeOperator::Substring,
+                }
+            }
+            _ => return Err(self.error("expected attribute operator")),
+        };
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some(quote @ ('"' | '\'')) => self.string(quote)?,
+            _ => self.identifier()?,
+        };
+        self.skip_whitespace();
+        let case_insensitive = if self.eat('i') || self.eat('I') {
+            true
+        } else {
+            // the s flag is the default
+            let _ = self.eat('s') || self.eat('S');
+            false
+        };
+        self.skip_whitespace();
+        self.expect(']')?;
+        Ok(SimpleSelector::Attribute {
+            name,
+            matcher: Some((operator, value, case_insensitive)),
+        })
+    }
+
+    fn pseudo_class(&mut self) -> Result<SimpleSelector, Error> {
+        if self.peek() == Some(':') {
+            return Err(self.error("pseudo-elements are not supported"));
+        }
+        let name = self.identifier()?.to_ascii_lowercase();
+        if self.eat('(') {
+            self.skip_whitespace();
+            let selector = match name.as_str() {
+                "nth-child" => SimpleSelector::NthChild(self.nth()?),
+                "nth-last-child" => SimpleSelector::NthLastChild(self.nth()?),
+                "nth-of-type" => SimpleSelector::NthOfType(self.nth()?),
+                "nth-last-of-type" => SimpleSelector::NthLastOfType(self.nth()?),
+                "not" => SimpleSelector::Not(self.selector_list()?),
+                "is" => SimpleSelector::Is(self.selector_list()?),
+                _ => return Err(self.error(&format!("unsupported pseudo-class :{}()", name))),
+            };
+            self.skip_whitespace();
+            self.expect(')')?;
+            return Ok(selector);
+        }
+        Ok(match name.as_str() {
+            "root" => SimpleSelector::Root,
+            "empty" => SimpleSelector::Empty,
+            "first-child" => SimpleSelector::NthChild(Nth::FIRST),
+            "last-child" => SimpleSelector::NthLastChild(Nth::FIRST),
+            "only-child" => SimpleSelector::Is(Selector {
+                complex_selectors: vec![ComplexSelector {
+                    compounds: vec![(
+                        Combinator::Descendant,
+                        Compound {
+                            simple_selectors: vec![
+                                SimpleSelector::NthChild(Nth::FIRST),
+                                SimpleSelector::NthLastChild(Nth::FIRST),
+                            ],
+                        },
+                    )],
+                }],
+            }),
+            "first-of-type" => SimpleSelector::NthOfType(Nth::FIRST),
+            "last-of-type" => SimpleSelector::NthLastOfType(Nth::FIRST),
+            "only-of-type" => SimpleSelector::Is(Selector {
+                complex_selectors: vec![ComplexSelector {
+                    compounds: vec![(
+                        Combinator::Descendant,
+                        Compound {
+                            simple_selectors: vec![
+                                SimpleSelector::NthOfType(Nth::FIRST),
+                                SimpleSelector::NthLastOfType(Nth::FIRST),
+                            ],
+                        },
+                    )],
+                }],
+            }),
+            _ => return Err(self.error(&format!("unsupported pseudo-class :{}", name))),
+        })
+    }
+
+    // parse the an+b microsyntax, up to the closing parenthesis
+    fn nth(&mut self) -> Result<Nth, Error> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != ')') {
+            self.advance();
+        }
+        let text: String = self.input[start..self.pos]
+            .chars()
+            .filter(|c| !is_css_whitespace(*c))
+            .collect::<String>()
+            .to_ascii_lowercase();
+        let nth = match text.as_str() {
+            "odd" => Some(Nth { a: 2, b: 1 }),
+            "even" => Some(Nth { a: 2, b: 0 }),
+            _ => match text.split_once('n') {
+                Some((a, b)) => {
+                    let a = match a {
+                        "" | "+" => Some(1),
+                        "-" => Some(-1),
+                        a => nth_integer(a),
+                    };
+                    let b = if b.is_empty() {
+                        Some(0)
+                    } else if b.starts_with(['+', '-']) {
+                        nth_integer(b)
+                    } else {
+                        None
+                    };
+                    a.zip(b).map(|(a, b)| Nth { a, b })
+                }
+                None => nth_integer(&text).map(|b| Nth { a: 0, b }),
+            },
+        };
+        nth.ok_or_else(|| self.error(&format!("invalid an+b expression '{}'", text)))
+    }
+
+    fn starts_identifier(&self) -> bool {
+        match self.peek() {
+            Some('-') => self
+                .peek_at(1)
+                .is_some_and(|c| is_name_start(c) || c == '-' || c == '\\'),
+            Some(c) => is_name_start(c) || c == '\\',
+            None => false,
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, Error> {
+        if !self.starts_identifier() {
+            return Err(self.error("expected identifier"));
+        }
+        let mut identifier = String::new();
+        while let Some(c) = self.peek() {
+            if c == '\\' {
+                self.advance();
+                identifier.push(self.escape()?);
+            } else if is_name_start(c) || c.is_ascii_digit() || c == '-' {
+                self.advance();
+                identifier.push(c);
+            } else {
+                break;
+            }
+        }
+        Ok(identifier)
+    }
+
+    fn escape(&mut self) -> Result<char, Error> {
+        let start = self.pos;
+        while self.pos - start < 6 && self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            self.advance();
+        }
+        if self.pos > start {
+            let code = u32::from_str_radix(&self.input[start..self.pos], 16).unwrap();
+            // a single whitespace character terminates the escape
+            if self.peek().is_some_and(is_css_whitespace) {
+                self.advance();
+            }
+            Ok(char::from_u32(code)
+                .filter(|c| *c != '\0')
+                .unwrap_or('\u{FFFD}'))
+        } else {
+            self.advance()
+                .ok_or_else(|| self.error("unexpected end of selector"))
+        }
+    }
+
+    fn string(&mut self, quote: char) -> Result<String, Error> {
+        self.advance();
+        let mut s = String::new();
+        loop {
+            match self.advance() {
+                Some(c) if c == quote => return Ok(s),
+                Some('\\') => s.push(self.escape()?),
+                Some(c) => s.push(c),
+                None => return Err(self.error("unclosed string")),
+            }
+        }
+    }
+}
+
+fn is_css_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0c')
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+/// ## CSS selectors
+impl Xot {
+    /// Select descendant elements of a node that match a CSS selector.
+    ///
+    /// This is like `querySelectorAll` in the DOM: the node itself is not
+    /// included, but the selector is matched against the whole tree, so a
+    /// selector like `div p` also matches if the `div` is an ancestor of
+    /// `node`. The matching elements are returned in document order.
+    ///
+    /// See the [`css`](`crate::css`) module for the supported selectors.
+    /// Returns [`Error::InvalidSelector`] if the selector cannot be parsed.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<div class="article"><p>A</p><p>B</p></div>"#)?;
+    /// let texts = xot
+    ///     .select(root, "div.article > p:first-child")?
+    ///     .map(|p| xot.text_content_str(p).unwrap())
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(texts, vec!["A"]);
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn select(
+        &self,
+        node: Node,
+        selector: &str,
+    ) -> Result<impl Iterator<Item = Node> + '_, Error> {
+        let selector = Selector::parse(selector)?;
+        Ok(self
+            .descendants(node)
+            .skip(1)
+            .filter(move |descendant| selector.matches(self, *descendant)))
+    }
+
+    /// Select the first descendant element of a node that matches a CSS
+    /// selector.
+    ///
+    /// This is like `querySelector` in the DOM. See [`Xot::select`].
+    pub fn select_first(&self, node: Node, selector: &str) -> Result<Option<Node>, Error> {
+        Ok(self.select(node, selector)?.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    const DOC: &str = r#"<html>
+<body id="top">
+<div class="article main" lang="en-US">
+<h1>Title</h1>
+<p class="intro">One</p>
+<p>Two</p>
+<p data-x="Hello World">Three</p>
+<span/>
+</div>
+<div class="other" lang="en"><p>Four</p><!-- comment --></div>
+</body>
+</html>"#;
+
+    fn select_texts(selector: &str) -> Vec<String> {
+        let mut xot = Xot::new();
+        let root = xot.parse(DOC).unwrap();
+        xot.select(root, selector)
+            .unwrap()
+            .map(|node| {
+                let name = xot.local_name_str(xot.element(node).unwrap().name());
+                match xot.text_content_str(node) {
+                    Some(text) if !text.is_empty() => text.to_string(),
+                    _ => name.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    #[rstest]
+    #[case("p", vec!["One", "Two", "Three", "Four"])]
+    #[case("*.intro", vec!["One"])]
+    #[case(".article.main > p", vec!["One", "Two", "Three"])]
+    #[case("#top p.intro", vec!["One"])]
+    #[case("div p", vec!["One", "Two", "Three", "Four"])]
+    #[case("h1 + p", vec!["One"])]
+    #[case("h1 ~ p", vec!["One", "Two", "Three"])]
+    #[case("p:first-child", vec!["Four"])]
+    #[case("div > :first-child", vec!["Title", "Four"])]
+    #[case("p:last-of-type", vec!["Three", "Four"])]
+    #[case("p:nth-of-type(2)", vec!["Two"])]
+    #[case("div > :nth-child(2n+1)", vec!["Title", "Two", "span", "Four"])]
+    #[case("div > :nth-child(odd)", vec!["Title", "Two", "span", "Four"])]
+    #[case("div > :nth-child(-n + 2)", vec!["Title", "One", "Four"])]
+    #[case("div > :nth-child(n-9223372036854775808)", vec!["Title", "One", "Two", "Three", "span", "Four"])]
+    #[case("div > :nth-child(-n+99999999999999999999)", vec!["Title", "One", "Two", "Three", "span", "Four"])]
+    #[case("div > :nth-child(n+99999999999999999999)", vec![])]
+    #[case("div > :nth-child(-9223372036854775808n)", vec![])]
+    #[case("div > :nth-last-child(1)", vec!["span", "Four"])]
+    #[case("p:only-child", vec!["Four"])]
+    #[case(":empty", vec!["span"])]
+    #[case(":root", vec!["html"])]
+    #[case("[data-x]", vec!["Three"])]
+    #[case("[data-x='Hello World']", vec!["Three"])]
+    #[case("[data-x=\"hello world\" i]", vec!["Three"])]
+    #[case("[data-x^=Hello]", vec!["Three"])]
+    #[case("[data-x$=World]", vec!["Three"])]
+    #[case("[data-x*='o W']", vec!["Three"])]
+    #[case("[data-x~=World]", vec!["Three"])]
+    #[case("[lang|=en]", vec!["div", "div"])]
+    #[case("p:not(.intro, :last-child)", vec!["Two", "Three"])]
+    #[case(":is(h1, span)", vec!["Title", "span"])]
+    #[case("h1, span", vec!["Title", "span"])]
+    fn test_select(#[case] selector: &str, #[case] expected: Vec<&str>) {
+        assert_eq!(select_texts(selector), expected);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("p >")]
+    #[case("p,")]
+    #[case("[x")]
+    #[case("[x=]")]
+    #[case(":hover")]
+    #[case("::before")]
+    #[case("ns|p")]
+    #[case(":nth-child(x)")]
+    #[case("p!")]
+    fn test_invalid_selector(#[case] selector: &str) {
+        assert!(matches!(
+            Selector::parse(selector),
+            Err(Error::InvalidSelector(_))
+        ));
+    }
+
+    #[test]
+    fn test_select_excludes_node_itself_but_matches_ancestors() {
+        let mut xot = Xot::new();
+        let root = xot.parse(r#"<div><p><b/></p></div>"#).unwrap();
+        let div = xot.document_element(root).unwrap();
+        let p = xot.first_child(div).unwrap();
+        assert_eq!(xot.select(p, "p").unwrap().count(), 0);
+        assert_eq!(xot.select(p, "div b").unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_select_html_case_insensitive() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<html xmlns="http://www.w3.org/1999/xhtml"><P CLASS="a"/></html>"#)
+            .unwrap();
+        assert!(xot.select_first(root, "p[class=a]").unwrap().is_some());
+
+        let root = xot.parse(r#"<doc><P/></doc>"#).unwrap();
+        assert!(xot.select_first(root, "p").unwrap().is_none());
+        assert!(xot.select_first(root, "P").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_select_escaped_identifier() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<doc><p class="a:b"/><p class="1x"/></doc>"#)
+            .unwrap();
+        assert_eq!(xot.select(root, r".a\:b").unwrap().count(), 1);
+        assert_eq!(xot.select(root, r".\31 x").unwrap().count(), 1);
+    }
+}
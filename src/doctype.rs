@@ -0,0 +1,567 @@
+use std::io::Write;
+
+use xmlparser::{EntityDefinition as XmlEntityDefinition, ExternalId, StrSpan, Token, Tokenizer};
+
+use crate::entity::{is_char, serialize_attribute};
+use crate::error::ParseError;
+use crate::output::xml::Newline;
+use crate::output::NoopNormalizer;
+use crate::Span;
+
+/// A document type declaration.
+///
+/// Xot doesn't represent the doctype as a node in the tree. Instead it's
+/// kept as information about the document node; see [`Xot::doctype`](`crate::Xot::doctype`)
+/// and [`Xot::set_doctype`](`crate::Xot::set_doctype`).
+///
+/// Examples:
+///
+/// ```html
+/// <!DOCTYPE html>
+/// ```
+///
+/// ```html
+/// <!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd">
+/// ```
+///
+/// When parsing XML, the entity, notation and attribute list declarations in
+/// the internal subset of the doctype are kept too. Other declarations, such
+/// as element declarations, are ignored.
+///
+/// ```xml
+/// <!DOCTYPE doc [
+///   <!ENTITY company "ACME Corp">
+///   <!NOTATION gif SYSTEM "image/gif">
+///   <!ATTLIST doc version CDATA "1.0">
+/// ]>
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Doctype {
+    /// The name of the doctype, usually the name of the document element.
+    pub name: String,
+    /// The public identifier, if any.
+    pub public_id: Option<String>,
+    /// The system identifier, if any.
+    pub system_id: Option<String>,
+    /// The entities declared in the internal subset, in order.
+    pub entities: Vec<EntityDeclaration>,
+    /// The notations declared in the internal subset, in order.
+    pub notations: Vec<NotationDeclaration>,
+    /// The attributes declared in the attribute list declarations of the
+    /// internal subset, in order.
+    pub attributes: Vec<AttributeDeclaration>,
+}
+
+/// An entity declaration in the internal subset of a doctype.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EntityDeclaration {
+    /// The name of the entity.
+    pub name: String,
+    /// Whether this is a parameter entity (`<!ENTITY % name ...>`), which can
+    /// only be referred to in the DTD.
+    pub parameter: bool,
+    /// The definition of the entity.
+    pub definition: EntityDefinition,
+}
+
+/// The definition of an entity.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum EntityDefinition {
+    /// An internal entity, with its replacement text.
+    ///
+    /// Character references in the entity value are replaced by the
+    /// character they refer to. Entity references are kept as they are, as
+    /// they're only expanded when the entity is used.
+    Internal(String),
+    /// An external entity.
+    External {
+        /// The public identifier, if any.
+        public_id: Option<String>,
+        /// The system identifier.
+        system_id: String,
+        /// The notation of an unparsed entity (`NDATA`), if any.
+        notation: Option<String>,
+    },
+}
+
+/// A notation declaration in the internal subset of a doctype.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NotationDeclaration {
+    /// The name of the notation.
+    pub name: String,
+    /// The public identifier, if any.
+    pub public_id: Option<String>,
+    /// The system identifier, if any.
+    pub system_id: Option<String>,
+}
+
+/// An attribute declared in an attribute list declaration (`<!ATTLIST>`) in
+/// the internal subset of a doctype.
+///
+/// A single `<!ATTLIST>` declaration can declare several attributes; each of
+/// them gets its own `AttributeDeclaration`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AttributeDeclaration {
+    /// The name of the element, as written in the declaration.
+    ///
+    /// DTDs don't know about namespaces, so this is a name like `x:doc`.
+    pub element: String,
+    /// The name of the attribute, as written in the declaration.
+    pub name: String,
+    /// The type of the attribute, such as `CDATA`, `ID` or `(yes|no)`.
+    pub attribute_type: String,
+    /// The default of the attribute.
+    pub default: AttributeDefault,
+}
+
+/// The default of a declared attribute.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AttributeDefault {
+    /// `#REQUIRED`: the attribute must be present.
+    Required,
+    /// `#IMPLIED`: the attribute has no default.
+    Implied,
+    /// `#FIXED "value"`: the attribute always has this value.
+    Fixed(String),
+    /// A default value, used when the attribute isn't present.
+    Value(String),
+}
+
+impl AttributeDefault {
+    /// The default value, if there is one.
+    ///
+    /// After parsing, references in the value are expanded and its
+    /// whitespace is normalized as for an attribute value in the document.
+    pub fn value(&self) -> Option<&str> {
+        match self {
+            AttributeDefault::Fixed(value) | AttributeDefault::Value(value) => Some(value),
+            AttributeDefault::Required | AttributeDefault::Implied => None,
+        }
+    }
+}
+
+impl Doctype {
+    /// Create a new doctype with only a name, such as `<!DOCTYPE html>`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            public_id: None,
+            system_id: None,
+            entities: Vec::new(),
+            notations: Vec::new(),
+            attributes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn from_dtd(name: StrSpan, external_id: Option<ExternalId>) -> Self {
+        let (public_id, system_id) = match external_id {
+            Some(ExternalId::System(system_id)) => (None, Some(system_id.to_string())),
+            Some(ExternalId::Public(public_id, system_id)) => {
+                (Some(public_id.to_string()), Some(system_id.to_string()))
+            }
+            None => (None, None),
+        };
+        Self {
+            public_id,
+            system_id,
+            ..Self::new(name.as_str())
+        }
+    }
+
+    pub(crate) fn serialize(&self, w: &mut impl Write) -> Result<(), std::io::Error> {
+        self.serialize_start(w)?;
+        w.write_all(b">")?;
+        Ok(())
+    }
+
+    // Like `serialize`, but with the declarations of the internal subset,
+    // if there are any. This is for XML output.
+    pub(crate) fn serialize_xml(
+        &self,
+        w: &mut impl Write,
+        newline: Newline,
+    ) -> Result<(), std::io::Error> {
+        if self.entities.is_empty() && self.notations.is_empty() && self.attributes.is_empty() {
+            return self.serialize(w);
+        }
+        let newline = newline.as_bytes();
+        self.serialize_start(w)?;
+        w.write_all(b" [")?;
+        w.write_all(newline)?;
+        for entity in &self.entities {
+            w.write_all(b"  ")?;
+            entity.serialize(w)?;
+            w.write_all(newline)?;
+        }
+        for notation in &self.notations {
+            w.write_all(b"  ")?;
+            notation.serialize(w)?;
+            w.write_all(newline)?;
+        }
+        for attribute in &self.attributes {
+            w.write_all(b"  ")?;
+            attribute.serialize(w)?;
+            w.write_all(newline)?;
+        }
+        w.write_all(b"]>")?;
+        Ok(())
+    }
+
+    // `<!DOCTYPE name` with its public and system identifiers.
+    fn serialize_start(&self, w: &mut impl Write) -> Result<(), std::io::Error> {
+        w.write_all(b"<!DOCTYPE ")?;
+        w.write_all(self.name.as_bytes())?;
+        serialize_external_id(self.public_id.as_deref(), self.system_id.as_deref(), w)
+    }
+}
+
+// The public and system identifiers of a declaration, with a space in front.
+fn serialize_external_id(
+    public_id: Option<&str>,
+    system_id: Option<&str>,
+    w: &mut impl Write,
+) -> Result<(), std::io::Error> {
+    match (public_id, system_id) {
+        (Some(public_id), system_id) => {
+            w.write_all(b" PUBLIC ")?;
+            serialize_literal(public_id, w)?;
+            if let Some(system_id) = system_id {
+                w.write_all(b" ")?;
+                serialize_literal(system_id, w)?;
+            }
+        }
+        (None, Some(system_id)) => {
+            w.write_all(b" SYSTEM ")?;
+            serialize_literal(system_id, w)?;
+        }
+        (None, None) => {}
+    }
+    Ok(())
+}
+
+// A quoted literal. Literals can't contain references, so we use single
+// quotes if the literal contains a double quote.
+fn serialize_literal(literal: &str, w: &mut impl Write) -> Result<(), std::io::Error> {
+    let quote: &[u8] = if literal.contains('"') { b"'" } else { b"\"" };
+    w.write_all(quote)?;
+    w.write_all(literal.as_bytes())?;
+    w.write_all(quote)?;
+    Ok(())
+}
+
+impl EntityDeclaration {
+    pub(crate) fn from_dtd(
+        name: StrSpan,
+        definition: XmlEntityDefinition,
+        span: StrSpan,
+    ) -> Result<Self, ParseError> {
+        // xmlparser doesn't tell us about these, so we look at the source
+        let parameter = span["<!ENTITY".len()..].trim_start().starts_with('%');
+        let definition = match definition {
+            XmlEntityDefinition::EntityValue(value) => {
+                EntityDefinition::Internal(replacement_text(value)?)
+            }
+            XmlEntityDefinition::ExternalId(external_id) => {
+                let (public_id, system_id) = match external_id {
+                    ExternalId::System(system_id) => (None, system_id),
+                    ExternalId::Public(public_id, system_id) => {
+                        (Some(public_id.to_string()), system_id)
+                    }
+                };
+                // what follows the closing quote of the system literal
+                let rest = &span[system_id.end() + 1 - span.start()..];
+                let notation = rest
+                    .trim_start()
+                    .strip_prefix("NDATA")
+                    .map(|rest| rest.trim_end_matches('>').trim().to_string());
+                EntityDefinition::External {
+                    public_id,
+                    system_id: system_id.to_string(),
+                    notation,
+                }
+            }
+        };
+        Ok(Self {
+            name: name.to_string(),
+            parameter,
+            definition,
+        })
+    }
+}
+
+impl EntityDeclaration {
+    fn serialize(&self, w: &mut impl Write) -> Result<(), std::io::Error> {
+        w.write_all(b"<!ENTITY ")?;
+        if self.parameter {
+            w.write_all(b"% ")?;
+        }
+        w.write_all(self.name.as_bytes())?;
+        match &self.definition {
+            EntityDefinition::Internal(value) => {
+                w.write_all(b" \"")?;
+                w.write_all(entity_value(value).as_bytes())?;
+                w.write_all(b"\"")?;
+            }
+            EntityDefinition::External {
+                public_id,
+                system_id,
+                notation,
+            } => {
+                serialize_external_id(public_id.as_deref(), Some(system_id), w)?;
+                if let Some(notation) = notation {
+                    w.write_all(b" NDATA ")?;
+                    w.write_all(notation.as_bytes())?;
+                }
+            }
+        }
+        w.write_all(b">")?;
+        Ok(())
+    }
+}
+
+impl NotationDeclaration {
+    fn serialize(&self, w: &mut impl Write) -> Result<(), std::io::Error> {
+        w.write_all(b"<!NOTATION ")?;
+        w.write_all(self.name.as_bytes())?;
+        serialize_external_id(self.public_id.as_deref(), self.system_id.as_deref(), w)?;
+        w.write_all(b">")?;
+        Ok(())
+    }
+}
+
+impl AttributeDeclaration {
+    fn serialize(&self, w: &mut impl Write) -> Result<(), std::io::Error> {
+        w.write_all(b"<!ATTLIST ")?;
+        w.write_all(self.element.as_bytes())?;
+        w.write_all(b" ")?;
+        w.write_all(self.name.as_bytes())?;
+        w.write_all(b" ")?;
+        w.write_all(self.attribute_type.as_bytes())?;
+        let value = match &self.default {
+            AttributeDefault::Required => {
+                w.write_all(b" #REQUIRED")?;
+                None
+            }
+            AttributeDefault::Implied => {
+                w.write_all(b" #IMPLIED")?;
+                None
+            }
+            AttributeDefault::Fixed(value) => {
+                w.write_all(b" #FIXED")?;
+                Some(value)
+            }
+            AttributeDefault::Value(value) => Some(value),
+        };
+        if let Some(value) = value {
+            w.write_all(b" \"")?;
+            w.write_all(serialize_attribute(value.into(), &NoopNormalizer).as_bytes())?;
+            w.write_all(b"\"")?;
+        }
+        w.write_all(b">")?;
+        Ok(())
+    }
+}
+
+// An entity value that has `value` as its replacement text. Entity
+// references are kept, but any other `&`, as well as `%` and `"`, have to
+// be written as character references.
+fn entity_value(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for (i, c) in value.char_indices() {
+        match c {
+            '&' if !is_entity_reference(&value[i..]) => result.push_str("&#x26;"),
+            '%' => result.push_str("&#x25;"),
+            '"' => result.push_str("&#x22;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+// Whether `text` starts with an entity reference such as `&name;`.
+fn is_entity_reference(text: &str) -> bool {
+    let Some(end) = text.find(';') else {
+        return false;
+    };
+    let name = &text[1..end];
+    name.chars().enumerate().all(|(i, c)| {
+        c.is_alphanumeric()
+            || matches!(c, '_' | ':')
+            || (i > 0 && matches!(c, '-' | '.' | '\u{B7}'))
+    }) && name.starts_with(|c: char| !c.is_numeric())
+}
+
+// The replacement text of an entity value: character references are
+// replaced, everything else is kept.
+// https://www.w3.org/TR/xml/#intern-replacement
+fn replacement_text(value: StrSpan) -> Result<String, ParseError> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value.as_str();
+    while let Some(start) = rest.find("&#") {
+        result.push_str(&rest[..start]);
+        let position = value.end() - rest.len() + start;
+        let end = rest[start..]
+            .find(';')
+            .ok_or_else(|| ParseError::UnclosedEntity(rest[start + 1..].to_string(), position))?;
+        let reference = &rest[start + 1..start + end];
+        let code = match reference[1..].strip_prefix('x') {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => reference[1..].parse().ok(),
+        };
+        let c = code
+            .and_then(char::from_u32)
+            .filter(|c| is_char(*c, false))
+            .ok_or_else(|| {
+                ParseError::InvalidEntity(
+                    reference.to_string(),
+                    Span::new(position, position + end + 1),
+                )
+            })?;
+        result.push(c);
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+// The entity declarations in an external DTD subset.
+pub(crate) fn external_subset_entities(subset: &str) -> Result<Vec<EntityDeclaration>, ParseError> {
+    // xmlparser only parses declarations in the internal subset of a doctype
+    let subset = strip_text_declaration(subset);
+    let text = format!("<!DOCTYPE x [{}]><x/>", subset);
+    let mut declarations = Vec::new();
+    for token in Tokenizer::from(text.as_str()) {
+        match token.map_err(|e| ParseError::XmlParser(e, 0))? {
+            Token::EntityDeclaration {
+                name,
+                definition,
+                span,
+            } => declarations.push(EntityDeclaration::from_dtd(name, definition, span)?),
+            Token::DtdEnd { .. } => break,
+            _ => {}
+        }
+    }
+    Ok(declarations)
+}
+
+// External entities may start with a text declaration, which isn't part of
+// their content.
+// https://www.w3.org/TR/xml/#sec-TextDecl
+pub(crate) fn strip_text_declaration(text: &str) -> &str {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    if text.starts_with("<?xml") && text[5..].starts_with(|c: char| c.is_ascii_whitespace()) {
+        if let Some(end) = text.find("?>") {
+            return &text[end + 2..];
+        }
+    }
+    text
+}
+
+// xmlparser skips notation declarations, so we find them in the markup it
+// skipped.
+pub(crate) fn notation_declarations(markup: &str) -> Vec<NotationDeclaration> {
+    markup
+        .match_indices("<!NOTATION")
+        .filter_map(|(i, _)| notation_declaration(&markup[i + "<!NOTATION".len()..]))
+        .collect()
+}
+
+fn notation_declaration(declaration: &str) -> Option<NotationDeclaration> {
+    let declaration = declaration.trim_start();
+    let name_end = declaration.find(|c: char| c.is_ascii_whitespace() || c == '>')?;
+    let name = &declaration[..name_end];
+    let rest = declaration[name_end..].trim_start();
+    let (keyword, mut rest) = rest.split_at(rest.find(|c: char| !c.is_ascii_alphabetic())?);
+    let mut literals = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        let quote = match rest.chars().next()? {
+            quote @ ('"' | '\'') => quote,
+            _ => break,
+        };
+        let end = rest[1..].find(quote)? + 1;
+        literals.push(rest[1..end].to_string());
+        rest = &rest[end + 1..];
+    }
+    let mut literals = literals.into_iter();
+    let (public_id, system_id) = match keyword {
+        "SYSTEM" => (None, Some(literals.next()?)),
+        "PUBLIC" => (Some(literals.next()?), literals.next()),
+        _ => return None,
+    };
+    Some(NotationDeclaration {
+        name: name.to_string(),
+        public_id,
+        system_id,
+    })
+}
+
+// xmlparser skips attribute list declarations too.
+pub(crate) fn attribute_list_declarations(markup: &str) -> Vec<AttributeDeclaration> {
+    let mut declarations = Vec::new();
+    let mut rest = markup;
+    while let Some(start) = rest.find("<!ATTLIST") {
+        rest = &rest[start + "<!ATTLIST".len()..];
+        rest = attribute_list_declaration(rest, &mut declarations).unwrap_or(rest);
+    }
+    declarations
+}
+
+// Parse the attribute definitions of an attribute list declaration, and
+// return what follows it.
+fn attribute_list_declaration<'a>(
+    declaration: &'a str,
+    declarations: &mut Vec<AttributeDeclaration>,
+) -> Option<&'a str> {
+    let (element, mut rest) = dtd_name(declaration)?;
+    loop {
+        rest = rest.trim_start();
+        if let Some(rest) = rest.strip_prefix('>') {
+            return Some(rest);
+        }
+        let (name, after_name) = dtd_name(rest)?;
+        rest = after_name.trim_start();
+        // an enumeration, or a keyword which may be followed by one
+        let mut type_end = rest.find(|c: char| c.is_ascii_whitespace() || c == '(')?;
+        if rest.starts_with('(') || rest[type_end..].trim_start().starts_with('(') {
+            type_end = rest.find(')')? + 1;
+        }
+        let attribute_type = rest[..type_end]
+            .split_ascii_whitespace()
+            .collect::<Vec<_>>();
+        rest = rest[type_end..].trim_start();
+        let default = if let Some(after) = rest.strip_prefix("#REQUIRED") {
+            rest = after;
+            AttributeDefault::Required
+        } else if let Some(after) = rest.strip_prefix("#IMPLIED") {
+            rest = after;
+            AttributeDefault::Implied
+        } else if let Some(after) = rest.strip_prefix("#FIXED") {
+            let (value, after) = dtd_literal(after.trim_start())?;
+            rest = after;
+            AttributeDefault::Fixed(value.to_string())
+        } else {
+            let (value, after) = dtd_literal(rest)?;
+            rest = after;
+            AttributeDefault::Value(value.to_string())
+        };
+        declarations.push(AttributeDeclaration {
+            element: element.to_string(),
+            name: name.to_string(),
+            attribute_type: attribute_type.join(" "),
+            default,
+        });
+    }
+}
+
+fn dtd_name(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    let end = text.find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '(')?;
+    (end > 0).then(|| text.split_at(end))
+}
+
+fn dtd_literal(text: &str) -> Option<(&str, &str)> {
+    let quote = text.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let end = text[1..].find(quote)? + 1;
+    Some((&text[1..end], &text[end + 1..]))
+}
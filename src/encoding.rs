@@ -1,22 +1,282 @@
-use encoding_rs::Encoding;
+use encoding_rs::{DecoderResult, Encoding};
 use xhtmlchardet::detect;
 
-pub fn encoding(data: &[u8], hint: Option<String>) -> Option<&'static Encoding> {
+use crate::error::ParseError;
+
+// the label of the encoding declared at the start of the data
+fn encoding_label(data: &[u8], hint: Option<String>) -> String {
     let mut cursor = std::io::Cursor::new(data);
-    let charsets = detect(&mut cursor, hint).ok()?;
-    // no encoding detected
-    let label = if charsets.is_empty() {
-        "UTF-8"
+    detect(&mut cursor, hint)
+        .ok()
+        .and_then(|charsets| charsets.into_iter().next())
+        // no encoding detected
+        .unwrap_or_else(|| "UTF-8".to_string())
+}
+
+pub fn encoding(data: &[u8], hint: Option<String>) -> Option<&'static Encoding> {
+    Encoding::for_label(encoding_label(data, hint).as_bytes())
+}
+
+/// Determine the encoding of an XML document.
+///
+/// A byte order mark takes precedence, then the `encoding` in the XML
+/// declaration. Without either, it's UTF-8.
+pub(crate) fn xml_encoding(data: &[u8]) -> Result<&'static Encoding, ParseError> {
+    if let Some((encoding, _)) = Encoding::for_bom(data) {
+        return Ok(encoding);
+    }
+    // UTF-16 without a byte order mark is recognized by the start of the XML
+    // declaration; https://www.w3.org/TR/xml/#sec-guessing-no-ext-info
+    if data.starts_with(b"<\0?\0") {
+        return Ok(encoding_rs::UTF_16LE);
+    }
+    if data.starts_with(b"\0<\0?") {
+        return Ok(encoding_rs::UTF_16BE);
+    }
+    encoding(data, None).ok_or_else(|| ParseError::UnsupportedEncoding(encoding_label(data, None)))
+}
+
+pub(crate) fn decode(data: &[u8]) -> Result<(String, &'static Encoding), ParseError> {
+    let encoding = xml_encoding(data)?;
+    let (s, _) = encoding.decode_with_bom_removal(data);
+    Ok((s.into_owned(), encoding))
+}
+
+// like `decode`, but also returns the positions in the decoded text where
+// malformed data was replaced by U+FFFD
+pub(crate) fn decode_recording_replacements(
+    data: &[u8],
+) -> Result<(String, &'static Encoding, Vec<usize>), ParseError> {
+    let encoding = xml_encoding(data)?;
+    let mut decoder = encoding.new_decoder_with_bom_removal();
+    let mut s = String::new();
+    let mut replacements = Vec::new();
+    let mut data = data;
+    loop {
+        let len = decoder
+            .max_utf8_buffer_length_without_replacement(data.len())
+            .unwrap_or(data.len());
+        s.reserve(len);
+        let (result, read) = decoder.decode_to_string_without_replacement(data, &mut s, true);
+        data = &data[read..];
+        match result {
+            DecoderResult::InputEmpty => break,
+            DecoderResult::OutputFull => {}
+            DecoderResult::Malformed(_, _) => {
+                replacements.push(s.len());
+                s.push('\u{FFFD}');
+            }
+        }
+    }
+    Ok((s, encoding, replacements))
+}
+
+/// Determine the encoding of an HTML document.
+///
+/// This follows the encoding sniffing algorithm of the HTML standard: a byte
+/// order mark takes precedence, then we prescan the first 1024 bytes for a
+/// `<meta>` element declaring a charset. If that fails we use UTF-8 if the
+/// data is valid UTF-8, and windows-1252 otherwise.
+#[cfg(feature = "html5ever")]
+pub(crate) fn html_encoding(data: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(data) {
+        return encoding;
+    }
+    if let Some(encoding) = html_prescan(&data[..data.len().min(1024)]) {
+        return encoding;
+    }
+    if std::str::from_utf8(data).is_ok() {
+        encoding_rs::UTF_8
     } else {
-        &charsets[0]
-    };
-    Encoding::for_label(label.as_bytes())
+        encoding_rs::WINDOWS_1252
+    }
 }
 
-pub(crate) fn decode(data: &[u8], hint: Option<String>) -> String {
-    let enc = encoding(data, hint).unwrap();
-    let (s, _, _) = enc.decode(data);
-    s.into_owned()
+pub(crate) fn is_html_whitespace(c: u8) -> bool {
+    matches!(c, b'\t' | b'\n' | b'\x0c' | b'\r' | b' ')
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+#[cfg(feature = "html5ever")]
+fn html_prescan(data: &[u8]) -> Option<&'static Encoding> {
+    let mut pos = 0;
+    while pos < data.len() {
+        let rest = &data[pos..];
+        if rest.starts_with(b"<!--") {
+            // the -- of the comment start may be part of the comment end
+            pos += 2 + find(&rest[2..], b"-->")? + 3;
+            continue;
+        }
+        if rest.len() > 6
+            && rest[..5].eq_ignore_ascii_case(b"<meta")
+            && (is_html_whitespace(rest[5]) || rest[5] == b'/')
+        {
+            pos += 5;
+            if let Some(encoding) = meta_encoding(data, &mut pos) {
+                return Some(encoding);
+            }
+            continue;
+        }
+        let is_tag = rest.len() > 1
+            && (rest[1].is_ascii_alphabetic()
+                || (rest.len() > 2 && rest[1] == b'/' && rest[2].is_ascii_alphabetic()));
+        if rest[0] == b'<' && is_tag {
+            // skip the tag name, then its attributes
+            while pos < data.len() && !is_html_whitespace(data[pos]) && data[pos] != b'>' {
+                pos += 1;
+            }
+            while get_attribute(data, &mut pos).is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            pos += find(rest, b">")?;
+        }
+        pos += 1;
+    }
+    None
+}
+
+#[cfg(feature = "html5ever")]
+fn meta_encoding(data: &[u8], pos: &mut usize) -> Option<&'static Encoding> {
+    let mut seen = Vec::new();
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+    while let Some((name, value)) = get_attribute(data, pos) {
+        if seen.contains(&name) {
+            continue;
+        }
+        match name.as_slice() {
+            b"http-equiv" if value.eq_ignore_ascii_case(b"content-type") => {
+                got_pragma = true;
+            }
+            b"content" if charset.is_none() => {
+                if let Some(label) = charset_from_content(&value) {
+                    charset = Encoding::for_label(label);
+                    need_pragma = Some(true);
+                }
+            }
+            b"charset" => {
+                charset = Encoding::for_label(&value);
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+        seen.push(name);
+    }
+    match need_pragma {
+        None => return None,
+        Some(true) if !got_pragma => return None,
+        _ => {}
+    }
+    let charset = charset?;
+    if charset == encoding_rs::UTF_16BE || charset == encoding_rs::UTF_16LE {
+        Some(encoding_rs::UTF_8)
+    } else if charset == encoding_rs::X_USER_DEFINED {
+        Some(encoding_rs::WINDOWS_1252)
+    } else {
+        Some(charset)
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#concept-get-attributes-when-sniffing
+#[cfg(feature = "html5ever")]
+fn get_attribute(data: &[u8], pos: &mut usize) -> Option<(Vec<u8>, Vec<u8>)> {
+    let at = |pos: usize| data.get(pos).copied();
+    while at(*pos).is_some_and(|c| is_html_whitespace(c) || c == b'/') {
+        *pos += 1;
+    }
+    if matches!(at(*pos), None | Some(b'>')) {
+        return None;
+    }
+    let mut name = Vec::new();
+    loop {
+        let c = at(*pos)?;
+        if c == b'=' && !name.is_empty() {
+            *pos += 1;
+            break;
+        }
+        if is_html_whitespace(c) {
+            while at(*pos).is_some_and(is_html_whitespace) {
+                *pos += 1;
+            }
+            if at(*pos) != Some(b'=') {
+                return Some((name, Vec::new()));
+            }
+            *pos += 1;
+            break;
+        }
+        if c == b'/' || c == b'>' {
+            return Some((name, Vec::new()));
+        }
+        name.push(c.to_ascii_lowercase());
+        *pos += 1;
+    }
+    while at(*pos).is_some_and(is_html_whitespace) {
+        *pos += 1;
+    }
+    let mut value = Vec::new();
+    match at(*pos)? {
+        quote @ (b'"' | b'\'') => {
+            *pos += 1;
+            loop {
+                let c = at(*pos)?;
+                *pos += 1;
+                if c == quote {
+                    return Some((name, value));
+                }
+                value.push(c.to_ascii_lowercase());
+            }
+        }
+        b'>' => Some((name, value)),
+        _ => {
+            while let Some(c) = at(*pos) {
+                if is_html_whitespace(c) || c == b'>' {
+                    break;
+                }
+                value.push(c.to_ascii_lowercase());
+                *pos += 1;
+            }
+            Some((name, value))
+        }
+    }
+}
+
+// https://html.spec.whatwg.org/multipage/urls-and-fetching.html#algorithm-for-extracting-a-character-encoding-from-a-meta-element
+// This expects the content to be lowercase.
+pub(crate) fn charset_from_content(content: &[u8]) -> Option<&[u8]> {
+    let mut pos = 0;
+    loop {
+        pos += find(&content[pos..], b"charset")? + 7;
+        while content.get(pos).copied().is_some_and(is_html_whitespace) {
+            pos += 1;
+        }
+        if content.get(pos) == Some(&b'=') {
+            pos += 1;
+            break;
+        }
+    }
+    while content.get(pos).copied().is_some_and(is_html_whitespace) {
+        pos += 1;
+    }
+    let rest = &content[pos..];
+    match rest.first()? {
+        quote @ (b'"' | b'\'') => {
+            let end = rest[1..].iter().position(|c| c == quote)?;
+            Some(&rest[1..end + 1])
+        }
+        _ => {
+            let end = rest
+                .iter()
+                .position(|&c| is_html_whitespace(c) || c == b';')
+                .unwrap_or(rest.len());
+            Some(&rest[..end])
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
 }
 
 #[cfg(test)]
@@ -52,4 +312,46 @@ mod tests {
         // windows-1252 is a superset of 8859-1
         assert_eq!(enc.name(), "windows-1252");
     }
+
+    #[cfg(feature = "html5ever")]
+    #[test]
+    fn test_html_meta_charset() {
+        let data = b"<html><head><meta charset=\"iso-8859-1\"></head></html>";
+        assert_eq!(html_encoding(data).name(), "windows-1252");
+    }
+
+    #[cfg(feature = "html5ever")]
+    #[test]
+    fn test_html_meta_http_equiv() {
+        let data = b"<!-- <meta charset=koi8-r> --><meta http-equiv=\"Content-Type\" content=\"text/html; charset=Shift_JIS\">";
+        assert_eq!(html_encoding(data).name(), "Shift_JIS");
+    }
+
+    #[cfg(feature = "html5ever")]
+    #[test]
+    fn test_html_meta_content_without_pragma() {
+        let data = b"<meta content=\"text/html; charset=Shift_JIS\">";
+        assert_eq!(html_encoding(data).name(), "UTF-8");
+    }
+
+    #[cfg(feature = "html5ever")]
+    #[test]
+    fn test_html_bom() {
+        let data = b"\xef\xbb\xbf<meta charset=\"iso-8859-1\">";
+        assert_eq!(html_encoding(data).name(), "UTF-8");
+    }
+
+    #[cfg(feature = "html5ever")]
+    #[test]
+    fn test_html_meta_utf16_is_utf8() {
+        let data = b"<meta charset=\"utf-16\">";
+        assert_eq!(html_encoding(data).name(), "UTF-8");
+    }
+
+    #[cfg(feature = "html5ever")]
+    #[test]
+    fn test_html_no_meta_invalid_utf8() {
+        let data = b"<p>\xe9</p>";
+        assert_eq!(html_encoding(data).name(), "windows-1252");
+    }
 }
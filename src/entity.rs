@@ -1,33 +1,75 @@
 use std::borrow::Cow;
 
 use crate::error::ParseError;
+use crate::output::xml::{CharacterReferences, Quote};
 use crate::output::Normalizer;
 use crate::Span;
 
-pub(crate) fn parse_text(content: Cow<str>, base_position: usize) -> Result<Cow<str>, ParseError> {
-    parse_content(content, false, base_position)
+pub(crate) fn parse_text(
+    content: Cow<str>,
+    base_position: usize,
+    xml_1_1: bool,
+) -> Result<Cow<str>, ParseError> {
+    parse_content(content, false, true, base_position, xml_1_1)
+}
+
+// like `parse_text`, but line ends are kept as they are
+pub(crate) fn parse_text_keeping_line_ends(
+    content: Cow<str>,
+    base_position: usize,
+    xml_1_1: bool,
+) -> Result<Cow<str>, ParseError> {
+    parse_content(content, false, false, base_position, xml_1_1)
 }
 
 pub(crate) fn parse_attribute(
     content: Cow<str>,
     base_position: usize,
+    xml_1_1: bool,
 ) -> Result<Cow<str>, ParseError> {
-    parse_content(content, true, base_position)
+    parse_content(content, true, true, base_position, xml_1_1)
+}
+
+// https://www.w3.org/TR/xml/#NT-Char and https://www.w3.org/TR/xml11/#NT-Char
+pub(crate) fn is_char(c: char, xml_1_1: bool) -> bool {
+    match c {
+        '\t' | '\n' | '\r' => true,
+        '\u{1}'..='\u{1F}' => xml_1_1,
+        '\0' | '\u{FFFE}' | '\u{FFFF}' => false,
+        _ => true,
+    }
+}
+
+// the characters XML 1.1 treats as line ends in addition to \r
+// https://www.w3.org/TR/xml11/#sec-line-ends
+fn is_line_end_1_1(c: char) -> bool {
+    matches!(c, '\u{85}' | '\u{2028}')
 }
 
 fn parse_content(
     content: Cow<str>,
     attribute: bool,
+    normalize_line_ends: bool,
     base_position: usize,
+    xml_1_1: bool,
 ) -> Result<Cow<str>, ParseError> {
     let mut result = String::new();
     let mut chars = content.char_indices().peekable();
     let mut change = false;
+    // we only build the result once we change something, so that content
+    // without changes isn't copied
+    let begin_change = |result: &mut String, change: &mut bool, position: usize| {
+        if !*change {
+            result.push_str(&content[..position]);
+            *change = true;
+        }
+    };
     while let Some((position, c)) = chars.next() {
         // https://www.w3.org/TR/xml/#sec-line-ends
-        if c == '\r' {
+        if normalize_line_ends && (c == '\r' || (xml_1_1 && is_line_end_1_1(c))) {
+            begin_change(&mut result, &mut change, position);
             if let Some((_, peeked)) = chars.peek() {
-                if peeked == &'\n' {
+                if c == '\r' && (peeked == &'\n' || (xml_1_1 && peeked == &'\u{85}')) {
                     // consume next char
                     chars.next();
                 }
@@ -38,7 +80,6 @@ fn parse_content(
                 // https://www.w3.org/TR/xml/#AVNormalize
                 result.push(' ');
             }
-            change = true;
         } else if c == '&' {
             let mut entity = String::new();
             let mut is_complete = false;
@@ -54,7 +95,7 @@ fn parse_content(
             if !is_complete {
                 return Err(ParseError::UnclosedEntity(entity, base_position + position));
             }
-            change = true;
+            begin_change(&mut result, &mut change, position);
 
             if let Some(entity) = entity.strip_prefix('#') {
                 let first_char = entity.chars().next().ok_or_else(|| {
@@ -74,12 +115,14 @@ fn parse_content(
                         Span::new(base_position + position, base_position + end_position),
                     )
                 })?;
-                let c = std::char::from_u32(code).ok_or_else(|| {
-                    ParseError::InvalidEntity(
-                        entity.to_string(),
-                        Span::new(base_position + position, base_position + end_position),
-                    )
-                })?;
+                let c = std::char::from_u32(code)
+                    .filter(|c| is_char(*c, xml_1_1))
+                    .ok_or_else(|| {
+                        ParseError::InvalidEntity(
+                            entity.to_string(),
+                            Span::new(base_position + position, base_position + end_position),
+                        )
+                    })?;
                 result.push(c);
             } else {
                 match entity.as_str() {
@@ -99,9 +142,9 @@ fn parse_content(
         } else if attribute && (c == '\t' || c == '\n') {
             // https://www.w3.org/TR/xml/#AVNormalize
             // \r and \r\n already handled earlier
+            begin_change(&mut result, &mut change, position);
             result.push(' ');
-            change = true;
-        } else {
+        } else if change {
             result.push(c);
         }
     }
@@ -218,6 +261,17 @@ pub(crate) fn serialize_cdata<'a, N: Normalizer>(
 pub(crate) fn serialize_attribute<'a, N: Normalizer>(
     content: Cow<'a, str>,
     normalizer: &N,
+) -> Cow<'a, str> {
+    serialize_attribute_quoted(content, normalizer, Quote::Double, false)
+}
+
+// Like `serialize_attribute`, for a value delimited by `quote`. With
+// `unescaped_quote`, the other quote character is left alone.
+pub(crate) fn serialize_attribute_quoted<'a, N: Normalizer>(
+    content: Cow<'a, str>,
+    normalizer: &N,
+    quote: Quote,
+    unescaped_quote: bool,
 ) -> Cow<'a, str> {
     let mut result = String::new();
     let mut change = false;
@@ -232,11 +286,11 @@ pub(crate) fn serialize_attribute<'a, N: Normalizer>(
                 change = true;
                 result.push_str("&lt;")
             }
-            '\'' => {
+            '\'' if quote == Quote::Single || !unescaped_quote => {
                 change = true;
                 result.push_str("&apos;")
             }
-            '"' => {
+            '"' if quote == Quote::Double || !unescaped_quote => {
                 change = true;
                 result.push_str("&quot;")
             }
@@ -251,6 +305,68 @@ pub(crate) fn serialize_attribute<'a, N: Normalizer>(
     }
 }
 
+// Like `escape_restricted_1_1`, for a serialized CDATA section. As a
+// character reference isn't recognized inside a CDATA section, we end the
+// section before it, and start a new one after it.
+pub(crate) fn escape_restricted_cdata_1_1(
+    content: Cow<str>,
+    character_references: CharacterReferences,
+) -> Cow<str> {
+    if !content
+        .chars()
+        .any(|c| is_restricted_1_1(c) || is_line_end_1_1(c))
+    {
+        return content;
+    }
+    let mut result = String::with_capacity(content.len());
+    for c in content.chars() {
+        if is_restricted_1_1(c) || is_line_end_1_1(c) {
+            result.push_str("]]>");
+            result.push_str(&character_reference(c, character_references));
+            result.push_str("<![CDATA[");
+        } else {
+            result.push(c);
+        }
+    }
+    result.into()
+}
+
+pub(crate) fn character_reference(c: char, character_references: CharacterReferences) -> String {
+    match character_references {
+        CharacterReferences::Hexadecimal => format!("&#x{:X};", c as u32),
+        CharacterReferences::Decimal => format!("&#{};", c as u32),
+    }
+}
+
+// https://www.w3.org/TR/xml11/#NT-RestrictedChar
+pub(crate) fn is_restricted_1_1(c: char) -> bool {
+    matches!(c, '\u{1}'..='\u{8}' | '\u{B}' | '\u{C}' | '\u{E}'..='\u{1F}' | '\u{7F}'..='\u{9F}')
+}
+
+// XML 1.1 requires restricted characters to be written as character
+// references. We write the XML 1.1 line end characters that way too, as
+// they'd otherwise be turned into newlines when the XML is parsed again.
+pub(crate) fn escape_restricted_1_1(
+    content: Cow<str>,
+    character_references: CharacterReferences,
+) -> Cow<str> {
+    if !content
+        .chars()
+        .any(|c| is_restricted_1_1(c) || is_line_end_1_1(c))
+    {
+        return content;
+    }
+    let mut result = String::with_capacity(content.len());
+    for c in content.chars() {
+        if is_restricted_1_1(c) || is_line_end_1_1(c) {
+            result.push_str(&character_reference(c, character_references));
+        } else {
+            result.push(c);
+        }
+    }
+    result.into()
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -261,19 +377,19 @@ mod tests {
     #[test]
     fn test_parse() {
         let text = "A &amp; B";
-        assert_eq!(parse_text(text.into(), 0).unwrap(), "A & B");
+        assert_eq!(parse_text(text.into(), 0, false).unwrap(), "A & B");
     }
 
     #[test]
     fn test_parse_multiple() {
         let text = "&amp;&apos;&gt;&lt;&quot;";
-        assert_eq!(parse_text(text.into(), 0).unwrap(), "&'><\"");
+        assert_eq!(parse_text(text.into(), 0, false).unwrap(), "&'><\"");
     }
 
     #[test]
     fn test_parse_unknown_entity() {
         let text = "&unknown;";
-        let err = parse_text(text.into(), 0);
+        let err = parse_text(text.into(), 0, false);
         if let Err(ParseError::InvalidEntity(entity, span)) = err {
             assert_eq!(entity, "unknown");
             assert_eq!(span, Span::new(0, 9));
@@ -285,7 +401,7 @@ mod tests {
     #[test]
     fn test_parse_unfinished_entity() {
         let text = "&amp";
-        let err = parse_text(text.into(), 0);
+        let err = parse_text(text.into(), 0, false);
         if let Err(ParseError::UnclosedEntity(entity, position)) = err {
             assert_eq!(entity, "amp");
             assert_eq!(position, 0);
@@ -294,10 +410,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_char_ref_control() {
+        let text = "&#x1;";
+        assert!(matches!(
+            parse_text(text.into(), 0, false),
+            Err(ParseError::InvalidEntity(..))
+        ));
+        assert_eq!(parse_text(text.into(), 0, true).unwrap(), "\u{1}");
+        let text = "&#0;";
+        assert!(parse_text(text.into(), 0, false).is_err());
+        assert!(parse_text(text.into(), 0, true).is_err());
+        let text = "&#9;&#xFFFD;";
+        assert_eq!(parse_text(text.into(), 0, false).unwrap(), "\t\u{FFFD}");
+        assert!(parse_text("&#xFFFF;".into(), 0, false).is_err());
+    }
+
+    #[test]
+    fn test_parse_newline_1_1() {
+        let text = "A\u{85}B\u{2028}C\r\u{85}D";
+        assert_eq!(parse_text(text.into(), 0, true).unwrap(), "A\nB\nC\nD");
+        assert_eq!(parse_attribute(text.into(), 0, true).unwrap(), "A B C D");
+        // in XML 1.0, these aren't line ends
+        assert_eq!(
+            parse_text(text.into(), 0, false).unwrap(),
+            "A\u{85}B\u{2028}C\n\u{85}D"
+        );
+    }
+
     #[test]
     fn test_parse_no_entities() {
         let text = "hello";
-        let result = parse_text(text.into(), 0).unwrap();
+        let result = parse_text(text.into(), 0, false).unwrap();
         // this is the same slice
         assert!(std::ptr::eq(text, result.as_ref()));
     }
@@ -305,49 +449,58 @@ mod tests {
     #[test]
     fn test_parse_newline_r() {
         let text = "A \r B";
-        assert_eq!(parse_text(text.into(), 0).unwrap(), "A \n B");
+        assert_eq!(parse_text(text.into(), 0, false).unwrap(), "A \n B");
     }
 
     #[test]
     fn test_parse_newline_rn() {
         let text = "A \r\n B";
-        assert_eq!(parse_text(text.into(), 0).unwrap(), "A \n B");
+        assert_eq!(parse_text(text.into(), 0, false).unwrap(), "A \n B");
+    }
+
+    #[test]
+    fn test_keep_text_line_ends() {
+        let text = "A\r\nB\rC&amp;";
+        assert_eq!(
+            parse_text_keeping_line_ends(text.into(), 0, false).unwrap(),
+            "A\r\nB\rC&"
+        );
     }
 
     #[test]
     fn test_do_not_normalize_text_tab() {
         let text = "A \t B";
-        assert_eq!(parse_text(text.into(), 0).unwrap(), "A \t B");
+        assert_eq!(parse_text(text.into(), 0, false).unwrap(), "A \t B");
     }
 
     #[test]
     fn test_do_not_normalize_text_newline() {
         let text = "A \n B";
-        assert_eq!(parse_text(text.into(), 0).unwrap(), "A \n B");
+        assert_eq!(parse_text(text.into(), 0, false).unwrap(), "A \n B");
     }
 
     #[test]
     fn test_normalize_attribute_tab() {
         let text = "A \t B";
-        assert_eq!(parse_attribute(text.into(), 0).unwrap(), "A   B");
+        assert_eq!(parse_attribute(text.into(), 0, false).unwrap(), "A   B");
     }
 
     #[test]
     fn test_normalize_attribute_r_newline() {
         let text = "A \r B";
-        assert_eq!(parse_attribute(text.into(), 0).unwrap(), "A   B");
+        assert_eq!(parse_attribute(text.into(), 0, false).unwrap(), "A   B");
     }
 
     #[test]
     fn test_normalize_attribute_rn_newline() {
         let text = "A \r\n B";
-        assert_eq!(parse_attribute(text.into(), 0).unwrap(), "A   B");
+        assert_eq!(parse_attribute(text.into(), 0, false).unwrap(), "A   B");
     }
 
     #[test]
     fn test_normalize_attribute_newline() {
         let text = "A \n B";
-        assert_eq!(parse_attribute(text.into(), 0).unwrap(), "A   B");
+        assert_eq!(parse_attribute(text.into(), 0, false).unwrap(), "A   B");
     }
 
     #[test]
@@ -457,6 +610,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_serialize_attribute_quoted() {
+        let text = "'\"";
+        assert_eq!(
+            serialize_attribute_quoted(text.into(), &NoopNormalizer, Quote::Single, false),
+            "&apos;&quot;"
+        );
+        assert_eq!(
+            serialize_attribute_quoted(text.into(), &NoopNormalizer, Quote::Single, true),
+            "&apos;\""
+        );
+        assert_eq!(
+            serialize_attribute_quoted(text.into(), &NoopNormalizer, Quote::Double, true),
+            "'&quot;"
+        );
+    }
+
     #[test]
     fn test_serialize_attribute_no_entities() {
         let text = "hello";
@@ -468,31 +638,31 @@ mod tests {
     #[test]
     fn test_parse_character_hex_entity() {
         let text = "A &#x26; B";
-        assert_eq!(parse_text(text.into(), 0).unwrap(), "A & B");
+        assert_eq!(parse_text(text.into(), 0, false).unwrap(), "A & B");
     }
 
     #[test]
     fn test_parse_character_decimal_entity() {
         let text = "A &#38; B";
-        assert_eq!(parse_text(text.into(), 0).unwrap(), "A & B");
+        assert_eq!(parse_text(text.into(), 0, false).unwrap(), "A & B");
     }
 
     #[test]
     fn test_parse_character_empty_entity() {
         let text = "A &#; B";
-        assert!(parse_text(text.into(), 0).is_err());
+        assert!(parse_text(text.into(), 0, false).is_err());
     }
 
     #[test]
     fn test_parse_character_empty_hex_entity() {
         let text = "A &x#; B";
-        assert!(parse_text(text.into(), 0).is_err());
+        assert!(parse_text(text.into(), 0, false).is_err());
     }
 
     #[test]
     fn test_parse_character_broken_hex_entity() {
         let text = "A &xflub#; B";
-        assert!(parse_text(text.into(), 0).is_err());
+        assert!(parse_text(text.into(), 0, false).is_err());
     }
 
     #[test]
@@ -10,9 +10,29 @@ pub enum ParseError {
     /// The XML is not well-formed - you use `&` to open an entity without
     /// closing it with `;`.
     UnclosedEntity(String, usize),
-    /// The entity is not known. Only the basic entities are supported
-    /// right now, not any user defined ones.
+    /// The entity is not known. It's not one of the predefined entities, and
+    /// it's not declared in the internal subset of the doctype either.
     InvalidEntity(String, Span),
+    /// An entity refers to itself, directly or through other entities.
+    RecursiveEntity(String, Span),
+    /// Expanding an entity exceeds one of the
+    /// [`EntityLimits`](crate::EntityLimits).
+    EntityLimitExceeded(String, Span),
+    /// Elements are nested deeper than
+    /// [`ParseLimits::max_depth`](crate::ParseLimits::max_depth).
+    DepthLimitExceeded(Span),
+    /// An element has more attributes than
+    /// [`ParseLimits::max_attributes`](crate::ParseLimits::max_attributes).
+    AttributeLimitExceeded(Span),
+    /// A name is longer than
+    /// [`ParseLimits::max_name_length`](crate::ParseLimits::max_name_length).
+    NameLengthLimitExceeded(Span),
+    /// The document has more nodes than
+    /// [`ParseLimits::max_nodes`](crate::ParseLimits::max_nodes).
+    NodeLimitExceeded(Span),
+    /// Parsing was cancelled by
+    /// [`ParseOptions::progress`](crate::ParseOptions::progress).
+    Cancelled(Span),
     /// You used a namespace prefix that is not declared during parsing.
     UnknownPrefix(String, Span),
     /// You declared an attribute of the same name twice.
@@ -26,7 +46,12 @@ pub enum ParseError {
         note = "The value of the standalone declaration is now ignored"
     )]
     UnsupportedNotStandalone(Span),
-    /// XML DTD is not supported.
+    /// The document starts with a byte order mark, which
+    /// [`ParseOptions::reject_bom`](crate::ParseOptions::reject_bom) doesn't
+    /// allow.
+    UnexpectedByteOrderMark(Span),
+    /// XML DTD is not supported. This is reported by
+    /// [`TokenReader`](crate::read::TokenReader).
     DtdUnsupported(Span),
     /// No top-level element in the document.
     NoElementAtTopLevel(usize),
@@ -38,9 +63,11 @@ pub enum ParseError {
     DuplicateId(String, Span),
     /// xmlparser error
     XmlParser(xmlparser::Error, usize),
+    /// The encoding of the document is not supported.
+    UnsupportedEncoding(String),
     /// html5ever parsing errors
     #[cfg(feature = "html5ever")]
-    HtmlParse(Vec<String>),
+    HtmlParse(Vec<crate::html5::Html5Error>),
 }
 
 impl ParseError {
@@ -51,19 +78,79 @@ impl ParseError {
             ParseError::InvalidCloseTag(_, _, span) => *span,
             ParseError::UnclosedEntity(_, position) => Span::new(*position, *position),
             ParseError::InvalidEntity(_, span) => *span,
+            ParseError::RecursiveEntity(_, span) => *span,
+            ParseError::EntityLimitExceeded(_, span) => *span,
+            ParseError::DepthLimitExceeded(span) => *span,
+            ParseError::AttributeLimitExceeded(span) => *span,
+            ParseError::NameLengthLimitExceeded(span) => *span,
+            ParseError::NodeLimitExceeded(span) => *span,
+            ParseError::Cancelled(span) => *span,
             ParseError::UnknownPrefix(_, span) => *span,
             ParseError::DuplicateAttribute(_, span) => *span,
             ParseError::UnsupportedVersion(_, span) => *span,
             #[allow(deprecated)]
             ParseError::UnsupportedNotStandalone(span) => *span,
+            ParseError::UnexpectedByteOrderMark(span) => *span,
             ParseError::DtdUnsupported(span) => *span,
             ParseError::NoElementAtTopLevel(position) => Span::new(*position, *position),
             ParseError::MultipleElementsAtTopLevel(span) => *span,
             ParseError::TextAtTopLevel(span) => *span,
             ParseError::DuplicateId(_, span) => *span,
             ParseError::XmlParser(_, position) => Span::new(*position, *position),
+            // the encoding is declared at the start of the document
+            ParseError::UnsupportedEncoding(_) => Span::new(0, 0),
             #[cfg(feature = "html5ever")]
-            ParseError::HtmlParse(_) => Span::new(0, 0), // html5ever doesn't easily provide spans
+            // without the source we can't turn the line into a span
+            ParseError::HtmlParse(_) => Span::new(0, 0),
+        }
+    }
+
+    // Shift the positions in the error by `offset`. This is used when the
+    // error was found in a piece of a larger source text.
+    pub(crate) fn with_offset(self, offset: usize) -> Self {
+        self.map_spans(|span| Span::new(span.start + offset, span.end + offset))
+    }
+
+    // Replace the spans in the error. A position is treated as an empty span.
+    pub(crate) fn map_spans(self, f: impl Fn(Span) -> Span) -> Self {
+        let position = |position: usize| f(Span::new(position, position)).start;
+        match self {
+            ParseError::UnclosedTag(span) => ParseError::UnclosedTag(f(span)),
+            ParseError::InvalidCloseTag(prefix, name, span) => {
+                ParseError::InvalidCloseTag(prefix, name, f(span))
+            }
+            ParseError::UnclosedEntity(s, p) => ParseError::UnclosedEntity(s, position(p)),
+            ParseError::InvalidEntity(s, span) => ParseError::InvalidEntity(s, f(span)),
+            ParseError::RecursiveEntity(s, span) => ParseError::RecursiveEntity(s, f(span)),
+            ParseError::EntityLimitExceeded(s, span) => ParseError::EntityLimitExceeded(s, f(span)),
+            ParseError::DepthLimitExceeded(span) => ParseError::DepthLimitExceeded(f(span)),
+            ParseError::AttributeLimitExceeded(span) => ParseError::AttributeLimitExceeded(f(span)),
+            ParseError::NameLengthLimitExceeded(span) => {
+                ParseError::NameLengthLimitExceeded(f(span))
+            }
+            ParseError::NodeLimitExceeded(span) => ParseError::NodeLimitExceeded(f(span)),
+            ParseError::Cancelled(span) => ParseError::Cancelled(f(span)),
+            ParseError::UnknownPrefix(s, span) => ParseError::UnknownPrefix(s, f(span)),
+            ParseError::DuplicateAttribute(s, span) => ParseError::DuplicateAttribute(s, f(span)),
+            ParseError::UnsupportedVersion(s, span) => ParseError::UnsupportedVersion(s, f(span)),
+            #[allow(deprecated)]
+            ParseError::UnsupportedNotStandalone(span) => {
+                ParseError::UnsupportedNotStandalone(f(span))
+            }
+            ParseError::UnexpectedByteOrderMark(span) => {
+                ParseError::UnexpectedByteOrderMark(f(span))
+            }
+            ParseError::DtdUnsupported(span) => ParseError::DtdUnsupported(f(span)),
+            ParseError::NoElementAtTopLevel(p) => ParseError::NoElementAtTopLevel(position(p)),
+            ParseError::MultipleElementsAtTopLevel(span) => {
+                ParseError::MultipleElementsAtTopLevel(f(span))
+            }
+            ParseError::TextAtTopLevel(span) => ParseError::TextAtTopLevel(f(span)),
+            ParseError::DuplicateId(s, span) => ParseError::DuplicateId(s, f(span)),
+            ParseError::XmlParser(e, p) => ParseError::XmlParser(e, position(p)),
+            ParseError::UnsupportedEncoding(s) => ParseError::UnsupportedEncoding(s),
+            #[cfg(feature = "html5ever")]
+            ParseError::HtmlParse(errors) => ParseError::HtmlParse(errors),
         }
     }
 }
@@ -74,6 +161,9 @@ pub enum Error {
     // access errors
     /// The node is not a Document node.
     NotDocument(Node),
+    /// The nodes are in different trees, so they have no order relative to
+    /// each other.
+    DifferentTrees(Node, Node),
 
     // manipulation errors
     /// Invalid operation on XML. You get this when
@@ -89,6 +179,8 @@ pub enum Error {
     InvalidTarget(String),
     /// The node you tried to act on is not an element.
     NotElement(Node),
+    /// The node you tried to act on is not a processing instruction.
+    NotProcessingInstruction(Node),
     /// Indextree error that can happen during manipulation.
     NodeError(indextree::NodeError),
 
@@ -105,6 +197,16 @@ pub enum Error {
     /// target name.
     NamespaceInProcessingInstruction,
 
+    /// A character can't be written in the serialized output, such as a
+    /// control character in XML 1.0. See
+    /// [`output::xml::Parameters::unrepresentable_characters`](crate::output::xml::Parameters::unrepresentable_characters).
+    UnrepresentableCharacter(char),
+
+    /// The serialized output would be larger than the limit of this many
+    /// bytes. See
+    /// [`output::xml::Parameters::max_output_size`](crate::output::xml::Parameters::max_output_size).
+    OutputTooLarge(usize),
+
     /// An error during parsing
     Parse(ParseError),
 
@@ -130,6 +232,29 @@ pub enum Error {
     /// We take the string version of the IO error so as to keep errors comparable,
     /// which is more important than the exact error object in this case (serialization)
     Io(String),
+
+    /// A CSS selector could not be parsed, or uses unsupported features.
+    InvalidSelector(String),
+
+    /// An XPath expression could not be parsed or evaluated, or uses
+    /// unsupported features.
+    InvalidXPath(String),
+
+    /// The encoding label isn't known.
+    UnsupportedEncoding(String),
+
+    /// A resource referred to by `xi:include` could not be included, and
+    /// there's no `xi:fallback`. This has the URI of the resource.
+    XIncludeResource(String),
+    /// A document includes itself, directly or indirectly. This has the URI
+    /// of the resource that would be included again.
+    XIncludeLoop(String),
+    /// An `xi:include` or `xi:fallback` element is used incorrectly.
+    InvalidXInclude(String),
+
+    /// The data of a processing instruction doesn't consist of
+    /// pseudo-attributes.
+    InvalidPseudoAttributes(String),
 }
 
 impl From<indextree::NodeError> for Error {
@@ -157,10 +282,12 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Error::NotDocument(_) => write!(f, "Not a document node"),
+            Error::DifferentTrees(_, _) => write!(f, "Nodes are in different trees"),
             Error::InvalidOperation(s) => write!(f, "Invalid operation: {}", s),
             Error::InvalidComment(s) => write!(f, "Invalid comment: {}", s),
             Error::InvalidTarget(s) => write!(f, "Invalid target: {}", s),
             Error::NotElement(_) => write!(f, "Not an element"),
+            Error::NotProcessingInstruction(_) => write!(f, "Not a processing instruction"),
             Error::NodeError(e) => write!(f, "Node error: {}", e),
             Error::MissingPrefix(_) => write!(f, "Missing prefix"),
             Error::ProcessingInstructionGtInHtml(s) => {
@@ -169,6 +296,12 @@ impl std::fmt::Display for Error {
             Error::NamespaceInProcessingInstruction => {
                 write!(f, "Namespace in processing instruction target")
             }
+            Error::UnrepresentableCharacter(c) => {
+                write!(f, "Character can't be written: {:?}", c)
+            }
+            Error::OutputTooLarge(limit) => {
+                write!(f, "Output is larger than {} bytes", limit)
+            }
             Error::Parse(e) => write!(f, "Parse error: {:?}", e),
             Error::UnknownPrefix(s) => write!(f, "Unknown prefix: {}", s),
             Error::IllegalAtTopLevel(_) => write!(f, "Illegal content under document node (attribute, namespace or document node"),
@@ -176,6 +309,13 @@ impl std::fmt::Display for Error {
             Error::NoElementAtTopLevel => write!(f, "No element under document root. Not allowed in a well-formed document, but allowed in a fragment"),
             Error::MultipleElementsAtTopLevel => write!(f, "Multiple elements under document root. Not allowed in a well-formed document, but allowed in a fragment"),
             Error::Io(s) => write!(f, "IO error: {}", s),
+            Error::InvalidSelector(s) => write!(f, "Invalid selector: {}", s),
+            Error::InvalidXPath(s) => write!(f, "Invalid XPath: {}", s),
+            Error::UnsupportedEncoding(s) => write!(f, "Unsupported encoding: {}", s),
+            Error::XIncludeResource(s) => write!(f, "Cannot include resource: {}", s),
+            Error::XIncludeLoop(s) => write!(f, "Inclusion loop: {}", s),
+            Error::InvalidXInclude(s) => write!(f, "Invalid XInclude: {}", s),
+            Error::InvalidPseudoAttributes(s) => write!(f, "Invalid pseudo-attributes: {}", s),
         }
     }
 }
@@ -187,11 +327,21 @@ impl std::fmt::Display for ParseError {
             ParseError::InvalidCloseTag(s, s2, _) => write!(f, "Invalid close tag: {} {}", s, s2),
             ParseError::UnclosedEntity(s, _) => write!(f, "Unclosed entity: {}", s),
             ParseError::InvalidEntity(s, _) => write!(f, "Invalid entity: {}", s),
+            ParseError::RecursiveEntity(s, _) => write!(f, "Recursive entity: {}", s),
+            ParseError::EntityLimitExceeded(s, _) => {
+                write!(f, "Entity expansion limit exceeded: {}", s)
+            }
+            ParseError::DepthLimitExceeded(_) => write!(f, "Element nesting limit exceeded"),
+            ParseError::AttributeLimitExceeded(_) => write!(f, "Attribute limit exceeded"),
+            ParseError::NameLengthLimitExceeded(_) => write!(f, "Name length limit exceeded"),
+            ParseError::NodeLimitExceeded(_) => write!(f, "Node limit exceeded"),
+            ParseError::Cancelled(_) => write!(f, "Parsing cancelled"),
             ParseError::UnknownPrefix(s, _) => write!(f, "Unknown prefix: {}", s),
             ParseError::DuplicateAttribute(s, _) => write!(f, "Duplicate attribute: {}", s),
             ParseError::UnsupportedVersion(s, _) => write!(f, "Unsupported version: {}", s),
             #[allow(deprecated)]
             ParseError::UnsupportedNotStandalone(_) => write!(f, "Unsupported standalone"),
+            ParseError::UnexpectedByteOrderMark(_) => write!(f, "Unexpected byte order mark"),
             ParseError::DtdUnsupported(_) => write!(f, "DTD is not supported"),
             ParseError::NoElementAtTopLevel(_) => write!(f, "No element at top level"),
             ParseError::MultipleElementsAtTopLevel(_) => {
@@ -200,8 +350,18 @@ impl std::fmt::Display for ParseError {
             ParseError::TextAtTopLevel(_) => write!(f, "Text at top level"),
             ParseError::DuplicateId(s, _) => write!(f, "Duplicate xml:id: {}", s),
             ParseError::XmlParser(e, _position) => write!(f, "Parser error: {}", e),
+            ParseError::UnsupportedEncoding(s) => write!(f, "Unsupported encoding: {}", s),
             #[cfg(feature = "html5ever")]
-            ParseError::HtmlParse(errors) => write!(f, "HTML parse errors: {:?}", errors),
+            ParseError::HtmlParse(errors) => {
+                write!(f, "HTML parse errors: ")?;
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -1,189 +1,741 @@
 //! HTML5 Parser integration using html5ever.
 #![cfg(feature = "html5ever")]
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::default::Default;
-use std::io::Cursor;
-use std::rc::Rc;
+use std::io::Read;
 
 use html5ever::driver::ParseOpts;
-use html5ever::tendril::{StrTendril, TendrilSink};
-// Import parse_document and related types from html5ever
-use html5ever::parse_document; // Removed unused imports
-use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use html5ever::tendril::stream::Utf8LossyDecoder;
+use html5ever::tendril::{ByteTendril, StrTendril, TendrilSink};
+use html5ever::tree_builder::{
+    ElementFlags, NodeOrText, QuirksMode as Html5everQuirksMode, TreeSink,
+};
+use html5ever::{
+    parse_document, parse_fragment, Attribute, ExpandedName, LocalName, Namespace, QualName,
+};
 
-use crate::error::ParseError;
+use crate::doctype::Doctype;
+use crate::encoding::html_encoding;
+use crate::error::{Error, ParseError};
+use crate::htmltext::drop_inter_element_whitespace;
 use crate::id::NamespaceId;
+use crate::parse::{Span, SpanInfo, SpanInfoKey};
 use crate::xotdata::{Node, Xot};
 
-
 // Define constants for common namespace URIs used in HTML5
 const HTML_NS: &str = "http://www.w3.org/1999/xhtml";
-const MATHML_NS: &str = "http://www.w3.org/1998/Math/MathML";
-const SVG_NS: &str = "http://www.w3.org/2000/svg";
 const XLINK_NS: &str = "http://www.w3.org/1999/xlink";
-const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
 const XMLNS_NS: &str = "http://www.w3.org/2000/xmlns/";
 
-struct DomConverter {
-    // Removed xot field
-    namespace_ids: HashMap<StrTendril, NamespaceId>,
-    // Use the pointer to the Rc container as the key
-    node_map: HashMap<*const markup5ever_rcdom::Node, Node>, // Map html5ever nodes to Xot nodes
+// Spans of nodes at line granularity, as html5ever only reports line numbers
+struct LineSpans {
+    // the byte offset where each line starts
+    line_starts: Vec<usize>,
+    source_len: usize,
+    span_info: SpanInfo,
 }
 
-impl DomConverter {
-    // Takes xot only to pre-add common namespaces
-    fn new(xot: &mut Xot) -> Self {
-        let mut namespace_ids = HashMap::new();
+impl LineSpans {
+    fn new(html: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(html.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineSpans {
+            line_starts,
+            source_len: html.len(),
+            span_info: SpanInfo::new(),
+        }
+    }
 
-        let html_ns_id = xot.add_namespace(HTML_NS);
-        namespace_ids.insert(StrTendril::from(HTML_NS), html_ns_id);
+    // the span of a (1-based) line, without the line break
+    fn line_span(&self, line: u64) -> Span {
+        let start = self
+            .line_starts
+            .get(line as usize - 1)
+            .copied()
+            .unwrap_or(self.source_len);
+        let end = self
+            .line_starts
+            .get(line as usize)
+            .map(|next_line_start| next_line_start - 1)
+            .unwrap_or(self.source_len);
+        Span::new(start, end)
+    }
+}
 
-        let mathml_ns_id = xot.add_namespace(MATHML_NS);
-        namespace_ids.insert(StrTendril::from(MATHML_NS), mathml_ns_id);
+// The result of parsing HTML with a `XotSink`.
+struct Parsed {
+    document: Node,
+    errors: Vec<Html5Error>,
+    span_info: Option<SpanInfo>,
+}
 
-        let svg_ns_id = xot.add_namespace(SVG_NS);
-        namespace_ids.insert(StrTendril::from(SVG_NS), svg_ns_id);
+// A tree sink that builds Xot nodes directly as html5ever parses.
+struct XotSink<'a> {
+    xot: &'a mut Xot,
+    document: Node,
+    // Where to place the nodes if we parse a fragment; html5ever puts them
+    // under a synthesized html element.
+    fragment_parent: Option<Node>,
+    options: Html5ParseOptions,
+    namespace_ids: HashMap<Namespace, NamespaceId>,
+    // html5ever needs the names of elements it created as atoms
+    element_names: HashMap<Node, QualName>,
+    mathml_annotation_xml_integration_points: HashSet<Node>,
+    // Detached elements that hold the content of templates, if we drop it
+    template_contents: HashMap<Node, Node>,
+    // Comments to drop or expand once parsing is done
+    comments: Vec<Node>,
+    // `noscript` elements to drop once parsing is done
+    noscripts: Vec<Node>,
+    errors: Vec<Html5Error>,
+    line: u64,
+    line_spans: Option<LineSpans>,
+}
 
-        let xlink_ns_id = xot.add_namespace(XLINK_NS);
-        namespace_ids.insert(StrTendril::from(XLINK_NS), xlink_ns_id);
+impl<'a> XotSink<'a> {
+    fn new(xot: &'a mut Xot, options: &Html5ParseOptions) -> Self {
+        let document = xot.new_document();
+        XotSink {
+            xot,
+            document,
+            fragment_parent: None,
+            options: options.clone(),
+            namespace_ids: HashMap::new(),
+            element_names: HashMap::new(),
+            mathml_annotation_xml_integration_points: HashSet::new(),
+            template_contents: HashMap::new(),
+            comments: Vec::new(),
+            noscripts: Vec::new(),
+            errors: Vec::new(),
+            line: 1,
+            line_spans: None,
+        }
+    }
 
-        let xml_ns_id = xot.add_namespace(XML_NS);
-        namespace_ids.insert(StrTendril::from(XML_NS), xml_ns_id);
+    fn with_line_spans(mut self, html: &str) -> Self {
+        self.line_spans = Some(LineSpans::new(html));
+        self
+    }
 
-        let xmlns_ns_id = xot.add_namespace(XMLNS_NS);
-        namespace_ids.insert(StrTendril::from(XMLNS_NS), xmlns_ns_id);
+    // Parse a fragment, placing the nodes under the document.
+    fn fragment(self) -> Self {
+        let document = self.document;
+        self.fragment_into(document)
+    }
 
-        DomConverter {
-            // xot removed
-            namespace_ids,
-            node_map: HashMap::new(),
-        }
+    // Parse a fragment, placing the nodes under `parent`.
+    fn fragment_into(mut self, parent: Node) -> Self {
+        self.fragment_parent = Some(parent);
+        self
     }
 
-    // Moved namespace logic here, takes &mut Xot
-    fn get_or_add_namespace_id(&mut self, xot: &mut Xot, uri: &StrTendril) -> NamespaceId {
-        if uri.is_empty() {
-            return xot.no_namespace();
+    fn namespace_id(&mut self, namespace: &Namespace) -> NamespaceId {
+        if namespace.is_empty() {
+            return self.xot.no_namespace();
         }
-        // Check pre-cached map first
-        if let Some(id) = self.namespace_ids.get(uri) {
+        if let Some(id) = self.namespace_ids.get(namespace) {
             return *id;
         }
-        // If not found, add it to xot and cache it
-        let id = xot.add_namespace(uri);
-        self.namespace_ids.insert(uri.clone(), id);
+        let id = self.xot.add_namespace(namespace);
+        self.namespace_ids.insert(namespace.clone(), id);
         id
     }
 
+    fn add_span(&mut self, key: SpanInfoKey) {
+        if let Some(line_spans) = &mut self.line_spans {
+            let span = line_spans.line_span(self.line);
+            line_spans.span_info.add(key, span);
+        }
+    }
 
-    // Takes &mut Xot as parameter now
-    fn convert_handle(&mut self, xot: &mut Xot, handle: Handle, parent_xot_node: Node) {
-        // Use the raw pointer to the Rc Node container as the key.
-        // This is safe as long as the RcDom lives.
-        // We clear the map after conversion.
-        let node_ptr = Rc::as_ptr(&handle);
-        if self.node_map.contains_key(&node_ptr) {
-            // Avoid cycles or redundant processing
-            return;
+    fn extend_text_span(&mut self, node: Node) {
+        if let Some(line_spans) = &mut self.line_spans {
+            let span = line_spans.line_span(self.line);
+            line_spans.span_info.extend_text_span(node, span);
         }
+    }
 
-        let xot_node = match handle.data {
-            NodeData::Document => {
-                // This should be the root call, parent is the Xot document node
-                parent_xot_node
+    // Add text to an existing text node if there is one, otherwise create
+    // a new text node with `insert`.
+    fn add_text(
+        &mut self,
+        text: &str,
+        existing: Option<Node>,
+        insert: impl FnOnce(&mut Xot, Node) -> Result<(), crate::Error>,
+    ) {
+        let node = match existing.filter(|node| self.xot.is_text(*node)) {
+            Some(node) => {
+                self.xot.text_mut(node).unwrap().get_mut().push_str(text);
+                node
             }
-            NodeData::Doctype { .. } => {
-                // Xot doesn't represent doctypes explicitly in the tree
-                return;
+            None => {
+                let node = self.xot.new_text(text);
+                insert(self.xot, node).unwrap();
+                node
             }
-            NodeData::Text { ref contents } => {
-                let text_content = contents.borrow();
-                // Consolidate text nodes if possible
-                if let Some(last_child) = xot.last_child(parent_xot_node) { // Use xot parameter
-                    if xot.is_text(last_child) { // Use xot parameter
-                        // text_node itself doesn't need to be mut, only the access via text_mut
-                        let text_node = xot.text_mut(last_child).unwrap(); // Use xot parameter
-                        text_node.set(&format!("{}{}", text_node.get(), *text_content));
-                        // Map this html5ever node to the existing Xot text node
-                        self.node_map.insert(node_ptr, last_child);
-                        return; // Don't create a new node
+        };
+        self.extend_text_span(node);
+    }
+
+    // html5ever expects to be able to move nodes around, so we detach them
+    // from their old location first.
+    fn detached(&mut self, node: Node) -> Node {
+        if self.xot.parent(node).is_some() {
+            self.xot.detach(node).unwrap();
+        }
+        node
+    }
+}
+
+impl TreeSink for XotSink<'_> {
+    type Handle = Node;
+    type Output = Parsed;
+
+    fn finish(self) -> Parsed {
+        let xot = self.xot;
+        for holder in self.template_contents.into_values() {
+            xot.remove(holder).unwrap();
+        }
+        let top_level = match self.fragment_parent {
+            Some(parent) => {
+                // the parsed nodes are placed directly under the parent
+                let mut top_level = Vec::new();
+                if let Some(root) = xot.first_child(self.document) {
+                    while let Some(child) = xot.first_child(root) {
+                        xot.detach(child).unwrap();
+                        top_level.push(child);
+                        xot.append(parent, child).unwrap();
                     }
+                    xot.remove(root).unwrap();
                 }
-                // Create a new text node
-                let text_node = xot.new_text(&text_content); // Use xot parameter
-                xot.append(parent_xot_node, text_node).unwrap(); // Use xot parameter
-                text_node
+                if parent != self.document {
+                    xot.remove(self.document).unwrap();
+                }
+                top_level
             }
-            NodeData::Comment { ref contents } => {
-                let comment_node = xot.new_comment(contents); // Use xot parameter
-                xot.append(parent_xot_node, comment_node).unwrap(); // Use xot parameter
-                comment_node
+            None => xot.children(self.document).collect(),
+        };
+        for comment in self.comments {
+            // the comment may have been in dropped template contents
+            if !xot.is_removed(comment) {
+                process_comment(xot, comment, self.fragment_parent, &self.options);
             }
-            NodeData::Element {
-                ref name,
-                ref attrs,
-                ..
-            } => {
-                // Convert Atom to StrTendril for namespace lookup
-                let namespace_id = self.get_or_add_namespace_id(xot, &StrTendril::from(&*name.ns)); // Use xot parameter
-                let name_id = xot.add_name_ns(&name.local, namespace_id); // Use xot parameter
-                let element_node = xot.new_element(name_id); // Use xot parameter
-                // Append the element node to the parent FIRST.
-                xot.append(parent_xot_node, element_node).unwrap();
-
-                // If this is the html element at the document root, add the default namespace
-                // AFTER appending the element node to its parent, but before adding attributes/children.
-                if name.local.as_ref() == "html" && namespace_id == self.namespace_ids[&StrTendril::from(HTML_NS)] && xot.value_type(parent_xot_node) == crate::ValueType::Document {
-                    let html_ns = self.namespace_ids[&StrTendril::from(HTML_NS)];
-                    let namespace_value = crate::xmlvalue::Value::Namespace(crate::xmlvalue::Namespace {
-                        prefix_id: xot.empty_prefix_id,
-                        namespace_id: html_ns,
-                    });
-                    // Create the namespace node directly in the arena
-                    let namespace_node_id = xot.arena.new_node(namespace_value);
-                    // Append using the internal indextree mechanism, bypassing Xot::append validation
-                    element_node.get().append(namespace_node_id, &mut xot.arena);
-                    // No need for .expect() here as indextree::append doesn't return Result for this case
+        }
+        for noscript in self.noscripts {
+            // the element may be inside another dropped element
+            if !xot.is_removed(noscript) {
+                xot.remove(noscript).unwrap();
+            }
+        }
+        if self.options.drop_inter_element_whitespace {
+            for node in &top_level {
+                if !xot.is_removed(*node) {
+                    drop_inter_element_whitespace(xot, *node);
                 }
+            }
+        }
+        for node in top_level {
+            // text may have been consolidated with existing text
+            if !xot.is_removed(node) {
+                declare_namespaces(xot, node);
+            }
+        }
+        Parsed {
+            document: self.document,
+            errors: self.errors,
+            span_info: self.line_spans.map(|line_spans| line_spans.span_info),
+        }
+    }
 
-                // Process attributes - Stage 1: Collect data and create IDs
-                let mut collected_attrs = Vec::new();
-                for attr in attrs.borrow().iter() {
-                    // Convert Atom to StrTendril for namespace lookup
-                    let attr_ns_tendril = StrTendril::from(&*attr.name.ns);
-                    let attr_ns_id = self.get_or_add_namespace_id(xot, &attr_ns_tendril); // Use xot parameter
-                    // html5ever uses "" for no prefix, which aligns with Xot's empty_prefix_id
-                    let attr_name_id = xot.add_name_ns(&attr.name.local, attr_ns_id); // Use xot parameter
-                    collected_attrs.push((attr_name_id, attr.value.to_string()));
-                }
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.errors.push(Html5Error::new(msg, self.line as usize));
+    }
 
-                // Process attributes - Stage 2: Add to Xot node
-                if !collected_attrs.is_empty() {
-                    let mut attributes = xot.attributes_mut(element_node); // Use xot parameter
-                    for (name_id, value) in collected_attrs {
-                        attributes.insert(name_id, value);
-                    }
+    fn get_document(&mut self) -> Node {
+        self.document
+    }
+
+    fn elem_name<'b>(&'b self, target: &'b Node) -> ExpandedName<'b> {
+        self.element_names
+            .get(target)
+            .expect("not an element")
+            .expanded()
+    }
+
+    fn create_element(&mut self, name: QualName, attrs: Vec<Attribute>, flags: ElementFlags) -> Node {
+        let namespace_id = self.namespace_id(&name.ns);
+        let name_id = self.xot.add_name_ns(&name.local, namespace_id);
+        let element = self.xot.new_element(name_id);
+        for attr in attrs.into_iter().filter(|attr| !is_namespace_declaration(attr)) {
+            let namespace_id = self.namespace_id(&attr.name.ns);
+            let name_id = self.xot.add_name_ns(&attr.name.local, namespace_id);
+            self.xot
+                .attributes_mut(element)
+                .insert(name_id, attr.value.to_string());
+        }
+        if flags.mathml_annotation_xml_integration_point {
+            self.mathml_annotation_xml_integration_points
+                .insert(element);
+        }
+        if self.options.noscript == Noscript::Drop
+            && &*name.ns == HTML_NS
+            && &*name.local == "noscript"
+        {
+            self.noscripts.push(element);
+        }
+        self.element_names.insert(element, name);
+        self.add_span(SpanInfoKey::ElementStart(element));
+        element
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Node {
+        // The HTML parser turns <?target data?> into a bogus comment
+        // "?target data?"; we turn that back into a processing instruction
+        // if we can.
+        let pi = text.strip_prefix('?').and_then(|pi| {
+            let pi = pi.strip_suffix('?').unwrap_or(pi);
+            let (target, data) = pi
+                .split_once(|c: char| c.is_ascii_whitespace())
+                .unwrap_or((pi, ""));
+            new_processing_instruction(self.xot, target, data)
+        });
+        match pi {
+            Some(pi) => {
+                self.add_span(SpanInfoKey::PiTarget(pi));
+                pi
+            }
+            None => {
+                let comment = self.xot.new_comment(&text);
+                self.add_span(SpanInfoKey::Comment(comment));
+                if self.options.drop_comments
+                    || self.options.conditional_comments != ConditionalComments::Keep
+                {
+                    self.comments.push(comment);
                 }
-                element_node
+                comment
+            }
+        }
+    }
+
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Node {
+        // The HTML parser doesn't produce these. If Xot can't represent the
+        // target, we keep it as a comment like the HTML parser would.
+        match new_processing_instruction(self.xot, &target, &data) {
+            Some(pi) => {
+                self.add_span(SpanInfoKey::PiTarget(pi));
+                pi
             }
-            NodeData::ProcessingInstruction { .. } => {
-                // HTML doesn't have PIs in the same way XML does. html5ever might produce them
-                // for <?xml-stylesheet ...?>, but Xot's PI handling expects a target without a namespace.
-                // We'll ignore them for now to avoid potential mismatches.
-                // TODO: Revisit if specific PI handling is needed.
-                return;
+            None => self.create_comment(format!("?{} {}?", target, data).into()),
+        }
+    }
+
+    fn append(&mut self, parent: &Node, child: NodeOrText<Node>) {
+        match child {
+            NodeOrText::AppendNode(node) => {
+                let node = self.detached(node);
+                self.xot.append(*parent, node).unwrap();
             }
+            NodeOrText::AppendText(text) => {
+                let parent = *parent;
+                let last_child = self.xot.last_child(parent);
+                self.add_text(&text, last_child, |xot, node| xot.append(parent, node));
+            }
+        }
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Node,
+        prev_element: &Node,
+        child: NodeOrText<Node>,
+    ) {
+        if self.xot.parent(*element).is_some() {
+            self.append_before_sibling(element, child);
+        } else {
+            self.append(prev_element, child);
+        }
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        // Xot doesn't represent doctypes in the tree, but keeps them as
+        // information about the document node
+        let non_empty = |s: StrTendril| (!s.is_empty()).then(|| s.to_string());
+        self.xot.doctypes.insert(
+            self.document.get(),
+            Doctype {
+                public_id: non_empty(public_id),
+                system_id: non_empty(system_id),
+                ..Doctype::new(name.to_string())
+            },
+        );
+    }
+
+    fn get_template_contents(&mut self, target: &Node) -> Node {
+        // The HTML parser keeps the content of a template element apart
+        // from the main document. Unless we drop it, we add it to the
+        // template element itself.
+        if !self.options.drop_template_contents {
+            return *target;
+        }
+        if let Some(holder) = self.template_contents.get(target) {
+            return *holder;
+        }
+        let name = self.xot.element(*target).unwrap().name();
+        let holder = self.xot.new_element(name);
+        self.template_contents.insert(*target, holder);
+        holder
+    }
+
+    fn same_node(&self, x: &Node, y: &Node) -> bool {
+        x == y
+    }
+
+    fn set_quirks_mode(&mut self, _mode: Html5everQuirksMode) {}
+
+    fn append_before_sibling(&mut self, sibling: &Node, new_node: NodeOrText<Node>) {
+        let sibling = *sibling;
+        match new_node {
+            NodeOrText::AppendNode(node) => {
+                let node = self.detached(node);
+                self.xot.insert_before(sibling, node).unwrap();
+            }
+            NodeOrText::AppendText(text) => {
+                let previous_sibling = self.xot.previous_sibling(sibling);
+                self.add_text(&text, previous_sibling, |xot, node| {
+                    xot.insert_before(sibling, node)
+                });
+            }
+        }
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Node, attrs: Vec<Attribute>) {
+        for attr in attrs.into_iter().filter(|attr| !is_namespace_declaration(attr)) {
+            let namespace_id = self.namespace_id(&attr.name.ns);
+            let name_id = self.xot.add_name_ns(&attr.name.local, namespace_id);
+            let mut attributes = self.xot.attributes_mut(*target);
+            if !attributes.contains_key(name_id) {
+                attributes.insert(name_id, attr.value.to_string());
+            }
+        }
+    }
+
+    fn remove_from_parent(&mut self, target: &Node) {
+        self.detached(*target);
+    }
+
+    fn reparent_children(&mut self, node: &Node, new_parent: &Node) {
+        while let Some(child) = self.xot.first_child(*node) {
+            self.xot.detach(child).unwrap();
+            self.xot.append(*new_parent, child).unwrap();
+        }
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, handle: &Node) -> bool {
+        self.mathml_annotation_xml_integration_points
+            .contains(handle)
+    }
+
+    fn set_current_line(&mut self, line_number: u64) {
+        self.line = line_number;
+    }
+}
+
+// Drop or expand a comment as requested by the options.
+fn process_comment(
+    xot: &mut Xot,
+    comment: Node,
+    fragment_parent: Option<Node>,
+    options: &Html5ParseOptions,
+) {
+    let parent = xot.parent(comment).unwrap();
+    let text = xot.comment_str(comment).unwrap();
+    let inner = match conditional_comment(text) {
+        Some(inner) => match options.conditional_comments {
+            ConditionalComments::Keep if !options.drop_comments => return,
+            ConditionalComments::Expand => inner.to_string(),
+            _ => String::new(),
+        },
+        None if options.drop_comments => String::new(),
+        None => return,
+    };
+    // A document can't hold the content of a conditional comment, unless
+    // we're parsing a fragment into it.
+    if !inner.is_empty() && (!xot.is_document(parent) || fragment_parent == Some(parent)) {
+        // We parse into a holder rather than into the parent directly, as
+        // the content must end up in the place of the comment.
+        let holder = match xot.element(parent) {
+            Some(element) => xot.new_element(element.name()),
+            None => xot.new_document(),
         };
+        let context = fragment_context(xot, parent).unwrap();
+        parse_fragment(
+            XotSink::new(xot, options).fragment_into(holder),
+            options.parse_opts(),
+            context,
+            vec![],
+        )
+        .one(inner);
+        while let Some(child) = xot.first_child(holder) {
+            xot.detach(child).unwrap();
+            xot.insert_before(comment, child).unwrap();
+        }
+        xot.remove(holder).unwrap();
+    }
+    xot.remove(comment).unwrap();
+}
 
-        // Store the mapping before processing children
-        self.node_map.insert(node_ptr, xot_node);
+// If `text` is the text of an Internet Explorer conditional comment, return
+// the markup it contains. This is empty for the markers around content that
+// is revealed to other browsers, such as `<![if !IE]>` and `<![endif]>`.
+fn conditional_comment(text: &str) -> Option<&str> {
+    let text = text.trim();
+    if text == "[endif]" || text == "<![endif]" {
+        return Some("");
+    }
+    let condition = text
+        .get(..3)
+        .filter(|start| start.eq_ignore_ascii_case("[if"))
+        .map(|_| &text[3..])?;
+    match condition.split_once("]>") {
+        Some((_, inner)) => {
+            let inner = inner.strip_suffix("<![endif]").unwrap_or(inner);
+            // `<!--[if !IE]><!-->` reveals what follows to other browsers
+            Some(if inner == "<!" { "" } else { inner })
+        }
+        None => condition.ends_with(']').then_some(""),
+    }
+}
+
+// The context element to use when parsing a fragment into `parent`.
+fn fragment_context(xot: &Xot, parent: Node) -> Option<QualName> {
+    let context = if xot.is_document(parent) {
+        "body".to_string()
+    } else {
+        xot.element(parent)?;
+        html_local_name(xot, parent).unwrap_or_else(|| "body".to_string())
+    };
+    Some(QualName::new(
+        None,
+        Namespace::from(HTML_NS),
+        LocalName::from(context),
+    ))
+}
 
-        // Recursively convert children
-        for child_handle in handle.children.borrow().iter() {
-            // Pass xot down recursively
-            self.convert_handle(xot, child_handle.clone(), xot_node);
+// Xot needs namespace declarations to serialize elements and attributes in
+// a namespace. An element declares its namespace as the default namespace
+// if it differs from the namespace of its parent and isn't already in scope;
+// for instance the top-level `html` element, or an `svg` element in HTML.
+// The HTML parser only puts attributes in the XLink and XML namespaces; we
+// declare the conventional `xlink` prefix where needed.
+fn declare_namespaces(xot: &mut Xot, top: Node) {
+    let empty_prefix = xot.empty_prefix();
+    let xlink_ns = xot.add_namespace(XLINK_NS);
+    let xlink_prefix = xot.add_prefix("xlink");
+    let elements = xot
+        .descendants(top)
+        .filter(|node| xot.is_element(*node))
+        .collect::<Vec<_>>();
+    for element in elements {
+        let namespace = xot.namespace_for_name(xot.element(element).unwrap().name());
+        let parent_namespace = xot
+            .parent(element)
+            .and_then(|parent| xot.element(parent))
+            .map(|parent| xot.namespace_for_name(parent.name()));
+        if namespace != xot.no_namespace()
+            && parent_namespace != Some(namespace)
+            && xot.namespace_for_prefix(element, empty_prefix) != Some(namespace)
+        {
+            xot.namespaces_mut(element).insert(empty_prefix, namespace);
+        }
+        let has_xlink_attribute = xot
+            .attributes(element)
+            .keys()
+            .any(|name| xot.namespace_for_name(name) == xlink_ns);
+        if has_xlink_attribute && xot.prefix_for_namespace(element, xlink_ns).is_none() {
+            xot.namespaces_mut(element).insert(xlink_prefix, xlink_ns);
+        }
+    }
+}
+
+// The HTML parser keeps `xmlns` and `xmlns:*` attributes as ordinary
+// attributes. In Xot, namespace declarations follow from the names of
+// elements and attributes instead, so we leave them out.
+fn is_namespace_declaration(attr: &Attribute) -> bool {
+    (attr.name.ns.is_empty() && &*attr.name.local == "xmlns")
+        || &*attr.name.ns == XMLNS_NS
+}
+
+// Create a processing instruction node, if the target is a name that Xot can
+// represent: a valid XML name without a prefix that isn't reserved.
+fn new_processing_instruction(xot: &mut Xot, target: &str, data: &str) -> Option<Node> {
+    if target.contains(':') || target.eq_ignore_ascii_case("xml") {
+        return None;
+    }
+    let source = format!("<?{} ?>", target);
+    let mut tokenizer = xmlparser::Tokenizer::from_fragment(&source, 0..source.len());
+    match tokenizer.next() {
+        Some(Ok(xmlparser::Token::ProcessingInstruction { target: t, .. })) if t == target => {}
+        _ => return None,
+    }
+    let data = data.trim_start();
+    let target = xot.add_name(target);
+    Some(xot.new_processing_instruction(target, (!data.is_empty()).then_some(data)))
+}
+
+/// The quirks mode of an HTML document.
+///
+/// See <https://quirks.spec.whatwg.org/> for background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum QuirksMode {
+    /// Full quirks mode.
+    Quirks,
+    /// Limited quirks ("almost standards") mode.
+    LimitedQuirks,
+    /// Standards mode.
+    #[default]
+    NoQuirks,
+}
+
+impl From<QuirksMode> for html5ever::tree_builder::QuirksMode {
+    fn from(mode: QuirksMode) -> Self {
+        match mode {
+            QuirksMode::Quirks => html5ever::tree_builder::QuirksMode::Quirks,
+            QuirksMode::LimitedQuirks => html5ever::tree_builder::QuirksMode::LimitedQuirks,
+            QuirksMode::NoQuirks => html5ever::tree_builder::QuirksMode::NoQuirks,
+        }
+    }
+}
+
+/// Options for HTML parsing.
+///
+/// You can pass these to [`parse_html_with_options`] (or
+/// [`Xot::parse_html_with_options`]) to control the behavior of the html5ever
+/// tree builder. The defaults are the same as those used by [`parse_html`].
+///
+/// ```rust
+/// use xot::Xot;
+/// use xot::html5::Html5ParseOptions;
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse_html_with_options(
+///     "<noscript><p>Hello</p></noscript>",
+///     Html5ParseOptions {
+///         scripting_enabled: true,
+///         ..Default::default()
+///     },
+/// )?;
+/// # Ok::<(), xot::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Html5ParseOptions {
+    /// Whether scripting is enabled.
+    ///
+    /// This influences the way `<noscript>` is parsed: if scripting is
+    /// enabled its content is treated as raw text, otherwise it is parsed as
+    /// markup. By default scripting is disabled. See also
+    /// [`Html5ParseOptions::noscript`].
+    pub scripting_enabled: bool,
+    /// Parse the document as the content of an `iframe` `srcdoc` attribute.
+    ///
+    /// This affects the quirks mode determination for documents without a
+    /// doctype. See also [`parse_html_srcdoc`].
+    pub iframe_srcdoc: bool,
+    /// Report all parse errors described in the HTML specification.
+    ///
+    /// By default html5ever only reports a subset of parse errors, which is
+    /// faster. As HTML parsing always recovers from errors, this only changes
+    /// the error information that is collected, not the resulting tree.
+    pub exact_errors: bool,
+    /// The initial quirks mode of the tree builder.
+    ///
+    /// A doctype in the document can still change the quirks mode.
+    pub quirks_mode: QuirksMode,
+    /// Drop the content of `<template>` elements.
+    ///
+    /// The HTML parser keeps the content of a `<template>` element apart from
+    /// the main document. By default Xot adds it to the tree as the children
+    /// of the `template` element. If you set this, `template` elements are
+    /// left empty.
+    pub drop_template_contents: bool,
+    /// Drop comments.
+    ///
+    /// Comments are left out of the tree. Processing instructions, which the
+    /// HTML parser reads as comments, are kept.
+    pub drop_comments: bool,
+    /// How to handle Internet Explorer conditional comments, such as
+    /// `<!--[if IE]>...<![endif]-->`.
+    pub conditional_comments: ConditionalComments,
+    /// Drop whitespace-only text between block-level elements.
+    ///
+    /// Such whitespace isn't rendered, so dropping it makes for smaller
+    /// trees if you only want to extract data. Whitespace inside `<pre>`,
+    /// `<textarea>` and other elements that preserve whitespace is kept, as
+    /// is whitespace next to inline elements such as `<b>`.
+    pub drop_inter_element_whitespace: bool,
+    /// How to handle `<noscript>` elements.
+    pub noscript: Noscript,
+}
+
+/// How to handle `<noscript>` elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Noscript {
+    /// Parse `<noscript>` like the HTML parser does. Its content is parsed as
+    /// markup, unless [`Html5ParseOptions::scripting_enabled`] is set.
+    #[default]
+    Parse,
+    /// Keep the content of `<noscript>` as text, as if scripting were
+    /// enabled. Other than that, the document is parsed the same way.
+    Text,
+    /// Drop `<noscript>` elements along with their content.
+    Drop,
+}
+
+/// How to handle Internet Explorer conditional comments.
+///
+/// Conditional comments hide markup in a comment, such as
+/// `<!--[if lt IE 9]><script src="shiv.js"></script><![endif]-->`. The
+/// markers of "downlevel-revealed" conditional comments, such as
+/// `<![if !IE]>` and `<![endif]>`, are treated as conditional comments
+/// without content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ConditionalComments {
+    /// Keep conditional comments as comments, like any other comment.
+    #[default]
+    Keep,
+    /// Drop conditional comments.
+    Strip,
+    /// Replace conditional comments by their content, parsed as HTML in
+    /// the context of the element that contains the comment.
+    ///
+    /// Conditional comments outside of the document element are dropped.
+    Expand,
+}
+
+impl Html5ParseOptions {
+    fn parse_opts(&self) -> ParseOpts {
+        ParseOpts {
+            tokenizer: html5ever::tokenizer::TokenizerOpts {
+                exact_errors: self.exact_errors,
+                ..Default::default()
+            },
+            tree_builder: html5ever::tree_builder::TreeBuilderOpts {
+                exact_errors: self.exact_errors,
+                // scripting only affects how noscript is parsed
+                scripting_enabled: self.scripting_enabled || self.noscript == Noscript::Text,
+                iframe_srcdoc: self.iframe_srcdoc,
+                // Keep doctype initially, html5ever handles it during parsing.
+                drop_doctype: false,
+                quirks_mode: self.quirks_mode.into(),
+                ..Default::default()
+            },
         }
     }
 }
@@ -193,55 +745,712 @@ impl DomConverter {
 /// than in the XML parser. Namespace handling follows HTML5 rules (e.g. implicit HTML namespace).
 /// It attempts to parse potentially full documents or fragments.
 pub fn parse_html(xot: &mut Xot, html: &str) -> Result<Node, ParseError> {
-    let mut cursor = Cursor::new(html);
-    let sink = RcDom::default();
-    let parse_opts = ParseOpts {
-        tree_builder: html5ever::tree_builder::TreeBuilderOpts {
-            scripting_enabled: false,
-            iframe_srcdoc: false,
-            // Keep doctype initially, html5ever handles it during parsing.
-            drop_doctype: false,
+    parse_html_with_options(xot, html, Html5ParseOptions::default())
+}
+
+/// Parses an HTML string into a Xot document node, with options.
+///
+/// This is like [`parse_html`], but lets you control the parser using
+/// [`Html5ParseOptions`].
+pub fn parse_html_with_options(
+    xot: &mut Xot,
+    html: &str,
+    options: Html5ParseOptions,
+) -> Result<Node, ParseError> {
+    // We ignore html5ever parser errors here, as HTML parsing always
+    // recovers. Use `parse_html_lenient` to get at them.
+    let parsed = parse_document(XotSink::new(xot, &options), options.parse_opts()).one(html);
+    Ok(parsed.document)
+}
+
+/// An error the HTML parser recovered from.
+///
+/// HTML5 parsing never fails: the parser recovers from errors in the
+/// input. These recovered errors are reported by [`parse_html_lenient`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Html5Error {
+    /// A description of the problem, as reported by html5ever.
+    pub message: String,
+    /// The line (1-based) on which the parser encountered the problem.
+    pub line: usize,
+    /// The column (1-based) on which the parser encountered the problem.
+    ///
+    /// html5ever only tracks lines, so this is currently always `None`.
+    pub column: Option<usize>,
+    /// The code of the parse error as defined by the HTML specification,
+    /// such as `duplicate-attribute`, if it could be determined.
+    pub code: Option<&'static str>,
+}
+
+impl Html5Error {
+    fn new(message: Cow<'static, str>, line: usize) -> Self {
+        let code = parse_error_code(&message);
+        Html5Error {
+            message: message.into_owned(),
+            line,
+            column: None,
+            code,
+        }
+    }
+
+    /// The byte range in `html` of the line on which the error occurred.
+    ///
+    /// `html` must be the source the error was reported for. This lets
+    /// you point at the offending input.
+    pub fn span(&self, html: &str) -> Span {
+        LineSpans::new(html).line_span(self.line as u64)
+    }
+}
+
+impl std::fmt::Display for Html5Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+// Map the messages html5ever uses to the parse error codes of the HTML
+// specification. Most of its messages don't correspond to a single code.
+fn parse_error_code(message: &str) -> Option<&'static str> {
+    Some(match message {
+        "Duplicate attribute" => "duplicate-attribute",
+        "Attributes on an end tag" => "end-tag-with-attributes",
+        "Self-closing end tag" => "end-tag-with-trailing-solidus",
+        "Unacknowledged self-closing tag" => {
+            "non-void-html-element-start-tag-with-trailing-solidus"
+        }
+        "Numeric character reference without digits" => {
+            "absence-of-digits-in-numeric-character-reference"
+        }
+        _ => return None,
+    })
+}
+
+/// Parses an HTML string into a Xot document node, collecting parse errors.
+///
+/// HTML parsing recovers from errors in the input, so this always produces a
+/// document. The errors the parser recovered from are returned as
+/// [`Html5Error`] values, so you can report them without discarding the
+/// document.
+///
+/// ```rust
+/// use xot::Xot;
+///
+/// let mut xot = Xot::new();
+/// let (root, errors) = xot.parse_html_lenient("<p>Unclosed <b>bold</p>");
+/// assert!(xot.document_element(root).is_ok());
+/// assert!(!errors.is_empty());
+/// assert_eq!(errors[0].line, 1);
+/// ```
+pub fn parse_html_lenient(xot: &mut Xot, html: &str) -> (Node, Vec<Html5Error>) {
+    parse_html_lenient_with_options(xot, html, Html5ParseOptions::default())
+}
+
+/// Parses an HTML string into a Xot document node with options, collecting
+/// parse errors.
+///
+/// This is like [`parse_html_lenient`], but lets you control the parser using
+/// [`Html5ParseOptions`]. Set [`Html5ParseOptions::exact_errors`] to get
+/// more detailed errors.
+pub fn parse_html_lenient_with_options(
+    xot: &mut Xot,
+    html: &str,
+    options: Html5ParseOptions,
+) -> (Node, Vec<Html5Error>) {
+    let parsed = parse_document(XotSink::new(xot, &options), options.parse_opts()).one(html);
+    (parsed.document, parsed.errors)
+}
+
+/// Parses the `srcdoc` attribute of an `iframe` into a Xot document node.
+///
+/// An `iframe` can embed a document in its `srcdoc` attribute. Such a
+/// document is parsed in the iframe `srcdoc` mode of the HTML parser: it
+/// doesn't need a doctype to be parsed in no-quirks mode.
+///
+/// ```rust
+/// use xot::Xot;
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse_html(r#"<iframe srcdoc="<p>Hello</p>"></iframe>"#)?;
+/// let iframe = xot.select_first(root, "iframe")?.unwrap();
+/// let srcdoc_name = xot.add_name("srcdoc");
+/// let srcdoc = xot.get_attribute(iframe, srcdoc_name).unwrap().to_string();
+/// let embedded = xot.parse_html_srcdoc(&srcdoc)?;
+/// assert!(xot.select_first(embedded, "body > p")?.is_some());
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub fn parse_html_srcdoc(xot: &mut Xot, srcdoc: &str) -> Result<Node, ParseError> {
+    parse_html_with_options(
+        xot,
+        srcdoc,
+        Html5ParseOptions {
+            iframe_srcdoc: true,
             ..Default::default()
         },
-        ..Default::default()
-    };
+    )
+}
 
-    // Use parse_document. It returns the RcDom (sink) after reading.
-    let sink = parse_document(sink, parse_opts)
+/// Parses HTML from a reader into a Xot document node.
+///
+/// The input is fed to the HTML tokenizer incrementally as it is read, so
+/// the source document never has to be loaded into memory as a whole. The
+/// input must be UTF-8; invalid sequences are replaced by U+FFFD.
+///
+/// An error is only returned if reading fails.
+///
+/// ```rust
+/// use xot::Xot;
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse_html_read("<p>Hello</p>".as_bytes())?;
+/// assert!(xot.to_string(root)?.contains("<p>Hello</p>"));
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub fn parse_html_read(xot: &mut Xot, mut reader: impl Read) -> Result<Node, Error> {
+    let options = Html5ParseOptions::default();
+    let parsed = parse_document(XotSink::new(xot, &options), options.parse_opts())
         .from_utf8()
-        .read_from(&mut cursor)
-        .map_err(|e| ParseError::HtmlParse(vec![e.to_string()]))?; // Map IO error
+        .read_from(&mut reader)
+        .map_err(|e| Error::Io(e.to_string()))?;
+    Ok(parsed.document)
+}
+
+/// A push parser for HTML.
+///
+/// This lets you parse HTML that arrives in chunks, for instance over a
+/// network connection, without having to collect it first. You feed the
+/// chunks to the parser with [`Html5Parser::feed`] as they arrive; the
+/// document is built as they're parsed. Once the input ends, you call
+/// [`Html5Parser::finish`] to get the document node.
+///
+/// The input must be UTF-8; invalid sequences are replaced by U+FFFD. A
+/// chunk may end in the middle of a UTF-8 sequence. If you need encoding
+/// detection, collect the bytes and use [`parse_html_bytes`] instead.
+///
+/// ```rust
+/// use xot::Xot;
+/// use xot::html5::Html5Parser;
+///
+/// let mut xot = Xot::new();
+/// let mut parser = Html5Parser::new(&mut xot);
+/// parser.feed(b"<p>Hel");
+/// parser.feed(b"lo</p>");
+/// let root = parser.finish();
+/// assert!(xot.to_string(root)?.contains("<p>Hello</p>"));
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub struct Html5Parser<'a> {
+    parser: Utf8LossyDecoder<html5ever::driver::Parser<XotSink<'a>>>,
+}
+
+impl<'a> Html5Parser<'a> {
+    /// Create a new push parser that builds a document in `xot`.
+    pub fn new(xot: &'a mut Xot) -> Self {
+        Self::with_options(xot, Html5ParseOptions::default())
+    }
+
+    /// Create a new push parser, with options.
+    pub fn with_options(xot: &'a mut Xot, options: Html5ParseOptions) -> Self {
+        let parser = parse_document(XotSink::new(xot, &options), options.parse_opts()).from_utf8();
+        Self { parser }
+    }
+
+    /// Feed the next chunk of input to the parser.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.parser.process(ByteTendril::from_slice(bytes));
+    }
+
+    /// Signal the end of the input, and get the document node.
+    pub fn finish(self) -> Node {
+        self.parser.finish().document
+    }
+}
+
+/// Parses bytes containing HTML into a Xot document node.
+///
+/// This determines the encoding of the bytes using the encoding sniffing
+/// algorithm of the HTML standard: a byte order mark takes precedence,
+/// followed by a `<meta charset>` (or `<meta http-equiv="Content-Type">`)
+/// declaration in the first 1024 bytes. Without either, UTF-8 is used if
+/// the bytes are valid UTF-8, and windows-1252 otherwise.
+///
+/// The detected encoding is recorded on the document; you can get it with
+/// [`Xot::encoding`](`crate::Xot::encoding`).
+///
+/// ```rust
+/// use xot::Xot;
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse_html_bytes(b"<meta charset=\"iso-8859-1\"><p>\xe9</p>")?;
+/// assert_eq!(xot.encoding(root), Some("windows-1252"));
+/// assert!(xot.to_string(root)?.contains("<p>\u{e9}</p>"));
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub fn parse_html_bytes(xot: &mut Xot, bytes: &[u8]) -> Result<Node, ParseError> {
+    let encoding = html_encoding(bytes);
+    let (html, _) = encoding.decode_with_bom_removal(bytes);
+    let node = parse_html(xot, &html)?;
+    xot.encodings.insert(node.get(), encoding);
+    Ok(node)
+}
+
+/// Parses an HTML fragment in the context of an element.
+///
+/// This uses the HTML fragment parsing algorithm: the fragment is parsed
+/// as if it were the content of an HTML element named `context`. This
+/// matters, as for instance `<td>` is only recognized in the context of
+/// `tr`. The result is a document node which contains the parsed nodes
+/// directly; no `html` or `body` element is synthesized around them. Like
+/// with [`Xot::parse_fragment`](`crate::Xot::parse_fragment`) there can be
+/// multiple elements and text at the top level.
+///
+/// ```rust
+/// use xot::Xot;
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse_html_fragment("tr", "<td>a</td><td>b</td>")?;
+/// assert_eq!(xot.children(root).count(), 2);
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub fn parse_html_fragment(xot: &mut Xot, context: &str, html: &str) -> Result<Node, ParseError> {
+    // HTML tag names are case-insensitive
+    let context_name = QualName::new(
+        None,
+        Namespace::from(HTML_NS),
+        LocalName::from(context.to_ascii_lowercase()),
+    );
+    let options = Html5ParseOptions::default();
+    let parsed = parse_fragment(
+        XotSink::new(xot, &options).fragment(),
+        options.parse_opts(),
+        context_name,
+        vec![],
+    )
+    .one(html);
+    Ok(parsed.document)
+}
+
+/// Parses an HTML fragment and appends the result to an existing node.
+///
+/// This is useful to assemble a document from multiple HTML snippets. The
+/// fragment is parsed using the HTML fragment parsing algorithm, like
+/// [`parse_html_fragment`]. If `parent` is an HTML element, its local name is
+/// used as the context, so that for instance `<td>` is recognized when
+/// `parent` is a `tr` element. Otherwise the fragment is parsed in the context
+/// of `body`.
+///
+/// The parsed nodes are appended as the last children of `parent`. HTML
+/// elements at the top level of the fragment declare the HTML namespace as the
+/// default namespace, unless it's already the default namespace of `parent`.
+///
+/// This returns an error if `parent` isn't an element or a document node.
+///
+/// ```rust
+/// use xot::Xot;
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse_html("<ul></ul>")?;
+/// let ul = xot.select_first(root, "ul")?.unwrap();
+/// xot.parse_html_into(ul, "<li>a</li>")?;
+/// xot.parse_html_into(ul, "<li>b</li>")?;
+/// assert_eq!(xot.to_string(ul)?, r#"<ul xmlns="http://www.w3.org/1999/xhtml"><li>a</li><li>b</li></ul>"#);
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub fn parse_html_into(xot: &mut Xot, parent: Node, html: &str) -> Result<(), Error> {
+    let context_name = fragment_context(xot, parent).ok_or_else(|| {
+        Error::InvalidOperation("Can only parse HTML into an element or document node".into())
+    })?;
+    let options = Html5ParseOptions::default();
+    parse_fragment(
+        XotSink::new(xot, &options).fragment_into(parent),
+        options.parse_opts(),
+        context_name,
+        vec![],
+    )
+    .one(html);
+    Ok(())
+}
+
+/// Parses an HTML string into a Xot document node, with span information.
+///
+/// This is like [`parse_html`], but also returns [`SpanInfo`] describing
+/// where elements, text, comments and processing instructions are located
+/// in the source, like
+/// [`Xot::parse_with_span_info`](`crate::Xot::parse_with_span_info`) does.
+///
+/// The HTML parser only reports line numbers, so the spans are not as
+/// precise as those of the XML parser: a span covers the whole line (or
+/// lines, for text) on which the parser encountered the node, without the
+/// line break. For elements this is the line where the start tag ends.
+/// Elements that are implied by the HTML parser, such as a missing `<body>`,
+/// get the span of the line of the content that caused them to be created.
+/// No spans are recorded for attributes.
+///
+/// ```rust
+/// use xot::{SpanInfoKey, Xot};
+///
+/// let mut xot = Xot::new();
+/// let html = "<html>\n<body>\n<p>Hello</p>\n</body>\n</html>";
+/// let (root, span_info) = xot.parse_html_with_span_info(html)?;
+/// let p = xot.select_first(root, "p")?.unwrap();
+/// let span = span_info.get(SpanInfoKey::ElementStart(p)).unwrap();
+/// assert_eq!(&html[span.range()], "<p>Hello</p>");
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub fn parse_html_with_span_info(
+    xot: &mut Xot,
+    html: &str,
+) -> Result<(Node, SpanInfo), ParseError> {
+    let options = Html5ParseOptions::default();
+    let sink = XotSink::new(xot, &options).with_line_spans(html);
+    let parsed = parse_document(sink, options.parse_opts()).one(html);
+    Ok((parsed.document, parsed.span_info.unwrap_or_else(SpanInfo::new)))
+}
+
+// Attributes that contain a URL, with the HTML elements they're on.
+const URL_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("action", &["form"]),
+    ("background", &["body"]),
+    ("cite", &["blockquote", "del", "ins", "q"]),
+    ("data", &["object"]),
+    ("formaction", &["button", "input"]),
+    ("href", &["a", "area", "base", "link"]),
+    ("longdesc", &["frame", "iframe", "img"]),
+    ("poster", &["video"]),
+    (
+        "src",
+        &[
+            "audio", "embed", "frame", "iframe", "img", "input", "script", "source", "track",
+            "video",
+        ],
+    ),
+    ("srcset", &["img", "source"]),
+];
+
+/// Resolves and rewrites the URLs in an HTML tree.
+///
+/// This looks for attributes of HTML elements that contain URLs, such as
+/// `href` on `a` and `link`, `src` on `img` and `script`, `poster` on
+/// `video` and each of the candidate URLs in `srcset`. Each URL is resolved
+/// against the base URL of the document, and then passed to `f`. The
+/// attribute is updated with the URL that `f` returns.
+///
+/// The base URL of the document is `base`, or if the tree contains a `<base
+/// href>` element, its `href` resolved against `base`. `base` must be an
+/// absolute URL; if it's not, relative URLs are passed to `f` unresolved.
+///
+/// URLs are resolved following RFC 3986. Only HTML elements are considered,
+/// so URLs in SVG and MathML content are left alone.
+///
+/// To make all URLs absolute, return the resolved URL as is:
+///
+/// ```rust
+/// use xot::Xot;
+/// use xot::html5::rewrite_urls;
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse_html(r#"<a href="../b.html">b</a><img src="c.png" srcset="c.png 1x, c2.png 2x">"#)?;
+/// rewrite_urls(&mut xot, root, "https://example.com/a/index.html", |url| url.to_string());
+/// let a = xot.select_first(root, "a")?.unwrap();
+/// let href = xot.add_name("href");
+/// assert_eq!(xot.get_attribute(a, href), Some("https://example.com/b.html"));
+/// let img = xot.select_first(root, "img")?.unwrap();
+/// let srcset = xot.add_name("srcset");
+/// assert_eq!(
+///     xot.get_attribute(img, srcset),
+///     Some("https://example.com/a/c.png 1x, https://example.com/a/c2.png 2x")
+/// );
+/// # Ok::<(), xot::Error>(())
+/// ```
+///
+/// A proxy could route all URLs through itself:
+///
+/// ```rust
+/// use xot::Xot;
+/// use xot::html5::rewrite_urls;
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse_html(r#"<a href="/page">page</a>"#)?;
+/// rewrite_urls(&mut xot, root, "https://example.com", |url| {
+///     format!("https://proxy.example.org/?url={}", url)
+/// });
+/// let a = xot.select_first(root, "a")?.unwrap();
+/// let href = xot.add_name("href");
+/// assert_eq!(
+///     xot.get_attribute(a, href),
+///     Some("https://proxy.example.org/?url=https://example.com/page")
+/// );
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub fn rewrite_urls(xot: &mut Xot, node: Node, base: &str, mut f: impl FnMut(&str) -> String) {
+    let base = match crate::HtmlDocument::new(xot, node).base_href() {
+        Some(base_href) => {
+            crate::url::resolve(base, base_href.trim()).unwrap_or_else(|| base.to_string())
+        }
+        None => base.to_string(),
+    };
+    let resolve = |url: &str| {
+        let url = url.trim_matches(|c: char| c.is_ascii_whitespace());
+        crate::url::resolve(&base, url).unwrap_or_else(|| url.to_string())
+    };
+
+    let mut updates = Vec::new();
+    for element in xot.descendants(node) {
+        let Some(element_name) = html_local_name(xot, element) else {
+            continue;
+        };
+        for (name, value) in xot.attributes(element).iter() {
+            if xot.namespace_for_name(name) != xot.no_namespace() {
+                continue;
+            }
+            let attribute_name = xot.local_name_str(name).to_ascii_lowercase();
+            let is_url = URL_ATTRIBUTES.iter().any(|(attribute, elements)| {
+                *attribute == attribute_name && elements.contains(&element_name.as_str())
+            });
+            if !is_url {
+                continue;
+            }
+            let value = if attribute_name == "srcset" {
+                rewrite_srcset(value, |url| f(&resolve(url)))
+            } else {
+                f(&resolve(value))
+            };
+            updates.push((element, name, value));
+        }
+    }
+    for (element, name, value) in updates {
+        xot.attributes_mut(element).insert(name, value);
+    }
+}
 
-    // We ignore html5ever parser errors for now, as it can be noisy.
-    // We rely on the conversion process and subsequent checks to validate the structure.
+// Rewrite the URLs in a srcset attribute, which contains comma-separated
+// image candidates: a URL optionally followed by descriptors such as `2x`
+// or `100w`.
+fn rewrite_srcset(srcset: &str, mut f: impl FnMut(&str) -> String) -> String {
+    let is_whitespace = |c: char| c.is_ascii_whitespace();
+    let mut candidates = Vec::new();
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| is_whitespace(c) || c == ',');
+        if rest.is_empty() {
+            break;
+        }
+        let end = rest.find(is_whitespace).unwrap_or(rest.len());
+        let (url, after) = rest.split_at(end);
+        // a URL that ends with a comma has no descriptors
+        let (url, descriptors) = if url.ends_with(',') {
+            rest = after;
+            (url.trim_end_matches(','), "")
+        } else {
+            let end = after.find(',').unwrap_or(after.len());
+            rest = &after[end..];
+            (url, after[..end].trim())
+        };
+        let url = f(url);
+        if descriptors.is_empty() {
+            candidates.push(url);
+        } else {
+            candidates.push(format!("{} {}", url, descriptors));
+        }
+    }
+    candidates.join(", ")
+}
 
-    // Create the Xot document node
-    let document_node = xot.new_document();
-    let mut converter = DomConverter::new(xot);
+/// Data extracted from an HTML `<table>` element.
+///
+/// See [`tables`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Table {
+    /// The column headers.
+    ///
+    /// If there are multiple header rows, the headers of a column are
+    /// joined with a space. This is empty if the table has no header rows.
+    pub headers: Vec<String>,
+    /// The rows of the table, each a list of cell texts.
+    pub rows: Vec<Vec<String>>,
+}
 
-    // Convert the entire parsed document, starting from the RcDom's document handle
-    converter.convert_handle(xot, sink.document.clone(), document_node);
+// The local name of an HTML element, in lowercase
+fn html_local_name(xot: &Xot, node: Node) -> Option<String> {
+    let name = xot.element(node)?.name();
+    let namespace = xot.namespace_str(xot.namespace_for_name(name));
+    (namespace.is_empty() || namespace == HTML_NS)
+        .then(|| xot.local_name_str(name).to_ascii_lowercase())
+}
 
-    // Clear the map after conversion is fully done
-    converter.node_map.clear();
+fn is_html_element(xot: &Xot, node: Node, local_name: &str) -> bool {
+    html_local_name(xot, node).is_some_and(|name| name == local_name)
+}
 
-    // Basic validation: Check if *any* element was added under the document node.
-    // This is a minimal check; specific tests should verify the actual structure.
-    if xot.first_child(document_node).is_none() {
-         // If the document is completely empty after conversion (ignoring comments/PIs),
-         // it might indicate a severe parsing issue not caught by html5ever's error recovery.
-         // Or it could genuinely be an empty input. Returning an error might be too strict.
-         // For now, we allow empty results, tests should specify expected content.
-         // Consider adding a more specific error if an empty result is always invalid.
+/// Extracts the data from the HTML tables in a tree.
+///
+/// This returns a [`Table`] for each `<table>` element in `node` (including
+/// `node` itself), in document order. Nested tables are returned as separate
+/// tables, and their content is also part of the text of the cell that
+/// contains them.
+///
+/// The header rows are the rows in `<thead>`, or if there is no `<thead>`,
+/// the leading rows that contain only `<th>` cells. The rows of `<tfoot>`
+/// come after the other rows. The text of a cell is extracted like
+/// [`Xot::html_text`](`crate::Xot::html_text`) does, with surrounding
+/// whitespace removed.
+///
+/// Cells that span multiple columns or rows with `colspan` and `rowspan`
+/// are repeated in each column and row they span, so that all rows have the
+/// same number of cells. Rows that are shorter than the table is wide are
+/// padded with empty strings.
+///
+/// ```rust
+/// use xot::Xot;
+/// use xot::html5::tables;
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse_html(r#"<table>
+///   <thead><tr><th>Name</th><th colspan="2">Size</th></tr></thead>
+///   <tbody>
+///     <tr><td rowspan="2">box</td><td>1</td><td>2</td></tr>
+///     <tr><td>3</td><td>4</td></tr>
+///   </tbody>
+/// </table>"#)?;
+/// let tables = tables(&xot, root);
+/// assert_eq!(tables[0].headers, vec!["Name", "Size", "Size"]);
+/// assert_eq!(tables[0].rows, vec![vec!["box", "1", "2"], vec!["box", "3", "4"]]);
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub fn tables(xot: &Xot, node: Node) -> Vec<Table> {
+    xot.descendants(node)
+        .filter(|node| is_html_element(xot, *node, "table"))
+        .map(|table| extract_table(xot, table))
+        .collect()
+}
+
+fn extract_table(xot: &Xot, table: Node) -> Table {
+    // the row groups of the table, and whether they are headers
+    let mut head = Vec::new();
+    let mut body = Vec::new();
+    let mut foot = Vec::new();
+    let rows = |group: Node| {
+        xot.children(group)
+            .filter(|node| is_html_element(xot, *node, "tr"))
+            .collect::<Vec<_>>()
+    };
+    for child in xot.children(table) {
+        match html_local_name(xot, child).as_deref() {
+            Some("thead") => head.push(rows(child)),
+            Some("tbody") => body.push(rows(child)),
+            Some("tfoot") => foot.push(rows(child)),
+            // rows directly in the table form a group together
+            Some("tr") => match body.last_mut() {
+                Some(group) if xot.previous_sibling(child).is_some_and(|previous| {
+                    group.last() == Some(&previous)
+                }) =>
+                {
+                    group.push(child)
+                }
+                _ => body.push(vec![child]),
+            },
+            _ => {}
+        }
+    }
+
+    let cells = |row: Node| {
+        xot.children(row)
+            .filter(|node| is_html_element(xot, *node, "td") || is_html_element(xot, *node, "th"))
+            .collect::<Vec<_>>()
+    };
+    let header_count = if head.is_empty() {
+        // without thead, leading rows with only th cells are headers
+        body.first()
+            .map(|group| {
+                group
+                    .iter()
+                    .take_while(|row| {
+                        let cells = cells(**row);
+                        !cells.is_empty()
+                            && cells.iter().all(|cell| is_html_element(xot, *cell, "th"))
+                    })
+                    .count()
+            })
+            .unwrap_or(0)
+    } else {
+        head.iter().map(|group| group.len()).sum()
+    };
+
+    let span = |cell: Node, name: &str, default: usize| {
+        let name = xot.name(name);
+        name.and_then(|name| xot.get_attribute(cell, name))
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .unwrap_or(default)
+    };
+
+    let mut grid: Vec<Vec<Option<String>>> = Vec::new();
+    for group in head.into_iter().chain(body).chain(foot) {
+        let start = grid.len();
+        let end = start + group.len();
+        grid.resize(end, Vec::new());
+        for (i, row) in group.into_iter().enumerate() {
+            let r = start + i;
+            let mut column = 0;
+            for cell in cells(row) {
+                while grid[r].get(column).is_some_and(|cell| cell.is_some()) {
+                    column += 1;
+                }
+                let colspan = span(cell, "colspan", 1).clamp(1, 1000);
+                // a rowspan of 0 extends to the end of the row group
+                let rowspan = match span(cell, "rowspan", 1) {
+                    0 => end - r,
+                    rowspan => rowspan.min(end - r),
+                };
+                let text = crate::htmltext::html_text(xot, cell).trim().to_string();
+                for spanned_row in &mut grid[r..r + rowspan] {
+                    if spanned_row.len() < column + colspan {
+                        spanned_row.resize(column + colspan, None);
+                    }
+                    for spanned_cell in &mut spanned_row[column..column + colspan] {
+                        *spanned_cell = Some(text.clone());
+                    }
+                }
+                column += colspan;
+            }
+        }
     }
 
-    Ok(document_node)
+    let width = grid.iter().map(|row| row.len()).max().unwrap_or(0);
+    let mut rows = grid
+        .into_iter()
+        .map(|row| {
+            let mut row = row
+                .into_iter()
+                .map(|cell| cell.unwrap_or_default())
+                .collect::<Vec<_>>();
+            row.resize(width, String::new());
+            row
+        })
+        .collect::<Vec<_>>();
+    let header_rows = rows.drain(..header_count).collect::<Vec<_>>();
+    let headers = if header_rows.is_empty() {
+        Vec::new()
+    } else {
+        (0..width)
+            .map(|column| {
+                let mut texts: Vec<&str> = Vec::new();
+                for row in &header_rows {
+                    let text = row[column].as_str();
+                    if !text.is_empty() && texts.last() != Some(&text) {
+                        texts.push(text);
+                    }
+                }
+                texts.join(" ")
+            })
+            .collect()
+    };
+    Table { headers, rows }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::Xot; // Import Xot for testing
+    use rstest::rstest;
 
     #[test]
     fn test_parse_html_simple_fragment() {
@@ -316,4 +1525,709 @@ mod tests {
         assert_eq!(xot.local_name_str(name), "html");
         assert_eq!(xot.namespace_str(xot.namespace_for_name(name)), HTML_NS);
     }
+
+    #[test]
+    fn test_parse_html_with_options_scripting() {
+        let html = "<body><noscript><p>Hi</p></noscript></body>";
+
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_with_options(html, Html5ParseOptions::default())
+            .unwrap();
+        let html_ns = xot.add_namespace(HTML_NS);
+        let p = xot.add_name_ns("p", html_ns);
+        assert!(xot
+            .descendants(root)
+            .any(|node| xot.element(node).is_some_and(|e| e.name() == p)));
+
+        let root = xot
+            .parse_html_with_options(
+                html,
+                Html5ParseOptions {
+                    scripting_enabled: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        // with scripting enabled, noscript content is raw text
+        assert!(!xot
+            .descendants(root)
+            .any(|node| xot.element(node).is_some_and(|e| e.name() == p)));
+        assert!(xot
+            .descendants(root)
+            .any(|node| xot.text_str(node) == Some("<p>Hi</p>")));
+    }
+
+    #[test]
+    fn test_parse_html_default_options_same_as_parse_html() {
+        let html = "<p>Hello <b>world</b></p>";
+        let mut xot = Xot::new();
+        let a = xot.parse_html(html).unwrap();
+        let b = xot
+            .parse_html_with_options(
+                html,
+                Html5ParseOptions {
+                    exact_errors: true,
+                    quirks_mode: QuirksMode::Quirks,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(xot.to_string(a).unwrap(), xot.to_string(b).unwrap());
+    }
+
+    #[test]
+    fn test_parse_html_lenient_warnings() {
+        let mut xot = Xot::new();
+        let (root, warnings) = xot.parse_html_lenient("<p>Unclosed <b>bold</p>");
+        assert!(!warnings.is_empty());
+        // the document is still there
+        let html_ns = xot.add_namespace(HTML_NS);
+        let b = xot.add_name_ns("b", html_ns);
+        assert!(xot
+            .descendants(root)
+            .any(|node| xot.element(node).is_some_and(|e| e.name() == b)));
+    }
+
+    #[test]
+    fn test_parse_html_lenient_no_warnings() {
+        let mut xot = Xot::new();
+        let (_, warnings) =
+            xot.parse_html_lenient("<!DOCTYPE html><html><head></head><body></body></html>");
+        assert_eq!(warnings, vec![]);
+    }
+
+    #[test]
+    fn test_parse_html_fragment_table_context() {
+        let mut xot = Xot::new();
+        let root = xot.parse_html_fragment("tr", "<td>a</td><td>b</td>").unwrap();
+        let html_ns = xot.add_namespace(HTML_NS);
+        let td = xot.add_name_ns("td", html_ns);
+        let children = xot.children(root).collect::<Vec<_>>();
+        assert_eq!(children.len(), 2);
+        for child in children {
+            assert_eq!(xot.element(child).unwrap().name(), td);
+        }
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            r#"<td xmlns="http://www.w3.org/1999/xhtml">a</td><td xmlns="http://www.w3.org/1999/xhtml">b</td>"#
+        );
+    }
+
+    #[test]
+    fn test_parse_html_fragment_uppercase_context() {
+        let mut xot = Xot::new();
+        let root = xot.parse_html_fragment("TR", "<td>x</td>").unwrap();
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            r#"<td xmlns="http://www.w3.org/1999/xhtml">x</td>"#
+        );
+    }
+
+    #[test]
+    fn test_parse_html_fragment_body_context() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_fragment("body", "text <p>para</p> tail")
+            .unwrap();
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            r#"text <p xmlns="http://www.w3.org/1999/xhtml">para</p> tail"#
+        );
+    }
+
+    #[test]
+    fn test_parse_html_fragment_td_outside_table_context() {
+        // without a table context, td tags are dropped
+        let mut xot = Xot::new();
+        let root = xot.parse_html_fragment("body", "<td>a</td>").unwrap();
+        assert_eq!(xot.to_string(root).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_parse_html_read_chunked() {
+        // a paragraph that spans many read chunks
+        let text = "x".repeat(100_000);
+        let html = format!("<p>{}</p><p>after</p>", text);
+        let mut xot = Xot::new();
+        let root = xot.parse_html_read(html.as_bytes()).unwrap();
+        let html_ns = xot.add_namespace(HTML_NS);
+        let p = xot.add_name_ns("p", html_ns);
+        let paragraphs = xot
+            .descendants(root)
+            .filter(|node| xot.element(*node).is_some_and(|e| e.name() == p))
+            .collect::<Vec<_>>();
+        assert_eq!(paragraphs.len(), 2);
+        assert_eq!(xot.text_content_str(paragraphs[0]), Some(text.as_str()));
+    }
+
+    #[test]
+    fn test_parse_html_read_error() {
+        struct FailingReader;
+        impl Read for FailingReader {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("broken"))
+            }
+        }
+        let mut xot = Xot::new();
+        assert!(matches!(
+            xot.parse_html_read(FailingReader),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_html_doctype() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html(r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01//EN" "http://www.w3.org/TR/html4/strict.dtd"><p>x</p>"#)
+            .unwrap();
+        let doctype = xot.doctype(root).unwrap();
+        assert_eq!(doctype.name, "html");
+        assert_eq!(doctype.public_id.as_deref(), Some("-//W3C//DTD HTML 4.01//EN"));
+        assert_eq!(
+            doctype.system_id.as_deref(),
+            Some("http://www.w3.org/TR/html4/strict.dtd")
+        );
+    }
+
+    #[test]
+    fn test_parse_html_no_doctype() {
+        let mut xot = Xot::new();
+        let root = xot.parse_html("<p>x</p>").unwrap();
+        assert_eq!(xot.doctype(root), None);
+    }
+
+    #[test]
+    fn test_parse_html_template_contents() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_fragment("body", "<template><li>item</li></template>")
+            .unwrap();
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            r#"<template xmlns="http://www.w3.org/1999/xhtml"><li>item</li></template>"#
+        );
+    }
+
+    #[test]
+    fn test_parse_html_drop_template_contents() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_with_options(
+                "<template><li>item</li></template>",
+                Html5ParseOptions {
+                    drop_template_contents: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(!xot.to_string(root).unwrap().contains("item"));
+    }
+
+    #[test]
+    fn test_parse_html_processing_instruction() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_fragment("body", r#"<?xml-stylesheet href="style.xsl"?><p>x</p><?empty?>"#)
+            .unwrap();
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            r#"<?xml-stylesheet href="style.xsl"?><p xmlns="http://www.w3.org/1999/xhtml">x</p><?empty?>"#
+        );
+    }
+
+    #[test]
+    fn test_parse_html_processing_instruction_fallback() {
+        // targets that cannot be represented stay comments
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_fragment("body", r#"<?xml version="1.0"?><?1nvalid?><?a:b?>"#)
+            .unwrap();
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            r#"<!--?xml version="1.0"?--><!--?1nvalid?--><!--?a:b?-->"#
+        );
+    }
+
+    #[test]
+    fn test_parse_html_with_span_info() {
+        let mut xot = Xot::new();
+        let html = "<!DOCTYPE html>\n<title>T</title>\n<p>one\ntwo</p><!-- c -->\n<div>x</div>";
+        let (root, span_info) = xot.parse_html_with_span_info(html).unwrap();
+        let lines = html.split('\n').collect::<Vec<_>>();
+
+        let p = xot.select_first(root, "p").unwrap().unwrap();
+        let span = span_info.get(SpanInfoKey::ElementStart(p)).unwrap();
+        assert_eq!(&html[span.range()], lines[2]);
+
+        // text spans multiple lines
+        let text = xot.first_child(p).unwrap();
+        let span = span_info.get(SpanInfoKey::Text(text)).unwrap();
+        assert_eq!(&html[span.range()], "<p>one\ntwo</p><!-- c -->");
+
+        let comment = xot.next_sibling(p).unwrap();
+        assert!(xot.is_comment(comment));
+        let span = span_info.get(SpanInfoKey::Comment(comment)).unwrap();
+        assert_eq!(&html[span.range()], lines[3]);
+
+        let div = xot.select_first(root, "div").unwrap().unwrap();
+        let span = span_info.get(SpanInfoKey::ElementStart(div)).unwrap();
+        assert_eq!(&html[span.range()], lines[4]);
+    }
+
+    #[test]
+    fn test_parse_html_with_span_info_same_tree() {
+        let html = "<ul>\n<li>a\n<li>b\n</ul>";
+        let mut xot = Xot::new();
+        let a = xot.parse_html(html).unwrap();
+        let (b, _) = xot.parse_html_with_span_info(html).unwrap();
+        assert_eq!(xot.to_string(a).unwrap(), xot.to_string(b).unwrap());
+    }
+
+    #[test]
+    fn test_parse_html_misnested_formatting() {
+        // the adoption agency algorithm moves nodes around
+        let mut xot = Xot::new();
+        let root = xot.parse_html_fragment("body", "<b>1<p>2</b>3</p>").unwrap();
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            r#"<b xmlns="http://www.w3.org/1999/xhtml">1</b><p xmlns="http://www.w3.org/1999/xhtml"><b>2</b>3</p>"#
+        );
+    }
+
+    #[test]
+    fn test_parse_html_foster_parenting() {
+        // text and elements in a table outside of cells are moved before it
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_fragment("body", "<table>a<b>b</b>c<tr><td>d</td></tr></table>")
+            .unwrap();
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            r#"a<b xmlns="http://www.w3.org/1999/xhtml">b</b>c<table xmlns="http://www.w3.org/1999/xhtml"><tbody><tr><td>d</td></tr></tbody></table>"#
+        );
+    }
+
+    #[test]
+    fn test_parse_html_add_attrs_if_missing() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html(r#"<html lang="en"><body class="a"><body id="b" class="c">"#)
+            .unwrap();
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            r#"<html xmlns="http://www.w3.org/1999/xhtml" lang="en"><head/><body class="a" id="b"/></html>"#
+        );
+    }
+
+    #[test]
+    fn test_parse_html_into_table_row() {
+        let mut xot = Xot::new();
+        let root = xot.parse_html("<table><tr></tr></table>").unwrap();
+        let tr = xot.select_first(root, "tr").unwrap().unwrap();
+        xot.parse_html_into(tr, "<td>a</td><td>b</td>").unwrap();
+        assert_eq!(xot.to_string(tr).unwrap(), r#"<tr xmlns="http://www.w3.org/1999/xhtml"><td>a</td><td>b</td></tr>"#);
+    }
+
+    #[test]
+    fn test_parse_html_into_non_html_parent() {
+        let mut xot = Xot::new();
+        let root = xot.parse("<doc>text </doc>").unwrap();
+        let doc = xot.document_element(root).unwrap();
+        xot.parse_html_into(doc, "more <p>para</p>").unwrap();
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            r#"<doc>text more <p xmlns="http://www.w3.org/1999/xhtml">para</p></doc>"#
+        );
+        // text is consolidated
+        assert_eq!(xot.text_str(xot.first_child(doc).unwrap()), Some("text more "));
+    }
+
+    #[test]
+    fn test_parse_html_into_text_node() {
+        let mut xot = Xot::new();
+        let root = xot.parse("<doc>text</doc>").unwrap();
+        let text = xot.first_child(xot.document_element(root).unwrap()).unwrap();
+        assert!(xot.parse_html_into(text, "<p>para</p>").is_err());
+    }
+
+    #[test]
+    fn test_parse_html_xmlns_attributes() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_fragment(
+                "body",
+                r#"<p xmlns="http://www.w3.org/1999/xhtml">a</p><svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><a xlink:href="x"/></svg>"#,
+            )
+            .unwrap();
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            r#"<p xmlns="http://www.w3.org/1999/xhtml">a</p><svg xmlns="http://www.w3.org/2000/svg"><a xmlns:xlink="http://www.w3.org/1999/xlink" xlink:href="x"/></svg>"#
+        );
+    }
+
+    #[test]
+    fn test_parse_html_foreign_content_namespaces() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html("<p><svg><circle/></svg><math><mi>x</mi></math></p>")
+            .unwrap();
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            r#"<html xmlns="http://www.w3.org/1999/xhtml"><head/><body><p><svg xmlns="http://www.w3.org/2000/svg"><circle/></svg><math xmlns="http://www.w3.org/1998/Math/MathML"><mi>x</mi></math></p></body></html>"#
+        );
+    }
+
+    #[test]
+    fn test_rewrite_urls_base_element() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html(r#"<base href="/sub/"><a href="page">a</a><form action=""></form><svg><a href="svg"/></svg>"#)
+            .unwrap();
+        let mut seen = Vec::new();
+        rewrite_urls(&mut xot, root, "http://example.com/index.html", |url| {
+            seen.push(url.to_string());
+            url.to_string()
+        });
+        assert_eq!(
+            seen,
+            vec![
+                "http://example.com/sub/",
+                "http://example.com/sub/page",
+                "http://example.com/sub/"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_srcset() {
+        let rewritten = rewrite_srcset(" a.png, b.png 2x , data:x,y 100w,,c.png,", |url| {
+            format!("[{}]", url)
+        });
+        assert_eq!(rewritten, "[a.png], [b.png] 2x, [data:x,y] 100w, [c.png]");
+    }
+
+    #[test]
+    fn test_tables_without_thead() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html(
+                "<table><tr><th>a</th><th>b</th></tr><tr><td>1</td></tr><tr><td>2</td><td>3</td><td>4</td></tr></table>",
+            )
+            .unwrap();
+        assert_eq!(
+            tables(&xot, root),
+            vec![Table {
+                headers: vec!["a".to_string(), "b".to_string(), "".to_string()],
+                rows: vec![
+                    vec!["1".to_string(), "".to_string(), "".to_string()],
+                    vec!["2".to_string(), "3".to_string(), "4".to_string()],
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_tables_no_headers() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html("<table><tr><td>1</td><th>2</th></tr></table>")
+            .unwrap();
+        let tables = tables(&xot, root);
+        assert!(tables[0].headers.is_empty());
+        assert_eq!(tables[0].rows, vec![vec!["1", "2"]]);
+    }
+
+    #[test]
+    fn test_tables_multiple_header_rows() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html(
+                r#"<table><thead><tr><th rowspan="2">Name</th><th colspan="2">Size</th></tr><tr><th>W</th><th>H</th></tr></thead></table>"#,
+            )
+            .unwrap();
+        let tables = tables(&xot, root);
+        assert_eq!(tables[0].headers, vec!["Name", "Size W", "Size H"]);
+        assert!(tables[0].rows.is_empty());
+    }
+
+    #[test]
+    fn test_tables_tfoot_and_rowspan_zero() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html(
+                r#"<table><tfoot><tr><td>total</td><td>3</td></tr></tfoot><tbody><tr><td rowspan="0">x</td><td>1</td></tr><tr><td>2</td></tr></tbody></table>"#,
+            )
+            .unwrap();
+        let tables = tables(&xot, root);
+        assert_eq!(
+            tables[0].rows,
+            vec![vec!["x", "1"], vec!["x", "2"], vec!["total", "3"]]
+        );
+    }
+
+    #[test]
+    fn test_tables_nested() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html("<table><tr><td>a<table><tr><td>b</td></tr></table></td></tr></table>")
+            .unwrap();
+        let tables = tables(&xot, root);
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].rows, vec![vec!["a\nb"]]);
+        assert_eq!(tables[1].rows, vec![vec!["b"]]);
+    }
+
+    #[test]
+    fn test_html5_parser_split_utf8() {
+        let html = "<p>caf\u{e9}</p>".as_bytes();
+        // split in the middle of the two-byte é
+        let split = html.len() - 5;
+        let mut xot = Xot::new();
+        let mut parser = Html5Parser::new(&mut xot);
+        parser.feed(&html[..split]);
+        parser.feed(&html[split..]);
+        let root = parser.finish();
+        let p = xot.select_first(root, "p").unwrap().unwrap();
+        assert_eq!(xot.text_content_str(p), Some("caf\u{e9}"));
+    }
+
+    #[test]
+    fn test_html5_parser_byte_by_byte() {
+        let html = "<!DOCTYPE html><title>t</title><ul><li>a<li>b</ul>";
+        let mut xot = Xot::new();
+        let mut parser = Html5Parser::new(&mut xot);
+        for byte in html.as_bytes() {
+            parser.feed(&[*byte]);
+        }
+        let a = parser.finish();
+        let b = xot.parse_html(html).unwrap();
+        assert_eq!(xot.to_string(a).unwrap(), xot.to_string(b).unwrap());
+        assert_eq!(xot.doctype(a), xot.doctype(b));
+    }
+
+    #[test]
+    fn test_parse_html_lenient_error_line_and_code() {
+        let html = "<!DOCTYPE html>\n<p>\n<a href=x href=y>link</a>\n</p>";
+        let mut xot = Xot::new();
+        let (_, errors) = xot.parse_html_lenient(html);
+        assert_eq!(
+            errors,
+            vec![Html5Error {
+                message: "Duplicate attribute".to_string(),
+                line: 3,
+                column: None,
+                code: Some("duplicate-attribute"),
+            }]
+        );
+        let span = errors[0].span(html);
+        assert_eq!(&html[span.start..span.end], "<a href=x href=y>link</a>");
+        assert_eq!(errors[0].to_string(), "line 3: Duplicate attribute");
+    }
+
+    #[test]
+    fn test_parse_html_lenient_error_without_code() {
+        let mut xot = Xot::new();
+        let (_, errors) = xot.parse_html_lenient("<p>Unclosed <b>bold</p>");
+        assert!(errors.iter().all(|error| error.line == 1));
+        assert!(errors.iter().any(|error| error.code.is_none()));
+    }
+
+    #[test]
+    fn test_parse_html_drop_comments() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_with_options(
+                "<p>a<!-- one -->b<?pi data?></p><!-- two -->",
+                Html5ParseOptions {
+                    drop_comments: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(!xot.descendants(root).any(|node| xot.is_comment(node)));
+        let p = xot.select_first(root, "p").unwrap().unwrap();
+        assert_eq!(
+            xot.to_string(p).unwrap(),
+            r#"<p xmlns="http://www.w3.org/1999/xhtml">ab<?pi data?></p>"#
+        );
+    }
+
+    #[rstest]
+    #[case(ConditionalComments::Keep, r#"<head xmlns="http://www.w3.org/1999/xhtml"><!--[if lt IE 9]><script src="shiv.js"></script><![endif]--><title>T</title></head>"#)]
+    #[case(ConditionalComments::Strip, r#"<head xmlns="http://www.w3.org/1999/xhtml"><title>T</title></head>"#)]
+    #[case(ConditionalComments::Expand, r#"<head xmlns="http://www.w3.org/1999/xhtml"><script src="shiv.js"/><title>T</title></head>"#)]
+    fn test_parse_html_conditional_comments(
+        #[case] conditional_comments: ConditionalComments,
+        #[case] expected: &str,
+    ) {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_with_options(
+                r#"<html><head><!--[if lt IE 9]><script src="shiv.js"></script><![endif]--><title>T</title></head></html>"#,
+                Html5ParseOptions {
+                    conditional_comments,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let head = xot.select_first(root, "head").unwrap().unwrap();
+        assert_eq!(xot.to_string(head).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_html_conditional_comments_expand_in_place() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_with_options(
+                "<table><!--[if IE]><tr><td>ie</td></tr><![endif]--><tr><td>all</td></tr></table><!-- plain -->",
+                Html5ParseOptions {
+                    conditional_comments: ConditionalComments::Expand,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        // parsed in the context of the table section
+        let cells = xot
+            .select(root, "tr > td")
+            .unwrap()
+            .map(|td| xot.text_content_str(td).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(cells, vec!["ie", "all"]);
+        // other comments are kept
+        assert!(xot
+            .descendants(root)
+            .any(|node| xot.comment_str(node) == Some(" plain ")));
+    }
+
+    #[test]
+    fn test_parse_html_conditional_comments_downlevel_revealed() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_with_options(
+                "<div><!--[if !IE]><!--><p>not ie</p><!--<![endif]--><![if !IE]>x<![endif]></div>",
+                Html5ParseOptions {
+                    conditional_comments: ConditionalComments::Expand,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let div = xot.select_first(root, "div").unwrap().unwrap();
+        assert_eq!(
+            xot.to_string(div).unwrap(),
+            r#"<div xmlns="http://www.w3.org/1999/xhtml"><p>not ie</p>x</div>"#
+        );
+    }
+
+    #[test]
+    fn test_conditional_comment() {
+        assert_eq!(conditional_comment("[if IE]><p>a</p><![endif]"), Some("<p>a</p>"));
+        assert_eq!(conditional_comment("[IF IE 8]>b<![endif]"), Some("b"));
+        assert_eq!(conditional_comment("[if !IE]><!"), Some(""));
+        assert_eq!(conditional_comment("<![endif]"), Some(""));
+        assert_eq!(conditional_comment("[if !IE]"), Some(""));
+        assert_eq!(conditional_comment("[endif]"), Some(""));
+        assert_eq!(conditional_comment(" just a comment "), None);
+        assert_eq!(conditional_comment("[iffy"), None);
+    }
+
+    #[rstest]
+    #[case("<div>\n  <p>a</p>\n  <p>b</p>\n</div>", "<div><p>a</p><p>b</p></div>")]
+    #[case("<div><b>a</b> <i>b</i></div>", "<div><b>a</b> <i>b</i></div>")]
+    #[case("<div><p>a</p> <b>b</b></div>", "<div><p>a</p> <b>b</b></div>")]
+    #[case("<div> <span>a</span></div>", "<div> <span>a</span></div>")]
+    #[case("<div> </div>", "<div> </div>")]
+    #[case("<div><pre>\n<p>a</p> <p>b</p></pre></div>", "<div><pre><p>a</p> <p>b</p></pre></div>")]
+    #[case("<div><table> <tr> <td>a</td> <td>b</td> </tr> </table></div>", "<div><table><tbody><tr><td>a</td><td>b</td></tr></tbody></table></div>")]
+    fn test_parse_html_drop_inter_element_whitespace(#[case] html: &str, #[case] expected: &str) {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_with_options(
+                html,
+                Html5ParseOptions {
+                    drop_inter_element_whitespace: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let div = xot.select_first(root, "div").unwrap().unwrap();
+        let expected = expected.replacen("<div>", r#"<div xmlns="http://www.w3.org/1999/xhtml">"#, 1);
+        assert_eq!(xot.html_string(div).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_html_drop_inter_element_whitespace_document() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_with_options(
+                "<!DOCTYPE html>\n<html>\n<head>\n<title>T</title>\n</head>\n<body>\n<p>a</p>\n</body>\n</html>\n",
+                Html5ParseOptions {
+                    drop_inter_element_whitespace: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            xot.html_string(root).unwrap(),
+            r#"<!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml"><head><title>T</title></head><body><p>a</p></body></html>"#
+        );
+    }
+
+    #[rstest]
+    #[case(Noscript::Parse, false, r#"<body xmlns="http://www.w3.org/1999/xhtml"><noscript><p>No JS</p></noscript><p>after</p></body>"#)]
+    #[case(Noscript::Parse, true, r#"<body xmlns="http://www.w3.org/1999/xhtml"><noscript>&lt;p&gt;No JS&lt;/p&gt;</noscript><p>after</p></body>"#)]
+    #[case(Noscript::Text, false, r#"<body xmlns="http://www.w3.org/1999/xhtml"><noscript>&lt;p&gt;No JS&lt;/p&gt;</noscript><p>after</p></body>"#)]
+    #[case(Noscript::Drop, false, r#"<body xmlns="http://www.w3.org/1999/xhtml"><p>after</p></body>"#)]
+    #[case(Noscript::Drop, true, r#"<body xmlns="http://www.w3.org/1999/xhtml"><p>after</p></body>"#)]
+    fn test_parse_html_noscript(
+        #[case] noscript: Noscript,
+        #[case] scripting_enabled: bool,
+        #[case] expected: &str,
+    ) {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_with_options(
+                "<body><noscript><p>No JS</p></noscript><p>after</p></body>",
+                Html5ParseOptions {
+                    noscript,
+                    scripting_enabled,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let body = xot.select_first(root, "body").unwrap().unwrap();
+        assert_eq!(xot.to_string(body).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_html_noscript_drop_in_head() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_html_with_options(
+                r#"<head><noscript><link rel="stylesheet" href="a.css"></noscript><title>T</title></head>"#,
+                Html5ParseOptions {
+                    noscript: Noscript::Drop,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let head = xot.select_first(root, "head").unwrap().unwrap();
+        assert_eq!(
+            xot.to_string(head).unwrap(),
+            r#"<head xmlns="http://www.w3.org/1999/xhtml"><title>T</title></head>"#
+        );
+    }
+
+    #[test]
+    fn test_parse_html_srcdoc_no_quirks() {
+        // in quirks mode a table doesn't close an open paragraph
+        let html = "<p><table></table>";
+        let mut xot = Xot::new();
+        let quirks = xot.parse_html(html).unwrap();
+        assert!(xot.select_first(quirks, "p > table").unwrap().is_some());
+        let srcdoc = xot.parse_html_srcdoc(html).unwrap();
+        assert!(xot.select_first(srcdoc, "p > table").unwrap().is_none());
+        assert!(xot.select_first(srcdoc, "body > table").unwrap().is_some());
+    }
 }
@@ -0,0 +1,151 @@
+use crate::xotdata::{Node, Xot};
+
+/// ## HTML classes and ids
+///
+/// These give convenient access to the `class` and `id` attributes of HTML
+/// elements. The `class` attribute is a whitespace-separated list of
+/// classes; these methods split and join it for you.
+impl Xot {
+    /// Iterate over the classes of an element.
+    ///
+    /// The classes are the whitespace-separated tokens in the `class`
+    /// attribute, in order. If the element has no `class` attribute, or the
+    /// node is not an element, this is empty.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<p class=" intro  main "/>"#)?;
+    /// let p = xot.document_element(root)?;
+    /// assert_eq!(xot.classes(p).collect::<Vec<_>>(), vec!["intro", "main"]);
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn classes(&self, node: Node) -> impl Iterator<Item = &str> + '_ {
+        self.class_attribute(node)
+            .unwrap_or("")
+            .split_ascii_whitespace()
+    }
+
+    /// Check whether an element has a class.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<p class="intro main"/>"#)?;
+    /// let p = xot.document_element(root)?;
+    /// assert!(xot.has_class(p, "main"));
+    /// assert!(!xot.has_class(p, "mai"));
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn has_class(&self, node: Node, class: &str) -> bool {
+        self.classes(node).any(|c| c == class)
+    }
+
+    /// Add a class to an element, or remove it.
+    ///
+    /// If `present` is true, `class` is added to the end of the `class`
+    /// attribute unless the element already has it. If it's false, `class` is
+    /// removed. Other classes are kept in order. The `class` attribute is
+    /// removed if no classes remain.
+    ///
+    /// Note that if this is invoked on a non-element it's going to panic.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<p class="intro"/>"#)?;
+    /// let p = xot.document_element(root)?;
+    /// xot.set_class(p, "main", true);
+    /// assert_eq!(xot.to_string(p)?, r#"<p class="intro main"/>"#);
+    /// xot.set_class(p, "intro", false);
+    /// assert_eq!(xot.to_string(p)?, r#"<p class="main"/>"#);
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn set_class(&mut self, node: Node, class: &str, present: bool) {
+        if self.has_class(node, class) == present {
+            return;
+        }
+        let mut classes = self
+            .classes(node)
+            .filter(|c| *c != class)
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if present {
+            classes.push(class.to_string());
+        }
+        let class_name = self.add_name("class");
+        if classes.is_empty() {
+            self.remove_attribute(node, class_name);
+        } else {
+            self.set_attribute(node, class_name, classes.join(" "));
+        }
+    }
+
+    /// Get the `id` attribute of an element.
+    ///
+    /// If the node is not an element or has no `id` attribute, this returns
+    /// `None`. Unlike `xml:id`, this is not checked for uniqueness.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<p id="first"/>"#)?;
+    /// let p = xot.document_element(root)?;
+    /// assert_eq!(xot.id_attr(p), Some("first"));
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn id_attr(&self, node: Node) -> Option<&str> {
+        self.get_attribute(node, self.name("id")?)
+    }
+
+    fn class_attribute(&self, node: Node) -> Option<&str> {
+        self.get_attribute(node, self.name("class")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Xot;
+
+    #[test]
+    fn test_classes_no_class_attribute() {
+        let mut xot = Xot::new();
+        let root = xot.parse("<p/>").unwrap();
+        let p = xot.document_element(root).unwrap();
+        assert_eq!(xot.classes(p).count(), 0);
+        assert_eq!(xot.classes(root).count(), 0);
+        assert_eq!(xot.id_attr(p), None);
+    }
+
+    #[test]
+    fn test_set_class_keeps_order_and_removes_duplicates() {
+        let mut xot = Xot::new();
+        let root = xot.parse(r#"<p class="a b a c"/>"#).unwrap();
+        let p = xot.document_element(root).unwrap();
+        // already present, so nothing changes
+        xot.set_class(p, "b", true);
+        assert_eq!(xot.to_string(p).unwrap(), r#"<p class="a b a c"/>"#);
+        xot.set_class(p, "a", false);
+        assert_eq!(xot.to_string(p).unwrap(), r#"<p class="b c"/>"#);
+        xot.set_class(p, "b", false);
+        xot.set_class(p, "c", false);
+        assert_eq!(xot.to_string(p).unwrap(), r#"<p/>"#);
+        xot.set_class(p, "d", true);
+        assert_eq!(xot.to_string(p).unwrap(), r#"<p class="d"/>"#);
+    }
+
+    #[test]
+    fn test_classes_namespaced_attribute_ignored() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<p xmlns:x="http://example.com" x:class="a" x:id="b"/>"#)
+            .unwrap();
+        let p = xot.document_element(root).unwrap();
+        assert!(!xot.has_class(p, "a"));
+        assert_eq!(xot.id_attr(p), None);
+    }
+}
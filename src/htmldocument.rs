@@ -0,0 +1,237 @@
+use encoding_rs::Encoding;
+
+use crate::encoding::charset_from_content;
+use crate::xotdata::{Node, Xot};
+
+const XHTML_NS: &str = "http://www.w3.org/1999/xhtml";
+
+/// Access to the metadata of an HTML document.
+///
+/// Most of this information lives in the `<head>` of an HTML document. This
+/// looks it up in the same way browsers do: the first matching element in
+/// document order wins. Elements count as HTML elements if they're in the
+/// XHTML namespace (as produced by [`Xot::parse_html`](`crate::Xot::parse_html`))
+/// or in no namespace, and their names are compared case-insensitively.
+///
+/// ```rust
+/// use xot::{HtmlDocument, Xot};
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse(r#"<html><head>
+///   <meta charset="utf-8"/>
+///   <title> My   page </title>
+///   <meta name="description" content="A page"/>
+///   <link rel="canonical" href="https://example.com/page"/>
+/// </head><body/></html>"#)?;
+///
+/// let document = HtmlDocument::new(&xot, root);
+/// assert_eq!(document.title(), Some("My page".to_string()));
+/// assert_eq!(document.meta("description"), Some("A page"));
+/// assert_eq!(document.canonical_link(), Some("https://example.com/page"));
+/// assert_eq!(document.charset(), Some("UTF-8"));
+/// assert_eq!(document.base_href(), None);
+/// # Ok::<(), xot::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct HtmlDocument<'a> {
+    xot: &'a Xot,
+    node: Node,
+}
+
+impl<'a> HtmlDocument<'a> {
+    /// Create a view on the HTML document in `node`.
+    ///
+    /// This is usually a document node, but can be any node; only it and
+    /// its descendants are searched.
+    pub fn new(xot: &'a Xot, node: Node) -> Self {
+        Self { xot, node }
+    }
+
+    fn is_html_element(&self, node: Node, local_name: &str) -> bool {
+        let Some(element) = self.xot.element(node) else {
+            return false;
+        };
+        let namespace = self
+            .xot
+            .namespace_str(self.xot.namespace_for_name(element.name()));
+        (namespace.is_empty() || namespace == XHTML_NS)
+            && self
+                .xot
+                .local_name_str(element.name())
+                .eq_ignore_ascii_case(local_name)
+    }
+
+    fn elements(&self, local_name: &'a str) -> impl Iterator<Item = Node> + 'a {
+        let document = *self;
+        self.xot
+            .descendants(self.node)
+            .filter(move |node| document.is_html_element(*node, local_name))
+    }
+
+    fn attribute(&self, node: Node, local_name: &str) -> Option<&'a str> {
+        self.xot
+            .attributes(node)
+            .iter()
+            .find(|(name, _)| {
+                self.xot.namespace_for_name(*name) == self.xot.no_namespace()
+                    && self
+                        .xot
+                        .local_name_str(*name)
+                        .eq_ignore_ascii_case(local_name)
+            })
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The title of the document.
+    ///
+    /// This is the text of the first `title` element, with leading and
+    /// trailing whitespace removed and any other whitespace collapsed into
+    /// a single space.
+    pub fn title(&self) -> Option<String> {
+        let title = self.elements("title").next()?;
+        let text = self.xot.string_value(title);
+        Some(text.split_ascii_whitespace().collect::<Vec<_>>().join(" "))
+    }
+
+    /// The content of the `<meta>` element with the given name.
+    ///
+    /// The name is compared case-insensitively, so `meta("description")`
+    /// finds `<meta name="Description" content="...">`.
+    pub fn meta(&self, name: &str) -> Option<&'a str> {
+        self.elements("meta")
+            .find(|meta| {
+                self.attribute(*meta, "name")
+                    .is_some_and(|value| value.trim().eq_ignore_ascii_case(name))
+            })
+            .and_then(|meta| self.attribute(meta, "content"))
+    }
+
+    /// The `href` of the first `<base>` element that has one.
+    ///
+    /// This is the URL against which relative URLs in the document are
+    /// resolved.
+    pub fn base_href(&self) -> Option<&'a str> {
+        self.elements("base")
+            .find_map(|base| self.attribute(base, "href"))
+    }
+
+    /// The `href` of the first `<link rel="canonical">` element.
+    pub fn canonical_link(&self) -> Option<&'a str> {
+        self.elements("link")
+            .filter(|link| {
+                self.attribute(*link, "rel").is_some_and(|rel| {
+                    rel.split_ascii_whitespace()
+                        .any(|token| token.eq_ignore_ascii_case("canonical"))
+                })
+            })
+            .find_map(|link| self.attribute(link, "href"))
+    }
+
+    /// The character encoding declared by the document.
+    ///
+    /// This is taken from the first `<meta charset>` element, or a `<meta
+    /// http-equiv="Content-Type">` element with a `charset` in its content.
+    /// It's returned as the canonical name of the encoding, so
+    /// `<meta charset="latin1">` gives `windows-1252`. Returns [`None`] if
+    /// there's no declaration or the encoding isn't known.
+    ///
+    /// This is only what the document declares. The encoding actually used
+    /// to decode the document is available with
+    /// [`Xot::encoding`](`crate::Xot::encoding`).
+    pub fn charset(&self) -> Option<&'static str> {
+        self.elements("meta").find_map(|meta| {
+            let label = if let Some(charset) = self.attribute(meta, "charset") {
+                charset.as_bytes().to_vec()
+            } else {
+                let http_equiv = self.attribute(meta, "http-equiv")?;
+                if !http_equiv.trim().eq_ignore_ascii_case("content-type") {
+                    return None;
+                }
+                let content = self.attribute(meta, "content")?.to_ascii_lowercase();
+                charset_from_content(content.as_bytes())?.to_vec()
+            };
+            Encoding::for_label(&label).map(|encoding| encoding.name())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_first_html_title() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><svg xmlns="http://www.w3.org/2000/svg"><title>svg</title></svg><TITLE>
+              a <b>b</b></TITLE><title>second</title></body></html>"#)
+            .unwrap();
+        assert_eq!(
+            HtmlDocument::new(&xot, root).title(),
+            Some("a b".to_string())
+        );
+    }
+
+    #[test]
+    fn test_no_metadata() {
+        let mut xot = Xot::new();
+        let root = xot.parse("<html><head/><body/></html>").unwrap();
+        let document = HtmlDocument::new(&xot, root);
+        assert_eq!(document.title(), None);
+        assert_eq!(document.meta("description"), None);
+        assert_eq!(document.base_href(), None);
+        assert_eq!(document.canonical_link(), None);
+        assert_eq!(document.charset(), None);
+    }
+
+    #[test]
+    fn test_meta_case_insensitive() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<html><head><meta name="author"/><META NAME="Description" CONTENT="d"/></head></html>"#)
+            .unwrap();
+        let document = HtmlDocument::new(&xot, root);
+        assert_eq!(document.meta("description"), Some("d"));
+        // a meta element without content
+        assert_eq!(document.meta("author"), None);
+    }
+
+    #[test]
+    fn test_base_href() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<html><head><base target="_blank"/><base href="/a/"/><base href="/b/"/></head></html>"#)
+            .unwrap();
+        assert_eq!(HtmlDocument::new(&xot, root).base_href(), Some("/a/"));
+    }
+
+    #[test]
+    fn test_canonical_link() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<html><head><link rel="stylesheet" href="s.css"/><link rel="Canonical alternate" href="c"/></head></html>"#)
+            .unwrap();
+        assert_eq!(HtmlDocument::new(&xot, root).canonical_link(), Some("c"));
+    }
+
+    #[test]
+    fn test_charset_http_equiv() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<html><head><meta http-equiv="Content-Type" content="text/html; Charset=ISO-8859-1"/></head></html>"#)
+            .unwrap();
+        assert_eq!(
+            HtmlDocument::new(&xot, root).charset(),
+            Some("windows-1252")
+        );
+    }
+
+    #[test]
+    fn test_charset_unknown() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<html><head><meta charset="bogus"/></head></html>"#)
+            .unwrap();
+        assert_eq!(HtmlDocument::new(&xot, root).charset(), None);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::encoding::is_html_whitespace;
+use crate::xotdata::{Node, Xot};
+
+const XHTML_NS: &str = "http://www.w3.org/1999/xhtml";
+
+// elements that aren't rendered
+pub(crate) const HIDDEN: &[&str] = &[
+    "area", "base", "basefont", "datalist", "head", "link", "meta", "noembed", "noframes", "param",
+    "rp", "script", "style", "template", "title",
+];
+
+// elements that are rendered as a block
+pub(crate) const BLOCK: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "legend",
+    "li",
+    "listing",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "optgroup",
+    "option",
+    "plaintext",
+    "pre",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "ul",
+    "xmp",
+];
+
+// elements in which whitespace is preserved
+const PREFORMATTED: &[&str] = &["listing", "plaintext", "pre", "textarea", "xmp"];
+
+struct TextBuilder {
+    text: String,
+    // the number of line breaks required before the next text
+    required_line_breaks: usize,
+    // whether collapsed whitespace precedes the next text
+    space: bool,
+}
+
+impl TextBuilder {
+    fn flush(&mut self) {
+        if !self.text.is_empty() {
+            if self.required_line_breaks > 0 {
+                for _ in 0..self.required_line_breaks {
+                    self.text.push('\n');
+                }
+            } else if self.space && !self.text.ends_with(['\n', '\t']) {
+                self.text.push(' ');
+            }
+        }
+        self.required_line_breaks = 0;
+        self.space = false;
+    }
+
+    fn require_line_breaks(&mut self, count: usize) {
+        if count > 0 {
+            self.required_line_breaks = self.required_line_breaks.max(count);
+            self.space = false;
+        }
+    }
+
+    fn push_collapsed(&mut self, text: &str) {
+        for c in text.chars() {
+            if matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0c') {
+                self.space = true;
+            } else {
+                self.flush();
+                self.text.push(c);
+            }
+        }
+    }
+
+    fn push_preserved(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.flush();
+        self.text.push_str(text);
+    }
+}
+
+// the lowercase local name of an element if it's an HTML element
+pub(crate) fn html_name(xot: &Xot, node: Node) -> Option<String> {
+    let name = xot.element(node)?.name();
+    let namespace = xot.namespace_str(xot.namespace_for_name(name));
+    if namespace.is_empty() || namespace == XHTML_NS {
+        Some(xot.local_name_str(name).to_ascii_lowercase())
+    } else {
+        None
+    }
+}
+
+fn is_element_named(xot: &Xot, node: Node, names: &[&str]) -> bool {
+    html_name(xot, node).is_some_and(|name| names.contains(&name.as_str()))
+}
+
+fn text(xot: &Xot, node: Node, preformatted: bool, builder: &mut TextBuilder) {
+    if let Some(text) = xot.text_str(node) {
+        if preformatted {
+            builder.push_preserved(text);
+        } else {
+            builder.push_collapsed(text);
+        }
+        return;
+    }
+    let name = html_name(xot, node);
+    let name = name.as_deref().unwrap_or("");
+    if HIDDEN.contains(&name) {
+        return;
+    }
+    if name == "br" {
+        builder.push_preserved("\n");
+        return;
+    }
+    let line_breaks = if name == "p" {
+        2
+    } else if BLOCK.contains(&name) {
+        1
+    } else {
+        0
+    };
+    builder.require_line_breaks(line_breaks);
+    let preformatted = preformatted || PREFORMATTED.contains(&name);
+    for child in xot.children(node) {
+        text(xot, child, preformatted, builder);
+    }
+    builder.require_line_breaks(line_breaks);
+    let next_element = || {
+        xot.following_siblings(node)
+            .skip(1)
+            .find(|n| xot.is_element(*n))
+    };
+    match name {
+        // cells are separated by tabs
+        "td" | "th" if next_element().is_some_and(|n| is_element_named(xot, n, &["td", "th"])) => {
+            builder.push_preserved("\t");
+        }
+        // rows are separated by line breaks, also across row groups
+        "tr" => builder.require_line_breaks(1),
+        _ => {}
+    }
+}
+
+// Elements around which whitespace isn't rendered, besides the block and
+// hidden elements.
+const NON_INLINE: &[&str] = &[
+    "caption", "col", "colgroup", "p", "tbody", "td", "tfoot", "th", "thead", "tr",
+];
+
+// Remove whitespace-only text between block-level elements, unless it's
+// in an element where whitespace is preserved.
+pub(crate) fn drop_inter_element_whitespace(xot: &mut Xot, top: Node) {
+    let is_block = |xot: &Xot, node: Node| {
+        if xot.is_document(node) || xot.is_comment(node) || xot.is_processing_instruction(node) {
+            return true;
+        }
+        html_name(xot, node).is_some_and(|name| {
+            let name = name.as_str();
+            BLOCK.contains(&name) || HIDDEN.contains(&name) || NON_INLINE.contains(&name)
+        })
+    };
+    let whitespace = xot
+        .descendants(top)
+        .filter(|node| {
+            xot.text_str(*node)
+                .is_some_and(|text| text.bytes().all(is_html_whitespace))
+        })
+        .filter(|node| {
+            let previous = xot.previous_sibling(*node);
+            let next = xot.next_sibling(*node);
+            let parent = xot.parent(*node);
+            let between_blocks = [previous, next]
+                .into_iter()
+                .all(|sibling| match sibling.or(parent) {
+                    Some(sibling) => is_block(xot, sibling),
+                    None => true,
+                });
+            let next_to_element = [previous, next]
+                .into_iter()
+                .flatten()
+                .any(|sibling| xot.is_element(sibling));
+            let preformatted = xot.ancestors(*node).any(|ancestor| {
+                html_name(xot, ancestor)
+                    .is_some_and(|name| PREFORMATTED.contains(&name.as_str()))
+            });
+            between_blocks && next_to_element && !preformatted
+        })
+        .collect::<Vec<_>>();
+    for node in whitespace {
+        xot.remove(node).unwrap();
+    }
+}
+
+pub(crate) fn html_text(xot: &Xot, node: Node) -> String {
+    let mut builder = TextBuilder {
+        text: String::new(),
+        required_line_breaks: 0,
+        space: false,
+    };
+    text(xot, node, false, &mut builder);
+    builder.text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("<div>a  b\n c</div>", "a b c")]
+    #[case("<div>  a <b> b </b> c  </div>", "a b c")]
+    #[case("<div>a <b>b</b> <i>c</i></div>", "a b c")]
+    #[case("<div><div>a</div><div>b</div></div>", "a\nb")]
+    #[case("<div><p>a</p><p>b</p></div>", "a\n\nb")]
+    #[case("<div>a<br/>b</div>", "a\nb")]
+    #[case("<div>a<br/><br/>b</div>", "a\n\nb")]
+    #[case("<div><pre>  a\n  b</pre>c</div>", "  a\n  b\nc")]
+    #[case("<div>a<script>b</script><style>c</style>d</div>", "ad")]
+    #[case("<html><head><title>t</title></head><body>b</body></html>", "b")]
+    #[case(
+        "<table><tr><td>a</td><td>b</td></tr><tr><th>c</th> <td>d</td></tr></table>",
+        "a\tb\nc\td"
+    )]
+    #[case("<ul><li>a</li> <li>b</li></ul>", "a\nb")]
+    #[case("<div>a&#160; b</div>", "a\u{a0} b")]
+    fn test_html_text(#[case] html: &str, #[case] expected: &str) {
+        let mut xot = Xot::new();
+        let root = xot.parse(html).unwrap();
+        assert_eq!(html_text(&xot, root), expected);
+    }
+
+    #[test]
+    fn test_html_text_xhtml_namespace() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><P>a</P><p>b</p></body></html>"#)
+            .unwrap();
+        assert_eq!(html_text(&xot, root), "a\n\nb");
+    }
+}
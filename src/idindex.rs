@@ -0,0 +1,280 @@
+use std::cmp::Ordering;
+
+use ahash::{HashMap, HashMapExt};
+
+use crate::error::Error;
+use crate::parse::normalize_xml_id;
+use crate::xmlname::NameStrInfo;
+use crate::xmlvalue::Value;
+use crate::xotdata::{Node, Xot};
+
+// the IDs of a document. See `index.rs` for how it's kept up to date.
+#[derive(Debug, Clone)]
+pub(crate) struct IdIndex {
+    pub(crate) generation: u64,
+    // the element and attribute names of the attributes the doctype
+    // declares as IDs. DTDs don't know about namespaces, so these are names
+    // as written, with prefixes.
+    declared: Vec<(String, String)>,
+    // the elements with each ID, in document order
+    ids: HashMap<String, Vec<Node>>,
+}
+
+impl IdIndex {
+    fn element_by_id(&self, id: &str) -> Option<Node> {
+        self.ids
+            .get(id)
+            .and_then(|elements| elements.first().copied())
+    }
+}
+
+/// ## ID index
+impl Xot {
+    /// Create an index of the IDs in a document, so that
+    /// [`Xot::element_by_id`] and [`Xot::duplicate_ids`] don't have to look
+    /// through the document.
+    ///
+    /// Xot keeps the index up to date as you append, insert, remove and
+    /// rename elements, and set and remove attributes. Some changes Xot
+    /// can't follow, such as those you make through [`Xot::value_mut`] or
+    /// [`Xot::attribute_node_mut`]. After these the index isn't used, until
+    /// you create it again. If the document already has an index, this
+    /// builds it again.
+    ///
+    /// Returns [`Error::NotDocument`] if `document_node` isn't a document
+    /// node.
+    pub fn create_id_index(&mut self, document_node: Node) -> Result<(), Error> {
+        if !self.is_document(document_node) {
+            return Err(Error::NotDocument(document_node));
+        }
+        let index = self.build_id_index(document_node);
+        self.id_indexes.insert(document_node.get(), index);
+        Ok(())
+    }
+
+    /// Remove the ID index of a document, to free the memory it takes, and
+    /// the time it takes to keep it up to date.
+    pub fn remove_id_index(&mut self, document_node: Node) {
+        self.id_indexes.remove(&document_node.get());
+    }
+
+    /// Get the element in a document with the given ID.
+    ///
+    /// IDs are given by `xml:id` attributes, and by attributes that the
+    /// doctype of the document declares with type `ID`. If several elements
+    /// have the same ID, this gives the first one in document order.
+    ///
+    /// If you created an index with [`Xot::create_id_index`], this takes
+    /// constant time. Otherwise, or if the index is out of date, this looks
+    /// through the document. Unlike [`Xot::xml_id_node`], the result is
+    /// always up to date.
+    ///
+    /// Returns [`Error::NotDocument`] if `document_node` isn't a document
+    /// node.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<doc><sec xml:id="intro"/></doc>"#)?;
+    /// xot.create_id_index(root)?;
+    /// let doc = xot.document_element(root)?;
+    /// let sec = xot.first_child(doc).unwrap();
+    /// assert_eq!(xot.element_by_id(root, "intro")?, Some(sec));
+    ///
+    /// // the index follows changes to the document
+    /// let xml_id = xot.xml_id_name();
+    /// xot.set_attribute(sec, xml_id, "start");
+    /// assert_eq!(xot.element_by_id(root, "intro")?, None);
+    /// assert_eq!(xot.element_by_id(root, "start")?, Some(sec));
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn element_by_id(&self, document_node: Node, id: &str) -> Result<Option<Node>, Error> {
+        if !self.is_document(document_node) {
+            return Err(Error::NotDocument(document_node));
+        }
+        Ok(match self.current_id_index(document_node) {
+            Some(index) => index.element_by_id(id),
+            None => self.build_id_index(document_node).element_by_id(id),
+        })
+    }
+
+    /// The IDs that more than one element in a document has.
+    ///
+    /// They're in the document order of the elements that repeat them. Like
+    /// [`Xot::element_by_id`], this uses the index of the document if
+    /// there's one that's up to date.
+    ///
+    /// Returns [`Error::NotDocument`] if `document_node` isn't a document
+    /// node.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<doc><a xml:id="x"/><b xml:id="y"/></doc>"#)?;
+    /// assert!(xot.duplicate_ids(root)?.is_empty());
+    ///
+    /// let doc = xot.document_element(root)?;
+    /// let c = xot.new_element(xot.name("a").unwrap());
+    /// let xml_id = xot.xml_id_name();
+    /// xot.set_attribute(c, xml_id, "x");
+    /// xot.append(doc, c)?;
+    /// assert_eq!(xot.duplicate_ids(root)?, vec!["x".to_string()]);
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn duplicate_ids(&self, document_node: Node) -> Result<Vec<String>, Error> {
+        if !self.is_document(document_node) {
+            return Err(Error::NotDocument(document_node));
+        }
+        let built;
+        let index = match self.current_id_index(document_node) {
+            Some(index) => index,
+            None => {
+                built = self.build_id_index(document_node);
+                &built
+            }
+        };
+        let mut duplicates = index
+            .ids
+            .iter()
+            .filter(|(_, elements)| elements.len() > 1)
+            .map(|(id, elements)| (elements[1], id.clone()))
+            .collect::<Vec<_>>();
+        duplicates.sort_by(|(a, _), (b, _)| {
+            self.compare_document_order(*a, *b)
+                .unwrap_or(Ordering::Equal)
+        });
+        Ok(duplicates.into_iter().map(|(_, id)| id).collect())
+    }
+
+    fn current_id_index(&self, document_node: Node) -> Option<&IdIndex> {
+        self.id_indexes
+            .get(&document_node.get())
+            .filter(|index| index.generation == self.generation)
+    }
+
+    fn build_id_index(&self, document_node: Node) -> IdIndex {
+        let declared = self
+            .doctype(document_node)
+            .map(|doctype| {
+                doctype
+                    .attributes
+                    .iter()
+                    .filter(|declaration| declaration.attribute_type == "ID")
+                    .map(|declaration| (declaration.element.clone(), declaration.name.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let mut ids: HashMap<String, Vec<Node>> = HashMap::new();
+        for element in self.descendants(document_node) {
+            for id in self.element_ids(element, &declared) {
+                ids.entry(id).or_default().push(element);
+            }
+        }
+        IdIndex {
+            generation: self.generation,
+            declared,
+            ids,
+        }
+    }
+
+    // The IDs of `element`, given the ID attributes the doctype declares.
+    fn element_ids(&self, element: Node, declared: &[(String, String)]) -> Vec<String> {
+        if !self.is_element(element) {
+            return Vec::new();
+        }
+        let full_name = |node| {
+            self.node_name_ref(node)
+                .ok()
+                .flatten()
+                .map(|name| name.full_name().into_owned())
+        };
+        let element_name = if declared.is_empty() {
+            None
+        } else {
+            full_name(element)
+        };
+        let mut ids = Vec::new();
+        for attribute in self.attribute_nodes(element) {
+            let Value::Attribute(value) = self.value(attribute) else {
+                continue;
+            };
+            let is_id = value.name() == self.xml_id_id
+                || element_name.as_ref().is_some_and(|element_name| {
+                    full_name(attribute).is_some_and(|name| {
+                        declared.iter().any(|(element, attribute)| {
+                            element == element_name && *attribute == name
+                        })
+                    })
+                });
+            if is_id {
+                let id = normalize_xml_id(value.value());
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+        ids
+    }
+
+    // The up to date ID index of the document `node` is in, if any.
+    fn id_index_of(&self, node: Node) -> Option<Node> {
+        if self.id_indexes.is_empty() || self.is_removed(node) {
+            return None;
+        }
+        let root = self.root(node);
+        self.current_id_index(root).map(|_| root)
+    }
+
+    // Take the IDs of `elements`, which are in the same tree as `node`, out
+    // of its ID index.
+    pub(crate) fn id_indexes_remove(&mut self, node: Node, elements: &[Node]) {
+        let Some(document_node) = self.id_index_of(node) else {
+            return;
+        };
+        let mut index = self.id_indexes.remove(&document_node.get()).unwrap();
+        for element in elements {
+            for id in self.element_ids(*element, &index.declared) {
+                if let Some(with_id) = index.ids.get_mut(&id) {
+                    with_id.retain(|other| other != element);
+                    if with_id.is_empty() {
+                        index.ids.remove(&id);
+                    }
+                }
+            }
+        }
+        self.id_indexes.insert(document_node.get(), index);
+    }
+
+    // Put the IDs of `elements`, which are in the same tree as `node`, into
+    // its ID index.
+    pub(crate) fn id_indexes_add(&mut self, node: Node, elements: &[Node]) {
+        let Some(document_node) = self.id_index_of(node) else {
+            return;
+        };
+        let mut index = self.id_indexes.remove(&document_node.get()).unwrap();
+        for element in elements {
+            for id in self.element_ids(*element, &index.declared) {
+                let with_id = index.ids.entry(id).or_default();
+                let position = with_id.partition_point(|other| {
+                    matches!(
+                        self.compare_document_order(*other, *element),
+                        Ok(Ordering::Less)
+                    )
+                });
+                with_id.insert(position, *element);
+            }
+        }
+        self.id_indexes.insert(document_node.get(), index);
+    }
+
+    // The doctype declares which attributes are IDs, so we build the index
+    // again when it changes.
+    pub(crate) fn doctype_changed(&mut self, document_node: Node) {
+        if self.id_indexes.contains_key(&document_node.get()) {
+            let index = self.build_id_index(document_node);
+            self.id_indexes.insert(document_node.get(), index);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::xotdata::{Node, Xot};
+
+// Keeping the ID and element name indexes of documents up to date.
+//
+// An index is up to date as long as it has the generation of the Xot. A
+// change to the tree goes through `Xot::arena_mut`, which moves the
+// generation on, so a change we don't follow makes the indexes out of date.
+// A change we do follow takes what it changes out of the indexes before,
+// and puts it back after, with the indexes that were up to date before
+// following the generation.
+
+// What a change took out of the indexes. See `Xot::indexes_leave`.
+pub(crate) struct IndexChange {
+    generation: u64,
+    // the element of the attribute or namespace node that was changed
+    element: Option<Node>,
+}
+
+impl Xot {
+    fn has_indexes(&self) -> bool {
+        !self.id_indexes.is_empty() || !self.name_indexes.is_empty()
+    }
+
+    // The elements in the subtree of `node`, in document order.
+    fn subtree_elements(&self, node: Node) -> Vec<Node> {
+        self.descendants(node)
+            .filter(|node| self.is_element(*node))
+            .collect()
+    }
+
+    // The element of an attribute or namespace node.
+    fn node_element(&self, node: Node) -> Option<Node> {
+        if self.is_attribute_node(node) || self.is_namespace_node(node) {
+            self.parent(node)
+        } else {
+            None
+        }
+    }
+
+    // Call before a change that moves or removes `node`, and pass what
+    // this returns to `indexes_enter` after it.
+    pub(crate) fn indexes_leave(&mut self, node: Node) -> IndexChange {
+        let generation = self.generation;
+        if !self.has_indexes() {
+            return IndexChange {
+                generation,
+                element: None,
+            };
+        }
+        let element = self.node_element(node);
+        if let Some(element) = element {
+            self.id_indexes_remove(element, &[element]);
+        } else {
+            let elements = self.subtree_elements(node);
+            self.indexes_remove(node, &elements);
+        }
+        IndexChange {
+            generation,
+            element,
+        }
+    }
+
+    // Call after a change that moves or removes `node`. This puts it back
+    // in the indexes of the document it's now in, if any.
+    pub(crate) fn indexes_enter(&mut self, node: Node, change: IndexChange) {
+        if !self.has_indexes() {
+            return;
+        }
+        self.indexes_follow(change.generation);
+        if let Some(element) = change.element {
+            self.id_indexes_add(element, &[element]);
+        }
+        if self.is_removed(node) {
+            return;
+        }
+        if let Some(element) = self.node_element(node) {
+            self.id_indexes_add(element, &[element]);
+        } else {
+            let elements = self.subtree_elements(node);
+            self.indexes_add(node, &elements);
+        }
+    }
+
+    // Take `elements`, which are in the same tree as `node`, out of its
+    // indexes.
+    pub(crate) fn indexes_remove(&mut self, node: Node, elements: &[Node]) {
+        self.name_indexes_remove(node, elements);
+        self.id_indexes_remove(node, elements);
+    }
+
+    // Put `elements`, which are in the same tree as `node`, into its
+    // indexes.
+    pub(crate) fn indexes_add(&mut self, node: Node, elements: &[Node]) {
+        self.name_indexes_add(node, elements);
+        self.id_indexes_add(node, elements);
+    }
+
+    // Call after a change, with the generation before it, once what it
+    // changed is out of the indexes. The indexes that were up to date then
+    // still are.
+    pub(crate) fn indexes_follow(&mut self, generation: u64) {
+        let current = self.generation;
+        let follow = |index_generation: &mut u64| {
+            if *index_generation == generation {
+                *index_generation = current;
+            }
+        };
+        self.name_indexes
+            .values_mut()
+            .for_each(|index| follow(&mut index.generation));
+        self.id_indexes
+            .values_mut()
+            .for_each(|index| follow(&mut index.generation));
+    }
+}
@@ -41,6 +41,20 @@ pub(crate) fn level_order_traverse(xot: &Xot, node: Node) -> impl Iterator<Item
     .into_iter()
 }
 
+// traverse the tree breadth-first, yielding each node with its depth
+// relative to the starting node
+pub(crate) fn breadth_first_traverse(
+    xot: &Xot,
+    node: Node,
+) -> impl Iterator<Item = (Node, usize)> + '_ {
+    let mut queue = VecDeque::from([(node, 0)]);
+    std::iter::from_fn(move || {
+        let (node, depth) = queue.pop_front()?;
+        queue.extend(xot.children(node).map(|child| (child, depth + 1)));
+        Some((node, depth))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -123,4 +137,33 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_breadth_first_traverse() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse("<doc><a>X<b/></a><a><b><c/></b></a></doc>")
+            .unwrap();
+        let doc = xot.document_element(root).unwrap();
+        let a0 = xot.first_child(doc).unwrap();
+        let a1 = xot.next_sibling(a0).unwrap();
+        let x0 = xot.first_child(a0).unwrap();
+        let b0 = xot.next_sibling(x0).unwrap();
+        let b1 = xot.first_child(a1).unwrap();
+        let c0 = xot.first_child(b1).unwrap();
+
+        let v = breadth_first_traverse(&xot, doc).collect::<Vec<_>>();
+        assert_eq!(
+            v,
+            vec![
+                (doc, 0),
+                (a0, 1),
+                (a1, 1),
+                (x0, 2),
+                (b0, 2),
+                (b1, 2),
+                (c0, 3)
+            ]
+        );
+    }
 }
@@ -60,40 +60,79 @@
 //! submodule.
 
 mod access;
+#[cfg(feature = "color")]
+mod color;
 mod creation;
+pub mod css;
+mod doctype;
 mod encoding;
 mod entity;
 mod error;
+mod htmlclass;
+mod htmldocument;
+mod htmltext;
 pub mod fixed;
 mod id;
+mod idindex;
+mod index;
 mod levelorder;
 mod manipulation;
+#[cfg(feature = "markdown")]
+mod markdown;
 mod nameaccess;
+mod nameindex;
 mod nodemap;
 pub mod output;
 mod parse;
+pub mod read;
+mod recover;
+mod sax;
 #[cfg(feature = "html5ever")]
 pub mod html5;
 #[cfg(feature = "proptest")]
 pub mod proptest;
+mod pseudoattributes;
+mod push;
+mod query;
 mod serialize;
 
 mod unpretty;
+mod url;
 mod valueaccess;
+mod xinclude;
 pub mod xmlname;
+mod xmlspace;
 mod xmlvalue;
 mod xotdata;
+#[cfg(feature = "xpath")]
+pub mod xpath;
 
 pub use access::{Axis, NodeEdge};
+pub use doctype::{
+    AttributeDeclaration, AttributeDefault, Doctype, EntityDeclaration, EntityDefinition,
+    NotationDeclaration,
+};
 pub use error::{Error, ParseError};
+pub use htmldocument::HtmlDocument;
 pub use id::{NameId, NamespaceId, PrefixId};
 pub use levelorder::LevelOrder;
 pub use nodemap::{
     Attributes, Entry, MutableAttributes, MutableNamespaces, MutableNodeMap, Namespaces, NodeMap,
 };
-pub use parse::{Span, SpanInfo, SpanInfoKey};
+pub use parse::{
+    EntityLimits, EntityResolver, ParseLimits, ParseOptions, ProgressHandler, Span, SpanInfo,
+    SpanInfoKey,
+};
+pub use pseudoattributes::PseudoAttributes;
+pub use push::XmlPushParser;
+pub use query::Query;
+pub use sax::SaxHandler;
 pub use serialize::Html5;
+pub use valueaccess::StringValueOptions;
+pub use xinclude::{XIncludeLoader, XIncludeOptions};
+pub use xmlspace::XmlSpace;
 pub use xmlvalue::{
-    Attribute, Comment, Element, Namespace, Prefixes, ProcessingInstruction, Text, Value, ValueType,
+    Attribute, Comment, Element, EntityReference, Namespace, Prefixes, ProcessingInstruction, Text,
+    Value, ValueType,
 };
 pub use xotdata::{Node, Xot};
@@ -3,6 +3,7 @@ use crate::xotdata::{Node, Xot};
 use crate::{xmlname, MutableAttributes, MutableNamespaces, NamespaceId, PrefixId};
 
 use crate::access::NodeEdge;
+use crate::doctype::Doctype;
 use crate::error::Error;
 use crate::id::NameId;
 use crate::xmlvalue::{Value, ValueCategory, ValueType};
@@ -90,11 +91,12 @@ impl Xot {
     /// ```
     pub fn append(&mut self, parent: Node, child: Node) -> Result<(), Error> {
         self.add_structure_check(Some(parent), child)?;
+        let change = self.indexes_leave(child);
         self.remove_consolidate_text_nodes(self.previous_sibling(child), self.next_sibling(child));
-        if self.add_consolidate_text_nodes(child, self.last_child(parent), None) {
-            return Ok(());
+        if !self.add_consolidate_text_nodes(child, self.last_child(parent), None) {
+            parent.get().checked_append(child.get(), self.arena_mut())?;
         }
-        parent.get().checked_append(child.get(), self.arena_mut())?;
+        self.indexes_enter(child, change);
         Ok(())
     }
 
@@ -331,8 +333,10 @@ impl Xot {
     /// It is now the new first node of the parent.
     pub fn prepend(&mut self, parent: Node, child: Node) -> Result<(), Error> {
         self.add_structure_check(Some(parent), child)?;
+        let change = self.indexes_leave(child);
         self.remove_consolidate_text_nodes(self.previous_sibling(child), self.next_sibling(child));
         if self.add_consolidate_text_nodes(child, None, self.first_child(parent)) {
+            self.indexes_enter(child, change);
             return Ok(());
         }
         // find the child to insert at; this is after the last namespace and attribute node
@@ -349,7 +353,7 @@ impl Xot {
                 .get()
                 .checked_prepend(child.get(), self.arena_mut())?;
         }
-
+        self.indexes_enter(child, change);
         Ok(())
     }
 
@@ -374,40 +378,42 @@ impl Xot {
     /// ```
     pub fn insert_after(&mut self, reference_node: Node, new_sibling: Node) -> Result<(), Error> {
         self.add_structure_check(self.parent(reference_node), new_sibling)?;
+        let change = self.indexes_leave(new_sibling);
         self.remove_consolidate_text_nodes(
             self.previous_sibling(new_sibling),
             self.next_sibling(new_sibling),
         );
-        if self.add_consolidate_text_nodes(
+        if !self.add_consolidate_text_nodes(
             new_sibling,
             Some(reference_node),
             self.next_sibling(reference_node),
         ) {
-            return Ok(());
+            reference_node
+                .get()
+                .checked_insert_after(new_sibling.get(), self.arena_mut())?;
         }
-        reference_node
-            .get()
-            .checked_insert_after(new_sibling.get(), self.arena_mut())?;
+        self.indexes_enter(new_sibling, change);
         Ok(())
     }
 
     /// Insert a new sibling before a reference node.
     pub fn insert_before(&mut self, reference_node: Node, new_sibling: Node) -> Result<(), Error> {
         self.add_structure_check(self.parent(reference_node), new_sibling)?;
+        let change = self.indexes_leave(new_sibling);
         self.remove_consolidate_text_nodes(
             self.previous_sibling(new_sibling),
             self.next_sibling(new_sibling),
         );
-        if self.add_consolidate_text_nodes(
+        if !self.add_consolidate_text_nodes(
             new_sibling,
             self.previous_sibling(reference_node),
             Some(reference_node),
         ) {
-            return Ok(());
+            reference_node
+                .get()
+                .checked_insert_before(new_sibling.get(), self.arena_mut())?;
         }
-        reference_node
-            .get()
-            .checked_insert_before(new_sibling.get(), self.arena_mut())?;
+        self.indexes_enter(new_sibling, change);
         Ok(())
     }
 
@@ -436,8 +442,10 @@ impl Xot {
     pub fn detach(&mut self, node: Node) -> Result<(), Error> {
         let prev_node = self.previous_sibling(node);
         let next_node = self.next_sibling(node);
+        let change = self.indexes_leave(node);
         node.get().detach(self.arena_mut());
         self.remove_consolidate_text_nodes(prev_node, next_node);
+        self.indexes_enter(node, change);
         Ok(())
     }
 
@@ -467,8 +475,10 @@ impl Xot {
     pub fn remove(&mut self, node: Node) -> Result<(), Error> {
         let prev_node = self.previous_sibling(node);
         let next_node = self.next_sibling(node);
+        let change = self.indexes_leave(node);
         node.get().remove_subtree(self.arena_mut());
         self.remove_consolidate_text_nodes(prev_node, next_node);
+        self.indexes_enter(node, change);
         Ok(())
     }
 
@@ -476,12 +486,16 @@ impl Xot {
     ///
     /// If this node is not an element, panic.
     pub fn set_element_name(&mut self, node: Node, name_id: NameId) {
+        let generation = self.generation;
+        self.indexes_remove(node, &[node]);
         match self.value_mut(node) {
             Value::Element(element) => {
                 element.set_name(name_id);
             }
             _ => panic!("Node is not an element, so cannot set element name"),
         }
+        self.indexes_follow(generation);
+        self.indexes_add(node, &[node]);
     }
 
     /// Mutable namespaces accessor.
@@ -743,6 +757,8 @@ impl Xot {
         let first_child = first_child.unwrap();
         // there is guaranteed to be a last child if there's a first child
         let last_child = self.last_child(node).unwrap();
+        let generation = self.generation;
+        self.indexes_remove(node, &[node]);
         self.remove_element(node);
 
         let prev_node = self.previous_sibling(first_child);
@@ -761,6 +777,7 @@ impl Xot {
             // first child did not get consolidated
             self.remove_consolidate_text_nodes(Some(last_child), self.next_sibling(last_child));
         }
+        self.indexes_follow(generation);
         Ok(())
     }
 
@@ -801,7 +818,8 @@ impl Xot {
             ));
         }
 
-        if let Some(parent) = self.parent(node) {
+        let change = self.indexes_leave(node);
+        let wrapper = if let Some(parent) = self.parent(node) {
             // record previous sibling
             let previous_node = self.previous_sibling(node);
             // create new element
@@ -817,13 +835,15 @@ impl Xot {
             } else {
                 self.prepend(parent, wrapper)?;
             }
-            Ok(wrapper)
+            wrapper
         } else {
             // we have no parent, standalone node
             let wrapper = self.new_element(name_id);
             self.append(wrapper, node)?;
-            Ok(wrapper)
-        }
+            wrapper
+        };
+        self.indexes_enter(wrapper, change);
+        Ok(wrapper)
     }
 
     /// Replace a node with another one.
@@ -859,6 +879,8 @@ impl Xot {
         let parent = self.parent(replaced_node).unwrap();
         // record previous sibling
         let previous_node = self.previous_sibling(replaced_node);
+        let change = self.indexes_leave(replaced_node);
+        self.indexes_leave(replacing_node);
         // remove the replaced node, use low-level remove_tree to avoid
         // text node reconciliation and document element detection
         replaced_node.get().remove_subtree(self.arena_mut());
@@ -868,9 +890,96 @@ impl Xot {
         } else {
             self.prepend(parent, replacing_node)?;
         }
+        self.indexes_enter(replacing_node, change);
+        Ok(())
+    }
+
+    /// Set the doctype of a document node.
+    ///
+    /// Pass [`None`] to remove the doctype.
+    ///
+    /// Returns [`Error::NotDocument`] if the node is not a document node.
+    pub fn set_doctype(
+        &mut self,
+        document_node: Node,
+        doctype: Option<Doctype>,
+    ) -> Result<(), Error> {
+        if !self.is_document(document_node) {
+            return Err(Error::NotDocument(document_node));
+        }
+        if let Some(doctype) = doctype {
+            self.doctypes.insert(document_node.get(), doctype);
+        } else {
+            self.doctypes.remove(&document_node.get());
+        }
+        self.doctype_changed(document_node);
         Ok(())
     }
 
+    /// Add a comment or processing instruction to the end of the prolog of
+    /// a document, i.e. right before the document element.
+    ///
+    /// Nodes you add this way are serialized in the order you add them,
+    /// after the ones already in the prolog. If the node is already attached
+    /// it is moved. Use [`Xot::prolog`] to get the prolog.
+    ///
+    /// Returns [`Error::NotDocument`] if the node is not a document node, and
+    /// [`Error::InvalidOperation`] if the added node is not a comment or a
+    /// processing instruction.
+    ///
+    /// ```rust
+    /// use xot::{PseudoAttributes, Xot};
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse("<doc/>")?;
+    /// let stylesheet = xot.add_name("xml-stylesheet");
+    /// let data = PseudoAttributes::new()
+    ///     .with("href", "style.xsl")
+    ///     .with("type", "text/xsl")
+    ///     .to_data();
+    /// let pi = xot.new_processing_instruction(stylesheet, Some(&data));
+    /// xot.append_prolog(root, pi)?;
+    /// let comment = xot.new_comment("generated");
+    /// xot.append_prolog(root, comment)?;
+    /// assert_eq!(
+    ///     xot.to_string(root)?,
+    ///     r#"<?xml-stylesheet href="style.xsl" type="text/xsl"?><!--generated--><doc/>"#
+    /// );
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn append_prolog(&mut self, document_node: Node, node: Node) -> Result<(), Error> {
+        let document_element = self.document_element(document_node)?;
+        if !self.is_comment(node) && !self.is_processing_instruction(node) {
+            return Err(Error::InvalidOperation(
+                "Only comments and processing instructions can be added to the prolog".to_string(),
+            ));
+        }
+        self.insert_before(document_element, node)
+    }
+
+    /// Add a comment to the end of the prolog of a document, given the
+    /// comment text. See [`Xot::append_prolog`].
+    pub fn append_prolog_comment(
+        &mut self,
+        document_node: Node,
+        comment: &str,
+    ) -> Result<(), Error> {
+        let comment_node_id = self.new_comment(comment);
+        self.append_prolog(document_node, comment_node_id)
+    }
+
+    /// Add a processing instruction to the end of the prolog of a document,
+    /// given target and data. See [`Xot::append_prolog`].
+    pub fn append_prolog_processing_instruction(
+        &mut self,
+        document_node: Node,
+        target: NameId,
+        data: Option<&str>,
+    ) -> Result<(), Error> {
+        let pi_node_id = self.new_processing_instruction(target, data);
+        self.append_prolog(document_node, pi_node_id)
+    }
+
     /// Set text consolidation
     ///
     /// By default, text nodes are consolidated when possible. You can turn
@@ -899,7 +1008,7 @@ impl Xot {
                 // we are allowed to move an element under the document node,
                 // though only in a fragment
             }
-            ValueType::Text => {
+            ValueType::Text | ValueType::Cdata | ValueType::EntityReference => {
                 // we are allowed to move text under the document node,
                 // though only in a fragment
             }
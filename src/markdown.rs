@@ -0,0 +1,429 @@
+use crate::htmltext::{html_name, BLOCK, HIDDEN};
+use crate::xotdata::{Node, Xot};
+
+// elements that start a block, besides those that are rendered as a block
+const OTHER_BLOCK: &[&str] = &["p", "tbody", "td", "tfoot", "th", "thead", "tr"];
+
+fn is_block(name: &str) -> bool {
+    BLOCK.contains(&name) || OTHER_BLOCK.contains(&name)
+}
+
+fn is_html_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0c')
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split(is_html_whitespace)
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// escape characters in text that have a meaning in inline Markdown
+fn push_escaped(out: &mut String, text: &str) {
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '&') {
+            out.push('\\');
+        }
+        if is_html_whitespace(c) {
+            out.push(' ');
+        } else {
+            out.push(c);
+        }
+    }
+}
+
+// escape the start of a line that would otherwise be read as the start of a
+// block, such as a heading or a list item
+fn escape_line_start(line: &str) -> String {
+    if line.starts_with(['#', '>', '-', '+', '=']) {
+        return format!("\\{}", line);
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 && line[digits..].starts_with(['.', ')']) {
+        return format!("{}\\{}", &line[..digits], &line[digits..]);
+    }
+    line.to_string()
+}
+
+// the shortest run of backticks that doesn't occur in `text`, at least
+// `minimum` long
+fn backtick_fence(text: &str, minimum: usize) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat((longest + 1).max(minimum))
+}
+
+fn link_destination(url: &str) -> String {
+    if url.contains(|c: char| c.is_whitespace() || c == '(' || c == ')') {
+        format!("<{}>", url.replace('<', "%3C").replace('>', "%3E"))
+    } else {
+        url.to_string()
+    }
+}
+
+// indent all lines but the first, and start the first with `marker`
+fn indent_item(marker: &str, content: &str) -> String {
+    if content.is_empty() {
+        return marker.trim_end().to_string();
+    }
+    let indentation = " ".repeat(marker.len());
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == 0 {
+                format!("{}{}", marker, line)
+            } else if line.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", indentation, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+struct Markdown<'a> {
+    xot: &'a Xot,
+}
+
+impl Markdown<'_> {
+    fn attribute(&self, node: Node, name: &str) -> Option<&str> {
+        self.xot.get_attribute(node, self.xot.name(name)?)
+    }
+
+    // Render a sequence of sibling nodes as Markdown blocks. Inline content
+    // between blocks becomes a paragraph.
+    fn blocks(&self, nodes: impl Iterator<Item = Node>) -> Vec<String> {
+        let mut blocks = Vec::new();
+        let mut inline = String::new();
+        for node in nodes {
+            let name = html_name(self.xot, node);
+            match name.as_deref() {
+                Some(name) if HIDDEN.contains(&name) => {}
+                Some(name) if is_block(name) => {
+                    self.flush_paragraph(&mut inline, &mut blocks);
+                    blocks.extend(self.block(node, name));
+                }
+                _ if self.xot.is_document(node) => {
+                    self.flush_paragraph(&mut inline, &mut blocks);
+                    blocks.extend(self.blocks(self.xot.children(node)));
+                }
+                _ => self.inline(node, &mut inline),
+            }
+        }
+        self.flush_paragraph(&mut inline, &mut blocks);
+        blocks
+    }
+
+    fn flush_paragraph(&self, inline: &mut String, blocks: &mut Vec<String>) {
+        let paragraph = paragraph(inline);
+        if !paragraph.is_empty() {
+            blocks.push(paragraph);
+        }
+        inline.clear();
+    }
+
+    fn block(&self, node: Node, name: &str) -> Vec<String> {
+        let block = match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse().unwrap();
+                let text = self.inline_text(node).replace('\n', " ");
+                if text.is_empty() {
+                    return Vec::new();
+                }
+                format!("{} {}", "#".repeat(level), text)
+            }
+            "ul" => self.list(node, None),
+            "ol" => {
+                let start = self
+                    .attribute(node, "start")
+                    .and_then(|start| start.trim().parse().ok())
+                    .unwrap_or(1);
+                self.list(node, Some(start))
+            }
+            "pre" => self.code_block(node),
+            "blockquote" => self
+                .blocks(self.xot.children(node))
+                .join("\n\n")
+                .lines()
+                .map(|line| {
+                    if line.is_empty() {
+                        ">".to_string()
+                    } else {
+                        format!("> {}", line)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            "hr" => "---".to_string(),
+            "table" => self.table(node),
+            _ => return self.blocks(self.xot.children(node)),
+        };
+        if block.is_empty() {
+            Vec::new()
+        } else {
+            vec![block]
+        }
+    }
+
+    fn list(&self, node: Node, start: Option<usize>) -> String {
+        self.xot
+            .children(node)
+            .filter(|child| html_name(self.xot, *child).as_deref() == Some("li"))
+            .enumerate()
+            .map(|(i, item)| {
+                let marker = match start {
+                    Some(start) => format!("{}. ", start + i),
+                    None => "- ".to_string(),
+                };
+                let content = self.blocks(self.xot.children(item)).join("\n\n");
+                indent_item(&marker, &content)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn code_block(&self, node: Node) -> String {
+        let text = self.xot.string_value(node);
+        let text = text.trim_end_matches('\n');
+        let language = self
+            .xot
+            .children(node)
+            .find(|child| html_name(self.xot, *child).as_deref() == Some("code"))
+            .and_then(|code| {
+                self.xot
+                    .classes(code)
+                    .find_map(|class| class.strip_prefix("language-"))
+            })
+            .unwrap_or("");
+        let fence = backtick_fence(text, 3);
+        format!("{}{}\n{}\n{}", fence, language, text, fence)
+    }
+
+    fn table(&self, node: Node) -> String {
+        let in_this_table = |row: Node| {
+            self.xot
+                .ancestors(row)
+                .skip(1)
+                .find(|ancestor| html_name(self.xot, *ancestor).as_deref() == Some("table"))
+                == Some(node)
+        };
+        let rows = self
+            .xot
+            .descendants(node)
+            .filter(|row| html_name(self.xot, *row).as_deref() == Some("tr") && in_this_table(*row))
+            .map(|row| {
+                self.xot
+                    .children(row)
+                    .filter(|cell| {
+                        matches!(html_name(self.xot, *cell).as_deref(), Some("td" | "th"))
+                    })
+                    .map(|cell| {
+                        self.inline_text(cell)
+                            .replace('\n', " ")
+                            .replace('|', "\\|")
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return String::new();
+        }
+        let row_line = |cells: &[String]| {
+            let mut line = String::from("|");
+            for i in 0..columns {
+                line.push(' ');
+                line.push_str(cells.get(i).map(String::as_str).unwrap_or(""));
+                line.push_str(" |");
+            }
+            line
+        };
+        let mut lines = vec![row_line(&rows[0])];
+        lines.push(format!("|{}", " --- |".repeat(columns)));
+        lines.extend(rows[1..].iter().map(|row| row_line(row)));
+        lines.join("\n")
+    }
+
+    // The content of an element as a single paragraph.
+    fn inline_text(&self, node: Node) -> String {
+        let mut inline = String::new();
+        for child in self.xot.children(node) {
+            self.inline(child, &mut inline);
+        }
+        paragraph(&inline)
+    }
+
+    fn inline_children(&self, node: Node) -> String {
+        let mut inline = String::new();
+        for child in self.xot.children(node) {
+            self.inline(child, &mut inline);
+        }
+        inline
+    }
+
+    // Render inline content. Line breaks are marked by a newline character,
+    // as all other whitespace is turned into spaces.
+    fn inline(&self, node: Node, out: &mut String) {
+        if let Some(text) = self.xot.text_str(node) {
+            push_escaped(out, text);
+            return;
+        }
+        if !self.xot.is_element(node) {
+            return;
+        }
+        let name = html_name(self.xot, node);
+        match name.as_deref() {
+            Some(name) if HIDDEN.contains(&name) => {}
+            Some("br") => out.push('\n'),
+            Some("em" | "i") => self.emphasis(node, "*", out),
+            Some("strong" | "b") => self.emphasis(node, "**", out),
+            Some("code" | "kbd" | "samp" | "tt") => {
+                let text = collapse_whitespace(&self.xot.string_value(node));
+                if !text.is_empty() {
+                    let fence = backtick_fence(&text, 1);
+                    let padding = if text.starts_with('`') || text.ends_with('`') {
+                        " "
+                    } else {
+                        ""
+                    };
+                    out.push_str(&format!("{fence}{padding}{text}{padding}{fence}"));
+                }
+            }
+            Some("a") => {
+                let text = self.inline_children(node);
+                match self.attribute(node, "href") {
+                    Some(href) => {
+                        out.push('[');
+                        out.push_str(text.trim());
+                        out.push_str("](");
+                        out.push_str(&link_destination(href.trim()));
+                        out.push(')');
+                    }
+                    None => out.push_str(&text),
+                }
+            }
+            Some("img") => {
+                if let Some(src) = self.attribute(node, "src") {
+                    out.push_str("![");
+                    push_escaped(out, self.attribute(node, "alt").unwrap_or(""));
+                    out.push_str("](");
+                    out.push_str(&link_destination(src.trim()));
+                    out.push(')');
+                }
+            }
+            Some(name) if is_block(name) => {
+                // a block inside inline content; we can only separate it
+                out.push(' ');
+                out.push_str(&self.inline_children(node));
+                out.push(' ');
+            }
+            _ => out.push_str(&self.inline_children(node)),
+        }
+    }
+
+    fn emphasis(&self, node: Node, marker: &str, out: &mut String) {
+        let text = self.inline_children(node);
+        let trimmed = text.trim_matches(is_html_whitespace);
+        if trimmed.is_empty() {
+            out.push_str(&text);
+            return;
+        }
+        // the markers have to be next to the emphasized text
+        if text.starts_with(is_html_whitespace) {
+            out.push(' ');
+        }
+        out.push_str(marker);
+        out.push_str(trimmed);
+        out.push_str(marker);
+        if text.ends_with(is_html_whitespace) {
+            out.push(' ');
+        }
+    }
+}
+
+// Turn rendered inline content into a paragraph: collapse whitespace, and
+// turn line breaks into hard line breaks.
+fn paragraph(inline: &str) -> String {
+    let lines = inline
+        .split('\n')
+        .map(|line| escape_line_start(&collapse_whitespace(line)))
+        .collect::<Vec<_>>();
+    // line breaks at the start or end of a paragraph have no effect
+    let start = lines.iter().position(|line| !line.is_empty());
+    let end = lines.iter().rposition(|line| !line.is_empty());
+    match (start, end) {
+        (Some(start), Some(end)) => lines[start..=end].join("\\\n"),
+        _ => String::new(),
+    }
+}
+
+pub(crate) fn html_markdown(xot: &Xot, node: Node) -> String {
+    Markdown { xot }.blocks(std::iter::once(node)).join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(
+        "<h1>Title</h1><p>Some  <b>bold</b> and\n<i>italic</i> text.</p>",
+        "# Title\n\nSome **bold** and *italic* text."
+    )]
+    #[case("<h3>A <code>x</code></h3>", "### A `x`")]
+    #[case("<p><em> spaced </em>word</p>", "*spaced* word")]
+    #[case("<p>a<br/>b<br/></p>", "a\\\nb")]
+    #[case("<p>Use <code>a`b</code></p>", "Use ``a`b``")]
+    #[case(
+        r#"<p><a href="https://example.com/">link <b>text</b></a></p>"#,
+        "[link **text**](https://example.com/)"
+    )]
+    #[case(
+        r#"<p><a href="a b.html">x</a><a>no href</a></p>"#,
+        "[x](<a b.html>)no href"
+    )]
+    #[case(
+        r#"<p><img src="a.png" alt="An [image]"/></p>"#,
+        r"![An \[image\]](a.png)"
+    )]
+    #[case(
+        "<ul><li>a</li><li>b<ul><li>c</li></ul></li></ul>",
+        "- a\n- b\n\n  - c"
+    )]
+    #[case(
+        r#"<ol start="3"><li>a</li><li><p>b</p><p>c</p></li></ol>"#,
+        "3. a\n4. b\n\n   c"
+    )]
+    #[case(
+        r#"<pre><code class="language-rust">fn main() {
+    x();
+}
+</code></pre>"#,
+        "```rust\nfn main() {\n    x();\n}\n```"
+    )]
+    #[case("<blockquote><p>a</p><p>b</p></blockquote>", "> a\n>\n> b")]
+    #[case("<div>one<hr/>two</div>", "one\n\n---\n\ntwo")]
+    #[case(
+        "<table><tr><th>A</th><th>B</th></tr><tr><td>1|2</td><td>3</td></tr></table>",
+        "| A | B |\n| --- | --- |\n| 1\\|2 | 3 |"
+    )]
+    #[case(
+        "<p>1. not a list, *not* emphasis</p>",
+        r"1\. not a list, \*not\* emphasis"
+    )]
+    #[case("<p># not a heading</p>", r"\# not a heading")]
+    #[case(
+        "<p>&amp;amp; &amp;copy; &amp;#123; a &amp; b</p>",
+        r"\&amp; \&copy; \&#123; a \& b"
+    )]
+    #[case("<div><script>x()</script><p>a</p><!-- c --></div>", "a")]
+    fn test_html_markdown(#[case] html: &str, #[case] expected: &str) {
+        let mut xot = Xot::new();
+        let root = xot.parse(&format!("<body>{}</body>", html)).unwrap();
+        assert_eq!(html_markdown(&xot, root), expected);
+    }
+}
@@ -1,4 +1,4 @@
-use ahash::{HashMap, HashSet};
+use ahash::HashSet;
 use genawaiter::rc::gen;
 use genawaiter::yield_;
 
@@ -426,6 +426,8 @@ impl Xot {
         match self.value(node) {
             Value::Element(element) => Some(element.name()),
             Value::Text(..) => None,
+            Value::Cdata(..) => None,
+            Value::EntityReference(..) => None,
             Value::ProcessingInstruction(pi) => Some(pi.target()),
             Value::Comment(..) => None,
             Value::Document => None,
@@ -564,11 +566,48 @@ impl Xot {
     /// the namespace in its ancestors.
     ///
     /// This function creates the missing prefixes on the given node. The
-    /// prefixes are named "n0", "n1", "n2", etc.
+    /// prefixes are named "n0", "n1", "n2", etc. To choose the prefixes
+    /// yourself, use [`Xot::create_missing_prefixes_with_preferred`].
     ///
     /// You can use this function just before serializing the tree to XML
     /// using [`Xot::write`] or [`Xot::to_string`].
     pub fn create_missing_prefixes(&mut self, node: Node) -> Result<(), Error> {
+        self.create_missing_prefixes_with_preferred(node, &std::collections::HashMap::new())
+    }
+
+    /// Creating missing prefixes, with preferred prefixes.
+    ///
+    /// This is like [`Xot::create_missing_prefixes`], but a namespace in
+    /// `preferred_prefixes` gets that prefix. The empty prefix makes it the
+    /// default namespace. A preferred prefix isn't used if it's already
+    /// declared in the tree, or if it's empty and the namespace is used by
+    /// an attribute or there's an element without a namespace; the
+    /// namespace then gets a prefix like "n0" instead.
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let ns = xot.add_namespace("http://example.com");
+    /// let doc = xot.add_name_ns("doc", ns);
+    /// let a = xot.add_name_ns("a", ns);
+    /// let root = xot.new_element(doc);
+    /// xot.attributes_mut(root).insert(a, "A".to_string());
+    ///
+    /// let preferred_prefixes = HashMap::from([(ns, "ex".to_string())]);
+    /// xot.create_missing_prefixes_with_preferred(root, &preferred_prefixes)?;
+    /// assert_eq!(
+    ///     xot.to_string(root)?,
+    ///     r#"<ex:doc xmlns:ex="http://example.com" ex:a="A"/>"#
+    /// );
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn create_missing_prefixes_with_preferred(
+        &mut self,
+        node: Node,
+        preferred_prefixes: &std::collections::HashMap<NamespaceId, String>,
+    ) -> Result<(), Error> {
         let node = if self.is_document(node) {
             self.document_element(node).unwrap()
         } else {
@@ -578,25 +617,45 @@ impl Xot {
             return Err(Error::NotElement(node));
         };
         let mut fullname_serializer = FullnameSerializer::new(self, vec![]);
-        let mut missing_namespace_ids = HashSet::default();
+        // in the order we find them, so that the prefixes we create don't
+        // depend on hashing
+        let mut missing_namespace_ids = Vec::new();
+        // we can't use the empty prefix for namespaces of attributes, or if
+        // it would put elements without a namespace in the default namespace
+        let mut attribute_namespace_ids = HashSet::default();
+        let mut has_no_namespace_element = false;
+        // the prefixes we can't use, as they'd override a declaration
+        let mut used_prefixes: HashSet<PrefixId> = self
+            .namespaces_in_scope(node)
+            .map(|(prefix_id, _)| prefix_id)
+            .collect();
         for edge in self.traverse(node) {
             match edge {
                 NodeEdge::Start(node) => {
                     let element = self.element(node);
                     if let Some(element) = element {
+                        used_prefixes.extend(self.namespaces(node).keys());
                         fullname_serializer.push(self.namespace_declarations(node));
                         let element_fullname =
                             fullname_serializer.element_fullname(element.name_id);
                         if element_fullname.is_err() {
                             let namespace_id = self.namespace_for_name(element.name_id);
-                            missing_namespace_ids.insert(namespace_id);
+                            if !missing_namespace_ids.contains(&namespace_id) {
+                                missing_namespace_ids.push(namespace_id);
+                            }
+                        }
+                        if self.namespace_for_name(element.name_id) == self.no_namespace() {
+                            has_no_namespace_element = true;
                         }
                         for name_id in self.attributes(node).keys() {
                             let attribute_fullname =
                                 fullname_serializer.attribute_fullname(name_id);
                             if attribute_fullname.is_err() {
                                 let namespace_id = self.namespace_for_name(name_id);
-                                missing_namespace_ids.insert(namespace_id);
+                                if !missing_namespace_ids.contains(&namespace_id) {
+                                    missing_namespace_ids.push(namespace_id);
+                                }
+                                attribute_namespace_ids.insert(namespace_id);
                             }
                         }
                     }
@@ -608,16 +667,40 @@ impl Xot {
                 }
             }
         }
-        let mut prefixes_to_add = HashMap::default();
-        for (i, namespace_id) in missing_namespace_ids.iter().enumerate() {
-            let prefix = format!("n{}", i);
-            let prefix_id = self.add_prefix(&prefix);
-            prefixes_to_add.insert(prefix_id, namespace_id);
+        let mut prefixes_to_add = Vec::new();
+        let mut generated = Vec::new();
+        for namespace_id in missing_namespace_ids {
+            let prefix_id = preferred_prefixes
+                .get(&namespace_id)
+                .filter(|prefix| {
+                    !prefix.is_empty()
+                        || !(has_no_namespace_element
+                            || attribute_namespace_ids.contains(&namespace_id))
+                })
+                .map(|prefix| self.add_prefix(prefix))
+                .filter(|prefix_id| !used_prefixes.contains(prefix_id));
+            if let Some(prefix_id) = prefix_id {
+                used_prefixes.insert(prefix_id);
+                prefixes_to_add.push((prefix_id, namespace_id));
+            } else {
+                generated.push(namespace_id);
+            }
+        }
+        let mut i = 0;
+        for namespace_id in generated {
+            let prefix_id = loop {
+                let prefix_id = self.add_prefix(&format!("n{}", i));
+                i += 1;
+                if !used_prefixes.contains(&prefix_id) {
+                    break prefix_id;
+                }
+            };
+            prefixes_to_add.push((prefix_id, namespace_id));
         }
         let mut namespaces = self.namespaces_mut(node);
 
         for (prefix_id, namespace_id) in prefixes_to_add {
-            namespaces.insert(prefix_id, *namespace_id);
+            namespaces.insert(prefix_id, namespace_id);
         }
         Ok(())
     }
@@ -0,0 +1,147 @@
+use std::cmp::Ordering;
+
+use ahash::{HashMap, HashMapExt, HashSet};
+
+use crate::error::Error;
+use crate::id::NameId;
+use crate::xotdata::{Node, Xot};
+
+// the elements of a document by name, in document order. See `index.rs` for
+// how it's kept up to date.
+#[derive(Debug, Clone)]
+pub(crate) struct NameIndex {
+    pub(crate) generation: u64,
+    elements: HashMap<NameId, Vec<Node>>,
+}
+
+/// ## Element name index
+impl Xot {
+    /// Get the elements in a document with the given name, in document
+    /// order.
+    ///
+    /// The first lookup in a document builds an index of its elements by
+    /// name. After that, Xot keeps the index up to date as you append,
+    /// insert, remove and rename elements, so repeated lookups in a large
+    /// document that changes don't have to look at the whole document
+    /// again. Some changes Xot can't follow, such as those you make through
+    /// [`Xot::value_mut`]; the index is then built again on the next
+    /// lookup. Use [`Xot::remove_name_index`] to free the index when you no
+    /// longer need it.
+    ///
+    /// Returns [`Error::NotDocument`] if `document_node` isn't a document
+    /// node.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<log><entry/><group><entry/></group></log>"#)?;
+    /// let entry = xot.name("entry").unwrap();
+    /// assert_eq!(xot.elements_named(root, entry)?.len(), 2);
+    ///
+    /// // the index follows changes to the document
+    /// let doc = xot.document_element(root)?;
+    /// xot.append_element(doc, entry)?;
+    /// let group = xot.first_child(doc).and_then(|first| xot.next_sibling(first)).unwrap();
+    /// xot.remove(group)?;
+    /// let entries = xot.elements_named(root, entry)?.to_vec();
+    /// assert_eq!(entries, vec![xot.first_child(doc).unwrap(), xot.last_child(doc).unwrap()]);
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn elements_named(&mut self, document_node: Node, name: NameId) -> Result<&[Node], Error> {
+        if !self.is_document(document_node) {
+            return Err(Error::NotDocument(document_node));
+        }
+        let up_to_date = self
+            .name_indexes
+            .get(&document_node.get())
+            .is_some_and(|index| index.generation == self.generation);
+        if !up_to_date {
+            let index = self.build_name_index(document_node);
+            self.name_indexes.insert(document_node.get(), index);
+        }
+        Ok(self.name_indexes[&document_node.get()]
+            .elements
+            .get(&name)
+            .map(|elements| elements.as_slice())
+            .unwrap_or_default())
+    }
+
+    /// Remove the element name index of a document, to free the memory it
+    /// takes, and the time it takes to keep it up to date.
+    ///
+    /// The next call to [`Xot::elements_named`] builds it again.
+    pub fn remove_name_index(&mut self, document_node: Node) {
+        self.name_indexes.remove(&document_node.get());
+    }
+
+    fn build_name_index(&self, document_node: Node) -> NameIndex {
+        let mut elements: HashMap<NameId, Vec<Node>> = HashMap::new();
+        for node in self.descendants(document_node) {
+            if let Some(element) = self.element(node) {
+                elements.entry(element.name()).or_default().push(node);
+            }
+        }
+        NameIndex {
+            generation: self.generation,
+            elements,
+        }
+    }
+
+    // The up to date name index of the document `node` is in, if any.
+    fn name_index_of(&self, node: Node) -> Option<Node> {
+        if self.name_indexes.is_empty() || self.is_removed(node) {
+            return None;
+        }
+        let root = self.root(node);
+        self.name_indexes
+            .get(&root.get())
+            .is_some_and(|index| index.generation == self.generation)
+            .then_some(root)
+    }
+
+    // Take `elements`, which are in the same tree as `node`, out of its
+    // name index.
+    pub(crate) fn name_indexes_remove(&mut self, node: Node, elements: &[Node]) {
+        let Some(document_node) = self.name_index_of(node) else {
+            return;
+        };
+        let mut removed: HashMap<NameId, HashSet<Node>> = HashMap::new();
+        for element in elements {
+            if let Some(name) = self.node_name(*element) {
+                removed.entry(name).or_default().insert(*element);
+            }
+        }
+        let index = self.name_indexes.get_mut(&document_node.get()).unwrap();
+        for (name, removed) in removed {
+            if let Some(elements) = index.elements.get_mut(&name) {
+                elements.retain(|element| !removed.contains(element));
+            }
+        }
+    }
+
+    // Put `elements`, which are in the same tree as `node`, into its name
+    // index.
+    pub(crate) fn name_indexes_add(&mut self, node: Node, elements: &[Node]) {
+        let Some(document_node) = self.name_index_of(node) else {
+            return;
+        };
+        // take the index out, so we can compare document order while we
+        // change it
+        let mut index = self.name_indexes.remove(&document_node.get()).unwrap();
+        for element in elements {
+            let Some(name) = self.node_name(*element) else {
+                continue;
+            };
+            let named = index.elements.entry(name).or_default();
+            let position = named.partition_point(|other| {
+                matches!(
+                    self.compare_document_order(*other, *element),
+                    Ok(Ordering::Less)
+                )
+            });
+            named.insert(position, *element);
+        }
+        self.name_indexes.insert(document_node.get(), index);
+    }
+}
@@ -278,29 +278,37 @@ where
     /// See also [`entry`](#method.entry) if you you want to insert *or* modify or if you need to
     /// get the index of the corresponding key-value pair.
     pub fn insert(&mut self, key: impl Into<K> + Copy, value: V) -> Option<V> {
+        // this can change the IDs of the element, but not its name
+        let generation = self.xot.generation;
+        self.xot.id_indexes_remove(self.parent, &[self.parent]);
         let node = self.get_node(key);
-        if let Some(node) = node {
-            // if we already have a node
+        let previous = if let Some(node) = node {
+            // if we already have a node; a value we set is no longer a
+            // default
+            self.xot.defaulted_attributes.remove(&node.get());
             let node_value = self.xot.value_mut(node);
             A::update(node_value, value)
         } else {
             // we need to insert a new node
             let new_value = A::create(key.into(), value);
-            let node = self.xot.arena.new_node(new_value);
+            let node = self.xot.arena_mut().new_node(new_value);
             let insertion_point = A::insertion_point(self.xot, self.parent);
             if let Some(insertion_point) = insertion_point {
                 insertion_point
                     .get()
-                    .checked_insert_after(node, &mut self.xot.arena)
+                    .checked_insert_after(node, self.xot.arena_mut())
                     .unwrap();
             } else {
                 self.parent
                     .get()
-                    .checked_prepend(node, &mut self.xot.arena)
+                    .checked_prepend(node, self.xot.arena_mut())
                     .unwrap();
             }
             None
-        }
+        };
+        self.xot.indexes_follow(generation);
+        self.xot.id_indexes_add(self.parent, &[self.parent]);
+        previous
     }
 
     /// Insert a node into the map. This node has to be of the right type,
@@ -320,9 +328,12 @@ where
         let key = A::key(node_value);
         let value = A::value(node_value).clone();
 
+        let generation = self.xot.generation;
+        self.xot.id_indexes_remove(self.parent, &[self.parent]);
         let existing_node = self.get_node(key);
-        if let Some(existing_node) = existing_node {
+        let node = if let Some(existing_node) = existing_node {
             // if we already have a node
+            self.xot.defaulted_attributes.remove(&existing_node.get());
             let node_value = self.xot.value_mut(existing_node);
             A::update(node_value, value);
             existing_node
@@ -331,16 +342,19 @@ where
             if let Some(insertion_point) = insertion_point {
                 insertion_point
                     .get()
-                    .checked_insert_after(node.get(), &mut self.xot.arena)
+                    .checked_insert_after(node.get(), self.xot.arena_mut())
                     .unwrap();
             } else {
                 self.parent
                     .get()
-                    .checked_prepend(node.get(), &mut self.xot.arena)
+                    .checked_prepend(node.get(), self.xot.arena_mut())
                     .unwrap();
             }
             node
-        }
+        };
+        self.xot.indexes_follow(generation);
+        self.xot.id_indexes_add(self.parent, &[self.parent]);
+        node
     }
 
     /// Remove a key-value pair from the map, if it exists.
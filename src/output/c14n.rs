@@ -0,0 +1,370 @@
+//! Canonical XML output method.
+//!
+//! [Canonical XML](https://www.w3.org/TR/xml-c14n) describes a form of XML in
+//! which documents that are logically the same are byte for byte the same.
+//! This is what XML signatures are computed over.
+//!
+//! The main entry point is [`Parameters`], which you can pass into
+//! [`Xot::serialize_c14n_string`] and [`Xot::serialize_c14n_write`].
+
+// Xot doesn't keep everything that canonicalization looks at, so it can't
+// undo everything a parser did:
+//
+// * Entity references are expanded when Xot parses XML, unless you ask
+//   to keep them, in which case they're written as references.
+// * Attribute defaults from the doctype are only there if you ask for them
+//   when parsing.
+// * Xot doesn't know the type of attributes, so attribute values aren't
+//   normalized any further than a parser does for `CDATA` attributes.
+
+use std::borrow::Cow;
+use std::io::Write;
+
+use crate::access::NodeEdge;
+use crate::error::Error;
+use crate::id::{NameId, PrefixId};
+use crate::url;
+use crate::xmlvalue::{Prefixes, Value};
+use crate::xotdata::{Node, Xot};
+
+/// Parameters for canonical XML.
+///
+/// You can use these parameters with [`Xot::serialize_c14n_string`] to
+/// control the canonical form generated by Xot. The default is inclusive
+/// canonicalization without comments.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Parameters {
+    /// The canonicalization method.
+    pub method: Method,
+    /// Keep comments. Without this, comments are left out.
+    pub with_comments: bool,
+}
+
+/// A canonicalization method.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Method {
+    /// [Canonical XML 1.0](https://www.w3.org/TR/xml-c14n).
+    ///
+    /// All namespaces that are in scope are declared on the top element, and
+    /// the `xml:` attributes of its ancestors are copied onto it, so the
+    /// subtree keeps its meaning on its own.
+    #[default]
+    Inclusive,
+    /// [Canonical XML 1.1](https://www.w3.org/TR/xml-c14n11/).
+    ///
+    /// This is like [`Method::Inclusive`], but fixed for subtrees taken
+    /// out of a larger document: of the `xml:` attributes of the ancestors,
+    /// only `xml:lang` and `xml:space` are copied onto the top element, and
+    /// the `xml:base` attributes of the ancestors are combined with that of
+    /// the top element, so that relative URLs in the subtree still resolve
+    /// the same way.
+    Inclusive11,
+    /// [Exclusive XML Canonicalization](https://www.w3.org/TR/xml-exc-c14n/).
+    ///
+    /// A namespace is only declared on an element that uses it, in its name
+    /// or in the name of one of its attributes. That way the canonical form
+    /// of a subtree doesn't depend on the context it appears in, which is
+    /// what you need to sign parts of a SOAP message or a SAML assertion.
+    Exclusive {
+        /// The prefixes of the namespaces that are treated as with inclusive
+        /// canonicalization; they are declared when they're in scope, even
+        /// when they're not used. Use `#default` for the default namespace.
+        /// This is the `PrefixList` of the `InclusiveNamespaces` element in
+        /// an XML signature.
+        inclusive_prefixes: Vec<String>,
+    },
+}
+
+pub(crate) fn serialize(
+    xot: &Xot,
+    parameters: &Parameters,
+    node: Node,
+    w: &mut impl Write,
+) -> Result<(), Error> {
+    let mut serializer = Serializer {
+        xot,
+        parameters,
+        top: node,
+        inclusive_prefixes: match &parameters.method {
+            Method::Inclusive | Method::Inclusive11 => Vec::new(),
+            Method::Exclusive { inclusive_prefixes } => inclusive_prefixes
+                .iter()
+                .filter_map(|prefix| match prefix.as_str() {
+                    "#default" => Some(xot.empty_prefix()),
+                    prefix => xot.prefix(prefix),
+                })
+                .collect(),
+        },
+        rendered: vec![Prefixes::new()],
+    };
+    for edge in xot.traverse(node) {
+        match edge {
+            NodeEdge::Start(node) => serializer.start(node, w)?,
+            NodeEdge::End(node) => serializer.end(node, w)?,
+        }
+    }
+    Ok(())
+}
+
+struct Serializer<'a> {
+    xot: &'a Xot,
+    parameters: &'a Parameters,
+    top: Node,
+    inclusive_prefixes: Vec<PrefixId>,
+    // the namespace declarations in effect in the output, per element
+    rendered: Vec<Prefixes>,
+}
+
+impl Serializer<'_> {
+    fn start(&mut self, node: Node, w: &mut impl Write) -> Result<(), Error> {
+        match self.xot.value(node) {
+            Value::Element(element) => self.start_tag(node, element.name(), w)?,
+            Value::Text(text) => write_text(text.get(), w)?,
+            Value::Cdata(cdata) => write_text(cdata.get(), w)?,
+            Value::EntityReference(entity_reference) => write!(w, "&{};", entity_reference.name())?,
+            Value::Comment(comment) => {
+                if self.parameters.with_comments {
+                    self.outside_document_element(node, w, |w| {
+                        write!(w, "<!--{}-->", comment.get())
+                    })?;
+                }
+            }
+            Value::ProcessingInstruction(pi) => {
+                let (target, namespace) = self.xot.name_ns_str(pi.target());
+                if !namespace.is_empty() {
+                    return Err(Error::NamespaceInProcessingInstruction);
+                }
+                self.outside_document_element(node, w, |w| match pi.data() {
+                    Some(data) if !data.is_empty() => write!(w, "<?{} {}?>", target, data),
+                    _ => write!(w, "<?{}?>", target),
+                })?;
+            }
+            Value::Document | Value::Attribute(_) | Value::Namespace(_) => {}
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, node: Node, w: &mut impl Write) -> Result<(), Error> {
+        if let Some(element) = self.xot.element(node) {
+            write!(w, "</{}>", self.xot.full_name(node, element.name())?)?;
+            self.rendered.pop();
+        }
+        Ok(())
+    }
+
+    // Comments and processing instructions before the document element are
+    // followed by a line end, and those after it are preceded by one.
+    fn outside_document_element<W: Write>(
+        &self,
+        node: Node,
+        w: &mut W,
+        write: impl FnOnce(&mut W) -> std::io::Result<()>,
+    ) -> Result<(), Error> {
+        let parent = self.xot.parent(node);
+        if parent != Some(self.top) || !self.xot.is_document(self.top) {
+            write(w)?;
+        } else if self
+            .xot
+            .preceding_siblings(node)
+            .any(|sibling| self.xot.is_element(sibling))
+        {
+            w.write_all(b"\n")?;
+            write(w)?;
+        } else {
+            write(w)?;
+            w.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn start_tag(&mut self, node: Node, name: NameId, w: &mut impl Write) -> Result<(), Error> {
+        write!(w, "<{}", self.xot.full_name(node, name)?)?;
+
+        let mut rendered = self.rendered.last().unwrap().clone();
+        let mut declarations = Vec::new();
+        for (prefix, namespace) in self.namespaces(node, name)? {
+            let current = rendered
+                .get(&prefix)
+                .copied()
+                .or_else(|| (prefix == self.xot.empty_prefix()).then(|| self.xot.no_namespace()));
+            if current != Some(namespace) {
+                rendered.insert(prefix, namespace);
+                declarations.push((self.xot.prefix_str(prefix), namespace));
+            }
+        }
+        declarations.sort_by_key(|(prefix, _)| *prefix);
+        for (prefix, namespace) in declarations {
+            let namespace = self.xot.namespace_str(namespace);
+            if prefix.is_empty() {
+                write!(w, " xmlns=\"")?;
+            } else {
+                write!(w, " xmlns:{}=\"", prefix)?;
+            }
+            write_attribute_value(namespace, w)?;
+            write!(w, "\"")?;
+        }
+        self.rendered.push(rendered);
+
+        let mut attributes = self
+            .xot
+            .attributes(node)
+            .iter()
+            .map(|(name, value)| (name, Cow::Borrowed(value.as_str())))
+            .collect::<Vec<_>>();
+        if node == self.top {
+            match self.parameters.method {
+                Method::Inclusive => self.inherit_xml_attributes(node, &mut attributes, |_| true),
+                Method::Inclusive11 => {
+                    let xml_lang = self.xot.name_ns("lang", self.xot.xml_namespace());
+                    let xml_space = self.xot.xml_space_name();
+                    self.inherit_xml_attributes(node, &mut attributes, |name| {
+                        name == xml_space || Some(name) == xml_lang
+                    });
+                    self.fixup_xml_base(node, &mut attributes);
+                }
+                Method::Exclusive { .. } => {}
+            }
+        }
+        let mut attributes = attributes
+            .into_iter()
+            .map(|(name, value)| {
+                let (local_name, namespace) = self.xot.name_ns_str(name);
+                ((namespace, local_name), name, value)
+            })
+            .collect::<Vec<_>>();
+        attributes.sort_by_key(|(key, _, _)| *key);
+        for (_, name, value) in attributes {
+            write!(w, " {}=\"", self.xot.full_name(node, name)?)?;
+            write_attribute_value(&value, w)?;
+            write!(w, "\"")?;
+        }
+        write!(w, ">")?;
+        Ok(())
+    }
+
+    // The namespaces an element could declare. Which of them it does
+    // declare depends on what its output ancestors declare.
+    fn namespaces(&self, node: Node, name: NameId) -> Result<Prefixes, Error> {
+        let xml_prefix = self.xot.xml_prefix();
+        let in_scope = self
+            .xot
+            .namespaces_in_scope(node)
+            .filter(|(prefix, _)| *prefix != xml_prefix);
+        if !matches!(self.parameters.method, Method::Exclusive { .. }) {
+            let mut namespaces = in_scope.collect::<Prefixes>();
+            // without a default namespace, it may have to be undeclared
+            namespaces
+                .entry(self.xot.empty_prefix())
+                .or_insert(self.xot.no_namespace());
+            return Ok(namespaces);
+        }
+        // with exclusive canonicalization, only the namespaces that are
+        // visibly used, and those that are treated inclusively
+        let mut namespaces = in_scope
+            .filter(|(prefix, _)| self.inclusive_prefixes.contains(prefix))
+            .collect::<Prefixes>();
+        let attribute_names = self.xot.attributes(node).keys().collect::<Vec<_>>();
+        for (i, name) in std::iter::once(name).chain(attribute_names).enumerate() {
+            let namespace = self.xot.namespace_for_name(name);
+            if namespace == self.xot.no_namespace() {
+                // an unprefixed element name uses the default namespace,
+                // even if that's no namespace at all
+                if i == 0 {
+                    namespaces.insert(self.xot.empty_prefix(), namespace);
+                }
+                continue;
+            }
+            let prefix = self
+                .xot
+                .prefix_for_namespace(node, namespace)
+                .ok_or_else(|| {
+                    Error::MissingPrefix(self.xot.namespace_str(namespace).to_string())
+                })?;
+            if prefix != xml_prefix {
+                namespaces.insert(prefix, namespace);
+            }
+        }
+        Ok(namespaces)
+    }
+
+    // The top element of a subtree gets the `xml:` attributes of its
+    // ancestors that it doesn't have itself; the nearest one wins.
+    fn inherit_xml_attributes<'b>(
+        &'b self,
+        node: Node,
+        attributes: &mut Vec<(NameId, Cow<'b, str>)>,
+        inherit: impl Fn(NameId) -> bool,
+    ) {
+        let xml_namespace = self.xot.xml_namespace();
+        for ancestor in self.xot.ancestors(node).skip(1) {
+            for (name, value) in self.xot.attributes(ancestor).iter() {
+                if self.xot.namespace_for_name(name) == xml_namespace
+                    && inherit(name)
+                    && !attributes.iter().any(|(n, _)| *n == name)
+                {
+                    attributes.push((name, Cow::Borrowed(value.as_str())));
+                }
+            }
+        }
+    }
+
+    // The `xml:base` of the top element of a subtree is joined to those of
+    // its ancestors, outermost first.
+    // https://www.w3.org/TR/xml-c14n11/#XMLBaseFixup
+    fn fixup_xml_base(&self, node: Node, attributes: &mut Vec<(NameId, Cow<'_, str>)>) {
+        let Some(xml_base) = self.xot.name_ns("base", self.xot.xml_namespace()) else {
+            return;
+        };
+        let mut bases = self
+            .xot
+            .ancestors(node)
+            .filter_map(|ancestor| self.xot.get_attribute(ancestor, xml_base))
+            .collect::<Vec<_>>();
+        let Some(mut base) = bases.pop().map(|base| base.to_string()) else {
+            return;
+        };
+        while let Some(reference) = bases.pop() {
+            base = url::join(&base, reference);
+        }
+        attributes.retain(|(name, _)| *name != xml_base);
+        attributes.push((xml_base, Cow::Owned(base)));
+    }
+}
+
+fn write_text(text: &str, w: &mut impl Write) -> Result<(), Error> {
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        let escaped = match c {
+            '&' => "&amp;",
+            '<' => "&lt;",
+            '>' => "&gt;",
+            '\r' => "&#xD;",
+            _ => continue,
+        };
+        w.write_all(&text.as_bytes()[start..i])?;
+        w.write_all(escaped.as_bytes())?;
+        start = i + 1;
+    }
+    w.write_all(&text.as_bytes()[start..])?;
+    Ok(())
+}
+
+fn write_attribute_value(value: &str, w: &mut impl Write) -> Result<(), Error> {
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        let escaped = match c {
+            '&' => "&amp;",
+            '<' => "&lt;",
+            '"' => "&quot;",
+            '\t' => "&#x9;",
+            '\n' => "&#xA;",
+            '\r' => "&#xD;",
+            _ => continue,
+        };
+        w.write_all(&value.as_bytes()[start..i])?;
+        w.write_all(escaped.as_bytes())?;
+        start = i + 1;
+    }
+    w.write_all(&value.as_bytes()[start..])?;
+    Ok(())
+}
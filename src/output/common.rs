@@ -1,10 +1,140 @@
-use crate::NameId;
+use std::collections::HashMap;
+
+use encoding_rs::Encoding;
+
+use crate::output::xml::{CharacterReferences, EmptyElementForm, Quote, UnrepresentableCharacters};
+use crate::xotdata::{Node, Xot};
+use crate::{NameId, NamespaceId};
 
 /// Indentation: pretty-print XML or HTML.
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Indentation {
     /// A list of element names where indentation changes are suppressed.
+    ///
+    /// The content of these elements is written as is, without indentation
+    /// or added line ends, like that of an element with
+    /// `xml:space="preserve"`. Use this for elements where whitespace is
+    /// significant, such as `pre` or `literal`.
     pub suppress: Vec<NameId>,
+    /// Decides per element whether it is laid out inline or as a block.
+    ///
+    /// An element that contains an inline element is written on a single
+    /// line, just like an element that contains text. Elements for which
+    /// this returns [`IndentDecision::Default`] are laid out as the
+    /// serializer would otherwise do.
+    ///
+    /// ```rust
+    /// use xot::output::{self, IndentDecision};
+    /// use xot::{Node, Xot};
+    ///
+    /// fn decide(xot: &Xot, node: Node) -> IndentDecision {
+    ///     match xot.element(node).map(|element| xot.local_name_str(element.name())) {
+    ///         Some("b" | "i") => IndentDecision::Inline,
+    ///         _ => IndentDecision::Default,
+    ///     }
+    /// }
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse("<doc><p><b>bold</b><i>italic</i></p><p><x/></p></doc>")?;
+    /// let xml = xot.serialize_xml_string(output::xml::Parameters {
+    ///     indentation: Some(output::Indentation {
+    ///         decision: Some(decide),
+    ///         ..Default::default()
+    ///     }),
+    ///     ..Default::default()
+    /// }, root)?;
+    /// assert_eq!(
+    ///     xml,
+    ///     "<doc>\n  <p><b>bold</b><i>italic</i></p>\n  <p>\n    <x/>\n  </p>\n</doc>\n"
+    /// );
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub decision: Option<fn(&Xot, Node) -> IndentDecision>,
+    /// How to indent.
+    pub options: PrettyOptions,
+}
+
+// decision callbacks are equal if they're the same function
+impl PartialEq for Indentation {
+    fn eq(&self, other: &Self) -> bool {
+        let decision_eq = match (self.decision, other.decision) {
+            (Some(a), Some(b)) => std::ptr::fn_addr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.suppress == other.suppress && decision_eq && self.options == other.options
+    }
+}
+
+impl Eq for Indentation {}
+
+/// How the pretty printer lays out an element.
+///
+/// This is returned by [`Indentation::decision`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndentDecision {
+    /// Lay out the element as the serializer would by default.
+    #[default]
+    Default,
+    /// The element is inline: it doesn't get line ends or indentation
+    /// around it, and neither does the content of its parent.
+    Inline,
+    /// The element is a block: it gets its own indented line, unless its
+    /// parent has text.
+    Block,
+}
+
+/// The style of pretty-printed output.
+///
+/// By default, each level is indented with two spaces, and the output ends
+/// with a line end.
+///
+/// ```rust
+/// use xot::{Xot, output};
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse("<a><b><c><d/></c></b></a>")?;
+///
+/// let xml = xot.serialize_xml_string(output::xml::Parameters {
+///     indentation: Some(output::Indentation {
+///         options: output::PrettyOptions {
+///             indent: "\t".to_string(),
+///             max_depth: Some(2),
+///             trailing_newline: false,
+///             attribute_wrap_width: None,
+///         },
+///         ..Default::default()
+///     }),
+///     ..Default::default()
+/// }, root)?;
+/// assert_eq!(xml, "<a>\n\t<b>\n\t\t<c><d/></c>\n\t</b>\n</a>");
+/// # Ok::<(), xot::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrettyOptions {
+    /// The string to indent each level with, such as two or four spaces,
+    /// or a tab.
+    pub indent: String,
+    /// The maximum number of levels to indent. The content of elements
+    /// nested deeper is written on a single line.
+    pub max_depth: Option<usize>,
+    /// Whether to end the output with a line end.
+    pub trailing_newline: bool,
+    /// If a start tag with more than one attribute (or namespace
+    /// declaration) would make its line longer than this many characters,
+    /// each attribute is put on its own line, aligned with the first one.
+    pub attribute_wrap_width: Option<usize>,
+}
+
+impl Default for PrettyOptions {
+    fn default() -> Self {
+        Self {
+            indent: "  ".to_string(),
+            max_depth: None,
+            trailing_newline: true,
+            attribute_wrap_width: None,
+        }
+    }
 }
 
 /// Parameters used when serializing tokens.
@@ -19,4 +149,43 @@ pub struct TokenSerializeParameters {
     /// `>` is not escaped, except for the special case of `]]>` outside of CDATA,
     /// which is mandated by the XML specification to always be escaped.
     pub unescaped_gt: bool,
+
+    /// Whether this is XML 1.1. If so, control characters in text and
+    /// attribute values are written as character references.
+    pub xml_1_1: bool,
+
+    /// The quote character around attribute values.
+    pub attribute_quote: Quote,
+
+    /// Whether to leave the quote character that doesn't delimit attribute
+    /// values unescaped.
+    pub unescaped_quote: bool,
+
+    /// How to write character references.
+    pub character_references: CharacterReferences,
+
+    /// How to write elements without content.
+    pub empty_element_form: EmptyElementForm,
+
+    /// Elements that are written in the other form than
+    /// `empty_element_form` when they're empty.
+    pub empty_element_form_exceptions: Vec<NameId>,
+
+    /// Prefixes to declare for namespaces that have no prefix in scope.
+    pub preferred_prefixes: HashMap<NamespaceId, String>,
+
+    /// Whether to move namespace declarations to the document element.
+    pub hoist_namespaces: bool,
+
+    /// What to do with a character that can't be written.
+    pub unrepresentable_characters: UnrepresentableCharacters,
+
+    /// The encoding the output is encoded in afterward, if it's not UTF-8
+    /// or UTF-16.
+    pub encoding: Option<&'static Encoding>,
+
+    /// The highest character the encoding the output is encoded in
+    /// afterward has, for an encoding that has every character up to it,
+    /// such as ASCII and ISO-8859-1.
+    pub max_character: Option<char>,
 }
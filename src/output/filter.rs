@@ -0,0 +1,98 @@
+use crate::id::NameId;
+use crate::xmlvalue::Element;
+use crate::xotdata::{Node, Xot};
+
+use super::Output;
+
+/// What to serialize in place of an [`Output`].
+///
+/// This is returned by an [`OutputFilter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilteredOutput {
+    /// The output that was filtered, unchanged.
+    Original,
+    /// Text. This is escaped as usual.
+    Text(String),
+    /// A comment, i.e. `<!--foo-->`.
+    Comment(String),
+    /// A processing instruction, i.e. `<?foo bar?>`.
+    ProcessingInstruction(NameId, Option<String>),
+    /// An attribute, i.e. `foo="bar"`. The value is escaped as usual. This
+    /// can only be used in a start tag.
+    Attribute(NameId, String),
+    /// The start of the start tag of an element with this name, i.e. `<foo`.
+    /// Use this with [`FilteredOutput::EndTag`] to rename an element.
+    StartTagOpen(NameId),
+    /// The end tag of an element with this name, i.e. `</foo>`.
+    EndTag(NameId),
+}
+
+impl FilteredOutput {
+    pub(crate) fn output<'a>(&'a self, original: &Output<'a>) -> Output<'a> {
+        match self {
+            FilteredOutput::Original => original.clone(),
+            FilteredOutput::Text(text) => Output::Text(text),
+            FilteredOutput::Comment(text) => Output::Comment(text),
+            FilteredOutput::ProcessingInstruction(target, data) => {
+                Output::ProcessingInstruction(*target, data.as_deref())
+            }
+            FilteredOutput::Attribute(name, value) => Output::Attribute(*name, value),
+            FilteredOutput::StartTagOpen(name) => Output::StartTagOpen(Element::new(*name)),
+            FilteredOutput::EndTag(name) => Output::EndTag(Element::new(*name)),
+        }
+    }
+}
+
+/// Transforms outputs before they're serialized.
+///
+/// You can install a filter with
+/// [`Xot::serialize_xml_write_with_filter`](crate::Xot::serialize_xml_write_with_filter)
+/// to rename elements, leave out attributes, add comments, or change text
+/// in the serialized XML, without changing the tree. Any closure that takes
+/// a [`Xot`], a node and an [`Output`] and returns a list of
+/// [`FilteredOutput`] is a filter.
+///
+/// ```rust
+/// use xot::output::{FilteredOutput, Output};
+/// use xot::{Node, Xot};
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse(r#"<doc><password hash="abc">secret</password><p>text</p></doc>"#)?;
+/// let password = xot.add_name("password");
+/// let hash = xot.add_name("hash");
+///
+/// let xml = xot.serialize_xml_string_with_filter(
+///     Default::default(),
+///     root,
+///     |xot: &Xot, node: Node, output: &Output| match output {
+///         // leave out the attribute
+///         Output::Attribute(name, _) if *name == hash => vec![],
+///         // redact the text of the element
+///         Output::Text(_) if xot.parent(node).and_then(|p| xot.element(p)).is_some_and(|e| e.name() == password) => {
+///             vec![FilteredOutput::Text("***".to_string())]
+///         }
+///         Output::StartTagOpen(element) if element.name() == password => vec![
+///             FilteredOutput::Comment(" redacted ".to_string()),
+///             FilteredOutput::Original,
+///         ],
+///         _ => vec![FilteredOutput::Original],
+///     },
+/// )?;
+/// assert_eq!(xml, "<doc><!-- redacted --><password>***</password><p>text</p></doc>");
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub trait OutputFilter {
+    /// The outputs to serialize instead of `output`, which is an output for
+    /// `node`. Return `vec![FilteredOutput::Original]` to keep it, and an
+    /// empty list to leave it out.
+    fn filter(&mut self, xot: &Xot, node: Node, output: &Output) -> Vec<FilteredOutput>;
+}
+
+impl<F> OutputFilter for F
+where
+    F: FnMut(&Xot, Node, &Output) -> Vec<FilteredOutput>,
+{
+    fn filter(&mut self, xot: &Xot, node: Node, output: &Output) -> Vec<FilteredOutput> {
+        self(xot, node, output)
+    }
+}
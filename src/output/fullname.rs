@@ -200,6 +200,27 @@ impl<'a> FullnameSerializer<'a> {
         }
     }
 
+    pub(crate) fn is_prefix_declared(&self, prefix_id: PrefixId) -> bool {
+        self.top()
+            .all_namespaces
+            .iter()
+            .any(|(p, _)| *p == prefix_id)
+    }
+
+    // whether the scope outside of the element on top of the stack declares
+    // this prefix for this namespace. this only works if that element
+    // declares namespaces.
+    pub(crate) fn is_declared_outside(
+        &self,
+        prefix_id: PrefixId,
+        namespace_id: NamespaceId,
+    ) -> bool {
+        self.stack.len() >= 2
+            && self.stack[self.stack.len() - 2]
+                .all_namespaces
+                .contains(&(prefix_id, namespace_id))
+    }
+
     pub(crate) fn is_namespace_known(&self, namespace_id: NamespaceId) -> bool {
         self.top()
             .all_namespaces
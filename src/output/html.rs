@@ -0,0 +1,42 @@
+//! HTML output method.
+//!
+//! The main entry point is [`Parameters`], which you can pass into
+//! [`Xot::serialize_html_write`].
+#[cfg(doc)]
+use crate::Xot;
+
+use super::Indentation;
+
+/// Parameters for HTML generation.
+///
+/// HTML is serialized as described by [`Xot::html_string`]. These parameters
+/// control the bytes that are produced.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Parameters {
+    /// Pretty-print HTML, and a list of elements where this is suppressed.
+    ///
+    /// Block-level elements are indented. Whitespace-only text between them
+    /// is replaced by the indentation, as it isn't rendered anyway. Elements
+    /// that contain text or inline elements such as `<b>` are left as they
+    /// are, as are whitespace-sensitive elements such as `<pre>` and
+    /// `<textarea>` and raw text elements such as `<script>`.
+    pub indentation: Option<Indentation>,
+    /// The encoding of the output, as an encoding label such as `"utf-8"` or
+    /// `"iso-8859-1"`.
+    ///
+    /// By default the output is UTF-8. Characters that can't be represented
+    /// in the encoding are written as numeric character references. Labels
+    /// of encodings that can't be produced, such as `"utf-16"`, result in
+    /// UTF-8 output, as in the HTML standard.
+    pub encoding: Option<String>,
+    /// Declare the output encoding in a `<meta charset>` element.
+    ///
+    /// If `<head>` contains a `<meta charset>` or a `<meta
+    /// http-equiv="Content-Type">` element, the charset it declares is
+    /// replaced by the name of the output encoding. Otherwise a `<meta
+    /// charset>` element is inserted as the first child of `<head>`. Nothing
+    /// is inserted if there is no `<head>`.
+    ///
+    /// This only affects the output; the tree isn't changed.
+    pub meta_charset: bool,
+}
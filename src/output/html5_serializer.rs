@@ -4,12 +4,13 @@ use std::io;
 use crate::entity::{serialize_attribute, serialize_cdata, serialize_text};
 use crate::error::Error;
 use crate::id::NameId;
+use crate::output::xml::Newline;
 use crate::output::Normalizer;
 use crate::xotdata::{Node, Xot};
 
 use super::fullname::FullnameSerializer;
 use super::html5elements::Html5Elements;
-use super::{Output, OutputToken, Pretty};
+use super::{AttributeWrapper, Indentation, Output, OutputToken, Pretty};
 
 pub(crate) struct Html5Serializer<'a, N: Normalizer> {
     xot: &'a Xot,
@@ -19,7 +20,7 @@ pub(crate) struct Html5Serializer<'a, N: Normalizer> {
     normalizer: N,
 }
 
-fn html_matches_suppress(
+pub(crate) fn html_matches_suppress(
     xot: &Xot,
     html5_elements: &Html5Elements,
     names: &[NameId],
@@ -83,7 +84,38 @@ impl<'a, N: Normalizer> Html5Serializer<'a, N> {
         &mut self,
         w: &mut W,
         outputs: impl Iterator<Item = (Node, Output<'a>)>,
-        suppress: &[NameId],
+        indentation: &Indentation,
+    ) -> Result<(), Error> {
+        let html5_elements = self.html5_elements;
+        let xot = self.xot;
+        self.serialize_pretty_with(w, outputs, indentation, |name_id| {
+            html5_elements.is_inline(xot, name_id)
+        })
+    }
+
+    // Like `serialize_pretty`, but `script` and `style` elements aren't
+    // treated as inline, as they aren't rendered. This lets us indent
+    // elements that contain them.
+    pub(crate) fn serialize_pretty_html<W: io::Write>(
+        &mut self,
+        w: &mut W,
+        outputs: impl Iterator<Item = (Node, Output<'a>)>,
+        indentation: &Indentation,
+    ) -> Result<(), Error> {
+        let html5_elements = self.html5_elements;
+        let xot = self.xot;
+        self.serialize_pretty_with(w, outputs, indentation, |name_id| {
+            html5_elements.is_inline(xot, name_id)
+                && !html5_elements.no_escape_names.matches(xot, name_id)
+        })
+    }
+
+    fn serialize_pretty_with<W: io::Write>(
+        &mut self,
+        w: &mut W,
+        outputs: impl Iterator<Item = (Node, Output<'a>)>,
+        indentation: &Indentation,
+        is_inline: impl Fn(NameId) -> bool,
     ) -> Result<(), Error> {
         // we have to do the relatively slow html_matches_suppress call here,
         // as we cannot make an efficient HtmlNames at this point (as this
@@ -92,19 +124,37 @@ impl<'a, N: Normalizer> Html5Serializer<'a, N> {
             self.html5_elements
                 .formatted_names
                 .matches(self.xot, name_id)
-                || html_matches_suppress(self.xot, self.html5_elements, suppress, name_id)
+                || html_matches_suppress(
+                    self.xot,
+                    self.html5_elements,
+                    &indentation.suppress,
+                    name_id,
+                )
         };
-        let is_inline = |name_id| self.html5_elements.is_inline(self.xot, name_id);
-        let mut pretty = Pretty::new(self.xot, is_suppressed, is_inline);
+        let mut pretty = Pretty::new(
+            self.xot,
+            is_suppressed,
+            is_inline,
+            indentation.options.max_depth,
+        )
+        .with_decision(indentation.decision);
+        let options = &indentation.options;
+        // we write a line end once we know it's not the last one
+        let mut newline = false;
+        let mut wrapper = AttributeWrapper::new(options.attribute_wrap_width, Newline::Lf);
         for (node, output) in outputs {
-            let (indentation, newline) = pretty.prettify(node, &output);
-            if indentation > 0 {
-                w.write_all(" ".repeat(indentation * 2).as_bytes())?;
-            }
-            self.serialize_node(w, node, output)?;
+            let (level, next_newline) = pretty.prettify(node, &output);
             if newline {
                 w.write_all(b"\n")?;
             }
+            let indent = options.indent.repeat(level);
+            w.write_all(indent.as_bytes())?;
+            let token = self.render_output(node, &output)?;
+            wrapper.write(w, indent, &output, token)?;
+            newline = next_newline;
+        }
+        if newline && options.trailing_newline {
+            w.write_all(b"\n")?;
         }
         Ok(())
     }
@@ -252,7 +302,8 @@ impl<'a, N: Normalizer> Html5Serializer<'a, N> {
                     text: format!("{}=\"{}\"", fullname, value),
                 }
             }
-            Text(text) => {
+            // HTML has no CDATA sections, so we serialize them as text
+            Text(text) | Cdata(text) => {
                 // a text node is always a child of an element
                 let parent = self.xot.parent(node).unwrap();
                 let element = self.xot.element(parent).unwrap();
@@ -277,6 +328,10 @@ impl<'a, N: Normalizer> Html5Serializer<'a, N> {
                     text: value,
                 }
             }
+            EntityReference(name) => OutputToken {
+                space: false,
+                text: format!("&{};", name),
+            },
             Comment(text) => OutputToken {
                 space: false,
                 text: format!("<!--{}-->", text),
@@ -388,7 +443,7 @@ pub(crate) fn serialize_text_no_escape<'a, N: Normalizer>(
 
 #[cfg(test)]
 mod tests {
-    use crate::output::{html5::Parameters, html5elements::XHTML_NS, Indentation};
+    use crate::output::{html5::Parameters, html5elements::XHTML_NS, IndentDecision, Indentation};
 
     use super::*;
 
@@ -580,12 +635,12 @@ mod tests {
     fn test_serialize_attribute_boolean_with_xhtml_prefix() {
         let mut xot = Xot::new();
         let root = xot
-            .parse(r#"<html><body><option xmlns:foo="https://www.w3.org/1999/xhtml" foo:selected="selected"/></body></html>"#)
+            .parse(r#"<html><body><option xmlns:foo="http://www.w3.org/1999/xhtml" foo:selected="selected"/></body></html>"#)
             .unwrap();
         let s = xot.html5().to_string(root).unwrap();
         assert_eq!(
             s,
-            r#"<!DOCTYPE html><html><body><option xmlns:foo="https://www.w3.org/1999/xhtml" foo:selected="selected"></option></body></html>"#
+            r#"<!DOCTYPE html><html><body><option xmlns:foo="http://www.w3.org/1999/xhtml" foo:selected="selected"></option></body></html>"#
         );
     }
 
@@ -618,12 +673,12 @@ mod tests {
     fn test_xhtml_namespace_without_prefix() {
         let mut xot = Xot::new();
         let root = xot
-            .parse(r#"<prefix:html xmlns:prefix="https://www.w3.org/1999/xhtml"></prefix:html>"#)
+            .parse(r#"<prefix:html xmlns:prefix="http://www.w3.org/1999/xhtml"></prefix:html>"#)
             .unwrap();
         let s = xot.html5().to_string(root).unwrap();
         assert_eq!(
             s,
-            r#"<!DOCTYPE html><html xmlns="https://www.w3.org/1999/xhtml"></html>"#
+            r#"<!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml"></html>"#
         );
     }
 
@@ -631,12 +686,12 @@ mod tests {
     fn test_xhtml_namespace_without_prefix_but_with_attribute() {
         let mut xot = Xot::new();
         let root = xot
-            .parse(r#"<prefix:html xmlns:prefix="https://www.w3.org/1999/xhtml" prefix:a="A"></prefix:html>"#)
+            .parse(r#"<prefix:html xmlns:prefix="http://www.w3.org/1999/xhtml" prefix:a="A"></prefix:html>"#)
             .unwrap();
         let s = xot.html5().to_string(root).unwrap();
         assert_eq!(
             s,
-            r#"<!DOCTYPE html><html xmlns="https://www.w3.org/1999/xhtml" xmlns:prefix="https://www.w3.org/1999/xhtml" prefix:a="A"></html>"#
+            r#"<!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml" xmlns:prefix="http://www.w3.org/1999/xhtml" prefix:a="A"></html>"#
         );
     }
 
@@ -644,12 +699,12 @@ mod tests {
     fn test_xhtml_namespace_without_prefix_dont_redeclare() {
         let mut xot = Xot::new();
         let root = xot
-            .parse(r#"<prefix:html xmlns:prefix="https://www.w3.org/1999/xhtml"><prefix:body></prefix:body></prefix:html>"#)
+            .parse(r#"<prefix:html xmlns:prefix="http://www.w3.org/1999/xhtml"><prefix:body></prefix:body></prefix:html>"#)
             .unwrap();
         let s = xot.html5().to_string(root).unwrap();
         assert_eq!(
             s,
-            r#"<!DOCTYPE html><html xmlns="https://www.w3.org/1999/xhtml"><body></body></html>"#
+            r#"<!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml"><body></body></html>"#
         );
     }
 
@@ -657,12 +712,12 @@ mod tests {
     fn test_default_namespace_different_from_element_is_ignored_xhtml() {
         let mut xot = Xot::new();
         let root = xot
-            .parse(r#"<prefix:html xmlns="different" xmlns:prefix="https://www.w3.org/1999/xhtml"><prefix:body></prefix:body></prefix:html>"#)
+            .parse(r#"<prefix:html xmlns="different" xmlns:prefix="http://www.w3.org/1999/xhtml"><prefix:body></prefix:body></prefix:html>"#)
             .unwrap();
         let s = xot.html5().to_string(root).unwrap();
         assert_eq!(
             s,
-            r#"<!DOCTYPE html><html xmlns="https://www.w3.org/1999/xhtml"><body></body></html>"#
+            r#"<!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml"><body></body></html>"#
         );
     }
 
@@ -683,14 +738,14 @@ mod tests {
     // fn test_xhtml_namespace_without_prefix_redeclare_if_intervening() {
     //     let mut xot = Xot::new();
     //     let root = xot
-    //         .parse(r#"<prefix:html xmlns:prefix="https://www.w3.org/1999/xhtml"><prefix:body xmlns="different"><prefix:p></prefix:p></prefix:body></prefix:html>"#)
+    //         .parse(r#"<prefix:html xmlns:prefix="http://www.w3.org/1999/xhtml"><prefix:body xmlns="different"><prefix:p></prefix:p></prefix:body></prefix:html>"#)
     //         .unwrap();
     //     let s = xot.html5().to_string(root).unwrap();
     //     // TODO: this is probably wrong; we don't expect an additional namespace declaration. On
     //     // the other hand, there was an intervening prefix, but it should have been ignored.
     //     assert_eq!(
     //         s,
-    //         r#"<!DOCTYPE html><html xmlns="https://www.w3.org/1999/xhtml"><body></body></html>"#
+    //         r#"<!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml"><body></body></html>"#
     //     );
     // }
 
@@ -820,6 +875,7 @@ mod tests {
                 Parameters {
                     indentation: Some(Indentation {
                         suppress: vec![body],
+                        ..Default::default()
                     }),
                     ..Default::default()
                 },
@@ -846,6 +902,7 @@ mod tests {
                 Parameters {
                     indentation: Some(Indentation {
                         suppress: vec![body],
+                        ..Default::default()
                     }),
                     ..Default::default()
                 },
@@ -877,6 +934,7 @@ mod tests {
                 Parameters {
                     indentation: Some(Indentation {
                         suppress: vec![foo],
+                        ..Default::default()
                     }),
                     ..Default::default()
                 },
@@ -936,6 +994,7 @@ mod tests {
                 Parameters {
                     indentation: Some(Indentation {
                         suppress: vec![body],
+                        ..Default::default()
                     }),
                     ..Default::default()
                 },
@@ -961,7 +1020,10 @@ mod tests {
             .html5()
             .serialize_string(
                 Parameters {
-                    indentation: Some(Indentation { suppress: vec![] }),
+                    indentation: Some(Indentation {
+                        suppress: vec![],
+                        ..Default::default()
+                    }),
                     ..Default::default()
                 },
                 root,
@@ -972,6 +1034,46 @@ mod tests {
             r#"<!DOCTYPE html><html>
   <body><FOO><p></p></FOO></body>
 </html>
+"#
+        );
+    }
+
+    #[test]
+    fn test_pretty_with_block_decision() {
+        fn decide(xot: &Xot, node: Node) -> IndentDecision {
+            match xot.element(node) {
+                Some(element) if xot.local_name_str(element.name()) == "FOO" => {
+                    IndentDecision::Block
+                }
+                _ => IndentDecision::Default,
+            }
+        }
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<html><body><FOO><p></p></FOO></body></html>"#)
+            .unwrap();
+        let s = xot
+            .html5()
+            .serialize_string(
+                Parameters {
+                    indentation: Some(Indentation {
+                        decision: Some(decide),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                root,
+            )
+            .unwrap();
+        assert_eq!(
+            s,
+            r#"<!DOCTYPE html><html>
+  <body>
+    <FOO>
+      <p></p>
+    </FOO>
+  </body>
+</html>
 "#
         );
     }
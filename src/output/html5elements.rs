@@ -5,7 +5,7 @@ use crate::xotdata::Xot;
 use crate::NamespaceId;
 
 // used to determine whether something is a HTML 5 element
-pub(crate) const XHTML_NS: &str = "https://www.w3.org/1999/xhtml";
+pub(crate) const XHTML_NS: &str = "http://www.w3.org/1999/xhtml";
 const MATHML_NS: &str = "http://www.w3.org/1998/Math/MathML";
 const SVG_NS: &str = "http://www.w3.org/2000/svg";
 
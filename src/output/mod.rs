@@ -1,8 +1,11 @@
 //! Xot offers functionality to serialize XML data in different ways.
 //!
 //! This module lets you control serialization in various ways.
+pub mod c14n;
 mod common;
+mod filter;
 mod fullname;
+pub mod html;
 pub mod html5;
 mod html5_serializer;
 mod html5elements;
@@ -11,17 +14,23 @@ mod icu_normalization;
 mod normalizer;
 mod pretty;
 mod serializer;
+pub mod xhtml;
+mod xhtml_serializer;
 pub mod xml;
 mod xml_serializer;
 
-pub use common::{Indentation, TokenSerializeParameters};
+pub use common::{IndentDecision, Indentation, PrettyOptions, TokenSerializeParameters};
+pub use filter::{FilteredOutput, OutputFilter};
 pub(crate) use fullname::FullnameSerializer;
 pub(crate) use fullname::NamespaceDeclarations;
 pub(crate) use html5_serializer::Html5Serializer;
 pub(crate) use html5elements::Html5Elements;
 pub use normalizer::{NoopNormalizer, Normalizer};
-pub(crate) use pretty::Pretty;
 pub use pretty::PrettyOutputToken;
-pub(crate) use serializer::gen_outputs;
+pub(crate) use pretty::{AttributeWrapper, Pretty};
+#[cfg(feature = "tokio")]
+pub(crate) use serializer::edge_outputs;
+pub(crate) use serializer::{gen_outputs, order_attributes};
 pub use serializer::{Output, OutputToken};
-pub(crate) use xml_serializer::XmlSerializer;
+pub(crate) use xhtml_serializer::XhtmlSerializer;
+pub(crate) use xml_serializer::{pretty_writer, PrettyWriter, XmlSerializer};
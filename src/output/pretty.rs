@@ -1,4 +1,7 @@
-use crate::output::Output;
+use std::io;
+
+use crate::output::xml::Newline;
+use crate::output::{IndentDecision, Output, OutputToken};
 use crate::xotdata::{Node, Xot};
 use crate::{NameId, Value};
 
@@ -49,6 +52,13 @@ where
     // a list of element names where we don't do indentation for the immediate content
     // suppress: &'a [NameId],
     stack: Vec<StackEntry>,
+    // the xml:space in effect for the node we serialize, which it may
+    // inherit from its ancestors. We know it once we see the first output.
+    inherited: Option<Space>,
+    // the content of elements at this depth isn't indented anymore
+    max_depth: Option<usize>,
+    // overrides is_inline for the elements it decides on
+    decision: Option<fn(&Xot, Node) -> IndentDecision>,
 }
 
 impl<'a, IsSuppressed, IsInline> Pretty<'a, IsSuppressed, IsInline>
@@ -56,12 +66,36 @@ where
     IsSuppressed: Fn(NameId) -> bool,
     IsInline: Fn(NameId) -> bool,
 {
-    pub(crate) fn new(xot: &'a Xot, is_suppressed: IsSuppressed, is_inline: IsInline) -> Self {
+    pub(crate) fn new(
+        xot: &'a Xot,
+        is_suppressed: IsSuppressed,
+        is_inline: IsInline,
+        max_depth: Option<usize>,
+    ) -> Self {
         Pretty {
             xot,
             is_suppressed,
             is_inline,
             stack: Vec::new(),
+            inherited: None,
+            max_depth,
+            decision: None,
+        }
+    }
+
+    pub(crate) fn with_decision(
+        mut self,
+        decision: Option<fn(&Xot, Node) -> IndentDecision>,
+    ) -> Self {
+        self.decision = decision;
+        self
+    }
+
+    fn is_inline_element(&self, node: Node, name: NameId) -> bool {
+        match self.decision.map(|decision| decision(self.xot, node)) {
+            Some(IndentDecision::Inline) => true,
+            Some(IndentDecision::Block) => false,
+            Some(IndentDecision::Default) | None => (self.is_inline)(name),
         }
     }
 
@@ -86,7 +120,7 @@ where
                 StackEntry::Mixed => return false,
             }
         }
-        false
+        self.inherited == Some(Space::Preserve)
     }
 
     fn pop(&mut self) {
@@ -94,11 +128,11 @@ where
     }
 
     fn get_indentation(&self) -> usize {
-        if self.in_mixed() {
+        if self.in_mixed() || self.in_space_preserve() {
             return 0;
         }
         let mut count = 0;
-        let mut in_preserve = false;
+        let mut in_preserve = self.inherited == Some(Space::Preserve);
         for entry in self.stack.iter() {
             match entry {
                 StackEntry::Unmixed(Space::Default) => {
@@ -127,8 +161,8 @@ where
         self.xot
             .children(node)
             .any(|child| match self.xot.value(child) {
-                Value::Text(_) => true,
-                Value::Element(element) => (self.is_inline)(element.name()),
+                Value::Text(_) | Value::Cdata(_) | Value::EntityReference(_) => true,
+                Value::Element(element) => self.is_inline_element(child, element.name()),
                 _ => false,
             })
     }
@@ -145,8 +179,22 @@ where
         }
     }
 
+    // The xml:space of the nearest ancestor that has one.
+    fn inherited_space(&self, node: Node) -> Space {
+        self.xot
+            .ancestors(node)
+            .skip(1)
+            .filter(|ancestor| self.xot.is_element(*ancestor))
+            .map(|ancestor| self.element_space(ancestor))
+            .find(|space| *space != Space::Empty)
+            .unwrap_or(Space::Empty)
+    }
+
     pub(crate) fn prettify(&mut self, node: Node, output_token: &Output) -> (usize, bool) {
         use Output::*;
+        if self.inherited.is_none() {
+            self.inherited = Some(self.inherited_space(node));
+        }
         match output_token {
             StartTagOpen(_) => (self.get_indentation(), false),
             Comment(_) | ProcessingInstruction(..) => (self.get_indentation(), self.get_newline()),
@@ -155,6 +203,9 @@ where
                     if !self.has_inline_child(node) {
                         let suppress = if let Some(element) = self.xot.element(node) {
                             (self.is_suppressed)(element.name())
+                                || self
+                                    .max_depth
+                                    .is_some_and(|max_depth| self.stack.len() >= max_depth)
                         } else {
                             false
                         };
@@ -178,7 +229,8 @@ where
             }
             EndTag(_) => {
                 let indentation = if self.xot.first_child(node).is_some() {
-                    let no_indentation = self.in_mixed();
+                    // indentation before the end tag is part of the content
+                    let no_indentation = self.in_mixed() || self.in_space_preserve();
                     self.pop();
                     if !no_indentation {
                         self.get_indentation()
@@ -195,6 +247,93 @@ where
     }
 }
 
+// Buffers the tokens of a start tag, so that if the tag turns out to be
+// wider than the maximum width, we can put each attribute on its own line,
+// aligned with the first one.
+pub(crate) struct AttributeWrapper {
+    width: Option<usize>,
+    line_end: Newline,
+    indent: String,
+    tokens: Vec<OutputToken>,
+}
+
+impl AttributeWrapper {
+    pub(crate) fn new(width: Option<usize>, line_end: Newline) -> Self {
+        AttributeWrapper {
+            width,
+            line_end,
+            indent: String::new(),
+            tokens: Vec::new(),
+        }
+    }
+
+    // `indent` is the indentation that was written before the token
+    pub(crate) fn write<W: io::Write>(
+        &mut self,
+        w: &mut W,
+        indent: String,
+        output: &Output,
+        token: OutputToken,
+    ) -> io::Result<()> {
+        match output {
+            Output::StartTagOpen(_) if self.width.is_some() => {
+                self.indent = indent;
+                self.tokens.push(token);
+            }
+            Output::Prefix(..) | Output::Attribute(..) if !self.tokens.is_empty() => {
+                if !token.text.is_empty() {
+                    self.tokens.push(token);
+                }
+            }
+            Output::StartTagClose if !self.tokens.is_empty() => {
+                self.tokens.push(token);
+                self.flush(w)?;
+            }
+            _ => write_token(w, &token)?,
+        }
+        Ok(())
+    }
+
+    fn flush<W: io::Write>(&mut self, w: &mut W) -> io::Result<()> {
+        let tokens = std::mem::take(&mut self.tokens);
+        let length = self.indent.chars().count()
+            + tokens
+                .iter()
+                .map(|token| token.text.chars().count() + usize::from(token.space))
+                .sum::<usize>();
+        // the open tag, the attributes and the close
+        let (open, rest) = tokens.split_first().unwrap();
+        let (close, attributes) = rest.split_last().unwrap();
+        if attributes.len() < 2 || self.width.is_some_and(|width| length <= width) {
+            for token in &tokens {
+                write_token(w, token)?;
+            }
+            return Ok(());
+        }
+        let align = format!(
+            "{}{}",
+            self.indent,
+            " ".repeat(open.text.chars().count() + 1)
+        );
+        write_token(w, open)?;
+        let (first, attributes) = attributes.split_first().unwrap();
+        write_token(w, first)?;
+        for attribute in attributes {
+            w.write_all(self.line_end.as_bytes())?;
+            w.write_all(align.as_bytes())?;
+            w.write_all(attribute.text.as_bytes())?;
+        }
+        write_token(w, close)
+    }
+}
+
+fn write_token<W: io::Write>(w: &mut W, token: &OutputToken) -> io::Result<()> {
+    if token.space {
+        w.write_all(b" ")?;
+    }
+    w.write_all(token.text.as_bytes())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -220,6 +359,7 @@ mod tests {
             ("preserve", r#"<doc xml:space="preserve"><p>Hello</p></doc>"#, vec![]),
             ("preserve_nested", r#"<doc xml:space="preserve">  <p><foo>  </foo></p></doc>"#, vec![]),
             ("preserve_back_to_default", r#"<doc xml:space="preserve"><p xml:space="default"><foo><bar/></foo></p></doc>"#, vec![]),
+            ("preserve_child", r#"<doc><a xml:space="preserve"><b><c/></b><!--x--></a><d><e/></d></doc>"#, vec![]),
             ("not suppressed", r#"<doc><a><b/></a></doc>"#, vec![]),
             ("suppressed", r#"<doc><a><b/></a></doc>"#, vec!["a"]),
             ("suppressed nested", r#"<doc><a><b><c/></b></a></doc>"#, vec!["a"]),
@@ -234,7 +374,10 @@ mod tests {
         let output_xml = xot
             .serialize_xml_string(
                 output::xml::Parameters {
-                    indentation: Some(output::Indentation { suppress }),
+                    indentation: Some(output::Indentation {
+                        suppress,
+                        ..Default::default()
+                    }),
                     ..Default::default()
                 },
                 document,
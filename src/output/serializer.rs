@@ -3,6 +3,7 @@ use genawaiter::yield_;
 
 use crate::access::NodeEdge;
 use crate::id::{NameId, NamespaceId, PrefixId};
+use crate::output::xml::AttributeOrder;
 use crate::xmlvalue::Element;
 use crate::xmlvalue::Value;
 use crate::xotdata::{Node, Xot};
@@ -14,7 +15,7 @@ use crate::xotdata::{Node, Xot};
 /// represents what the token represents in the XML tree.
 ///
 /// You can use this information for customized serialization.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Output<'a> {
     /// Start tag open, i.e `<foo` or `<ns:foo`
     StartTagOpen(Element),
@@ -28,6 +29,10 @@ pub enum Output<'a> {
     Attribute(NameId, &'a str),
     /// Text, i.e. `foo`
     Text(&'a str),
+    /// CDATA section, i.e. `<![CDATA[foo]]>`
+    Cdata(&'a str),
+    /// Entity reference, i.e. `&foo;`
+    EntityReference(&'a str),
     /// Comment, i.e. `<!-- foo -->`
     Comment(&'a str),
     /// Processing instruction, i.e. `<?foo bar?>`
@@ -56,6 +61,19 @@ pub(crate) fn gen_outputs(xot: &Xot, node: Node) -> impl Iterator<Item = (Node,
     .into_iter()
 }
 
+// The outputs of one edge in the traversal of `top_node`.
+#[cfg(feature = "tokio")]
+pub(crate) fn edge_outputs(xot: &Xot, top_node: Node, edge: NodeEdge) -> Vec<(Node, Output<'_>)> {
+    match edge {
+        NodeEdge::Start(node) => gen_edge_start(xot, top_node, node)
+            .map(|output| (node, output))
+            .collect(),
+        NodeEdge::End(node) => gen_edge_end(xot, node)
+            .map(|output| (node, output))
+            .collect(),
+    }
+}
+
 fn gen_edge_start(xot: &Xot, top_node: Node, node: Node) -> impl Iterator<Item = Output> + '_ {
     gen!({
         let value = xot.value(node);
@@ -89,6 +107,12 @@ fn gen_edge_start(xot: &Xot, top_node: Node, node: Node) -> impl Iterator<Item =
             Value::Text(text) => {
                 yield_!(Output::Text(text.get()));
             }
+            Value::Cdata(text) => {
+                yield_!(Output::Cdata(text.get()));
+            }
+            Value::EntityReference(entity_reference) => {
+                yield_!(Output::EntityReference(entity_reference.name()));
+            }
             Value::Comment(comment) => {
                 yield_!(Output::Comment(comment.get()));
             }
@@ -113,6 +137,37 @@ fn gen_edge_end(xot: &Xot, node: Node) -> impl Iterator<Item = Output> + '_ {
     .into_iter()
 }
 
+// The attributes of an element are consecutive outputs, so we can sort each
+// run of them.
+pub(crate) fn order_attributes<'a>(
+    xot: &'a Xot,
+    outputs: impl Iterator<Item = (Node, Output<'a>)> + 'a,
+    order: AttributeOrder,
+) -> impl Iterator<Item = (Node, Output<'a>)> + 'a {
+    gen!({
+        let mut attributes = Vec::new();
+        for (node, output) in outputs {
+            if order != AttributeOrder::Insertion && matches!(output, Output::Attribute(..)) {
+                attributes.push((node, output));
+                continue;
+            }
+            if !attributes.is_empty() {
+                attributes.sort_by(|(_, a), (_, b)| match (a, b) {
+                    (Output::Attribute(a, _), Output::Attribute(b, _)) => {
+                        order.compare(xot, *a, *b)
+                    }
+                    _ => unreachable!(),
+                });
+                for attribute in attributes.drain(..) {
+                    yield_!(attribute);
+                }
+            }
+            yield_!((node, output));
+        }
+    })
+    .into_iter()
+}
+
 /// Output token
 ///
 /// This represents an [`Output`] as a rendered output token.
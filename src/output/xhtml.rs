@@ -0,0 +1,46 @@
+//! XHTML output method.
+//!
+//! The main entry point is [`Parameters`], which you can pass into
+//! [`Xot::serialize_xhtml_string`] and [`Xot::serialize_xhtml_write`].
+#[cfg(doc)]
+use crate::Xot;
+
+use super::Indentation;
+
+/// Parameters for XHTML generation.
+///
+/// This generates polyglot markup: output that is well-formed XML and that
+/// HTML parsers read into the same tree. This lets you feed documents parsed
+/// with [`Xot::parse_html`] into XML toolchains.
+///
+/// In summary:
+///
+/// - no-namespace and XHTML namespace elements are treated as HTML elements.
+///   Their names, and the names of their attributes without a namespace, are
+///   serialized in lowercase.
+///
+/// - Void elements such as `br` are serialized as self-closing tags with a
+///   space, i.e. `<br />`.
+///
+/// - Other HTML elements always get an explicit close tag, even if they are
+///   empty, i.e. `<p></p>`.
+///
+/// - Namespaces are declared like in XML output. The HTML parser puts HTML
+///   elements in the XHTML namespace, so its `xmlns` is declared explicitly.
+///
+/// - Text is escaped so that both XML and HTML parsers read it the same way.
+///   The content of `script` and `style` elements isn't escaped; if it
+///   contains `<` or `&` it's wrapped in a CDATA section hidden in comments.
+///   CDATA sections are only kept in foreign elements such as `svg`, as
+///   HTML parsers read them as comments elsewhere; in HTML elements their
+///   content is written as text.
+///
+/// - When you serialize a document node, its doctype is emitted (see
+///   [`Xot::doctype`]), or `<!DOCTYPE html>` if it doesn't have one.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Parameters {
+    /// Pretty-print XHTML, and a list of elements where this is suppressed.
+    ///
+    /// This recognizes inline (phrasing) elements.
+    pub indentation: Option<Indentation>,
+}
@@ -0,0 +1,405 @@
+use std::io;
+
+use crate::entity::{serialize_attribute, serialize_cdata, serialize_text};
+use crate::error::Error;
+use crate::id::NameId;
+use crate::output::xml::Newline;
+use crate::output::Normalizer;
+use crate::xotdata::{Node, Xot};
+
+use super::fullname::FullnameSerializer;
+use super::html5_serializer::html_matches_suppress;
+use super::html5elements::Html5Elements;
+use super::{AttributeWrapper, Indentation, Output, OutputToken, Pretty};
+
+pub(crate) struct XhtmlSerializer<'a, N: Normalizer> {
+    xot: &'a Xot,
+    html5_elements: &'a Html5Elements,
+    fullname_serializer: FullnameSerializer<'a>,
+    normalizer: N,
+}
+
+// lowercase the local name, but leave any prefix alone
+fn lowercase_local_name(fullname: &str) -> String {
+    match fullname.split_once(':') {
+        Some((prefix, local_name)) => format!("{}:{}", prefix, local_name.to_ascii_lowercase()),
+        None => fullname.to_ascii_lowercase(),
+    }
+}
+
+impl<'a, N: Normalizer> XhtmlSerializer<'a, N> {
+    pub(crate) fn new(
+        xot: &'a Xot,
+        html5_elements: &'a Html5Elements,
+        node: Node,
+        normalizer: N,
+    ) -> Self {
+        let extra_declarations = xot.namespaces_in_scope(node).collect();
+        let fullname_serializer = FullnameSerializer::new(xot, extra_declarations);
+        Self {
+            xot,
+            html5_elements,
+            fullname_serializer,
+            normalizer,
+        }
+    }
+
+    pub(crate) fn serialize<W: io::Write>(
+        &mut self,
+        w: &mut W,
+        outputs: impl Iterator<Item = (Node, Output<'a>)>,
+    ) -> Result<(), Error> {
+        for (node, output) in outputs {
+            self.serialize_node(w, node, output)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn serialize_pretty<W: io::Write>(
+        &mut self,
+        w: &mut W,
+        outputs: impl Iterator<Item = (Node, Output<'a>)>,
+        indentation: &Indentation,
+    ) -> Result<(), Error> {
+        let is_suppressed = |name_id| {
+            self.html5_elements
+                .formatted_names
+                .matches(self.xot, name_id)
+                || html_matches_suppress(
+                    self.xot,
+                    self.html5_elements,
+                    &indentation.suppress,
+                    name_id,
+                )
+        };
+        let is_inline = |name_id| self.html5_elements.is_inline(self.xot, name_id);
+        let mut pretty = Pretty::new(
+            self.xot,
+            is_suppressed,
+            is_inline,
+            indentation.options.max_depth,
+        )
+        .with_decision(indentation.decision);
+        let options = &indentation.options;
+        // we write a line end once we know it's not the last one
+        let mut newline = false;
+        let mut wrapper = AttributeWrapper::new(options.attribute_wrap_width, Newline::Lf);
+        for (node, output) in outputs {
+            let (level, next_newline) = pretty.prettify(node, &output);
+            if newline {
+                w.write_all(b"\n")?;
+            }
+            let indent = options.indent.repeat(level);
+            w.write_all(indent.as_bytes())?;
+            let token = self.render_output(node, &output)?;
+            wrapper.write(w, indent, &output, token)?;
+            newline = next_newline;
+        }
+        if newline && options.trailing_newline {
+            w.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn serialize_node<W: io::Write>(
+        &mut self,
+        w: &mut W,
+        node: Node,
+        output: Output<'a>,
+    ) -> Result<(), Error> {
+        let data = self.render_output(node, &output)?;
+        if data.space {
+            w.write_all(b" ")?;
+        }
+        w.write_all(data.text.as_bytes())?;
+        Ok(())
+    }
+
+    fn element_fullname(&self, name_id: NameId) -> Result<String, Error> {
+        let fullname = self.fullname_serializer.element_fullname(name_id)?;
+        if self.html5_elements.is_html_element(self.xot, name_id) {
+            Ok(lowercase_local_name(&fullname))
+        } else {
+            Ok(fullname.to_string())
+        }
+    }
+
+    pub(crate) fn render_output(
+        &mut self,
+        node: Node,
+        output: &Output<'a>,
+    ) -> Result<OutputToken, Error> {
+        use Output::*;
+        let r = match output {
+            StartTagOpen(element) => {
+                self.fullname_serializer
+                    .push(self.xot.namespace_declarations(node));
+                OutputToken {
+                    space: false,
+                    text: format!("<{}", self.element_fullname(element.name())?),
+                }
+            }
+            StartTagClose => {
+                let name = self.xot.element(node).unwrap().name();
+                let text = if !self.html5_elements.is_html_element(self.xot, name) {
+                    if self.xot.first_child(node).is_none() {
+                        "/>"
+                    } else {
+                        ">"
+                    }
+                } else if self.html5_elements.void_names.matches(self.xot, name) {
+                    " />"
+                } else {
+                    ">"
+                };
+                OutputToken {
+                    space: false,
+                    text: text.to_string(),
+                }
+            }
+            EndTag(element) => {
+                let name = element.name();
+                let is_html_element = self.html5_elements.is_html_element(self.xot, name);
+                let is_closed = if is_html_element {
+                    self.html5_elements.void_names.matches(self.xot, name)
+                } else {
+                    self.xot.first_child(node).is_none()
+                };
+                let r = if is_closed {
+                    OutputToken {
+                        space: false,
+                        text: "".to_string(),
+                    }
+                } else {
+                    OutputToken {
+                        space: false,
+                        text: format!("</{}>", self.element_fullname(name)?),
+                    }
+                };
+                self.fullname_serializer
+                    .pop(self.xot.has_namespace_declarations(node));
+                r
+            }
+            Prefix(prefix_id, namespace_id) => {
+                // we don't want to output the xml prefix
+                if *namespace_id == self.xot.xml_namespace() {
+                    return Ok(OutputToken {
+                        space: false,
+                        text: "".to_string(),
+                    });
+                }
+                let namespace = self.xot.namespace_str(*namespace_id);
+                if *prefix_id == self.xot.empty_prefix_id {
+                    OutputToken {
+                        space: true,
+                        text: format!("xmlns=\"{}\"", namespace),
+                    }
+                } else {
+                    let prefix = self.xot.prefix_str(*prefix_id);
+                    OutputToken {
+                        space: true,
+                        text: format!("xmlns:{}=\"{}\"", prefix, namespace),
+                    }
+                }
+            }
+            Attribute(name_id, value) => {
+                let fullname = self.fullname_serializer.attribute_fullname(*name_id)?;
+                let element_name = self.xot.element(node).unwrap().name();
+                // attribute names of HTML elements are case-insensitive,
+                // unlike those of foreign elements such as SVG
+                let fullname = if self.html5_elements.is_html_element(self.xot, element_name)
+                    && self.xot.namespace_for_name(*name_id) == self.xot.no_namespace()
+                {
+                    fullname.to_ascii_lowercase()
+                } else {
+                    fullname.to_string()
+                };
+                OutputToken {
+                    space: true,
+                    text: format!(
+                        "{}=\"{}\"",
+                        fullname,
+                        serialize_attribute((*value).into(), &self.normalizer)
+                    ),
+                }
+            }
+            Text(text) => OutputToken {
+                space: false,
+                text: self.serialize_text(node, text),
+            },
+            Cdata(text) => {
+                let parent = self.xot.parent(node).unwrap();
+                let in_html_element = self.xot.element(parent).is_some_and(|element| {
+                    self.html5_elements
+                        .is_html_element(self.xot, element.name())
+                });
+                // HTML parsers only read CDATA sections in foreign elements
+                // such as SVG; elsewhere they're taken for a comment
+                let text = if in_html_element {
+                    self.serialize_text(node, text)
+                } else {
+                    serialize_cdata((*text).into(), &self.normalizer).to_string()
+                };
+                OutputToken { space: false, text }
+            }
+            EntityReference(name) => OutputToken {
+                space: false,
+                text: format!("&{};", name),
+            },
+            Comment(text) => OutputToken {
+                space: false,
+                text: format!("<!--{}-->", text),
+            },
+            ProcessingInstruction(target, data) => {
+                let (target, ns) = self.xot.name_ns_str(*target);
+                if !ns.is_empty() {
+                    return Err(Error::NamespaceInProcessingInstruction);
+                }
+                if let Some(data) = data {
+                    OutputToken {
+                        space: false,
+                        text: format!("<?{} {}?>", target, data),
+                    }
+                } else {
+                    OutputToken {
+                        space: false,
+                        text: format!("<?{}?>", target),
+                    }
+                }
+            }
+        };
+        Ok(r)
+    }
+
+    fn serialize_text(&self, node: Node, text: &str) -> String {
+        // a text node can be a child of an element or document
+        let parent = self.xot.parent(node).unwrap();
+        let is_no_escape = self.xot.element(parent).is_some_and(|element| {
+            self.html5_elements
+                .no_escape_names
+                .matches(self.xot, element.name())
+        });
+        if is_no_escape {
+            self.serialize_text_no_escape(text)
+        } else {
+            serialize_text(text.into(), &self.normalizer, false).to_string()
+        }
+    }
+
+    // The content of script and style elements is not escaped by HTML
+    // parsers, so it has to be written as is. If it contains characters that
+    // are special in XML, we wrap it in a CDATA section, hidden from HTML in
+    // comments that both JavaScript and CSS understand.
+    fn serialize_text_no_escape(&self, text: &str) -> String {
+        let text = self.normalizer.normalize(text.into());
+        if !text.contains(['<', '&']) {
+            text.to_string()
+        } else if !text.contains("]]>") {
+            format!("/*<![CDATA[*/{}/*]]>*/", text)
+        } else {
+            // we can't represent this in both; prefer XML
+            serialize_text(text, &self.normalizer, false).to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{xhtml::Parameters, Indentation};
+
+    use super::*;
+
+    fn xhtml(xml: &str) -> String {
+        let mut xot = Xot::new();
+        let root = xot.parse(xml).unwrap();
+        let html = xot.document_element(root).unwrap();
+        xot.serialize_xhtml_string(Parameters::default(), html)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_void_element() {
+        assert_eq!(
+            xhtml(r#"<p xmlns="http://www.w3.org/1999/xhtml">a<br/>b<img src="x"></img></p>"#),
+            r#"<p xmlns="http://www.w3.org/1999/xhtml">a<br />b<img src="x" /></p>"#
+        );
+    }
+
+    #[test]
+    fn test_empty_non_void_element() {
+        assert_eq!(
+            xhtml(r#"<div xmlns="http://www.w3.org/1999/xhtml"><p/><span/></div>"#),
+            r#"<div xmlns="http://www.w3.org/1999/xhtml"><p></p><span></span></div>"#
+        );
+    }
+
+    #[test]
+    fn test_foreign_element() {
+        assert_eq!(
+            xhtml(r#"<div xmlns="http://www.w3.org/1999/xhtml"><svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 1 1"><circle/><g></g></svg></div>"#),
+            r#"<div xmlns="http://www.w3.org/1999/xhtml"><svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 1 1"><circle/><g/></svg></div>"#
+        );
+    }
+
+    #[test]
+    fn test_lowercase() {
+        assert_eq!(
+            xhtml(r#"<DIV xmlns="http://www.w3.org/1999/xhtml" CLASS="A"><BR/></DIV>"#),
+            r#"<div xmlns="http://www.w3.org/1999/xhtml" class="A"><br /></div>"#
+        );
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(
+            xhtml(r#"<p xmlns="http://www.w3.org/1999/xhtml" title="&quot;a&quot; &amp; &lt;b&gt;">a &lt; b &amp; c &gt; d</p>"#),
+            r#"<p xmlns="http://www.w3.org/1999/xhtml" title="&quot;a&quot; &amp; &lt;b>">a &lt; b &amp; c &gt; d</p>"#
+        );
+    }
+
+    #[test]
+    fn test_script() {
+        assert_eq!(
+            xhtml(r#"<head xmlns="http://www.w3.org/1999/xhtml"><script>a = b</script><script>if (a &lt; b) c()</script></head>"#),
+            r#"<head xmlns="http://www.w3.org/1999/xhtml"><script>a = b</script><script>/*<![CDATA[*/if (a < b) c()/*]]>*/</script></head>"#
+        );
+    }
+
+    #[test]
+    fn test_cdata() {
+        let mut xot = Xot::new();
+        let options = crate::ParseOptions {
+            preserve_cdata: true,
+            ..Default::default()
+        };
+        let root = xot
+            .parse_with_options(r#"<p xmlns="http://www.w3.org/1999/xhtml"><![CDATA[a < b]]><svg xmlns="http://www.w3.org/2000/svg"><text><![CDATA[c < d]]></text></svg><script><![CDATA[if (a < b) c()]]></script></p>"#, &options)
+            .unwrap();
+        let p = xot.document_element(root).unwrap();
+        assert_eq!(
+            xot.serialize_xhtml_string(Parameters::default(), p)
+                .unwrap(),
+            r#"<p xmlns="http://www.w3.org/1999/xhtml">a &lt; b<svg xmlns="http://www.w3.org/2000/svg"><text><![CDATA[c < d]]></text></svg><script>/*<![CDATA[*/if (a < b) c()/*]]>*/</script></p>"#
+        );
+    }
+
+    #[test]
+    fn test_pretty() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><p>a<br/>b</p></body></html>"#)
+            .unwrap();
+        let s = xot
+            .serialize_xhtml_string(
+                Parameters {
+                    indentation: Some(Indentation::default()),
+                },
+                root,
+            )
+            .unwrap();
+        assert_eq!(
+            s,
+            "<!DOCTYPE html><html xmlns=\"http://www.w3.org/1999/xhtml\">\n  <body>\n    <p>a<br />b</p>\n  </body>\n</html>\n"
+        );
+    }
+}
@@ -17,26 +17,29 @@
 // * `normalization-form` is not directly supported, but if you enable the `icu`
 //   feature you can use [`Xot::serialize_xml_string_with_normalizer`] to control
 //   normalization with an ICU normalizer.
-// * There is no way to declare the `version` parameter, as only XML 1.0 is
-//   permitted at this time.
-// * You can only influence encoding parameter of the XML declaration, and
-//   this does not trigger actual encoding; output is always UTF-8 and it's up
-//   to you to do any further re-encoding.
+// * The `version` parameter is part of [`Declaration`], as without an XML
+//   declaration the document is XML 1.0.
+// * The `encoding` of [`Declaration`] only declares an encoding. Output is
+//   UTF-8, except with [`Xot::serialize_xml_write_with_encoding`] and
+//   [`Xot::serialize_xml_bytes_with_encoding`], which write the encoding you
+//   give them and declare it.
 // * The `item-separator` parameter is specific to XPath/XSLT sequences and is
 //   not supported directly by Xot.
 // * The `media-type` property is only meaningful in the context of a larger
 //   system and is not supported directly by Xot.
-// * `undeclare-prefixes` is only supported by XML 1.1, which Xot does not
-//   support at present.
+// * `undeclare-prefixes` is only supported by XML 1.1, which Xot supports
+//   only in part at present.
 
-#[cfg(doc)]
-use crate::Xot;
+use crate::{Error, Node, Xot};
 
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::io::Write;
+use std::sync::Arc;
 
-use crate::NameId;
+use crate::{NameId, NamespaceId};
 
-use super::Indentation;
+use super::{Indentation, TokenSerializeParameters};
 
 /// Parameters for XML generation.
 ///
@@ -48,19 +51,539 @@ pub struct Parameters {
     pub indentation: Option<Indentation>,
     /// Elements that should have their text content be serialized as CDATA
     /// sections.
+    ///
+    /// This applies to the text that's a direct child of such an element.
+    /// If the text contains `]]>`, the CDATA section is split in two there,
+    /// as it would end it otherwise. In XML 1.1, the section is also split
+    /// around the character reference of a control character, as these
+    /// have to be written as references.
     pub cdata_section_elements: Vec<NameId>,
     /// The XML declaration, if any.
+    ///
+    /// This is independent of the declaration the document was parsed with,
+    /// which you can get with [`Xot::xml_declaration`].
     pub declaration: Option<Declaration>,
     /// The doctype declaration, if any.
+    ///
+    /// Use [`DocType::Document`] to write the doctype of the document,
+    /// which you can get with [`Xot::doctype`].
     pub doctype: Option<DocType>,
     /// Whether to escape the `>` character in text content. By default this is
     /// true, which means that `>` is escaped as `&gt;`. If you set this to true,
     /// `>` is not escaped, except for the special case of `]]>` outside of CDATA,
     /// which is mandated by the XML specification to always be escaped.
     pub unescaped_gt: bool,
+    /// Leave out attributes that were added because the doctype declares a
+    /// default for them.
+    ///
+    /// See [`ParseOptions::apply_attribute_defaults`](crate::ParseOptions::apply_attribute_defaults)
+    /// and [`Xot::is_defaulted_attribute`]. A parser that reads the doctype
+    /// adds them again.
+    pub omit_defaulted_attributes: bool,
+    /// The order in which the attributes of an element are written.
+    ///
+    /// By default this is the order in which they were added to the tree.
+    pub attribute_order: AttributeOrder,
+    /// The quote character around attribute values and namespace
+    /// declarations.
+    pub attribute_quote: Quote,
+    /// Whether to leave the quote character that doesn't delimit attribute
+    /// values unescaped. By default both are escaped, so with double quotes
+    /// `'` is written as `&apos;`. If you set this, only the delimiting
+    /// quote is escaped.
+    pub unescaped_quote: bool,
+    /// How to write character references, such as those for control
+    /// characters in XML 1.1.
+    pub character_references: CharacterReferences,
+    /// How to write elements without content.
+    pub empty_element_form: EmptyElementForm,
+    /// Elements that are written in the other form than
+    /// [`empty_element_form`](Parameters::empty_element_form) when they're
+    /// empty.
+    pub empty_element_form_exceptions: Vec<NameId>,
+    /// Start the output with a byte order mark.
+    ///
+    /// When you serialize to UTF-16 with
+    /// [`Xot::serialize_xml_write_with_encoding`], there always is one.
+    pub byte_order_mark: bool,
+    /// Prefixes to declare for namespaces that have no prefix in scope.
+    ///
+    /// Without a prefix, an element or attribute in a namespace can't be
+    /// serialized, and you get [`Error::MissingPrefix`].
+    /// With a prefix in this map, the namespace is declared on the element
+    /// where it's needed instead. The empty prefix makes it the default
+    /// namespace; this only applies to elements, as attributes can't be in
+    /// the default namespace. A prefix that's already declared for another
+    /// namespace in the tree isn't used.
+    ///
+    /// To add the declarations to the tree instead, use
+    /// [`Xot::create_missing_prefixes_with_preferred`](crate::Xot::create_missing_prefixes_with_preferred).
+    pub preferred_prefixes: HashMap<NamespaceId, String>,
+    /// Move namespace declarations to the document element, and leave out
+    /// declarations of a prefix that's already declared for the same
+    /// namespace.
+    ///
+    /// A prefix that's declared for different namespaces isn't moved, and
+    /// neither is a default namespace if there are elements that aren't in
+    /// a namespace. When you serialize an element, the declarations are
+    /// moved to that element.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    /// use xot::output::xml::Parameters;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<doc><a:p xmlns:a="urn:a"/><a:p xmlns:a="urn:a"><b:q xmlns:b="urn:b"/></a:p></doc>"#)?;
+    /// let xml = xot.serialize_xml_string(Parameters {
+    ///     hoist_namespaces: true,
+    ///     ..Default::default()
+    /// }, root)?;
+    /// assert_eq!(xml, r#"<doc xmlns:a="urn:a" xmlns:b="urn:b"><a:p/><a:p><b:q/></a:p></doc>"#);
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub hoist_namespaces: bool,
+    /// What to do with a character that can't be written.
+    ///
+    /// By default, serialization fails.
+    pub unrepresentable_characters: UnrepresentableCharacters,
+    /// The line end after the XML declaration and the doctype, and in
+    /// pretty-printed output.
+    ///
+    /// ```rust
+    /// use xot::{Xot, output};
+    /// use xot::output::xml::{Declaration, Newline, Parameters};
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse("<a><b/></a>")?;
+    /// let xml = xot.serialize_xml_string(Parameters {
+    ///     declaration: Some(Declaration::default()),
+    ///     indentation: Some(Default::default()),
+    ///     newline: Newline::CrLf,
+    ///     ..Default::default()
+    /// }, root)?;
+    /// assert_eq!(xml, "<?xml version=\"1.0\"?>\r\n<a>\r\n  <b/>\r\n</a>\r\n");
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub newline: Newline,
+    /// The maximum size of the output in bytes.
+    ///
+    /// Once the output would get larger than this, serialization stops with
+    /// [`Error::OutputTooLarge`], so that a large tree can't take up too
+    /// much memory or space. Part of the output may have been written
+    /// already. The size is that of the bytes written, so when you
+    /// serialize in an encoding with
+    /// [`Xot::serialize_xml_write_with_encoding`](crate::Xot::serialize_xml_write_with_encoding),
+    /// it's counted after encoding.
+    pub max_output_size: Option<usize>,
     // TODO: character maps
 }
 
+impl Parameters {
+    pub(crate) fn token_serialize_parameters(&self) -> TokenSerializeParameters {
+        TokenSerializeParameters {
+            cdata_section_elements: self.cdata_section_elements.clone(),
+            unescaped_gt: self.unescaped_gt,
+            xml_1_1: self
+                .declaration
+                .as_ref()
+                .is_some_and(|declaration| declaration.version == Version::V1_1),
+            attribute_quote: self.attribute_quote,
+            unescaped_quote: self.unescaped_quote,
+            character_references: self.character_references,
+            empty_element_form: self.empty_element_form,
+            empty_element_form_exceptions: self.empty_element_form_exceptions.clone(),
+            preferred_prefixes: self.preferred_prefixes.clone(),
+            hoist_namespaces: self.hoist_namespaces,
+            unrepresentable_characters: self.unrepresentable_characters.clone(),
+            encoding: None,
+            max_character: None,
+        }
+    }
+}
+
+/// Serialize a node as XML, with options set one by one.
+///
+/// You get this with [`Node::serialize`]. Each method changes one of the
+/// [`Parameters`]; [`Serializer::to_string`] and [`Serializer::write`]
+/// serialize the node with them.
+///
+/// ```rust
+/// use xot::Xot;
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse(r#"<doc><p b="B" a="A"/></doc>"#)?;
+///
+/// let xml = root.serialize(&xot).pretty().sort_attributes().to_string()?;
+/// assert_eq!(xml, "<doc>\n  <p a=\"A\" b=\"B\"/>\n</doc>\n");
+/// # Ok::<(), xot::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Serializer<'a> {
+    xot: &'a Xot,
+    node: Node,
+    parameters: Parameters,
+}
+
+impl<'a> Serializer<'a> {
+    pub(crate) fn new(xot: &'a Xot, node: Node) -> Self {
+        Serializer {
+            xot,
+            node,
+            parameters: Parameters::default(),
+        }
+    }
+
+    /// Use these parameters, replacing any set before.
+    pub fn parameters(mut self, parameters: Parameters) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    /// Pretty-print with the default indentation.
+    pub fn pretty(self) -> Self {
+        self.indentation(Indentation::default())
+    }
+
+    /// Pretty-print with this indentation.
+    pub fn indentation(mut self, indentation: Indentation) -> Self {
+        self.parameters.indentation = Some(indentation);
+        self
+    }
+
+    /// Whether to write an XML declaration, `<?xml version="1.0"?>`.
+    pub fn declaration(mut self, declaration: bool) -> Self {
+        self.parameters.declaration = declaration.then(Declaration::default);
+        self
+    }
+
+    /// Write this doctype declaration.
+    pub fn doctype(mut self, doctype: DocType) -> Self {
+        self.parameters.doctype = Some(doctype);
+        self
+    }
+
+    /// Write the attributes of each element sorted by name.
+    pub fn sort_attributes(self) -> Self {
+        self.attribute_order(AttributeOrder::Name)
+    }
+
+    /// Write the attributes of each element in this order.
+    pub fn attribute_order(mut self, attribute_order: AttributeOrder) -> Self {
+        self.parameters.attribute_order = attribute_order;
+        self
+    }
+
+    /// Write the text content of these elements as CDATA sections.
+    pub fn cdata_section_elements(mut self, names: Vec<NameId>) -> Self {
+        self.parameters.cdata_section_elements = names;
+        self
+    }
+
+    /// Delimit attribute values with this quote character.
+    pub fn attribute_quote(mut self, quote: Quote) -> Self {
+        self.parameters.attribute_quote = quote;
+        self
+    }
+
+    /// Write elements without content in this form.
+    pub fn empty_element_form(mut self, form: EmptyElementForm) -> Self {
+        self.parameters.empty_element_form = form;
+        self
+    }
+
+    /// Move namespace declarations to the top element.
+    pub fn hoist_namespaces(mut self) -> Self {
+        self.parameters.hoist_namespaces = true;
+        self
+    }
+
+    /// Use this line end.
+    pub fn newline(mut self, newline: Newline) -> Self {
+        self.parameters.newline = newline;
+        self
+    }
+
+    /// Serialize to a string.
+    ///
+    /// See [`Xot::serialize_xml_string`].
+    pub fn to_string(self) -> Result<String, Error> {
+        self.xot.serialize_xml_string(self.parameters, self.node)
+    }
+
+    /// Serialize to a writer.
+    ///
+    /// See [`Xot::serialize_xml_write`].
+    pub fn write(self, w: &mut impl Write) -> Result<(), Error> {
+        self.xot.serialize_xml_write(self.parameters, self.node, w)
+    }
+}
+
+/// How an element without content is written.
+///
+/// ```rust
+/// use xot::Xot;
+/// use xot::output::xml::{EmptyElementForm, Parameters};
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse("<doc><a/><br/></doc>")?;
+/// let br = xot.add_name("br");
+///
+/// let xml = xot.serialize_xml_string(Parameters {
+///     empty_element_form: EmptyElementForm::Expanded,
+///     empty_element_form_exceptions: vec![br],
+///     ..Default::default()
+/// }, root)?;
+/// assert_eq!(xml, "<doc><a></a><br/></doc>");
+/// # Ok::<(), xot::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EmptyElementForm {
+    /// A self-closing tag, like `<a/>`.
+    #[default]
+    SelfClosing,
+    /// A start tag followed by an end tag, like `<a></a>`.
+    Expanded,
+}
+
+/// The quote character used to delimit attribute values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Quote {
+    /// `"`
+    #[default]
+    Double,
+    /// `'`
+    Single,
+}
+
+impl Quote {
+    pub(crate) fn as_char(self) -> char {
+        match self {
+            Quote::Double => '"',
+            Quote::Single => '\'',
+        }
+    }
+}
+
+/// The line end written in the output.
+///
+/// This is the line end after the XML declaration and the doctype, in the
+/// internal subset of the doctype, and between the lines of pretty-printed
+/// output. Line ends in text, attribute values, comments and processing
+/// instructions are written as they are in the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Newline {
+    /// `\n`
+    #[default]
+    Lf,
+    /// `\r\n`, as is common on Windows.
+    CrLf,
+}
+
+impl Newline {
+    pub(crate) fn as_bytes(self) -> &'static [u8] {
+        match self {
+            Newline::Lf => b"\n",
+            Newline::CrLf => b"\r\n",
+        }
+    }
+}
+
+/// The notation of character references in serialized output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CharacterReferences {
+    /// Hexadecimal, such as `&#x1F;`.
+    #[default]
+    Hexadecimal,
+    /// Decimal, such as `&#31;`.
+    Decimal,
+}
+
+/// What to do with a character that can't be written in the output.
+///
+/// A character can't be written if XML doesn't allow it, such as `\0`, or a
+/// control character in XML 1.0. In XML 1.1, control characters can only be
+/// written as character references, so they can't be written in comments
+/// and processing instructions. When you serialize in an encoding with
+/// [`Xot::serialize_xml_write_with_encoding`], a character the encoding
+/// doesn't have can't be written in comments, processing instructions and
+/// CDATA sections either.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use xot::Xot;
+/// use xot::output::xml::{Parameters, UnrepresentableCharacters};
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse("<doc/>")?;
+/// let doc = xot.document_element(root)?;
+/// xot.append_text(doc, "a\u{1}b")?;
+///
+/// assert!(xot.serialize_xml_string(Parameters::default(), root).is_err());
+///
+/// let xml = xot.serialize_xml_string(Parameters {
+///     unrepresentable_characters: UnrepresentableCharacters::Replace(Arc::new(|_c| {
+///         "\u{FFFD}".to_string()
+///     })),
+///     ..Default::default()
+/// }, root)?;
+/// assert_eq!(xml, "<doc>a\u{FFFD}b</doc>");
+/// # Ok::<(), xot::Error>(())
+/// ```
+#[derive(Clone, Default)]
+pub enum UnrepresentableCharacters {
+    /// Fail with [`Error::UnrepresentableCharacter`].
+    #[default]
+    Error,
+    /// Write the text the replacer returns instead of the character. This
+    /// text is escaped like the content it's in, but it isn't checked
+    /// again.
+    Replace(Arc<dyn CharacterReplacer>),
+}
+
+impl std::fmt::Debug for UnrepresentableCharacters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnrepresentableCharacters::Error => write!(f, "Error"),
+            UnrepresentableCharacters::Replace(_) => write!(f, "Replace"),
+        }
+    }
+}
+
+// replacers are equal only if they're the same one
+impl PartialEq for UnrepresentableCharacters {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (UnrepresentableCharacters::Error, UnrepresentableCharacters::Error) => true,
+            (UnrepresentableCharacters::Replace(a), UnrepresentableCharacters::Replace(b)) => {
+                Arc::ptr_eq(a, b)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for UnrepresentableCharacters {}
+
+/// Replaces a character that can't be written in the output.
+///
+/// Use this with [`UnrepresentableCharacters::Replace`]. Any closure that
+/// takes a character and returns a string is a replacer.
+pub trait CharacterReplacer: Send + Sync {
+    /// The text to write instead of the character.
+    fn replace(&self, c: char) -> String;
+}
+
+impl<F> CharacterReplacer for F
+where
+    F: Fn(char) -> String + Send + Sync,
+{
+    fn replace(&self, c: char) -> String {
+        self(c)
+    }
+}
+
+/// The order in which attributes are serialized.
+///
+/// Namespace declarations always come before the attributes, and aren't
+/// affected by this.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use xot::{Xot, output};
+/// use xot::output::xml::{AttributeOrder, Parameters};
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse(r#"<doc c="3" a="1" b="2"/>"#)?;
+///
+/// let xml = xot.serialize_xml_string(Parameters {
+///     attribute_order: AttributeOrder::Name,
+///     ..Default::default()
+/// }, root)?;
+/// assert_eq!(xml, r#"<doc a="1" b="2" c="3"/>"#);
+///
+/// // reverse order by local name
+/// let xml = xot.serialize_xml_string(Parameters {
+///     attribute_order: AttributeOrder::Custom(Arc::new(|xot: &Xot, a, b| {
+///         xot.local_name_str(b).cmp(xot.local_name_str(a))
+///     })),
+///     ..Default::default()
+/// }, root)?;
+/// assert_eq!(xml, r#"<doc c="3" b="2" a="1"/>"#);
+/// # Ok::<(), xot::Error>(())
+/// ```
+#[derive(Clone, Default)]
+pub enum AttributeOrder {
+    /// The order in which the attributes were added to the element. For a
+    /// parsed document, this is the order in the source.
+    #[default]
+    Insertion,
+    /// Sorted by name: first by namespace URI, where attributes without a
+    /// namespace come first, and then by local name. This is the order
+    /// canonical XML uses.
+    Name,
+    /// Sorted with a comparator. Attributes that compare equal keep their
+    /// insertion order.
+    Custom(Arc<dyn AttributeComparator>),
+}
+
+impl std::fmt::Debug for AttributeOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttributeOrder::Insertion => write!(f, "Insertion"),
+            AttributeOrder::Name => write!(f, "Name"),
+            AttributeOrder::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+// comparators are equal only if they're the same one
+impl PartialEq for AttributeOrder {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (AttributeOrder::Insertion, AttributeOrder::Insertion) => true,
+            (AttributeOrder::Name, AttributeOrder::Name) => true,
+            (AttributeOrder::Custom(a), AttributeOrder::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for AttributeOrder {}
+
+/// Compares attribute names to decide the order in which they're written.
+///
+/// Use this with [`AttributeOrder::Custom`]. Any closure that takes a
+/// [`Xot`] and two attribute names and returns an [`Ordering`] is an
+/// attribute comparator. It has to be [`Send`] and [`Sync`] so that
+/// parameters can be used in async code.
+pub trait AttributeComparator: Send + Sync {
+    /// Compare the names of two attributes of the same element.
+    fn compare(&self, xot: &Xot, a: NameId, b: NameId) -> Ordering;
+}
+
+impl<F> AttributeComparator for F
+where
+    F: Fn(&Xot, NameId, NameId) -> Ordering + Send + Sync,
+{
+    fn compare(&self, xot: &Xot, a: NameId, b: NameId) -> Ordering {
+        self(xot, a, b)
+    }
+}
+
+impl AttributeOrder {
+    pub(crate) fn compare(&self, xot: &Xot, a: NameId, b: NameId) -> Ordering {
+        match self {
+            AttributeOrder::Insertion => Ordering::Equal,
+            AttributeOrder::Name => {
+                let (a_local, a_namespace) = xot.name_ns_str(a);
+                let (b_local, b_namespace) = xot.name_ns_str(b);
+                (a_namespace, a_local).cmp(&(b_namespace, b_local))
+            }
+            AttributeOrder::Custom(comparator) => comparator.compare(xot, a, b),
+        }
+    }
+}
+
 /// How to format the XML declaration.
 ///
 /// Examples:
@@ -80,18 +603,39 @@ pub struct Parameters {
 pub struct Declaration {
     /// This causes an encoding declaration to be included in the XML declaration.
     /// The text given here is taken literally. It does not affect the encoding of
-    /// the output of serialization; that is always UTF-8.
+    /// the output of serialization; that is UTF-8, unless you use
+    /// [`Xot::serialize_xml_write_with_encoding`], which sets this for you.
     pub encoding: Option<String>,
     /// This causes a standalone declaration to be included in the XML declaration.
     pub standalone: Option<bool>,
-    // TODO: if in the future we add XML 1.1 support, we can add the version here.
-    // This way without a declaration it is automatically an XML 1.0 document. Potentially
-    // also include `undeclare-prefixes` here, as that's only supported in XML 1.1.
+    /// The XML version. If this is XML 1.1, control characters in text and
+    /// attribute values are serialized as character references, as XML 1.1
+    /// requires.
+    pub version: Version,
+    // Potentially also include `undeclare-prefixes` here, as that's only
+    // supported in XML 1.1.
+}
+
+/// The XML version declared in the XML declaration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Version {
+    /// XML 1.0
+    #[default]
+    V1_0,
+    /// XML 1.1
+    V1_1,
 }
 
 impl Declaration {
-    pub(crate) fn serialize(&self, w: &mut impl Write) -> Result<(), std::io::Error> {
-        w.write_all(b"<?xml version=\"1.0\"")?;
+    pub(crate) fn serialize(
+        &self,
+        w: &mut impl Write,
+        newline: Newline,
+    ) -> Result<(), std::io::Error> {
+        w.write_all(match self.version {
+            Version::V1_0 => b"<?xml version=\"1.0\"",
+            Version::V1_1 => b"<?xml version=\"1.1\"",
+        })?;
         if let Some(encoding) = &self.encoding {
             w.write_all(b" encoding=\"")?;
             w.write_all(encoding.as_bytes())?;
@@ -102,7 +646,8 @@ impl Declaration {
             w.write_all(if standalone { b"yes" } else { b"no" })?;
             w.write_all(b"\"")?;
         }
-        w.write_all(b"?>\n")?;
+        w.write_all(b"?>")?;
+        w.write_all(newline.as_bytes())?;
         Ok(())
     }
 }
@@ -132,10 +677,22 @@ pub enum DocType {
         /// The system identifier.
         system: String,
     },
+    /// The doctype of the document that's serialized, as it was parsed or
+    /// set with [`Xot::set_doctype`](crate::Xot::set_doctype), including
+    /// the declarations in its internal subset.
+    ///
+    /// Nothing is written if the document has no doctype, or if the node
+    /// you serialize isn't a document node.
+    Document,
 }
 
 impl DocType {
-    pub(crate) fn serialize(&self, name: &str, w: &mut impl Write) -> Result<(), std::io::Error> {
+    pub(crate) fn serialize(
+        &self,
+        name: &str,
+        w: &mut impl Write,
+        newline: Newline,
+    ) -> Result<(), std::io::Error> {
         w.write_all(b"<!DOCTYPE ")?;
         w.write_all(name.as_bytes())?;
         match self {
@@ -151,15 +708,21 @@ impl DocType {
                 w.write_all(system.as_bytes())?;
                 w.write_all(b"\"")?;
             }
+            // the serializer writes the doctype of the document instead
+            DocType::Document => {}
         }
-        w.write_all(b">\n")?;
+        w.write_all(b">")?;
+        w.write_all(newline.as_bytes())?;
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{output::Indentation, Xot};
+    use crate::{
+        output::{Indentation, PrettyOptions},
+        Error, Xot,
+    };
 
     use super::*;
 
@@ -200,7 +763,10 @@ mod tests {
         let mut xot = Xot::new();
 
         let m = Parameters {
-            indentation: Some(Indentation { suppress: vec![] }),
+            indentation: Some(Indentation {
+                suppress: vec![],
+                ..Default::default()
+            }),
             ..Default::default()
         };
         let doc = xot.parse("<doc><p><k>foo</k></p></doc>").unwrap();
@@ -221,7 +787,10 @@ mod tests {
         let mut xot = Xot::new();
         let p = xot.add_name("p");
         let m = Parameters {
-            indentation: Some(Indentation { suppress: vec![p] }),
+            indentation: Some(Indentation {
+                suppress: vec![p],
+                ..Default::default()
+            }),
             ..Default::default()
         };
         let doc = xot.parse("<doc><p><k>foo</k></p></doc>").unwrap();
@@ -235,6 +804,269 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_xml_output_indent_preserve_inherited() {
+        let mut xot = Xot::new();
+        let doc = xot
+            .parse(r#"<doc><a xml:space="preserve"><b><c><d/></c></b><e xml:space="default"><f/></e></a></doc>"#)
+            .unwrap();
+        let a = xot.first_child(xot.document_element(doc).unwrap()).unwrap();
+        let b = xot.first_child(a).unwrap();
+        let e = xot.next_sibling(b).unwrap();
+        let m = || Parameters {
+            indentation: Some(Default::default()),
+            ..Default::default()
+        };
+
+        // a subtree keeps the xml:space of its ancestors
+        assert_eq!(
+            xot.serialize_xml_string(m(), b).unwrap(),
+            "<b><c><d/></c></b>"
+        );
+        assert_eq!(
+            xot.serialize_xml_string(m(), e).unwrap(),
+            "<e xml:space=\"default\">\n  <f/>\n</e>"
+        );
+    }
+
+    #[test]
+    fn test_xml_output_indent_options() {
+        let mut xot = Xot::new();
+        let doc = xot
+            .parse("<doc><p><k>foo</k><l/></p><!--c--></doc>")
+            .unwrap();
+        let serialize = |xot: &Xot, options: PrettyOptions| {
+            xot.serialize_xml_string(
+                Parameters {
+                    indentation: Some(Indentation {
+                        options,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                doc,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            serialize(
+                &xot,
+                PrettyOptions {
+                    indent: "    ".to_string(),
+                    ..Default::default()
+                }
+            ),
+            "<doc>\n    <p>\n        <k>foo</k>\n        <l/>\n    </p>\n    <!--c-->\n</doc>\n"
+        );
+        assert_eq!(
+            serialize(
+                &xot,
+                PrettyOptions {
+                    indent: "\t".to_string(),
+                    trailing_newline: false,
+                    ..Default::default()
+                }
+            ),
+            "<doc>\n\t<p>\n\t\t<k>foo</k>\n\t\t<l/>\n\t</p>\n\t<!--c-->\n</doc>"
+        );
+        assert_eq!(
+            serialize(
+                &xot,
+                PrettyOptions {
+                    max_depth: Some(1),
+                    ..Default::default()
+                }
+            ),
+            "<doc>\n  <p><k>foo</k><l/></p>\n  <!--c-->\n</doc>\n"
+        );
+        assert_eq!(
+            serialize(
+                &xot,
+                PrettyOptions {
+                    max_depth: Some(0),
+                    ..Default::default()
+                }
+            ),
+            "<doc><p><k>foo</k><l/></p><!--c--></doc>\n"
+        );
+    }
+
+    #[test]
+    fn test_xml_output_attribute_wrap_width() {
+        let mut xot = Xot::new();
+        let doc = xot
+            .parse(r#"<config xmlns:x="urn:x"><server name="main" host="localhost" port="8080"/><client name="c" x:id="1"/></config>"#)
+            .unwrap();
+        let output_xml = xot
+            .serialize_xml_string(
+                Parameters {
+                    indentation: Some(Indentation {
+                        options: PrettyOptions {
+                            attribute_wrap_width: Some(30),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                doc,
+            )
+            .unwrap();
+        assert_eq!(
+            output_xml,
+            r#"<config xmlns:x="urn:x">
+  <server name="main"
+          host="localhost"
+          port="8080"/>
+  <client name="c" x:id="1"/>
+</config>
+"#
+        );
+    }
+
+    #[test]
+    fn test_xml_output_attribute_order() {
+        let mut xot = Xot::new();
+        let doc = xot
+            .parse(r#"<doc xmlns:x="urn:x" x:a="1" b="2" a="3"><p/></doc>"#)
+            .unwrap();
+        let doc_el = xot.document_element(doc).unwrap();
+        let c = xot.add_name("c");
+        let p = xot.first_child(doc_el).unwrap();
+        xot.attributes_mut(p).insert(c, "4".to_string());
+        let a = xot.add_name("a");
+        xot.attributes_mut(p).insert(a, "5".to_string());
+        let serialize = |xot: &Xot, attribute_order: AttributeOrder| {
+            xot.serialize_xml_string(
+                Parameters {
+                    attribute_order,
+                    ..Default::default()
+                },
+                doc,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            serialize(&xot, AttributeOrder::Insertion),
+            r#"<doc xmlns:x="urn:x" x:a="1" b="2" a="3"><p c="4" a="5"/></doc>"#
+        );
+        assert_eq!(
+            serialize(&xot, AttributeOrder::Name),
+            r#"<doc xmlns:x="urn:x" a="3" b="2" x:a="1"><p a="5" c="4"/></doc>"#
+        );
+        // put b first; the others keep their order
+        let b_first = |xot: &Xot, a: NameId, b: NameId| {
+            (xot.local_name_str(b) == "b").cmp(&(xot.local_name_str(a) == "b"))
+        };
+        assert_eq!(
+            serialize(&xot, AttributeOrder::Custom(Arc::new(b_first))),
+            r#"<doc xmlns:x="urn:x" b="2" x:a="1" a="3"><p c="4" a="5"/></doc>"#
+        );
+    }
+
+    #[test]
+    fn test_xml_output_quotes() {
+        let mut xot = Xot::new();
+        let doc = xot
+            .parse(r#"<doc xmlns="urn:it's" a="it's" b='say "hi"'>a > b</doc>"#)
+            .unwrap();
+        let serialize =
+            |xot: &Xot, parameters: Parameters| xot.serialize_xml_string(parameters, doc).unwrap();
+
+        assert_eq!(
+            serialize(&xot, Parameters::default()),
+            r#"<doc xmlns="urn:it&apos;s" a="it&apos;s" b="say &quot;hi&quot;">a &gt; b</doc>"#
+        );
+        assert_eq!(
+            serialize(
+                &xot,
+                Parameters {
+                    unescaped_quote: true,
+                    unescaped_gt: true,
+                    ..Default::default()
+                }
+            ),
+            r#"<doc xmlns="urn:it's" a="it's" b="say &quot;hi&quot;">a > b</doc>"#
+        );
+        assert_eq!(
+            serialize(
+                &xot,
+                Parameters {
+                    attribute_quote: Quote::Single,
+                    unescaped_quote: true,
+                    ..Default::default()
+                }
+            ),
+            r#"<doc xmlns='urn:it&apos;s' a='it&apos;s' b='say "hi"'>a &gt; b</doc>"#
+        );
+    }
+
+    #[test]
+    fn test_xml_output_character_references() {
+        let mut xot = Xot::new();
+        let doc = xot.parse("<doc a=\"\u{7F}\">\u{85}</doc>").unwrap();
+        let serialize = |xot: &Xot, character_references| {
+            xot.serialize_xml_string(
+                Parameters {
+                    declaration: Some(Declaration {
+                        version: Version::V1_1,
+                        ..Default::default()
+                    }),
+                    character_references,
+                    ..Default::default()
+                },
+                doc,
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            serialize(&xot, CharacterReferences::Hexadecimal),
+            "<?xml version=\"1.1\"?>\n<doc a=\"&#x7F;\">&#x85;</doc>"
+        );
+        assert_eq!(
+            serialize(&xot, CharacterReferences::Decimal),
+            "<?xml version=\"1.1\"?>\n<doc a=\"&#127;\">&#133;</doc>"
+        );
+    }
+
+    #[test]
+    fn test_xml_output_empty_element_form() {
+        let mut xot = Xot::new();
+        let doc = xot
+            .parse(r#"<doc xmlns:x="urn:x"><x:a b="c"/><br/><p>text</p></doc>"#)
+            .unwrap();
+        let br = xot.add_name("br");
+        let output_xml = xot
+            .serialize_xml_string(
+                Parameters {
+                    indentation: Some(Indentation::default()),
+                    empty_element_form: EmptyElementForm::Expanded,
+                    ..Default::default()
+                },
+                doc,
+            )
+            .unwrap();
+        assert_eq!(
+            output_xml,
+            "<doc xmlns:x=\"urn:x\">\n  <x:a b=\"c\"></x:a>\n  <br></br>\n  <p>text</p>\n</doc>\n"
+        );
+        let output_xml = xot
+            .serialize_xml_string(
+                Parameters {
+                    empty_element_form_exceptions: vec![br],
+                    ..Default::default()
+                },
+                doc,
+            )
+            .unwrap();
+        assert_eq!(
+            output_xml,
+            r#"<doc xmlns:x="urn:x"><x:a b="c"/><br></br><p>text</p></doc>"#
+        );
+    }
+
     #[test]
     fn test_xml_output_declaration() {
         let m = Parameters {
@@ -251,6 +1083,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_xml_output_declaration_1_1() {
+        let m = Parameters {
+            declaration: Some(Declaration {
+                version: Version::V1_1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut xot = Xot::new();
+        let doc = xot.parse("<doc/>").unwrap();
+        let doc_el = xot.document_element(doc).unwrap();
+        let a = xot.add_name("a");
+        xot.set_attribute(doc_el, a, "\u{1}\u{2028}");
+        xot.append_text(doc_el, "\u{7F}\u{85}\té").unwrap();
+
+        assert_eq!(
+            xot.serialize_xml_string(m, doc).unwrap(),
+            "<?xml version=\"1.1\"?>\n<doc a=\"&#x1;&#x2028;\">&#x7F;&#x85;\té</doc>"
+        );
+    }
+
     #[test]
     fn test_xml_output_declaration_standalone() {
         let m = Parameters {
@@ -325,6 +1179,252 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_xml_output_doctype_document() {
+        let m = Parameters {
+            doctype: Some(DocType::Document),
+            ..Default::default()
+        };
+        let mut xot = Xot::new();
+        let doc = xot
+            .parse(r#"<!DOCTYPE doc SYSTEM "doc.dtd"><doc/>"#)
+            .unwrap();
+        assert_eq!(
+            xot.serialize_xml_string(m.clone(), doc).unwrap(),
+            r#"<!DOCTYPE doc SYSTEM "doc.dtd">
+<doc/>"#
+        );
+        // only a document node has a doctype
+        let doc_el = xot.document_element(doc).unwrap();
+        assert_eq!(
+            xot.serialize_xml_string(m.clone(), doc_el).unwrap(),
+            "<doc/>"
+        );
+
+        let xml = r#"<!DOCTYPE doc PUBLIC "-//Example//DTD Doc//EN" 'say "doc".dtd' [
+  <!ENTITY company "ACME &#38; &#x53;ons &more; &#38;#60; 100&#37;">
+  <!ENTITY % param "<!ELEMENT x ANY>">
+  <!ENTITY logo SYSTEM "logo.gif" NDATA gif>
+  <!NOTATION gif PUBLIC "GIF">
+  <!ATTLIST doc a CDATA "&lt;&amp;&quot;" b (x|y) #FIXED "x" c ID #REQUIRED>
+]>
+<doc c="c"/>"#;
+        let doc = xot.parse(xml).unwrap();
+        let serialized = xot.serialize_xml_string(m, doc).unwrap();
+        assert_eq!(
+            serialized,
+            r#"<!DOCTYPE doc PUBLIC "-//Example//DTD Doc//EN" 'say "doc".dtd' [
+  <!ENTITY company "ACME &#x26; Sons &more; &#x26;#60; 100&#x25;">
+  <!ENTITY % param "<!ELEMENT x ANY>">
+  <!ENTITY logo SYSTEM "logo.gif" NDATA gif>
+  <!NOTATION gif PUBLIC "GIF">
+  <!ATTLIST doc a CDATA "&lt;&amp;&quot;">
+  <!ATTLIST doc b (x|y) #FIXED "x">
+  <!ATTLIST doc c ID #REQUIRED>
+]>
+<doc c="c"/>"#
+        );
+        // the doctype survives a round trip
+        let doctype = xot.doctype(doc).unwrap().clone();
+        let reparsed = xot.parse(&serialized).unwrap();
+        assert_eq!(xot.doctype(reparsed), Some(&doctype));
+    }
+
+    #[test]
+    fn test_xml_output_newline() {
+        let mut xot = Xot::new();
+        let doc = xot
+            .parse("<!DOCTYPE doc [<!ENTITY e \"x\">]><doc a=\"1\" b=\"2\"><p>a\nb</p></doc>")
+            .unwrap();
+        let m = Parameters {
+            declaration: Some(Declaration::default()),
+            doctype: Some(DocType::Document),
+            indentation: Some(Indentation {
+                options: PrettyOptions {
+                    attribute_wrap_width: Some(10),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            newline: Newline::CrLf,
+            ..Default::default()
+        };
+        // line ends in text stay as they are
+        assert_eq!(
+            xot.serialize_xml_string(m, doc).unwrap(),
+            "<?xml version=\"1.0\"?>\r\n<!DOCTYPE doc [\r\n  <!ENTITY e \"x\">\r\n]>\r\n<doc a=\"1\"\r\n     b=\"2\">\r\n  <p>a\nb</p>\r\n</doc>\r\n"
+        );
+    }
+
+    #[test]
+    fn test_xml_output_serializer() {
+        let mut xot = Xot::new();
+        let doc = xot.parse(r#"<doc b="B" a="A"><p/></doc>"#).unwrap();
+
+        assert_eq!(
+            doc.serialize(&xot)
+                .declaration(true)
+                .declaration(false)
+                .empty_element_form(EmptyElementForm::Expanded)
+                .attribute_quote(Quote::Single)
+                .sort_attributes()
+                .to_string()
+                .unwrap(),
+            "<doc a='A' b='B'><p></p></doc>"
+        );
+
+        let parameters = Parameters {
+            declaration: Some(Declaration::default()),
+            indentation: Some(Default::default()),
+            newline: Newline::CrLf,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        doc.serialize(&xot)
+            .sort_attributes()
+            .parameters(parameters.clone())
+            .write(&mut buf)
+            .unwrap();
+        assert_eq!(
+            buf,
+            xot.serialize_xml_string(parameters, doc)
+                .unwrap()
+                .as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_xml_output_preferred_prefixes() {
+        let mut xot = Xot::new();
+        let ns = xot.add_namespace("http://example.com");
+        let x = xot.add_namespace("urn:x");
+        let a = xot.add_name_ns("a", ns);
+        let b = xot.add_name_ns("b", x);
+        let c = xot.add_name_ns("c", ns);
+        let d = xot.add_name("d");
+        let a_id = xot.new_element(a);
+        xot.attributes_mut(a_id).insert(b, "B".to_string());
+        let c_id = xot.new_element(c);
+        xot.append(a_id, c_id).unwrap();
+        let d_id = xot.new_element(d);
+        xot.append(c_id, d_id).unwrap();
+        let e_id = xot.new_element(c);
+        xot.append(d_id, e_id).unwrap();
+
+        assert!(matches!(
+            xot.serialize_xml_string(Parameters::default(), a_id),
+            Err(Error::MissingPrefix(_))
+        ));
+        let m = Parameters {
+            preferred_prefixes: HashMap::from([(ns, "".to_string()), (x, "ex".to_string())]),
+            ..Default::default()
+        };
+        assert_eq!(
+            xot.serialize_xml_string(m, a_id).unwrap(),
+            r#"<a xmlns="http://example.com" xmlns:ex="urn:x" ex:b="B"><c><d xmlns=""><c xmlns="http://example.com"/></d></c></a>"#
+        );
+        // a prefix declared in the tree isn't overridden
+        let root = xot.parse(r#"<ex:doc xmlns:ex="urn:other"/>"#).unwrap();
+        let doc_id = xot.document_element(root).unwrap();
+        let b_id = xot.new_element(b);
+        xot.append(doc_id, b_id).unwrap();
+        let m = Parameters {
+            preferred_prefixes: HashMap::from([(x, "ex".to_string())]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            xot.serialize_xml_string(m, root),
+            Err(Error::MissingPrefix(_))
+        ));
+    }
+
+    #[test]
+    fn test_xml_output_hoist_namespaces() {
+        let mut xot = Xot::new();
+        let m = Parameters {
+            hoist_namespaces: true,
+            ..Default::default()
+        };
+        // the prefix c is declared for two namespaces, so it's not moved
+        let root = xot
+            .parse(r#"<doc xmlns:a="urn:a"><p xmlns="urn:d" xmlns:a="urn:a" xmlns:b="urn:b"><c:q xmlns:c="urn:c1"/><c:q xmlns:c="urn:c2"/></p></doc>"#)
+            .unwrap();
+        assert_eq!(
+            xot.serialize_xml_string(m.clone(), root).unwrap(),
+            r#"<doc xmlns:a="urn:a" xmlns:b="urn:b"><p xmlns="urn:d"><c:q xmlns:c="urn:c1"/><c:q xmlns:c="urn:c2"/></p></doc>"#
+        );
+        // the declarations go before the attributes
+        let root = xot
+            .parse(r#"<doc a="A" b="B"><p xmlns:x="urn:x"/></doc>"#)
+            .unwrap();
+        assert_eq!(
+            xot.serialize_xml_string(m.clone(), root).unwrap(),
+            r#"<doc xmlns:x="urn:x" a="A" b="B"><p/></doc>"#
+        );
+        // without elements outside of a namespace, the default namespace is
+        // moved too
+        let root = xot
+            .parse(r#"<a:doc xmlns:a="urn:a"><p xmlns="urn:d"/><p xmlns="urn:d"/></a:doc>"#)
+            .unwrap();
+        assert_eq!(
+            xot.serialize_xml_string(m.clone(), root).unwrap(),
+            r#"<a:doc xmlns:a="urn:a" xmlns="urn:d"><p/><p/></a:doc>"#
+        );
+        // an element gets the declarations of its descendants
+        let p = xot
+            .first_child(xot.document_element(root).unwrap())
+            .unwrap();
+        let a = xot.add_name_ns("a", xot.namespace("urn:a").unwrap());
+        let q = xot.new_element(a);
+        xot.append(p, q).unwrap();
+        let x = xot.add_namespace("urn:x");
+        let x_prefix = xot.add_prefix("x");
+        xot.namespaces_mut(q).insert(x_prefix, x);
+        assert_eq!(
+            xot.serialize_xml_string(m, p).unwrap(),
+            r#"<p xmlns:a="urn:a" xmlns="urn:d" xmlns:x="urn:x"><a:a/></p>"#
+        );
+    }
+
+    #[test]
+    fn test_xml_output_unrepresentable_characters() {
+        let mut xot = Xot::new();
+        let doc = xot.parse("<doc/>").unwrap();
+        let doc_el = xot.document_element(doc).unwrap();
+        let a = xot.add_name("a");
+        xot.set_attribute(doc_el, a, "\u{1}");
+        let comment = xot.new_comment("\u{1}\u{85}");
+        xot.append(doc_el, comment).unwrap();
+
+        assert!(matches!(
+            xot.serialize_xml_string(Parameters::default(), doc),
+            Err(Error::UnrepresentableCharacter('\u{1}'))
+        ));
+        let replace = Parameters {
+            unrepresentable_characters: UnrepresentableCharacters::Replace(Arc::new(|c| {
+                format!("[{}]", c as u32)
+            })),
+            ..Default::default()
+        };
+        assert_eq!(
+            xot.serialize_xml_string(replace.clone(), doc).unwrap(),
+            "<doc a=\"[1]\"><!--[1]\u{85}--></doc>"
+        );
+        // in XML 1.1, a control character can only be written as a
+        // character reference
+        let replace_1_1 = Parameters {
+            declaration: Some(Declaration {
+                version: Version::V1_1,
+                ..Default::default()
+            }),
+            ..replace
+        };
+        assert_eq!(
+            xot.serialize_xml_string(replace_1_1, doc).unwrap(),
+            "<?xml version=\"1.1\"?>\n<doc a=\"&#x1;\"><!--[1][133]--></doc>"
+        );
+    }
+
     #[test]
     fn test_cdata_sections_elements() {
         let mut xot = Xot::new();
@@ -357,6 +1457,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_cdata_sections_elements_xml_1_1() {
+        let mut xot = Xot::new();
+        let p = xot.add_name("p");
+        let m = Parameters {
+            cdata_section_elements: vec![p],
+            declaration: Some(Declaration {
+                version: Version::V1_1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let doc = xot.parse("<doc><p>a]]\u{7F}>b</p></doc>").unwrap();
+
+        assert_eq!(
+            xot.serialize_xml_string(m, doc).unwrap(),
+            "<?xml version=\"1.1\"?>\n<doc><p><![CDATA[a]]]]>&#x7F;<![CDATA[>b]]></p></doc>"
+        );
+    }
+
     #[test]
     fn test_cdata_sections_elements_with_end_characters() {
         let mut xot = Xot::new();
@@ -1,19 +1,39 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::io;
 
-use crate::entity::{serialize_attribute, serialize_cdata, serialize_text};
+use crate::entity::{
+    escape_restricted_1_1, escape_restricted_cdata_1_1, is_char, is_restricted_1_1,
+    serialize_attribute_quoted, serialize_cdata, serialize_text,
+};
 use crate::error::Error;
-use crate::id::NameId;
+use crate::id::{NameId, NamespaceId};
+use crate::output::xml::{EmptyElementForm, Newline, UnrepresentableCharacters};
 use crate::output::Normalizer;
+use crate::xmlvalue::Value;
 use crate::xotdata::{Node, Xot};
 
-use super::fullname::FullnameSerializer;
-use super::{Output, OutputToken, Pretty, TokenSerializeParameters};
+use super::fullname::{FullnameSerializer, NamespaceDeclarations};
+use super::{
+    AttributeWrapper, Indentation, Output, OutputToken, Pretty, PrettyOptions,
+    TokenSerializeParameters,
+};
 
 pub(crate) struct XmlSerializer<'a, N: Normalizer> {
     xot: &'a Xot,
     fullname_serializer: FullnameSerializer<'a>,
     normalizer: N,
     parameters: TokenSerializeParameters,
+    // the preferred prefixes we declared, with the element we declared
+    // them on
+    declared_prefixes: Vec<(Node, String, NamespaceId)>,
+    top: Node,
+    // the element we hoist namespace declarations to, and the declarations
+    hoisted: Option<(Node, NamespaceDeclarations)>,
+    // the hoisted declarations we still have to write
+    hoisted_declarations: Vec<String>,
+    // the namespaces declared where the output goes
+    context: NamespaceDeclarations,
 }
 
 impl<'a, N: Normalizer> XmlSerializer<'a, N> {
@@ -25,14 +45,39 @@ impl<'a, N: Normalizer> XmlSerializer<'a, N> {
     ) -> Self {
         let extra_declarations = xot.namespaces_in_scope(node).collect();
         let fullname_serializer = FullnameSerializer::new(xot, extra_declarations);
+        let hoisted = if parameters.hoist_namespaces {
+            hoisted_declarations(xot, node)
+        } else {
+            None
+        };
         Self {
             xot,
             fullname_serializer,
             normalizer,
             parameters,
+            declared_prefixes: Vec::new(),
+            top: node,
+            hoisted,
+            hoisted_declarations: Vec::new(),
+            context: Vec::new(),
         }
     }
 
+    // Serialize for a place where these namespaces are already declared.
+    pub(crate) fn with_context(mut self, context: NamespaceDeclarations) -> Self {
+        // the declarations in the tree override those of the context
+        let in_scope: NamespaceDeclarations = self.xot.namespaces_in_scope(self.top).collect();
+        let declarations = context
+            .iter()
+            .filter(|(prefix_id, _)| !in_scope.iter().any(|(p, _)| p == prefix_id))
+            .chain(in_scope.iter())
+            .copied()
+            .collect();
+        self.fullname_serializer = FullnameSerializer::new(self.xot, declarations);
+        self.context = context;
+        self
+    }
+
     pub(crate) fn serialize<W: io::Write>(
         &mut self,
         w: &mut W,
@@ -48,76 +93,118 @@ impl<'a, N: Normalizer> XmlSerializer<'a, N> {
         &mut self,
         w: &mut W,
         outputs: impl Iterator<Item = (Node, Output<'a>)>,
-        suppress: &[NameId],
+        indentation: &Indentation,
+        newline: Newline,
     ) -> Result<(), Error> {
-        let is_suppressed = |name_id| suppress.contains(&name_id);
-        let mut pretty = Pretty::new(self.xot, is_suppressed, |_| false);
+        let mut pretty = pretty_writer(self.xot, indentation, newline);
         for (node, output) in outputs {
-            let (indentation, newline) = pretty.prettify(node, &output);
-            if indentation > 0 {
-                w.write_all(" ".repeat(indentation * 2).as_bytes())?;
-            }
-            self.serialize_node(w, node, output)?;
-            if newline {
-                w.write_all(b"\n")?;
-            }
+            pretty.write(self, w, node, output)?;
         }
-        Ok(())
+        pretty.finish(w)
     }
 
     pub(crate) fn serialize_node<W: io::Write>(
         &mut self,
         w: &mut W,
         node: Node,
-        output: Output<'a>,
+        output: Output,
     ) -> Result<(), Error> {
         let data = self.render_output(node, &output)?;
         if data.space {
-            w.write_all(b" ").unwrap();
+            w.write_all(b" ")?;
         }
-        w.write_all(data.text.as_bytes()).unwrap();
+        w.write_all(data.text.as_bytes())?;
         Ok(())
     }
 
     pub(crate) fn render_output(
         &mut self,
         node: Node,
-        output: &Output<'a>,
+        output: &Output,
     ) -> Result<OutputToken, Error> {
         use Output::*;
         let r = match output {
             StartTagOpen(element) => {
-                self.fullname_serializer
-                    .push(self.xot.namespace_declarations(node));
+                let mut namespace_declarations = self.xot.namespace_declarations(node);
+                if let Some((_, hoisted)) = self.hoisted.take_if(|(target, _)| *target == node) {
+                    // we write these after the declarations of the element
+                    self.hoisted_declarations = hoisted
+                        .iter()
+                        .map(|(prefix_id, namespace_id)| {
+                            self.namespace_declaration(
+                                self.xot.prefix_str(*prefix_id),
+                                *namespace_id,
+                            )
+                        })
+                        .collect::<Result<_, _>>()?;
+                    namespace_declarations.extend(hoisted);
+                }
+                let mut declarations = String::new();
+                // the default namespace of the context doesn't apply to an
+                // element without a namespace
+                if node == self.top
+                    && self.xot.namespace_for_name(element.name_id) == self.xot.no_namespace()
+                    && self.context.iter().any(|(prefix_id, namespace_id)| {
+                        *prefix_id == self.xot.empty_prefix()
+                            && *namespace_id != self.xot.no_namespace()
+                    })
+                    && !self
+                        .xot
+                        .namespaces_in_scope(node)
+                        .any(|(prefix_id, _)| prefix_id == self.xot.empty_prefix())
+                {
+                    declarations = format!(
+                        " {}",
+                        self.namespace_declaration("", self.xot.no_namespace())?
+                    );
+                    namespace_declarations.push((self.xot.empty_prefix(), self.xot.no_namespace()));
+                }
+                self.fullname_serializer.push(namespace_declarations);
+                let namespace_id = self.xot.namespace_for_name(element.name_id);
+                if namespace_id == self.xot.no_namespace() {
+                    // a default namespace we declared doesn't apply here
+                    if self
+                        .declared_namespace("")
+                        .is_some_and(|namespace_id| namespace_id != self.xot.no_namespace())
+                    {
+                        declarations = format!(" {}", self.declare_prefix(node, "", namespace_id)?);
+                    }
+                } else if self.element_fullname(element.name_id).is_err() {
+                    let prefix = self.parameters.preferred_prefixes.get(&namespace_id);
+                    if let Some(prefix) = prefix.cloned() {
+                        if !self.is_prefix_declared_in_tree(&prefix) {
+                            let declaration = self.declare_prefix(node, &prefix, namespace_id)?;
+                            declarations = format!(" {}", declaration);
+                        }
+                    }
+                }
                 OutputToken {
                     space: false,
                     text: format!(
-                        "<{}",
-                        self.fullname_serializer.element_fullname(element.name_id)?
+                        "<{}{}",
+                        self.element_fullname(element.name_id)?,
+                        declarations
                     ),
                 }
             }
             StartTagClose => {
-                if self.xot.first_child(node).is_none() {
-                    OutputToken {
-                        space: false,
-                        text: "/>".to_string(),
-                    }
+                let mut text = self
+                    .hoisted_declarations
+                    .drain(..)
+                    .map(|declaration| format!(" {}", declaration))
+                    .collect::<String>();
+                text.push_str(if self.is_self_closing(node) {
+                    "/>"
                 } else {
-                    OutputToken {
-                        space: false,
-                        text: ">".to_string(),
-                    }
-                }
+                    ">"
+                });
+                OutputToken { space: false, text }
             }
             EndTag(element) => {
-                let r = if self.xot.first_child(node).is_some() {
+                let r = if !self.is_self_closing(node) {
                     OutputToken {
                         space: false,
-                        text: format!(
-                            "</{}>",
-                            self.fullname_serializer.element_fullname(element.name_id)?
-                        ),
+                        text: format!("</{}>", self.element_fullname(element.name_id)?),
                     }
                 } else {
                     OutputToken {
@@ -127,6 +214,13 @@ impl<'a, N: Normalizer> XmlSerializer<'a, N> {
                 };
                 self.fullname_serializer
                     .pop(self.xot.has_namespace_declarations(node));
+                while self
+                    .declared_prefixes
+                    .last()
+                    .is_some_and(|(declared_node, _, _)| *declared_node == node)
+                {
+                    self.declared_prefixes.pop();
+                }
                 r
             }
             Prefix(prefix_id, namespace_id) => {
@@ -137,28 +231,56 @@ impl<'a, N: Normalizer> XmlSerializer<'a, N> {
                         text: "".to_string(),
                     });
                 }
-                let namespace = self.xot.namespace_str(*namespace_id);
-                if *prefix_id == self.xot.empty_prefix_id {
-                    OutputToken {
-                        space: true,
-                        text: format!("xmlns=\"{}\"", namespace),
-                    }
-                } else {
-                    let prefix = self.xot.prefix_str(*prefix_id);
-                    OutputToken {
-                        space: true,
-                        text: format!("xmlns:{}=\"{}\"", prefix, namespace),
-                    }
+                // leave out a declaration that's already in scope
+                if (node == self.top && self.context.contains(&(*prefix_id, *namespace_id)))
+                    || self.parameters.hoist_namespaces
+                        && node != self.top
+                        && self
+                            .fullname_serializer
+                            .is_declared_outside(*prefix_id, *namespace_id)
+                {
+                    return Ok(OutputToken {
+                        space: false,
+                        text: "".to_string(),
+                    });
+                }
+                OutputToken {
+                    space: true,
+                    text: self
+                        .namespace_declaration(self.xot.prefix_str(*prefix_id), *namespace_id)?,
                 }
             }
             Attribute(name_id, value) => {
-                let fullname = self.fullname_serializer.attribute_fullname(*name_id)?;
+                let mut declarations = String::new();
+                if self.attribute_fullname(*name_id).is_err() {
+                    let namespace_id = self.xot.namespace_for_name(*name_id);
+                    let prefix = self.parameters.preferred_prefixes.get(&namespace_id);
+                    if let Some(prefix) = prefix.cloned() {
+                        // we can't change what a prefix means once the
+                        // element name is written
+                        if !prefix.is_empty()
+                            && !self.is_prefix_declared_in_tree(&prefix)
+                            && self.declared_namespace(&prefix).is_none()
+                        {
+                            let declaration = self.declare_prefix(node, &prefix, namespace_id)?;
+                            declarations = format!("{} ", declaration);
+                        }
+                    }
+                }
+                for declaration in self.hoisted_declarations.drain(..).rev() {
+                    declarations.insert_str(0, &format!("{} ", declaration));
+                }
+                let fullname = self.attribute_fullname(*name_id)?;
+                let quote = self.parameters.attribute_quote.as_char();
                 OutputToken {
                     space: true,
                     text: format!(
-                        "{}=\"{}\"",
+                        "{}{}={}{}{}",
+                        declarations,
                         fullname,
-                        serialize_attribute((*value).into(), &self.normalizer)
+                        quote,
+                        self.attribute_value(&self.representable(value, Content::Escaped)?),
+                        quote
                     ),
                 }
             }
@@ -177,30 +299,41 @@ impl<'a, N: Normalizer> XmlSerializer<'a, N> {
                 if is_cdata_element {
                     OutputToken {
                         space: false,
-                        text: serialize_cdata((*text).into(), &self.normalizer).to_string(),
+                        text: self.cdata(text)?,
                     }
                 } else {
                     OutputToken {
                         space: false,
-                        text: serialize_text(
-                            (*text).into(),
-                            &self.normalizer,
-                            self.parameters.unescaped_gt,
-                        )
-                        .to_string(),
+                        text: self
+                            .escape_restricted(serialize_text(
+                                self.representable(text, Content::Escaped)?,
+                                &self.normalizer,
+                                self.parameters.unescaped_gt,
+                            ))
+                            .to_string(),
                     }
                 }
             }
+            Cdata(text) => OutputToken {
+                space: false,
+                text: self.cdata(text)?,
+            },
+            EntityReference(name) => OutputToken {
+                space: false,
+                text: format!("&{};", self.encodable_name((*name).into())?),
+            },
             Comment(text) => OutputToken {
                 space: false,
-                text: format!("<!--{}-->", text),
+                text: format!("<!--{}-->", self.representable(text, Content::Markup)?),
             },
             ProcessingInstruction(target, data) => {
                 let (target, ns) = self.xot.name_ns_str(*target);
                 if !ns.is_empty() {
                     return Err(Error::NamespaceInProcessingInstruction);
                 }
+                let target = self.encodable_name(target.into())?;
                 if let Some(data) = data {
+                    let data = self.representable(data, Content::Markup)?;
                     OutputToken {
                         space: false,
                         text: format!("<?{} {}?>", target, data),
@@ -215,4 +348,313 @@ impl<'a, N: Normalizer> XmlSerializer<'a, N> {
         };
         Ok(r)
     }
+
+    // The full name of an element, with a prefix from the tree or one of
+    // the preferred prefixes we declared.
+    fn element_fullname(&self, name_id: NameId) -> Result<Cow<'a, str>, Error> {
+        self.fullname_serializer
+            .element_fullname(name_id)
+            .or_else(|error| {
+                let prefix = self.declared_prefix(name_id).ok_or(error)?;
+                Ok(self.prefixed_name(prefix, name_id))
+            })
+            .and_then(|name| self.encodable_name(name))
+    }
+
+    fn attribute_fullname(&self, name_id: NameId) -> Result<Cow<'a, str>, Error> {
+        self.fullname_serializer
+            .attribute_fullname(name_id)
+            .or_else(|error| {
+                let prefix = self
+                    .declared_prefix(name_id)
+                    .filter(|prefix| !prefix.is_empty())
+                    .ok_or(error)?;
+                Ok(self.prefixed_name(prefix, name_id))
+            })
+            .and_then(|name| self.encodable_name(name))
+    }
+
+    fn prefixed_name(&self, prefix: &str, name_id: NameId) -> Cow<'a, str> {
+        let local_name = self.xot.local_name_str(name_id);
+        if prefix.is_empty() {
+            Cow::Borrowed(local_name)
+        } else {
+            Cow::Owned(format!("{}:{}", prefix, local_name))
+        }
+    }
+
+    // The preferred prefix of the namespace of a name, if we declared it
+    // and it's still in effect.
+    fn declared_prefix(&self, name_id: NameId) -> Option<&str> {
+        let namespace_id = self.xot.namespace_for_name(name_id);
+        let prefix = self.parameters.preferred_prefixes.get(&namespace_id)?;
+        (self.declared_namespace(prefix) == Some(namespace_id)).then_some(prefix.as_str())
+    }
+
+    // The namespace we declared for a prefix, unless a declaration in the
+    // tree overrides it.
+    fn declared_namespace(&self, prefix: &str) -> Option<NamespaceId> {
+        if self.is_prefix_declared_in_tree(prefix) {
+            return None;
+        }
+        self.declared_prefixes
+            .iter()
+            .rev()
+            .find(|(_, declared_prefix, _)| declared_prefix == prefix)
+            .map(|(_, _, namespace_id)| *namespace_id)
+    }
+
+    fn is_prefix_declared_in_tree(&self, prefix: &str) -> bool {
+        self.xot
+            .prefix(prefix)
+            .is_some_and(|prefix_id| self.fullname_serializer.is_prefix_declared(prefix_id))
+    }
+
+    // Declare a prefix on an element, and return the namespace declaration
+    // to write.
+    fn declare_prefix(
+        &mut self,
+        node: Node,
+        prefix: &str,
+        namespace_id: NamespaceId,
+    ) -> Result<String, Error> {
+        self.declared_prefixes
+            .push((node, prefix.to_string(), namespace_id));
+        self.namespace_declaration(prefix, namespace_id)
+    }
+
+    // A namespace declaration; the empty prefix declares the default
+    // namespace.
+    fn namespace_declaration(
+        &self,
+        prefix: &str,
+        namespace_id: NamespaceId,
+    ) -> Result<String, Error> {
+        let namespace = self.attribute_value(self.xot.namespace_str(namespace_id));
+        let quote = self.parameters.attribute_quote.as_char();
+        if prefix.is_empty() {
+            Ok(format!("xmlns={}{}{}", quote, namespace, quote))
+        } else {
+            let prefix = self.encodable_name(prefix.into())?;
+            Ok(format!("xmlns:{}={}{}{}", prefix, quote, namespace, quote))
+        }
+    }
+
+    fn is_self_closing(&self, node: Node) -> bool {
+        if self.xot.first_child(node).is_some() {
+            return false;
+        }
+        let is_exception = self.xot.element(node).is_some_and(|element| {
+            self.parameters
+                .empty_element_form_exceptions
+                .contains(&element.name())
+        });
+        (self.parameters.empty_element_form == EmptyElementForm::SelfClosing) != is_exception
+    }
+
+    fn attribute_value<'b>(&self, value: &'b str) -> Cow<'b, str> {
+        self.escape_restricted(serialize_attribute_quoted(
+            value.into(),
+            &self.normalizer,
+            self.parameters.attribute_quote,
+            self.parameters.unescaped_quote,
+        ))
+    }
+
+    fn cdata(&self, text: &str) -> Result<String, Error> {
+        let cdata = serialize_cdata(self.representable(text, Content::Cdata)?, &self.normalizer);
+        if self.parameters.xml_1_1 {
+            Ok(
+                escape_restricted_cdata_1_1(cdata, self.parameters.character_references)
+                    .to_string(),
+            )
+        } else {
+            Ok(cdata.to_string())
+        }
+    }
+
+    // Replace the characters in `content` that can't be written, or fail
+    // if we're not asked to replace them.
+    fn representable<'b>(&self, content: &'b str, kind: Content) -> Result<Cow<'b, str>, Error> {
+        if content.chars().all(|c| self.is_representable(c, kind)) {
+            return Ok(Cow::Borrowed(content));
+        }
+        let mut result = String::with_capacity(content.len());
+        for c in content.chars() {
+            if self.is_representable(c, kind) {
+                result.push(c);
+                continue;
+            }
+            match &self.parameters.unrepresentable_characters {
+                UnrepresentableCharacters::Error => {
+                    return Err(Error::UnrepresentableCharacter(c));
+                }
+                UnrepresentableCharacters::Replace(replacer) => {
+                    result.push_str(&replacer.replace(c));
+                }
+            }
+        }
+        Ok(result.into())
+    }
+
+    fn is_representable(&self, c: char, kind: Content) -> bool {
+        if !is_char(c, self.parameters.xml_1_1) {
+            return false;
+        }
+        match kind {
+            Content::Escaped => true,
+            // we end a CDATA section around a character reference for a
+            // restricted character, but not for one the encoding doesn't
+            // have
+            Content::Cdata => self.is_encodable(c),
+            Content::Markup => {
+                !(self.parameters.xml_1_1 && is_restricted_1_1(c)) && self.is_encodable(c)
+            }
+        }
+    }
+
+    fn is_encodable(&self, c: char) -> bool {
+        if let Some(max_character) = self.parameters.max_character {
+            return c <= max_character;
+        }
+        match self.parameters.encoding {
+            Some(encoding) if !c.is_ascii() => {
+                let mut buf = [0; 4];
+                let (_, _, had_errors) = encoding.encode(c.encode_utf8(&mut buf));
+                !had_errors
+            }
+            _ => true,
+        }
+    }
+
+    // A name can't contain a character reference, so a character in it
+    // that the encoding doesn't have is an error, even if we're asked to
+    // replace unrepresentable characters.
+    fn encodable_name<'b>(&self, name: Cow<'b, str>) -> Result<Cow<'b, str>, Error> {
+        match name.chars().find(|c| !self.is_encodable(*c)) {
+            Some(c) => Err(Error::UnrepresentableCharacter(c)),
+            None => Ok(name),
+        }
+    }
+
+    fn escape_restricted<'b>(&self, content: Cow<'b, str>) -> Cow<'b, str> {
+        if self.parameters.xml_1_1 {
+            escape_restricted_1_1(content, self.parameters.character_references)
+        } else {
+            content
+        }
+    }
+}
+
+// Where content is written, which decides which characters it can have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Content {
+    // text and attribute values, where characters can be escaped
+    Escaped,
+    // CDATA sections
+    Cdata,
+    // comments and processing instructions
+    Markup,
+}
+
+// The element to move the namespace declarations in the tree of `node` to,
+// and the declarations to move. We leave out a prefix that's declared for
+// different namespaces, and the default namespace if an element isn't in a
+// namespace, as moving these would change the names of elements.
+fn hoisted_declarations(xot: &Xot, node: Node) -> Option<(Node, NamespaceDeclarations)> {
+    let target = match xot.value(node) {
+        Value::Document => xot.document_element(node).ok()?,
+        Value::Element(_) => node,
+        _ => return None,
+    };
+    let in_scope: NamespaceDeclarations = xot.namespaces_in_scope(target).collect();
+    let mut declarations = NamespaceDeclarations::new();
+    let mut conflicting = HashSet::new();
+    let mut has_no_namespace_element = false;
+    for descendant in xot.descendants(target) {
+        let Some(element) = xot.element(descendant) else {
+            continue;
+        };
+        if xot.namespace_for_name(element.name()) == xot.no_namespace() {
+            has_no_namespace_element = true;
+        }
+        if descendant == target {
+            continue;
+        }
+        for (prefix_id, namespace_id) in xot.namespaces(descendant).iter() {
+            let declared = in_scope
+                .iter()
+                .chain(declarations.iter())
+                .find(|(declared_prefix_id, _)| *declared_prefix_id == prefix_id);
+            match declared {
+                Some((_, declared_namespace_id)) if declared_namespace_id == namespace_id => {}
+                Some(_) => {
+                    conflicting.insert(prefix_id);
+                }
+                None => declarations.push((prefix_id, *namespace_id)),
+            }
+        }
+    }
+    declarations.retain(|(prefix_id, namespace_id)| {
+        !conflicting.contains(prefix_id)
+            && *namespace_id != xot.no_namespace()
+            && (*prefix_id != xot.empty_prefix() || !has_no_namespace_element)
+    });
+    Some((target, declarations))
+}
+
+// Pretty-prints outputs one at a time.
+pub(crate) struct PrettyWriter<'a, IsSuppressed: Fn(NameId) -> bool> {
+    pretty: Pretty<'a, IsSuppressed, fn(NameId) -> bool>,
+    options: &'a PrettyOptions,
+    wrapper: AttributeWrapper,
+    line_end: Newline,
+    // we write a line end once we know it's not the last one
+    newline: bool,
+}
+
+impl<'a, IsSuppressed: Fn(NameId) -> bool> PrettyWriter<'a, IsSuppressed> {
+    pub(crate) fn write<W: io::Write, N: Normalizer>(
+        &mut self,
+        serializer: &mut XmlSerializer<'_, N>,
+        w: &mut W,
+        node: Node,
+        output: Output,
+    ) -> Result<(), Error> {
+        let (level, next_newline) = self.pretty.prettify(node, &output);
+        if self.newline {
+            w.write_all(self.line_end.as_bytes())?;
+        }
+        let indent = self.options.indent.repeat(level);
+        w.write_all(indent.as_bytes())?;
+        let token = serializer.render_output(node, &output)?;
+        self.wrapper.write(w, indent, &output, token)?;
+        self.newline = next_newline;
+        Ok(())
+    }
+
+    pub(crate) fn finish<W: io::Write>(&mut self, w: &mut W) -> Result<(), Error> {
+        if self.newline && self.options.trailing_newline {
+            w.write_all(self.line_end.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn pretty_writer<'a>(
+    xot: &'a Xot,
+    indentation: &'a Indentation,
+    line_end: Newline,
+) -> PrettyWriter<'a, impl Fn(NameId) -> bool + 'a> {
+    let is_suppressed = |name_id| indentation.suppress.contains(&name_id);
+    let is_inline: fn(NameId) -> bool = |_| false;
+    let options = &indentation.options;
+    PrettyWriter {
+        pretty: Pretty::new(xot, is_suppressed, is_inline, options.max_depth)
+            .with_decision(indentation.decision),
+        options,
+        wrapper: AttributeWrapper::new(options.attribute_wrap_width, line_end),
+        line_end,
+        newline: false,
+    }
 }
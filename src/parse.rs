@@ -1,16 +1,38 @@
+use std::borrow::Cow;
+use std::ops::ControlFlow;
+use std::ops::Range;
+use std::sync::Arc;
+
 use ahash::{HashMap, HashMapExt, HashSet, HashSetExt};
+use encoding_rs::Encoding;
 use indextree::NodeId;
 use xmlparser::{ElementEnd, StrSpan, Token, Tokenizer};
 
-use crate::encoding::decode;
-use crate::entity::{parse_attribute, parse_text};
-use crate::error::ParseError;
+use crate::doctype::{
+    self, attribute_list_declarations, external_subset_entities, notation_declarations,
+    strip_text_declaration, AttributeDefault, Doctype, EntityDefinition,
+};
+use crate::encoding::{decode, decode_recording_replacements};
+use crate::entity::{is_char, parse_attribute, parse_text, parse_text_keeping_line_ends};
+use crate::error::{Error, ParseError};
 use crate::id::{Name, NameId, PrefixId};
-use crate::xmlvalue::{Attribute, Comment, Element, Namespace, ProcessingInstruction, Text, Value};
+use crate::output;
+use crate::push::{Documents, XmlPushParser};
+use crate::recover::parse_lenient;
+use crate::xmlspace::XmlSpace;
+use crate::xmlvalue::{
+    Attribute, Comment, Element, EntityReference, Namespace, ProcessingInstruction, Text, Value,
+};
 use crate::xotdata::{Node, Xot};
 use crate::NamespaceId;
 
-type Namespaces = Vec<(PrefixId, NamespaceId)>;
+pub(crate) const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+// When we recover from an undeclared prefix, we put it in a namespace that
+// starts with this.
+pub(crate) const UNDECLARED_NAMESPACE: &str = "urn:xot:undeclared:";
+
+pub(crate) type Namespaces = Vec<(PrefixId, NamespaceId)>;
 
 struct AttributeBuilder {
     prefix: String,
@@ -19,12 +41,16 @@ struct AttributeBuilder {
     name_span: Span,
     value_span: Span,
     prefix_span: Span,
+    // whether the value comes from a default in the doctype
+    defaulted: bool,
 }
 
 struct ElementBuilder {
     prefix: String,
     name: String,
     namespaces: Namespaces,
+    // the spans of the prefix and the namespace URI of each declaration
+    namespace_spans: Vec<(Span, Span)>,
     attributes: Vec<AttributeBuilder>,
     prefix_span: Span,
     span: Span,
@@ -36,6 +62,7 @@ impl ElementBuilder {
             prefix: prefix.to_string(),
             name: name.to_string(),
             namespaces: Namespaces::new(),
+            namespace_spans: Vec::new(),
             attributes: Vec::new(),
             prefix_span: prefix.into(),
             span: Span::from_prefix_name(prefix, name),
@@ -43,7 +70,10 @@ impl ElementBuilder {
     }
 }
 
-struct DocumentBuilder {
+// `parse_text` or `parse_attribute`
+type ParseReferences = fn(Cow<str>, usize, bool) -> Result<Cow<str>, ParseError>;
+
+pub(crate) struct DocumentBuilder {
     tree: NodeId,
     current_node_id: NodeId,
     name_id_builder: NameIdBuilder,
@@ -51,10 +81,32 @@ struct DocumentBuilder {
     seen_ids: HashSet<String>,
     id_nodes: HashMap<String, NodeId>,
     xml_id_id: NameId,
+    options: ParseOptions,
+    xml_1_1: bool,
+    declaration: Option<output::xml::Declaration>,
+    doctype: Option<Doctype>,
+    in_dtd: bool,
+    // the general entities we expand
+    entities: HashMap<String, EntityDefinition>,
+    // the entities we're expanding right now
+    expanding: Vec<String>,
+    // what we've expanded so far, to enforce the entity limits
+    expansions: usize,
+    expanded_size: usize,
+    // the source text we parse, if we have all of it and own it, so that
+    // text can refer to it
+    source: Option<Arc<String>>,
+    // the depth of the current element and the number of nodes we created,
+    // to enforce the parse limits
+    depth: usize,
+    nodes: usize,
+    // whether we recover from errors, and the errors we recovered from
+    pub(crate) recover: bool,
+    pub(crate) errors: Vec<ParseError>,
 }
 
 impl DocumentBuilder {
-    fn new(xot: &mut Xot) -> Self {
+    pub(crate) fn new(xot: &mut Xot, options: ParseOptions) -> Self {
         let document = xot.arena.new_node(Value::Document);
         let mut name_id_builder = NameIdBuilder::new(xot.base_prefixes().into_iter().collect());
         let base_prefixes = vec![(xot.empty_prefix_id, xot.no_namespace_id)];
@@ -67,6 +119,155 @@ impl DocumentBuilder {
             seen_ids: HashSet::new(),
             id_nodes: HashMap::new(),
             xml_id_id: xot.xml_id_id,
+            options,
+            xml_1_1: false,
+            declaration: None,
+            doctype: None,
+            in_dtd: false,
+            entities: HashMap::new(),
+            expanding: Vec::new(),
+            expansions: 0,
+            expanded_size: 0,
+            source: None,
+            depth: 0,
+            nodes: 1,
+            recover: false,
+            errors: Vec::new(),
+        }
+    }
+
+    // When we recover from errors, record the error so we can go on.
+    // Otherwise, return it.
+    pub(crate) fn recover(&mut self, error: ParseError) -> Result<(), ParseError> {
+        if self.recover {
+            self.errors.push(error);
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+
+    // Parse the references in text or an attribute value. When we recover,
+    // a `&` that doesn't start a valid reference is taken literally.
+    fn parse_references<'a>(
+        &mut self,
+        content: &'a str,
+        position: usize,
+        parse: ParseReferences,
+    ) -> Result<Cow<'a, str>, ParseError> {
+        let mut error = match parse(content.into(), position, self.xml_1_1) {
+            Ok(result) => return Ok(result),
+            Err(error) => error,
+        };
+        let mut content = content.to_string();
+        // the number of bytes we inserted to escape a `&`
+        let mut escaped = 0;
+        loop {
+            if !matches!(
+                error,
+                ParseError::InvalidEntity(..) | ParseError::UnclosedEntity(..)
+            ) {
+                return Err(error);
+            }
+            let ampersand = error.span().start - position;
+            self.recover(
+                error.map_spans(|span| Span::new(span.start - escaped, span.end - escaped)),
+            )?;
+            content.insert_str(ampersand + 1, "amp;");
+            escaped += "amp;".len();
+            error = match parse(content.as_str().into(), position, self.xml_1_1) {
+                Ok(result) => return Ok(result.into_owned().into()),
+                Err(error) => error,
+            };
+        }
+    }
+
+    // Markup that the tokenizer skipped. In the internal subset of the
+    // doctype this contains the declarations it doesn't report.
+    pub(crate) fn skipped_markup(&mut self, markup: &str) {
+        if let (true, Some(doctype)) = (self.in_dtd, &mut self.doctype) {
+            doctype.notations.extend(notation_declarations(markup));
+            doctype
+                .attributes
+                .extend(attribute_list_declarations(markup));
+        }
+    }
+
+    // Normalize the default values of the declared attributes like attribute
+    // values in the document. Entities used in them have to be declared
+    // before, so we can do this at the end of the internal subset.
+    // https://www.w3.org/TR/xml/#AVNormalize
+    fn normalize_attribute_defaults(&mut self, span: Span) -> Result<(), ParseError> {
+        let Some(mut doctype) = self.doctype.take() else {
+            return Ok(());
+        };
+        for declaration in &mut doctype.attributes {
+            let (AttributeDefault::Fixed(value) | AttributeDefault::Value(value)) =
+                &mut declaration.default
+            else {
+                continue;
+            };
+            let normalized = self
+                .attribute_value(value, 0, &mut Vec::new())
+                .map_err(|e| e.map_spans(|_| span))?;
+            *value = if declaration.attribute_type == "CDATA" {
+                normalized
+            } else {
+                normalized
+                    .split(' ')
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+        }
+        self.doctype = Some(doctype);
+        Ok(())
+    }
+
+    // Add the attributes the doctype declares a default for that the
+    // element doesn't have. Declarations of namespace attributes are
+    // ignored, as they would change the meaning of names.
+    fn apply_attribute_defaults(&self, element_builder: &mut ElementBuilder) {
+        let Some(doctype) = &self.doctype else {
+            return;
+        };
+        let full_name = |prefix: &str, name: &str| {
+            if prefix.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}:{}", prefix, name)
+            }
+        };
+        let element = full_name(&element_builder.prefix, &element_builder.name);
+        for declaration in &doctype.attributes {
+            let Some(value) = declaration.default.value() else {
+                continue;
+            };
+            if declaration.element != element
+                || declaration.name == "xmlns"
+                || declaration.name.starts_with("xmlns:")
+            {
+                continue;
+            }
+            // an attribute that's present, or declared before, wins
+            if element_builder.attributes.iter().any(|attribute_builder| {
+                full_name(&attribute_builder.prefix, &attribute_builder.name) == declaration.name
+            }) {
+                continue;
+            }
+            let (prefix, name) = declaration
+                .name
+                .split_once(':')
+                .unwrap_or(("", &declaration.name));
+            element_builder.attributes.push(AttributeBuilder {
+                prefix: prefix.to_string(),
+                name: name.to_string(),
+                value: value.to_string(),
+                name_span: element_builder.span,
+                value_span: element_builder.span,
+                prefix_span: element_builder.span,
+                defaulted: true,
+            });
         }
     }
 
@@ -74,14 +275,21 @@ impl DocumentBuilder {
         self.element_builder = Some(ElementBuilder::new(prefix, name));
     }
 
-    fn prefix(&mut self, prefix: &str, namespace_uri: &str, xot: &mut Xot) {
+    fn prefix(
+        &mut self,
+        prefix: &str,
+        namespace_uri: &str,
+        prefix_span: Span,
+        namespace_uri_span: Span,
+        xot: &mut Xot,
+    ) {
         let prefix_id = xot.prefix_lookup.get_id_mut(prefix);
         let namespace_id = xot.namespace_lookup.get_id_mut(namespace_uri);
-        self.element_builder
-            .as_mut()
-            .unwrap()
-            .namespaces
-            .push((prefix_id, namespace_id));
+        let element_builder = self.element_builder.as_mut().unwrap();
+        element_builder.namespaces.push((prefix_id, namespace_id));
+        element_builder
+            .namespace_spans
+            .push((prefix_span, namespace_uri_span));
     }
 
     fn attribute(
@@ -90,7 +298,7 @@ impl DocumentBuilder {
         name: StrSpan<'_>,
         value: StrSpan<'_>,
     ) -> Result<(), ParseError> {
-        let attributes = &mut self.element_builder.as_mut().unwrap().attributes;
+        let attributes = &self.element_builder.as_ref().unwrap().attributes;
         let is_duplicate = attributes.iter().any(|attribute_builder| {
             attribute_builder.prefix == prefix.as_str() && attribute_builder.name == name.as_str()
         });
@@ -101,10 +309,11 @@ impl DocumentBuilder {
                 format!("{}:{}", prefix, name)
             };
             let span = Span::from_prefix_name(prefix, name);
-            return Err(ParseError::DuplicateAttribute(attr_name, span));
+            // we keep the first attribute
+            return self.recover(ParseError::DuplicateAttribute(attr_name, span));
         }
         let value_span = value.into();
-        let value = parse_attribute(value.as_str().into(), value.start())?.to_string();
+        let value = self.attribute_value(value.as_str(), value.start(), &mut Vec::new())?;
         // if this is an xml:id we want to apply xml:id normalization as described here
         // https://www.w3.org/TR/xml-id/#id-avn
         let value = if name == "id" && prefix == "xml" {
@@ -112,6 +321,7 @@ impl DocumentBuilder {
         } else {
             value
         };
+        let attributes = &mut self.element_builder.as_mut().unwrap().attributes;
         attributes.push(AttributeBuilder {
             prefix: prefix.to_string(),
             name: name.to_string(),
@@ -119,25 +329,239 @@ impl DocumentBuilder {
             name_span: Span::from_prefix_name(prefix, name),
             value_span,
             prefix_span: prefix.into(),
+            defaulted: false,
         });
         Ok(())
     }
 
-    fn add(&mut self, value: Value, xot: &mut Xot) -> NodeId {
+    // The attribute value with entity references expanded.
+    // https://www.w3.org/TR/xml/#inclusion-in-literal
+    fn attribute_value(
+        &mut self,
+        mut value: &str,
+        mut position: usize,
+        expanding: &mut Vec<String>,
+    ) -> Result<String, ParseError> {
+        let mut result = String::new();
+        while let Some((range, name)) = self.entity_reference(value) {
+            let span = Span::new(position + range.start, position + range.end);
+            result.push_str(&self.parse_references(
+                &value[..range.start],
+                position,
+                parse_attribute,
+            )?);
+            if expanding.iter().any(|expanding| expanding == name) {
+                return Err(ParseError::RecursiveEntity(name.to_string(), span));
+            }
+            // external entities and markup aren't allowed in attribute values
+            let replacement = match &self.entities[name] {
+                EntityDefinition::Internal(replacement) if !replacement.contains('<') => {
+                    replacement.clone()
+                }
+                _ => return Err(ParseError::InvalidEntity(name.to_string(), span)),
+            };
+            self.check_entity_limits(name, &replacement, expanding.len(), span)?;
+            expanding.push(name.to_string());
+            let expanded = self
+                .attribute_value(&replacement, 0, expanding)
+                .map_err(|e| e.map_spans(|_| span))?;
+            result.push_str(&expanded);
+            expanding.pop();
+            position += range.end;
+            value = &value[range.end..];
+        }
+        result.push_str(&self.parse_references(value, position, parse_attribute)?);
+        Ok(result)
+    }
+
+    fn declare_entity(&mut self, declaration: &doctype::EntityDeclaration) {
+        // the predefined entities can be declared, but they always mean the
+        // same
+        if declaration.parameter
+            || self.options.disable_entity_expansion
+            || is_predefined_entity(&declaration.name)
+        {
+            return;
+        }
+        // we can only expand external entities if we can resolve them, and
+        // never unparsed ones
+        if let EntityDefinition::External { notation, .. } = &declaration.definition {
+            if self.options.entity_resolver.is_none() || notation.is_some() {
+                return;
+            }
+        }
+        // if an entity is declared more than once, the first declaration is
+        // used
+        self.entities
+            .entry(declaration.name.clone())
+            .or_insert_with(|| declaration.definition.clone());
+    }
+
+    // Declare the entities in the external subset of the doctype, if we can
+    // resolve it. As the internal subset comes first, its declarations take
+    // precedence.
+    fn external_subset(&mut self) -> Result<(), ParseError> {
+        if self.options.disable_entity_expansion {
+            return Ok(());
+        }
+        let (Some(resolver), Some(doctype)) = (&self.options.entity_resolver, &self.doctype) else {
+            return Ok(());
+        };
+        let Some(system_id) = &doctype.system_id else {
+            return Ok(());
+        };
+        if let Some(subset) = resolver.resolve(doctype.public_id.as_deref(), system_id) {
+            for declaration in external_subset_entities(&subset)? {
+                self.declare_entity(&declaration);
+            }
+        }
+        Ok(())
+    }
+
+    // The replacement text of an entity referred to in content.
+    fn replacement_text(&self, name: &str, span: Span) -> Result<String, ParseError> {
+        match &self.entities[name] {
+            EntityDefinition::Internal(replacement) => Ok(replacement.clone()),
+            EntityDefinition::External {
+                public_id,
+                system_id,
+                ..
+            } => self
+                .options
+                .entity_resolver
+                .as_ref()
+                .and_then(|resolver| resolver.resolve(public_id.as_deref(), system_id))
+                .map(|content| strip_text_declaration(&content).to_string())
+                .ok_or_else(|| ParseError::InvalidEntity(name.to_string(), span)),
+        }
+    }
+
+    // Account for the expansion of an entity, at `depth` levels of nesting.
+    fn check_entity_limits(
+        &mut self,
+        name: &str,
+        replacement: &str,
+        depth: usize,
+        span: Span,
+    ) -> Result<(), ParseError> {
+        let limits = &self.options.entity_limits;
+        self.expansions += 1;
+        self.expanded_size += replacement.len();
+        if depth >= limits.max_depth
+            || self.expansions > limits.max_expansions
+            || self.expanded_size > limits.max_expanded_size
+        {
+            return Err(ParseError::EntityLimitExceeded(name.to_string(), span));
+        }
+        Ok(())
+    }
+
+    // Report that we parsed the source up to `position`.
+    fn report_progress(&self, position: usize) -> Result<(), ParseError> {
+        if let Some(progress) = &self.options.progress {
+            if progress.progress(position, self.nodes).is_break() {
+                return Err(ParseError::Cancelled(Span::new(position, position)));
+            }
+        }
+        Ok(())
+    }
+
+    // Account for `count` new nodes in the tree.
+    fn check_node_limit(&mut self, count: usize, span: Span) -> Result<(), ParseError> {
+        if let Some(max_nodes) = self.options.limits.max_nodes {
+            if self.nodes + count > max_nodes {
+                return Err(ParseError::NodeLimitExceeded(span));
+            }
+        }
+        self.nodes += count;
+        Ok(())
+    }
+
+    fn check_name_length(&self, prefix: &str, name: &str, span: Span) -> Result<(), ParseError> {
+        let len = if prefix.is_empty() {
+            name.len()
+        } else {
+            prefix.len() + 1 + name.len()
+        };
+        match self.options.limits.max_name_length {
+            Some(max_name_length) if len > max_name_length => {
+                Err(ParseError::NameLengthLimitExceeded(span))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Check that the element we're building has room for another attribute
+    // or namespace declaration.
+    fn check_attribute_limit(&self, span: Span) -> Result<(), ParseError> {
+        let Some(max_attributes) = self.options.limits.max_attributes else {
+            return Ok(());
+        };
+        let element_builder = self.element_builder.as_ref().unwrap();
+        if element_builder.namespaces.len() + element_builder.attributes.len() >= max_attributes {
+            return Err(ParseError::AttributeLimitExceeded(span));
+        }
+        Ok(())
+    }
+
+    // Find the first reference to an entity we expand in `text`. Returns the
+    // range of the reference and the name of the entity.
+    fn entity_reference<'a>(&self, text: &'a str) -> Option<(Range<usize>, &'a str)> {
+        if self.entities.is_empty() {
+            return None;
+        }
+        find_reference(text, |name| self.entities.contains_key(name))
+    }
+
+    // The first reference in content that we handle rather than
+    // `parse_text`. If we preserve entity references, that's a reference to
+    // any entity that isn't predefined.
+    fn content_reference<'a>(&self, text: &'a str) -> Option<(Range<usize>, &'a str)> {
+        if !self.options.preserve_entity_references {
+            return self.entity_reference(text);
+        }
+        find_reference(text, |name| {
+            !name.is_empty()
+                && !name.starts_with('#')
+                && !name.contains(|c: char| c.is_whitespace() || c == '&')
+                && !is_predefined_entity(name)
+        })
+    }
+
+    fn add(&mut self, value: Value, span: Span, xot: &mut Xot) -> Result<NodeId, ParseError> {
+        self.check_node_limit(1, span)?;
+        Ok(self.append(value, xot))
+    }
+
+    // Add a node we already accounted for.
+    fn append(&mut self, value: Value, xot: &mut Xot) -> NodeId {
+        if !matches!(value, Value::Text(_)) {
+            self.strip_whitespace(xot);
+        }
         let node_id = xot.arena.new_node(value);
         self.current_node_id.append(node_id, &mut xot.arena);
         node_id
     }
 
-    fn open_element(
-        &mut self,
-        xot: &mut Xot,
-    ) -> Result<(NodeId, Span, AttributeSpans), ParseError> {
-        let element_builder = self.element_builder.take().unwrap();
+    fn open_element(&mut self, xot: &mut Xot) -> Result<(NodeId, Span, ElementSpans), ParseError> {
+        let mut element_builder = self.element_builder.take().unwrap();
+        if self.options.apply_attribute_defaults {
+            self.apply_attribute_defaults(&mut element_builder);
+        }
         let span = element_builder.span;
+        if matches!(self.options.limits.max_depth, Some(max_depth) if self.depth >= max_depth) {
+            return Err(ParseError::DepthLimitExceeded(span));
+        }
+        self.check_node_limit(
+            1 + element_builder.namespaces.len() + element_builder.attributes.len(),
+            span,
+        )?;
 
         self.name_id_builder
             .push(element_builder.namespaces.clone());
+        if self.recover {
+            self.declare_unknown_prefixes(&mut element_builder, xot);
+        }
 
         let name_id = self.name_id_builder.element_name_id(
             &element_builder.prefix,
@@ -146,8 +570,9 @@ impl DocumentBuilder {
             xot,
         )?;
         let element_value = Value::Element(Element { name_id });
-        let node_id = self.add(element_value, xot);
+        let node_id = self.append(element_value, xot);
         self.current_node_id = node_id;
+        self.depth += 1;
 
         // add namespace nodes
         for (prefix_id, namespace_id) in &element_builder.namespaces {
@@ -157,8 +582,23 @@ impl DocumentBuilder {
             }));
             self.current_node_id.append(namespace_node, &mut xot.arena);
         }
+        // namespaces we declare to recover from errors have no spans
+        let mut element_spans = Vec::new();
+        for ((prefix_id, _), (prefix_span, namespace_uri_span)) in element_builder
+            .namespaces
+            .iter()
+            .zip(element_builder.namespace_spans)
+        {
+            element_spans.push((
+                SpanInfoKey::NamespacePrefix(node_id.into(), *prefix_id),
+                prefix_span,
+            ));
+            element_spans.push((
+                SpanInfoKey::NamespaceUri(node_id.into(), *prefix_id),
+                namespace_uri_span,
+            ));
+        }
         // add attribute nodes
-        let mut attribute_spans = Vec::new();
         for attribute_builder in element_builder.attributes {
             let name_id = self.name_id_builder.attribute_name_id(
                 &attribute_builder.prefix,
@@ -170,30 +610,79 @@ impl DocumentBuilder {
             // and keep track of all node ids that have an xml:id
             if name_id == self.xml_id_id {
                 if self.seen_ids.contains(&attribute_builder.value) {
-                    return Err(ParseError::DuplicateId(
-                        attribute_builder.value,
+                    // we keep the attribute, but the id refers to the
+                    // first element
+                    self.recover(ParseError::DuplicateId(
+                        attribute_builder.value.clone(),
                         attribute_builder.value_span,
-                    ));
+                    ))?;
+                } else {
+                    self.seen_ids.insert(attribute_builder.value.clone());
+                    // use entry api to add node id for the value given
+                    self.id_nodes
+                        .insert(attribute_builder.value.clone(), node_id);
                 }
-                self.seen_ids.insert(attribute_builder.value.clone());
-                // use entry api to add node id for the value given
-                self.id_nodes
-                    .insert(attribute_builder.value.clone(), node_id);
             }
 
             let attribute_node = xot.arena.new_node(Value::Attribute(Attribute {
                 name_id,
                 value: attribute_builder.value,
             }));
-            attribute_spans.push((
-                name_id,
+            self.current_node_id.append(attribute_node, &mut xot.arena);
+            // a defaulted attribute isn't in the source
+            if attribute_builder.defaulted {
+                xot.defaulted_attributes.insert(attribute_node);
+                continue;
+            }
+            element_spans.push((
+                SpanInfoKey::AttributeName(node_id.into(), name_id),
                 attribute_builder.name_span,
+            ));
+            element_spans.push((
+                SpanInfoKey::AttributeValue(node_id.into(), name_id),
                 attribute_builder.value_span,
             ));
-            self.current_node_id.append(attribute_node, &mut xot.arena);
         }
 
-        Ok((node_id, span, attribute_spans))
+        Ok((node_id, span, element_spans))
+    }
+
+    // Declare the prefixes the element uses but that aren't declared, with a
+    // namespace made up for them, so we can recover from this error.
+    fn declare_unknown_prefixes(&mut self, element_builder: &mut ElementBuilder, xot: &mut Xot) {
+        let prefixes = std::iter::once((&element_builder.prefix, element_builder.prefix_span))
+            .chain(
+                element_builder
+                    .attributes
+                    .iter()
+                    .filter(|attribute_builder| !attribute_builder.prefix.is_empty())
+                    .map(|attribute_builder| {
+                        (&attribute_builder.prefix, attribute_builder.prefix_span)
+                    }),
+            );
+        let mut unknown: Vec<(String, Span)> = Vec::new();
+        for (prefix, span) in prefixes {
+            let prefix_id = xot.prefix_lookup.get_id_mut(prefix);
+            if !self.name_id_builder.has_prefix(prefix_id)
+                && !unknown.iter().any(|(unknown, _)| unknown == prefix)
+            {
+                unknown.push((prefix.clone(), span));
+            }
+        }
+        if unknown.is_empty() {
+            return;
+        }
+        for (prefix, span) in unknown {
+            let prefix_id = xot.prefix_lookup.get_id_mut(&prefix);
+            let namespace_id = xot
+                .namespace_lookup
+                .get_id_mut(&format!("{}{}", UNDECLARED_NAMESPACE, prefix));
+            element_builder.namespaces.push((prefix_id, namespace_id));
+            self.errors.push(ParseError::UnknownPrefix(prefix, span));
+        }
+        self.name_id_builder.pop();
+        self.name_id_builder
+            .push(element_builder.namespaces.clone());
     }
 
     // consolidates a text node with previous node if possible. If consolidation
@@ -211,25 +700,86 @@ impl DocumentBuilder {
         None
     }
 
-    fn text(&mut self, content: &StrSpan, xot: &mut Xot) -> Result<NodeId, ParseError> {
-        let content = parse_text(content.as_str().into(), content.start())?;
+    fn text(
+        &mut self,
+        content: &str,
+        position: usize,
+        xot: &mut Xot,
+    ) -> Result<NodeId, ParseError> {
+        let span = Span::new(position, position + content.len());
+        let parse = if self.options.normalize_line_endings {
+            parse_text
+        } else {
+            parse_text_keeping_line_ends
+        };
+        let content = self.parse_references(content, position, parse)?;
         if let Some(last) = self.consolidate_text(&content, xot) {
             return Ok(last);
         }
-        Ok(self.add(Value::Text(Text::new(content.to_string())), xot))
+        let text = match (&self.source, content) {
+            // text without changes refers to the source, unless it's in the
+            // replacement text of an entity
+            (Some(source), Cow::Borrowed(_)) if self.expanding.is_empty() => {
+                Text::shared(source.clone(), span.range())
+            }
+            (_, content) => Text::new(content.into_owned()),
+        };
+        self.add(Value::Text(text), span, xot)
     }
 
-    fn cdata_text(&mut self, content: &str, xot: &mut Xot) -> Result<NodeId, ParseError> {
+    fn cdata_text(
+        &mut self,
+        content: &str,
+        span: Span,
+        xot: &mut Xot,
+    ) -> Result<NodeId, ParseError> {
+        if self.options.preserve_cdata {
+            return self.add(Value::Cdata(Text::new(content.to_string())), span, xot);
+        }
         if let Some(last) = self.consolidate_text(content, xot) {
             return Ok(last);
         }
-        Ok(self.add(Value::Text(Text::new(content.to_string())), xot))
+        self.add(Value::Text(Text::new(content.to_string())), span, xot)
+    }
+
+    // When we strip whitespace, remove the last child of the current node if
+    // it's text that's only whitespace. We do this once the text is complete,
+    // as it can be made up of multiple tokens.
+    fn strip_whitespace(&mut self, xot: &mut Xot) {
+        if !self.options.strip_whitespace {
+            return;
+        }
+        let Some(last) = xot.arena[self.current_node_id].last_child() else {
+            return;
+        };
+        let Value::Text(text) = xot.arena[last].get() else {
+            return;
+        };
+        if !text.get().chars().all(|c| c.is_ascii_whitespace()) {
+            return;
+        }
+        if let Value::Element(element) = xot.arena[self.current_node_id].get() {
+            if self
+                .options
+                .preserve_whitespace_elements
+                .contains(&element.name_id)
+            {
+                return;
+            }
+        }
+        if xot.xml_space(Node::new(self.current_node_id)) == XmlSpace::Preserve {
+            return;
+        }
+        last.remove(&mut xot.arena);
+        self.nodes -= 1;
     }
 
     fn close_element_immediate(&mut self, xot: &mut Xot) -> NodeId {
+        self.strip_whitespace(xot);
         let current_node = xot.arena.get(self.current_node_id).unwrap();
         if matches!(current_node.get(), Value::Element(_)) {
             self.name_id_builder.pop();
+            self.depth -= 1;
         }
         let closed_node_id = self.current_node_id;
         self.current_node_id = current_node.parent().expect("Cannot close document node");
@@ -246,70 +796,136 @@ impl DocumentBuilder {
             .name_id_builder
             .element_name_id(&prefix, &name, prefix.into(), xot)?;
         let current_node = xot.arena.get(self.current_node_id).unwrap();
-        if let Value::Element(element) = current_node.get() {
-            if element.name_id != name_id {
-                return Err(ParseError::InvalidCloseTag(
-                    prefix.to_string(),
-                    name.to_string(),
-                    Span::from_prefix_name(prefix, name),
-                ));
-            }
-            self.name_id_builder.pop();
+        if !matches!(current_node.get(), Value::Element(element) if element.name_id == name_id) {
+            return Err(ParseError::InvalidCloseTag(
+                prefix.to_string(),
+                name.to_string(),
+                Span::from_prefix_name(prefix, name),
+            ));
         }
-        let closed_node_id = self.current_node_id;
-        self.current_node_id = current_node.parent().expect("Cannot close document node");
-        Ok(closed_node_id)
+        Ok(self.close_element_immediate(xot))
     }
 
-    fn comment(&mut self, content: &str, xot: &mut Xot) -> Result<NodeId, ParseError> {
+    fn comment(&mut self, content: &str, span: Span, xot: &mut Xot) -> Result<NodeId, ParseError> {
         // XXX are there illegal comments, like those with -- inside? or
         // won't they pass the parser?
-        Ok(self.add(Value::Comment(Comment::new(content.to_string())), xot))
+        self.add(Value::Comment(Comment::new(content.to_string())), span, xot)
     }
 
     fn processing_instruction(
         &mut self,
         target: &str,
         content: Option<&str>,
+        span: Span,
         xot: &mut Xot,
     ) -> Result<NodeId, ParseError> {
         // XXX are there illegal processing instructions, like those with
         // ?> inside? or won't they pass the parser? What about those with xml?
         let target = xot.add_name(target);
-        Ok(self.add(
+        self.add(
             Value::ProcessingInstruction(ProcessingInstruction::new(
                 target,
                 content.map(|s| s.to_string()),
             )),
+            span,
             xot,
-        ))
+        )
+    }
+
+    // Recover from an end tag that doesn't match the current element: if an
+    // open element has this name, we close the elements up to it. Otherwise
+    // we ignore the end tag.
+    fn close_element_recover(
+        &mut self,
+        prefix: StrSpan,
+        name: StrSpan,
+        xot: &mut Xot,
+    ) -> Option<NodeId> {
+        let prefix_id = xot.prefix_lookup.get_id_mut(prefix.as_str());
+        let name_id = self
+            .name_id_builder
+            .name_id_with_prefix_id(prefix_id, &name, xot)
+            .ok()?;
+        let is_open = self.current_node_id.ancestors(&xot.arena).any(|node_id| {
+            matches!(xot.arena[node_id].get(), Value::Element(element) if element.name_id == name_id)
+        });
+        if !is_open {
+            return None;
+        }
+        loop {
+            let closed_node_id = self.close_element_immediate(xot);
+            if matches!(xot.arena[closed_node_id].get(), Value::Element(element) if element.name_id == name_id)
+            {
+                return Some(closed_node_id);
+            }
+        }
     }
 
-    fn is_current_node_document(&self, xot: &Xot) -> bool {
+    pub(crate) fn is_current_node_document(&self, xot: &Xot) -> bool {
         matches!(xot.arena[self.current_node_id].get(), Value::Document)
     }
+
+    // Whether the document element is complete.
+    pub(crate) fn is_document_element_closed(&self, xot: &Xot) -> bool {
+        self.is_current_node_document(xot)
+            && self
+                .tree
+                .children(&xot.arena)
+                .any(|child| matches!(xot.arena[child].get(), Value::Element(_)))
+    }
+
+    // Whether nothing has been added to the document.
+    pub(crate) fn is_empty(&self, xot: &Xot) -> bool {
+        xot.arena[self.tree].first_child().is_none() && self.doctype.is_none()
+    }
+
+    // Whether only comments and processing instructions have been added to
+    // the document, so it can be the epilog of the document before it.
+    pub(crate) fn is_epilog(&self, xot: &Xot) -> bool {
+        self.doctype.is_none()
+            && self.tree.children(&xot.arena).all(|child| {
+                matches!(
+                    xot.arena[child].get(),
+                    Value::Comment(_) | Value::ProcessingInstruction(_)
+                )
+            })
+    }
+
+    // Move what was added to the document to the end of `document`.
+    pub(crate) fn move_to(&self, xot: &mut Xot, document: Node) -> Result<(), Error> {
+        let children = self.tree.children(&xot.arena).collect::<Vec<_>>();
+        for child in children {
+            xot.append(document, child.into())?;
+        }
+        Ok(())
+    }
+
+    // Remove the document we were building.
+    pub(crate) fn discard(&self, xot: &mut Xot) {
+        xot.remove_document(self.tree.into());
+    }
 }
 
-struct NameIdBuilder {
+pub(crate) struct NameIdBuilder {
     namespace_stack: Vec<Namespaces>,
 }
 
 impl NameIdBuilder {
-    fn new(prefixes: Namespaces) -> Self {
+    pub(crate) fn new(prefixes: Namespaces) -> Self {
         let namespace_stack = vec![prefixes];
         Self { namespace_stack }
     }
 
-    fn push(&mut self, namespaces: Namespaces) {
+    pub(crate) fn push(&mut self, namespaces: Namespaces) {
         self.namespace_stack.push(namespaces);
     }
 
-    fn pop(&mut self) {
+    pub(crate) fn pop(&mut self) {
         // should always be able to pop as there's a bottom entry
         self.namespace_stack.pop();
     }
 
-    fn element_name_id(
+    pub(crate) fn element_name_id(
         &mut self,
         prefix: &str,
         name: &str,
@@ -324,7 +940,7 @@ impl NameIdBuilder {
         }
     }
 
-    fn attribute_name_id(
+    pub(crate) fn attribute_name_id(
         &mut self,
         prefix: &str,
         name: &str,
@@ -346,6 +962,12 @@ impl NameIdBuilder {
         }
     }
 
+    fn has_prefix(&self, prefix_id: PrefixId) -> bool {
+        self.namespace_stack
+            .iter()
+            .any(|namespaces| namespaces.iter().any(|(p, _)| *p == prefix_id))
+    }
+
     fn name_id_with_prefix_id(
         &mut self,
         prefix_id: PrefixId,
@@ -365,6 +987,382 @@ impl NameIdBuilder {
     }
 }
 
+/// Resolves external entities.
+///
+/// You can set an entity resolver with [`ParseOptions::entity_resolver`],
+/// for instance to look up external entities and external DTD subsets in a
+/// catalog. Any closure that takes a public identifier and a system
+/// identifier is an entity resolver.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use xot::{ParseOptions, Xot};
+///
+/// let options = ParseOptions {
+///     entity_resolver: Some(Arc::new(|_public_id: Option<&str>, system_id: &str| {
+///         (system_id == "chapter.xml").then(|| "<p>Chapter</p>".to_string())
+///     })),
+///     ..Default::default()
+/// };
+/// let mut xot = Xot::new();
+/// let root = xot.parse_with_options(
+///     r#"<!DOCTYPE doc [<!ENTITY chapter SYSTEM "chapter.xml">]><doc>&chapter;</doc>"#,
+///     &options,
+/// )?;
+/// assert_eq!(xot.to_string(root)?, "<doc><p>Chapter</p></doc>");
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub trait EntityResolver {
+    /// Get the content of the external entity with the given public and
+    /// system identifier.
+    ///
+    /// The system identifier is given as it appears in the document; a
+    /// relative URI is not resolved. If the entity cannot be resolved, this
+    /// returns `None`.
+    fn resolve(&self, public_id: Option<&str>, system_id: &str) -> Option<String>;
+}
+
+impl<F> EntityResolver for F
+where
+    F: Fn(Option<&str>, &str) -> Option<String>,
+{
+    fn resolve(&self, public_id: Option<&str>, system_id: &str) -> Option<String> {
+        self(public_id, system_id)
+    }
+}
+
+/// Receives progress reports while parsing.
+///
+/// You can set a progress handler with [`ParseOptions::progress`]. Any
+/// closure that takes a position and a number of nodes and returns a
+/// [`ControlFlow`] is a progress handler.
+///
+/// ```rust
+/// use std::ops::ControlFlow;
+/// use std::sync::Arc;
+/// use xot::{ParseError, ParseOptions, Xot};
+///
+/// let options = ParseOptions {
+///     // give up once the document has more than 100 nodes
+///     progress: Some(Arc::new(|_position: usize, nodes: usize| {
+///         if nodes > 100 {
+///             ControlFlow::Break(())
+///         } else {
+///             ControlFlow::Continue(())
+///         }
+///     })),
+///     ..Default::default()
+/// };
+/// let mut xot = Xot::new();
+/// let xml = format!("<doc>{}</doc>", "<p/>".repeat(1000));
+/// let err = xot.parse_with_options(&xml, &options).unwrap_err();
+/// assert!(matches!(err, ParseError::Cancelled(_)));
+/// ```
+pub trait ProgressHandler {
+    /// Report that the source text is parsed up to `position`, which is a
+    /// byte offset, and that the tree has `nodes` nodes so far, counting
+    /// the document node and attribute and namespace nodes.
+    ///
+    /// Return [`ControlFlow::Break`] to cancel parsing, which results in
+    /// [`ParseError::Cancelled`].
+    fn progress(&self, position: usize, nodes: usize) -> ControlFlow<()>;
+}
+
+impl<F> ProgressHandler for F
+where
+    F: Fn(usize, usize) -> ControlFlow<()>,
+{
+    fn progress(&self, position: usize, nodes: usize) -> ControlFlow<()> {
+        self(position, nodes)
+    }
+}
+
+/// Options for parsing XML.
+///
+/// You use these with [`Xot::parse_with_options`].
+#[derive(Clone)]
+pub struct ParseOptions {
+    /// Accept XML 1.1 documents.
+    ///
+    /// If a document declares `version="1.1"`, it's parsed following the
+    /// rules of XML 1.1: character references to control characters such as
+    /// `&#x1;` are allowed, and NEL (U+0085) and LS (U+2028) are line ends
+    /// that are normalized to a newline. If this is false, such a document
+    /// results in [`ParseError::UnsupportedVersion`]. Documents without a
+    /// declaration, or that declare version 1.0, are always parsed as
+    /// XML 1.0.
+    ///
+    /// To serialize an XML 1.1 document, use
+    /// [`output::xml::Version::V1_1`](crate::output::xml::Version::V1_1).
+    pub xml_1_1: bool,
+    /// Don't expand entities declared in the doctype.
+    ///
+    /// By default, a reference like `&company;` to an internal entity
+    /// declared in the internal subset of the doctype, such as `<!ENTITY
+    /// company "ACME Corp">`, is replaced by the replacement text of the
+    /// entity. This may contain markup, which is parsed too; errors in it
+    /// are reported at the span of the reference. If this is set,
+    /// such references are an error, like references to undeclared entities.
+    /// The declarations are still available with [`Xot::doctype`].
+    pub disable_entity_expansion: bool,
+    /// Resolve external entities and the external DTD subset.
+    ///
+    /// By default, Xot doesn't load anything that's external to the
+    /// document. References to external entities are an error, and the
+    /// external subset of the doctype is ignored.
+    ///
+    /// With a resolver, references to external parsed entities in text are
+    /// expanded to the content the resolver gives, and entities declared in
+    /// the external subset of the doctype can be used as well. When an entity
+    /// is declared in both, the declaration in the internal subset is used.
+    /// Of the external subset, only entity declarations are used.
+    pub entity_resolver: Option<Arc<dyn EntityResolver>>,
+    /// Limits to entity expansion.
+    ///
+    /// The defaults are safe to use with untrusted documents.
+    pub entity_limits: EntityLimits,
+    /// Limits to the size of the tree.
+    ///
+    /// By default there are no limits. Set these when you parse untrusted
+    /// documents.
+    pub limits: ParseLimits,
+    /// Report progress while parsing.
+    ///
+    /// The handler is called after each piece of markup or text, so you can
+    /// show progress of parsing a large document, or cancel it. See
+    /// [`ProgressHandler`].
+    pub progress: Option<Arc<dyn ProgressHandler>>,
+    /// Keep CDATA sections.
+    ///
+    /// By default, a CDATA section becomes text, which is consolidated with
+    /// adjacent text. If this is set, each CDATA section becomes a
+    /// [`Value::Cdata`] node instead, which is serialized as a CDATA section
+    /// again.
+    pub preserve_cdata: bool,
+    /// Keep references to entities in text.
+    ///
+    /// If this is set, a reference such as `&chapter1;` in text becomes a
+    /// [`Value::EntityReference`] node, which is serialized as the reference
+    /// again, instead of being expanded. This is so for any entity but the
+    /// predefined ones like `&amp;`, even if it isn't declared. References
+    /// in attribute values are treated as usual.
+    pub preserve_entity_references: bool,
+    /// Remove text that consists of whitespace only.
+    ///
+    /// Data-oriented documents typically have whitespace between elements
+    /// to indent them, which results in a lot of text nodes you're not
+    /// interested in. If this is set, text nodes that consist only of
+    /// whitespace aren't added to the tree, unless they're a child of an
+    /// element in
+    /// [`preserve_whitespace_elements`](ParseOptions::preserve_whitespace_elements).
+    pub strip_whitespace: bool,
+    /// Elements in which whitespace isn't stripped.
+    ///
+    /// Whitespace-only text that's a direct child of one of these elements is
+    /// kept when [`strip_whitespace`](ParseOptions::strip_whitespace) is
+    /// set.
+    pub preserve_whitespace_elements: Vec<NameId>,
+    /// Keep comments.
+    ///
+    /// This is set by default. If you're not interested in comments, you can
+    /// turn it off so they're not added to the tree at all. Text on either
+    /// side of a comment that's left out becomes a single text node.
+    pub keep_comments: bool,
+    /// Keep processing instructions.
+    ///
+    /// This is set by default. If you turn it off, processing instructions
+    /// are left out of the tree like comments are with
+    /// [`keep_comments`](ParseOptions::keep_comments).
+    pub keep_pis: bool,
+    /// Normalize line ends in text.
+    ///
+    /// This is set by default, as the XML specification requires: `\r\n`
+    /// and a lone `\r` in text become `\n`. If you turn it off, text keeps
+    /// the line ends it has in the source, so you can write them out the
+    /// same way again. Line ends in attribute values are always normalized.
+    pub normalize_line_endings: bool,
+    /// Reject input that starts with a byte order mark.
+    ///
+    /// By default, a byte order mark is accepted and left out of the tree;
+    /// you can find out whether there was one with [`Xot::has_bom`]. If this
+    /// is set, a byte order mark results in
+    /// [`ParseError::UnexpectedByteOrderMark`].
+    pub reject_bom: bool,
+    /// Replace characters that aren't allowed in XML.
+    ///
+    /// By default, a character that isn't allowed in XML 1.0, such as a
+    /// control character like U+0001, is an error. If this is set, such
+    /// characters are replaced by U+FFFD (the replacement character) before
+    /// parsing, which lets you read documents that are almost XML. You can
+    /// find out where characters were replaced with
+    /// [`Xot::replaced_characters`]; this includes data that couldn't be
+    /// decoded when you parse bytes. Spans still refer to the original text.
+    pub replace_invalid_characters: bool,
+    /// Add attributes that have a default in the doctype.
+    ///
+    /// The internal subset of the doctype can declare a default value for
+    /// an attribute, for instance with `<!ATTLIST doc version CDATA "1.0">`.
+    /// A validating parser adds such an attribute to each element that
+    /// doesn't have it. If this is set, Xot does so too, and marks the
+    /// attribute as defaulted; see [`Xot::is_defaulted_attribute`]. You can
+    /// leave these attributes out again when serializing with
+    /// [`omit_defaulted_attributes`](crate::output::xml::Parameters::omit_defaulted_attributes).
+    pub apply_attribute_defaults: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            xml_1_1: false,
+            disable_entity_expansion: false,
+            entity_resolver: None,
+            entity_limits: EntityLimits::default(),
+            limits: ParseLimits::default(),
+            progress: None,
+            preserve_cdata: false,
+            preserve_entity_references: false,
+            strip_whitespace: false,
+            preserve_whitespace_elements: Vec::new(),
+            keep_comments: true,
+            keep_pis: true,
+            normalize_line_endings: true,
+            reject_bom: false,
+            replace_invalid_characters: false,
+            apply_attribute_defaults: false,
+        }
+    }
+}
+
+/// Limits to entity expansion.
+///
+/// As entities can refer to other entities, a small document can expand to
+/// a huge one, for instance in the "billion laughs" attack. These limits
+/// prevent this. When a limit is exceeded, parsing fails with
+/// [`ParseError::EntityLimitExceeded`].
+///
+/// ```rust
+/// use xot::{EntityLimits, ParseError, ParseOptions, Xot};
+///
+/// let options = ParseOptions {
+///     entity_limits: EntityLimits {
+///         max_expansions: 2,
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// let mut xot = Xot::new();
+/// let err = xot
+///     .parse_with_options(
+///         r#"<!DOCTYPE doc [<!ENTITY a "x">]><doc>&a;&a;&a;</doc>"#,
+///         &options,
+///     )
+///     .unwrap_err();
+/// assert!(matches!(err, ParseError::EntityLimitExceeded(..)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EntityLimits {
+    /// The maximum depth of entity references within entities. The default
+    /// is 16.
+    pub max_depth: usize,
+    /// The maximum number of entity references that are expanded in a
+    /// document, including references within entities. The default is
+    /// 10,000.
+    pub max_expansions: usize,
+    /// The maximum total size in bytes of the replacement text of all
+    /// expanded entity references. The default is 10 MB.
+    pub max_expanded_size: usize,
+}
+
+impl Default for EntityLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 16,
+            max_expansions: 10_000,
+            max_expanded_size: 10_000_000,
+        }
+    }
+}
+
+/// Limits to the size of the tree that's parsed.
+///
+/// These let you bound the memory and stack use when you parse untrusted
+/// documents. Each limit is `None` by default, which means there's no limit.
+/// When a limit is exceeded, parsing fails with the error for that limit.
+///
+/// ```rust
+/// use xot::{ParseError, ParseLimits, ParseOptions, Xot};
+///
+/// let options = ParseOptions {
+///     limits: ParseLimits {
+///         max_depth: Some(2),
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// let mut xot = Xot::new();
+/// assert!(xot.parse_with_options("<a><b/></a>", &options).is_ok());
+/// let err = xot
+///     .parse_with_options("<a><b><c/></b></a>", &options)
+///     .unwrap_err();
+/// assert!(matches!(err, ParseError::DepthLimitExceeded(..)));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ParseLimits {
+    /// The maximum depth of nested elements. The document element is at
+    /// depth 1. Exceeding it results in [`ParseError::DepthLimitExceeded`].
+    pub max_depth: Option<usize>,
+    /// The maximum number of attributes of an element, including namespace
+    /// declarations. Exceeding it results in
+    /// [`ParseError::AttributeLimitExceeded`].
+    pub max_attributes: Option<usize>,
+    /// The maximum length in bytes of the name of an element, attribute or
+    /// processing instruction target, including its prefix. Exceeding it
+    /// results in [`ParseError::NameLengthLimitExceeded`].
+    pub max_name_length: Option<usize>,
+    /// The maximum number of nodes in the tree, including the document node
+    /// and attribute and namespace nodes. Exceeding it results in
+    /// [`ParseError::NodeLimitExceeded`].
+    pub max_nodes: Option<usize>,
+}
+
+impl std::fmt::Debug for ParseOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ParseOptions")
+            .field("xml_1_1", &self.xml_1_1)
+            .field("disable_entity_expansion", &self.disable_entity_expansion)
+            .field(
+                "entity_resolver",
+                &self.entity_resolver.as_ref().map(|_| "EntityResolver"),
+            )
+            .field("entity_limits", &self.entity_limits)
+            .field("limits", &self.limits)
+            .field(
+                "progress",
+                &self.progress.as_ref().map(|_| "ProgressHandler"),
+            )
+            .field("preserve_cdata", &self.preserve_cdata)
+            .field(
+                "preserve_entity_references",
+                &self.preserve_entity_references,
+            )
+            .field("strip_whitespace", &self.strip_whitespace)
+            .field(
+                "preserve_whitespace_elements",
+                &self.preserve_whitespace_elements,
+            )
+            .field("keep_comments", &self.keep_comments)
+            .field("keep_pis", &self.keep_pis)
+            .field("normalize_line_endings", &self.normalize_line_endings)
+            .field("reject_bom", &self.reject_bom)
+            .field(
+                "replace_invalid_characters",
+                &self.replace_invalid_characters,
+            )
+            .field("apply_attribute_defaults", &self.apply_attribute_defaults)
+            .finish()
+    }
+}
+
 /// A span with a start and end position
 ///
 /// Spans describe ranges in the source text, with the end point not inclusive,
@@ -388,7 +1386,7 @@ impl Span {
         Span { start, end }
     }
 
-    fn from_prefix_name(prefix: StrSpan<'_>, name: StrSpan<'_>) -> Self {
+    pub(crate) fn from_prefix_name(prefix: StrSpan<'_>, name: StrSpan<'_>) -> Self {
         if prefix.is_empty() {
             Self::new(name.start(), name.end())
         } else {
@@ -435,7 +1433,8 @@ impl From<std::ops::Range<usize>> for Span {
     }
 }
 
-type AttributeSpans = Vec<(NameId, Span, Span)>;
+// The spans of the attributes and namespace declarations of an element.
+type ElementSpans = Vec<(SpanInfoKey, Span)>;
 
 /// A key to use to look up span information using
 /// [`SpanInfo::get`](`crate::SpanInfo::get`)
@@ -447,6 +1446,13 @@ pub enum SpanInfoKey {
     /// The value part of an attribute.
     /// In `foo:name="value"`, the `value` part
     AttributeValue(Node, NameId),
+    /// The prefix part of a namespace declaration.
+    /// In `xmlns:foo="uri"`, the `xmlns:foo` part, and in `xmlns="uri"`, the
+    /// `xmlns` part
+    NamespacePrefix(Node, PrefixId),
+    /// The namespace URI part of a namespace declaration.
+    /// In `xmlns:foo="uri"`, the `uri` part
+    NamespaceUri(Node, PrefixId),
     /// The name part of a start element tag.
     /// In `<foo:name ..>`, the `foo:name` part
     ElementStart(Node),
@@ -454,12 +1460,16 @@ pub enum SpanInfoKey {
     /// In `</foo:name>`, the `</foo:name>` part, or if it is an empty element
     /// `<foo:name/>`, the `/>` part
     ElementEnd(Node),
-    /// Text node.
-    /// In `<foo>text</foo>`, the `text` part
+    /// Text node, or CDATA section.
+    /// In `<foo>text</foo>`, the `text` part, and in
+    /// `<foo><![CDATA[text]]></foo>` the `text` part too
     Text(Node),
     /// Comment node.
     /// In `<!--comment-->`, the `comment` part
     Comment(Node),
+    /// Entity reference node.
+    /// In `&name;`, the whole reference
+    EntityReference(Node),
     /// The target part of a processing instruction.
     /// In `<?target content?>`, the `target` part
     PiTarget(Node),
@@ -468,6 +1478,28 @@ pub enum SpanInfoKey {
     PiContent(Node),
 }
 
+impl SpanInfoKey {
+    /// The node this is the span information of.
+    ///
+    /// For attributes and namespace declarations, this is the element they're
+    /// on.
+    pub fn node(&self) -> Node {
+        match self {
+            SpanInfoKey::AttributeName(node, _)
+            | SpanInfoKey::AttributeValue(node, _)
+            | SpanInfoKey::NamespacePrefix(node, _)
+            | SpanInfoKey::NamespaceUri(node, _)
+            | SpanInfoKey::ElementStart(node)
+            | SpanInfoKey::ElementEnd(node)
+            | SpanInfoKey::Text(node)
+            | SpanInfoKey::Comment(node)
+            | SpanInfoKey::EntityReference(node)
+            | SpanInfoKey::PiTarget(node)
+            | SpanInfoKey::PiContent(node) => *node,
+        }
+    }
+}
+
 /// Span information for a parsed XML document.
 ///
 /// This span information is valid immediately after the parse. It becomes
@@ -509,11 +1541,12 @@ pub enum SpanInfoKey {
 /// In every case all text content in the adjacent CDATA and text is included
 /// in the span.
 pub struct SpanInfo {
-    map: HashMap<SpanInfoKey, Span>,
+    // the spans of each node, ordered by their start
+    map: HashMap<Node, Vec<(SpanInfoKey, Span)>>,
 }
 
 impl SpanInfo {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         SpanInfo {
             map: HashMap::new(),
         }
@@ -521,36 +1554,103 @@ impl SpanInfo {
 
     /// Get span info by [`SpanInfoKey`](crate::SpanInfoKey)
     pub fn get(&self, key: SpanInfoKey) -> Option<&Span> {
-        self.map.get(&key)
-    }
-
-    fn add(&mut self, key: SpanInfoKey, span: Span) {
-        self.map.insert(key, span);
+        self.map
+            .get(&key.node())?
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, span)| span)
     }
 
-    fn extend_text_span(&mut self, node: Node, span: Span) {
-        // if we already have span for this (text) node it, we need to store the span with that
+    /// All span info for a node, in the order it appears in the source.
+    ///
+    /// For an element, this includes the spans of its start and end tag,
+    /// and of the names and values of its attributes and namespace
+    /// declarations. This lets you report a problem with a node at the
+    /// right place in the source without having to know which keys to look
+    /// up.
+    ///
+    /// ```rust
+    /// use xot::{SpanInfoKey, Xot};
+    ///
+    /// let mut xot = Xot::new();
+    /// let (root, span_info) = xot.parse_with_span_info(r#"<a xmlns:p="uri" b="c"></a>"#)?;
+    /// let a = xot.document_element(root)?;
+    /// let spans = span_info
+    ///     .spans(a)
+    ///     .map(|(key, span)| (key, span.range()))
+    ///     .collect::<Vec<_>>();
+    /// let p = xot.prefix("p").unwrap();
+    /// let b = xot.name("b").unwrap();
+    /// assert_eq!(
+    ///     spans,
+    ///     vec![
+    ///         (SpanInfoKey::ElementStart(a), 1..2),
+    ///         (SpanInfoKey::NamespacePrefix(a, p), 3..10),
+    ///         (SpanInfoKey::NamespaceUri(a, p), 12..15),
+    ///         (SpanInfoKey::AttributeName(a, b), 17..18),
+    ///         (SpanInfoKey::AttributeValue(a, b), 20..21),
+    ///         (SpanInfoKey::ElementEnd(a), 23..27),
+    ///     ]
+    /// );
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn spans(&self, node: Node) -> impl Iterator<Item = (SpanInfoKey, Span)> + '_ {
+        self.map.get(&node).into_iter().flatten().copied()
+    }
+
+    pub(crate) fn add(&mut self, key: SpanInfoKey, span: Span) {
+        let spans = self.map.entry(key.node()).or_default();
+        if let Some(existing) = spans.iter_mut().find(|(k, _)| *k == key) {
+            existing.1 = span;
+        } else {
+            spans.push((key, span));
+        }
+        spans.sort_by_key(|(_, span)| span.start);
+    }
+
+    pub(crate) fn extend(&mut self, spans: impl IntoIterator<Item = (SpanInfoKey, Span)>) {
+        for (key, span) in spans {
+            self.add(key, span);
+        }
+    }
+
+    pub(crate) fn extend_text_span(&mut self, node: Node, span: Span) {
+        // if we already have span for this (text) node it, we need to store the span with that
         // start and the given ending
         let key = SpanInfoKey::Text(node);
-        if let Some(existing_span) = self.map.get(&key) {
+        if let Some(existing_span) = self.get(key) {
             let start = existing_span.start;
             let end = span.end;
-            self.map.insert(key, Span::new(start, end));
+            self.add(key, Span::new(start, end));
         } else {
-            self.map.insert(key, span);
+            self.add(key, span);
         }
     }
 
-    fn add_attribute_spans(&mut self, node_id: NodeId, attribute_spans: AttributeSpans) {
-        for (attribute_name, name_span, value_span) in attribute_spans {
-            self.add(
-                SpanInfoKey::AttributeName(node_id.into(), attribute_name),
-                name_span,
-            );
-            self.add(
-                SpanInfoKey::AttributeValue(node_id.into(), attribute_name),
-                value_span,
-            );
+    // Change the position of all spans. `f` must keep the order of
+    // positions.
+    pub(crate) fn map_spans(self, f: impl Fn(Span) -> Span) -> SpanInfo {
+        let map = self
+            .map
+            .into_iter()
+            .map(|(node, spans)| {
+                let spans = spans.into_iter().map(|(key, span)| (key, f(span)));
+                (node, spans.collect())
+            })
+            .collect();
+        SpanInfo { map }
+    }
+
+    // Add the spans of another span info, shifted by `offset`. This is used
+    // when the other span info describes a piece of a larger source text.
+    pub(crate) fn extend_with_offset(&mut self, other: SpanInfo, offset: usize) {
+        for (key, span) in other.map.into_values().flatten() {
+            let span = Span::new(span.start + offset, span.end + offset);
+            if let SpanInfoKey::Text(node) = key {
+                self.extend_text_span(node, span);
+            } else {
+                self.add(key, span);
+            }
         }
     }
 }
@@ -571,56 +1671,278 @@ impl Xot {
         crate::html5::parse_html(self, html)
     }
 
+    /// Parse a string containing HTML into a document node, with options.
+    ///
+    /// This requires the `html5ever` feature.
+    ///
+    /// This is like [`Xot::parse_html`], but allows you to configure the
+    /// parser using [`Html5ParseOptions`](`crate::html5::Html5ParseOptions`).
+    #[cfg(feature = "html5ever")]
+    pub fn parse_html_with_options(
+        &mut self,
+        html: &str,
+        options: crate::html5::Html5ParseOptions,
+    ) -> Result<Node, ParseError> {
+        crate::html5::parse_html_with_options(self, html, options)
+    }
+
+    /// Parse a string containing HTML into a document node. Retain span
+    /// information.
+    ///
+    /// This requires the `html5ever` feature.
+    ///
+    /// The HTML parser only reports line numbers, so spans cover whole
+    /// lines. See
+    /// [`html5::parse_html_with_span_info`](`crate::html5::parse_html_with_span_info`).
+    #[cfg(feature = "html5ever")]
+    pub fn parse_html_with_span_info(
+        &mut self,
+        html: &str,
+    ) -> Result<(Node, SpanInfo), ParseError> {
+        crate::html5::parse_html_with_span_info(self, html)
+    }
+
+    /// Parse a string containing HTML into a document node, collecting
+    /// parse errors.
+    ///
+    /// This requires the `html5ever` feature.
+    ///
+    /// HTML parsing recovers from errors, so this always returns a document
+    /// node. The errors the parser recovered from are returned as
+    /// [`Html5Error`](`crate::html5::Html5Error`) values.
+    #[cfg(feature = "html5ever")]
+    pub fn parse_html_lenient(&mut self, html: &str) -> (Node, Vec<crate::html5::Html5Error>) {
+        crate::html5::parse_html_lenient(self, html)
+    }
+
+    /// Parse a string containing HTML into a document node with options,
+    /// collecting parse errors.
+    ///
+    /// This requires the `html5ever` feature.
+    ///
+    /// This is like [`Xot::parse_html_lenient`], but allows you to configure
+    /// the parser using
+    /// [`Html5ParseOptions`](`crate::html5::Html5ParseOptions`).
+    #[cfg(feature = "html5ever")]
+    pub fn parse_html_lenient_with_options(
+        &mut self,
+        html: &str,
+        options: crate::html5::Html5ParseOptions,
+    ) -> (Node, Vec<crate::html5::Html5Error>) {
+        crate::html5::parse_html_lenient_with_options(self, html, options)
+    }
+
+    /// Parse the `srcdoc` attribute of an `iframe` into a document node.
+    ///
+    /// This requires the `html5ever` feature.
+    ///
+    /// The document is parsed in the iframe `srcdoc` mode of the HTML parser.
+    /// See [`html5::parse_html_srcdoc`](`crate::html5::parse_html_srcdoc`).
+    #[cfg(feature = "html5ever")]
+    pub fn parse_html_srcdoc(&mut self, srcdoc: &str) -> Result<Node, ParseError> {
+        crate::html5::parse_html_srcdoc(self, srcdoc)
+    }
+
+    /// Parse bytes containing HTML into a document node.
+    ///
+    /// This requires the `html5ever` feature.
+    ///
+    /// The encoding is determined using the HTML encoding sniffing
+    /// algorithm. You can get the encoding that was used with
+    /// [`Xot::encoding`]. See
+    /// [`html5::parse_html_bytes`](`crate::html5::parse_html_bytes`).
+    #[cfg(feature = "html5ever")]
+    pub fn parse_html_bytes(&mut self, bytes: &[u8]) -> Result<Node, ParseError> {
+        crate::html5::parse_html_bytes(self, bytes)
+    }
+
+    /// Parse HTML from a reader into a document node.
+    ///
+    /// This requires the `html5ever` feature.
+    ///
+    /// The input is parsed incrementally as it is read. See
+    /// [`html5::parse_html_read`](`crate::html5::parse_html_read`).
+    #[cfg(feature = "html5ever")]
+    pub fn parse_html_read(&mut self, reader: impl std::io::Read) -> Result<Node, crate::Error> {
+        crate::html5::parse_html_read(self, reader)
+    }
+
+    /// Parse a string containing an HTML fragment into a document node.
+    ///
+    /// This requires the `html5ever` feature.
+    ///
+    /// The fragment is parsed as the content of an HTML element with the
+    /// local name `context`. See
+    /// [`html5::parse_html_fragment`](`crate::html5::parse_html_fragment`).
+    #[cfg(feature = "html5ever")]
+    pub fn parse_html_fragment(&mut self, context: &str, html: &str) -> Result<Node, ParseError> {
+        crate::html5::parse_html_fragment(self, context, html)
+    }
+
+    /// Parse a string containing an HTML fragment, appending the result to
+    /// `parent`.
+    ///
+    /// This requires the `html5ever` feature.
+    ///
+    /// See [`html5::parse_html_into`](`crate::html5::parse_html_into`).
+    #[cfg(feature = "html5ever")]
+    pub fn parse_html_into(&mut self, parent: Node, html: &str) -> Result<(), crate::Error> {
+        crate::html5::parse_html_into(self, parent, html)
+    }
+
     /// Parse a string containing XML into a document node. Retain span information.
     ///
     /// This parses the XML source into a Xot tree, and also returns
     /// [`SpanInfo`](`crate::SpanInfo`) which describes where nodes in the
     /// tree are located in the source text.
     pub fn parse_with_span_info(&mut self, xml: &str) -> Result<(Node, SpanInfo), ParseError> {
+        self.parse_document(Cow::Borrowed(xml), ParseOptions::default())
+    }
+
+    /// Parse a string containing XML into a document node, with options.
+    ///
+    /// This is like [`Xot::parse`], but you can use [`ParseOptions`] to
+    /// control parsing.
+    ///
+    /// ```rust
+    /// use xot::{ParseOptions, Xot};
+    ///
+    /// let mut xot = Xot::new();
+    /// let options = ParseOptions {
+    ///     xml_1_1: true,
+    ///     ..Default::default()
+    /// };
+    /// let document = xot.parse_with_options(r#"<?xml version="1.1"?><p>&#x1;</p>"#, &options)?;
+    /// let p = xot.document_element(document)?;
+    /// assert_eq!(xot.text_content_str(p), Some("\u{1}"));
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn parse_with_options(
+        &mut self,
+        xml: &str,
+        options: &ParseOptions,
+    ) -> Result<Node, ParseError> {
+        self.parse_document(Cow::Borrowed(xml), options.clone())
+            .map(|(node, _)| node)
+    }
+
+    fn parse_document(
+        &mut self,
+        xml: Cow<'_, str>,
+        options: ParseOptions,
+    ) -> Result<(Node, SpanInfo), ParseError> {
+        let has_bom = xml.starts_with('\u{FEFF}');
+        if has_bom && options.reject_bom {
+            return Err(ParseError::UnexpectedByteOrderMark(Span::new(
+                0,
+                '\u{FEFF}'.len_utf8(),
+            )));
+        }
+        let replaced = if options.replace_invalid_characters {
+            replace_invalid_characters(&xml)
+        } else {
+            None
+        };
+        let (node, span_info) = match replaced {
+            Some((replaced, positions)) => {
+                // spans refer to the text with replacements, so we map them
+                // back to the source text
+                let shifts = source_shifts(&xml, &positions);
+                let position = |position| source_position(&shifts, position);
+                let span = |span: Span| Span::new(position(span.start), position(span.end));
+                let (node, span_info) = self
+                    .parse_source(Cow::Owned(replaced), options)
+                    .map_err(|error| error.map_spans(span))?;
+                let span_info = span_info.map_spans(span);
+                self.replaced_characters.insert(node.get(), positions);
+                (node, span_info)
+            }
+            None => self.parse_source(xml, options)?,
+        };
+        if has_bom {
+            self.boms.insert(node.get());
+        }
+        Ok((node, span_info))
+    }
+
+    // Text parsed without changes can refer to a source we own. We don't
+    // copy a source we borrow to do this, as that would keep a copy of all
+    // of it for as long as any of its text is around.
+    fn parse_source(
+        &mut self,
+        xml: Cow<'_, str>,
+        options: ParseOptions,
+    ) -> Result<(Node, SpanInfo), ParseError> {
+        match xml {
+            Cow::Borrowed(xml) => self.parse_source_str(xml, None, options),
+            Cow::Owned(xml) => {
+                let source = Arc::new(xml);
+                self.parse_source_str(&source, Some(source.clone()), options)
+            }
+        }
+    }
+
+    fn parse_source_str(
+        &mut self,
+        xml: &str,
+        source: Option<Arc<String>>,
+        options: ParseOptions,
+    ) -> Result<(Node, SpanInfo), ParseError> {
         let tokenizer = Tokenizer::from(xml);
-        let (span_info, builder) = self._parse(tokenizer)?;
+        let mut builder = DocumentBuilder::new(self, options);
+        builder.source = source;
+        let (span_info, mut builder) = self._parse(tokenizer, builder)?;
+        self.finish_document(&mut builder, span_info, xml.len())
+    }
+
+    pub(crate) fn finish_document(
+        &mut self,
+        builder: &mut DocumentBuilder,
+        span_info: SpanInfo,
+        len: usize,
+    ) -> Result<(Node, SpanInfo), ParseError> {
         // we expect both a document as the current node (everything else being
         // closed) *and* the content of this node containing a single element
         // if not, we have a problem. We want to produce a parse error for
-        // this, as this is the parser.
-        if builder.is_current_node_document(self) {
-            let document_node = Node::new(builder.tree);
-            let mut element_nodes = Vec::new();
-
-            for child in self.children(document_node) {
-                match self.value(child) {
-                    Value::Element(_) => element_nodes.push(child),
-                    Value::Text(_) => {
-                        return Err(ParseError::TextAtTopLevel(
-                            *span_info.get(SpanInfoKey::Text(child)).unwrap(),
-                        ));
-                    }
-                    _ => {}
+        // this, as this is the parser. When we recover, we close the elements
+        // that are still open.
+        let span = |key| span_info.get(key).copied().unwrap_or(Span::new(len, len));
+        while !builder.is_current_node_document(self) {
+            let current_node = Node::new(builder.current_node_id);
+            builder.recover(ParseError::UnclosedTag(span(SpanInfoKey::ElementStart(
+                current_node,
+            ))))?;
+            builder.close_element_immediate(self);
+        }
+        let document_node = Node::new(builder.tree);
+        let mut element_nodes = Vec::new();
+
+        for child in self.children(document_node).collect::<Vec<_>>() {
+            match self.value(child) {
+                Value::Element(_) => element_nodes.push(child),
+                Value::Text(_) | Value::Cdata(_) => {
+                    builder.recover(ParseError::TextAtTopLevel(span(SpanInfoKey::Text(child))))?;
                 }
+                _ => {}
             }
-            if element_nodes.is_empty() {
-                return Err(ParseError::NoElementAtTopLevel(xml.len()));
-            }
-            if element_nodes.len() > 1 {
-                return Err(ParseError::MultipleElementsAtTopLevel(
-                    *span_info
-                        .get(SpanInfoKey::ElementStart(element_nodes[1]))
-                        .unwrap(),
-                ));
-            }
-            self.id_nodes_map
-                .insert(document_node.get(), builder.id_nodes);
-            Ok((document_node, span_info))
-        } else {
-            let current_node = Node::new(builder.current_node_id);
-
-            // the top level node's span is the problem
-            Err(ParseError::UnclosedTag(
-                *span_info
-                    .get(SpanInfoKey::ElementStart(current_node))
-                    .unwrap(),
-            ))
         }
+        if element_nodes.is_empty() {
+            builder.recover(ParseError::NoElementAtTopLevel(len))?;
+        }
+        for element_node in element_nodes.into_iter().skip(1) {
+            builder.recover(ParseError::MultipleElementsAtTopLevel(span(
+                SpanInfoKey::ElementStart(element_node),
+            )))?;
+        }
+        self.id_nodes_map
+            .insert(document_node.get(), std::mem::take(&mut builder.id_nodes));
+        if let Some(declaration) = builder.declaration.take() {
+            self.declarations.insert(document_node.get(), declaration);
+        }
+        if let Some(doctype) = builder.doctype.take() {
+            self.doctypes.insert(document_node.get(), doctype);
+        }
+        Ok((document_node, span_info))
     }
 
     /// Parse a string containing an XML fragment into a document node.
@@ -641,9 +1963,20 @@ impl Xot {
     pub fn parse_fragment_with_span_info(
         &mut self,
         xml: &str,
+    ) -> Result<(Node, SpanInfo), ParseError> {
+        self.parse_fragment_in_context(xml, Namespaces::new())
+    }
+
+    // Parse a fragment with the prefixes in `context` in scope.
+    fn parse_fragment_in_context(
+        &mut self,
+        xml: &str,
+        context: Namespaces,
     ) -> Result<(Node, SpanInfo), ParseError> {
         let tokenizer = Tokenizer::from_fragment(xml, 0..xml.len());
-        let (span_info, builder) = self._parse(tokenizer)?;
+        let mut builder = DocumentBuilder::new(self, ParseOptions::default());
+        builder.name_id_builder.push(context);
+        let (span_info, builder) = self._parse(tokenizer, builder)?;
         if builder.is_current_node_document(self) {
             let document_node = Node::new(builder.tree);
             self.id_nodes_map
@@ -664,10 +1997,8 @@ impl Xot {
     fn _parse(
         &mut self,
         mut tokenizer: Tokenizer<'_>,
+        mut builder: DocumentBuilder,
     ) -> Result<(SpanInfo, DocumentBuilder), ParseError> {
-        use Token::*;
-
-        let mut builder = DocumentBuilder::new(self);
         let mut span_info = SpanInfo::new();
 
         let mut position;
@@ -683,110 +2014,257 @@ impl Xot {
                         return Err(ParseError::XmlParser(e, position));
                     }
                 };
-                match token {
-                    Attribute {
-                        prefix,
-                        local,
-                        value,
-                        span: _,
-                    } => {
-                        if prefix.as_str() == "xmlns" {
-                            builder.prefix(local.as_str(), value.as_str(), self);
-                        } else if local.as_str() == "xmlns" {
-                            builder.prefix("", value.as_str(), self);
-                        } else {
-                            builder.attribute(prefix, local, value)?;
-                        }
-                    }
-                    Text { text } => {
-                        let node_id = builder.text(&text, self)?;
-                        span_info.extend_text_span(node_id.into(), text.into());
-                    }
-                    Cdata { text, span: _ } => {
-                        let node_id = builder.cdata_text(text.as_str(), self)?;
-                        span_info.extend_text_span(node_id.into(), text.into());
-                    }
-                    ElementStart {
-                        prefix,
-                        local,
-                        span: _,
-                    } => {
-                        builder.element(prefix, local);
-                    }
+                let skipped = tokenizer.stream().slice_back(position);
+                builder.skipped_markup(&skipped[..token_span(&token).start() - position]);
+                self.parse_token(&mut builder, &mut span_info, token)?;
+                builder.report_progress(tokenizer.stream().pos())?;
+            } else {
+                return Ok((span_info, builder));
+            }
+        }
+    }
 
-                    ElementEnd {
-                        end,
-                        span: end_span,
-                    } => {
-                        use self::ElementEnd::*;
-
-                        match end {
-                            Open => {
-                                let (node_id, span, attribute_spans) =
-                                    builder.open_element(self)?;
-                                span_info.add(SpanInfoKey::ElementStart(node_id.into()), span);
-                                span_info.add_attribute_spans(node_id, attribute_spans);
-                            }
-                            Close(prefix, local) => {
-                                let node_id = builder.close_element(prefix, local, self)?;
-                                span_info
-                                    .add(SpanInfoKey::ElementEnd(node_id.into()), end_span.into());
-                            }
-                            Empty => {
-                                let (node_id, span, attribute_spans) =
-                                    builder.open_element(self)?;
-                                span_info.add(SpanInfoKey::ElementStart(node_id.into()), span);
-                                span_info.add_attribute_spans(node_id, attribute_spans);
-                                let node_id = builder.close_element_immediate(self);
+    pub(crate) fn parse_token(
+        &mut self,
+        builder: &mut DocumentBuilder,
+        span_info: &mut SpanInfo,
+        token: Token<'_>,
+    ) -> Result<(), ParseError> {
+        use Token::*;
+
+        match token {
+            Attribute {
+                prefix,
+                local,
+                value,
+                span,
+            } => {
+                let name_span = Span::from_prefix_name(prefix, local);
+                builder.check_attribute_limit(span.into())?;
+                builder.check_name_length(prefix.as_str(), local.as_str(), name_span)?;
+                if prefix.as_str() == "xmlns" {
+                    builder.prefix(
+                        local.as_str(),
+                        value.as_str(),
+                        name_span,
+                        value.into(),
+                        self,
+                    );
+                } else if local.as_str() == "xmlns" {
+                    builder.prefix("", value.as_str(), name_span, value.into(), self);
+                } else {
+                    builder.attribute(prefix, local, value)?;
+                }
+            }
+            Text { text } => {
+                self.parse_text_token(builder, span_info, text.as_str(), text.start())?;
+            }
+            Cdata { text, span } => {
+                let node_id = builder.cdata_text(text.as_str(), span.into(), self)?;
+                span_info.extend_text_span(node_id.into(), text.into());
+            }
+            ElementStart {
+                prefix,
+                local,
+                span: _,
+            } => {
+                builder.element(prefix, local);
+                builder.check_name_length(
+                    prefix.as_str(),
+                    local.as_str(),
+                    Span::from_prefix_name(prefix, local),
+                )?;
+            }
+
+            ElementEnd {
+                end,
+                span: end_span,
+            } => {
+                use self::ElementEnd::*;
+
+                match end {
+                    Open => {
+                        let (node_id, span, element_spans) = builder.open_element(self)?;
+                        span_info.add(SpanInfoKey::ElementStart(node_id.into()), span);
+                        span_info.extend(element_spans);
+                    }
+                    Close(prefix, local) => match builder.close_element(prefix, local, self) {
+                        Ok(node_id) => {
+                            span_info.add(SpanInfoKey::ElementEnd(node_id.into()), end_span.into());
+                        }
+                        Err(error) => {
+                            builder.recover(error)?;
+                            if let Some(node_id) =
+                                builder.close_element_recover(prefix, local, self)
+                            {
                                 span_info
                                     .add(SpanInfoKey::ElementEnd(node_id.into()), end_span.into());
                             }
                         }
+                    },
+                    Empty => {
+                        let (node_id, span, element_spans) = builder.open_element(self)?;
+                        span_info.add(SpanInfoKey::ElementStart(node_id.into()), span);
+                        span_info.extend(element_spans);
+                        let node_id = builder.close_element_immediate(self);
+                        span_info.add(SpanInfoKey::ElementEnd(node_id.into()), end_span.into());
                     }
-                    Comment { text, span: _ } => {
-                        let node_id = builder.comment(text.as_str(), self)?;
-                        span_info.add(SpanInfoKey::Comment(node_id.into()), text.into());
-                    }
-                    ProcessingInstruction {
-                        target,
-                        content,
-                        span: _,
-                    } => {
-                        let node_id = builder.processing_instruction(
-                            target.as_str(),
-                            content.map(|s| s.as_str()),
-                            self,
-                        )?;
-                        span_info.add(SpanInfoKey::PiTarget(node_id.into()), target.into());
-                        if let Some(content) = content {
-                            span_info.add(SpanInfoKey::PiContent(node_id.into()), content.into());
-                        }
-                    }
-                    Declaration { version, .. } => {
-                        if version.as_str() != "1.0" {
-                            return Err(ParseError::UnsupportedVersion(
-                                version.to_string(),
-                                version.into(),
-                            ));
-                        }
-                    }
-                    DtdStart { span, .. } => {
-                        return Err(ParseError::DtdUnsupported(span.into()));
-                    }
-                    DtdEnd { span, .. } => {
-                        return Err(ParseError::DtdUnsupported(span.into()));
-                    }
-                    EmptyDtd { span, .. } => {
-                        return Err(ParseError::DtdUnsupported(span.into()));
-                    }
-                    EntityDeclaration { span, .. } => {
-                        return Err(ParseError::DtdUnsupported(span.into()));
+                }
+            }
+            // the document node has no place for these
+            Comment { .. } | ProcessingInstruction { .. } if builder.in_dtd => {}
+            Comment { .. } if !builder.options.keep_comments => {}
+            ProcessingInstruction { .. } if !builder.options.keep_pis => {}
+            Comment { text, span } => {
+                let node_id = builder.comment(text.as_str(), span.into(), self)?;
+                span_info.add(SpanInfoKey::Comment(node_id.into()), text.into());
+            }
+            ProcessingInstruction {
+                target,
+                content,
+                span,
+            } => {
+                builder.check_name_length("", target.as_str(), target.into())?;
+                let node_id = builder.processing_instruction(
+                    target.as_str(),
+                    content.map(|s| s.as_str()),
+                    span.into(),
+                    self,
+                )?;
+                span_info.add(SpanInfoKey::PiTarget(node_id.into()), target.into());
+                if let Some(content) = content {
+                    span_info.add(SpanInfoKey::PiContent(node_id.into()), content.into());
+                }
+            }
+            Declaration {
+                version,
+                encoding,
+                standalone,
+                ..
+            } => {
+                match version.as_str() {
+                    "1.0" => {}
+                    "1.1" if builder.options.xml_1_1 => builder.xml_1_1 = true,
+                    _ => {
+                        builder.recover(ParseError::UnsupportedVersion(
+                            version.to_string(),
+                            version.into(),
+                        ))?;
                     }
                 }
-            } else {
-                return Ok((span_info, builder));
+                builder.declaration = Some(output::xml::Declaration {
+                    encoding: encoding.map(|encoding| encoding.to_string()),
+                    standalone,
+                    version: if builder.xml_1_1 {
+                        output::xml::Version::V1_1
+                    } else {
+                        output::xml::Version::V1_0
+                    },
+                });
+            }
+            DtdStart {
+                name, external_id, ..
+            } => {
+                builder.doctype = Some(Doctype::from_dtd(name, external_id));
+                builder.in_dtd = true;
+            }
+            DtdEnd { span } => {
+                builder.in_dtd = false;
+                builder.normalize_attribute_defaults(span.into())?;
+                builder.external_subset()?;
+            }
+            EmptyDtd {
+                name, external_id, ..
+            } => {
+                builder.doctype = Some(Doctype::from_dtd(name, external_id));
+                builder.external_subset()?;
+            }
+            EntityDeclaration {
+                name,
+                definition,
+                span,
+            } => {
+                let declaration = doctype::EntityDeclaration::from_dtd(name, definition, span)?;
+                builder.declare_entity(&declaration);
+                if let Some(doctype) = &mut builder.doctype {
+                    doctype.entities.push(declaration);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Text, with the references to entities we expand replaced by their
+    // content.
+    fn parse_text_token(
+        &mut self,
+        builder: &mut DocumentBuilder,
+        span_info: &mut SpanInfo,
+        mut text: &str,
+        mut position: usize,
+    ) -> Result<(), ParseError> {
+        while let Some((range, name)) = builder.content_reference(text) {
+            if range.start > 0 {
+                let node_id = builder.text(&text[..range.start], position, self)?;
+                span_info
+                    .extend_text_span(node_id.into(), Span::new(position, position + range.start));
+            }
+            let span = Span::new(position + range.start, position + range.end);
+            let depth = builder.expanding.len();
+            if builder.options.preserve_entity_references {
+                let entity_reference = EntityReference::new(name.to_string());
+                let node_id = builder.add(Value::EntityReference(entity_reference), span, self)?;
+                span_info.add(SpanInfoKey::EntityReference(node_id.into()), span);
+            } else if let Err(error) = self.expand_entity(builder, name, span) {
+                builder.expanding.truncate(depth);
+                builder.recover(error)?;
+            }
+            position += range.end;
+            text = &text[range.end..];
+        }
+        if !text.is_empty() {
+            let node_id = builder.text(text, position, self)?;
+            span_info.extend_text_span(node_id.into(), Span::new(position, position + text.len()));
+        }
+        Ok(())
+    }
+
+    // Parse the replacement text of an entity referred to in content, which
+    // may contain markup.
+    // https://www.w3.org/TR/xml/#included
+    fn expand_entity(
+        &mut self,
+        builder: &mut DocumentBuilder,
+        name: &str,
+        span: Span,
+    ) -> Result<(), ParseError> {
+        if builder.expanding.iter().any(|expanding| expanding == name) {
+            return Err(ParseError::RecursiveEntity(name.to_string(), span));
+        }
+        let replacement = builder.replacement_text(name, span)?;
+        builder.check_entity_limits(name, &replacement, builder.expanding.len(), span)?;
+        builder.expanding.push(name.to_string());
+        let current_node_id = builder.current_node_id;
+        // the replacement text isn't in the source, so we don't keep its
+        // spans, and report errors in it at the reference
+        let mut span_info = SpanInfo::new();
+        let mut tokenizer = Tokenizer::from_fragment(&replacement, 0..replacement.len());
+        loop {
+            let position = tokenizer.stream().pos();
+            match tokenizer.next() {
+                Some(Ok(token)) => self
+                    .parse_token(builder, &mut span_info, token)
+                    .map_err(|e| e.map_spans(|_| span))?,
+                Some(Err(e)) => return Err(ParseError::XmlParser(e, position).map_spans(|_| span)),
+                None => break,
             }
         }
+        // elements have to start and end in the same entity
+        if builder.current_node_id != current_node_id || builder.element_builder.is_some() {
+            return Err(ParseError::UnclosedTag(span));
+        }
+        builder.expanding.pop();
+        Ok(())
     }
 
     /// Parse a string containing XML into a document node.
@@ -810,6 +2288,30 @@ impl Xot {
         self.parse_with_span_info(xml).map(|(node, _)| node)
     }
 
+    /// Parse a string containing XML into a document node, taking ownership
+    /// of the string.
+    ///
+    /// This is like [`Xot::parse`], but text that's parsed without changes
+    /// refers to `xml` instead of getting its own copy. This saves memory
+    /// for a large document with a lot of text. The string is kept in memory
+    /// as long as any text that refers to it is, so a single text node that
+    /// outlives the rest of the document keeps all of the source alive.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let xml = String::from("<p>Hello</p>");
+    /// let document = xot.parse_string(xml)?;
+    /// let p = xot.document_element(document)?;
+    /// assert_eq!(xot.text_content_str(p), Some("Hello"));
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn parse_string(&mut self, xml: String) -> Result<Node, ParseError> {
+        self.parse_document(Cow::Owned(xml), ParseOptions::default())
+            .map(|(node, _)| node)
+    }
+
     /// Parse a string containing an XML fragment into a document node.
     ///
     /// This is similar to [`Xot::parse``], but it relaxes the well-formedness
@@ -826,12 +2328,166 @@ impl Xot {
             .map(|(node, _)| node)
     }
 
+    /// Parse a string containing an XML fragment into a document node, with
+    /// namespace prefixes in scope.
+    ///
+    /// This is like [`Xot::parse_fragment`], but the fragment can use the
+    /// prefixes in `namespaces`, given as pairs of prefix and namespace URI,
+    /// without declaring them. Use the empty prefix to give a default
+    /// namespace. This way you can parse a fragment that was taken from a
+    /// larger document, where these prefixes were declared by one of its
+    /// ancestors.
+    ///
+    /// So that the result can be serialized, the prefixes that are used are
+    /// declared on the top-level elements of the fragment.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse_fragment_with_context(
+    ///     "<x:item>one</x:item><x:item>two</x:item>",
+    ///     &[("x", "http://example.com")],
+    /// )?;
+    /// let item = xot.first_child(root).unwrap();
+    /// assert_eq!(
+    ///     xot.name_ns_str(xot.node_name(item).unwrap()),
+    ///     ("item", "http://example.com")
+    /// );
+    /// assert_eq!(
+    ///     xot.to_string(item)?,
+    ///     r#"<x:item xmlns:x="http://example.com">one</x:item>"#
+    /// );
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn parse_fragment_with_context(
+        &mut self,
+        xml: &str,
+        namespaces: &[(&str, &str)],
+    ) -> Result<Node, ParseError> {
+        let context = namespaces
+            .iter()
+            .map(|(prefix, uri)| (self.add_prefix(prefix), self.add_namespace(uri)))
+            .collect::<Namespaces>();
+        let (fragment, _) = self.parse_fragment_in_context(xml, context.clone())?;
+        let elements = self
+            .children(fragment)
+            .filter(|child| self.is_element(*child))
+            .collect::<Vec<_>>();
+        for element in elements {
+            let unresolved = self.unresolved_namespaces(element);
+            for (prefix, namespace) in &context {
+                if unresolved.contains(namespace) && !self.namespaces(element).contains_key(*prefix)
+                {
+                    self.set_namespace(element, *prefix, *namespace);
+                }
+            }
+        }
+        Ok(fragment)
+    }
+
+    /// Parse a string containing XML into a document node, recovering from
+    /// errors.
+    ///
+    /// Instead of failing at the first error, this repairs common defects
+    /// and goes on parsing, so it always produces a document. It's useful to
+    /// ingest XML that's not quite well-formed. The errors it recovered from
+    /// are returned along with the document; if there are none, the
+    /// document is the same as [`Xot::parse`] produces.
+    ///
+    /// It recovers like this:
+    ///
+    /// - Elements that are still open at the end are closed.
+    ///
+    /// - An end tag that doesn't match the current element closes the open
+    ///   element with its name and the elements in it. If no open element
+    ///   has its name, it's ignored.
+    ///
+    /// - A `&` that doesn't start a valid reference is taken literally.
+    ///
+    /// - An attribute value without quotes extends to the next whitespace or
+    ///   the end of the tag.
+    ///
+    /// - An undeclared prefix is declared on the element where it's used,
+    ///   with a namespace `urn:xot:undeclared:` followed by the prefix.
+    ///
+    /// - Of duplicate attributes, the first is kept.
+    ///
+    /// - Other markup that can't be parsed is taken as text.
+    ///
+    /// The document may have text, no element, or multiple elements at the
+    /// top level, which is also reported as an error.
+    ///
+    /// ```rust
+    /// use xot::{ParseError, Xot};
+    ///
+    /// let mut xot = Xot::new();
+    /// let (root, errors) = xot.parse_lenient("<p class=intro>Fish & chips<br></p>");
+    /// assert_eq!(
+    ///     xot.to_string(root)?,
+    ///     r#"<p class="intro">Fish &amp; chips<br/></p>"#
+    /// );
+    /// assert_eq!(errors.len(), 3);
+    /// assert!(matches!(errors[0], ParseError::XmlParser(..)));
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn parse_lenient(&mut self, xml: &str) -> (Node, Vec<ParseError>) {
+        parse_lenient(self, xml, ParseOptions::default())
+    }
+
+    /// Parse a string containing XML into a node, reporting all errors.
+    ///
+    /// Where [`Xot::parse`] stops at the first error, this goes on after
+    /// errors it can recover from in the same way as [`Xot::parse_lenient`],
+    /// so that you can report every problem in a document at once. The
+    /// errors are in the order they occur in the document.
+    ///
+    /// If there are no errors, this returns the document node. Otherwise the
+    /// document is discarded and this returns `None`.
+    ///
+    /// ```rust
+    /// use xot::{ParseError, Xot};
+    ///
+    /// let mut xot = Xot::new();
+    /// let (root, errors) = xot.parse_collect_errors("<doc><a b='1' b='2'/><x:c/></doc>");
+    /// assert!(root.is_none());
+    /// assert_eq!(errors.len(), 2);
+    /// assert!(matches!(errors[0], ParseError::DuplicateAttribute(..)));
+    /// assert!(matches!(errors[1], ParseError::UnknownPrefix(..)));
+    ///
+    /// let (root, errors) = xot.parse_collect_errors("<doc/>");
+    /// assert!(root.is_some());
+    /// assert!(errors.is_empty());
+    /// ```
+    pub fn parse_collect_errors(&mut self, xml: &str) -> (Option<Node>, Vec<ParseError>) {
+        let (document, errors) = parse_lenient(self, xml, ParseOptions::default());
+        if errors.is_empty() {
+            return (Some(document), errors);
+        }
+        self.remove_document(document);
+        (None, errors)
+    }
+
     /// Parse bytes containing XML into a node.
     ///
-    /// This attempts to decode the data in the bytes into a Rust string
-    /// (UTF-8) first, then parses this string.
+    /// This decodes the data in the bytes into a Rust string (UTF-8) first,
+    /// then parses this string. The encoding is determined by a byte order
+    /// mark, or otherwise by the `encoding` in the XML declaration. Without
+    /// either, the data has to be UTF-8. UTF-16 documents without byte order
+    /// mark are recognized by their XML declaration. All encodings supported
+    /// by [`encoding_rs`] can be used, such as ISO-8859-1, windows-1252 and
+    /// Shift_JIS. If the encoding isn't known, you get
+    /// [`ParseError::UnsupportedEncoding`].
+    ///
+    /// The encoding is recorded; you can obtain it with [`Xot::encoding`].
+    ///
+    /// The decoded string is the only copy of the source Xot makes: text
+    /// that's parsed without changes refers to it, like with
+    /// [`Xot::parse_string`]. The bytes themselves aren't kept, so you can
+    /// drop them once this returns.
     ///
-    /// If you already have a Rust string, use [`Xot::parse`].
+    /// If you already have a Rust string, use [`Xot::parse`], or
+    /// [`Xot::parse_string`] to hand it over.
     ///
     /// The returned node is the document node of the parsed XML document.
     ///
@@ -854,15 +2510,256 @@ impl Xot {
     /// let doc_el = xot.document_element(document)?;
     /// let txt_value = xot.text_content_str(doc_el).unwrap();
     /// assert_eq!(txt_value, "é");
+    /// assert_eq!(xot.encoding(document), Some("windows-1252"));
     /// # Ok::<(), xot::Error>(())
     /// ```
     pub fn parse_bytes(&mut self, bytes: &[u8]) -> Result<Node, ParseError> {
-        let xml = decode(bytes, None);
-        self.parse(&xml)
+        self.parse_bytes_with_options(bytes, &ParseOptions::default())
+    }
+
+    /// Parse bytes containing XML into a node, with options.
+    ///
+    /// This decodes the bytes like [`Xot::parse_bytes`] does, and parses them
+    /// like [`Xot::parse_with_options`].
+    ///
+    /// ```rust
+    /// use xot::{ParseError, ParseOptions, Xot};
+    ///
+    /// let mut xot = Xot::new();
+    /// let options = ParseOptions {
+    ///     reject_bom: true,
+    ///     ..Default::default()
+    /// };
+    /// let err = xot
+    ///     .parse_bytes_with_options(b"\xEF\xBB\xBF<doc/>", &options)
+    ///     .unwrap_err();
+    /// assert!(matches!(err, ParseError::UnexpectedByteOrderMark(_)));
+    /// ```
+    pub fn parse_bytes_with_options(
+        &mut self,
+        bytes: &[u8],
+        options: &ParseOptions,
+    ) -> Result<Node, ParseError> {
+        let bom = Encoding::for_bom(bytes).map(|(_, len)| len);
+        if let (Some(len), true) = (bom, options.reject_bom) {
+            return Err(ParseError::UnexpectedByteOrderMark(Span::new(0, len)));
+        }
+        let (xml, encoding, mut replacements) = if options.replace_invalid_characters {
+            decode_recording_replacements(bytes)?
+        } else {
+            let (xml, encoding) = decode(bytes)?;
+            (xml, encoding, Vec::new())
+        };
+        // we own the decoded text, so parsed text can refer to it
+        let (node, _) = self.parse_document(Cow::Owned(xml), options.clone())?;
+        self.encodings.insert(node.get(), encoding);
+        if bom.is_some() {
+            self.boms.insert(node.get());
+        }
+        // data that couldn't be decoded was replaced as well
+        if !replacements.is_empty() {
+            let positions = self.replaced_characters.entry(node.get()).or_default();
+            positions.append(&mut replacements);
+            positions.sort_unstable();
+        }
+        Ok(node)
+    }
+
+    /// Parse XML from a reader into a document node.
+    ///
+    /// The input is read in chunks and parsed as it comes in with an
+    /// [`XmlPushParser`](crate::XmlPushParser), so you don't have to read it
+    /// into a string first. The encoding is detected like
+    /// [`Xot::parse_bytes`] does.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let document = xot.parse_read(&b"<hello/>"[..])?;
+    /// assert_eq!(xot.to_string(document)?, "<hello/>");
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn parse_read(&mut self, mut reader: impl std::io::Read) -> Result<Node, crate::Error> {
+        let mut parser = XmlPushParser::new(self);
+        let mut buffer = vec![0; READ_BUFFER_SIZE];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => parser.feed(&buffer[..n])?,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(parser.finish()?)
+    }
+
+    /// Parse XML documents that follow each other in a reader.
+    ///
+    /// This is for streams that carry one document after another, such as
+    /// logs or protocols that send XML messages over a connection. Each
+    /// document is returned as soon as its document element is closed; the
+    /// input is read in chunks like [`Xot::parse_read`] does. Whitespace
+    /// between documents is ignored, and comments and processing
+    /// instructions between them belong to the document that follows, except
+    /// after the last document, where they're added to the end of it. A
+    /// document may start with its own XML declaration, but the encoding is
+    /// detected from the start of the stream only.
+    ///
+    /// Positions in parse errors are relative to the start of the document
+    /// they're in. After an error, the iterator doesn't return any more
+    /// documents.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let data = &b"<?xml version=\"1.0\"?><a>1</a>\n<b>2</b>\n"[..];
+    /// let documents = xot.parse_many(data).collect::<Result<Vec<_>, _>>()?;
+    /// assert_eq!(documents.len(), 2);
+    /// assert_eq!(xot.to_string(documents[0])?, "<a>1</a>");
+    /// assert_eq!(xot.to_string(documents[1])?, "<b>2</b>");
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn parse_many<'x, R: std::io::Read + 'x>(
+        &'x mut self,
+        reader: R,
+    ) -> impl Iterator<Item = Result<Node, crate::Error>> + 'x {
+        Documents::new(self, reader)
+    }
+
+    /// Parse XML from an async reader into a document node.
+    ///
+    /// This requires the `tokio` feature.
+    ///
+    /// This is the async version of [`Xot::parse_read`]: the input is parsed
+    /// in chunks as it comes in, so you can parse a request body without
+    /// blocking or collecting it first.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// # let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    /// # runtime.block_on(async {
+    /// let mut xot = Xot::new();
+    /// let document = xot.parse_async(&b"<hello/>"[..]).await?;
+    /// assert_eq!(xot.to_string(document)?, "<hello/>");
+    /// # Ok::<(), xot::Error>(())
+    /// # })?;
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub async fn parse_async(
+        &mut self,
+        mut reader: impl tokio::io::AsyncRead + Unpin,
+    ) -> Result<Node, crate::Error> {
+        use tokio::io::AsyncReadExt;
+
+        let mut parser = XmlPushParser::new(self);
+        let mut buffer = vec![0; READ_BUFFER_SIZE];
+        loop {
+            let n = reader.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            parser.feed(&buffer[..n])?;
+        }
+        Ok(parser.finish()?)
+    }
+
+    /// Parse an XML file into a document node.
+    ///
+    /// See [`Xot::parse_read`].
+    pub fn parse_file(&mut self, path: impl AsRef<std::path::Path>) -> Result<Node, crate::Error> {
+        self.parse_read(std::fs::File::open(path)?)
+    }
+}
+
+pub(crate) fn token_span<'a>(token: &Token<'a>) -> StrSpan<'a> {
+    match token {
+        Token::Declaration { span, .. }
+        | Token::ProcessingInstruction { span, .. }
+        | Token::Comment { span, .. }
+        | Token::DtdStart { span, .. }
+        | Token::EmptyDtd { span, .. }
+        | Token::EntityDeclaration { span, .. }
+        | Token::DtdEnd { span }
+        | Token::ElementStart { span, .. }
+        | Token::Attribute { span, .. }
+        | Token::ElementEnd { span, .. }
+        | Token::Cdata { span, .. } => *span,
+        Token::Text { text } => *text,
+    }
+}
+
+// The first reference `&name;` in text for which `is_reference(name)` holds.
+fn find_reference(text: &str, is_reference: impl Fn(&str) -> bool) -> Option<(Range<usize>, &str)> {
+    let mut start = 0;
+    while let Some(i) = text[start..].find('&') {
+        let begin = start + i;
+        let end = begin + text[begin..].find(';')?;
+        let name = &text[begin + 1..end];
+        if is_reference(name) {
+            return Some((begin..end + 1, name));
+        }
+        start = begin + 1;
+    }
+    None
+}
+
+// The entities that are always defined.
+// https://www.w3.org/TR/xml/#sec-predefined-ent
+fn is_predefined_entity(name: &str) -> bool {
+    matches!(name, "amp" | "lt" | "gt" | "apos" | "quot")
+}
+
+// Replace the characters that aren't allowed in XML 1.0 by U+FFFD. If there
+// are any, this returns the text with replacements and the positions of the
+// characters we replaced.
+fn replace_invalid_characters(xml: &str) -> Option<(String, Vec<usize>)> {
+    let mut replaced = String::new();
+    let mut positions = Vec::new();
+    let mut start = 0;
+    for (position, c) in xml.char_indices() {
+        if !is_char(c, false) {
+            replaced.push_str(&xml[start..position]);
+            replaced.push('\u{FFFD}');
+            positions.push(position);
+            start = position + c.len_utf8();
+        }
+    }
+    if positions.is_empty() {
+        return None;
+    }
+    replaced.push_str(&xml[start..]);
+    Some((replaced, positions))
+}
+
+// For each replacement, where it ends in the text with replacements, and
+// how much longer that text is than the source text up to there.
+fn source_shifts(xml: &str, positions: &[usize]) -> Vec<(usize, usize)> {
+    let mut shift = 0;
+    positions
+        .iter()
+        .map(|&replaced| {
+            let len = xml[replaced..].chars().next().unwrap().len_utf8();
+            let end = replaced + shift + '\u{FFFD}'.len_utf8();
+            shift += '\u{FFFD}'.len_utf8() - len;
+            (end, shift)
+        })
+        .collect()
+}
+
+// Map a position in the text with replacements back to the source text.
+fn source_position(shifts: &[(usize, usize)], position: usize) -> usize {
+    let replaced = shifts.partition_point(|(end, _)| *end <= position);
+    match replaced {
+        0 => position,
+        replaced => position - shifts[replaced - 1].1,
     }
 }
 
-fn normalize_xml_id(value: &str) -> String {
+pub(crate) fn normalize_xml_id(value: &str) -> String {
     // strip both leading and trailing space characters
     let value = value.strip_prefix(' ').unwrap_or(value);
     let value = value.strip_suffix(' ').unwrap_or(value);
@@ -0,0 +1,245 @@
+use crate::entity::parse_text;
+use crate::error::Error;
+use crate::xotdata::{Node, Xot};
+
+/// The pseudo-attributes in the data of a processing instruction.
+///
+/// Processing instructions such as `xml-stylesheet` and `xml-model` have
+/// data that looks like attributes: `href="style.css" type="text/css"`. See
+/// [the `xml-stylesheet`
+/// specification](https://www.w3.org/TR/xml-stylesheet/#NT-PseudoAtt) for
+/// their syntax.
+///
+/// You get these from a processing instruction with
+/// [`Xot::pi_pseudo_attributes`]. You can also build them up with
+/// [`PseudoAttributes::with`] and turn them into data for a new processing
+/// instruction:
+///
+/// ```rust
+/// use xot::{PseudoAttributes, Xot};
+///
+/// let mut xot = Xot::new();
+/// let data = PseudoAttributes::new()
+///     .with("href", "style.css")
+///     .with("title", r#"The "default" style"#)
+///     .to_data();
+/// let target = xot.add_name("xml-stylesheet");
+/// let pi = xot.new_processing_instruction(target, Some(&data));
+/// assert_eq!(
+///     xot.to_string(pi)?,
+///     r#"<?xml-stylesheet href="style.css" title="The &quot;default&quot; style"?>"#
+/// );
+/// # Ok::<(), xot::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PseudoAttributes {
+    attributes: Vec<(String, String)>,
+}
+
+impl PseudoAttributes {
+    /// Create an empty list of pseudo-attributes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the data of a processing instruction into pseudo-attributes.
+    ///
+    /// Character references and references to the predefined entities such
+    /// as `&quot;` in values are expanded. If the data doesn't consist of
+    /// pseudo-attributes, or a name occurs more than once, this is
+    /// [`Error::InvalidPseudoAttributes`].
+    pub fn parse(data: &str) -> Result<Self, Error> {
+        let invalid = |message: &str| Error::InvalidPseudoAttributes(message.to_string());
+        let mut attributes: Vec<(String, String)> = Vec::new();
+        let mut rest = data.trim_start();
+        while !rest.is_empty() {
+            let end = rest
+                .find(|c: char| c == '=' || c.is_ascii_whitespace())
+                .unwrap_or(rest.len());
+            let name = &rest[..end];
+            if name.is_empty() {
+                return Err(invalid("missing name"));
+            }
+            rest = rest[end..]
+                .trim_start()
+                .strip_prefix('=')
+                .ok_or_else(|| invalid("missing ="))?
+                .trim_start();
+            let quote = rest
+                .chars()
+                .next()
+                .filter(|c| *c == '"' || *c == '\'')
+                .ok_or_else(|| invalid("value isn't quoted"))?;
+            let value_end = rest[1..]
+                .find(quote)
+                .ok_or_else(|| invalid("unclosed value"))?
+                + 1;
+            let value = &rest[1..value_end];
+            if value.contains('<') {
+                return Err(invalid("< in value"));
+            }
+            let value = parse_text(value.into(), 0, false).map_err(|e| invalid(&e.to_string()))?;
+            if attributes.iter().any(|(n, _)| n == name) {
+                return Err(invalid(&format!("duplicate name {}", name)));
+            }
+            attributes.push((name.to_string(), value.into_owned()));
+            rest = &rest[value_end + 1..];
+            if !rest.is_empty() && !rest.starts_with(|c: char| c.is_ascii_whitespace()) {
+                return Err(invalid("missing whitespace between pseudo-attributes"));
+            }
+            rest = rest.trim_start();
+        }
+        Ok(Self { attributes })
+    }
+
+    /// Add a pseudo-attribute.
+    ///
+    /// If there already is a pseudo-attribute with this name, its value is
+    /// replaced.
+    pub fn with(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into();
+        let value = value.into();
+        match self.attributes.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => *v = value,
+            None => self.attributes.push((name, value)),
+        }
+        self
+    }
+
+    /// Get the value of a pseudo-attribute.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Iterate over the names and values of the pseudo-attributes, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.attributes
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// The number of pseudo-attributes.
+    pub fn len(&self) -> usize {
+        self.attributes.len()
+    }
+
+    /// Whether there are no pseudo-attributes.
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty()
+    }
+
+    /// Serialize the pseudo-attributes as data for a processing instruction.
+    ///
+    /// Values are quoted with `"`. The characters `&`, `<`, `>` and `"` are
+    /// escaped, so a value can't end the processing instruction.
+    pub fn to_data(&self) -> String {
+        let mut data = String::new();
+        for (name, value) in &self.attributes {
+            if !data.is_empty() {
+                data.push(' ');
+            }
+            data.push_str(name);
+            data.push_str("=\"");
+            for c in value.chars() {
+                match c {
+                    '&' => data.push_str("&amp;"),
+                    '<' => data.push_str("&lt;"),
+                    '>' => data.push_str("&gt;"),
+                    '"' => data.push_str("&quot;"),
+                    c => data.push(c),
+                }
+            }
+            data.push('"');
+        }
+        data
+    }
+}
+
+/// ## Processing instructions
+impl Xot {
+    /// Parse the data of a processing instruction into pseudo-attributes.
+    ///
+    /// A processing instruction without data has no pseudo-attributes. If
+    /// the node isn't a processing instruction, this is
+    /// [`Error::NotProcessingInstruction`]. See [`PseudoAttributes::parse`]
+    /// for how the data is parsed.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<?xml-stylesheet type="text/xsl" href='style.xsl'?><doc/>"#)?;
+    /// let pi = xot.first_child(root).unwrap();
+    /// let attributes = xot.pi_pseudo_attributes(pi)?;
+    /// assert_eq!(attributes.get("href"), Some("style.xsl"));
+    /// assert_eq!(
+    ///     attributes.iter().collect::<Vec<_>>(),
+    ///     vec![("type", "text/xsl"), ("href", "style.xsl")]
+    /// );
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn pi_pseudo_attributes(&self, node: Node) -> Result<PseudoAttributes, Error> {
+        let pi = self
+            .processing_instruction(node)
+            .ok_or(Error::NotProcessingInstruction(node))?;
+        PseudoAttributes::parse(pi.data().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let attributes =
+            PseudoAttributes::parse(" a = 'x \"y\"'\tb=\"&lt;&#65;&apos;\" c=''  ").unwrap();
+        assert_eq!(
+            attributes.iter().collect::<Vec<_>>(),
+            vec![("a", "x \"y\""), ("b", "<A'"), ("c", "")]
+        );
+        assert!(PseudoAttributes::parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for data in [
+            "a",
+            "a=b",
+            "=\"b\"",
+            "a=\"b",
+            "a=\"b\"c=\"d\"",
+            "a=\"<\"",
+            "a=\"&foo;\"",
+            "a=\"b\" a=\"c\"",
+        ] {
+            assert!(
+                matches!(
+                    PseudoAttributes::parse(data),
+                    Err(Error::InvalidPseudoAttributes(_))
+                ),
+                "{}",
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let attributes = PseudoAttributes::new()
+            .with("a", "1")
+            .with("b", "<?x?> & \"'")
+            .with("a", "2");
+        assert_eq!(
+            attributes.to_data(),
+            "a=\"2\" b=\"&lt;?x?&gt; &amp; &quot;'\""
+        );
+        assert_eq!(
+            PseudoAttributes::parse(&attributes.to_data()).unwrap(),
+            attributes
+        );
+    }
+}
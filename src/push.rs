@@ -0,0 +1,497 @@
+use std::io::Read;
+
+use encoding_rs::{Decoder, Encoding};
+use xmlparser::{Token, Tokenizer};
+
+use crate::encoding::xml_encoding;
+use crate::error::{Error, ParseError};
+use crate::parse::{token_span, DocumentBuilder, ParseOptions, SpanInfo, READ_BUFFER_SIZE};
+use crate::xotdata::{Node, Xot};
+
+/// A parser for XML that arrives in chunks.
+///
+/// You [`feed`](XmlPushParser::feed) it bytes as they come in, for instance
+/// from a socket, and call [`finish`](XmlPushParser::finish) at the end to
+/// obtain the document node. The tree is constructed as markup completes, so
+/// only incomplete markup at the end of the data fed so far is kept in a
+/// buffer.
+///
+/// The encoding is detected from the start of the data in the same way as
+/// [`Xot::parse_bytes`] does.
+///
+/// The resulting document is the same as if you had parsed all data with
+/// [`Xot::parse_bytes`]. Errors in the tree, such as an unknown namespace
+/// prefix or a mismatched end tag, are reported by `feed` as soon as they
+/// are found. Syntax errors are only reported by `finish`, as until then it's
+/// possible the markup isn't complete yet. After an error you shouldn't use
+/// the parser anymore.
+///
+/// ```rust
+/// use xot::{Xot, XmlPushParser};
+///
+/// let mut xot = Xot::new();
+/// let mut parser = XmlPushParser::new(&mut xot);
+/// parser.feed(b"<doc><p>Hel")?;
+/// parser.feed(b"lo</p><p/")?;
+/// parser.feed(b"></doc>")?;
+/// let root = parser.finish()?;
+/// assert_eq!(xot.to_string(root)?, "<doc><p>Hello</p><p/></doc>");
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub struct XmlPushParser<'x> {
+    xot: &'x mut Xot,
+    builder: DocumentBuilder,
+    span_info: SpanInfo,
+    // bytes we haven't decoded yet, as we don't know the encoding
+    undecoded: Vec<u8>,
+    decoder: Option<Decoder>,
+    // whether the document we're parsing started with a byte order mark
+    bom: bool,
+    // decoded text that we haven't parsed yet
+    text: String,
+    // the position of `text` in the complete source text
+    offset: usize,
+    // whether we stop once the document element is closed, as another
+    // document follows
+    many: bool,
+}
+
+impl<'x> XmlPushParser<'x> {
+    /// Create a new push parser that constructs a document in `xot`.
+    pub fn new(xot: &'x mut Xot) -> Self {
+        let builder = DocumentBuilder::new(xot, ParseOptions::default());
+        XmlPushParser {
+            xot,
+            builder,
+            span_info: SpanInfo::new(),
+            undecoded: Vec::new(),
+            decoder: None,
+            bom: false,
+            text: String::new(),
+            offset: 0,
+            many: false,
+        }
+    }
+
+    /// Feed the next chunk of XML data to the parser.
+    ///
+    /// This parses all complete markup.
+    pub fn feed(&mut self, data: &[u8]) -> Result<(), ParseError> {
+        self.decode(data, false)?;
+        // everything up to the last `>` may be complete markup. If it's not,
+        // tokenizing it fails and we try again when we have more data.
+        if let Some(end) = self.text.rfind('>') {
+            self.parse_text(end + 1, false)?;
+        }
+        Ok(())
+    }
+
+    /// Finish parsing and return the document node.
+    pub fn finish(mut self) -> Result<Node, ParseError> {
+        self.decode(&[], true)?;
+        self.parse_text(self.text.len(), true)?;
+        let len = self.offset + self.text.len();
+        let (node, _) = self
+            .xot
+            .finish_document(&mut self.builder, self.span_info, len)?;
+        if let Some(decoder) = self.decoder {
+            self.xot.encodings.insert(node.get(), decoder.encoding());
+        }
+        if self.bom {
+            self.xot.boms.insert(node.get());
+        }
+        Ok(node)
+    }
+
+    // Finish the document we're parsing, and start the next one with the
+    // text that's left.
+    fn finish_document(&mut self, len: usize) -> Result<Node, ParseError> {
+        let next_builder = DocumentBuilder::new(self.xot, ParseOptions::default());
+        let mut builder = std::mem::replace(&mut self.builder, next_builder);
+        let span_info = std::mem::replace(&mut self.span_info, SpanInfo::new());
+        self.offset = 0;
+        let (node, _) = self.xot.finish_document(&mut builder, span_info, len)?;
+        if let Some(decoder) = &self.decoder {
+            self.xot.encodings.insert(node.get(), decoder.encoding());
+        }
+        // only the first document can start with a byte order mark
+        if std::mem::take(&mut self.bom) {
+            self.xot.boms.insert(node.get());
+        }
+        Ok(node)
+    }
+
+    fn decode(&mut self, data: &[u8], last: bool) -> Result<(), ParseError> {
+        let decoder = match &mut self.decoder {
+            Some(decoder) => decoder,
+            None => {
+                self.undecoded.extend_from_slice(data);
+                // wait until we can see the XML declaration, if any
+                if !last && !self.undecoded.contains(&b'>') {
+                    return Ok(());
+                }
+                self.bom = Encoding::for_bom(&self.undecoded).is_some();
+                let decoder = xml_encoding(&self.undecoded)?.new_decoder_with_bom_removal();
+                let decoder = self.decoder.insert(decoder);
+                let data = std::mem::take(&mut self.undecoded);
+                decode_into(decoder, &data, &mut self.text, last);
+                return Ok(());
+            }
+        };
+        decode_into(decoder, data, &mut self.text, last);
+        Ok(())
+    }
+
+    // Parse `text` up to `end`, and remove what we parsed from it. Unless
+    // `last` is set, we stop at the first syntax error and keep the rest
+    // for later.
+    fn parse_text(&mut self, mut end: usize, last: bool) -> Result<(), ParseError> {
+        // whitespace between documents isn't part of the next one, which
+        // may start with an XML declaration
+        if self.many && self.offset == 0 {
+            let whitespace = self.text[..end].len()
+                - self.text[..end]
+                    .trim_start_matches(|c: char| c.is_ascii_whitespace())
+                    .len();
+            self.text.drain(..whitespace);
+            end -= whitespace;
+        }
+        // until we've parsed something, we're in the prolog, where we allow
+        // an XML declaration and a doctype
+        let mut prolog = self.offset == 0;
+        let mut tokenizer = if prolog {
+            Tokenizer::from(&self.text[..end])
+        } else {
+            Tokenizer::from_fragment(&self.text, 0..end)
+        };
+        let mut span_info = SpanInfo::new();
+        let mut parsed = 0;
+        // tokens we can only parse when they're complete: the prolog, which
+        // we have to tokenize from the start, and the start tag we're in.
+        // We keep the markup skipped before each token with it.
+        let mut pending = Vec::new();
+        loop {
+            let position = tokenizer.stream().pos();
+            let token = match tokenizer.next() {
+                Some(Ok(token)) => token,
+                Some(Err(e)) if last => {
+                    return Err(ParseError::XmlParser(e, position).with_offset(self.offset))
+                }
+                Some(Err(_)) | None => break,
+            };
+            let skipped = &self.text[position..token_span(&token).start()];
+            match token {
+                Token::ElementStart { .. } => {
+                    prolog = false;
+                    pending.push((token, skipped));
+                    continue;
+                }
+                Token::Attribute { .. } => {
+                    pending.push((token, skipped));
+                    continue;
+                }
+                // whitespace between the top-level nodes isn't text
+                Token::Text { text }
+                    if self.builder.is_current_node_document(self.xot)
+                        && text.as_str().chars().all(|c| c.is_ascii_whitespace()) =>
+                {
+                    parsed = text.end();
+                    continue;
+                }
+                _ if prolog => {
+                    pending.push((token, skipped));
+                    continue;
+                }
+                _ => {}
+            }
+            let end = token_span(&token).end();
+            pending.push((token, skipped));
+            self.xot
+                .parse_tokens(&mut self.builder, &mut span_info, pending.drain(..))
+                .map_err(|e| e.with_offset(self.offset))?;
+            parsed = end;
+            // what follows belongs to the next document
+            if self.many && self.builder.is_document_element_closed(self.xot) {
+                break;
+            }
+        }
+        // there's nothing but the prolog, which we won't get to complete
+        if last {
+            self.xot
+                .parse_tokens(&mut self.builder, &mut span_info, pending)
+                .map_err(|e| e.with_offset(self.offset))?;
+        }
+        self.span_info.extend_with_offset(span_info, self.offset);
+        self.text.drain(..parsed);
+        self.offset += parsed;
+        Ok(())
+    }
+}
+
+// Parses documents that follow each other in a reader. See
+// `Xot::parse_many`.
+pub(crate) struct Documents<'x, R> {
+    parser: XmlPushParser<'x>,
+    reader: R,
+    buffer: Vec<u8>,
+    // whether we've read all data
+    eof: bool,
+    // whether there may be markup we've read but not parsed yet
+    unparsed: bool,
+    // whether we're done, after the last document or an error
+    done: bool,
+    // the last document we returned
+    last: Option<Node>,
+}
+
+impl<'x, R: Read> Documents<'x, R> {
+    pub(crate) fn new(xot: &'x mut Xot, reader: R) -> Self {
+        let mut parser = XmlPushParser::new(xot);
+        parser.many = true;
+        Documents {
+            parser,
+            reader,
+            buffer: vec![0; READ_BUFFER_SIZE],
+            eof: false,
+            unparsed: false,
+            done: false,
+            last: None,
+        }
+    }
+
+    fn next_document(&mut self) -> Result<Option<Node>, Error> {
+        let parser = &mut self.parser;
+        loop {
+            if parser.builder.is_document_element_closed(parser.xot) {
+                self.unparsed = true;
+                let len = parser.offset;
+                let document = parser.finish_document(len)?;
+                self.last = Some(document);
+                return Ok(Some(document));
+            }
+            if self.unparsed {
+                self.unparsed = false;
+                if self.eof {
+                    parser.parse_text(parser.text.len(), true)?;
+                } else {
+                    parser.feed(&[])?;
+                }
+                continue;
+            }
+            if self.eof {
+                // there's nothing but whitespace after the last document
+                if parser.builder.is_empty(parser.xot) {
+                    parser.builder.discard(parser.xot);
+                    return Ok(None);
+                }
+                // comments and processing instructions after the last
+                // document are its epilog
+                if let Some(last) = self.last {
+                    if parser.builder.is_epilog(parser.xot) {
+                        parser.builder.move_to(parser.xot, last)?;
+                        parser.builder.discard(parser.xot);
+                        return Ok(None);
+                    }
+                }
+                let len = parser.offset + parser.text.len();
+                let node = parser.finish_document(len)?;
+                parser.builder.discard(parser.xot);
+                return Ok(Some(node));
+            }
+            match self.reader.read(&mut self.buffer) {
+                Ok(0) => {
+                    self.eof = true;
+                    self.unparsed = true;
+                    parser.decode(&[], true)?;
+                }
+                Ok(n) => parser.feed(&self.buffer[..n])?,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for Documents<'_, R> {
+    type Item = Result<Node, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_document().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+impl Xot {
+    fn parse_tokens<'a>(
+        &mut self,
+        builder: &mut DocumentBuilder,
+        span_info: &mut SpanInfo,
+        tokens: impl IntoIterator<Item = (Token<'a>, &'a str)>,
+    ) -> Result<(), ParseError> {
+        for (token, skipped) in tokens {
+            builder.skipped_markup(skipped);
+            self.parse_token(builder, span_info, token)?;
+        }
+        Ok(())
+    }
+}
+
+fn decode_into(decoder: &mut Decoder, data: &[u8], text: &mut String, last: bool) {
+    if let Some(len) = decoder.max_utf8_buffer_length(data.len()) {
+        text.reserve(len);
+    }
+    // with enough room reserved, this decodes all of the data
+    let _ = decoder.decode_to_string(data, text, last);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    fn push_parse(xot: &mut Xot, xml: &[u8], chunk_size: usize) -> Result<Node, ParseError> {
+        let mut parser = XmlPushParser::new(xot);
+        for chunk in xml.chunks(chunk_size) {
+            parser.feed(chunk)?;
+        }
+        parser.finish()
+    }
+
+    #[rstest]
+    fn test_push_parse_chunks(#[values(1, 2, 3, 7, 1000)] chunk_size: usize) {
+        let xml = r#"<?xml version="1.0"?>
+<!-- before -->
+<x:doc xmlns:x="http://example.com" a="1 &gt; 0">
+  <x:p>Caf&#233; &amp; <![CDATA[<tea>]]> é</x:p>
+  <?pi content > more?>
+  <empty b="c>d"/>
+</x:doc>
+<!-- after -->
+"#;
+        let mut xot = Xot::new();
+        let expected = xot.parse(xml).unwrap();
+        let root = push_parse(&mut xot, xml.as_bytes(), chunk_size).unwrap();
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            xot.to_string(expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_push_parse_encoding() {
+        let mut xot = Xot::new();
+        let root = push_parse(
+            &mut xot,
+            b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><p>\xe9</p>",
+            3,
+        )
+        .unwrap();
+        let p = xot.document_element(root).unwrap();
+        assert_eq!(xot.text_content_str(p), Some("é"));
+    }
+
+    #[rstest]
+    fn test_push_parse_errors(#[values(1, 4, 1000)] chunk_size: usize) {
+        let mut xot = Xot::new();
+        let err = push_parse(&mut xot, b"<doc><a></b></doc>", chunk_size).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidCloseTag(_, _, span) if span.range() == (10..11)));
+        let err = push_parse(&mut xot, b"<doc><a>", chunk_size).unwrap_err();
+        assert!(matches!(err, ParseError::UnclosedTag(span) if span.range() == (6..7)));
+        let err = push_parse(&mut xot, b"<doc/><doc/>", chunk_size).unwrap_err();
+        assert!(matches!(err, ParseError::MultipleElementsAtTopLevel(_)));
+        let err = push_parse(&mut xot, b"<doc><a b='x</doc>", chunk_size).unwrap_err();
+        assert!(matches!(err, ParseError::XmlParser(..)));
+        let err = push_parse(&mut xot, b"", chunk_size).unwrap_err();
+        assert!(matches!(err, ParseError::NoElementAtTopLevel(0)));
+    }
+
+    // A reader that returns data in chunks of at most `chunk_size` bytes.
+    struct ChunkReader<'a> {
+        data: &'a [u8],
+        chunk_size: usize,
+    }
+
+    impl Read for ChunkReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.data.len().min(self.chunk_size).min(buf.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    fn parse_many(xot: &mut Xot, data: &[u8], chunk_size: usize) -> Vec<Result<String, Error>> {
+        let reader = ChunkReader { data, chunk_size };
+        let nodes = xot.parse_many(reader).collect::<Vec<_>>();
+        nodes
+            .into_iter()
+            .map(|node| node.and_then(|node| xot.to_string(node)))
+            .collect()
+    }
+
+    #[rstest]
+    fn test_parse_many(#[values(1, 2, 3, 7, 1000)] chunk_size: usize) {
+        let mut xot = Xot::new();
+        let data = b"<?xml version=\"1.0\"?>\n<a>1</a>\n<!--c--><b x='>'/>  <?xml version=\"1.0\"?><c><d/></c>\n";
+        let documents = parse_many(&mut xot, data, chunk_size)
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            documents,
+            vec!["<a>1</a>", r#"<!--c--><b x=">"/>"#, "<c><d/></c>"]
+        );
+        assert!(parse_many(&mut xot, b"", chunk_size).is_empty());
+        assert!(parse_many(&mut xot, b" \n ", chunk_size).is_empty());
+    }
+
+    #[rstest]
+    fn test_parse_many_epilog(#[values(1, 2, 3, 7, 1000)] chunk_size: usize) {
+        let mut xot = Xot::new();
+        let documents = parse_many(&mut xot, b"<r/><!--end-->", chunk_size)
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(documents, vec!["<r/><!--end-->"]);
+        let documents = parse_many(
+            &mut xot,
+            b"<a/>\n<!--b--><b/>\n<!--x--><?pi?>\n",
+            chunk_size,
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        assert_eq!(documents, vec!["<a/>", "<!--b--><b/><!--x--><?pi?>"]);
+        // without a document before it, it's still an error
+        let results = parse_many(&mut xot, b"<!--only-->", chunk_size);
+        assert!(matches!(
+            &results[..],
+            [Err(Error::Parse(ParseError::NoElementAtTopLevel(_)))]
+        ));
+    }
+
+    #[rstest]
+    fn test_parse_many_error(#[values(1, 4, 1000)] chunk_size: usize) {
+        let mut xot = Xot::new();
+        let results = parse_many(&mut xot, b"<a/>\n<b><c></b><d/>", chunk_size);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap(), "<a/>");
+        assert!(matches!(
+            &results[1],
+            Err(Error::Parse(ParseError::InvalidCloseTag(_, _, span))) if span.range() == (8..9)
+        ));
+        let results = parse_many(&mut xot, b"<a/><b>", chunk_size);
+        assert_eq!(results.len(), 2);
+        assert!(matches!(
+            &results[1],
+            Err(Error::Parse(ParseError::UnclosedTag(span))) if span.range() == (1..2)
+        ));
+    }
+}
@@ -0,0 +1,238 @@
+use ahash::{HashSet, HashSetExt};
+
+use crate::id::NameId;
+use crate::xotdata::{Node, Xot};
+
+/// A chainable query over nodes.
+///
+/// You start a query with [`Xot::query`], and then chain steps that go to
+/// other nodes, such as [`Query::descendants`], and filters, such as
+/// [`Query::name`]. Each step wraps the iterator of the previous one, so
+/// nothing is evaluated or allocated until you ask for nodes.
+///
+/// A query is an iterator over the nodes it finds. A step is applied to each
+/// node in turn, so it never gives a node twice, but if the nodes are nested,
+/// the result of [`Query::children`] or [`Query::attributes`] isn't in
+/// document order.
+///
+/// ```rust
+/// use xot::Xot;
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse(r#"<list><item id="6">A</item><item id="7">B</item></list>"#)?;
+/// let item = xot.name("item").unwrap();
+/// let id = xot.name("id").unwrap();
+///
+/// let found = xot.query(root).descendants().name(item).attr_eq(id, "7").first();
+/// assert_eq!(xot.text_content_str(found.unwrap()), Some("B"));
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub struct Query<'a> {
+    xot: &'a Xot,
+    nodes: Box<dyn Iterator<Item = Node> + 'a>,
+}
+
+impl<'a> Query<'a> {
+    fn new(xot: &'a Xot, nodes: impl Iterator<Item = Node> + 'a) -> Self {
+        Query {
+            xot,
+            nodes: Box::new(nodes),
+        }
+    }
+
+    /// Go to the children of each node.
+    pub fn children(self) -> Self {
+        let Query { xot, nodes } = self;
+        Query::new(xot, nodes.flat_map(move |node| xot.children(node)))
+    }
+
+    /// Go to the descendants of each node, not including the node itself.
+    ///
+    /// If a node is a descendant of a node before it, it's skipped, as its
+    /// descendants have already been found.
+    pub fn descendants(self) -> Self {
+        let Query { xot, nodes } = self;
+        // the nodes whose descendants we've gone to. A step never gives a
+        // node after one of its descendants, so this is all we need to check
+        let mut expanded: HashSet<Node> = HashSet::new();
+        let nodes = nodes
+            .filter(move |node| {
+                if xot
+                    .ancestors(*node)
+                    .any(|ancestor| expanded.contains(&ancestor))
+                {
+                    return false;
+                }
+                expanded.insert(*node);
+                true
+            })
+            .flat_map(move |node| xot.descendants(node).skip(1));
+        Query::new(xot, nodes)
+    }
+
+    /// Go to the attribute nodes of each node.
+    pub fn attributes(self) -> Self {
+        let Query { xot, nodes } = self;
+        Query::new(xot, nodes.flat_map(move |node| xot.attribute_nodes(node)))
+    }
+
+    /// Keep only elements.
+    pub fn elements(self) -> Self {
+        let xot = self.xot;
+        self.filter(move |node| xot.is_element(node))
+    }
+
+    /// Keep only elements and attribute nodes with this name.
+    ///
+    /// To look up the name by its local name and namespace, use
+    /// [`Xot::name_ns`].
+    pub fn name(self, name: NameId) -> Self {
+        let xot = self.xot;
+        self.filter(move |node| {
+            (xot.is_element(node) || xot.is_attribute_node(node))
+                && xot.node_name(node) == Some(name)
+        })
+    }
+
+    /// Keep only elements that have an attribute with this name.
+    pub fn has_attr(self, name: NameId) -> Self {
+        let xot = self.xot;
+        self.filter(move |node| xot.get_attribute(node, name).is_some())
+    }
+
+    /// Keep only elements that have an attribute with this name and value.
+    pub fn attr_eq(self, name: NameId, value: &str) -> Self {
+        let xot = self.xot;
+        let value = value.to_string();
+        self.filter(move |node| xot.get_attribute(node, name) == Some(value.as_str()))
+    }
+
+    /// Keep only the nodes for which `predicate` returns `true`.
+    pub fn filter(self, predicate: impl Fn(Node) -> bool + 'a) -> Self {
+        let Query { xot, nodes } = self;
+        Query::new(xot, nodes.filter(move |node| predicate(*node)))
+    }
+
+    /// The first node the query finds, if any.
+    ///
+    /// This stops the query as soon as the node is found.
+    pub fn first(mut self) -> Option<Node> {
+        self.nodes.next()
+    }
+}
+
+impl Iterator for Query<'_> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nodes.next()
+    }
+}
+
+/// ## Queries
+impl Xot {
+    /// Start a chainable query at `node`.
+    ///
+    /// See [`Query`] for the steps and filters you can chain.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<doc><sec><p/><p class="x"/></sec><p class="x"/></doc>"#)?;
+    /// let p = xot.name("p").unwrap();
+    /// let class = xot.name("class").unwrap();
+    ///
+    /// let count = xot.query(root).descendants().name(p).has_attr(class).count();
+    /// assert_eq!(count, 2);
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn query(&self, node: Node) -> Query<'_> {
+        Query::new(self, std::iter::once(node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_descendants() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse("<doc><sec><sec><p/></sec><p/></sec><p/></doc>")
+            .unwrap();
+        let sec = xot.name("sec").unwrap();
+        let p = xot.name("p").unwrap();
+        // the inner section is skipped, so its paragraph is found once
+        let found = xot
+            .query(root)
+            .descendants()
+            .name(sec)
+            .descendants()
+            .name(p)
+            .collect::<Vec<_>>();
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            found,
+            xot.query(root)
+                .descendants()
+                .name(p)
+                .take(2)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_descendants_of_children() {
+        let mut xot = Xot::new();
+        let root = xot.parse("<doc><a><b><c/></b>t</a><x/></doc>").unwrap();
+        // `b` is a child of `a`, which is also a child here, so its
+        // descendants are found once
+        let found = xot
+            .query(root)
+            .descendants()
+            .children()
+            .descendants()
+            .collect::<Vec<_>>();
+        let doc = xot.document_element(root).unwrap();
+        let a = xot.first_child(doc).unwrap();
+        assert_eq!(found, xot.descendants(a).skip(1).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<doc><a id="1" class="x"/><b id="2"/><c/></doc>"#)
+            .unwrap();
+        let id = xot.name("id").unwrap();
+        let values = xot
+            .query(root)
+            .descendants()
+            .elements()
+            .attributes()
+            .name(id)
+            .map(|node| xot.string_value(node))
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec!["1", "2"]);
+    }
+
+    #[test]
+    fn test_children() {
+        let mut xot = Xot::new();
+        let root = xot.parse("<doc><a>text</a><!--c--><b/></doc>").unwrap();
+        let doc = xot.document_element(root).unwrap();
+        assert_eq!(xot.query(root).children().first(), Some(doc));
+        assert_eq!(xot.query(root).children().children().count(), 3);
+        assert_eq!(xot.query(root).children().children().elements().count(), 2);
+        assert_eq!(
+            xot.query(root)
+                .children()
+                .children()
+                .filter(|node| xot.is_comment(node))
+                .count(),
+            1
+        );
+    }
+}
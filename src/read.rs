@@ -0,0 +1,437 @@
+//! Read XML as a stream of tokens, without constructing a tree.
+//!
+//! When a document is too large to fit into a Xot tree comfortably, or when
+//! you only want to scan it for a few bits of information, you can use a
+//! [`TokenReader`]. It checks the same well-formedness constraints as
+//! [`Xot::parse`], and resolves names to [`NameId`] using the name and
+//! namespace tables of the [`Xot`] you give it, so that you can compare
+//! them with names you've obtained with [`Xot::add_name`] and friends.
+//!
+//! ```rust
+//! use xot::Xot;
+//! use xot::read::{Token, TokenReader};
+//!
+//! let mut xot = Xot::new();
+//! let item = xot.add_name("item");
+//!
+//! let mut count = 0;
+//! for token in TokenReader::new(&mut xot, "<list><item/><item>B</item></list>") {
+//!     if let Token::ElementStart { name, .. } = token? {
+//!         if name == item {
+//!             count += 1;
+//!         }
+//!     }
+//! }
+//! assert_eq!(count, 2);
+//! # Ok::<(), xot::ParseError>(())
+//! ```
+use ahash::{HashSet, HashSetExt};
+use xmlparser::{ElementEnd, StrSpan, Tokenizer};
+
+use crate::entity::{parse_attribute, parse_text};
+use crate::error::ParseError;
+use crate::id::{NameId, NamespaceId, PrefixId};
+use crate::parse::{normalize_xml_id, NameIdBuilder, Namespaces, Span};
+use crate::xotdata::Xot;
+
+/// A token produced by a [`TokenReader`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// The start of an element, including its namespace declarations and
+    /// attributes.
+    ElementStart {
+        /// The name of the element.
+        name: NameId,
+        /// The namespace declarations on the element, in order.
+        namespaces: Vec<(PrefixId, NamespaceId)>,
+        /// The attributes on the element, in order.
+        attributes: Vec<(NameId, String)>,
+        /// The span of the element name in the start tag.
+        span: Span,
+    },
+    /// The end of an element. An empty element (`<a/>`) produces this too,
+    /// directly after its [`Token::ElementStart`].
+    ElementEnd {
+        /// The name of the element.
+        name: NameId,
+        /// The span of the end tag, or of the `/>` of an empty element.
+        span: Span,
+    },
+    /// Text, with entities and character references resolved.
+    ///
+    /// Unlike during parsing into a tree, adjacent text and CDATA sections
+    /// aren't consolidated, so they may be reported as separate tokens.
+    Text(String),
+    /// A comment.
+    Comment(String),
+    /// A processing instruction.
+    ProcessingInstruction {
+        /// The target of the processing instruction.
+        target: NameId,
+        /// The content of the processing instruction, if any.
+        content: Option<String>,
+    },
+}
+
+struct StartTag<'a> {
+    prefix: StrSpan<'a>,
+    local: StrSpan<'a>,
+    namespaces: Namespaces,
+    attributes: Vec<(StrSpan<'a>, StrSpan<'a>, String)>,
+}
+
+/// A pull-based reader that produces XML [`Token`]s.
+///
+/// This is an iterator of `Result<Token, ParseError>`. After it has produced
+/// an error, it doesn't produce anything else.
+///
+/// The reader uses the name and namespace tables of the given [`Xot`], and
+/// adds names to it as it encounters them, but it doesn't create any nodes.
+pub struct TokenReader<'a, 'x> {
+    xot: &'x mut Xot,
+    xml: &'a str,
+    tokenizer: Tokenizer<'a>,
+    name_id_builder: NameIdBuilder,
+    start_tag: Option<StartTag<'a>>,
+    open_elements: Vec<(NameId, Span)>,
+    pending_end: Option<Token>,
+    seen_element: bool,
+    seen_ids: HashSet<String>,
+    done: bool,
+}
+
+impl<'a, 'x> TokenReader<'a, 'x> {
+    /// Create a new token reader for an XML document.
+    pub fn new(xot: &'x mut Xot, xml: &'a str) -> Self {
+        let mut name_id_builder = NameIdBuilder::new(xot.base_prefixes().into_iter().collect());
+        name_id_builder.push(vec![(xot.empty_prefix_id, xot.no_namespace_id)]);
+        TokenReader {
+            xot,
+            xml,
+            tokenizer: Tokenizer::from(xml),
+            name_id_builder,
+            start_tag: None,
+            open_elements: Vec::new(),
+            pending_end: None,
+            seen_element: false,
+            seen_ids: HashSet::new(),
+            done: false,
+        }
+    }
+
+    fn read_token(&mut self) -> Result<Option<Token>, ParseError> {
+        use xmlparser::Token::*;
+
+        if let Some(token) = self.pending_end.take() {
+            return Ok(Some(token));
+        }
+        loop {
+            let position = self.tokenizer.stream().pos();
+            let token = match self.tokenizer.next() {
+                Some(Ok(token)) => token,
+                Some(Err(e)) => return Err(ParseError::XmlParser(e, position)),
+                None => return self.finish().map(|_| None),
+            };
+            match token {
+                ElementStart { prefix, local, .. } => {
+                    self.start_tag = Some(StartTag {
+                        prefix,
+                        local,
+                        namespaces: Namespaces::new(),
+                        attributes: Vec::new(),
+                    });
+                }
+                Attribute {
+                    prefix,
+                    local,
+                    value,
+                    ..
+                } => self.attribute(prefix, local, value)?,
+                ElementEnd { end, span } => {
+                    use self::ElementEnd::*;
+                    match end {
+                        Open => return self.open_element().map(Some),
+                        Empty => {
+                            let token = self.open_element()?;
+                            let (name, _) = self.open_elements.pop().unwrap();
+                            self.name_id_builder.pop();
+                            self.pending_end = Some(Token::ElementEnd {
+                                name,
+                                span: span.into(),
+                            });
+                            return Ok(Some(token));
+                        }
+                        Close(prefix, local) => {
+                            return self.close_element(prefix, local, span.into()).map(Some)
+                        }
+                    }
+                }
+                Text { text } => {
+                    if self.open_elements.is_empty() {
+                        return Err(ParseError::TextAtTopLevel(text.into()));
+                    }
+                    let content = parse_text(text.as_str().into(), text.start(), false)?;
+                    return Ok(Some(Token::Text(content.into_owned())));
+                }
+                Cdata { text, .. } => return Ok(Some(Token::Text(text.to_string()))),
+                Comment { text, .. } => return Ok(Some(Token::Comment(text.to_string()))),
+                ProcessingInstruction {
+                    target, content, ..
+                } => {
+                    return Ok(Some(Token::ProcessingInstruction {
+                        target: self.xot.add_name(target.as_str()),
+                        content: content.map(|s| s.to_string()),
+                    }))
+                }
+                Declaration { version, .. } => {
+                    if version.as_str() != "1.0" {
+                        return Err(ParseError::UnsupportedVersion(
+                            version.to_string(),
+                            version.into(),
+                        ));
+                    }
+                }
+                DtdStart { span, .. }
+                | DtdEnd { span, .. }
+                | EmptyDtd { span, .. }
+                | EntityDeclaration { span, .. } => {
+                    return Err(ParseError::DtdUnsupported(span.into()));
+                }
+            }
+        }
+    }
+
+    fn attribute(
+        &mut self,
+        prefix: StrSpan<'a>,
+        local: StrSpan<'a>,
+        value: StrSpan<'a>,
+    ) -> Result<(), ParseError> {
+        let start_tag = self.start_tag.as_mut().unwrap();
+        if prefix.as_str() == "xmlns" || (prefix.is_empty() && local.as_str() == "xmlns") {
+            let prefix = if prefix.is_empty() {
+                ""
+            } else {
+                local.as_str()
+            };
+            let prefix_id = self.xot.prefix_lookup.get_id_mut(prefix);
+            let namespace_id = self.xot.namespace_lookup.get_id_mut(value.as_str());
+            start_tag.namespaces.push((prefix_id, namespace_id));
+            return Ok(());
+        }
+        let is_duplicate = start_tag
+            .attributes
+            .iter()
+            .any(|(p, l, _)| p.as_str() == prefix.as_str() && l.as_str() == local.as_str());
+        if is_duplicate {
+            let attr_name = if prefix.is_empty() {
+                local.to_string()
+            } else {
+                format!("{}:{}", prefix, local)
+            };
+            return Err(ParseError::DuplicateAttribute(
+                attr_name,
+                Span::from_prefix_name(prefix, local),
+            ));
+        }
+        let value_span: Span = value.into();
+        let value = parse_attribute(value.as_str().into(), value.start(), false)?.into_owned();
+        let value = if prefix.as_str() == "xml" && local.as_str() == "id" {
+            let value = normalize_xml_id(&value);
+            if !self.seen_ids.insert(value.clone()) {
+                return Err(ParseError::DuplicateId(value, value_span));
+            }
+            value
+        } else {
+            value
+        };
+        start_tag.attributes.push((prefix, local, value));
+        Ok(())
+    }
+
+    fn open_element(&mut self) -> Result<Token, ParseError> {
+        let start_tag = self.start_tag.take().unwrap();
+        let span = Span::from_prefix_name(start_tag.prefix, start_tag.local);
+        if self.open_elements.is_empty() {
+            if self.seen_element {
+                return Err(ParseError::MultipleElementsAtTopLevel(span));
+            }
+            self.seen_element = true;
+        }
+        self.name_id_builder.push(start_tag.namespaces.clone());
+        let name = self.name_id_builder.element_name_id(
+            &start_tag.prefix,
+            &start_tag.local,
+            start_tag.prefix.into(),
+            self.xot,
+        )?;
+        let attributes = start_tag
+            .attributes
+            .into_iter()
+            .map(|(prefix, local, value)| {
+                self.name_id_builder
+                    .attribute_name_id(&prefix, &local, prefix.into(), self.xot)
+                    .map(|name| (name, value))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.open_elements.push((name, span));
+        Ok(Token::ElementStart {
+            name,
+            namespaces: start_tag.namespaces,
+            attributes,
+            span,
+        })
+    }
+
+    fn close_element(
+        &mut self,
+        prefix: StrSpan<'a>,
+        local: StrSpan<'a>,
+        span: Span,
+    ) -> Result<Token, ParseError> {
+        let name =
+            self.name_id_builder
+                .element_name_id(&prefix, &local, prefix.into(), self.xot)?;
+        match self.open_elements.last() {
+            Some((open_name, _)) if *open_name == name => {
+                self.open_elements.pop();
+                self.name_id_builder.pop();
+                Ok(Token::ElementEnd { name, span })
+            }
+            _ => Err(ParseError::InvalidCloseTag(
+                prefix.to_string(),
+                local.to_string(),
+                Span::from_prefix_name(prefix, local),
+            )),
+        }
+    }
+
+    fn finish(&self) -> Result<(), ParseError> {
+        if let Some((_, span)) = self.open_elements.last() {
+            return Err(ParseError::UnclosedTag(*span));
+        }
+        if !self.seen_element {
+            return Err(ParseError::NoElementAtTopLevel(self.xml.len()));
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for TokenReader<'_, '_> {
+    type Item = Result<Token, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_token() {
+            Ok(Some(token)) => Some(Ok(token)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(xot: &mut Xot, xml: &str) -> Result<Vec<Token>, ParseError> {
+        TokenReader::new(xot, xml).collect()
+    }
+
+    #[test]
+    fn test_tokens_resolve_namespaces() {
+        let mut xot = Xot::new();
+        let ns = xot.add_namespace("http://example.com");
+        let prefix = xot.add_prefix("x");
+        let a = xot.add_name_ns("a", ns);
+        let b = xot.add_name("b");
+        let c = xot.add_name_ns("c", ns);
+        let tokens = tokens(
+            &mut xot,
+            r#"<x:a xmlns:x="http://example.com" b="&lt;1"><?pi data?><!--c--><x:c/>t<![CDATA[<]]></x:a>"#,
+        )
+        .unwrap();
+        let target = xot.name("pi").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::ElementStart {
+                    name: a,
+                    namespaces: vec![(prefix, ns)],
+                    attributes: vec![(b, "<1".to_string())],
+                    span: Span::new(1, 4),
+                },
+                Token::ProcessingInstruction {
+                    target,
+                    content: Some("data".to_string()),
+                },
+                Token::Comment("c".to_string()),
+                Token::ElementStart {
+                    name: c,
+                    namespaces: vec![],
+                    attributes: vec![],
+                    span: Span::new(64, 67),
+                },
+                Token::ElementEnd {
+                    name: c,
+                    span: Span::new(67, 69),
+                },
+                Token::Text("t".to_string()),
+                Token::Text("<".to_string()),
+                Token::ElementEnd {
+                    name: a,
+                    span: Span::new(83, 89),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokens_errors() {
+        let mut xot = Xot::new();
+        assert!(matches!(
+            tokens(&mut xot, "<a><b></a>"),
+            Err(ParseError::InvalidCloseTag(..))
+        ));
+        assert!(matches!(
+            tokens(&mut xot, "<a><b>"),
+            Err(ParseError::UnclosedTag(span)) if span == Span::new(4, 5)
+        ));
+        assert!(matches!(
+            tokens(&mut xot, "<x:a/>"),
+            Err(ParseError::UnknownPrefix(..))
+        ));
+        assert!(matches!(
+            tokens(&mut xot, r#"<a b="1" b="2"/>"#),
+            Err(ParseError::DuplicateAttribute(..))
+        ));
+        assert!(matches!(
+            tokens(&mut xot, "<!--only a comment-->"),
+            Err(ParseError::NoElementAtTopLevel(..))
+        ));
+    }
+
+    #[test]
+    fn test_tokens_stop_after_error() {
+        let mut xot = Xot::new();
+        let mut reader = TokenReader::new(&mut xot, "<a><b></a><c/>");
+        assert!(matches!(
+            reader.next(),
+            Some(Ok(Token::ElementStart { .. }))
+        ));
+        assert!(matches!(
+            reader.next(),
+            Some(Ok(Token::ElementStart { .. }))
+        ));
+        assert!(matches!(reader.next(), Some(Err(_))));
+        assert!(reader.next().is_none());
+    }
+}
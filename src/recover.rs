@@ -0,0 +1,249 @@
+use xmlparser::{Error as XmlError, StreamError, TextPos, Token, Tokenizer};
+
+use crate::error::ParseError;
+use crate::parse::{token_span, DocumentBuilder, ParseOptions, SpanInfo};
+use crate::xotdata::{Node, Xot};
+use crate::Span;
+
+// The source text, which we repair when it has syntax errors by inserting
+// text into it.
+struct Source {
+    text: String,
+    // the position and length of the text we inserted, in order
+    insertions: Vec<(usize, usize)>,
+}
+
+impl Source {
+    fn insert(&mut self, position: usize, text: &str) {
+        self.text.insert_str(position, text);
+        self.insertions.push((position, text.len()));
+    }
+
+    // Turn the character at `position` into text by putting it in a CDATA
+    // section.
+    fn escape(&mut self, position: usize) {
+        let len = self.text[position..]
+            .chars()
+            .next()
+            .map_or(0, char::len_utf8);
+        self.insert(position + len, "]]>");
+        self.insert(position, "<![CDATA[");
+    }
+
+    // The position in the original text.
+    fn original_position(&self, mut position: usize) -> usize {
+        for (inserted, len) in self.insertions.iter().rev() {
+            if position >= inserted + len {
+                position -= len;
+            } else if position > *inserted {
+                position = *inserted;
+            }
+        }
+        position
+    }
+
+    fn original_span(&self, span: Span) -> Span {
+        Span::new(
+            self.original_position(span.start),
+            self.original_position(span.end),
+        )
+    }
+
+    // The position of a row and column in the text.
+    fn position(&self, text_pos: TextPos) -> usize {
+        let line_start = match text_pos.row {
+            1 => 0,
+            row => self
+                .text
+                .match_indices('\n')
+                .nth(row as usize - 2)
+                .map_or(self.text.len(), |(i, _)| i + 1),
+        };
+        self.text[line_start..]
+            .char_indices()
+            .nth(text_pos.col as usize - 1)
+            .map_or(self.text.len(), |(i, _)| line_start + i)
+    }
+
+    // Repair the syntax error in the markup that starts at `start`, so that
+    // we can tokenize it.
+    fn repair(&mut self, start: usize, error: &XmlError) {
+        // an attribute value without quotes
+        if let XmlError::InvalidAttribute(StreamError::InvalidQuote(_, text_pos), _) = error {
+            let value_start = self.position(*text_pos);
+            let value_len = self.text[value_start..]
+                .find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '<')
+                .unwrap_or(self.text.len() - value_start);
+            // we don't take a `/` that ends an empty element
+            let value = &self.text[value_start..value_start + value_len];
+            let value = if self.text[value_start + value_len..].starts_with('>') {
+                value.strip_suffix('/').unwrap_or(value)
+            } else {
+                value
+            };
+            let quote = if value.contains('"') { "'" } else { "\"" };
+            if !value.contains(quote) {
+                let value_end = value_start + value.len();
+                self.insert(value_end, quote);
+                self.insert(value_start, quote);
+                return;
+            }
+        }
+        // otherwise we take the start of the markup as text
+        let start = start
+            + self.text[start..]
+                .find(|c: char| !c.is_ascii_whitespace())
+                .unwrap_or(0);
+        if self.text[start..].starts_with('<') {
+            self.escape(start);
+        } else if let Some(end) = self.text[start..].find("]]>") {
+            // `]]>` isn't allowed in text
+            self.escape(start + end + 2);
+        } else {
+            self.escape(start);
+        }
+    }
+}
+
+// Parse XML, recovering from errors.
+pub(crate) fn parse_lenient(
+    xot: &mut Xot,
+    xml: &str,
+    options: ParseOptions,
+) -> (Node, Vec<ParseError>) {
+    let mut source = Source {
+        text: xml.to_string(),
+        insertions: Vec::new(),
+    };
+    let mut builder = DocumentBuilder::new(xot, options);
+    builder.recover = true;
+    let mut span_info = SpanInfo::new();
+    let mut parsed = 0;
+    // after a syntax error we tokenize in fragment mode, which allows
+    // anything at the top level
+    let mut fragment = false;
+    loop {
+        let start_document = !fragment;
+        let (start, error) = tokenize(
+            xot,
+            &mut builder,
+            &mut span_info,
+            &source.text,
+            parsed,
+            fragment,
+        );
+        let Some(error) = error else {
+            break;
+        };
+        fragment = true;
+        parsed = start;
+        // in document mode, xmlparser rejects anything but a single element
+        // at the top level. We report this when we finish, so we go on in
+        // fragment mode first.
+        if start_document {
+            continue;
+        }
+        builder
+            .errors
+            .push(ParseError::XmlParser(error, start));
+        source.repair(start, &error);
+    }
+    let len = source.text.len();
+    let (document, _) = xot
+        .finish_document(&mut builder, span_info, len)
+        .expect("the builder recovers from all errors");
+    let errors = builder
+        .errors
+        .into_iter()
+        .map(|error| error.map_spans(|span| source.original_span(span)))
+        .collect();
+    (document, errors)
+}
+
+// Tokenize the text from `parsed`, and parse the tokens. This stops at the
+// first syntax error, and returns the start of the markup that has the
+// error, from where we need to parse again.
+fn tokenize(
+    xot: &mut Xot,
+    builder: &mut DocumentBuilder,
+    span_info: &mut SpanInfo,
+    text: &str,
+    parsed: usize,
+    fragment: bool,
+) -> (usize, Option<XmlError>) {
+    let mut tokenizer = if fragment {
+        Tokenizer::from_fragment(text, parsed..text.len())
+    } else {
+        Tokenizer::from(text)
+    };
+    // as in the push parser, we parse the prolog and start tags only once
+    // they're complete, so that we can start over at their start
+    let mut prolog = !fragment;
+    let mut pending = Vec::new();
+    let mut start_tag = None;
+    loop {
+        let position = tokenizer.stream().pos();
+        let token = match tokenizer.next() {
+            Some(Ok(token)) => token,
+            Some(Err(error)) => {
+                // the prolog we have so far is fine, but we start over with
+                // the start tag we're in
+                let prolog_len = pending
+                    .iter()
+                    .position(|(token, _)| matches!(token, Token::ElementStart { .. }))
+                    .unwrap_or(pending.len());
+                pending.truncate(prolog_len);
+                parse_tokens(xot, builder, span_info, pending);
+                return (start_tag.unwrap_or(position), Some(error));
+            }
+            None => {
+                parse_tokens(xot, builder, span_info, pending);
+                return (text.len(), None);
+            }
+        };
+        let skipped = &text[position..token_span(&token).start()];
+        match token {
+            Token::ElementStart { span, .. } => {
+                prolog = false;
+                start_tag = Some(span.start());
+                pending.push((token, skipped));
+                continue;
+            }
+            Token::Attribute { .. } => {
+                pending.push((token, skipped));
+                continue;
+            }
+            // whitespace between the top-level nodes isn't text
+            Token::Text { text }
+                if builder.is_current_node_document(xot)
+                    && text.as_str().chars().all(|c| c.is_ascii_whitespace()) =>
+            {
+                continue;
+            }
+            _ if prolog => {
+                pending.push((token, skipped));
+                continue;
+            }
+            _ => {}
+        }
+        pending.push((token, skipped));
+        parse_tokens(xot, builder, span_info, pending.drain(..));
+        start_tag = None;
+    }
+}
+
+fn parse_tokens<'a>(
+    xot: &mut Xot,
+    builder: &mut DocumentBuilder,
+    span_info: &mut SpanInfo,
+    tokens: impl IntoIterator<Item = (Token<'a>, &'a str)>,
+) {
+    for (token, skipped) in tokens {
+        builder.skipped_markup(skipped);
+        // the builder recovers from errors in the tree where it can; for
+        // the others we skip the token
+        if let Err(error) = xot.parse_token(builder, span_info, token) {
+            builder.errors.push(error);
+        }
+    }
+}
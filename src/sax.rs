@@ -0,0 +1,147 @@
+// Reporting a tree as a sequence of SAX-style events.
+
+use crate::access::NodeEdge;
+use crate::error::Error;
+use crate::xmlname::RefName;
+use crate::xmlvalue::Value;
+use crate::xotdata::{Node, Xot};
+
+/// Receives the events for a tree that you [emit](Xot::emit).
+///
+/// This is like a SAX content handler: each method is called for a part of
+/// the tree, in document order. All methods do nothing by default, so you
+/// only have to implement the ones you need.
+///
+/// ```rust
+/// use xot::xmlname::{NameStrInfo, RefName};
+/// use xot::{SaxHandler, Xot};
+///
+/// // collects the local names of the elements and the text
+/// #[derive(Default)]
+/// struct Collect(Vec<String>);
+///
+/// impl SaxHandler for Collect {
+///     fn start_element(&mut self, name: &RefName, _attributes: &[(RefName, &str)]) {
+///         self.0.push(format!("<{}>", name.local_name()));
+///     }
+///     fn characters(&mut self, text: &str) {
+///         self.0.push(text.to_string());
+///     }
+/// }
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse("<doc><p>Hello</p><p>world</p></doc>")?;
+/// let mut collect = Collect::default();
+/// xot.emit(root, &mut collect)?;
+/// assert_eq!(collect.0, ["<doc>", "<p>", "Hello", "<p>", "world"]);
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub trait SaxHandler {
+    /// The start of a document node.
+    fn start_document(&mut self) {}
+
+    /// The end of a document node.
+    fn end_document(&mut self) {}
+
+    /// A namespace declaration of the element that starts next.
+    ///
+    /// The prefix is the empty string for the default namespace.
+    fn start_prefix_mapping(&mut self, _prefix: &str, _namespace: &str) {}
+
+    /// The end of the scope of a namespace declaration, after the end of
+    /// the element that declares it.
+    fn end_prefix_mapping(&mut self, _prefix: &str) {}
+
+    /// The start of an element, with its attributes and their values.
+    fn start_element(&mut self, _name: &RefName, _attributes: &[(RefName, &str)]) {}
+
+    /// The end of an element.
+    fn end_element(&mut self, _name: &RefName) {}
+
+    /// Text.
+    fn characters(&mut self, _text: &str) {}
+
+    /// The text of a CDATA section. By default this is reported as
+    /// [`characters`](SaxHandler::characters).
+    fn cdata(&mut self, text: &str) {
+        self.characters(text);
+    }
+
+    /// A reference to an entity that wasn't expanded, such as `&company;`.
+    /// Its name is given without `&` and `;`.
+    fn entity_reference(&mut self, _name: &str) {}
+
+    /// A comment.
+    fn comment(&mut self, _text: &str) {}
+
+    /// A processing instruction.
+    fn processing_instruction(&mut self, _target: &RefName, _data: Option<&str>) {}
+}
+
+impl Xot {
+    /// Report a node and its descendants to a [`SaxHandler`].
+    ///
+    /// This walks the tree in document order and calls the method of the
+    /// handler for each part of it. This way you can hand a tree to code
+    /// that consumes events, such as a signer, an indexer or another
+    /// serializer, without serializing and parsing it again.
+    ///
+    /// If a name is in a namespace without a prefix in scope, this fails
+    /// with [`Error::MissingPrefix`], after the events before it have been
+    /// reported.
+    pub fn emit(&self, node: Node, handler: &mut impl SaxHandler) -> Result<(), Error> {
+        for edge in self.traverse(node) {
+            match edge {
+                NodeEdge::Start(node) => self.emit_start(node, handler)?,
+                NodeEdge::End(node) => self.emit_end(node, handler)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_start(&self, node: Node, handler: &mut impl SaxHandler) -> Result<(), Error> {
+        match self.value(node) {
+            Value::Document => handler.start_document(),
+            Value::Element(element) => {
+                for (prefix_id, namespace_id) in self.namespaces(node).iter() {
+                    handler.start_prefix_mapping(
+                        self.prefix_str(prefix_id),
+                        self.namespace_str(*namespace_id),
+                    );
+                }
+                let name = self.name_ref(element.name(), node)?;
+                let attributes = self
+                    .attributes(node)
+                    .iter()
+                    .map(|(name_id, value)| Ok((self.name_ref(name_id, node)?, value.as_str())))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                handler.start_element(&name, &attributes);
+            }
+            Value::Text(text) => handler.characters(text.get()),
+            Value::Cdata(cdata) => handler.cdata(cdata.get()),
+            Value::EntityReference(entity_reference) => {
+                handler.entity_reference(entity_reference.name())
+            }
+            Value::Comment(comment) => handler.comment(comment.get()),
+            Value::ProcessingInstruction(pi) => {
+                handler.processing_instruction(&self.name_ref(pi.target(), node)?, pi.data())
+            }
+            Value::Attribute(_) | Value::Namespace(_) => {}
+        }
+        Ok(())
+    }
+
+    fn emit_end(&self, node: Node, handler: &mut impl SaxHandler) -> Result<(), Error> {
+        match self.value(node) {
+            Value::Document => handler.end_document(),
+            Value::Element(element) => {
+                handler.end_element(&self.name_ref(element.name(), node)?);
+                for (prefix_id, _) in self.namespaces(node).iter() {
+                    handler.end_prefix_mapping(self.prefix_str(prefix_id));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -1,17 +1,28 @@
 use std::io::Write;
 
+use encoding_rs::{Encoder, EncoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+use crate::entity::character_reference;
 use crate::error::Error;
+use crate::htmltext::{drop_inter_element_whitespace, html_name};
+#[cfg(feature = "tokio")]
+use crate::output::edge_outputs;
 use crate::output::{
-    gen_outputs, Html5Elements, Html5Serializer, Output, OutputToken, TokenSerializeParameters,
-    XmlSerializer,
+    gen_outputs, order_attributes, Html5Elements, Html5Serializer, Output, OutputToken,
+    TokenSerializeParameters, XhtmlSerializer, XmlSerializer,
 };
-use crate::output::{NoopNormalizer, Normalizer};
+use crate::output::{pretty_writer, PrettyWriter};
+use crate::output::{NoopNormalizer, Normalizer, OutputFilter};
 use crate::output::{Pretty, PrettyOutputToken};
 use crate::xmlname::NameStrInfo;
-use crate::{output, NameId, Value};
+use crate::{output, NameId, Prefixes, Value};
 
 use crate::xotdata::{Node, Xot};
 
+// how much output we collect before we write it to an async writer
+#[cfg(feature = "tokio")]
+const WRITE_BUFFER_SIZE: usize = 64 * 1024;
+
 /// Serialize a Xot node to HTML5
 pub struct Html5<'a> {
     xot: &'a Xot,
@@ -77,6 +88,35 @@ impl<'a> Html5<'a> {
         normalizer: N,
     ) -> Result<(), Error> {
         w.write_all(b"<!DOCTYPE html>").unwrap();
+        self.serialize_body(parameters, node, w, normalizer)
+    }
+
+    // Serialize as HTML, which uses a pretty printer that is more aware of
+    // HTML than the HTML 5 output method.
+    fn serialize_html_body(
+        &self,
+        indentation: Option<output::Indentation>,
+        node: Node,
+        w: &mut impl Write,
+    ) -> Result<(), Error> {
+        let outputs = gen_outputs(self.xot, node);
+        let mut serializer =
+            Html5Serializer::new(self.xot, &self.html5_elements, node, &[], NoopNormalizer);
+        if let Some(indentation) = indentation {
+            serializer.serialize_pretty_html(w, outputs, &indentation)?;
+        } else {
+            serializer.serialize(w, outputs)?;
+        }
+        Ok(())
+    }
+
+    fn serialize_body<N: Normalizer>(
+        &self,
+        parameters: output::html5::Parameters,
+        node: Node,
+        w: &mut impl Write,
+        normalizer: N,
+    ) -> Result<(), Error> {
         let outputs = gen_outputs(self.xot, node);
         let mut serializer = Html5Serializer::new(
             self.xot,
@@ -86,7 +126,7 @@ impl<'a> Html5<'a> {
             normalizer,
         );
         if let Some(indentation) = parameters.indentation {
-            serializer.serialize_pretty(w, outputs, &indentation.suppress)?;
+            serializer.serialize_pretty(w, outputs, &indentation)?;
         } else {
             serializer.serialize(w, outputs)?;
         }
@@ -150,6 +190,34 @@ impl Xot {
         self.serialize_xml_string(Default::default(), node)
     }
 
+    /// Serialize node as pretty printed XML with ANSI colors.
+    ///
+    /// This requires the `color` feature.
+    ///
+    /// This is meant for humans reading XML in a terminal, for instance in
+    /// the output of a command line tool or of a failing test. Tags are
+    /// blue, attribute names cyan, attribute values green, text yellow and
+    /// comments and processing instructions grey. Without the color codes
+    /// this is the same as [`Xot::to_string`] with indentation, without a
+    /// trailing newline.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<doc><p a="A">Hello</p></doc>"#)?;
+    /// let colored = xot.to_colored_string(root)?;
+    /// assert!(colored.starts_with("\x1b[34m<doc\x1b[0m\x1b[34m>\x1b[0m\n  \x1b[34m<p\x1b[0m"));
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    ///
+    /// If there are missing namespace prefixes, this errors, like
+    /// [`Xot::to_string`].
+    #[cfg(feature = "color")]
+    pub fn to_colored_string(&self, node: Node) -> Result<String, Error> {
+        crate::color::colored_string(self, node)
+    }
+
     /// Serialize to XML, with options.
     ///
     /// Note that if you don't need string output and have a writer available,
@@ -263,6 +331,58 @@ assert_eq!(s, "<doc>\u{1E0D}\u{0307}</doc>");
         Ok(String::from_utf8(buf).unwrap())
     }
 
+    /// Serialize to XML, appending to a string.
+    ///
+    /// This is like [`Xot::serialize_xml_string`], but you can reuse the
+    /// string for many nodes, so that it doesn't have to be allocated each
+    /// time. If serialization fails, the string is left as it was.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse("<a><b/><c/></a>")?;
+    /// let a = xot.document_element(root)?;
+    ///
+    /// let mut buf = String::new();
+    /// for child in xot.children(a) {
+    ///     buf.clear();
+    ///     xot.serialize_xml_into(Default::default(), child, &mut buf)?;
+    ///     assert!(buf == "<b/>" || buf == "<c/>");
+    /// }
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn serialize_xml_into(
+        &self,
+        parameters: output::xml::Parameters,
+        node: Node,
+        buf: &mut String,
+    ) -> Result<(), Error> {
+        // we write to the bytes of the string, keeping its allocation
+        let mut bytes = std::mem::take(buf).into_bytes();
+        let result = self.serialize_xml_bytes_into(parameters, node, &mut bytes);
+        *buf = String::from_utf8(bytes).unwrap();
+        result
+    }
+
+    /// Serialize to XML, appending to a byte buffer.
+    ///
+    /// This is like [`Xot::serialize_xml_into`], but for a byte buffer. If
+    /// serialization fails, the buffer is left as it was.
+    pub fn serialize_xml_bytes_into(
+        &self,
+        parameters: output::xml::Parameters,
+        node: Node,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        let len = buf.len();
+        let result = self.serialize_xml_write(parameters, node, buf);
+        if result.is_err() {
+            buf.truncate(len);
+        }
+        result
+    }
+
     /// Serialize to XML via a [`Write`], with options.
     ///
     /// This is like [`Xot::serialize_xml_string`] but writes to a [`Write`]. This
@@ -287,10 +407,297 @@ assert_eq!(s, "<doc>\u{1E0D}\u{0307}</doc>");
         w: &mut impl Write,
         normalizer: N,
     ) -> Result<(), Error> {
-        if let Some(declaration) = parameters.declaration {
-            declaration.serialize(w)?;
+        let serializer = XmlSerializer::new(
+            self,
+            node,
+            parameters.token_serialize_parameters(),
+            normalizer,
+        );
+        self.write_xml(parameters, serializer, node, w)
+    }
+
+    /// Serialize a node as XML to insert where namespaces are declared
+    /// already.
+    ///
+    /// This is useful if you splice the XML into a larger document, as in
+    /// templating. `namespaces` are the namespaces declared where the XML
+    /// is inserted. Declarations of these on `node` or its ancestors
+    /// aren't written, and their prefixes can be used for names in their
+    /// namespace, even if they're not declared in the tree.
+    ///
+    /// ```rust
+    /// use xot::{Prefixes, Xot};
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<h:html xmlns:h="http://www.w3.org/1999/xhtml"><h:p>text</h:p></h:html>"#)?;
+    /// let html = xot.document_element(root)?;
+    /// let p = xot.first_child(html).unwrap();
+    ///
+    /// // the ancestor declares the prefix
+    /// assert_eq!(
+    ///     xot.to_string(p)?,
+    ///     r#"<h:p xmlns:h="http://www.w3.org/1999/xhtml">text</h:p>"#
+    /// );
+    ///
+    /// let mut namespaces = Prefixes::new();
+    /// namespaces.insert(xot.prefix("h").unwrap(), xot.namespace("http://www.w3.org/1999/xhtml").unwrap());
+    /// assert_eq!(
+    ///     xot.serialize_fragment_with_context(Default::default(), p, &namespaces)?,
+    ///     "<h:p>text</h:p>"
+    /// );
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn serialize_fragment_with_context(
+        &self,
+        parameters: output::xml::Parameters,
+        node: Node,
+        namespaces: &Prefixes,
+    ) -> Result<String, Error> {
+        let mut buf = Vec::new();
+        self.serialize_fragment_write_with_context(parameters, node, namespaces, &mut buf)?;
+        Ok(String::from_utf8(buf).unwrap())
+    }
+
+    /// Write a node as XML to insert where namespaces are declared already.
+    ///
+    /// See [`Xot::serialize_fragment_with_context`] for details.
+    pub fn serialize_fragment_write_with_context(
+        &self,
+        parameters: output::xml::Parameters,
+        node: Node,
+        namespaces: &Prefixes,
+        w: &mut impl Write,
+    ) -> Result<(), Error> {
+        let context = namespaces
+            .iter()
+            .map(|(prefix_id, namespace_id)| (*prefix_id, *namespace_id))
+            .collect();
+        let serializer = XmlSerializer::new(
+            self,
+            node,
+            parameters.token_serialize_parameters(),
+            NoopNormalizer,
+        )
+        .with_context(context);
+        self.write_xml(parameters, serializer, node, w)
+    }
+
+    fn write_xml<'a, N: Normalizer>(
+        &'a self,
+        parameters: output::xml::Parameters,
+        mut serializer: XmlSerializer<'a, N>,
+        node: Node,
+        w: &mut impl Write,
+    ) -> Result<(), Error> {
+        let mut w = LimitedWriter::new(w, parameters.max_output_size);
+        let result = self
+            .write_xml_prolog(&parameters, node, &mut w)
+            .and_then(|_| {
+                let outputs = xml_outputs(self, gen_outputs(self, node), &parameters);
+                if let Some(indentation) = &parameters.indentation {
+                    serializer.serialize_pretty(&mut w, outputs, indentation, parameters.newline)
+                } else {
+                    serializer.serialize(&mut w, outputs)
+                }
+            });
+        w.check(result)
+    }
+
+    /// Write a node as XML to an async writer.
+    ///
+    /// This requires the `tokio` feature.
+    ///
+    /// This is the async version of [`Xot::serialize_xml_write`]. The output
+    /// is written in chunks as it's generated, waiting for the writer to
+    /// accept each, so you can stream a large document without having all
+    /// of it in memory.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// # let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    /// # runtime.block_on(async {
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse("<a><b/></a>")?;
+    /// let mut buf = Vec::new();
+    /// xot.serialize_xml_async(Default::default(), root, &mut buf).await?;
+    /// assert_eq!(buf, b"<a><b/></a>");
+    /// # Ok::<(), xot::Error>(())
+    /// # })?;
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub async fn serialize_xml_async(
+        &self,
+        parameters: output::xml::Parameters,
+        node: Node,
+        mut writer: impl tokio::io::AsyncWrite + Unpin,
+    ) -> Result<(), Error> {
+        use tokio::io::AsyncWriteExt;
+
+        let mut buf = LimitedWriter::new(Vec::new(), parameters.max_output_size);
+        let result = self.write_xml_prolog(&parameters, node, &mut buf);
+        buf.check(result)?;
+        let mut serializer = XmlSerializer::new(
+            self,
+            node,
+            parameters.token_serialize_parameters(),
+            NoopNormalizer,
+        );
+        let mut pretty = parameters
+            .indentation
+            .as_ref()
+            .map(|indentation| pretty_writer(self, indentation, parameters.newline));
+        for edge in self.traverse(node) {
+            let outputs = edge_outputs(self, node, edge);
+            for (node, output) in xml_outputs(self, outputs.into_iter(), &parameters) {
+                let result = write_xml_output(&mut serializer, &mut pretty, &mut buf, node, output);
+                buf.check(result)?;
+            }
+            if buf.inner.len() >= WRITE_BUFFER_SIZE {
+                writer.write_all(&buf.inner).await?;
+                buf.inner.clear();
+            }
+        }
+        if let Some(pretty) = &mut pretty {
+            let result = pretty.finish(&mut buf);
+            buf.check(result)?;
         }
-        if let Some(doctype) = parameters.doctype {
+        writer.write_all(&buf.inner).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    /// Serialize a node as XML, with a filter that changes the output.
+    ///
+    /// See [`output::OutputFilter`] for an example.
+    pub fn serialize_xml_string_with_filter(
+        &self,
+        parameters: output::xml::Parameters,
+        node: Node,
+        filter: impl OutputFilter,
+    ) -> Result<String, Error> {
+        let mut buf = Vec::new();
+        self.serialize_xml_write_with_filter(parameters, node, filter, &mut buf)?;
+        Ok(String::from_utf8(buf).unwrap())
+    }
+
+    /// Write a node as XML, with a filter that changes the output.
+    ///
+    /// The filter sees the outputs after defaulted attributes are left out
+    /// and attributes are put in order, as set in `parameters`.
+    pub fn serialize_xml_write_with_filter(
+        &self,
+        parameters: output::xml::Parameters,
+        node: Node,
+        mut filter: impl OutputFilter,
+        w: &mut impl Write,
+    ) -> Result<(), Error> {
+        let mut w = LimitedWriter::new(w, parameters.max_output_size);
+        let result = self
+            .write_xml_prolog(&parameters, node, &mut w)
+            .and_then(|_| {
+                let mut serializer = XmlSerializer::new(
+                    self,
+                    node,
+                    parameters.token_serialize_parameters(),
+                    NoopNormalizer,
+                );
+                let mut pretty = parameters
+                    .indentation
+                    .as_ref()
+                    .map(|indentation| pretty_writer(self, indentation, parameters.newline));
+                for (node, output) in xml_outputs(self, gen_outputs(self, node), &parameters) {
+                    for filtered in filter.filter(self, node, &output) {
+                        let output = filtered.output(&output);
+                        write_xml_output(&mut serializer, &mut pretty, &mut w, node, output)?;
+                    }
+                }
+                if let Some(pretty) = &mut pretty {
+                    pretty.finish(&mut w)?;
+                }
+                Ok(())
+            });
+        w.check(result)
+    }
+
+    /// Serialize children of a document as separate XML documents.
+    ///
+    /// This serializes each child of the document element of `node` for
+    /// which `select` returns true, each to a writer that `writer` gives
+    /// for it, along with the number of children written before it. This
+    /// way you can split up a large document of records into many small
+    /// ones. Each starts with the XML declaration and doctype from
+    /// `parameters`, and declares the namespaces it uses from the
+    /// document element. `node` can be the document element itself.
+    ///
+    /// This returns the number of children written.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<records xmlns:x="urn:x"><record x:id="1"/><comment/><record x:id="2"/></records>"#)?;
+    /// let record = xot.name("record").unwrap();
+    ///
+    /// let mut buffers = vec![Vec::new(), Vec::new()];
+    /// let mut writers = buffers.iter_mut();
+    /// let count = xot.serialize_xml_split(
+    ///     Default::default(),
+    ///     root,
+    ///     |node| xot.element(node).is_some_and(|element| element.name() == record),
+    ///     |_i, _node| Ok(writers.next().unwrap()),
+    /// )?;
+    /// assert_eq!(count, 2);
+    /// assert_eq!(buffers[0], br#"<record xmlns:x="urn:x" x:id="1"/>"#);
+    /// assert_eq!(buffers[1], br#"<record xmlns:x="urn:x" x:id="2"/>"#);
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn serialize_xml_split<W: Write>(
+        &self,
+        parameters: output::xml::Parameters,
+        node: Node,
+        mut select: impl FnMut(Node) -> bool,
+        mut writer: impl FnMut(usize, Node) -> std::io::Result<W>,
+    ) -> Result<usize, Error> {
+        let node = if self.is_document(node) {
+            self.document_element(node)?
+        } else {
+            node
+        };
+        let mut count = 0;
+        for child in self.children(node) {
+            if !select(child) {
+                continue;
+            }
+            let mut w = writer(count, child)?;
+            self.serialize_xml_write(parameters.clone(), child, &mut w)?;
+            w.flush()?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // Write what comes before the XML of `node`: the byte order mark, the
+    // XML declaration and the doctype declaration.
+    fn write_xml_prolog(
+        &self,
+        parameters: &output::xml::Parameters,
+        node: Node,
+        w: &mut impl Write,
+    ) -> Result<(), Error> {
+        if parameters.byte_order_mark {
+            w.write_all("\u{FEFF}".as_bytes())?;
+        }
+        if let Some(declaration) = &parameters.declaration {
+            declaration.serialize(w, parameters.newline)?;
+        }
+        if let Some(output::xml::DocType::Document) = &parameters.doctype {
+            if let Some(doctype) = self.doctype(node) {
+                doctype.serialize_xml(w, parameters.newline)?;
+                w.write_all(parameters.newline.as_bytes())?;
+            }
+        } else if let Some(doctype) = &parameters.doctype {
             // if we are in a document node, we look for the document_element,
             // otherwise we take the current element, if possible
             let node = match self.value(node) {
@@ -302,20 +709,359 @@ assert_eq!(s, "<doc>\u{1E0D}\u{0307}</doc>");
             // know it's an element now
             let name = self.node_name_ref(node)?.unwrap();
             let name = name.full_name();
-            doctype.serialize(name.as_ref(), w)?;
+            doctype.serialize(name.as_ref(), w, parameters.newline)?;
+        }
+        Ok(())
+    }
+
+    /// Serialize a node as XML in an encoding.
+    ///
+    /// The encoding is given by a label such as `"utf-16le"` or
+    /// `"iso-8859-2"`, as the [WHATWG Encoding
+    /// Standard](https://encoding.spec.whatwg.org/#names-and-labels) reads
+    /// it. There labels of ASCII and ISO-8859-1 such as `"us-ascii"` and
+    /// `"latin1"` mean windows-1252, but here they give ASCII and
+    /// ISO-8859-1, as they do in XML. The XML declaration in `parameters`
+    /// declares this encoding. If there is none, one is added, unless the
+    /// encoding is UTF-8 or UTF-16, as XML requires. UTF-16 output starts
+    /// with a byte order mark. Characters that can't be represented in the encoding are written
+    /// as character references, as set by
+    /// [`output::xml::Parameters::character_references`]. That's not
+    /// possible in comments, processing instructions and CDATA sections, so
+    /// there these are handled as set by
+    /// [`output::xml::Parameters::unrepresentable_characters`]. Names and
+    /// prefixes can't have character references either, and aren't
+    /// replaced, so a character in them that the encoding doesn't have
+    /// results in [`Error::UnrepresentableCharacter`].
+    ///
+    /// ```rust
+    /// use xot::{Xot, output};
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse("<doc>é€</doc>")?;
+    ///
+    /// let bytes = xot.serialize_xml_bytes_with_encoding(Default::default(), root, "iso-8859-2")?;
+    /// assert_eq!(bytes, b"<?xml version=\"1.0\" encoding=\"ISO-8859-2\"?>\n<doc>\xe9&#x20AC;</doc>");
+    ///
+    /// let bytes = xot.serialize_xml_bytes_with_encoding(Default::default(), root, "utf-16le")?;
+    /// assert_eq!(bytes, b"\xff\xfe<\0d\0o\0c\0>\0\xe9\0\xac\x20<\0/\0d\0o\0c\0>\0");
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    ///
+    /// If the encoding label isn't known, this returns
+    /// [`Error::UnsupportedEncoding`].
+    pub fn serialize_xml_bytes_with_encoding(
+        &self,
+        parameters: output::xml::Parameters,
+        node: Node,
+        encoding: &str,
+    ) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        self.serialize_xml_write_with_encoding(parameters, node, encoding, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Write a node as XML in an encoding.
+    ///
+    /// See [`Xot::serialize_xml_bytes_with_encoding`] for details.
+    pub fn serialize_xml_write_with_encoding(
+        &self,
+        mut parameters: output::xml::Parameters,
+        node: Node,
+        encoding: &str,
+        w: &mut impl Write,
+    ) -> Result<(), Error> {
+        // encoding_rs follows the WHATWG Encoding Standard, which reads the
+        // labels of ASCII and ISO-8859-1 as windows-1252. We write these
+        // ourselves, so that we write what we're asked for.
+        let (name, encoding, max_character) = match single_byte_encoding(encoding) {
+            Some((name, max_character)) => (name, None, Some(max_character)),
+            None => {
+                let encoding = Encoding::for_label(encoding.as_bytes())
+                    .ok_or_else(|| Error::UnsupportedEncoding(encoding.to_string()))?;
+                if encoding == UTF_16LE || encoding == UTF_16BE {
+                    // the byte order mark tells which UTF-16 it is
+                    ("UTF-16", Some(encoding), None)
+                } else {
+                    let encoding = encoding.output_encoding();
+                    (encoding.name(), Some(encoding), None)
+                }
+            }
+        };
+        let is_utf_16 = encoding == Some(UTF_16LE) || encoding == Some(UTF_16BE);
+        let is_utf_8 = encoding == Some(UTF_8);
+        if let Some(declaration) = &mut parameters.declaration {
+            declaration.encoding = Some(name.to_string());
+        } else if !is_utf_8 && !is_utf_16 {
+            parameters.declaration = Some(output::xml::Declaration {
+                encoding: Some(name.to_string()),
+                ..Default::default()
+            });
+        }
+        let character_references = parameters.character_references;
+        if is_utf_8 {
+            return self.serialize_xml_write(parameters, node, w);
+        }
+        parameters.byte_order_mark = false;
+        // the limit is on the encoded output. A character takes at least one
+        // byte in any encoding, and at most four in UTF-8, so we can stop
+        // once the UTF-8 gets larger than four times the limit
+        let max_output_size = parameters.max_output_size;
+        parameters.max_output_size = max_output_size.map(|limit| limit.saturating_mul(4));
+        let mut token_parameters = parameters.token_serialize_parameters();
+        if !is_utf_16 {
+            token_parameters.encoding = encoding;
+            token_parameters.max_character = max_character;
+        }
+        let serializer = XmlSerializer::new(self, node, token_parameters, NoopNormalizer);
+        let mut buf = Vec::new();
+        self.write_xml(parameters, serializer, node, &mut buf)
+            .map_err(|error| match (error, max_output_size) {
+                (Error::OutputTooLarge(_), Some(limit)) => Error::OutputTooLarge(limit),
+                (error, _) => error,
+            })?;
+        let xml = String::from_utf8(buf).unwrap();
+        let mut w = LimitedWriter::new(w, max_output_size);
+        let result = match (encoding, max_character) {
+            (_, Some(max_character)) => {
+                encode_single_byte(&xml, max_character, character_references, &mut w)
+            }
+            (Some(encoding), None) if !is_utf_16 => {
+                let mut encoder = encoding.new_encoder();
+                encode(&mut encoder, &xml, true, character_references, &mut w)
+            }
+            (encoding, None) => {
+                let big_endian = encoding == Some(UTF_16BE);
+                let mut bytes = Vec::with_capacity((xml.len() + 1) * 2);
+                for unit in std::iter::once(0xFEFF).chain(xml.encode_utf16()) {
+                    if big_endian {
+                        bytes.extend_from_slice(&unit.to_be_bytes());
+                    } else {
+                        bytes.extend_from_slice(&unit.to_le_bytes());
+                    }
+                }
+                w.write_all(&bytes).map_err(Error::from)
+            }
+        };
+        w.check(result)
+    }
+
+    /// Serialize a node as canonical XML.
+    ///
+    /// The node can be a document node, or any node in a document, such as
+    /// an element that you want to sign. See [`output::c14n::Parameters`] for
+    /// the canonicalization methods.
+    ///
+    /// ```rust
+    /// use xot::{Xot, output};
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<a xmlns:x="urn:x" xmlns:y="urn:y" b='2' a="1"><x:b/></a>"#)?;
+    ///
+    /// let xml = xot.serialize_c14n_string(Default::default(), root)?;
+    /// assert_eq!(xml, r#"<a xmlns:x="urn:x" xmlns:y="urn:y" a="1" b="2"><x:b></x:b></a>"#);
+    ///
+    /// // with exclusive canonicalization, only the namespaces used are declared
+    /// let b = xot.first_child(xot.document_element(root)?).unwrap();
+    /// let xml = xot.serialize_c14n_string(output::c14n::Parameters {
+    ///     method: output::c14n::Method::Exclusive {
+    ///         inclusive_prefixes: Vec::new(),
+    ///     },
+    ///     ..Default::default()
+    /// }, b)?;
+    /// assert_eq!(xml, r#"<x:b xmlns:x="urn:x"></x:b>"#);
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn serialize_c14n_string(
+        &self,
+        parameters: output::c14n::Parameters,
+        node: Node,
+    ) -> Result<String, Error> {
+        let mut buf = Vec::new();
+        self.serialize_c14n_write(parameters, node, &mut buf)?;
+        Ok(String::from_utf8(buf).unwrap())
+    }
+
+    /// Write canonical XML via a [`Write`].
+    ///
+    /// This is like [`Xot::serialize_c14n_string`] but writes to a [`Write`],
+    /// for instance to compute a digest without creating a string.
+    pub fn serialize_c14n_write(
+        &self,
+        parameters: output::c14n::Parameters,
+        node: Node,
+        w: &mut impl Write,
+    ) -> Result<(), Error> {
+        output::c14n::serialize(self, &parameters, node, w)
+    }
+
+    /// Serialize a node to HTML.
+    ///
+    /// This follows the HTML serialization algorithm: void elements such as
+    /// `<br>` don't get an end tag, the content of raw text elements such as
+    /// `<script>` and `<style>` isn't escaped, boolean attributes are
+    /// minimized and elements in the XHTML namespace are serialized without
+    /// prefix.
+    ///
+    /// Unlike [`Xot::html5`], a doctype is only emitted if you serialize a
+    /// document node, so you can use this to serialize fragments of HTML. The
+    /// doctype of the document (see [`Xot::doctype`]) is used if it has one,
+    /// and `<!DOCTYPE html>` otherwise.
+    ///
+    /// This is a mutable call as it needs to create HTML names first.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<p>A<br/>B<script>a &lt; b</script></p>"#)?;
+    /// let p = xot.document_element(root)?;
+    /// assert_eq!(xot.html_string(p)?, "<p>A<br>B<script>a < b</script></p>");
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn html_string(&mut self, node: Node) -> Result<String, Error> {
+        let mut buf = Vec::new();
+        self.serialize_html(node, &mut buf)?;
+        Ok(String::from_utf8(buf).unwrap())
+    }
+
+    /// Write a node as HTML.
+    ///
+    /// See [`Xot::html_string`] for details.
+    pub fn serialize_html(&mut self, node: Node, w: &mut impl Write) -> Result<(), Error> {
+        self.serialize_html_write(Default::default(), node, w)
+    }
+
+    /// Write a node as HTML, with options.
+    ///
+    /// This lets you pretty-print the HTML, choose the encoding of the
+    /// output, and declare it in a `<meta charset>` element so that it
+    /// matches the bytes that are produced. See [`output::html::Parameters`]
+    /// for details.
+    ///
+    /// ```rust
+    /// use xot::{Xot, output};
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<html><head><meta charset="utf-8"/></head><body>é</body></html>"#)?;
+    /// let mut buf = Vec::new();
+    /// xot.serialize_html_write(output::html::Parameters {
+    ///     encoding: Some("iso-8859-1".to_string()),
+    ///     meta_charset: true,
+    ///     ..Default::default()
+    /// }, root, &mut buf)?;
+    /// assert_eq!(buf, b"<!DOCTYPE html><html><head><meta charset=\"windows-1252\"></head><body>\xe9</body></html>");
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn serialize_html_write(
+        &mut self,
+        parameters: output::html::Parameters,
+        node: Node,
+        w: &mut impl Write,
+    ) -> Result<(), Error> {
+        let encoding = match &parameters.encoding {
+            Some(label) => Encoding::for_label(label.as_bytes())
+                .ok_or_else(|| Error::UnsupportedEncoding(label.clone()))?
+                .output_encoding(),
+            None => UTF_8,
+        };
+        // we change a copy so that the tree isn't changed
+        let body = if parameters.meta_charset || parameters.indentation.is_some() {
+            self.clone_node(node)
+        } else {
+            node
+        };
+        if parameters.meta_charset {
+            set_meta_charset(self, body, encoding.name());
+        }
+        if parameters.indentation.is_some() {
+            // we indent instead
+            drop_inter_element_whitespace(self, body);
+        }
+        let indentation = parameters.indentation;
+        let result = if encoding == UTF_8 {
+            self.write_html(indentation, node, body, w)
+        } else {
+            let mut buf = Vec::new();
+            self.write_html(indentation, node, body, &mut buf).and_then(|_| {
+                let html = String::from_utf8(buf).unwrap();
+                let (bytes, _, _) = encoding.encode(&html);
+                Ok(w.write_all(&bytes)?)
+            })
+        };
+        if body != node {
+            self.remove(body)?;
+        }
+        result
+    }
+
+    // Write `body` as HTML, with the doctype of `node` if it's a document.
+    fn write_html(
+        &mut self,
+        indentation: Option<output::Indentation>,
+        node: Node,
+        body: Node,
+        w: &mut impl Write,
+    ) -> Result<(), Error> {
+        let html5 = self.html5();
+        if html5.xot.is_document(node) {
+            if let Some(doctype) = html5.xot.doctype(node) {
+                doctype.serialize(w)?;
+            } else {
+                w.write_all(b"<!DOCTYPE html>")?;
+            }
+        }
+        html5.serialize_html_body(indentation, body, w)
+    }
+
+    /// Serialize a node as XHTML, with options.
+    ///
+    /// This produces polyglot markup, which can be read both by XML and by
+    /// HTML parsers. See [`output::xhtml::Parameters`] for details.
+    ///
+    /// This is a mutable call as it needs to create HTML names first.
+    ///
+    /// ```rust
+    /// use xot::{Xot, output};
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<p xmlns="http://www.w3.org/1999/xhtml">A<br/>B<span/></p>"#)?;
+    /// let p = xot.document_element(root)?;
+    /// let xhtml = xot.serialize_xhtml_string(output::xhtml::Parameters::default(), p)?;
+    /// assert_eq!(xhtml, r#"<p xmlns="http://www.w3.org/1999/xhtml">A<br />B<span></span></p>"#);
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn serialize_xhtml_string(
+        &mut self,
+        parameters: output::xhtml::Parameters,
+        node: Node,
+    ) -> Result<String, Error> {
+        let mut buf = Vec::new();
+        self.serialize_xhtml_write(parameters, node, &mut buf)?;
+        Ok(String::from_utf8(buf).unwrap())
+    }
+
+    /// Write a node as XHTML, with options.
+    ///
+    /// See [`Xot::serialize_xhtml_string`] for details.
+    pub fn serialize_xhtml_write(
+        &mut self,
+        parameters: output::xhtml::Parameters,
+        node: Node,
+        w: &mut impl Write,
+    ) -> Result<(), Error> {
+        let html5_elements = Html5Elements::new(self);
+        if self.is_document(node) {
+            if let Some(doctype) = self.doctype(node) {
+                doctype.serialize(w)?;
+            } else {
+                w.write_all(b"<!DOCTYPE html>")?;
+            }
         }
         let outputs = gen_outputs(self, node);
-        let mut serializer = XmlSerializer::new(
-            self,
-            node,
-            TokenSerializeParameters {
-                cdata_section_elements: parameters.cdata_section_elements,
-                unescaped_gt: parameters.unescaped_gt,
-            },
-            normalizer,
-        );
+        let mut serializer = XhtmlSerializer::new(self, &html5_elements, node, NoopNormalizer);
         if let Some(indentation) = parameters.indentation {
-            serializer.serialize_pretty(w, outputs, &indentation.suppress)?;
+            serializer.serialize_pretty(w, outputs, &indentation)?;
         } else {
             serializer.serialize(w, outputs)?;
         }
@@ -407,6 +1153,7 @@ assert_eq!(s, "<doc>\u{1E0D}\u{0307}</doc>");
             self,
             |name| suppress_elements.contains(&name),
             |_name| false,
+            None,
         );
         outputs.map(move |(node, output)| {
             let (indentation, newline) = pretty.prettify(node, &output);
@@ -424,3 +1171,198 @@ assert_eq!(s, "<doc>\u{1E0D}\u{0307}</doc>");
         })
     }
 }
+
+impl Node {
+    /// Serialize this node as XML, with options you set one by one.
+    ///
+    /// See [`output::xml::Serializer`] for the options.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse("<a><b/></a>")?;
+    ///
+    /// let xml = root.serialize(&xot).declaration(true).to_string()?;
+    /// assert_eq!(xml, "<?xml version=\"1.0\"?>\n<a><b/></a>");
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn serialize(self, xot: &Xot) -> output::xml::Serializer<'_> {
+        output::xml::Serializer::new(xot, self)
+    }
+}
+
+// Fails once more than `limit` bytes are written to it, so that we can tell
+// that apart from other IO errors.
+struct LimitedWriter<W: Write> {
+    inner: W,
+    limit: Option<usize>,
+    written: usize,
+}
+
+impl<W: Write> LimitedWriter<W> {
+    fn new(inner: W, limit: Option<usize>) -> Self {
+        LimitedWriter {
+            inner,
+            limit,
+            written: 0,
+        }
+    }
+
+    // The result of writing, unless it failed because of the limit.
+    fn check<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        match self.limit {
+            Some(limit) if self.written > limit => Err(Error::OutputTooLarge(limit)),
+            _ => result,
+        }
+    }
+}
+
+impl<W: Write> Write for LimitedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.written += buf.len();
+        if self.limit.is_some_and(|limit| self.written > limit) {
+            return Err(std::io::Error::other("output too large"));
+        }
+        self.inner.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Leave out defaulted attributes if we're asked to, and put the attributes
+// in order.
+fn xml_outputs<'a>(
+    xot: &'a Xot,
+    outputs: impl Iterator<Item = (Node, Output<'a>)> + 'a,
+    parameters: &output::xml::Parameters,
+) -> impl Iterator<Item = (Node, Output<'a>)> + 'a {
+    let omit_defaulted_attributes = parameters.omit_defaulted_attributes;
+    let outputs = outputs.filter(move |(node, output)| match output {
+        Output::Attribute(name, _) if omit_defaulted_attributes => {
+            !xot.is_defaulted_attribute(*node, *name)
+        }
+        _ => true,
+    });
+    order_attributes(xot, outputs, parameters.attribute_order.clone())
+}
+
+fn write_xml_output<N: Normalizer>(
+    serializer: &mut XmlSerializer<N>,
+    pretty: &mut Option<PrettyWriter<impl Fn(NameId) -> bool>>,
+    w: &mut impl Write,
+    node: Node,
+    output: Output,
+) -> Result<(), Error> {
+    if let Some(pretty) = pretty {
+        pretty.write(serializer, w, node, output)
+    } else {
+        serializer.serialize_node(w, node, output)
+    }
+}
+
+// Encode `text`, writing characters the encoding doesn't have as character
+// references.
+fn encode(
+    encoder: &mut Encoder,
+    mut text: &str,
+    last: bool,
+    character_references: output::xml::CharacterReferences,
+    w: &mut impl Write,
+) -> Result<(), Error> {
+    let mut buf = [0; 4096];
+    loop {
+        let (result, read, written) =
+            encoder.encode_from_utf8_without_replacement(text, &mut buf, last);
+        w.write_all(&buf[..written])?;
+        text = &text[read..];
+        match result {
+            EncoderResult::InputEmpty => return Ok(()),
+            EncoderResult::OutputFull => {}
+            EncoderResult::Unmappable(c) => {
+                // a reference is ASCII, so it can always be encoded; we do
+                // so with the encoder, as it may have to switch back to
+                // ASCII first
+                let reference = character_reference(c, character_references);
+                encode(encoder, &reference, false, character_references, w)?;
+            }
+        }
+    }
+}
+
+// The name and highest character of an encoding that writes each character
+// up to that one as a single byte with its code point, if `label` is the
+// label of one.
+fn single_byte_encoding(label: &str) -> Option<(&'static str, char)> {
+    match label.trim().to_ascii_lowercase().as_str() {
+        "us-ascii" | "ascii" | "ansi_x3.4-1968" | "iso646-us" | "csascii" => {
+            Some(("US-ASCII", '\u{7F}'))
+        }
+        "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1" | "l1" | "iso-ir-100" | "ibm819"
+        | "cp819" | "csisolatin1" => Some(("ISO-8859-1", '\u{FF}')),
+        _ => None,
+    }
+}
+
+// Like `encode`, for a single byte encoding as given by
+// `single_byte_encoding`.
+fn encode_single_byte(
+    text: &str,
+    max_character: char,
+    character_references: output::xml::CharacterReferences,
+    w: &mut impl Write,
+) -> Result<(), Error> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        if c <= max_character {
+            bytes.push(c as u8);
+        } else {
+            bytes.extend_from_slice(character_reference(c, character_references).as_bytes());
+        }
+    }
+    w.write_all(&bytes)?;
+    Ok(())
+}
+
+// Declare `charset` in the `<meta>` elements of the HTML head in `node`, or
+// add a `<meta charset>` element to it.
+fn set_meta_charset(xot: &mut Xot, node: Node, charset: &str) {
+    let head = xot
+        .descendants(node)
+        .find(|node| html_name(xot, *node).as_deref() == Some("head"));
+    let Some(head) = head else {
+        return;
+    };
+    let charset_name = xot.add_name("charset");
+    let http_equiv_name = xot.add_name("http-equiv");
+    let content_name = xot.add_name("content");
+    let metas = xot
+        .children(head)
+        .filter(|node| html_name(xot, *node).as_deref() == Some("meta"))
+        .collect::<Vec<_>>();
+    let mut declared = false;
+    for meta in metas {
+        let mut attributes = xot.attributes_mut(meta);
+        if attributes.contains_key(charset_name) {
+            attributes.insert(charset_name, charset.to_string());
+            declared = true;
+        } else if attributes
+            .get(http_equiv_name)
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("content-type"))
+        {
+            attributes.insert(content_name, format!("text/html; charset={}", charset));
+            declared = true;
+        }
+    }
+    if !declared {
+        let namespace = xot.namespace_for_name(xot.element(head).unwrap().name());
+        let meta_name = xot.add_name_ns("meta", namespace);
+        let meta = xot.new_element(meta_name);
+        xot.attributes_mut(meta)
+            .insert(charset_name, charset.to_string());
+        xot.prepend(head, meta).unwrap();
+    }
+}
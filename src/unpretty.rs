@@ -1,3 +1,4 @@
+use crate::xmlspace::XmlSpace;
 use crate::xotdata::{Node, Xot};
 
 pub(crate) fn remove_insignificant_whitespace(xot: &mut Xot, node: Node) {
@@ -24,20 +25,9 @@ fn is_significant_text_node(xot: &Xot, node: Node) -> bool {
     }
 }
 
-fn in_preserve_space(xot: &Xot, node: Node) -> bool {
-    let space = xot.xml_space_name();
-    for ancestor in xot.ancestors(node) {
-        let attributes = xot.attributes(ancestor);
-        if let Some(value) = attributes.get(space) {
-            return value == "preserve";
-        }
-    }
-    false
-}
-
 fn is_insignificant_whitespace(xot: &Xot, node: Node) -> bool {
     if let Some(text) = xot.text_str(node) {
-        if in_preserve_space(xot, node) {
+        if xot.xml_space(node) == XmlSpace::Preserve {
             return false;
         }
         if !is_whitespace(text) {
@@ -0,0 +1,317 @@
+// Resolution of URL references, following RFC 3986, section 5.
+
+// The components of a URL reference, following the regular expression in
+// RFC 3986, appendix B.
+struct Components<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: &'a str,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+fn is_scheme(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+impl<'a> Components<'a> {
+    fn parse(s: &'a str) -> Self {
+        let (s, fragment) = match s.split_once('#') {
+            Some((s, fragment)) => (s, Some(fragment)),
+            None => (s, None),
+        };
+        let (s, query) = match s.split_once('?') {
+            Some((s, query)) => (s, Some(query)),
+            None => (s, None),
+        };
+        let (scheme, s) = match s.split_once(':') {
+            Some((scheme, rest)) if is_scheme(scheme) => (Some(scheme), rest),
+            _ => (None, s),
+        };
+        let (authority, path) = match s.strip_prefix("//") {
+            Some(rest) => {
+                let end = rest.find('/').unwrap_or(rest.len());
+                (Some(&rest[..end]), &rest[end..])
+            }
+            None => (None, s),
+        };
+        Components {
+            scheme,
+            authority,
+            path,
+            query,
+            fragment,
+        }
+    }
+}
+
+// RFC 3986, section 5.2.4
+fn remove_dot_segments(path: &str) -> String {
+    let mut input = path;
+    let mut output: Vec<&str> = Vec::new();
+    while !input.is_empty() {
+        if let Some(rest) = input.strip_prefix("../") {
+            input = rest;
+        } else if let Some(rest) = input.strip_prefix("./") {
+            input = rest;
+        } else if input.starts_with("/./") {
+            input = &input[2..];
+        } else if input == "/." {
+            input = "/";
+        } else if input.starts_with("/../") {
+            input = &input[3..];
+            output.pop();
+        } else if input == "/.." {
+            input = "/";
+            output.pop();
+        } else if input == "." || input == ".." {
+            input = "";
+        } else {
+            // move the first segment, including its leading slash, to the
+            // output
+            let start = usize::from(input.starts_with('/'));
+            let end = input[start..]
+                .find('/')
+                .map(|i| i + start)
+                .unwrap_or(input.len());
+            output.push(&input[..end]);
+            input = &input[end..];
+        }
+    }
+    output.concat()
+}
+
+// RFC 3986, section 5.2.3
+fn merge(base: &Components, path: &str) -> String {
+    if base.authority.is_some() && base.path.is_empty() {
+        format!("/{}", path)
+    } else {
+        match base.path.rfind('/') {
+            Some(i) => format!("{}{}", &base.path[..=i], path),
+            None => path.to_string(),
+        }
+    }
+}
+
+/// Resolve a URL reference against an absolute base URL.
+///
+/// Returns `None` if the base URL isn't absolute.
+pub(crate) fn resolve(base: &str, reference: &str) -> Option<String> {
+    let base = Components::parse(base);
+    let base_scheme = base.scheme?;
+    let reference = Components::parse(reference);
+
+    // RFC 3986, section 5.2.2
+    let (scheme, authority, path, query) = if let Some(scheme) = reference.scheme {
+        (
+            scheme,
+            reference.authority,
+            remove_dot_segments(reference.path),
+            reference.query,
+        )
+    } else if reference.authority.is_some() {
+        (
+            base_scheme,
+            reference.authority,
+            remove_dot_segments(reference.path),
+            reference.query,
+        )
+    } else if reference.path.is_empty() {
+        (
+            base_scheme,
+            base.authority,
+            base.path.to_string(),
+            reference.query.or(base.query),
+        )
+    } else if reference.path.starts_with('/') {
+        (
+            base_scheme,
+            base.authority,
+            remove_dot_segments(reference.path),
+            reference.query,
+        )
+    } else {
+        (
+            base_scheme,
+            base.authority,
+            remove_dot_segments(&merge(&base, reference.path)),
+            reference.query,
+        )
+    };
+
+    // RFC 3986, section 5.3
+    let mut result = format!("{}:", scheme);
+    if let Some(authority) = authority {
+        result.push_str("//");
+        result.push_str(authority);
+    }
+    result.push_str(&path);
+    if let Some(query) = query {
+        result.push('?');
+        result.push_str(query);
+    }
+    if let Some(fragment) = reference.fragment {
+        result.push('#');
+        result.push_str(fragment);
+    }
+    Some(result)
+}
+
+// Like `remove_dot_segments`, but for a relative path, where `..` segments
+// that go above the start of the path are kept.
+fn remove_dot_segments_relative(path: &str) -> String {
+    let mut output: Vec<&str> = Vec::new();
+    let segments = path.split('/').collect::<Vec<_>>();
+    for (i, segment) in segments.iter().enumerate() {
+        let last = i == segments.len() - 1;
+        match *segment {
+            "." | ".." => {
+                if *segment == ".." {
+                    if output.last().is_some_and(|last| *last != "..") {
+                        output.pop();
+                    } else {
+                        output.push("..");
+                    }
+                }
+                // a path that ends with a dot segment refers to a directory
+                if last {
+                    output.push("");
+                }
+            }
+            segment => output.push(segment),
+        }
+    }
+    output.join("/")
+}
+
+/// Join a URL reference to a base URL, which can be relative itself.
+///
+/// This is how Canonical XML 1.1 combines `xml:base` attributes. With an
+/// absolute base URL this is the same as [`resolve`].
+pub(crate) fn join(base: &str, reference: &str) -> String {
+    if let Some(resolved) = resolve(base, reference) {
+        return resolved;
+    }
+    let base = Components::parse(base);
+    let parsed = Components::parse(reference);
+    if parsed.scheme.is_some() || parsed.authority.is_some() {
+        return reference.to_string();
+    }
+    let mut result = String::new();
+    if let Some(authority) = base.authority {
+        result.push_str("//");
+        result.push_str(authority);
+    }
+    let query = if parsed.path.is_empty() {
+        result.push_str(base.path);
+        parsed.query.or(base.query)
+    } else {
+        let path = if parsed.path.starts_with('/') {
+            parsed.path.to_string()
+        } else {
+            merge(&base, parsed.path)
+        };
+        if path.starts_with('/') {
+            result.push_str(&remove_dot_segments(&path));
+        } else {
+            result.push_str(&remove_dot_segments_relative(&path));
+        }
+        parsed.query
+    };
+    if let Some(query) = query {
+        result.push('?');
+        result.push_str(query);
+    }
+    if let Some(fragment) = parsed.fragment {
+        result.push('#');
+        result.push_str(fragment);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    // RFC 3986, section 5.4
+    #[rstest]
+    #[case("g:h", "g:h")]
+    #[case("g", "http://a/b/c/g")]
+    #[case("./g", "http://a/b/c/g")]
+    #[case("g/", "http://a/b/c/g/")]
+    #[case("/g", "http://a/g")]
+    #[case("//g", "http://g")]
+    #[case("?y", "http://a/b/c/d;p?y")]
+    #[case("g?y", "http://a/b/c/g?y")]
+    #[case("#s", "http://a/b/c/d;p?q#s")]
+    #[case("g#s", "http://a/b/c/g#s")]
+    #[case("g?y#s", "http://a/b/c/g?y#s")]
+    #[case(";x", "http://a/b/c/;x")]
+    #[case("g;x", "http://a/b/c/g;x")]
+    #[case("g;x?y#s", "http://a/b/c/g;x?y#s")]
+    #[case("", "http://a/b/c/d;p?q")]
+    #[case(".", "http://a/b/c/")]
+    #[case("./", "http://a/b/c/")]
+    #[case("..", "http://a/b/")]
+    #[case("../", "http://a/b/")]
+    #[case("../g", "http://a/b/g")]
+    #[case("../..", "http://a/")]
+    #[case("../../", "http://a/")]
+    #[case("../../g", "http://a/g")]
+    #[case("../../../g", "http://a/g")]
+    #[case("../../../../g", "http://a/g")]
+    #[case("/./g", "http://a/g")]
+    #[case("/../g", "http://a/g")]
+    #[case("g.", "http://a/b/c/g.")]
+    #[case(".g", "http://a/b/c/.g")]
+    #[case("g..", "http://a/b/c/g..")]
+    #[case("..g", "http://a/b/c/..g")]
+    #[case("./../g", "http://a/b/g")]
+    #[case("./g/.", "http://a/b/c/g/")]
+    #[case("g/./h", "http://a/b/c/g/h")]
+    #[case("g/../h", "http://a/b/c/h")]
+    #[case("g;x=1/./y", "http://a/b/c/g;x=1/y")]
+    #[case("g;x=1/../y", "http://a/b/c/y")]
+    #[case("g?y/./x", "http://a/b/c/g?y/./x")]
+    #[case("g?y/../x", "http://a/b/c/g?y/../x")]
+    #[case("g#s/./x", "http://a/b/c/g#s/./x")]
+    #[case("g#s/../x", "http://a/b/c/g#s/../x")]
+    fn test_resolve_rfc3986_examples(#[case] reference: &str, #[case] expected: &str) {
+        assert_eq!(
+            resolve("http://a/b/c/d;p?q", reference).as_deref(),
+            Some(expected)
+        );
+    }
+
+    #[test]
+    fn test_resolve_empty_base_path() {
+        assert_eq!(
+            resolve("http://example.com", "a").as_deref(),
+            Some("http://example.com/a")
+        );
+    }
+
+    #[test]
+    fn test_resolve_relative_base() {
+        assert_eq!(resolve("/a/b", "c"), None);
+    }
+
+    #[rstest]
+    #[case("http://a/b/", "c/d", "http://a/b/c/d")]
+    #[case("a/", "b/", "a/b/")]
+    #[case("a/b", "c", "a/c")]
+    #[case("a/", "../b", "b")]
+    #[case("a/", "../../b/", "../b/")]
+    #[case("../a/", "../../b", "../../b")]
+    #[case("a/b/", "..", "a/")]
+    #[case("/a/b/", "../../../c", "/c")]
+    #[case("a/b", "?q#f", "a/b?q#f")]
+    #[case("a/", "http://b/", "http://b/")]
+    fn test_join(#[case] base: &str, #[case] reference: &str, #[case] expected: &str) {
+        assert_eq!(join(base, reference), expected);
+    }
+}
@@ -1,10 +1,25 @@
 use crate::access::NodeEdge;
+use crate::htmltext::html_text;
 use crate::xmlvalue::{
-    Attribute, Comment, Element, Namespace, ProcessingInstruction, Text, Value, ValueType,
+    Attribute, Comment, Element, EntityReference, Namespace, ProcessingInstruction, Text, Value,
+    ValueType,
 };
 use crate::xotdata::{Node, Xot};
 use crate::NameId;
 
+/// Options for [`Xot::string_value_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct StringValueOptions {
+    /// Normalize whitespace.
+    ///
+    /// Leading and trailing whitespace is removed, and each run of
+    /// whitespace in between is replaced by a single space, as the XPath
+    /// `normalize-space` function does. Whitespace is space, tab, carriage
+    /// return and newline; other characters, such as a non-breaking space,
+    /// are kept.
+    pub normalize_space: bool,
+}
+
 /// ## Value and type access
 impl Xot {
     /// Access to the XML value for this node.
@@ -61,7 +76,13 @@ impl Xot {
     /// methods like [`Xot::text_mut`]
     #[inline]
     pub fn value_mut(&mut self, node_id: Node) -> &mut Value {
-        self.arena[node_id.get()].get_mut()
+        self.arena_mut()[node_id.get()].get_mut()
+    }
+
+    // Like `value_mut`, for a value that can't change the ID and element
+    // name indexes, so they stay up to date.
+    fn content_value_mut(&mut self, node: Node) -> &mut Value {
+        self.arena[node.get()].get_mut()
     }
 
     /// Get the [`ValueType`](crate::xmlvalue::ValueType) of a node.
@@ -171,6 +192,16 @@ impl Xot {
         self.value_type(node) == ValueType::Text
     }
 
+    /// Return true if node is a CDATA section.
+    pub fn is_cdata(&self, node: Node) -> bool {
+        self.value_type(node) == ValueType::Cdata
+    }
+
+    /// Return true if node is an entity reference.
+    pub fn is_entity_reference(&self, node: Node) -> bool {
+        self.value_type(node) == ValueType::EntityReference
+    }
+
     /// Return true if node is a comment.
     pub fn is_comment(&self, node: Node) -> bool {
         self.value_type(node) == ValueType::Comment
@@ -264,7 +295,7 @@ impl Xot {
     /// # Ok::<(), xot::Error>(())
     /// ```
     pub fn text_mut(&mut self, node: Node) -> Option<&mut Text> {
-        let xml_node = self.value_mut(node);
+        let xml_node = self.content_value_mut(node);
         if let Value::Text(text) = xml_node {
             Some(text)
         } else {
@@ -450,6 +481,56 @@ impl Xot {
         self.text_content(node).map(|n| n.get())
     }
 
+    /// If this node's value is a CDATA section, return a reference to its
+    /// text.
+    pub fn cdata(&self, node: Node) -> Option<&Text> {
+        let xml_node = self.value(node);
+        if let Value::Cdata(text) = xml_node {
+            Some(text)
+        } else {
+            None
+        }
+    }
+
+    /// If this node's value is a CDATA section, return a reference to the
+    /// string.
+    pub fn cdata_str(&self, node: Node) -> Option<&str> {
+        self.cdata(node).map(|n| n.get())
+    }
+
+    /// If this node's value is a CDATA section, return a mutable reference to
+    /// its text.
+    pub fn cdata_mut(&mut self, node: Node) -> Option<&mut Text> {
+        let xml_node = self.content_value_mut(node);
+        if let Value::Cdata(text) = xml_node {
+            Some(text)
+        } else {
+            None
+        }
+    }
+
+    /// If this node's value is an entity reference, return a reference to
+    /// it.
+    pub fn entity_reference(&self, node: Node) -> Option<&EntityReference> {
+        let xml_node = self.value(node);
+        if let Value::EntityReference(entity_reference) = xml_node {
+            Some(entity_reference)
+        } else {
+            None
+        }
+    }
+
+    /// If this node's value is an entity reference, return a mutable
+    /// reference to it.
+    pub fn entity_reference_mut(&mut self, node: Node) -> Option<&mut EntityReference> {
+        let xml_node = self.content_value_mut(node);
+        if let Value::EntityReference(entity_reference) = xml_node {
+            Some(entity_reference)
+        } else {
+            None
+        }
+    }
+
     /// If this node's value is a comment, return a reference to it.
     pub fn comment(&self, node: Node) -> Option<&Comment> {
         let xml_node = self.value(node);
@@ -467,7 +548,7 @@ impl Xot {
 
     /// If this node's value is a comment, return a mutable reference to it.
     pub fn comment_mut(&mut self, node: Node) -> Option<&mut Comment> {
-        let xml_node = self.value_mut(node);
+        let xml_node = self.content_value_mut(node);
         if let Value::Comment(comment) = xml_node {
             Some(comment)
         } else {
@@ -487,7 +568,7 @@ impl Xot {
 
     /// If this node's value is a processing instruction, return a mutable reference to it.
     pub fn processing_instruction_mut(&mut self, node: Node) -> Option<&mut ProcessingInstruction> {
-        let xml_node = self.value_mut(node);
+        let xml_node = self.content_value_mut(node);
         if let Value::ProcessingInstruction(pi) = xml_node {
             Some(pi)
         } else {
@@ -538,9 +619,12 @@ impl Xot {
     /// Given a node, give back a string representation.
     ///
     /// For the root node and element nodes this gives back all text node
-    /// descendant content, concatenated.
+    /// and CDATA section descendant content, concatenated.
+    ///
+    /// For text nodes and CDATA sections, it gives back the text.
     ///
-    /// For text nodes, it gives back the text.
+    /// For entity references, it gives back the empty string, as their
+    /// content isn't known.
     ///
     /// For comments, it gives back the comment text.
     ///
@@ -552,10 +636,14 @@ impl Xot {
     ///
     /// This is defined by the `string-value` property in
     /// <https://www.w3.org/TR/xpath-datamodel-31>
+    ///
+    /// To normalize whitespace in the result, use
+    /// [`Xot::string_value_with_options`].
     pub fn string_value(&self, node: Node) -> String {
         match self.value(node) {
             Value::Document | Value::Element(_) => descendants_to_string(self, node),
-            Value::Text(text) => text.get().to_string(),
+            Value::Text(text) | Value::Cdata(text) => text.get().to_string(),
+            Value::EntityReference(_) => String::new(),
             Value::ProcessingInstruction(pi) => pi.data().unwrap_or("").to_string(),
             Value::Comment(comment) => comment.get().to_string(),
             Value::Attribute(attribute) => attribute.value().to_string(),
@@ -566,6 +654,105 @@ impl Xot {
         }
     }
 
+    /// Given a node, give back a string representation, with options.
+    ///
+    /// This is like [`Xot::string_value`], but you can ask for whitespace to
+    /// be normalized, as the XPath `normalize-space` function does.
+    ///
+    /// ```rust
+    /// use xot::{StringValueOptions, Xot};
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse("<p>\n  Some <b>bold</b>\n  text\n</p>")?;
+    /// let options = StringValueOptions {
+    ///     normalize_space: true,
+    /// };
+    /// assert_eq!(xot.string_value_with_options(root, &options), "Some bold text");
+    /// assert_eq!(
+    ///     xot.string_value_with_options(root, &StringValueOptions::default()),
+    ///     xot.string_value(root)
+    /// );
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn string_value_with_options(&self, node: Node, options: &StringValueOptions) -> String {
+        let value = self.string_value(node);
+        if options.normalize_space {
+            normalize_space(&value)
+        } else {
+            value
+        }
+    }
+
+    /// Given a node, give back its text as it would be rendered as HTML.
+    ///
+    /// This is modeled after the `innerText` property in the DOM, and is
+    /// useful to extract readable text from HTML. Unlike
+    /// [`Xot::string_value`] it takes HTML rendering into account:
+    ///
+    /// - whitespace is collapsed, except in elements like `<pre>`,
+    ///
+    /// - block elements like `<div>` are separated by a line break, and
+    ///   paragraphs (`<p>`) by an empty line,
+    ///
+    /// - `<br>` is a line break,
+    ///
+    /// - table cells are separated by tabs, and rows by line breaks,
+    ///
+    /// - the content of elements that aren't rendered, such as `<script>`,
+    ///   `<style>` and `<head>`, is skipped.
+    ///
+    /// Elements in the XHTML namespace and in no namespace are treated as
+    /// HTML elements.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(
+    ///     "<div><h1>Title</h1>\n  <p>Some   <b>bold</b> text<br/>next line</p><script>x()</script><p>More</p></div>",
+    /// )?;
+    /// assert_eq!(
+    ///     xot.html_text(root),
+    ///     "Title\n\nSome bold text\nnext line\n\nMore"
+    /// );
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn html_text(&self, node: Node) -> String {
+        html_text(self, node)
+    }
+
+    /// Given a node, convert the HTML in it to Markdown.
+    ///
+    /// This requires the `markdown` feature.
+    ///
+    /// This is useful if you need a plain text representation of HTML that
+    /// retains its structure. Headings, paragraphs, emphasis, inline code,
+    /// links, images, lists, block quotes, code blocks (`<pre>`) and tables
+    /// are converted. Other elements contribute their content, except for
+    /// elements that aren't rendered such as `<script>` and `<head>`. Text
+    /// is escaped so that it isn't read as Markdown syntax.
+    ///
+    /// Elements in the XHTML namespace and in no namespace are treated as
+    /// HTML elements.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(
+    ///     r#"<div><h1>Title</h1><p>Some <b>bold</b> <a href="https://example.com">link</a></p><ul><li>One</li><li>Two</li></ul></div>"#,
+    /// )?;
+    /// assert_eq!(
+    ///     xot.html_markdown(root),
+    ///     "# Title\n\nSome **bold** [link](https://example.com)\n\n- One\n- Two"
+    /// );
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    #[cfg(feature = "markdown")]
+    pub fn html_markdown(&self, node: Node) -> String {
+        crate::markdown::html_markdown(self, node)
+    }
+
     /// Check two nodes for semantic equality.
     ///
     /// This is a deep comparison of the nodes and their children.
@@ -685,7 +872,7 @@ impl Xot {
                 b,
                 // we need to only consider elements and text nodes for
                 // root/element content comparison
-                |node| self.is_element(node) || self.is_text(node),
+                |node| self.is_element(node) || self.is_text(node) || self.is_cdata(node),
                 text_compare,
             ),
             _ => self.advanced_compare_value(a, b, text_compare),
@@ -757,8 +944,11 @@ impl Xot {
                 a_element.name() == b_element.name()
                     && self.advanced_compare_attributes(a, b, text_compare)
             }
-            (Value::Text(a), Value::Text(b)) => text_compare(a.get(), b.get()),
+            (Value::Text(a), Value::Text(b)) | (Value::Cdata(a), Value::Cdata(b)) => {
+                text_compare(a.get(), b.get())
+            }
             (Value::Comment(a), Value::Comment(b)) => a.get() == b.get(),
+            (Value::EntityReference(a), Value::EntityReference(b)) => a.name() == b.name(),
             (Value::ProcessingInstruction(a), Value::ProcessingInstruction(b)) => {
                 if a.target() != b.target() {
                     return false;
@@ -859,8 +1049,17 @@ impl Xot {
     }
 }
 
+fn normalize_space(text: &str) -> String {
+    text.split([' ', '\t', '\r', '\n'])
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 fn descendants_to_string(xot: &Xot, node: Node) -> String {
-    let texts = xot.descendants(node).filter_map(|n| xot.text_str(n));
+    let texts = xot
+        .descendants(node)
+        .filter_map(|n| xot.text_str(n).or_else(|| xot.cdata_str(n)));
     let (lower_bound, _) = texts.size_hint();
     let mut r = String::with_capacity(lower_bound);
     for text in texts {
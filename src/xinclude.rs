@@ -0,0 +1,493 @@
+// XInclude processing, following https://www.w3.org/TR/xinclude/
+
+use std::sync::Arc;
+
+use encoding_rs::Encoding;
+
+use crate::error::Error;
+use crate::id::NameId;
+use crate::parse::ParseOptions;
+use crate::xotdata::{Node, Xot};
+
+const XINCLUDE_NAMESPACE: &str = "http://www.w3.org/2001/XInclude";
+
+/// Loads the resources that `xi:include` elements refer to.
+///
+/// You set a loader with [`XIncludeOptions::loader`], for instance to read
+/// files relative to a directory, or to look up resources in memory. Any
+/// closure that takes a URI and returns the bytes of the resource is a
+/// loader.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use xot::{XIncludeOptions, Xot};
+///
+/// let options = XIncludeOptions {
+///     loader: Some(Arc::new(|uri: &str| {
+///         (uri == "chapter.xml").then(|| b"<chapter/>".to_vec())
+///     })),
+///     ..Default::default()
+/// };
+/// let mut xot = Xot::new();
+/// let root = xot.parse(
+///     r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="chapter.xml"/></doc>"#,
+/// )?;
+/// xot.process_xinclude(root, &options)?;
+/// assert_eq!(
+///     xot.to_string(root)?,
+///     r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><chapter xml:base="chapter.xml"/></doc>"#
+/// );
+/// # Ok::<(), xot::Error>(())
+/// ```
+pub trait XIncludeLoader {
+    /// Load the resource with the given URI.
+    ///
+    /// The URI is resolved against the base URI of the `xi:include` element
+    /// if that's known; otherwise it's the `href` as it appears in the
+    /// document. If the resource cannot be loaded, this returns `None`, and
+    /// the `xi:fallback` of the `xi:include` element is used instead.
+    fn load(&self, uri: &str) -> Option<Vec<u8>>;
+}
+
+impl<F> XIncludeLoader for F
+where
+    F: Fn(&str) -> Option<Vec<u8>>,
+{
+    fn load(&self, uri: &str) -> Option<Vec<u8>> {
+        self(uri)
+    }
+}
+
+/// Options for XInclude processing.
+///
+/// You use these with [`Xot::process_xinclude`].
+#[derive(Clone)]
+pub struct XIncludeOptions {
+    /// Load included resources.
+    ///
+    /// Without a loader, only references to the document itself (an
+    /// `xi:include` with an `xpointer` but without a `href`) can be
+    /// included. Including anything else is a resource error, for which the
+    /// `xi:fallback` is used.
+    pub loader: Option<Arc<dyn XIncludeLoader>>,
+    /// The URI of the document.
+    ///
+    /// Relative `href` attributes are resolved against this, taking
+    /// `xml:base` attributes into account, before they're given to the
+    /// loader. If this isn't set, or isn't an absolute URI, the `href` is
+    /// given to the loader as it is.
+    pub base_uri: Option<String>,
+    /// The options to parse included XML documents with.
+    pub parse_options: ParseOptions,
+    /// Add an `xml:base` attribute to included elements.
+    ///
+    /// This is set by default, as the XInclude specification requires. It
+    /// makes sure that relative URIs in included content keep referring to
+    /// the same resources. Elements that already have an `xml:base`
+    /// attribute, and elements included from the document itself, are left
+    /// alone.
+    pub fixup_base_uris: bool,
+}
+
+impl Default for XIncludeOptions {
+    fn default() -> Self {
+        Self {
+            loader: None,
+            base_uri: None,
+            parse_options: ParseOptions::default(),
+            fixup_base_uris: true,
+        }
+    }
+}
+
+impl std::fmt::Debug for XIncludeOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("XIncludeOptions")
+            .field("loader", &self.loader.as_ref().map(|_| "XIncludeLoader"))
+            .field("base_uri", &self.base_uri)
+            .field("parse_options", &self.parse_options)
+            .field("fixup_base_uris", &self.fixup_base_uris)
+            .finish()
+    }
+}
+
+struct Names {
+    include: NameId,
+    fallback: NameId,
+    href: NameId,
+    parse: NameId,
+    xpointer: NameId,
+    encoding: NameId,
+    xml_base: NameId,
+}
+
+struct Processor<'a> {
+    options: &'a XIncludeOptions,
+    names: Names,
+    // the URIs of the documents we're processing, innermost last
+    documents: Vec<Option<String>>,
+    // the resources we're including, to detect loops
+    including: Vec<(Option<String>, Option<String>)>,
+}
+
+// the content of a resource an `xi:include` element refers to
+enum Resource {
+    Nodes(Vec<Node>),
+    Text(String),
+}
+
+/// ## XInclude
+impl Xot {
+    /// Process the `xi:include` elements in a document.
+    ///
+    /// Each `xi:include` element is replaced by the resource it refers to:
+    /// the nodes of an XML document with `parse="xml"`, which is the
+    /// default, or text with `parse="text"`. Resources are loaded with
+    /// [`XIncludeOptions::loader`]. Included documents are processed in
+    /// turn.
+    ///
+    /// An `xpointer` attribute selects an element in an included XML
+    /// document. This can be a shorthand pointer, which is the `xml:id` of
+    /// the element, or use the `element()` scheme, like `element(intro/2)`
+    /// or `element(/1/3)`. Without an `href`, the `xpointer` selects an
+    /// element in the document itself, which is copied.
+    ///
+    /// If a resource cannot be loaded, or the `xpointer` doesn't select
+    /// anything, the content of the `xi:fallback` child of the `xi:include`
+    /// element is used instead. Without a fallback, this is
+    /// [`Error::XIncludeResource`].
+    ///
+    /// If a document ends up including itself, directly or indirectly, this
+    /// is [`Error::XIncludeLoop`]. An `xi:include` element that's not
+    /// valid, for instance because it has an unknown `parse` attribute or
+    /// more than one `xi:fallback`, results in [`Error::InvalidXInclude`].
+    ///
+    /// ```rust
+    /// use xot::{XIncludeOptions, Xot};
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(
+    ///     r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><p xml:id="a">A</p><xi:include xpointer="a"/><xi:include href="missing.xml"><xi:fallback>B</xi:fallback></xi:include></doc>"#,
+    /// )?;
+    /// xot.process_xinclude(root, &XIncludeOptions::default())?;
+    /// assert_eq!(
+    ///     xot.to_string(root)?,
+    ///     r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><p xml:id="a">A</p><p xml:id="a">A</p>B</doc>"#
+    /// );
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn process_xinclude(
+        &mut self,
+        document: Node,
+        options: &XIncludeOptions,
+    ) -> Result<(), Error> {
+        if !self.is_document(document) {
+            return Err(Error::NotDocument(document));
+        }
+        let Some(namespace) = self.namespace(XINCLUDE_NAMESPACE) else {
+            // there can't be any xi:include elements
+            return Ok(());
+        };
+        let names = Names {
+            include: self.add_name_ns("include", namespace),
+            fallback: self.add_name_ns("fallback", namespace),
+            href: self.add_name("href"),
+            parse: self.add_name("parse"),
+            xpointer: self.add_name("xpointer"),
+            encoding: self.add_name("encoding"),
+            xml_base: self.add_name_ns("base", self.xml_namespace()),
+        };
+        let mut processor = Processor {
+            options,
+            names,
+            documents: vec![options.base_uri.clone()],
+            including: Vec::new(),
+        };
+        processor.process_children(self, document)
+    }
+}
+
+impl Processor<'_> {
+    fn process_children(&mut self, xot: &mut Xot, node: Node) -> Result<(), Error> {
+        // we only hold on to elements, as text may be consolidated with
+        // adjacent text when we include something
+        let children = xot
+            .children(node)
+            .filter(|child| xot.is_element(*child))
+            .collect::<Vec<_>>();
+        self.process_nodes(xot, children)
+    }
+
+    fn process_nodes(&mut self, xot: &mut Xot, nodes: Vec<Node>) -> Result<(), Error> {
+        for node in nodes {
+            let Some(element) = xot.element(node) else {
+                continue;
+            };
+            let name = element.name();
+            if name == self.names.include {
+                self.include(xot, node)?;
+            } else if name == self.names.fallback {
+                return Err(Error::InvalidXInclude(
+                    "xi:fallback outside of xi:include".to_string(),
+                ));
+            } else {
+                self.process_children(xot, node)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, xot: &mut Xot, include: Node) -> Result<(), Error> {
+        let mut fallback = None;
+        for child in xot.children(include) {
+            let Some(element) = xot.element(child) else {
+                continue;
+            };
+            if element.name() == self.names.include {
+                return Err(Error::InvalidXInclude(
+                    "xi:include contains xi:include".to_string(),
+                ));
+            }
+            if element.name() == self.names.fallback {
+                if fallback.is_some() {
+                    return Err(Error::InvalidXInclude(
+                        "xi:include contains more than one xi:fallback".to_string(),
+                    ));
+                }
+                fallback = Some(child);
+            }
+        }
+        let href = xot
+            .get_attribute(include, self.names.href)
+            .filter(|href| !href.is_empty())
+            .map(|href| href.to_string());
+        let xpointer = xot
+            .get_attribute(include, self.names.xpointer)
+            .map(|xpointer| xpointer.to_string());
+        let text = match xot.get_attribute(include, self.names.parse) {
+            None | Some("xml") => false,
+            Some("text") => true,
+            Some(parse) => {
+                return Err(Error::InvalidXInclude(format!(
+                    "unknown parse attribute: {}",
+                    parse
+                )))
+            }
+        };
+        if href.as_deref().is_some_and(|href| href.contains('#')) {
+            return Err(Error::InvalidXInclude(
+                "href contains a fragment identifier".to_string(),
+            ));
+        }
+        if href.is_none() && xpointer.is_none() {
+            return Err(Error::InvalidXInclude(
+                "xi:include has neither href nor xpointer".to_string(),
+            ));
+        }
+        if text && xpointer.is_some() {
+            return Err(Error::InvalidXInclude(
+                "xpointer with parse=\"text\"".to_string(),
+            ));
+        }
+        let uri = match &href {
+            Some(href) => Some(self.resolve(xot, include, href)),
+            None => self.documents.last().unwrap().clone(),
+        };
+        // including text can't result in a loop
+        let key = (uri.clone(), xpointer.clone());
+        if !text && self.including.contains(&key) {
+            return Err(Error::XIncludeLoop(
+                uri.unwrap_or_else(|| format!("#{}", xpointer.unwrap_or_default())),
+            ));
+        }
+
+        let resource = match (&href, &uri) {
+            (Some(_), Some(uri)) if text => {
+                let encoding = xot
+                    .get_attribute(include, self.names.encoding)
+                    .map(|label| label.to_string());
+                self.load_text(uri, encoding.as_deref())
+            }
+            (Some(_), Some(uri)) => self.load_xml(xot, key.clone(), xpointer.as_deref(), uri)?,
+            _ => {
+                let document = xot.root(include);
+                find_xpointer(xot, document, xpointer.as_deref().unwrap())
+                    .map(|node| Resource::Nodes(vec![xot.clone_with_prefixes(node)]))
+            }
+        };
+
+        // included documents are processed already; content copied from
+        // this document and the content of the fallback still need to be
+        // processed
+        let copied = href.is_none() && resource.is_some();
+        let (nodes, process) = match resource {
+            Some(Resource::Nodes(nodes)) => {
+                if let (Some(href), true) = (&href, self.options.fixup_base_uris) {
+                    for node in &nodes {
+                        if xot.is_element(*node)
+                            && xot.get_attribute(*node, self.names.xml_base).is_none()
+                        {
+                            xot.set_attribute(*node, self.names.xml_base, href.as_str());
+                        }
+                    }
+                }
+                (nodes, href.is_none())
+            }
+            Some(Resource::Text(text)) => (vec![xot.new_text(&text)], false),
+            None => {
+                let Some(fallback) = fallback else {
+                    return Err(Error::XIncludeResource(
+                        uri.unwrap_or_else(|| format!("#{}", xpointer.unwrap_or_default())),
+                    ));
+                };
+                let children = xot.children(fallback).collect::<Vec<_>>();
+                for child in &children {
+                    xot.detach(*child)?;
+                }
+                (children, true)
+            }
+        };
+        let elements = nodes
+            .iter()
+            .copied()
+            .filter(|node| xot.is_element(*node))
+            .collect::<Vec<_>>();
+        for node in nodes {
+            xot.insert_before(include, node)?;
+        }
+        xot.remove(include)?;
+        if !process {
+            return Ok(());
+        }
+        if copied {
+            self.including.push(key);
+        }
+        let processed = self.process_nodes(xot, elements);
+        if copied {
+            self.including.pop();
+        }
+        processed
+    }
+
+    // resolve a href against the base URI of the node it's on
+    fn resolve(&self, xot: &Xot, node: Node, href: &str) -> String {
+        let bases = xot
+            .ancestors(node)
+            .filter_map(|ancestor| xot.get_attribute(ancestor, self.names.xml_base))
+            .collect::<Vec<_>>();
+        let mut base = self.documents.last().unwrap().clone();
+        for xml_base in bases.into_iter().rev() {
+            base = Some(resolve_uri(base.as_deref(), xml_base));
+        }
+        resolve_uri(base.as_deref(), href)
+    }
+
+    fn load_text(&self, uri: &str, encoding: Option<&str>) -> Option<Resource> {
+        let bytes = self.options.loader.as_ref()?.load(uri)?;
+        let encoding = match encoding {
+            Some(label) => Encoding::for_label(label.as_bytes())?,
+            None => encoding_rs::UTF_8,
+        };
+        let (text, _) = encoding.decode_with_bom_removal(&bytes);
+        Some(Resource::Text(text.into_owned()))
+    }
+
+    fn load_xml(
+        &mut self,
+        xot: &mut Xot,
+        key: (Option<String>, Option<String>),
+        xpointer: Option<&str>,
+        uri: &str,
+    ) -> Result<Option<Resource>, Error> {
+        let Some(loader) = &self.options.loader else {
+            return Ok(None);
+        };
+        let Some(bytes) = loader.load(uri) else {
+            return Ok(None);
+        };
+        let document = xot.parse_bytes_with_options(&bytes, &self.options.parse_options)?;
+        self.documents.push(Some(uri.to_string()));
+        self.including.push(key);
+        let processed = self.process_children(xot, document);
+        self.including.pop();
+        self.documents.pop();
+        let selected = processed.map(|_| match xpointer {
+            Some(xpointer) => find_xpointer(xot, document, xpointer).into_iter().collect(),
+            None => xot.children(document).collect::<Vec<_>>(),
+        });
+        let nodes = selected.map(|selected| {
+            selected
+                .into_iter()
+                .map(|node| xot.clone_with_prefixes(node))
+                .collect::<Vec<_>>()
+        });
+        xot.remove_document(document);
+        let nodes = nodes?;
+        Ok((!nodes.is_empty()).then_some(Resource::Nodes(nodes)))
+    }
+}
+
+fn resolve_uri(base: Option<&str>, reference: &str) -> String {
+    base.and_then(|base| crate::url::resolve(base, reference))
+        .unwrap_or_else(|| reference.to_string())
+}
+
+// find the element a pointer refers to. we support shorthand pointers and
+// the element() scheme; parts that use other schemes are skipped.
+// https://www.w3.org/TR/xptr-framework/ and
+// https://www.w3.org/TR/xptr-element/
+fn find_xpointer(xot: &Xot, document: Node, xpointer: &str) -> Option<Node> {
+    let xpointer = xpointer.trim();
+    if !xpointer.contains('(') {
+        return find_id(xot, document, xpointer);
+    }
+    let mut rest = xpointer;
+    while let Some(open) = rest.find('(') {
+        let scheme = rest[..open].trim();
+        let mut depth = 0;
+        let mut close = None;
+        for (i, c) in rest[open..].char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(open + i);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let close = close?;
+        if scheme == "element" {
+            if let Some(node) = find_element(xot, document, &rest[open + 1..close]) {
+                return Some(node);
+            }
+        }
+        rest = &rest[close + 1..];
+    }
+    None
+}
+
+fn find_element(xot: &Xot, document: Node, data: &str) -> Option<Node> {
+    let mut steps = data.split('/');
+    let mut node = match steps.next()? {
+        "" => document,
+        id => find_id(xot, document, id)?,
+    };
+    for step in steps {
+        let index = step.parse::<usize>().ok()?.checked_sub(1)?;
+        node = xot
+            .children(node)
+            .filter(|child| xot.is_element(*child))
+            .nth(index)?;
+    }
+    xot.is_element(node).then_some(node)
+}
+
+fn find_id(xot: &Xot, document: Node, id: &str) -> Option<Node> {
+    let xml_id = xot.xml_id_name();
+    xot.descendants(document)
+        .find(|node| xot.get_attribute(*node, xml_id) == Some(id))
+}
@@ -0,0 +1,73 @@
+use crate::xotdata::{Node, Xot};
+
+/// The whitespace handling that applies to a node, as given by `xml:space`.
+///
+/// See <https://www.w3.org/TR/xml/#sec-white-space>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XmlSpace {
+    /// Whitespace may be changed, for instance to indent the document. This
+    /// applies if there's no `xml:space` attribute, or if it's `default`.
+    Default,
+    /// Whitespace is significant and must be kept as it is. This applies in
+    /// an element with `xml:space="preserve"` and its descendants.
+    Preserve,
+}
+
+/// ## Whitespace handling
+impl Xot {
+    /// The whitespace handling that applies to a node.
+    ///
+    /// This is given by the `xml:space` attribute on the node or its nearest
+    /// ancestor that has one. Values other than `preserve` and `default` are
+    /// ignored.
+    ///
+    /// When you parse with
+    /// [`ParseOptions::strip_whitespace`](crate::ParseOptions::strip_whitespace),
+    /// whitespace isn't stripped where this is [`XmlSpace::Preserve`]. The
+    /// pretty printer doesn't add indentation there either.
+    ///
+    /// ```rust
+    /// use xot::{Xot, XmlSpace};
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<doc><pre xml:space="preserve"><b> x </b></pre></doc>"#)?;
+    /// let doc_el = xot.document_element(root)?;
+    /// let pre = xot.first_child(doc_el).unwrap();
+    /// let b = xot.first_child(pre).unwrap();
+    /// assert_eq!(xot.xml_space(doc_el), XmlSpace::Default);
+    /// assert_eq!(xot.xml_space(b), XmlSpace::Preserve);
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn xml_space(&self, node: Node) -> XmlSpace {
+        let space = self.xml_space_name();
+        for ancestor in self.ancestors(node) {
+            match self.get_attribute(ancestor, space) {
+                Some("preserve") => return XmlSpace::Preserve,
+                Some("default") => return XmlSpace::Default,
+                _ => {}
+            }
+        }
+        XmlSpace::Default
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xml_space_nested() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse(r#"<a xml:space="preserve"><b xml:space="default"><c xml:space="other">t</c></b></a>"#)
+            .unwrap();
+        let a = xot.document_element(root).unwrap();
+        let b = xot.first_child(a).unwrap();
+        let c = xot.first_child(b).unwrap();
+        let text = xot.first_child(c).unwrap();
+        assert_eq!(xot.xml_space(root), XmlSpace::Default);
+        assert_eq!(xot.xml_space(a), XmlSpace::Preserve);
+        assert_eq!(xot.xml_space(b), XmlSpace::Default);
+        assert_eq!(xot.xml_space(text), XmlSpace::Default);
+    }
+}
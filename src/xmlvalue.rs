@@ -1,4 +1,7 @@
 use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::Arc;
 
 use ahash::AHashMap;
 
@@ -22,6 +25,10 @@ pub enum ValueType {
     Element,
     /// Text. You can get and set the text value.
     Text,
+    /// CDATA section. You can get and set its text value.
+    Cdata,
+    /// Entity reference that isn't expanded.
+    EntityReference,
     /// Processing instruction
     ProcessingInstruction,
     /// Comment.
@@ -52,6 +59,17 @@ pub enum Value {
     Element(Element),
     /// Text. You can get and set the text value.
     Text(Text),
+    /// CDATA section. This is only created when parsing with
+    /// [`ParseOptions::preserve_cdata`](crate::ParseOptions::preserve_cdata)
+    /// or with [`Xot::new_cdata`](crate::xotdata::Xot::new_cdata); otherwise
+    /// CDATA sections become text.
+    Cdata(Text),
+    /// Entity reference that isn't expanded. This is only created when
+    /// parsing with
+    /// [`ParseOptions::preserve_entity_references`](crate::ParseOptions::preserve_entity_references)
+    /// or with
+    /// [`Xot::new_entity_reference`](crate::xotdata::Xot::new_entity_reference).
+    EntityReference(EntityReference),
     /// Processing instruction.
     ProcessingInstruction(ProcessingInstruction),
     /// Comment.
@@ -69,6 +87,8 @@ impl Value {
             Value::Document => ValueType::Document,
             Value::Element(_) => ValueType::Element,
             Value::Text(_) => ValueType::Text,
+            Value::Cdata(_) => ValueType::Cdata,
+            Value::EntityReference(_) => ValueType::EntityReference,
             Value::Comment(_) => ValueType::Comment,
             Value::ProcessingInstruction(_) => ValueType::ProcessingInstruction,
             Value::Attribute(_) => ValueType::Attribute,
@@ -81,6 +101,8 @@ impl Value {
             Value::Document
             | Value::Element(_)
             | Value::Text(_)
+            | Value::Cdata(_)
+            | Value::EntityReference(_)
             | Value::ProcessingInstruction(_)
             | Value::Comment(_) => ValueCategory::Normal,
             Value::Attribute(_) => ValueCategory::Attribute,
@@ -94,6 +116,8 @@ impl Value {
             Value::Document
                 | Value::Element(_)
                 | Value::Text(_)
+                | Value::Cdata(_)
+                | Value::EntityReference(_)
                 | Value::ProcessingInstruction(_)
                 | Value::Comment(_)
         )
@@ -152,14 +176,34 @@ impl Element {
 /// XML text value.
 ///
 /// Example: `Bar` in `<foo>Bar</foo>`, or `hello` and `world` in `<greeting>hello<sep/>world</greeting>`.
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+///
+/// When Xot owns the source text it parses, such as with
+/// [`Xot::parse_string`](`crate::Xot::parse_string`) and
+/// [`Xot::parse_bytes`](`crate::Xot::parse_bytes`), text that's parsed
+/// without changes refers to the source rather than being copied. It's
+/// copied once you change it, and a clone gets its own copy, so that it
+/// doesn't keep the source in memory.
 pub struct Text {
-    pub(crate) text: String,
+    pub(crate) text: TextStorage,
+}
+
+pub(crate) enum TextStorage {
+    Owned(String),
+    // a range of the source text
+    Shared(Arc<String>, Range<usize>),
 }
 
 impl Text {
     pub(crate) fn new(text: String) -> Self {
-        Text { text }
+        Text {
+            text: TextStorage::Owned(text),
+        }
+    }
+
+    pub(crate) fn shared(source: Arc<String>, range: Range<usize>) -> Self {
+        Text {
+            text: TextStorage::Shared(source, range),
+        }
     }
 
     /// Get the text value.
@@ -167,12 +211,21 @@ impl Text {
     /// See [`Xot::text_str`](`crate::Xot::text_str`) and [`Xot::text_content_str`](`crate::Xot::text_content_str`) for
     /// more convenient ways to get text values.
     pub fn get(&self) -> &str {
-        &self.text
+        match &self.text {
+            TextStorage::Owned(text) => text,
+            TextStorage::Shared(source, range) => &source[range.clone()],
+        }
     }
 
     /// Get the text value, as a mutable reference.
     pub fn get_mut(&mut self) -> &mut String {
-        &mut self.text
+        if let TextStorage::Shared(source, range) = &self.text {
+            self.text = TextStorage::Owned(source[range.clone()].to_string());
+        }
+        match &mut self.text {
+            TextStorage::Owned(text) => text,
+            TextStorage::Shared(..) => unreachable!(),
+        }
     }
 
     /// Set the text value.
@@ -192,7 +245,33 @@ impl Text {
     /// # Ok::<(), xot::Error>(())
     /// ```
     pub fn set<S: Into<String>>(&mut self, text: S) {
-        self.text = text.into();
+        self.text = TextStorage::Owned(text.into());
+    }
+}
+
+impl Clone for Text {
+    fn clone(&self) -> Self {
+        Text::new(self.get().to_string())
+    }
+}
+
+impl Debug for Text {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Text").field("text", &self.get()).finish()
+    }
+}
+
+impl PartialEq for Text {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl Eq for Text {}
+
+impl Hash for Text {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.get().hash(state);
     }
 }
 
@@ -227,6 +306,30 @@ impl Comment {
     }
 }
 
+/// XML entity reference.
+///
+/// Example: `&chapter1;`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct EntityReference {
+    pub(crate) name: String,
+}
+
+impl EntityReference {
+    pub(crate) fn new(name: String) -> Self {
+        EntityReference { name }
+    }
+
+    /// Get the name of the entity.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Set the name of the entity.
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
+        self.name = name.into();
+    }
+}
+
 /// XML processing instruction value.
 ///
 /// Example: `<?foo?>` or `<?foo bar?>`.
@@ -301,6 +404,8 @@ impl Namespace {
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Attribute {
     pub(crate) name_id: NameId,
+    // TODO: refer to the source like `Text` does. Attribute maps give out
+    // `&String`, so this needs them to give out `&str` instead.
     pub(crate) value: String,
 }
 
@@ -343,4 +448,51 @@ mod tests {
         assert_eq!(alpha_hash, beta_hash);
         assert_ne!(alpha_hash, gamma_hash);
     }
+
+    #[test]
+    fn test_text_shared_with_source() {
+        let mut xot = Xot::new();
+        let root = xot
+            .parse_string("<doc><a>unchanged</a><b>a &amp; b</b></doc>".to_string())
+            .unwrap();
+        let doc_el = xot.document_element(root).unwrap();
+        let a = xot.first_child(doc_el).unwrap();
+        let b = xot.next_sibling(a).unwrap();
+        let a_text = xot.first_child(a).unwrap();
+        let b_text = xot.first_child(b).unwrap();
+        assert!(matches!(
+            xot.text(a_text).unwrap().text,
+            TextStorage::Shared(_, ref range) if *range == (8..17)
+        ));
+        assert!(matches!(
+            xot.text(b_text).unwrap().text,
+            TextStorage::Owned(_)
+        ));
+        // a clone doesn't keep the source alive
+        assert!(matches!(
+            xot.text(a_text).unwrap().clone().text,
+            TextStorage::Owned(_)
+        ));
+
+        let text = xot.text_mut(a_text).unwrap();
+        text.get_mut().push('!');
+        assert!(matches!(text.text, TextStorage::Owned(_)));
+        assert_eq!(text.get(), "unchanged!");
+        assert_eq!(
+            xot.to_string(root).unwrap(),
+            "<doc><a>unchanged!</a><b>a &amp; b</b></doc>"
+        );
+    }
+
+    #[test]
+    fn test_text_borrowed_source_not_shared() {
+        let mut xot = Xot::new();
+        let root = xot.parse("<doc>unchanged</doc>").unwrap();
+        let doc_el = xot.document_element(root).unwrap();
+        let text = xot.first_child(doc_el).unwrap();
+        assert!(matches!(
+            xot.text(text).unwrap().text,
+            TextStorage::Owned(_)
+        ));
+    }
 }
@@ -1,7 +1,11 @@
-use ahash::{HashMap, HashMapExt};
+use ahash::{HashMap, HashMapExt, HashSet, HashSetExt};
 use indextree::{Arena, NodeId};
 
+use crate::doctype::Doctype;
 use crate::id::{Name, NameId, NameLookup, NamespaceId, NamespaceLookup, PrefixId, PrefixLookup};
+use crate::idindex::IdIndex;
+use crate::nameindex::NameIndex;
+use crate::output;
 use crate::xmlvalue::Value;
 
 pub(crate) type XmlArena = Arena<Value>;
@@ -46,11 +50,36 @@ impl From<NodeId> for Node {
 /// * [Parsing](#parsing)
 /// * [Serialization](#serialization)
 /// * [Value and type access](#value-and-type-access)
+/// * [CSS selectors](#css-selectors)
+/// * [ID index](#id-index)
+/// * [Element name index](#element-name-index)
 #[derive(Debug, Clone)]
 pub struct Xot {
     pub(crate) arena: XmlArena,
     // a mapping of document node, to hashmap of node value to node with that id
     pub(crate) id_nodes_map: HashMap<NodeId, HashMap<String, NodeId>>,
+    // the ID indexes of documents, see `Xot::create_id_index`
+    pub(crate) id_indexes: HashMap<NodeId, IdIndex>,
+    // the element name indexes of documents, built on demand
+    pub(crate) name_indexes: HashMap<NodeId, NameIndex>,
+    // changes whenever the tree may have changed, so we know when an ID
+    // or name index is out of date
+    pub(crate) generation: u64,
+    // a mapping of document node to the encoding it was decoded from
+    pub(crate) encodings: HashMap<NodeId, &'static encoding_rs::Encoding>,
+    // the document nodes that were parsed from data starting with a byte
+    // order mark
+    pub(crate) boms: HashSet<NodeId>,
+    // the positions of invalid characters we replaced when parsing a
+    // document
+    pub(crate) replaced_characters: HashMap<NodeId, Vec<usize>>,
+    // a mapping of document node to the XML declaration it was parsed with
+    pub(crate) declarations: HashMap<NodeId, output::xml::Declaration>,
+    // a mapping of document node to its doctype
+    pub(crate) doctypes: HashMap<NodeId, Doctype>,
+    // the attribute nodes we added because the doctype declares a default
+    // for them
+    pub(crate) defaulted_attributes: HashSet<NodeId>,
     pub(crate) namespace_lookup: NamespaceLookup,
     pub(crate) prefix_lookup: PrefixLookup,
     pub(crate) name_lookup: NameLookup,
@@ -78,6 +107,15 @@ impl Xot {
         Xot {
             arena: XmlArena::new(),
             id_nodes_map: HashMap::new(),
+            id_indexes: HashMap::new(),
+            name_indexes: HashMap::new(),
+            generation: 0,
+            encodings: HashMap::new(),
+            boms: HashSet::new(),
+            replaced_characters: HashMap::new(),
+            declarations: HashMap::new(),
+            doctypes: HashMap::new(),
+            defaulted_attributes: HashSet::new(),
             namespace_lookup,
             prefix_lookup,
             name_lookup,
@@ -98,8 +136,33 @@ impl Xot {
 
     #[inline]
     pub(crate) fn arena_mut(&mut self) -> &mut XmlArena {
+        self.generation = self.generation.wrapping_add(1);
         &mut self.arena
     }
+
+    // remove a document node and its content, including all the
+    // information we keep about the document
+    pub(crate) fn remove_document(&mut self, document: Node) {
+        let document_id = document.get();
+        self.id_nodes_map.remove(&document_id);
+        self.id_indexes.remove(&document_id);
+        self.name_indexes.remove(&document_id);
+        self.encodings.remove(&document_id);
+        self.boms.remove(&document_id);
+        self.replaced_characters.remove(&document_id);
+        self.declarations.remove(&document_id);
+        self.doctypes.remove(&document_id);
+        if !self.defaulted_attributes.is_empty() {
+            for node in self.all_descendants(document).collect::<Vec<_>>() {
+                self.defaulted_attributes.remove(&node.get());
+            }
+        }
+        // this doesn't change other documents, so their indexes stay up to
+        // date
+        let generation = self.generation;
+        document_id.remove_subtree(self.arena_mut());
+        self.indexes_follow(generation);
+    }
 }
 
 impl Default for Xot {
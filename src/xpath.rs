@@ -0,0 +1,2022 @@
+//! XPath 1.0 expressions.
+//!
+//! This module implements evaluation of [XPath
+//! 1.0](https://www.w3.org/TR/xpath-10/) expressions. You usually use it
+//! through [`Xot::evaluate`], but you can also parse an [`XPath`] once and
+//! evaluate it many times. A [`Pattern`] tests whether a node matches an
+//! XSLT match pattern.
+//!
+//! This requires the `xpath` feature.
+//!
+//! The following is supported:
+//!
+//! * Location paths, both absolute (`/doc/p`) and relative (`p/b`), with the
+//!   abbreviations `//`, `.`, `..` and `@`.
+//!
+//! * All axes except the namespace axis.
+//!
+//! * The node tests: names, `*`, `prefix:*`, `node()`, `text()`,
+//!   `comment()`, `processing-instruction()` and
+//!   `processing-instruction('target')`.
+//!
+//! * Predicates, including positional predicates such as `p[2]` and
+//!   `p[last()]`.
+//!
+//! * Filter expressions, unions (`|`) and the boolean, comparison and
+//!   arithmetic operators.
+//!
+//! * The complete core function library. `id()` finds elements by their
+//!   `xml:id` attribute, as Xot doesn't know which attributes a DTD
+//!   declares as IDs.
+//!
+//! * Variable references such as `$id`, which you bind with
+//!   [`XPath::set_variable`].
+//!
+//! Like in XPath 1.0, a name without a prefix only matches nodes that are
+//! not in a namespace. To match nodes in a namespace, you declare a prefix
+//! for it with [`XPath::compile`]. The `xml` prefix is always declared.
+
+use std::str::FromStr;
+
+use ahash::{HashMap, HashMapExt};
+
+use crate::access::Axis;
+use crate::error::Error;
+use crate::xmlname::NameStrInfo;
+use crate::xotdata::{Node, Xot};
+
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// The result of an XPath expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A node-set. The nodes are in document order, without duplicates.
+    Nodes(Vec<Node>),
+    /// A string.
+    String(String),
+    /// A number.
+    Number(f64),
+    /// A boolean.
+    Boolean(bool),
+}
+
+impl Value {
+    /// The nodes, if this is a node-set.
+    pub fn nodes(&self) -> Option<&[Node]> {
+        match self {
+            Value::Nodes(nodes) => Some(nodes),
+            _ => None,
+        }
+    }
+
+    /// Convert to a string, like the XPath `string()` function.
+    ///
+    /// A node-set converts to the string value of its first node.
+    pub fn string(&self, xot: &Xot) -> String {
+        match self {
+            Value::Nodes(nodes) => nodes
+                .first()
+                .map(|node| xot.string_value(*node))
+                .unwrap_or_default(),
+            Value::String(s) => s.clone(),
+            Value::Number(n) => number_to_string(*n),
+            Value::Boolean(b) => b.to_string(),
+        }
+    }
+
+    /// Convert to a number, like the XPath `number()` function.
+    pub fn number(&self, xot: &Xot) -> f64 {
+        match self {
+            Value::Number(n) => *n,
+            Value::Boolean(b) => f64::from(u8::from(*b)),
+            _ => string_to_number(&self.string(xot)),
+        }
+    }
+
+    /// Convert to a boolean, like the XPath `boolean()` function.
+    pub fn boolean(&self) -> bool {
+        match self {
+            Value::Nodes(nodes) => !nodes.is_empty(),
+            Value::String(s) => !s.is_empty(),
+            Value::Number(n) => *n != 0.0 && !n.is_nan(),
+            Value::Boolean(b) => *b,
+        }
+    }
+}
+
+/// A compiled XPath 1.0 expression.
+///
+/// Compile an expression once and evaluate it against many nodes or
+/// documents, without parsing it again. An expression can refer to
+/// variables, which you bind with [`XPath::set_variable`].
+///
+/// ```rust
+/// use xot::Xot;
+/// use xot::xpath::{Value, XPath};
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse(r#"<doc><p id="a">A</p><p id="b">B</p></doc>"#)?;
+/// let xpath = XPath::parse("count(//p)")?;
+/// assert_eq!(xpath.evaluate(&xot, root)?, Value::Number(2.0));
+/// # Ok::<(), xot::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct XPath {
+    expr: Expr,
+    variables: HashMap<String, Value>,
+}
+
+impl XPath {
+    /// Parse an XPath 1.0 expression that doesn't use namespace prefixes.
+    ///
+    /// Returns [`Error::InvalidXPath`] if the expression cannot be parsed or
+    /// uses unsupported features.
+    pub fn parse(xpath: &str) -> Result<Self, Error> {
+        Self::compile(xpath, &[])
+    }
+
+    /// Compile an XPath 1.0 expression, with a namespace context: a list of
+    /// prefixes and the namespace URIs they stand for.
+    ///
+    /// The prefixes are resolved now, so the namespace context isn't needed
+    /// anymore when you evaluate the expression. Returns
+    /// [`Error::InvalidXPath`] if the expression cannot be parsed, uses an
+    /// undeclared prefix or uses unsupported features.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    /// use xot::xpath::{Value, XPath};
+    ///
+    /// let mut xot = Xot::new();
+    /// let mut xpath = XPath::compile("/ex:doc/ex:p[@id = $id]", &[("ex", "urn:example")])?;
+    /// xpath.set_variable("id", Value::String("b".to_string()));
+    ///
+    /// for xml in [
+    ///     r#"<doc xmlns="urn:example"><p id="a">A</p><p id="b">B</p></doc>"#,
+    ///     r#"<doc xmlns="urn:example"><p id="b">C</p></doc>"#,
+    /// ] {
+    ///     let root = xot.parse(xml)?;
+    ///     let value = xpath.evaluate(&xot, root)?;
+    ///     assert_eq!(value.nodes().unwrap().len(), 1);
+    /// }
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn compile(xpath: &str, namespaces: &[(&str, &str)]) -> Result<Self, Error> {
+        let mut parser = Parser::new(xpath, namespaces)?;
+        let expr = parser.expr()?;
+        parser.end()?;
+        Ok(XPath {
+            expr,
+            variables: HashMap::new(),
+        })
+    }
+
+    /// Bind the variable `$name` to a value, replacing any previous value.
+    ///
+    /// The nodes of a node-set can come from any document.
+    pub fn set_variable(&mut self, name: &str, value: Value) {
+        self.variables.insert(name.to_string(), value);
+    }
+
+    /// Remove the binding of the variable `$name`, returning its value.
+    pub fn remove_variable(&mut self, name: &str) -> Option<Value> {
+        self.variables.remove(name)
+    }
+
+    /// Evaluate the expression with `node` as the context node.
+    ///
+    /// Returns [`Error::InvalidXPath`] if a value has the wrong type, for
+    /// instance if a path is applied to a string, or if the expression
+    /// refers to a variable that isn't bound.
+    pub fn evaluate(&self, xot: &Xot, node: Node) -> Result<Value, Error> {
+        let mut evaluator = Evaluator {
+            xot,
+            variables: &self.variables,
+            order: HashMap::new(),
+        };
+        evaluator.eval(
+            &self.expr,
+            Context {
+                node,
+                position: 1,
+                size: 1,
+            },
+        )
+    }
+}
+
+impl FromStr for XPath {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        XPath::parse(s)
+    }
+}
+
+/// An XSLT match pattern.
+///
+/// A pattern such as `section/title | @id` doesn't select nodes, but tests
+/// whether a node matches it. This is what rule-based transformations
+/// dispatch on. Matching a node only looks at the node and its ancestors,
+/// so it's a lot cheaper than evaluating an XPath expression and checking
+/// whether the node is in the result.
+///
+/// Patterns follow [XSLT
+/// 1.0](https://www.w3.org/TR/xslt-10/#patterns): they're alternatives
+/// separated by `|`, of paths that only use the child and attribute axes,
+/// with `/` and `//` as separators. A path may start with `/`, `//` or
+/// `id('value')`. Steps may have predicates, which can be any XPath
+/// expression except for variable references.
+///
+/// ```rust
+/// use xot::Xot;
+/// use xot::xpath::Pattern;
+///
+/// let mut xot = Xot::new();
+/// let root = xot.parse(r#"<doc><section id="a"><title>T</title></section><title>U</title></doc>"#)?;
+/// let section = xot.document_element(root)?;
+/// let title = xot.first_child(xot.first_child(section).unwrap()).unwrap();
+///
+/// let pattern = Pattern::parse("section/title | @id")?;
+/// assert!(pattern.matches(&xot, title)?);
+/// assert!(!pattern.matches(&xot, xot.last_child(section).unwrap())?);
+/// let id = xot.attributes(xot.first_child(section).unwrap()).nodes().next().unwrap();
+/// assert!(pattern.matches(&xot, id)?);
+/// # Ok::<(), xot::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    alternatives: Vec<PathPattern>,
+}
+
+impl Pattern {
+    /// Parse a pattern that doesn't use namespace prefixes.
+    ///
+    /// Returns [`Error::InvalidXPath`] if the pattern cannot be parsed or
+    /// uses unsupported features.
+    pub fn parse(pattern: &str) -> Result<Self, Error> {
+        Self::compile(pattern, &[])
+    }
+
+    /// Compile a pattern with a namespace context, like
+    /// [`XPath::compile`].
+    pub fn compile(pattern: &str, namespaces: &[(&str, &str)]) -> Result<Self, Error> {
+        let mut parser = Parser::new(pattern, namespaces)?;
+        let mut alternatives = vec![parser.path_pattern()?];
+        while parser.eat(&Token::Pipe) {
+            alternatives.push(parser.path_pattern()?);
+        }
+        parser.end()?;
+        Ok(Pattern { alternatives })
+    }
+
+    /// Whether `node` matches the pattern.
+    ///
+    /// Returns [`Error::InvalidXPath`] if a predicate cannot be evaluated.
+    pub fn matches(&self, xot: &Xot, node: Node) -> Result<bool, Error> {
+        let variables = HashMap::new();
+        let mut evaluator = Evaluator {
+            xot,
+            variables: &variables,
+            order: HashMap::new(),
+        };
+        for alternative in &self.alternatives {
+            if evaluator.matches_path(alternative, node, alternative.steps.len())? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pattern::parse(s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PathPattern {
+    anchor: Anchor,
+    // each step, with whether it's separated from what comes before by `//`
+    steps: Vec<(bool, Step)>,
+}
+
+// what the first step of a path pattern is relative to
+#[derive(Debug, Clone, PartialEq)]
+enum Anchor {
+    // any node, like in `section/title`
+    Any,
+    // the document node, like in `/doc` and `//title`
+    Root,
+    // the elements with one of these ids, like in `id('intro')/title`
+    Id(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Compare(CompareOperator, Box<Expr>, Box<Expr>),
+    Arithmetic(ArithmeticOperator, Box<Expr>, Box<Expr>),
+    Negate(Box<Expr>),
+    Union(Box<Expr>, Box<Expr>),
+    Literal(String),
+    Number(f64),
+    Variable(String),
+    Function(Function, Vec<Expr>),
+    // the root of the tree that contains the context node
+    Root,
+    ContextNode,
+    Filter(Box<Expr>, Vec<Expr>),
+    // the steps are applied to the node-set the expression evaluates to
+    Path(Box<Expr>, Vec<Step>),
+}
+
+impl Expr {
+    // whether the expression refers to a variable anywhere
+    fn has_variable(&self) -> bool {
+        match self {
+            Expr::Variable(_) => true,
+            Expr::Or(a, b)
+            | Expr::And(a, b)
+            | Expr::Compare(_, a, b)
+            | Expr::Arithmetic(_, a, b)
+            | Expr::Union(a, b) => a.has_variable() || b.has_variable(),
+            Expr::Negate(a) => a.has_variable(),
+            Expr::Function(_, args) => args.iter().any(Expr::has_variable),
+            Expr::Filter(primary, predicates) => {
+                primary.has_variable() || predicates.iter().any(Expr::has_variable)
+            }
+            Expr::Path(start, steps) => {
+                start.has_variable()
+                    || steps
+                        .iter()
+                        .any(|step| step.predicates.iter().any(Expr::has_variable))
+            }
+            Expr::Literal(_) | Expr::Number(_) | Expr::Root | Expr::ContextNode => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareOperator {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArithmeticOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+}
+
+impl ArithmeticOperator {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            ArithmeticOperator::Add => a + b,
+            ArithmeticOperator::Subtract => a - b,
+            ArithmeticOperator::Multiply => a * b,
+            ArithmeticOperator::Divide => a / b,
+            ArithmeticOperator::Modulo => a % b,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    axis: Axis,
+    test: NodeTest,
+    predicates: Vec<Expr>,
+}
+
+impl Step {
+    fn new(axis: Axis, test: NodeTest) -> Self {
+        Step {
+            axis,
+            test,
+            predicates: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NodeTest {
+    // the namespace is empty for names without a namespace
+    Name { namespace: String, local: String },
+    AnyName,
+    NamespaceName(String),
+    Node,
+    Text,
+    Comment,
+    ProcessingInstruction(Option<String>),
+}
+
+impl NodeTest {
+    fn matches(&self, xot: &Xot, node: Node, axis: Axis) -> bool {
+        use crate::xmlvalue::Value as NodeValue;
+        match self {
+            NodeTest::Node => true,
+            NodeTest::Text => matches!(xot.value(node), NodeValue::Text(_) | NodeValue::Cdata(_)),
+            NodeTest::Comment => xot.is_comment(node),
+            NodeTest::ProcessingInstruction(target) => match xot.value(node) {
+                NodeValue::ProcessingInstruction(pi) => target
+                    .as_ref()
+                    .is_none_or(|target| xot.local_name_str(pi.target()) == target),
+                _ => false,
+            },
+            NodeTest::Name { .. } | NodeTest::AnyName | NodeTest::NamespaceName(_) => {
+                // a name test only matches the principal node type of the
+                // axis
+                let name = match xot.value(node) {
+                    NodeValue::Element(element) if axis != Axis::Attribute => element.name(),
+                    NodeValue::Attribute(attribute) if axis == Axis::Attribute => attribute.name(),
+                    _ => return false,
+                };
+                let namespace = || xot.namespace_str(xot.namespace_for_name(name));
+                match self {
+                    NodeTest::Name {
+                        namespace: ns,
+                        local,
+                    } => xot.local_name_str(name) == local && namespace() == ns,
+                    NodeTest::NamespaceName(ns) => namespace() == ns,
+                    _ => true,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Last,
+    Position,
+    Count,
+    Id,
+    LocalName,
+    NamespaceUri,
+    Name,
+    String,
+    Concat,
+    StartsWith,
+    Contains,
+    SubstringBefore,
+    SubstringAfter,
+    Substring,
+    StringLength,
+    NormalizeSpace,
+    Translate,
+    Boolean,
+    Not,
+    True,
+    False,
+    Lang,
+    Number,
+    Sum,
+    Floor,
+    Ceiling,
+    Round,
+}
+
+impl Function {
+    fn parse(name: &str) -> Option<Self> {
+        use Function::*;
+        Some(match name {
+            "last" => Last,
+            "position" => Position,
+            "count" => Count,
+            "id" => Id,
+            "local-name" => LocalName,
+            "namespace-uri" => NamespaceUri,
+            "name" => Name,
+            "string" => String,
+            "concat" => Concat,
+            "starts-with" => StartsWith,
+            "contains" => Contains,
+            "substring-before" => SubstringBefore,
+            "substring-after" => SubstringAfter,
+            "substring" => Substring,
+            "string-length" => StringLength,
+            "normalize-space" => NormalizeSpace,
+            "translate" => Translate,
+            "boolean" => Boolean,
+            "not" => Not,
+            "true" => True,
+            "false" => False,
+            "lang" => Lang,
+            "number" => Number,
+            "sum" => Sum,
+            "floor" => Floor,
+            "ceiling" => Ceiling,
+            "round" => Round,
+            _ => return None,
+        })
+    }
+
+    // the minimum and maximum number of arguments
+    fn arity(self) -> (usize, usize) {
+        use Function::*;
+        match self {
+            Last | Position | True | False => (0, 0),
+            LocalName | NamespaceUri | Name | String | StringLength | NormalizeSpace | Number => {
+                (0, 1)
+            }
+            Count | Id | Boolean | Not | Lang | Sum | Floor | Ceiling | Round => (1, 1),
+            StartsWith | Contains | SubstringBefore | SubstringAfter => (2, 2),
+            Substring => (2, 3),
+            Translate => (3, 3),
+            Concat => (2, usize::MAX),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Slash,
+    DoubleSlash,
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Dot,
+    DotDot,
+    At,
+    Comma,
+    ColonColon,
+    Pipe,
+    Plus,
+    Minus,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Multiply,
+    And,
+    Or,
+    Mod,
+    Div,
+    Literal(String),
+    Number(f64),
+    // `*` has neither a prefix nor a local name, `prefix:*` has no local name
+    NameTest(Option<String>, Option<String>),
+    NodeType(String),
+    FunctionName(String),
+    AxisName(String),
+    Variable(String),
+}
+
+impl Token {
+    // Whether an operand follows this token. If not, `*` is the multiply
+    // operator and a name is an operator name.
+    fn precedes_operand(&self) -> bool {
+        !matches!(
+            self,
+            Token::RightParen
+                | Token::RightBracket
+                | Token::Dot
+                | Token::DotDot
+                | Token::Literal(_)
+                | Token::Number(_)
+                | Token::NameTest(..)
+                | Token::NodeType(_)
+                | Token::FunctionName(_)
+                | Token::AxisName(_)
+                | Token::Variable(_)
+        )
+    }
+}
+
+struct Spanned {
+    token: Token,
+    start: usize,
+    end: usize,
+}
+
+struct Lexer<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Lexer<'_> {
+    fn error(&self, message: &str) -> Error {
+        Error::InvalidXPath(format!("{} at position {}", message, self.pos))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.input[self.pos..].chars().nth(offset)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.input[self.pos..].starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    // whether the input continues with `s` after whitespace
+    fn followed_by(&self, s: &str) -> bool {
+        self.input[self.pos..]
+            .trim_start_matches(is_xpath_whitespace)
+            .starts_with(s)
+    }
+
+    fn ncname(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(is_name_char) {
+            self.pos += self.peek().unwrap().len_utf8();
+        }
+        self.input[start..self.pos].to_string()
+    }
+
+    fn literal(&mut self, quote: char) -> Result<Token, Error> {
+        let start = self.pos + 1;
+        match self.input[start..].find(quote) {
+            Some(length) => {
+                self.pos = start + length + 1;
+                Ok(Token::Literal(
+                    self.input[start..start + length].to_string(),
+                ))
+            }
+            None => Err(self.error("unterminated string literal")),
+        }
+    }
+
+    fn number(&mut self) -> Token {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if self.eat(".") {
+            while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.pos += 1;
+            }
+        }
+        Token::Number(string_to_number(&self.input[start..self.pos]))
+    }
+
+    fn name(&mut self, operator_expected: bool) -> Result<Token, Error> {
+        let start = self.pos;
+        let name = self.ncname();
+        if operator_expected {
+            return match name.as_str() {
+                "and" => Ok(Token::And),
+                "or" => Ok(Token::Or),
+                "mod" => Ok(Token::Mod),
+                "div" => Ok(Token::Div),
+                _ => {
+                    self.pos = start;
+                    Err(self.error("expected an operator"))
+                }
+            };
+        }
+        if self.followed_by("::") {
+            return Ok(Token::AxisName(name));
+        }
+        if self.peek() == Some(':') && self.peek_at(1) == Some('*') {
+            self.pos += 2;
+            return Ok(Token::NameTest(Some(name), None));
+        }
+        if self.peek() == Some(':') && self.peek_at(1).is_some_and(is_name_start) {
+            self.pos += 1;
+            let local = self.ncname();
+            if self.followed_by("(") {
+                self.pos = start;
+                return Err(self.error("unknown function"));
+            }
+            return Ok(Token::NameTest(Some(name), Some(local)));
+        }
+        if self.followed_by("(") {
+            return Ok(match name.as_str() {
+                "node" | "text" | "comment" | "processing-instruction" => Token::NodeType(name),
+                _ => Token::FunctionName(name),
+            });
+        }
+        Ok(Token::NameTest(None, Some(name)))
+    }
+
+    fn tokens(mut self) -> Result<Vec<Spanned>, Error> {
+        let mut tokens: Vec<Spanned> = Vec::new();
+        loop {
+            while self.peek().is_some_and(is_xpath_whitespace) {
+                self.pos += 1;
+            }
+            let Some(c) = self.peek() else {
+                break;
+            };
+            let start = self.pos;
+            let operator_expected = tokens
+                .last()
+                .is_some_and(|spanned| !spanned.token.precedes_operand());
+            let token = match c {
+                '/' if self.eat("//") => Token::DoubleSlash,
+                '.' if self.eat("..") => Token::DotDot,
+                '.' if self.peek_at(1).is_some_and(|c| c.is_ascii_digit()) => self.number(),
+                '!' if self.eat("!=") => Token::NotEqual,
+                '<' if self.eat("<=") => Token::LessEqual,
+                '>' if self.eat(">=") => Token::GreaterEqual,
+                ':' if self.eat("::") => Token::ColonColon,
+                '"' | '\'' => self.literal(c)?,
+                '$' => {
+                    self.pos += 1;
+                    if !self.peek().is_some_and(is_name_start) {
+                        return Err(self.error("expected a variable name"));
+                    }
+                    Token::Variable(self.ncname())
+                }
+                '*' if operator_expected => {
+                    self.pos += 1;
+                    Token::Multiply
+                }
+                c if c.is_ascii_digit() => self.number(),
+                c if is_name_start(c) => self.name(operator_expected)?,
+                _ => {
+                    let token = match c {
+                        '/' => Token::Slash,
+                        '.' => Token::Dot,
+                        '(' => Token::LeftParen,
+                        ')' => Token::RightParen,
+                        '[' => Token::LeftBracket,
+                        ']' => Token::RightBracket,
+                        '@' => Token::At,
+                        ',' => Token::Comma,
+                        '|' => Token::Pipe,
+                        '+' => Token::Plus,
+                        '-' => Token::Minus,
+                        '=' => Token::Equal,
+                        '<' => Token::Less,
+                        '>' => Token::Greater,
+                        '*' => Token::NameTest(None, None),
+                        _ => return Err(self.error(&format!("unexpected character '{}'", c))),
+                    };
+                    self.pos += c.len_utf8();
+                    token
+                }
+            };
+            tokens.push(Spanned {
+                token,
+                start,
+                end: self.pos,
+            });
+        }
+        Ok(tokens)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Spanned>,
+    pos: usize,
+    namespaces: &'a [(&'a str, &'a str)],
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str, namespaces: &'a [(&'a str, &'a str)]) -> Result<Self, Error> {
+        let tokens = Lexer { input, pos: 0 }.tokens()?;
+        Ok(Parser {
+            input,
+            tokens,
+            pos: 0,
+            namespaces,
+        })
+    }
+
+    // make sure all input has been parsed
+    fn end(&self) -> Result<(), Error> {
+        match self.peek() {
+            Some(_) => Err(self.unexpected()),
+            None => Ok(()),
+        }
+    }
+
+    fn error(&self, message: &str) -> Error {
+        let position = self
+            .tokens
+            .get(self.pos)
+            .map_or(self.input.len(), |spanned| spanned.start);
+        Error::InvalidXPath(format!("{} at position {}", message, position))
+    }
+
+    fn unexpected(&self) -> Error {
+        match self.tokens.get(self.pos) {
+            Some(spanned) => self.error(&format!(
+                "unexpected '{}'",
+                &self.input[spanned.start..spanned.end]
+            )),
+            None => self.error("unexpected end of expression"),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|spanned| &spanned.token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<(), Error> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn namespace(&self, prefix: &str) -> Result<String, Error> {
+        if prefix == "xml" {
+            return Ok(XML_NS.to_string());
+        }
+        self.namespaces
+            .iter()
+            .find(|(p, _)| *p == prefix)
+            .map(|(_, namespace)| namespace.to_string())
+            .ok_or_else(|| self.error(&format!("unknown prefix '{}'", prefix)))
+    }
+
+    fn expr(&mut self) -> Result<Expr, Error> {
+        let mut left = self.and_expr()?;
+        while self.eat(&Token::Or) {
+            left = Expr::Or(Box::new(left), Box::new(self.and_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, Error> {
+        let mut left = self.equality_expr()?;
+        while self.eat(&Token::And) {
+            left = Expr::And(Box::new(left), Box::new(self.equality_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn equality_expr(&mut self) -> Result<Expr, Error> {
+        let mut left = self.relational_expr()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Equal) => CompareOperator::Equal,
+                Some(Token::NotEqual) => CompareOperator::NotEqual,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::Compare(operator, Box::new(left), Box::new(self.relational_expr()?));
+        }
+    }
+
+    fn relational_expr(&mut self) -> Result<Expr, Error> {
+        let mut left = self.additive_expr()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Less) => CompareOperator::Less,
+                Some(Token::LessEqual) => CompareOperator::LessEqual,
+                Some(Token::Greater) => CompareOperator::Greater,
+                Some(Token::GreaterEqual) => CompareOperator::GreaterEqual,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::Compare(operator, Box::new(left), Box::new(self.additive_expr()?));
+        }
+    }
+
+    fn additive_expr(&mut self) -> Result<Expr, Error> {
+        let mut left = self.multiplicative_expr()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Plus) => ArithmeticOperator::Add,
+                Some(Token::Minus) => ArithmeticOperator::Subtract,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::Arithmetic(
+                operator,
+                Box::new(left),
+                Box::new(self.multiplicative_expr()?),
+            );
+        }
+    }
+
+    fn multiplicative_expr(&mut self) -> Result<Expr, Error> {
+        let mut left = self.unary_expr()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Multiply) => ArithmeticOperator::Multiply,
+                Some(Token::Div) => ArithmeticOperator::Divide,
+                Some(Token::Mod) => ArithmeticOperator::Modulo,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::Arithmetic(operator, Box::new(left), Box::new(self.unary_expr()?));
+        }
+    }
+
+    fn unary_expr(&mut self) -> Result<Expr, Error> {
+        if self.eat(&Token::Minus) {
+            Ok(Expr::Negate(Box::new(self.unary_expr()?)))
+        } else {
+            self.union_expr()
+        }
+    }
+
+    fn union_expr(&mut self) -> Result<Expr, Error> {
+        let mut left = self.path_expr()?;
+        while self.eat(&Token::Pipe) {
+            left = Expr::Union(Box::new(left), Box::new(self.path_expr()?));
+        }
+        Ok(left)
+    }
+
+    fn starts_step(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(
+                Token::NameTest(..)
+                    | Token::NodeType(_)
+                    | Token::AxisName(_)
+                    | Token::At
+                    | Token::Dot
+                    | Token::DotDot
+            )
+        )
+    }
+
+    fn path_expr(&mut self) -> Result<Expr, Error> {
+        if self.eat(&Token::Slash) {
+            if !self.starts_step() {
+                return Ok(Expr::Root);
+            }
+            return Ok(Expr::Path(Box::new(Expr::Root), self.relative_path()?));
+        }
+        if self.eat(&Token::DoubleSlash) {
+            let mut steps = vec![descendant_or_self()];
+            steps.extend(self.relative_path()?);
+            return Ok(Expr::Path(Box::new(Expr::Root), steps));
+        }
+        if self.starts_step() {
+            return Ok(Expr::Path(
+                Box::new(Expr::ContextNode),
+                self.relative_path()?,
+            ));
+        }
+        let primary = self.primary_expr()?;
+        let predicates = self.predicates()?;
+        let filter = if predicates.is_empty() {
+            primary
+        } else {
+            Expr::Filter(Box::new(primary), predicates)
+        };
+        if self.eat(&Token::Slash) {
+            Ok(Expr::Path(Box::new(filter), self.relative_path()?))
+        } else if self.eat(&Token::DoubleSlash) {
+            let mut steps = vec![descendant_or_self()];
+            steps.extend(self.relative_path()?);
+            Ok(Expr::Path(Box::new(filter), steps))
+        } else {
+            Ok(filter)
+        }
+    }
+
+    fn relative_path(&mut self) -> Result<Vec<Step>, Error> {
+        let mut steps = vec![self.step()?];
+        loop {
+            if self.eat(&Token::Slash) {
+                steps.push(self.step()?);
+            } else if self.eat(&Token::DoubleSlash) {
+                steps.push(descendant_or_self());
+                steps.push(self.step()?);
+            } else {
+                return Ok(steps);
+            }
+        }
+    }
+
+    fn step(&mut self) -> Result<Step, Error> {
+        if self.eat(&Token::Dot) {
+            return Ok(Step::new(Axis::Self_, NodeTest::Node));
+        }
+        if self.eat(&Token::DotDot) {
+            return Ok(Step::new(Axis::Parent, NodeTest::Node));
+        }
+        let axis = if self.eat(&Token::At) {
+            Axis::Attribute
+        } else if let Some(Token::AxisName(name)) = self.peek() {
+            let axis = match name.as_str() {
+                "ancestor" => Axis::Ancestor,
+                "ancestor-or-self" => Axis::AncestorOrSelf,
+                "attribute" => Axis::Attribute,
+                "child" => Axis::Child,
+                "descendant" => Axis::Descendant,
+                "descendant-or-self" => Axis::DescendantOrSelf,
+                "following" => Axis::Following,
+                "following-sibling" => Axis::FollowingSibling,
+                "parent" => Axis::Parent,
+                "preceding" => Axis::Preceding,
+                "preceding-sibling" => Axis::PrecedingSibling,
+                "self" => Axis::Self_,
+                "namespace" => return Err(self.error("the namespace axis is not supported")),
+                _ => return Err(self.error(&format!("unknown axis '{}'", name))),
+            };
+            self.pos += 1;
+            self.expect(&Token::ColonColon)?;
+            axis
+        } else {
+            Axis::Child
+        };
+        let test = self.node_test()?;
+        Ok(Step {
+            axis,
+            test,
+            predicates: self.predicates()?,
+        })
+    }
+
+    fn node_test(&mut self) -> Result<NodeTest, Error> {
+        let test = match self.peek().cloned() {
+            Some(Token::NameTest(prefix, local)) => {
+                let namespace = prefix.map(|prefix| self.namespace(&prefix)).transpose()?;
+                match (namespace, local) {
+                    (None, None) => NodeTest::AnyName,
+                    (Some(namespace), None) => NodeTest::NamespaceName(namespace),
+                    (namespace, Some(local)) => NodeTest::Name {
+                        namespace: namespace.unwrap_or_default(),
+                        local,
+                    },
+                }
+            }
+            Some(Token::NodeType(node_type)) => {
+                self.pos += 1;
+                self.expect(&Token::LeftParen)?;
+                let test = match node_type.as_str() {
+                    "node" => NodeTest::Node,
+                    "text" => NodeTest::Text,
+                    "comment" => NodeTest::Comment,
+                    _ => match self.peek().cloned() {
+                        Some(Token::Literal(target)) => {
+                            self.pos += 1;
+                            NodeTest::ProcessingInstruction(Some(target))
+                        }
+                        _ => NodeTest::ProcessingInstruction(None),
+                    },
+                };
+                self.expect(&Token::RightParen)?;
+                return Ok(test);
+            }
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        Ok(test)
+    }
+
+    fn predicates(&mut self) -> Result<Vec<Expr>, Error> {
+        let mut predicates = Vec::new();
+        while self.eat(&Token::LeftBracket) {
+            predicates.push(self.expr()?);
+            self.expect(&Token::RightBracket)?;
+        }
+        Ok(predicates)
+    }
+
+    fn path_pattern(&mut self) -> Result<PathPattern, Error> {
+        let anchor = match self.peek() {
+            Some(Token::Slash | Token::DoubleSlash) => Anchor::Root,
+            Some(Token::FunctionName(name)) if name == "id" => {
+                self.pos += 1;
+                self.expect(&Token::LeftParen)?;
+                let Some(Token::Literal(ids)) = self.peek().cloned() else {
+                    return Err(self.error("id() in a pattern takes a literal"));
+                };
+                self.pos += 1;
+                self.expect(&Token::RightParen)?;
+                Anchor::Id(ids)
+            }
+            _ => Anchor::Any,
+        };
+        let mut steps = Vec::new();
+        if anchor == Anchor::Any {
+            steps.push((false, self.step_pattern()?));
+        } else if !self.starts_separator() {
+            // `/` and `id(...)` are patterns by themselves
+            return Ok(PathPattern { anchor, steps });
+        }
+        while self.starts_separator() {
+            let descendant = self.eat(&Token::DoubleSlash);
+            if !descendant {
+                self.expect(&Token::Slash)?;
+            }
+            if anchor == Anchor::Root && steps.is_empty() && !descendant && !self.starts_step() {
+                return Ok(PathPattern { anchor, steps });
+            }
+            steps.push((descendant, self.step_pattern()?));
+        }
+        Ok(PathPattern { anchor, steps })
+    }
+
+    fn starts_separator(&self) -> bool {
+        matches!(self.peek(), Some(Token::Slash | Token::DoubleSlash))
+    }
+
+    fn step_pattern(&mut self) -> Result<Step, Error> {
+        let start = self.pos;
+        let step = self.step()?;
+        if !matches!(step.axis, Axis::Child | Axis::Attribute) {
+            self.pos = start;
+            return Err(self.error("only the child and attribute axes are allowed in a pattern"));
+        }
+        if step.predicates.iter().any(Expr::has_variable) {
+            self.pos = start;
+            return Err(self.error("variables are not allowed in a pattern"));
+        }
+        Ok(step)
+    }
+
+    fn primary_expr(&mut self) -> Result<Expr, Error> {
+        let expr = match self.peek().cloned() {
+            Some(Token::LeftParen) => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect(&Token::RightParen)?;
+                return Ok(expr);
+            }
+            Some(Token::Literal(literal)) => Expr::Literal(literal),
+            Some(Token::Number(number)) => Expr::Number(number),
+            Some(Token::Variable(name)) => Expr::Variable(name),
+            Some(Token::FunctionName(name)) => return self.function_call(&name),
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        Ok(expr)
+    }
+
+    fn function_call(&mut self, name: &str) -> Result<Expr, Error> {
+        let function = Function::parse(name)
+            .ok_or_else(|| self.error(&format!("unknown function '{}'", name)))?;
+        let start = self.pos;
+        self.pos += 1;
+        self.expect(&Token::LeftParen)?;
+        let mut args = Vec::new();
+        if !self.eat(&Token::RightParen) {
+            loop {
+                args.push(self.expr()?);
+                if self.eat(&Token::RightParen) {
+                    break;
+                }
+                self.expect(&Token::Comma)?;
+            }
+        }
+        let (min, max) = function.arity();
+        if args.len() < min || args.len() > max {
+            self.pos = start;
+            return Err(self.error(&format!(
+                "wrong number of arguments for function '{}'",
+                name
+            )));
+        }
+        Ok(Expr::Function(function, args))
+    }
+}
+
+// `//` is short for `/descendant-or-self::node()/`
+fn descendant_or_self() -> Step {
+    Step::new(Axis::DescendantOrSelf, NodeTest::Node)
+}
+
+fn is_xpath_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r' | '\n')
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start(c) || c.is_ascii_digit() || c == '-' || c == '.'
+}
+
+fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n == 0.0 {
+        // this includes negative zero
+        "0".to_string()
+    } else {
+        // this doesn't use exponents, and leaves out `.0` for integers
+        n.to_string()
+    }
+}
+
+// XPath numbers have no exponent and no leading `+`
+fn string_to_number(s: &str) -> f64 {
+    let s = s.trim_matches(is_xpath_whitespace);
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let is_number = (!integer.is_empty() || !fraction.is_empty())
+        && integer.chars().all(|c| c.is_ascii_digit())
+        && fraction.chars().all(|c| c.is_ascii_digit());
+    if is_number {
+        s.parse().unwrap_or(f64::NAN)
+    } else {
+        f64::NAN
+    }
+}
+
+fn round(n: f64) -> f64 {
+    if n.is_nan() || n.is_infinite() {
+        n
+    } else if (-0.5..0.0).contains(&n) {
+        -0.0
+    } else {
+        (n + 0.5).floor()
+    }
+}
+
+fn compare_values(xot: &Xot, operator: CompareOperator, a: &Value, b: &Value) -> bool {
+    use CompareOperator::*;
+    match operator {
+        Equal | NotEqual => {
+            let equal = if matches!(a, Value::Boolean(_)) || matches!(b, Value::Boolean(_)) {
+                a.boolean() == b.boolean()
+            } else if matches!(a, Value::Number(_)) || matches!(b, Value::Number(_)) {
+                a.number(xot) == b.number(xot)
+            } else {
+                a.string(xot) == b.string(xot)
+            };
+            equal == (operator == Equal)
+        }
+        _ => {
+            let (a, b) = (a.number(xot), b.number(xot));
+            match operator {
+                Less => a < b,
+                LessEqual => a <= b,
+                Greater => a > b,
+                _ => a >= b,
+            }
+        }
+    }
+}
+
+// a node in a node-set, to compare with a value that isn't a node-set
+fn node_value(xot: &Xot, node: Node, other: &Value) -> Value {
+    let s = xot.string_value(node);
+    match other {
+        Value::Number(_) => Value::Number(string_to_number(&s)),
+        _ => Value::String(s),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Context {
+    node: Node,
+    position: usize,
+    size: usize,
+}
+
+struct Evaluator<'a> {
+    xot: &'a Xot,
+    variables: &'a HashMap<String, Value>,
+    // the position of nodes in document order, for the trees we've seen
+    order: HashMap<Node, usize>,
+}
+
+impl Evaluator<'_> {
+    fn eval(&mut self, expr: &Expr, context: Context) -> Result<Value, Error> {
+        let xot = self.xot;
+        Ok(match expr {
+            Expr::Or(a, b) => {
+                Value::Boolean(self.eval(a, context)?.boolean() || self.eval(b, context)?.boolean())
+            }
+            Expr::And(a, b) => {
+                Value::Boolean(self.eval(a, context)?.boolean() && self.eval(b, context)?.boolean())
+            }
+            Expr::Compare(operator, a, b) => {
+                let a = self.eval(a, context)?;
+                let b = self.eval(b, context)?;
+                Value::Boolean(self.compare(*operator, &a, &b))
+            }
+            Expr::Arithmetic(operator, a, b) => {
+                let a = self.eval(a, context)?.number(xot);
+                let b = self.eval(b, context)?.number(xot);
+                Value::Number(operator.apply(a, b))
+            }
+            Expr::Negate(a) => Value::Number(-self.eval(a, context)?.number(xot)),
+            Expr::Union(a, b) => {
+                let mut nodes = self.nodes(a, context)?;
+                nodes.extend(self.nodes(b, context)?);
+                self.sort(&mut nodes);
+                Value::Nodes(nodes)
+            }
+            Expr::Literal(literal) => Value::String(literal.clone()),
+            Expr::Number(number) => Value::Number(*number),
+            Expr::Variable(name) => match self.variables.get(name) {
+                // a node-set we're given may not be in document order
+                Some(Value::Nodes(nodes)) => {
+                    let mut nodes = nodes.clone();
+                    self.sort(&mut nodes);
+                    Value::Nodes(nodes)
+                }
+                Some(value) => value.clone(),
+                None => return Err(Error::InvalidXPath(format!("unbound variable '${}'", name))),
+            },
+            Expr::Function(function, args) => self.call(*function, args, context)?,
+            Expr::Root => Value::Nodes(vec![xot.root(context.node)]),
+            Expr::ContextNode => Value::Nodes(vec![context.node]),
+            Expr::Filter(primary, predicates) => {
+                let mut nodes = self.nodes(primary, context)?;
+                for predicate in predicates {
+                    nodes = self.filter(nodes, predicate)?;
+                }
+                Value::Nodes(nodes)
+            }
+            Expr::Path(start, steps) => {
+                let mut nodes = self.nodes(start, context)?;
+                for step in steps {
+                    nodes = self.step(&nodes, step)?;
+                }
+                Value::Nodes(nodes)
+            }
+        })
+    }
+
+    fn nodes(&mut self, expr: &Expr, context: Context) -> Result<Vec<Node>, Error> {
+        match self.eval(expr, context)? {
+            Value::Nodes(nodes) => Ok(nodes),
+            _ => Err(Error::InvalidXPath("expected a node-set".to_string())),
+        }
+    }
+
+    // keep the nodes for which the predicate is true, or which are at the
+    // position it gives
+    fn filter(&mut self, nodes: Vec<Node>, predicate: &Expr) -> Result<Vec<Node>, Error> {
+        let size = nodes.len();
+        let mut filtered = Vec::new();
+        for (i, node) in nodes.into_iter().enumerate() {
+            let position = i + 1;
+            let keep = match self.eval(
+                predicate,
+                Context {
+                    node,
+                    position,
+                    size,
+                },
+            )? {
+                Value::Number(n) => n == position as f64,
+                value => value.boolean(),
+            };
+            if keep {
+                filtered.push(node);
+            }
+        }
+        Ok(filtered)
+    }
+
+    fn step(&mut self, contexts: &[Node], step: &Step) -> Result<Vec<Node>, Error> {
+        let xot = self.xot;
+        let mut result = Vec::new();
+        for context in contexts {
+            // the nodes are in the order of the axis, which is what
+            // positions in the predicates are based on
+            let mut nodes = xot
+                .axis(step.axis, *context)
+                .filter(|node| step.test.matches(xot, *node, step.axis))
+                .collect::<Vec<_>>();
+            for predicate in &step.predicates {
+                nodes = self.filter(nodes, predicate)?;
+            }
+            result.extend(nodes);
+        }
+        if contexts.len() > 1 || step.axis.is_reverse() {
+            self.sort(&mut result);
+        }
+        Ok(result)
+    }
+
+    // whether `node` matches the first `n` steps of a path pattern
+    fn matches_path(&mut self, pattern: &PathPattern, node: Node, n: usize) -> Result<bool, Error> {
+        let xot = self.xot;
+        if n == 0 {
+            return Ok(match &pattern.anchor {
+                Anchor::Any => true,
+                Anchor::Root => xot.is_document(node),
+                Anchor::Id(ids) => {
+                    let root = xot.root(node);
+                    xot.is_document(root)
+                        && ids
+                            .split(is_xpath_whitespace)
+                            .any(|id| xot.xml_id_node(root, id) == Some(node))
+                }
+            });
+        }
+        let (descendant, step) = &pattern.steps[n - 1];
+        let Some(parent) = xot.parent(node) else {
+            return Ok(false);
+        };
+        if !self.matches_step(step, node, parent)? {
+            return Ok(false);
+        }
+        if *descendant {
+            for ancestor in xot.ancestors(parent) {
+                if self.matches_path(pattern, ancestor, n - 1)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        } else {
+            self.matches_path(pattern, parent, n - 1)
+        }
+    }
+
+    fn matches_step(&mut self, step: &Step, node: Node, parent: Node) -> Result<bool, Error> {
+        let xot = self.xot;
+        let is_attribute = xot.is_attribute_node(node);
+        if is_attribute != (step.axis == Axis::Attribute)
+            || xot.is_namespace_node(node)
+            || !step.test.matches(xot, node, step.axis)
+        {
+            return Ok(false);
+        }
+        if step.predicates.is_empty() {
+            return Ok(true);
+        }
+        // predicates are evaluated against the nodes the step selects from
+        // the parent, so that positions work
+        let mut nodes = xot
+            .axis(step.axis, parent)
+            .filter(|node| step.test.matches(xot, *node, step.axis))
+            .collect::<Vec<_>>();
+        for predicate in &step.predicates {
+            nodes = self.filter(nodes, predicate)?;
+        }
+        Ok(nodes.contains(&node))
+    }
+
+    // put nodes in document order and remove duplicates
+    fn sort(&mut self, nodes: &mut Vec<Node>) {
+        if nodes.len() < 2 {
+            return;
+        }
+        for node in nodes.iter() {
+            if !self.order.contains_key(node) {
+                let start = self.order.len();
+                for (i, node) in self.xot.all_descendants(self.xot.root(*node)).enumerate() {
+                    self.order.insert(node, start + i);
+                }
+            }
+        }
+        nodes.sort_by_key(|node| self.order[node]);
+        nodes.dedup();
+    }
+
+    fn compare(&self, operator: CompareOperator, a: &Value, b: &Value) -> bool {
+        let xot = self.xot;
+        match (a, b) {
+            (Value::Nodes(a), Value::Nodes(b)) => {
+                let b = b
+                    .iter()
+                    .map(|node| Value::String(xot.string_value(*node)))
+                    .collect::<Vec<_>>();
+                a.iter().any(|node| {
+                    let a = Value::String(xot.string_value(*node));
+                    b.iter().any(|b| compare_values(xot, operator, &a, b))
+                })
+            }
+            (Value::Nodes(_), Value::Boolean(_)) | (Value::Boolean(_), Value::Nodes(_)) => {
+                compare_values(
+                    xot,
+                    operator,
+                    &Value::Boolean(a.boolean()),
+                    &Value::Boolean(b.boolean()),
+                )
+            }
+            (Value::Nodes(nodes), other) => nodes
+                .iter()
+                .any(|node| compare_values(xot, operator, &node_value(xot, *node, other), other)),
+            (other, Value::Nodes(nodes)) => nodes
+                .iter()
+                .any(|node| compare_values(xot, operator, other, &node_value(xot, *node, other))),
+            _ => compare_values(xot, operator, a, b),
+        }
+    }
+
+    // the string value of an argument, or of the context node if it's
+    // left out
+    fn string_arg(
+        &mut self,
+        args: &[Expr],
+        index: usize,
+        context: Context,
+    ) -> Result<String, Error> {
+        match args.get(index) {
+            Some(arg) => Ok(self.eval(arg, context)?.string(self.xot)),
+            None => Ok(self.xot.string_value(context.node)),
+        }
+    }
+
+    fn number_arg(&mut self, args: &[Expr], index: usize, context: Context) -> Result<f64, Error> {
+        Ok(self.eval(&args[index], context)?.number(self.xot))
+    }
+
+    fn call(
+        &mut self,
+        function: Function,
+        args: &[Expr],
+        context: Context,
+    ) -> Result<Value, Error> {
+        use Function::*;
+        let xot = self.xot;
+        Ok(match function {
+            Last => Value::Number(context.size as f64),
+            Position => Value::Number(context.position as f64),
+            Count => Value::Number(self.nodes(&args[0], context)?.len() as f64),
+            Id => {
+                let ids = match self.eval(&args[0], context)? {
+                    Value::Nodes(nodes) => nodes
+                        .iter()
+                        .map(|node| xot.string_value(*node))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    value => value.string(xot),
+                };
+                let root = xot.root(context.node);
+                let mut nodes = if xot.is_document(root) {
+                    ids.split(is_xpath_whitespace)
+                        .filter_map(|id| xot.xml_id_node(root, id))
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                self.sort(&mut nodes);
+                Value::Nodes(nodes)
+            }
+            LocalName | NamespaceUri | Name => {
+                let node = match args.first() {
+                    Some(arg) => self.nodes(arg, context)?.first().copied(),
+                    None => Some(context.node),
+                };
+                let name = node.and_then(|node| Some((node, xot.node_name(node)?)));
+                Value::String(match name {
+                    None => std::string::String::new(),
+                    Some((_, name)) if function == LocalName => {
+                        xot.local_name_str(name).to_string()
+                    }
+                    Some((_, name)) if function == NamespaceUri => {
+                        xot.namespace_str(xot.namespace_for_name(name)).to_string()
+                    }
+                    // a name in a namespace without a prefix in scope is
+                    // given by its local name
+                    Some((node, name)) => match xot.node_name_ref(node) {
+                        Ok(Some(name)) => name.full_name().to_string(),
+                        _ => xot.local_name_str(name).to_string(),
+                    },
+                })
+            }
+            String => Value::String(self.string_arg(args, 0, context)?),
+            Concat => {
+                let mut s = std::string::String::new();
+                for arg in args {
+                    s.push_str(&self.eval(arg, context)?.string(xot));
+                }
+                Value::String(s)
+            }
+            StartsWith | Contains | SubstringBefore | SubstringAfter => {
+                let a = self.string_arg(args, 0, context)?;
+                let b = self.string_arg(args, 1, context)?;
+                match function {
+                    StartsWith => Value::Boolean(a.starts_with(&b)),
+                    Contains => Value::Boolean(a.contains(&b)),
+                    SubstringBefore => {
+                        Value::String(a.find(&b).map_or("", |i| &a[..i]).to_string())
+                    }
+                    _ => Value::String(a.find(&b).map_or("", |i| &a[i + b.len()..]).to_string()),
+                }
+            }
+            Substring => {
+                let s = self.string_arg(args, 0, context)?;
+                let start = round(self.number_arg(args, 1, context)?);
+                let end = if args.len() > 2 {
+                    start + round(self.number_arg(args, 2, context)?)
+                } else {
+                    f64::INFINITY
+                };
+                Value::String(
+                    s.chars()
+                        .enumerate()
+                        .filter(|(i, _)| {
+                            let position = (i + 1) as f64;
+                            position >= start && position < end
+                        })
+                        .map(|(_, c)| c)
+                        .collect(),
+                )
+            }
+            StringLength => {
+                Value::Number(self.string_arg(args, 0, context)?.chars().count() as f64)
+            }
+            NormalizeSpace => Value::String(
+                self.string_arg(args, 0, context)?
+                    .split(is_xpath_whitespace)
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            Translate => {
+                let s = self.string_arg(args, 0, context)?;
+                let from = self
+                    .string_arg(args, 1, context)?
+                    .chars()
+                    .collect::<Vec<_>>();
+                let to = self
+                    .string_arg(args, 2, context)?
+                    .chars()
+                    .collect::<Vec<_>>();
+                Value::String(
+                    s.chars()
+                        .filter_map(|c| match from.iter().position(|f| *f == c) {
+                            Some(i) => to.get(i).copied(),
+                            None => Some(c),
+                        })
+                        .collect(),
+                )
+            }
+            Boolean => Value::Boolean(self.eval(&args[0], context)?.boolean()),
+            Not => Value::Boolean(!self.eval(&args[0], context)?.boolean()),
+            True => Value::Boolean(true),
+            False => Value::Boolean(false),
+            Lang => {
+                let lang = self.string_arg(args, 0, context)?.to_ascii_lowercase();
+                let node_lang = xot.name_ns("lang", xot.xml_namespace()).and_then(|name| {
+                    xot.ancestors(context.node)
+                        .find_map(|node| xot.get_attribute(node, name))
+                });
+                Value::Boolean(node_lang.is_some_and(|node_lang| {
+                    let node_lang = node_lang.to_ascii_lowercase();
+                    node_lang == lang || node_lang.starts_with(&format!("{}-", lang))
+                }))
+            }
+            Number => Value::Number(match args.first() {
+                Some(arg) => self.eval(arg, context)?.number(xot),
+                None => string_to_number(&xot.string_value(context.node)),
+            }),
+            Sum => Value::Number(
+                self.nodes(&args[0], context)?
+                    .iter()
+                    .map(|node| string_to_number(&xot.string_value(*node)))
+                    .sum(),
+            ),
+            Floor => Value::Number(self.number_arg(args, 0, context)?.floor()),
+            Ceiling => Value::Number(self.number_arg(args, 0, context)?.ceil()),
+            Round => Value::Number(round(self.number_arg(args, 0, context)?)),
+        })
+    }
+}
+
+/// ## XPath
+impl Xot {
+    /// Evaluate an XPath 1.0 expression, with `node` as the context node.
+    ///
+    /// This requires the `xpath` feature. See the [`xpath`](`crate::xpath`)
+    /// module for what is supported. Returns [`Error::InvalidXPath`] if the
+    /// expression cannot be parsed or evaluated.
+    ///
+    /// ```rust
+    /// use xot::Xot;
+    /// use xot::xpath::Value;
+    ///
+    /// let mut xot = Xot::new();
+    /// let root = xot.parse(r#"<a><b id="x">Hello</b><b id="y">World</b></a>"#)?;
+    ///
+    /// let value = xot.evaluate(root, "/a/b[@id='x']/text()")?;
+    /// let text = value.nodes().unwrap()[0];
+    /// assert_eq!(xot.text_str(text), Some("Hello"));
+    ///
+    /// assert_eq!(xot.evaluate(root, "count(//b)")?, Value::Number(2.0));
+    /// assert_eq!(
+    ///     xot.evaluate(root, "concat(/a/b[1], ' ', /a/b[2])")?,
+    ///     Value::String("Hello World".to_string())
+    /// );
+    /// assert_eq!(xot.evaluate(root, "//b[2]/@id = 'y'")?, Value::Boolean(true));
+    /// # Ok::<(), xot::Error>(())
+    /// ```
+    pub fn evaluate(&self, node: Node, xpath: &str) -> Result<Value, Error> {
+        XPath::parse(xpath)?.evaluate(self, node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    const DOC: &str = r#"<doc xmlns:x="urn:x">
+<!--start-->
+<section id="s1" xml:lang="en-US">
+<title>First</title>
+<p n="1">one</p>
+<p n="2">two <b>bold</b></p>
+<x:p n="3">three</x:p>
+</section>
+<section id="s2" xml:id="second">
+<title>Second</title>
+<p n="4">four</p>
+<?target data?>
+</section>
+</doc>"#;
+
+    fn evaluate(xpath: &str) -> Value {
+        let mut xot = Xot::new();
+        let root = xot.parse(DOC).unwrap();
+        XPath::compile(xpath, &[("x", "urn:x")])
+            .unwrap()
+            .evaluate(&xot, root)
+            .unwrap()
+    }
+
+    // the nodes of a node-set, described by their name or their text
+    fn select(xpath: &str) -> Vec<String> {
+        let mut xot = Xot::new();
+        let root = xot.parse(DOC).unwrap();
+        let value = XPath::compile(xpath, &[("x", "urn:x")])
+            .unwrap()
+            .evaluate(&xot, root)
+            .unwrap();
+        value
+            .nodes()
+            .unwrap()
+            .iter()
+            .map(|node| match xot.node_name(*node) {
+                Some(name) if xot.is_element(*node) => {
+                    let n = xot.get_attribute(*node, xot.name("n").unwrap());
+                    match n {
+                        Some(n) => format!("{}{}", xot.local_name_str(name), n),
+                        None => xot.local_name_str(name).to_string(),
+                    }
+                }
+                Some(name) if xot.is_processing_instruction(*node) => {
+                    format!("?{}", xot.local_name_str(name))
+                }
+                _ => xot.string_value(*node),
+            })
+            .collect()
+    }
+
+    #[rstest]
+    #[case("/doc/section/p", vec!["p1", "p2", "p4"])]
+    #[case("//p", vec!["p1", "p2", "p4"])]
+    #[case("//x:p", vec!["p3"])]
+    #[case("//x:*", vec!["p3"])]
+    #[case("/doc/section[2]/*", vec!["title", "p4"])]
+    #[case("//section/*[1]", vec!["title", "title"])]
+    #[case("//p[last()]", vec!["p2", "p4"])]
+    #[case("(//p)[last()]", vec!["p4"])]
+    #[case("//p[@n > 1][1]", vec!["p2", "p4"])]
+    #[case("//p[b]", vec!["p2"])]
+    #[case("//b/ancestor::*", vec!["doc", "section", "p2"])]
+    #[case("//b/ancestor::*[1]", vec!["p2"])]
+    #[case("//p[@n='4']/preceding::p[1]", vec!["p2"])]
+    #[case("//x:p/preceding-sibling::*[2]", vec!["p1"])]
+    #[case("//title/following-sibling::p", vec!["p1", "p2", "p4"])]
+    #[case("//b/..", vec!["p2"])]
+    #[case("//p/@n", vec!["1", "2", "4"])]
+    #[case("//@*[. = 's2']", vec!["s2"])]
+    #[case("//title/text()", vec!["First", "Second"])]
+    #[case("/doc/comment()", vec!["start"])]
+    #[case("//processing-instruction('target')", vec!["?target"])]
+    #[case("//processing-instruction('other')", vec![])]
+    #[case("//title | //b", vec!["title", "b", "title"])]
+    #[case("//section[p[@n = 4]]/title", vec!["title"])]
+    #[case("/", vec!["\n\n\nFirst\none\ntwo bold\nthree\n\n\nSecond\nfour\n\n\n"])]
+    #[case("/doc/section[1]/self::section/child::title", vec!["title"])]
+    #[case("id('second')/title", vec!["title"])]
+    #[case("//*[lang('en')]", vec!["section", "title", "p1", "p2", "b", "p3"])]
+    fn test_select(#[case] xpath: &str, #[case] expected: Vec<&str>) {
+        assert_eq!(select(xpath), expected);
+    }
+
+    #[rstest]
+    #[case("count(//p)", 3.0)]
+    #[case("sum(//@n)", 10.0)]
+    #[case("1 + 2 * 3 - 4 div 2", 5.0)]
+    #[case("7 mod 3", 1.0)]
+    #[case("-(2 - 5)", 3.0)]
+    #[case("string-length('héllo')", 5.0)]
+    #[case("number(' 12.5 ')", 12.5)]
+    #[case("floor(2.5)", 2.0)]
+    #[case("ceiling(2.1)", 3.0)]
+    #[case("round(2.5)", 3.0)]
+    #[case("round(-2.5)", -2.0)]
+    #[case("number(//p[1]/@n) * 10", 10.0)]
+    #[case("count(//section[1]/p[2]/preceding-sibling::*)", 2.0)]
+    fn test_number(#[case] xpath: &str, #[case] expected: f64) {
+        assert_eq!(evaluate(xpath), Value::Number(expected));
+    }
+
+    #[rstest]
+    #[case("concat('a', 'b', 'c')", "abc")]
+    #[case("string(//title)", "First")]
+    #[case("substring('12345', 2, 3)", "234")]
+    #[case("substring('12345', 1.5, 2.6)", "234")]
+    #[case("substring('12345', 0, 3)", "12")]
+    #[case("substring('12345', 2)", "2345")]
+    #[case("substring-before('1999/04/01', '/')", "1999")]
+    #[case("substring-after('1999/04/01', '/')", "04/01")]
+    #[case("normalize-space('  a \n b  ')", "a b")]
+    #[case("translate('bar', 'abc', 'ABC')", "BAr")]
+    #[case("translate('--aaa--', 'abc-', 'ABC')", "AAA")]
+    #[case("local-name(//x:p)", "p")]
+    #[case("name(//x:p)", "x:p")]
+    #[case("namespace-uri(//x:p)", "urn:x")]
+    #[case("namespace-uri(//p)", "")]
+    #[case("name(/)", "")]
+    #[case("string(1 div 0)", "Infinity")]
+    #[case("string(0 div 0)", "NaN")]
+    #[case("string(-0)", "0")]
+    #[case("string(1.50)", "1.5")]
+    #[case("string(1 = 1)", "true")]
+    fn test_string(#[case] xpath: &str, #[case] expected: &str) {
+        assert_eq!(evaluate(xpath), Value::String(expected.to_string()));
+    }
+
+    #[rstest]
+    #[case("//p = 'four'", true)]
+    #[case("//p != 'four'", true)]
+    #[case("//p = 'five'", false)]
+    #[case("//p/@n = 4", true)]
+    #[case("//p/@n > 3", true)]
+    #[case("//p/@n > 4", false)]
+    #[case("//title = //section/title", true)]
+    #[case("//nothing = //nothing", false)]
+    #[case("//p = true()", true)]
+    #[case("//nothing = false()", true)]
+    #[case("1 < 2 and 2 <= 2", true)]
+    #[case("1 > 2 or 2 >= 3", false)]
+    #[case("'1' = 1.0", true)]
+    #[case("not(0 div 0 = 0 div 0)", true)]
+    #[case("boolean('')", false)]
+    #[case("starts-with('hello', 'he')", true)]
+    #[case("contains('hello', 'ell')", true)]
+    #[case("lang('en')", false)]
+    fn test_boolean(#[case] xpath: &str, #[case] expected: bool) {
+        assert_eq!(evaluate(xpath), Value::Boolean(expected));
+    }
+
+    #[test]
+    fn test_relative_to_context() {
+        let mut xot = Xot::new();
+        let root = xot.parse(DOC).unwrap();
+        let section = xot.evaluate(root, "//section[2]").unwrap().nodes().unwrap()[0];
+        assert_eq!(
+            xot.evaluate(section, "string(p)").unwrap(),
+            Value::String("four".to_string())
+        );
+        assert_eq!(
+            xot.evaluate(section, "count(//p)").unwrap(),
+            Value::Number(3.0)
+        );
+        assert_eq!(
+            xot.evaluate(section, "string(@id)").unwrap(),
+            Value::String("s2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut xot = Xot::new();
+        let root = xot.parse(DOC).unwrap();
+        let mut xpath = XPath::parse("count(//p[@n > $min]) + $extra").unwrap();
+        xpath.set_variable("min", Value::Number(1.0));
+        xpath.set_variable("extra", Value::String("10".to_string()));
+        assert_eq!(xpath.evaluate(&xot, root).unwrap(), Value::Number(12.0));
+
+        // a node-set variable is put in document order
+        let titles = xot
+            .evaluate(root, "//title")
+            .unwrap()
+            .nodes()
+            .unwrap()
+            .to_vec();
+        let mut xpath = XPath::parse("$titles[1]/following-sibling::p[1]/@n").unwrap();
+        xpath.set_variable("titles", Value::Nodes(vec![titles[1], titles[0]]));
+        assert_eq!(xpath.evaluate(&xot, root).unwrap().string(&xot), "1");
+
+        assert_eq!(
+            xpath.remove_variable("titles"),
+            Some(Value::Nodes(vec![titles[1], titles[0]]))
+        );
+        assert!(matches!(
+            xpath.evaluate(&xot, root),
+            Err(Error::InvalidXPath(_))
+        ));
+    }
+
+    #[rstest]
+    #[case("p", "//p[1]", true)]
+    #[case("p", "//x:p", false)]
+    #[case("x:p", "//x:p", true)]
+    #[case("section/p", "//p[1]", true)]
+    #[case("doc/p", "//p[1]", false)]
+    #[case("doc//p", "//p[1]", true)]
+    #[case("/doc/section/p", "//p[1]", true)]
+    #[case("//p", "//p[1]", true)]
+    #[case("/", "/", true)]
+    #[case("/", "/doc", false)]
+    #[case("/doc", "/doc", true)]
+    #[case("section/title | @id", "//title[1]", true)]
+    #[case("section/title | @id", "//section[1]/@id", true)]
+    #[case("section/title | @id", "//p[1]/@n", false)]
+    #[case("@*", "//section[1]", false)]
+    #[case("*", "//section[1]/@id", false)]
+    #[case("node()", "//section[1]/@id", false)]
+    #[case("p[2]", "//p[2]", true)]
+    #[case("p[2]", "(//p)[3]", false)]
+    #[case("p[last()]", "(//p)[3]", true)]
+    #[case("section[@id = 's2']/p", "(//p)[3]", true)]
+    #[case("section[@id = 's2']/p", "//p[1]", false)]
+    #[case("text()", "//title[1]/text()", true)]
+    #[case("comment()", "/doc/comment()", true)]
+    #[case("processing-instruction('target')", "//processing-instruction()", true)]
+    #[case("id('second')/title", "(//title)[2]", true)]
+    #[case("id('second')//b", "//b", false)]
+    #[case("id('second')", "//section[2]", true)]
+    fn test_pattern(#[case] pattern: &str, #[case] xpath: &str, #[case] expected: bool) {
+        let mut xot = Xot::new();
+        let root = xot.parse(DOC).unwrap();
+        let namespaces = [("x", "urn:x")];
+        let node = XPath::compile(xpath, &namespaces)
+            .unwrap()
+            .evaluate(&xot, root)
+            .unwrap()
+            .nodes()
+            .unwrap()[0];
+        let pattern = Pattern::compile(pattern, &namespaces).unwrap();
+        assert_eq!(pattern.matches(&xot, node).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("..")]
+    #[case("ancestor::p")]
+    #[case("p/descendant::b")]
+    #[case("count(p)")]
+    #[case("id(@ref)")]
+    #[case("p[$n]")]
+    #[case("p |")]
+    fn test_invalid_pattern(#[case] pattern: &str) {
+        assert!(matches!(
+            Pattern::parse(pattern),
+            Err(Error::InvalidXPath(_))
+        ));
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("//")]
+    #[case("p[")]
+    #[case("p]")]
+    #[case("'abc")]
+    #[case("foo()")]
+    #[case("count()")]
+    #[case("y:p")]
+    #[case("namespace::*")]
+    #[case("wrong::p")]
+    #[case("p q")]
+    #[case("1 + ")]
+    #[case("#")]
+    fn test_invalid_xpath(#[case] xpath: &str) {
+        assert!(matches!(XPath::parse(xpath), Err(Error::InvalidXPath(_))));
+    }
+
+    #[rstest]
+    #[case("'a'/b")]
+    #[case("count('a')")]
+    #[case("1 | //p")]
+    #[case("$unbound")]
+    fn test_type_error(#[case] xpath: &str) {
+        let mut xot = Xot::new();
+        let root = xot.parse(DOC).unwrap();
+        assert!(matches!(
+            xot.evaluate(root, xpath),
+            Err(Error::InvalidXPath(_))
+        ));
+    }
+}
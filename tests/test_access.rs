@@ -276,3 +276,94 @@ fn test_document_element_for_fragment_without_elements() {
         Err(xot::Error::NoElementAtTopLevel)
     ));
 }
+
+#[test]
+fn test_descendants_named_with_namespace() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse(r#"<doc xmlns:x="urn:x"><item/><x:item>text</x:item><a><x:item/></a></doc>"#)
+        .unwrap();
+    let doc_el = xot.document_element(doc).unwrap();
+    let x = xot.namespace("urn:x").unwrap();
+    let x_item = xot.name_ns("item", x).unwrap();
+    let item = xot.name("item").unwrap();
+
+    let x_items = xot.descendants_named(doc_el, x_item).collect::<Vec<_>>();
+    assert_eq!(x_items.len(), 2);
+    assert_eq!(xot.text_content_str(x_items[0]), Some("text"));
+    assert_eq!(xot.children_named(doc_el, x_item).count(), 1);
+    assert_eq!(xot.descendants_named(doc_el, item).count(), 1);
+    // a text node doesn't have a name
+    let text = xot.first_child(x_items[0]).unwrap();
+    assert_eq!(xot.descendants_named(x_items[0], x_item).count(), 0);
+    assert_eq!(xot.children_named(text, item).count(), 0);
+}
+
+#[test]
+fn test_compare_document_order() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse(r#"<a xmlns:x="urn:x" x:y="Y"><b z="Z">text<c/></b><!--d--><e/></a>"#)
+        .unwrap();
+    // all_descendants is in document order, including namespace and
+    // attribute nodes
+    let nodes = xot.all_descendants(doc).collect::<Vec<_>>();
+    for (i, a) in nodes.iter().enumerate() {
+        for (j, b) in nodes.iter().enumerate() {
+            assert_eq!(xot.compare_document_order(*a, *b).unwrap(), i.cmp(&j));
+        }
+    }
+}
+
+#[test]
+fn test_compare_document_order_different_trees() {
+    let mut xot = Xot::new();
+    let doc1 = xot.parse("<a/>").unwrap();
+    let doc2 = xot.parse("<a/>").unwrap();
+    let a1 = xot.document_element(doc1).unwrap();
+    let a2 = xot.document_element(doc2).unwrap();
+    assert!(matches!(
+        xot.compare_document_order(a1, a2),
+        Err(xot::Error::DifferentTrees(_, _))
+    ));
+}
+
+#[test]
+fn test_common_ancestor_attribute() {
+    let mut xot = Xot::new();
+    let doc = xot.parse(r#"<a><b x="X"/><c y="Y"/></a>"#).unwrap();
+    let a = xot.document_element(doc).unwrap();
+    let b = xot.first_child(a).unwrap();
+    let c = xot.next_sibling(b).unwrap();
+    let x = xot.attribute_nodes(b).next().unwrap();
+    let y = xot.attribute_nodes(c).next().unwrap();
+    assert_eq!(xot.common_ancestor(x, y), Some(a));
+    assert_eq!(xot.common_ancestor(x, b), Some(b));
+    assert!(xot.is_ancestor(b, x));
+    assert!(!xot.is_ancestor(x, b));
+}
+
+#[test]
+fn test_string_value_normalize_space() {
+    let mut xot = Xot::new();
+    let root = xot
+        .parse("<!-- a  comment --><doc a=\" x\ty \"><?pi  some\n data ?><![CDATA[ c  d ]]>\u{a0}e</doc>")
+        .unwrap();
+    let options = xot::StringValueOptions {
+        normalize_space: true,
+    };
+    let comment = xot.first_child(root).unwrap();
+    let doc = xot.document_element(root).unwrap();
+    let attribute = xot.attribute_nodes(doc).next().unwrap();
+    let pi = xot.first_child(doc).unwrap();
+
+    assert_eq!(
+        xot.string_value_with_options(comment, &options),
+        "a comment"
+    );
+    assert_eq!(xot.string_value_with_options(attribute, &options), "x y");
+    assert_eq!(xot.string_value_with_options(pi, &options), "some data");
+    // a non-breaking space isn't whitespace here
+    assert_eq!(xot.string_value_with_options(doc, &options), "c d \u{a0}e");
+    assert_eq!(xot.string_value_with_options(root, &options), "c d \u{a0}e");
+}
@@ -0,0 +1,235 @@
+use xot::output::c14n::{Method, Parameters};
+use xot::{ParseOptions, Xot};
+
+fn exclusive(inclusive_prefixes: &[&str]) -> Parameters {
+    Parameters {
+        method: Method::Exclusive {
+            inclusive_prefixes: inclusive_prefixes.iter().map(|p| p.to_string()).collect(),
+        },
+        ..Default::default()
+    }
+}
+
+// https://www.w3.org/TR/xml-c14n#Example-OutsideDoc
+#[test]
+fn test_c14n_outside_document_element() {
+    let xml = r#"<?xml version="1.0"?>
+
+<?xml-stylesheet   href="doc.xsl"
+   type="text/xsl"   ?>
+
+<!DOCTYPE doc SYSTEM "doc.dtd">
+
+<doc>Hello, world!<!-- Comment 1 --></doc>
+
+<?pi-without-data     ?>
+
+<!-- Comment 2 -->
+
+<!-- Comment 3 -->"#;
+    let mut xot = Xot::new();
+    let root = xot.parse(xml).unwrap();
+    assert_eq!(
+        xot.serialize_c14n_string(Parameters::default(), root)
+            .unwrap(),
+        r#"<?xml-stylesheet href="doc.xsl"
+   type="text/xsl"   ?>
+<doc>Hello, world!</doc>
+<?pi-without-data?>"#
+    );
+    assert_eq!(
+        xot.serialize_c14n_string(
+            Parameters {
+                with_comments: true,
+                ..Default::default()
+            },
+            root
+        )
+        .unwrap(),
+        r#"<?xml-stylesheet href="doc.xsl"
+   type="text/xsl"   ?>
+<doc>Hello, world!<!-- Comment 1 --></doc>
+<?pi-without-data?>
+<!-- Comment 2 -->
+<!-- Comment 3 -->"#
+    );
+}
+
+// https://www.w3.org/TR/xml-c14n#Example-SETags
+#[test]
+fn test_c14n_start_and_end_tags() {
+    let xml = r#"<!DOCTYPE doc [<!ATTLIST e9 attr CDATA "default">]>
+<doc>
+   <e1   />
+   <e2   ></e2>
+   <e3   name = "elem3"   id="elem3"   />
+   <e4   name="elem4"   id="elem4"   ></e4>
+   <e5 a:attr="out" b:attr="sorted" attr2="all" attr="I'm"
+      xmlns:b="http://www.ietf.org"
+      xmlns:a="http://www.w3.org"
+      xmlns="http://example.org"/>
+   <e6 xmlns="" xmlns:a="http://www.w3.org">
+      <e7 xmlns="http://www.ietf.org">
+         <e8 xmlns="" xmlns:a="http://www.w3.org">
+            <e9 xmlns="" xmlns:a="http://www.ietf.org"/>
+         </e8>
+      </e7>
+   </e6>
+</doc>"#;
+    let mut xot = Xot::new();
+    let options = ParseOptions {
+        apply_attribute_defaults: true,
+        ..Default::default()
+    };
+    let root = xot.parse_with_options(xml, &options).unwrap();
+    assert_eq!(
+        xot.serialize_c14n_string(Parameters::default(), root)
+            .unwrap(),
+        r#"<doc>
+   <e1></e1>
+   <e2></e2>
+   <e3 id="elem3" name="elem3"></e3>
+   <e4 id="elem4" name="elem4"></e4>
+   <e5 xmlns="http://example.org" xmlns:a="http://www.w3.org" xmlns:b="http://www.ietf.org" attr="I'm" attr2="all" b:attr="sorted" a:attr="out"></e5>
+   <e6 xmlns:a="http://www.w3.org">
+      <e7 xmlns="http://www.ietf.org">
+         <e8 xmlns="">
+            <e9 xmlns:a="http://www.ietf.org" attr="default"></e9>
+         </e8>
+      </e7>
+   </e6>
+</doc>"#
+    );
+}
+
+#[test]
+fn test_c14n_escaping() {
+    let mut xot = Xot::new();
+    let root = xot
+        .parse(
+            "<doc a=\"&lt;&amp;&quot;'>&#9;&#10;&#13;\">&lt;&amp;&gt;\"'&#13;<![CDATA[<]]></doc>",
+        )
+        .unwrap();
+    assert_eq!(
+        xot.serialize_c14n_string(Parameters::default(), root)
+            .unwrap(),
+        "<doc a=\"&lt;&amp;&quot;'>&#x9;&#xA;&#xD;\">&lt;&amp;&gt;\"'&#xD;&lt;</doc>"
+    );
+}
+
+// https://www.w3.org/TR/xml-exc-c14n/#sec-Enveloping
+#[test]
+fn test_c14n_subtree() {
+    let xml = r#"<n0:local xmlns:n0="foo:bar" xmlns:n3="ftp://example.org" xml:lang="en">
+  <n1:elem2 xmlns:n1="http://example.net">
+    <n3:stuff xmlns:n3="ftp://example.org"/>
+  </n1:elem2>
+</n0:local>"#;
+    let mut xot = Xot::new();
+    let root = xot.parse(xml).unwrap();
+    let local = xot.document_element(root).unwrap();
+    let elem2 = xot
+        .first_child(local)
+        .and_then(|n| xot.next_sibling(n))
+        .unwrap();
+    // the subtree gets the namespaces and xml attributes of its context
+    assert_eq!(
+        xot.serialize_c14n_string(Parameters::default(), elem2)
+            .unwrap(),
+        r#"<n1:elem2 xmlns:n0="foo:bar" xmlns:n1="http://example.net" xmlns:n3="ftp://example.org" xml:lang="en">
+    <n3:stuff></n3:stuff>
+  </n1:elem2>"#
+    );
+    // but not with exclusive canonicalization
+    assert_eq!(
+        xot.serialize_c14n_string(exclusive(&[]), elem2).unwrap(),
+        r#"<n1:elem2 xmlns:n1="http://example.net">
+    <n3:stuff xmlns:n3="ftp://example.org"></n3:stuff>
+  </n1:elem2>"#
+    );
+    assert_eq!(
+        xot.serialize_c14n_string(exclusive(&["n0", "n2"]), elem2)
+            .unwrap(),
+        r#"<n1:elem2 xmlns:n0="foo:bar" xmlns:n1="http://example.net">
+    <n3:stuff xmlns:n3="ftp://example.org"></n3:stuff>
+  </n1:elem2>"#
+    );
+}
+
+#[test]
+fn test_exc_c14n_default_namespace() {
+    let xml = r#"<a xmlns="urn:a"><b xmlns:x="urn:x" x:y="1"><x:c><d/></x:c><e xmlns=""/></b></a>"#;
+    let mut xot = Xot::new();
+    let root = xot.parse(xml).unwrap();
+    let a = xot.document_element(root).unwrap();
+    let b = xot.first_child(a).unwrap();
+    assert_eq!(
+        xot.serialize_c14n_string(exclusive(&[]), b).unwrap(),
+        r#"<b xmlns="urn:a" xmlns:x="urn:x" x:y="1"><x:c><d></d></x:c><e xmlns=""></e></b>"#
+    );
+    let c = xot.first_child(b).unwrap();
+    assert_eq!(
+        xot.serialize_c14n_string(exclusive(&[]), c).unwrap(),
+        r#"<x:c xmlns:x="urn:x"><d xmlns="urn:a"></d></x:c>"#
+    );
+    assert_eq!(
+        xot.serialize_c14n_string(exclusive(&["#default"]), c)
+            .unwrap(),
+        r#"<x:c xmlns="urn:a" xmlns:x="urn:x"><d></d></x:c>"#
+    );
+}
+
+#[test]
+fn test_c14n_11_xml_attributes() {
+    let xml = r#"<l:doc xmlns:l="urn:l" xml:base="http://example.com/a/" xml:space="preserve"><p xml:base="b/" xml:lang="en" xml:id="p"><q xml:base="../c/"><r/></q><s/></p></l:doc>"#;
+    let mut xot = Xot::new();
+    let root = xot.parse(xml).unwrap();
+    let p = xot
+        .first_child(xot.document_element(root).unwrap())
+        .unwrap();
+    let q = xot.first_child(p).unwrap();
+    let s = xot.next_sibling(q).unwrap();
+    let c14n11 = Parameters {
+        method: Method::Inclusive11,
+        ..Default::default()
+    };
+    // 1.0 copies all xml attributes, 1.1 only xml:lang and xml:space, and
+    // it combines xml:base
+    assert_eq!(
+        xot.serialize_c14n_string(Parameters::default(), q).unwrap(),
+        r#"<q xmlns:l="urn:l" xml:base="../c/" xml:id="p" xml:lang="en" xml:space="preserve"><r></r></q>"#
+    );
+    assert_eq!(
+        xot.serialize_c14n_string(c14n11.clone(), q).unwrap(),
+        r#"<q xmlns:l="urn:l" xml:base="http://example.com/a/c/" xml:lang="en" xml:space="preserve"><r></r></q>"#
+    );
+    assert_eq!(
+        xot.serialize_c14n_string(c14n11.clone(), s).unwrap(),
+        r#"<s xmlns:l="urn:l" xml:base="http://example.com/a/b/" xml:lang="en" xml:space="preserve"></s>"#
+    );
+    // nested xml:base attributes in the subtree aren't changed
+    assert_eq!(
+        xot.serialize_c14n_string(c14n11, root).unwrap(),
+        xot.serialize_c14n_string(Parameters::default(), root)
+            .unwrap()
+    );
+
+    let root = xot
+        .parse(r#"<doc xml:base="a/"><p xml:base="../../b"><q/></p></doc>"#)
+        .unwrap();
+    let p = xot
+        .first_child(xot.document_element(root).unwrap())
+        .unwrap();
+    let q = xot.first_child(p).unwrap();
+    assert_eq!(
+        xot.serialize_c14n_string(
+            Parameters {
+                method: Method::Inclusive11,
+                ..Default::default()
+            },
+            q
+        )
+        .unwrap(),
+        r#"<q xml:base="../b"></q>"#
+    );
+}
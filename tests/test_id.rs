@@ -93,3 +93,111 @@ fn xml_id_node_none_available() {
 
     assert_eq!(xot.xml_id_node(root, "FOO"), None);
 }
+
+#[test]
+fn test_element_by_id_doctype() {
+    let mut xot = Xot::new();
+    let root = xot
+        .parse(
+            r#"<!DOCTYPE doc [<!ATTLIST sec name ID #IMPLIED>]>
+<doc><sec name=" intro "/><p name="other"/><sec name="intro"/></doc>"#,
+        )
+        .unwrap();
+    xot.create_id_index(root).unwrap();
+    let doc = xot.document_element(root).unwrap();
+    let sec = xot.first_child(doc).unwrap();
+    // declared ID values are normalized
+    assert_eq!(xot.element_by_id(root, "intro").unwrap(), Some(sec));
+    // the attribute is only an ID on the element it's declared for
+    assert_eq!(xot.element_by_id(root, "other").unwrap(), None);
+    assert_eq!(xot.duplicate_ids(root).unwrap(), vec!["intro".to_string()]);
+
+    // without the doctype there are no IDs
+    xot.set_doctype(root, None).unwrap();
+    assert_eq!(xot.element_by_id(root, "intro").unwrap(), None);
+    assert!(xot.duplicate_ids(root).unwrap().is_empty());
+}
+
+#[test]
+fn test_element_by_id_after_removal() {
+    let mut xot = Xot::new();
+    let root = xot
+        .parse(r#"<doc><a xml:id="a"><b xml:id="b"/></a><c xml:id="c"/></doc>"#)
+        .unwrap();
+    xot.create_id_index(root).unwrap();
+    let doc = xot.document_element(root).unwrap();
+    let a = xot.first_child(doc).unwrap();
+    let c = xot.next_sibling(a).unwrap();
+    assert!(xot.element_by_id(root, "b").unwrap().is_some());
+    xot.remove(a).unwrap();
+    assert_eq!(xot.element_by_id(root, "a").unwrap(), None);
+    assert_eq!(xot.element_by_id(root, "b").unwrap(), None);
+    assert_eq!(xot.element_by_id(root, "c").unwrap(), Some(c));
+
+    // a change through a mutable value is seen too
+    let xml_id = xot.xml_id_name();
+    let id_node = xot.attributes(c).get_node(xml_id).unwrap();
+    if let xot::Value::Attribute(attribute) = xot.value_mut(id_node) {
+        attribute.set_value("d");
+    }
+    assert_eq!(xot.element_by_id(root, "c").unwrap(), None);
+    assert_eq!(xot.element_by_id(root, "d").unwrap(), Some(c));
+}
+
+#[test]
+fn test_element_by_id_attribute_changes() {
+    let mut xot = Xot::new();
+    let root = xot.parse(r#"<doc><a xml:id="x"/><b/><c/></doc>"#).unwrap();
+    xot.create_id_index(root).unwrap();
+    let doc = xot.document_element(root).unwrap();
+    let a = xot.first_child(doc).unwrap();
+    let b = xot.next_sibling(a).unwrap();
+    let c = xot.next_sibling(b).unwrap();
+    let xml_id = xot.xml_id_name();
+    xot.set_attribute(c, xml_id, "x");
+    assert_eq!(xot.element_by_id(root, "x").unwrap(), Some(a));
+    assert_eq!(xot.duplicate_ids(root).unwrap(), vec!["x".to_string()]);
+
+    // the element that comes first in document order has the ID
+    xot.set_attribute(b, xml_id, "x");
+    xot.remove_attribute(a, xml_id);
+    assert_eq!(xot.element_by_id(root, "x").unwrap(), Some(b));
+    xot.remove_attribute(b, xml_id);
+    assert_eq!(xot.element_by_id(root, "x").unwrap(), Some(c));
+    assert!(xot.duplicate_ids(root).unwrap().is_empty());
+
+    // an element moved into the document brings its IDs along
+    let d_name = xot.add_name("d");
+    let d = xot.new_element(d_name);
+    xot.set_attribute(d, xml_id, "x");
+    xot.prepend(doc, d).unwrap();
+    assert_eq!(xot.element_by_id(root, "x").unwrap(), Some(d));
+    xot.detach(d).unwrap();
+    assert_eq!(xot.element_by_id(root, "x").unwrap(), Some(c));
+
+    // the doctype declares which attributes are IDs
+    let name = xot.add_name("name");
+    xot.set_attribute(b, name, "y");
+    assert_eq!(xot.element_by_id(root, "y").unwrap(), None);
+    let declared = xot
+        .parse(r#"<!DOCTYPE doc [<!ATTLIST b name ID #IMPLIED>]><doc/>"#)
+        .unwrap();
+    let doctype = xot.doctype(declared).cloned();
+    xot.set_doctype(root, doctype).unwrap();
+    assert_eq!(xot.element_by_id(root, "y").unwrap(), Some(b));
+    // and for which elements
+    let a_name = xot.add_name("a");
+    xot.set_element_name(b, a_name);
+    assert_eq!(xot.element_by_id(root, "y").unwrap(), None);
+}
+
+#[test]
+fn test_element_by_id_not_document() {
+    let mut xot = Xot::new();
+    let root = xot.parse(r#"<doc xml:id="a"/>"#).unwrap();
+    let doc = xot.document_element(root).unwrap();
+    assert!(matches!(
+        xot.element_by_id(doc, "a"),
+        Err(xot::Error::NotDocument(_))
+    ));
+}
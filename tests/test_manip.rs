@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use xot::{Error, Value, Xot};
 
 #[test]
@@ -563,6 +565,39 @@ fn test_clone_with_namespaces() {
     );
 }
 
+#[test]
+fn test_create_missing_prefixes_with_preferred() {
+    let mut xot = Xot::new();
+    let root = xot
+        .parse(r#"<doc xmlns="http://example.com" xmlns:x="urn:x"><a x:b="B"><c/></a></doc>"#)
+        .unwrap();
+    let doc_id = xot.document_element(root).unwrap();
+    let a_id = xot.first_child(doc_id).unwrap();
+    let ns = xot.namespace("http://example.com").unwrap();
+    let x = xot.namespace("urn:x").unwrap();
+    let preferred = HashMap::from([(ns, "".to_string()), (x, "ex".to_string())]);
+
+    let a_clone = xot.clone_node(a_id);
+    xot.create_missing_prefixes_with_preferred(a_clone, &preferred)
+        .unwrap();
+    assert_eq!(
+        xot.to_string(a_clone).unwrap(),
+        r#"<a xmlns="http://example.com" xmlns:ex="urn:x" ex:b="B"><c/></a>"#
+    );
+
+    // the default namespace would apply to an element without a namespace
+    let c_clone = xot.clone_node(xot.first_child(a_id).unwrap());
+    let c = xot.add_name("c");
+    let c_id = xot.new_element(c);
+    xot.append(c_clone, c_id).unwrap();
+    xot.create_missing_prefixes_with_preferred(c_clone, &preferred)
+        .unwrap();
+    assert_eq!(
+        xot.to_string(c_clone).unwrap(),
+        r#"<n0:c xmlns:n0="http://example.com"><c/></n0:c>"#
+    );
+}
+
 #[test]
 fn test_clone_with_prefixes() {
     let mut xot = Xot::new();
@@ -1010,3 +1045,110 @@ fn test_prepend_text_with_attributes() -> Result<(), Error> {
     assert_eq!(xot.children(document_element).count(), 1);
     Ok(())
 }
+
+#[test]
+fn test_append_prolog() -> Result<(), Error> {
+    let mut xot = Xot::new();
+    let doc = xot.parse(r#"<!--first--><doc><a/></doc><!--after-->"#)?;
+    let target = xot.add_name("target");
+    xot.append_prolog_processing_instruction(doc, target, Some("data"))?;
+    xot.append_prolog_comment(doc, "last")?;
+    assert_eq!(
+        xot.to_string(doc)?,
+        r#"<!--first--><?target data?><!--last--><doc><a/></doc><!--after-->"#
+    );
+    let prolog = xot.prolog(doc)?.collect::<Vec<_>>();
+    assert_eq!(prolog.len(), 3);
+    assert_eq!(xot.comment_str(prolog[0]), Some("first"));
+    assert_eq!(xot.comment_str(prolog[2]), Some("last"));
+
+    // an attached node is moved to the end of the prolog
+    xot.append_prolog(doc, prolog[0])?;
+    assert_eq!(
+        xot.to_string(doc)?,
+        r#"<?target data?><!--last--><!--first--><doc><a/></doc><!--after-->"#
+    );
+
+    let document_element = xot.document_element(doc)?;
+    let a = xot.first_child(document_element).unwrap();
+    assert!(matches!(
+        xot.append_prolog(doc, a),
+        Err(Error::InvalidOperation(_))
+    ));
+    assert!(matches!(
+        xot.prolog(document_element).map(|prolog| prolog.count()),
+        Err(Error::NotDocument(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_elements_named_follows_changes() -> Result<(), Error> {
+    let mut xot = Xot::new();
+    let root = xot.parse(r#"<log><entry/><group><entry>a</entry><b/></group><entry/></log>"#)?;
+    let other = xot.parse(r#"<other><entry/></other>"#)?;
+    let entry = xot.name("entry").unwrap();
+    let group = xot.name("group").unwrap();
+    let b = xot.name("b").unwrap();
+
+    fn check(xot: &mut Xot, root: xot::Node, name: xot::NameId) -> Result<(), Error> {
+        let scanned = xot
+            .descendants(root)
+            .filter(|node| xot.node_name(*node) == Some(name) && xot.is_element(*node))
+            .collect::<Vec<_>>();
+        assert_eq!(xot.elements_named(root, name)?, scanned.as_slice());
+        Ok(())
+    }
+
+    assert_eq!(xot.elements_named(root, entry)?.len(), 3);
+    assert_eq!(xot.elements_named(other, entry)?.len(), 1);
+    let doc = xot.document_element(root)?;
+    let group_node = xot.elements_named(root, group)?[0];
+
+    // insert new elements in the middle of the document
+    let new_entry = xot.new_element(entry);
+    xot.prepend(group_node, new_entry)?;
+    let new_group = xot.new_element(group);
+    xot.append_element(new_group, entry)?;
+    xot.insert_after(new_entry, new_group)?;
+    // text and attributes don't change elements
+    xot.append_text(new_entry, "text")?;
+    xot.set_attribute(new_entry, b, "value");
+    for name in [entry, group, b] {
+        check(&mut xot, root, name)?;
+    }
+
+    // move an element from another document
+    let other_entry = xot.elements_named(other, entry)?[0];
+    xot.insert_before(group_node, other_entry)?;
+    check(&mut xot, root, entry)?;
+    check(&mut xot, other, entry)?;
+
+    // rename, wrap, unwrap, replace and remove
+    xot.set_element_name(new_entry, b);
+    let wrapper = xot.element_wrap(group_node, group)?;
+    xot.element_unwrap(new_group)?;
+    let replacing = xot.new_element(b);
+    let first = xot.first_child(doc).unwrap();
+    xot.replace(first, replacing)?;
+    let last = xot.last_child(doc).unwrap();
+    xot.remove(last)?;
+    for name in [entry, group, b] {
+        check(&mut xot, root, name)?;
+    }
+
+    // detach, and changes the index can't follow
+    xot.detach(wrapper)?;
+    if let Value::Element(element) = xot.value_mut(replacing) {
+        element.set_name(entry);
+    }
+    for name in [entry, group, b] {
+        check(&mut xot, root, name)?;
+    }
+
+    assert!(matches!(
+        xot.elements_named(doc, entry),
+        Err(Error::NotDocument(_))
+    ));
+    Ok(())
+}
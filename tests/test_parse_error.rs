@@ -78,30 +78,12 @@ fn test_duplicate_attribute_with_prefix() {
 }
 
 #[test]
-fn test_invalid_dtd() {
-    let xml = r#"<!DOCTYPE note><note></note>"#;
+fn test_dtd_invalid_character_reference() {
+    let xml = r#"<!DOCTYPE note [<!ENTITY nul "a&#0;">]><note></note>"#;
     let mut xot = Xot::new();
     let err = xot.parse(xml).unwrap_err();
-    assert!(matches!(err, xot::ParseError::DtdUnsupported { .. }));
-    assert_eq!(err.span(), (0..15).into());
-}
-
-#[test]
-fn test_dtd_unsupported() {
-    let xml = r#"<!DOCTYPE note SYSTEM "Note.dtd"><note></note>"#;
-    let mut xot = Xot::new();
-    let err = xot.parse(xml).unwrap_err();
-    assert!(matches!(err, xot::ParseError::DtdUnsupported { .. }));
-    assert_eq!(err.span(), (0..33).into());
-}
-
-#[test]
-fn test_dtd_unsupported2() {
-    let xml = r#"<?xml version="1.0"?><!DOCTYPE note SYSTEM "Note.dtd"><note></note>"#;
-    let mut xot = Xot::new();
-    let err = xot.parse(xml).unwrap_err();
-    assert!(matches!(err, xot::ParseError::DtdUnsupported { .. }));
-    assert_eq!(err.span(), (21..54).into());
+    assert!(matches!(err, xot::ParseError::InvalidEntity { .. }));
+    assert_eq!(err.span(), (31..35).into());
 }
 
 #[test]
@@ -1,4 +1,8 @@
-use xot::{ParseError, Span, SpanInfoKey, Xot};
+use xot::output;
+use xot::{
+    AttributeDeclaration, AttributeDefault, EntityDeclaration, EntityDefinition,
+    NotationDeclaration, ParseError, ParseOptions, Span, SpanInfoKey, Xot,
+};
 
 const US_ASCII: &str = include_str!("fixtures/us-ascii.xml");
 
@@ -226,6 +230,44 @@ fn test_parse_with_span_info_attribute_value_prefixed() {
     );
 }
 
+#[test]
+fn test_parse_with_span_info_namespace_declaration() {
+    let mut xot = Xot::new();
+    let (doc, span_info) = xot
+        .parse_with_span_info(
+            r#"<a xmlns="http://example.com" xmlns:foo='http://example.com/foo'/>"#,
+        )
+        .unwrap();
+    let doc_el = xot.document_element(doc).unwrap();
+    let empty = xot.empty_prefix();
+    let foo = xot.prefix("foo").unwrap();
+
+    assert_eq!(
+        span_info
+            .get(SpanInfoKey::NamespacePrefix(doc_el, empty))
+            .unwrap(),
+        &Span::new(3, 8)
+    );
+    assert_eq!(
+        span_info
+            .get(SpanInfoKey::NamespaceUri(doc_el, empty))
+            .unwrap(),
+        &Span::new(10, 28)
+    );
+    assert_eq!(
+        span_info
+            .get(SpanInfoKey::NamespacePrefix(doc_el, foo))
+            .unwrap(),
+        &Span::new(30, 39)
+    );
+    assert_eq!(
+        span_info
+            .get(SpanInfoKey::NamespaceUri(doc_el, foo))
+            .unwrap(),
+        &Span::new(41, 63)
+    );
+}
+
 #[test]
 fn test_parse_with_span_info_end_normal() {
     let mut xot = Xot::new();
@@ -477,3 +519,1044 @@ fn test_parse_fragment_with_span_info() {
         &Span::new(4, 5)
     );
 }
+
+// a reader that returns its data in small pieces
+struct ChunkedReader<'a> {
+    data: &'a [u8],
+}
+
+impl std::io::Read for ChunkedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.data.len().min(buf.len()).min(3);
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn test_parse_read() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse_read(ChunkedReader {
+            data: US_ASCII.as_bytes(),
+        })
+        .unwrap();
+    let expected = xot.parse(US_ASCII).unwrap();
+    assert_eq!(
+        xot.to_string(doc).unwrap(),
+        xot.to_string(expected).unwrap()
+    );
+}
+
+#[test]
+fn test_parse_read_error() {
+    let mut xot = Xot::new();
+    let err = xot
+        .parse_read(ChunkedReader {
+            data: b"<a><b></a>",
+        })
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        xot::Error::Parse(ParseError::InvalidCloseTag(_, _, _))
+    ));
+}
+
+#[test]
+fn test_parse_file() {
+    let mut xot = Xot::new();
+    let doc = xot.parse_file("tests/fixtures/us-ascii.xml").unwrap();
+    let expected = xot.parse(US_ASCII).unwrap();
+    assert_eq!(
+        xot.to_string(doc).unwrap(),
+        xot.to_string(expected).unwrap()
+    );
+    assert!(matches!(
+        xot.parse_file("tests/fixtures/missing.xml"),
+        Err(xot::Error::Io(_))
+    ));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn test_parse_async() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut xot = Xot::new();
+    let doc = runtime
+        .block_on(xot.parse_async(US_ASCII.as_bytes()))
+        .unwrap();
+    let expected = xot.parse(US_ASCII).unwrap();
+    assert_eq!(
+        xot.to_string(doc).unwrap(),
+        xot.to_string(expected).unwrap()
+    );
+    let err = runtime
+        .block_on(xot.parse_async(&b"<a><b></a>"[..]))
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        xot::Error::Parse(ParseError::InvalidCloseTag(_, _, _))
+    ));
+}
+
+#[test]
+fn test_parse_bytes_shift_jis() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse_bytes(b"<?xml version=\"1.0\" encoding=\"Shift_JIS\"?><p>\x82\xa0</p>")
+        .unwrap();
+    let p = xot.document_element(doc).unwrap();
+    assert_eq!(xot.text_content_str(p), Some("\u{3042}"));
+    assert_eq!(xot.encoding(doc), Some("Shift_JIS"));
+}
+
+#[test]
+fn test_parse_bytes_utf16() {
+    let xml = "<?xml version=\"1.0\" encoding=\"UTF-16\"?><p>\u{e9}</p>";
+    let utf16le = xml
+        .encode_utf16()
+        .flat_map(|c| c.to_le_bytes())
+        .collect::<Vec<_>>();
+    let utf16be = xml
+        .encode_utf16()
+        .flat_map(|c| c.to_be_bytes())
+        .collect::<Vec<_>>();
+    let mut xot = Xot::new();
+    // without byte order mark
+    let doc = xot.parse_bytes(&utf16le).unwrap();
+    assert_eq!(xot.encoding(doc), Some("UTF-16LE"));
+    assert_eq!(xot.to_string(doc).unwrap(), "<p>\u{e9}</p>");
+    let doc = xot.parse_bytes(&utf16be).unwrap();
+    assert_eq!(xot.encoding(doc), Some("UTF-16BE"));
+    assert_eq!(xot.to_string(doc).unwrap(), "<p>\u{e9}</p>");
+    // with byte order mark
+    let mut with_bom = vec![0xff, 0xfe];
+    with_bom.extend(&utf16le);
+    let doc = xot.parse_bytes(&with_bom).unwrap();
+    assert_eq!(xot.to_string(doc).unwrap(), "<p>\u{e9}</p>");
+    let doc = xot.parse_read(&with_bom[..]).unwrap();
+    assert_eq!(xot.encoding(doc), Some("UTF-16LE"));
+    assert_eq!(xot.to_string(doc).unwrap(), "<p>\u{e9}</p>");
+}
+
+#[test]
+fn test_parse_bytes_unsupported_encoding() {
+    let mut xot = Xot::new();
+    let err = xot
+        .parse_bytes(b"<?xml version=\"1.0\" encoding=\"bogus\"?><p/>")
+        .unwrap_err();
+    assert!(matches!(err, ParseError::UnsupportedEncoding(name) if name == "bogus"));
+    let err = xot
+        .parse_read(&b"<?xml version=\"1.0\" encoding=\"bogus\"?><p/>"[..])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        xot::Error::Parse(ParseError::UnsupportedEncoding(_))
+    ));
+}
+
+#[test]
+fn test_parse_xml_1_1() {
+    let xml = "<?xml version=\"1.1\"?><p a=\"&#x2;\">&#x1;\u{85}</p>";
+    let mut xot = Xot::new();
+    let err = xot.parse(xml).unwrap_err();
+    assert!(matches!(err, ParseError::UnsupportedVersion(version, _) if version == "1.1"));
+    let options = xot::ParseOptions {
+        xml_1_1: true,
+        ..Default::default()
+    };
+    let doc = xot.parse_with_options(xml, &options).unwrap();
+    let p = xot.document_element(doc).unwrap();
+    assert_eq!(xot.text_content_str(p), Some("\u{1}\n"));
+    let a = xot.name("a").unwrap();
+    assert_eq!(xot.get_attribute(p, a), Some("\u{2}"));
+    // a document without declaration is still XML 1.0
+    let err = xot
+        .parse_with_options("<p>&#x1;</p>", &options)
+        .unwrap_err();
+    assert!(matches!(err, ParseError::InvalidEntity(..)));
+}
+
+#[test]
+fn test_parse_doctype_internal_subset() {
+    let xml = r#"<?xml version="1.0"?>
+<!DOCTYPE doc PUBLIC "-//Example//DTD Doc//EN" "doc.dtd" [
+  <!ELEMENT doc (#PCDATA)>
+  <!ATTLIST doc src ENTITY #IMPLIED>
+  <!-- the notations -->
+  <!NOTATION gif SYSTEM "image/gif">
+  <!NOTATION png PUBLIC 'PNG 1.0' "image/png">
+  <!NOTATION jpeg PUBLIC "JPEG">
+  <?pi content?>
+  <!ENTITY company "ACME &#38; &#x53;ons &more;">
+  <!ENTITY % param "<!ELEMENT x ANY>">
+  <!ENTITY logo SYSTEM "logo.gif" NDATA gif>
+  <!ENTITY chapter PUBLIC "-//Example//Chapter//EN" "chapter.xml">
+]>
+<doc/>"#;
+    let mut xot = Xot::new();
+    let doc = xot.parse(xml).unwrap();
+    // the comment and processing instruction in the DTD aren't in the tree
+    assert_eq!(xot.children(doc).count(), 1);
+    let doctype = xot.doctype(doc).unwrap();
+    assert_eq!(doctype.name, "doc");
+    assert_eq!(
+        doctype.public_id.as_deref(),
+        Some("-//Example//DTD Doc//EN")
+    );
+    assert_eq!(doctype.system_id.as_deref(), Some("doc.dtd"));
+    assert_eq!(
+        doctype.notations,
+        vec![
+            NotationDeclaration {
+                name: "gif".to_string(),
+                public_id: None,
+                system_id: Some("image/gif".to_string()),
+            },
+            NotationDeclaration {
+                name: "png".to_string(),
+                public_id: Some("PNG 1.0".to_string()),
+                system_id: Some("image/png".to_string()),
+            },
+            NotationDeclaration {
+                name: "jpeg".to_string(),
+                public_id: Some("JPEG".to_string()),
+                system_id: None,
+            },
+        ]
+    );
+    assert_eq!(
+        doctype.entities,
+        vec![
+            EntityDeclaration {
+                name: "company".to_string(),
+                parameter: false,
+                definition: EntityDefinition::Internal("ACME & Sons &more;".to_string()),
+            },
+            EntityDeclaration {
+                name: "param".to_string(),
+                parameter: true,
+                definition: EntityDefinition::Internal("<!ELEMENT x ANY>".to_string()),
+            },
+            EntityDeclaration {
+                name: "logo".to_string(),
+                parameter: false,
+                definition: EntityDefinition::External {
+                    public_id: None,
+                    system_id: "logo.gif".to_string(),
+                    notation: Some("gif".to_string()),
+                },
+            },
+            EntityDeclaration {
+                name: "chapter".to_string(),
+                parameter: false,
+                definition: EntityDefinition::External {
+                    public_id: Some("-//Example//Chapter//EN".to_string()),
+                    system_id: "chapter.xml".to_string(),
+                    notation: None,
+                },
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_parse_doctype_without_internal_subset() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse(r#"<!DOCTYPE note SYSTEM "Note.dtd"><note></note>"#)
+        .unwrap();
+    let doctype = xot.doctype(doc).unwrap();
+    assert_eq!(doctype.name, "note");
+    assert_eq!(doctype.public_id, None);
+    assert_eq!(doctype.system_id.as_deref(), Some("Note.dtd"));
+    assert!(doctype.entities.is_empty());
+    assert!(doctype.notations.is_empty());
+    // the doctype isn't serialized
+    assert_eq!(xot.to_string(doc).unwrap(), "<note/>");
+
+    let doc = xot.parse("<note/>").unwrap();
+    assert_eq!(xot.doctype(doc), None);
+}
+
+#[test]
+fn test_parse_attribute_list_declarations() {
+    let xml = r#"<!DOCTYPE doc [
+  <!ENTITY e "&#38;#60;e">
+  <!ATTLIST doc
+    a CDATA " x  &e; "
+    b NMTOKENS " x  y "
+    c (yes | no) #FIXED 'yes'
+    d ID #IMPLIED>
+  <!ATTLIST p:e
+    e NOTATION (gif) #REQUIRED
+    f CDATA "a &amp; b">
+]>
+<doc/>"#;
+    let mut xot = Xot::new();
+    let doc = xot.parse(xml).unwrap();
+    let doctype = xot.doctype(doc).unwrap();
+    let declaration =
+        |element: &str, name: &str, attribute_type: &str, default| AttributeDeclaration {
+            element: element.to_string(),
+            name: name.to_string(),
+            attribute_type: attribute_type.to_string(),
+            default,
+        };
+    assert_eq!(
+        doctype.attributes,
+        vec![
+            declaration(
+                "doc",
+                "a",
+                "CDATA",
+                AttributeDefault::Value(" x  <e ".to_string())
+            ),
+            declaration(
+                "doc",
+                "b",
+                "NMTOKENS",
+                AttributeDefault::Value("x y".to_string())
+            ),
+            declaration(
+                "doc",
+                "c",
+                "(yes | no)",
+                AttributeDefault::Fixed("yes".to_string())
+            ),
+            declaration("doc", "d", "ID", AttributeDefault::Implied),
+            declaration("p:e", "e", "NOTATION (gif)", AttributeDefault::Required),
+            declaration(
+                "p:e",
+                "f",
+                "CDATA",
+                AttributeDefault::Value("a & b".to_string())
+            ),
+        ]
+    );
+    // defaults aren't applied unless asked for
+    assert_eq!(xot.to_string(doc).unwrap(), "<doc/>");
+}
+
+#[test]
+fn test_parse_apply_attribute_defaults() {
+    let xml = r#"<!DOCTYPE doc [
+  <!ATTLIST doc a CDATA "1" b CDATA "2" xmlns:x CDATA "urn:x">
+  <!ATTLIST doc a CDATA "3" c CDATA #IMPLIED>
+  <!ATTLIST x:e x:a CDATA "4">
+]>
+<doc b="5"><x:e xmlns:x="urn:x"/><e/></doc>"#;
+    let mut xot = Xot::new();
+    let options = ParseOptions {
+        apply_attribute_defaults: true,
+        ..Default::default()
+    };
+    let root = xot.parse_with_options(xml, &options).unwrap();
+    assert_eq!(
+        xot.to_string(root).unwrap(),
+        r#"<doc b="5" a="1"><x:e xmlns:x="urn:x" x:a="4"/><e/></doc>"#
+    );
+    let doc = xot.document_element(root).unwrap();
+    let a = xot.add_name("a");
+    let b = xot.add_name("b");
+    assert!(xot.is_defaulted_attribute(doc, a));
+    assert!(!xot.is_defaulted_attribute(doc, b));
+
+    // serialization can leave them out again
+    let parameters = xot::output::xml::Parameters {
+        omit_defaulted_attributes: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        xot.serialize_xml_string(parameters.clone(), root).unwrap(),
+        r#"<doc b="5"><x:e xmlns:x="urn:x"/><e/></doc>"#
+    );
+
+    // once you set it, it's no longer a default
+    xot.attributes_mut(doc).insert(a, "6".to_string());
+    assert!(!xot.is_defaulted_attribute(doc, a));
+    assert_eq!(
+        xot.serialize_xml_string(parameters, root).unwrap(),
+        r#"<doc b="5" a="6"><x:e xmlns:x="urn:x"/><e/></doc>"#
+    );
+}
+
+#[test]
+fn test_push_parse_doctype() {
+    let xml = br#"<?xml version="1.0"?><!-- c --><!DOCTYPE doc [<!NOTATION gif SYSTEM "gif"><!ENTITY e "x">]><doc/>"#;
+    for chunk_size in [1, 5, 1000] {
+        let mut xot = Xot::new();
+        let mut parser = xot::XmlPushParser::new(&mut xot);
+        for chunk in xml.chunks(chunk_size) {
+            parser.feed(chunk).unwrap();
+        }
+        let doc = parser.finish().unwrap();
+        let doctype = xot.doctype(doc).unwrap();
+        assert_eq!(doctype.notations.len(), 1);
+        assert_eq!(doctype.entities.len(), 1);
+        assert_eq!(xot.children(doc).count(), 2);
+    }
+}
+
+#[test]
+fn test_parse_expand_entities() {
+    let xml = r#"<!DOCTYPE doc [
+  <!ENTITY company "ACME Corp">
+  <!ENTITY full "&company; &amp; Sons">
+  <!ENTITY bold "<b title='&company;'>bold</b>">
+  <!ENTITY company "Ignored">
+  <!ENTITY % param "not general">
+]>
+<doc name="&full;">Hello &company;! &bold; &#38;company;</doc>"#;
+    let mut xot = Xot::new();
+    let doc = xot.parse(xml).unwrap();
+    let element = xot.document_element(doc).unwrap();
+    assert_eq!(
+        xot.to_string(element).unwrap(),
+        r#"<doc name="ACME Corp &amp; Sons">Hello ACME Corp! <b title="ACME Corp">bold</b> &amp;company;</doc>"#
+    );
+}
+
+#[test]
+fn test_parse_expand_entities_errors() {
+    let mut xot = Xot::new();
+    let err = xot
+        .parse(r#"<!DOCTYPE doc [<!ENTITY a "&b;"><!ENTITY b "&a;">]><doc>&a;</doc>"#)
+        .unwrap_err();
+    assert!(matches!(err, ParseError::RecursiveEntity(name, _) if name == "a"));
+    let err = xot
+        .parse(r#"<!DOCTYPE doc [<!ENTITY a "x&a;">]><doc a="&a;"/>"#)
+        .unwrap_err();
+    assert!(matches!(err, ParseError::RecursiveEntity(name, _) if name == "a"));
+    let err = xot
+        .parse(r#"<!DOCTYPE doc [<!ENTITY a "<b>">]><doc>&a;</doc>"#)
+        .unwrap_err();
+    assert!(matches!(err, ParseError::UnclosedTag(span) if span.range() == (39..42)));
+    let err = xot
+        .parse(r#"<!DOCTYPE doc [<!ENTITY a "<b/>">]><doc a="&a;"/>"#)
+        .unwrap_err();
+    assert!(matches!(err, ParseError::InvalidEntity(name, _) if name == "a"));
+    let err = xot
+        .parse(r#"<!DOCTYPE doc [<!ENTITY a "&unknown;">]><doc>&a;</doc>"#)
+        .unwrap_err();
+    assert!(matches!(err, ParseError::InvalidEntity(name, _) if name == "unknown"));
+}
+
+#[test]
+fn test_parse_disable_entity_expansion() {
+    let xml = r#"<!DOCTYPE doc [<!ENTITY company "ACME Corp">]><doc>&company;</doc>"#;
+    let mut xot = Xot::new();
+    let options = xot::ParseOptions {
+        disable_entity_expansion: true,
+        ..Default::default()
+    };
+    let err = xot.parse_with_options(xml, &options).unwrap_err();
+    assert!(
+        matches!(err, ParseError::InvalidEntity(name, span) if name == "company" && span.range() == (51..60))
+    );
+}
+
+fn catalog_options() -> xot::ParseOptions {
+    xot::ParseOptions {
+        entity_resolver: Some(std::sync::Arc::new(
+            |public_id: Option<&str>, system_id: &str| match (public_id, system_id) {
+                (None, "chapter.xml") => Some(
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?><p>&company; chapter</p>"
+                        .to_string(),
+                ),
+                (Some("-//Example//DTD Doc//EN"), _) => Some(
+                    r#"<!ENTITY company "External Corp"><!ENTITY motto "Quality first">"#
+                        .to_string(),
+                ),
+                _ => None,
+            },
+        )),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_parse_entity_resolver() {
+    let xml = r#"<!DOCTYPE doc PUBLIC "-//Example//DTD Doc//EN" "doc.dtd" [
+  <!ENTITY company "ACME Corp">
+  <!ENTITY chapter SYSTEM "chapter.xml">
+]>
+<doc motto="&motto;">&chapter;</doc>"#;
+    let mut xot = Xot::new();
+    // by default nothing external is loaded
+    let err = xot.parse(xml).unwrap_err();
+    assert!(matches!(err, ParseError::InvalidEntity(name, _) if name == "motto"));
+
+    let doc = xot.parse_with_options(xml, &catalog_options()).unwrap();
+    let element = xot.document_element(doc).unwrap();
+    assert_eq!(
+        xot.to_string(element).unwrap(),
+        r#"<doc motto="Quality first"><p>ACME Corp chapter</p></doc>"#
+    );
+    // the doctype only has the declarations in the internal subset
+    assert_eq!(xot.doctype(doc).unwrap().entities.len(), 2);
+}
+
+#[test]
+fn test_parse_entity_resolver_errors() {
+    let mut xot = Xot::new();
+    let options = catalog_options();
+    // external entities aren't allowed in attribute values
+    let err = xot
+        .parse_with_options(
+            r#"<!DOCTYPE doc [<!ENTITY chapter SYSTEM "chapter.xml">]><doc a="&chapter;"/>"#,
+            &options,
+        )
+        .unwrap_err();
+    assert!(matches!(err, ParseError::InvalidEntity(name, _) if name == "chapter"));
+    // the resolver doesn't know this one
+    let err = xot
+        .parse_with_options(
+            r#"<!DOCTYPE doc [<!ENTITY missing SYSTEM "missing.xml">]><doc>&missing;</doc>"#,
+            &options,
+        )
+        .unwrap_err();
+    assert!(
+        matches!(err, ParseError::InvalidEntity(name, span) if name == "missing" && span.range() == (60..69))
+    );
+    // unparsed entities can't be referred to
+    let err = xot
+        .parse_with_options(
+            r#"<!DOCTYPE doc [<!ENTITY logo SYSTEM "chapter.xml" NDATA gif>]><doc>&logo;</doc>"#,
+            &options,
+        )
+        .unwrap_err();
+    assert!(matches!(err, ParseError::InvalidEntity(name, _) if name == "logo"));
+}
+
+#[test]
+fn test_parse_billion_laughs() {
+    let xml = r#"<!DOCTYPE lolz [
+  <!ENTITY lol "lol">
+  <!ENTITY lol1 "&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;">
+  <!ENTITY lol2 "&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;">
+  <!ENTITY lol3 "&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;">
+  <!ENTITY lol4 "&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;">
+  <!ENTITY lol5 "&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;&lol4;">
+]>
+<lolz a="&lol5;">&lol5;</lolz>"#;
+    let mut xot = Xot::new();
+    let err = xot.parse(xml).unwrap_err();
+    assert!(
+        matches!(err, ParseError::EntityLimitExceeded(name, span) if name == "lol" && span.range() == (441..447))
+    );
+}
+
+#[test]
+fn test_parse_entity_limits() {
+    let xml = r#"<!DOCTYPE doc [
+  <!ENTITY a "aaaaaaaaaa">
+  <!ENTITY b "&a;">
+  <!ENTITY c "&b;">
+]>
+<doc>&c;&a;</doc>"#;
+    let mut xot = Xot::new();
+    let parse = |xot: &mut Xot, entity_limits| {
+        let options = xot::ParseOptions {
+            entity_limits,
+            ..Default::default()
+        };
+        xot.parse_with_options(xml, &options)
+    };
+    let limits = xot::EntityLimits {
+        max_depth: 3,
+        max_expansions: 4,
+        max_expanded_size: 26,
+    };
+    let doc = parse(&mut xot, limits).unwrap();
+    assert_eq!(
+        xot.text_content_str(xot.document_element(doc).unwrap()),
+        Some("a".repeat(20).as_str())
+    );
+    for limits in [
+        xot::EntityLimits {
+            max_depth: 2,
+            ..limits
+        },
+        xot::EntityLimits {
+            max_expansions: 3,
+            ..limits
+        },
+        xot::EntityLimits {
+            max_expanded_size: 25,
+            ..limits
+        },
+    ] {
+        let err = parse(&mut xot, limits).unwrap_err();
+        assert!(matches!(err, ParseError::EntityLimitExceeded(..)));
+    }
+}
+
+#[test]
+fn test_parse_lenient() {
+    let mut xot = Xot::new();
+    let (doc, errors) = xot.parse_lenient("<doc><p a=1 b=x/><q>Fish & chips<r>");
+    assert_eq!(
+        xot.to_string(doc).unwrap(),
+        r#"<doc><p a="1" b="x"/><q>Fish &amp; chips<r/></q></doc>"#
+    );
+    assert_eq!(errors.len(), 6);
+    assert!(matches!(errors[0], ParseError::XmlParser(_, 5)));
+    assert!(matches!(errors[1], ParseError::XmlParser(_, 5)));
+    assert!(matches!(errors[2], ParseError::UnclosedEntity(_, 25)));
+    assert!(matches!(errors[3], ParseError::UnclosedTag(span) if span.range() == (33..34)));
+    assert!(matches!(errors[4], ParseError::UnclosedTag(span) if span.range() == (18..19)));
+    assert!(matches!(errors[5], ParseError::UnclosedTag(span) if span.range() == (1..4)));
+}
+
+#[test]
+fn test_parse_lenient_undeclared_prefix() {
+    let mut xot = Xot::new();
+    let (doc, errors) = xot.parse_lenient(r#"<x:doc y:a="1"><x:p/></x:doc>"#);
+    let doc_el = xot.document_element(doc).unwrap();
+    let x = xot.namespace("urn:xot:undeclared:x").unwrap();
+    assert_eq!(
+        xot.name_ns_str(xot.node_name(doc_el).unwrap()),
+        ("doc", "urn:xot:undeclared:x")
+    );
+    assert_eq!(
+        xot.namespace_for_prefix(doc_el, xot.prefix("x").unwrap()),
+        Some(x)
+    );
+    assert_eq!(errors.len(), 2);
+    assert!(matches!(&errors[0], ParseError::UnknownPrefix(prefix, _) if prefix == "x"));
+    assert!(matches!(&errors[1], ParseError::UnknownPrefix(prefix, _) if prefix == "y"));
+}
+
+#[test]
+fn test_parse_lenient_mismatched_close_tag() {
+    let mut xot = Xot::new();
+    let (doc, errors) = xot.parse_lenient("<a><b><c></b>text</d></a>");
+    assert_eq!(xot.to_string(doc).unwrap(), "<a><b><c/></b>text</a>");
+    assert_eq!(errors.len(), 2);
+    assert!(matches!(&errors[0], ParseError::InvalidCloseTag(_, name, _) if name == "b"));
+    assert!(matches!(&errors[1], ParseError::InvalidCloseTag(_, name, _) if name == "d"));
+}
+
+#[test]
+fn test_parse_lenient_top_level() {
+    let mut xot = Xot::new();
+    let (doc, errors) = xot.parse_lenient("text<a/><b/>");
+    assert_eq!(xot.to_string(doc).unwrap(), "text<a/><b/>");
+    assert_eq!(errors.len(), 2);
+    assert!(matches!(errors[0], ParseError::TextAtTopLevel(span) if span.range() == (0..4)));
+    assert!(
+        matches!(errors[1], ParseError::MultipleElementsAtTopLevel(span) if span.range() == (9..10))
+    );
+}
+
+#[test]
+fn test_parse_lenient_well_formed() {
+    let xml = r#"<?xml version="1.0"?>
+<!DOCTYPE doc [<!ENTITY e "entity">]>
+<!-- comment -->
+<doc xmlns:x="http://example.com"><x:p a="&e;">&e; &amp; <![CDATA[<]]></x:p></doc>"#;
+    let mut xot = Xot::new();
+    let expected = xot.parse(xml).unwrap();
+    let (doc, errors) = xot.parse_lenient(xml);
+    assert!(errors.is_empty());
+    assert_eq!(
+        xot.to_string(doc).unwrap(),
+        xot.to_string(expected).unwrap()
+    );
+    assert_eq!(xot.doctype(doc), xot.doctype(expected));
+}
+
+#[test]
+fn test_parse_fragment_stray_close_tag() {
+    let mut xot = Xot::new();
+    let err = xot.parse_fragment("</b>x").unwrap_err();
+    assert!(matches!(err, ParseError::InvalidCloseTag(..)));
+}
+
+#[test]
+fn test_parse_collect_errors() {
+    let mut xot = Xot::new();
+    let (root, errors) = xot.parse_collect_errors("<doc><a b='1' b='2'>&unknown;</a><x:c/></d><e>");
+    assert!(root.is_none());
+    assert_eq!(errors.len(), 6);
+    assert!(matches!(errors[0], ParseError::DuplicateAttribute(..)));
+    assert!(matches!(errors[1], ParseError::InvalidEntity(..)));
+    assert!(matches!(errors[2], ParseError::UnknownPrefix(..)));
+    assert!(matches!(errors[3], ParseError::InvalidCloseTag(..)));
+    assert!(matches!(errors[4], ParseError::UnclosedTag(span) if span.range() == (44..45)));
+    assert!(matches!(errors[5], ParseError::UnclosedTag(span) if span.range() == (1..4)));
+}
+
+#[test]
+fn test_parse_collect_errors_no_errors() {
+    let mut xot = Xot::new();
+    let (root, errors) = xot.parse_collect_errors("<doc><a/></doc>");
+    assert!(errors.is_empty());
+    assert_eq!(xot.to_string(root.unwrap()).unwrap(), "<doc><a/></doc>");
+}
+
+#[test]
+fn test_parse_fragment_with_context() {
+    let mut xot = Xot::new();
+    let root = xot
+        .parse_fragment_with_context(
+            r#"text<x:a b="1" x:c="2"><item/></x:a><plain xmlns=""/><y:d xmlns:y="http://example.com/y"/>"#,
+            &[
+                ("x", "http://example.com/x"),
+                ("", "http://example.com/default"),
+                ("unused", "http://example.com/unused"),
+            ],
+        )
+        .unwrap();
+    assert_eq!(
+        xot.to_string(root).unwrap(),
+        r#"text<x:a xmlns:x="http://example.com/x" xmlns="http://example.com/default" b="1" x:c="2"><item/></x:a><plain xmlns=""/><y:d xmlns:y="http://example.com/y"/>"#
+    );
+    let a = xot.children(root).nth(1).unwrap();
+    let item = xot.first_child(a).unwrap();
+    assert_eq!(
+        xot.name_ns_str(xot.node_name(item).unwrap()),
+        ("item", "http://example.com/default")
+    );
+}
+
+#[test]
+fn test_parse_fragment_with_context_unknown_prefix() {
+    let mut xot = Xot::new();
+    let err = xot
+        .parse_fragment_with_context("<y:a/>", &[("x", "http://example.com/x")])
+        .unwrap_err();
+    assert!(matches!(err, ParseError::UnknownPrefix(prefix, _) if prefix == "y"));
+}
+
+#[test]
+fn test_parse_preserve_cdata() {
+    let xml = "<doc>a<![CDATA[<b>]]><![CDATA[x]]>c</doc>";
+    let mut xot = Xot::new();
+    let options = xot::ParseOptions {
+        preserve_cdata: true,
+        ..Default::default()
+    };
+    let (root, span_info) = xot.parse_with_span_info(xml).unwrap();
+    let doc_el = xot.document_element(root).unwrap();
+    assert_eq!(xot.children(doc_el).count(), 1);
+    assert_eq!(xot.text_content_str(doc_el), Some("a<b>xc"));
+    assert!(span_info
+        .get(SpanInfoKey::Text(xot.first_child(doc_el).unwrap()))
+        .is_some());
+
+    let root = xot.parse_with_options(xml, &options).unwrap();
+    let doc_el = xot.document_element(root).unwrap();
+    let children = xot.children(doc_el).collect::<Vec<_>>();
+    assert_eq!(children.len(), 4);
+    assert_eq!(xot.text_str(children[0]), Some("a"));
+    assert_eq!(xot.cdata_str(children[1]), Some("<b>"));
+    assert_eq!(xot.cdata_str(children[2]), Some("x"));
+    assert_eq!(xot.text_str(children[3]), Some("c"));
+    assert_eq!(xot.value_type(children[1]), xot::ValueType::Cdata);
+    assert_eq!(xot.string_value(doc_el), "a<b>xc");
+    assert_eq!(xot.to_string(root).unwrap(), xml);
+}
+
+#[test]
+fn test_parse_preserve_entity_references() {
+    let xml = r#"<!DOCTYPE doc [<!ENTITY c1 "Chapter">]>
+<doc a="&c1;">&c1; &amp; &undeclared;<p/>&#65;</doc>"#;
+    let mut xot = Xot::new();
+    assert!(matches!(
+        xot.parse(xml),
+        Err(ParseError::InvalidEntity(name, _)) if name == "undeclared"
+    ));
+    let options = xot::ParseOptions {
+        preserve_entity_references: true,
+        ..Default::default()
+    };
+    let root = xot.parse_with_options(xml, &options).unwrap();
+    let doc_el = xot.document_element(root).unwrap();
+    let children = xot.children(doc_el).collect::<Vec<_>>();
+    assert_eq!(children.len(), 5);
+    assert_eq!(
+        xot.entity_reference(children[0]).map(|e| e.name()),
+        Some("c1")
+    );
+    assert_eq!(xot.text_str(children[1]), Some(" & "));
+    assert_eq!(
+        xot.entity_reference(children[2]).map(|e| e.name()),
+        Some("undeclared")
+    );
+    assert_eq!(xot.text_str(children[4]), Some("A"));
+    assert_eq!(
+        xot.to_string(doc_el).unwrap(),
+        r#"<doc a="Chapter">&c1; &amp; &undeclared;<p/>A</doc>"#
+    );
+}
+
+#[test]
+fn test_parse_strip_whitespace() {
+    let xml = "<doc>\n  <a> </a>\n  <b> x </b>\n  <pre>\n  <c/> </pre>\n  <p><i>a</i> <i>b</i></p> <![CDATA[ ]]>\n</doc>";
+    let mut xot = Xot::new();
+    let pre = xot.add_name("pre");
+    let options = xot::ParseOptions {
+        strip_whitespace: true,
+        preserve_whitespace_elements: vec![pre],
+        ..Default::default()
+    };
+    let root = xot.parse_with_options(xml, &options).unwrap();
+    assert_eq!(
+        xot.to_string(root).unwrap(),
+        "<doc><a/><b> x </b><pre>\n  <c/> </pre><p><i>a</i><i>b</i></p></doc>"
+    );
+}
+
+#[test]
+fn test_parse_strip_whitespace_xml_space() {
+    let xml = r#"<doc> <a xml:space="preserve"> <b> <c/> </b> <d xml:space="default"> <e/> </d> </a> </doc>"#;
+    let mut xot = Xot::new();
+    let options = xot::ParseOptions {
+        strip_whitespace: true,
+        ..Default::default()
+    };
+    let root = xot.parse_with_options(xml, &options).unwrap();
+    assert_eq!(
+        xot.to_string(root).unwrap(),
+        r#"<doc><a xml:space="preserve"> <b> <c/> </b> <d xml:space="default"><e/></d> </a></doc>"#
+    );
+}
+
+#[test]
+fn test_parse_without_comments_and_pis() {
+    let xml = "<!--before--><?pi before?><doc>a<!--x-->b<?pi x?>c<p><!--y--></p></doc><!--after-->";
+    let mut xot = Xot::new();
+    let options = xot::ParseOptions {
+        keep_comments: false,
+        keep_pis: false,
+        ..Default::default()
+    };
+    let root = xot.parse_with_options(xml, &options).unwrap();
+    assert_eq!(xot.to_string(root).unwrap(), "<doc>abc<p/></doc>");
+    let doc = xot.document_element(root).unwrap();
+    assert_eq!(xot.children(doc).count(), 2);
+
+    let options = xot::ParseOptions {
+        keep_pis: false,
+        ..Default::default()
+    };
+    let root = xot.parse_with_options(xml, &options).unwrap();
+    assert_eq!(
+        xot.to_string(root).unwrap(),
+        "<!--before--><doc>a<!--x-->bc<p><!--y--></p></doc><!--after-->"
+    );
+}
+
+#[test]
+fn test_parse_keeping_line_endings() {
+    let xml = "<doc a='x\r\ny'>a\r\nb\rc&amp;</doc>";
+    let mut xot = Xot::new();
+    let options = xot::ParseOptions {
+        normalize_line_endings: false,
+        ..Default::default()
+    };
+    let root = xot.parse_with_options(xml, &options).unwrap();
+    let doc = xot.document_element(root).unwrap();
+    assert_eq!(xot.text_content_str(doc), Some("a\r\nb\rc&"));
+    // attribute values are still normalized
+    assert_eq!(xot.get_attribute(doc, xot.name("a").unwrap()), Some("x y"));
+
+    let root = xot.parse(xml).unwrap();
+    let doc = xot.document_element(root).unwrap();
+    assert_eq!(xot.text_content_str(doc), Some("a\nb\nc&"));
+}
+
+#[test]
+fn test_parse_bom() {
+    let mut xot = Xot::new();
+    let root = xot.parse("\u{FEFF}<doc/>").unwrap();
+    assert!(xot.has_bom(root));
+    let root = xot.parse_bytes(b"\xFF\xFE<\0d\0o\0c\0/\0>\0").unwrap();
+    assert!(xot.has_bom(root));
+    assert_eq!(xot.encoding(root), Some("UTF-16LE"));
+    let root = xot.parse_read(&b"\xEF\xBB\xBF<doc/>"[..]).unwrap();
+    assert!(xot.has_bom(root));
+    let root = xot.parse_bytes(b"<doc/>").unwrap();
+    assert!(!xot.has_bom(root));
+
+    let options = xot::ParseOptions {
+        reject_bom: true,
+        ..Default::default()
+    };
+    let err = xot
+        .parse_with_options("\u{FEFF}<doc/>", &options)
+        .unwrap_err();
+    assert!(matches!(err, ParseError::UnexpectedByteOrderMark(span) if span == Span::new(0, 3)));
+    let err = xot
+        .parse_bytes_with_options(b"\xFE\xFF\0<\0d\0o\0c\0/\0>", &options)
+        .unwrap_err();
+    assert!(matches!(err, ParseError::UnexpectedByteOrderMark(span) if span == Span::new(0, 2)));
+    assert!(xot.parse_bytes_with_options(b"<doc/>", &options).is_ok());
+}
+
+#[test]
+fn test_parse_keeps_xml_declaration() {
+    let mut xot = Xot::new();
+    let root = xot.parse("<doc/>").unwrap();
+    assert_eq!(xot.xml_declaration(root), None);
+    let root = xot.parse(r#"<?xml version="1.0"?><doc/>"#).unwrap();
+    assert_eq!(
+        xot.xml_declaration(root),
+        Some(&output::xml::Declaration::default())
+    );
+    let options = ParseOptions {
+        xml_1_1: true,
+        ..Default::default()
+    };
+    let root = xot
+        .parse_with_options(
+            r#"<?xml version="1.1" encoding="utf-8" standalone="no"?><doc/>"#,
+            &options,
+        )
+        .unwrap();
+    assert_eq!(
+        xot.xml_declaration(root),
+        Some(&output::xml::Declaration {
+            encoding: Some("utf-8".to_string()),
+            standalone: Some(false),
+            version: output::xml::Version::V1_1,
+        })
+    );
+}
+
+#[test]
+fn test_parse_replace_invalid_characters() {
+    let mut xot = Xot::new();
+    let options = xot::ParseOptions {
+        replace_invalid_characters: true,
+        ..Default::default()
+    };
+    let root = xot
+        .parse_with_options("<doc a='\u{1}'>x\u{2}\u{FFFF}y<!--\u{3}--></doc>", &options)
+        .unwrap();
+    assert_eq!(xot.replaced_characters(root), &[8, 12, 13, 21]);
+    assert_eq!(
+        xot.to_string(root).unwrap(),
+        "<doc a=\"\u{FFFD}\">x\u{FFFD}\u{FFFD}y<!--\u{FFFD}--></doc>"
+    );
+
+    // spans of errors refer to the original text
+    let err = xot
+        .parse_with_options("<doc>\u{1}</dox>", &options)
+        .unwrap_err();
+    assert_eq!(err.span(), xot.parse("<doc>x</dox>").unwrap_err().span());
+    let err = xot
+        .parse_with_options("<doc>\u{1}\u{FFFF}\u{2}</dox>", &options)
+        .unwrap_err();
+    assert_eq!(
+        err.span(),
+        xot.parse("<doc>x\u{FFFD}y</dox>").unwrap_err().span()
+    );
+
+    // data that can't be decoded is replaced too
+    let root = xot
+        .parse_bytes_with_options(b"<doc>\xFF\x01</doc>", &options)
+        .unwrap();
+    assert_eq!(xot.replaced_characters(root), &[5, 8]);
+    let doc = xot.document_element(root).unwrap();
+    assert_eq!(xot.text_content_str(doc), Some("\u{FFFD}\u{FFFD}"));
+
+    assert!(xot.parse("<doc>\u{1}</doc>").is_err());
+    let root = xot.parse_with_options("<doc/>", &options).unwrap();
+    assert!(xot.replaced_characters(root).is_empty());
+}
+
+#[test]
+fn test_parse_progress() {
+    use std::ops::ControlFlow;
+    use std::sync::{Arc, Mutex};
+
+    let xml = "<doc a='1'>text<p/></doc>";
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let handler_reports = reports.clone();
+    let options = xot::ParseOptions {
+        progress: Some(Arc::new(move |position: usize, nodes: usize| {
+            handler_reports.lock().unwrap().push((position, nodes));
+            ControlFlow::Continue(())
+        })),
+        ..Default::default()
+    };
+    let mut xot = Xot::new();
+    xot.parse_with_options(xml, &options).unwrap();
+    assert_eq!(
+        *reports.lock().unwrap(),
+        vec![(4, 1), (10, 1), (11, 3), (15, 4), (17, 4), (19, 5), (25, 5)]
+    );
+
+    let options = xot::ParseOptions {
+        progress: Some(Arc::new(|position: usize, _nodes: usize| {
+            if position > 15 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })),
+        ..Default::default()
+    };
+    let err = xot.parse_with_options(xml, &options).unwrap_err();
+    assert!(matches!(err, ParseError::Cancelled(span) if span == Span::new(17, 17)));
+}
+
+#[test]
+fn test_parse_limits() {
+    let mut xot = Xot::new();
+    let parse = |xot: &mut Xot, xml: &str, limits: xot::ParseLimits| {
+        let options = xot::ParseOptions {
+            limits,
+            ..Default::default()
+        };
+        xot.parse_with_options(xml, &options)
+    };
+
+    let limits = xot::ParseLimits {
+        max_depth: Some(2),
+        ..Default::default()
+    };
+    assert!(parse(&mut xot, "<a><b/><b/></a>", limits).is_ok());
+    let err = parse(&mut xot, "<a><b><c/></b></a>", limits).unwrap_err();
+    assert!(matches!(err, ParseError::DepthLimitExceeded(span) if span.range() == (7..8)));
+
+    let limits = xot::ParseLimits {
+        max_attributes: Some(2),
+        ..Default::default()
+    };
+    assert!(parse(
+        &mut xot,
+        r#"<a x="1" xmlns:p="p"><b y="2" z="3"/></a>"#,
+        limits
+    )
+    .is_ok());
+    let err = parse(&mut xot, r#"<a x="1" xmlns:p="p" y="2"/>"#, limits).unwrap_err();
+    assert!(matches!(err, ParseError::AttributeLimitExceeded(span) if span.range() == (21..26)));
+
+    let limits = xot::ParseLimits {
+        max_name_length: Some(7),
+        ..Default::default()
+    };
+    assert!(parse(&mut xot, r#"<a:b xmlns:a="a" abc="1"/>"#, limits).is_ok());
+    let err = parse(&mut xot, "<a:bcdefg xmlns:a='a'/>", limits).unwrap_err();
+    assert!(matches!(err, ParseError::NameLengthLimitExceeded(span) if span.range() == (1..9)));
+    let err = parse(&mut xot, "<a abcdefgh='1'/>", limits).unwrap_err();
+    assert!(matches!(err, ParseError::NameLengthLimitExceeded(span) if span.range() == (3..11)));
+    let err = parse(&mut xot, "<a><?abcdefgh?></a>", limits).unwrap_err();
+    assert!(matches!(err, ParseError::NameLengthLimitExceeded(span) if span.range() == (5..13)));
+
+    // the document node, a, x, text and b
+    let limits = xot::ParseLimits {
+        max_nodes: Some(5),
+        ..Default::default()
+    };
+    assert!(parse(&mut xot, "<a x='1'>text<b/></a>", limits).is_ok());
+    let err = parse(&mut xot, "<a x='1'>text<b/><!--c--></a>", limits).unwrap_err();
+    assert!(matches!(err, ParseError::NodeLimitExceeded(span) if span.range() == (17..25)));
+}
@@ -218,3 +218,732 @@ fn test_serialize_text_fragment() {
     let serialized = xot.to_string(fragment).unwrap();
     assert_eq!(serialized, r#"text"#);
 }
+
+#[test]
+fn test_html_string_element() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse(r#"<div><input type="checkbox" checked="checked"/><style>p > a {}</style></div>"#)
+        .unwrap();
+    let node = xot.document_element(doc).unwrap();
+    assert_eq!(
+        xot.html_string(node).unwrap(),
+        r#"<div><input type="checkbox" checked><style>p > a {}</style></div>"#
+    );
+}
+
+#[test]
+fn test_html_string_document() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse(r#"<html><body><p>A &amp; B</p></body></html>"#)
+        .unwrap();
+    assert_eq!(
+        xot.html_string(doc).unwrap(),
+        r#"<!DOCTYPE html><html><body><p>A &amp; B</p></body></html>"#
+    );
+}
+
+#[test]
+fn test_html_string_xhtml_namespace() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse(r#"<h:p xmlns:h="http://www.w3.org/1999/xhtml">A<h:br/>B</h:p>"#)
+        .unwrap();
+    let node = xot.document_element(doc).unwrap();
+    assert_eq!(
+        xot.html_string(node).unwrap(),
+        r#"<p xmlns="http://www.w3.org/1999/xhtml">A<br>B</p>"#
+    );
+}
+
+#[cfg(feature = "html5ever")]
+#[test]
+fn test_html_string_parsed_html() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse_html(r#"<p>A<br>B<script>if (a < b) {}</script></p>"#)
+        .unwrap();
+    assert_eq!(
+        xot.html_string(doc).unwrap(),
+        r#"<!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml"><head></head><body><p>A<br>B<script>if (a < b) {}</script></p></body></html>"#
+    );
+}
+
+#[test]
+fn test_html_string_doctype() {
+    let mut xot = Xot::new();
+    let doc = xot.parse(r#"<html><body/></html>"#).unwrap();
+    xot.set_doctype(
+        doc,
+        Some(xot::Doctype {
+            public_id: Some("-//W3C//DTD HTML 4.01//EN".to_string()),
+            system_id: Some("http://www.w3.org/TR/html4/strict.dtd".to_string()),
+            ..xot::Doctype::new("html")
+        }),
+    )
+    .unwrap();
+    assert_eq!(
+        xot.html_string(doc).unwrap(),
+        r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01//EN" "http://www.w3.org/TR/html4/strict.dtd"><html><body></body></html>"#
+    );
+}
+
+#[test]
+fn test_set_doctype_not_document() {
+    let mut xot = Xot::new();
+    let doc = xot.parse(r#"<html/>"#).unwrap();
+    let html = xot.document_element(doc).unwrap();
+    assert!(xot
+        .set_doctype(html, Some(xot::Doctype::new("html")))
+        .is_err());
+}
+
+#[cfg(feature = "html5ever")]
+#[test]
+fn test_html_doctype_roundtrip() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse_html(r#"<!DOCTYPE html><html><head></head><body></body></html>"#)
+        .unwrap();
+    assert_eq!(xot.doctype(doc), Some(&xot::Doctype::new("html")));
+    assert_eq!(
+        xot.html_string(doc).unwrap(),
+        r#"<!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml"><head></head><body></body></html>"#
+    );
+}
+
+#[cfg(feature = "html5ever")]
+#[test]
+fn test_xhtml_parsed_html_roundtrip() {
+    let html = r#"<!DOCTYPE html><title>T</title><p>A<br>B &amp; C<script>if (a < b) {}</script><input disabled></p>"#;
+    let mut xot = Xot::new();
+    let doc = xot.parse_html(html).unwrap();
+    let xhtml = xot
+        .serialize_xhtml_string(xot::output::xhtml::Parameters::default(), doc)
+        .unwrap();
+    assert_eq!(
+        xhtml,
+        r#"<!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml"><head><title>T</title></head><body><p>A<br />B &amp; C<script>/*<![CDATA[*/if (a < b) {}/*]]>*/</script><input disabled="" /></p></body></html>"#
+    );
+    // the result can be read by both XML and HTML parsers, which only
+    // differ in the script content
+    // (Xot's XML parser doesn't support a doctype)
+    let from_xml = xot
+        .parse(xhtml.strip_prefix("<!DOCTYPE html>").unwrap())
+        .unwrap();
+    let from_html = xot.parse_html(&xhtml).unwrap();
+    assert_eq!(
+        xot.html_string(from_xml).unwrap(),
+        xot.html_string(doc)
+            .unwrap()
+            .replace("if (a < b) {}", "/**/if (a < b) {}/**/")
+    );
+    assert_eq!(
+        xot.html_string(from_html).unwrap(),
+        xot.html_string(doc)
+            .unwrap()
+            .replace("if (a < b) {}", "/*<![CDATA[*/if (a < b) {}/*]]>*/")
+    );
+}
+
+#[test]
+fn test_serialize_html_write_insert_meta_charset() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse(r#"<html><head><title>T</title></head><body>€ é</body></html>"#)
+        .unwrap();
+    let mut buf = Vec::new();
+    xot.serialize_html_write(
+        xot::output::html::Parameters {
+            encoding: Some("latin1".to_string()),
+            meta_charset: true,
+            ..Default::default()
+        },
+        doc,
+        &mut buf,
+    )
+    .unwrap();
+    // the latin1 label stands for windows-1252, which has the euro sign
+    assert_eq!(
+        buf,
+        b"<!DOCTYPE html><html><head><meta charset=\"windows-1252\"><title>T</title></head><body>\x80 \xe9</body></html>"
+    );
+    // the tree itself is unchanged
+    assert_eq!(
+        xot.html_string(doc).unwrap(),
+        "<!DOCTYPE html><html><head><title>T</title></head><body>€ é</body></html>"
+    );
+}
+
+#[test]
+fn test_serialize_html_write_unmappable_character() {
+    let mut xot = Xot::new();
+    let doc = xot.parse(r#"<p>Ω</p>"#).unwrap();
+    let p = xot.document_element(doc).unwrap();
+    let mut buf = Vec::new();
+    xot.serialize_html_write(
+        xot::output::html::Parameters {
+            encoding: Some("iso-8859-1".to_string()),
+            ..Default::default()
+        },
+        p,
+        &mut buf,
+    )
+    .unwrap();
+    assert_eq!(buf, b"<p>&#937;</p>");
+}
+
+#[test]
+fn test_serialize_html_write_rewrite_http_equiv() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse(r#"<html><head><meta http-equiv="Content-Type" content="text/html; charset=iso-8859-1"/></head></html>"#)
+        .unwrap();
+    let mut buf = Vec::new();
+    xot.serialize_html_write(
+        xot::output::html::Parameters {
+            meta_charset: true,
+            ..Default::default()
+        },
+        doc,
+        &mut buf,
+    )
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        r#"<!DOCTYPE html><html><head><meta http-equiv="Content-Type" content="text/html; charset=UTF-8"></head></html>"#
+    );
+}
+
+#[test]
+fn test_serialize_html_write_unknown_encoding() {
+    let mut xot = Xot::new();
+    let doc = xot.parse(r#"<p/>"#).unwrap();
+    let mut buf = Vec::new();
+    assert!(matches!(
+        xot.serialize_html_write(
+            xot::output::html::Parameters {
+                encoding: Some("klingon".to_string()),
+                ..Default::default()
+            },
+            doc,
+            &mut buf,
+        ),
+        Err(xot::Error::UnsupportedEncoding(_))
+    ));
+}
+
+#[test]
+fn test_serialize_xml_bytes_with_encoding() {
+    let mut xot = Xot::new();
+    let doc = xot.parse(r#"<doc a="Ω">é€</doc>"#).unwrap();
+    let bytes = xot
+        .serialize_xml_bytes_with_encoding(
+            xot::output::xml::Parameters {
+                declaration: Some(xot::output::xml::Declaration {
+                    encoding: Some("UTF-8".to_string()),
+                    standalone: Some(true),
+                    ..Default::default()
+                }),
+                character_references: xot::output::xml::CharacterReferences::Decimal,
+                ..Default::default()
+            },
+            doc,
+            "latin1",
+        )
+        .unwrap();
+    assert_eq!(
+        bytes,
+        b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\" standalone=\"yes\"?>\n<doc a=\"&#937;\">\xe9&#8364;</doc>"
+    );
+    let parsed = xot.parse_bytes(&bytes).unwrap();
+    assert!(xot.deep_equal(doc, parsed));
+
+    let bytes = xot
+        .serialize_xml_bytes_with_encoding(Default::default(), doc, "utf-16be")
+        .unwrap();
+    assert_eq!(&bytes[..6], b"\xfe\xff\0<\0d");
+    let parsed = xot.parse_bytes(&bytes).unwrap();
+    assert!(xot.deep_equal(doc, parsed));
+}
+
+#[test]
+fn test_serialize_xml_bytes_with_stateful_encoding() {
+    let mut xot = Xot::new();
+    let doc = xot.parse("<doc>日本é</doc>").unwrap();
+    let bytes = xot
+        .serialize_xml_bytes_with_encoding(Default::default(), doc, "iso-2022-jp")
+        .unwrap();
+    // the encoder switches back to ASCII for the character reference
+    assert_eq!(
+        bytes,
+        b"<?xml version=\"1.0\" encoding=\"ISO-2022-JP\"?>\n<doc>\x1b$BF|K\\\x1b(B&#xE9;</doc>"
+    );
+}
+
+#[test]
+fn test_serialize_xml_bytes_with_encoding_ascii() {
+    let mut xot = Xot::new();
+    let doc = xot.parse("<doc>é€\u{7F}</doc>").unwrap();
+    // not windows-1252, which is what these labels mean on the web
+    for label in ["us-ascii", "ASCII"] {
+        let bytes = xot
+            .serialize_xml_bytes_with_encoding(Default::default(), doc, label)
+            .unwrap();
+        assert_eq!(
+            bytes,
+            b"<?xml version=\"1.0\" encoding=\"US-ASCII\"?>\n<doc>&#xE9;&#x20AC;\x7f</doc>"
+        );
+    }
+    let doc = xot.parse("<doc>\u{FF}\u{100}</doc>").unwrap();
+    let bytes = xot
+        .serialize_xml_bytes_with_encoding(Default::default(), doc, "iso-8859-1")
+        .unwrap();
+    assert_eq!(
+        bytes,
+        b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>\n<doc>\xff&#x100;</doc>"
+    );
+    let doc = xot.parse("<é/>").unwrap();
+    assert!(matches!(
+        xot.serialize_xml_bytes_with_encoding(Default::default(), doc, "us-ascii"),
+        Err(xot::Error::UnrepresentableCharacter('é'))
+    ));
+}
+
+#[test]
+fn test_serialize_xml_bytes_with_encoding_unrepresentable() {
+    use std::sync::Arc;
+    use xot::output::xml::{Parameters, UnrepresentableCharacters};
+
+    let mut xot = Xot::new();
+    let doc = xot.parse("<doc>€<!--€--><?pi €?><p>€</p></doc>").unwrap();
+    let p = xot.add_name("p");
+    // only text can have a character reference
+    assert!(matches!(
+        xot.serialize_xml_bytes_with_encoding(Default::default(), doc, "iso-8859-2"),
+        Err(xot::Error::UnrepresentableCharacter('€'))
+    ));
+    let bytes = xot
+        .serialize_xml_bytes_with_encoding(
+            Parameters {
+                unrepresentable_characters: UnrepresentableCharacters::Replace(Arc::new(
+                    |c: char| format!("U+{:04X}", c as u32),
+                )),
+                cdata_section_elements: vec![p],
+                ..Default::default()
+            },
+            doc,
+            "iso-8859-2",
+        )
+        .unwrap();
+    assert_eq!(
+        bytes,
+        b"<?xml version=\"1.0\" encoding=\"ISO-8859-2\"?>\n<doc>&#x20AC;<!--U+20AC--><?pi U+20AC?><p><![CDATA[U+20AC]]></p></doc>"
+    );
+}
+
+#[test]
+fn test_serialize_xml_bytes_with_encoding_unrepresentable_name() {
+    use std::sync::Arc;
+    use xot::output::xml::{Parameters, UnrepresentableCharacters};
+
+    let mut xot = Xot::new();
+    let replace = || Parameters {
+        unrepresentable_characters: UnrepresentableCharacters::Replace(Arc::new(|_c: char| {
+            "x".to_string()
+        })),
+        ..Default::default()
+    };
+    // a name can't have a character reference, and it isn't replaced
+    for xml in [
+        "<d中c/>",
+        r#"<doc a中="1"/>"#,
+        r#"<doc xmlns:中="urn:x"/>"#,
+        r#"<中:doc xmlns:中="urn:x"/>"#,
+        "<doc><?中 data?></doc>",
+    ] {
+        let doc = xot.parse(xml).unwrap();
+        assert!(matches!(
+            xot.serialize_xml_bytes_with_encoding(Default::default(), doc, "iso-8859-1"),
+            Err(xot::Error::UnrepresentableCharacter('中'))
+        ));
+        assert!(matches!(
+            xot.serialize_xml_bytes_with_encoding(replace(), doc, "iso-8859-1"),
+            Err(xot::Error::UnrepresentableCharacter('中'))
+        ));
+        // in UTF-16 all is well
+        assert!(xot
+            .serialize_xml_bytes_with_encoding(Default::default(), doc, "utf-16le")
+            .is_ok());
+    }
+    // names with characters the encoding has are fine
+    let doc = xot.parse(r#"<dé a="中"/>"#).unwrap();
+    assert_eq!(
+        xot.serialize_xml_bytes_with_encoding(Default::default(), doc, "iso-8859-1")
+            .unwrap(),
+        b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>\n<d\xe9 a=\"&#x4E2D;\"/>"
+    );
+}
+
+#[test]
+fn test_serialize_xml_bytes_with_encoding_utf_8() {
+    let mut xot = Xot::new();
+    let doc = xot.parse("<doc>é</doc>").unwrap();
+    let bytes = xot
+        .serialize_xml_bytes_with_encoding(
+            xot::output::xml::Parameters {
+                byte_order_mark: true,
+                ..Default::default()
+            },
+            doc,
+            "utf-8",
+        )
+        .unwrap();
+    assert_eq!(bytes, "\u{FEFF}<doc>é</doc>".as_bytes());
+    assert!(matches!(
+        xot.serialize_xml_bytes_with_encoding(Default::default(), doc, "klingon"),
+        Err(xot::Error::UnsupportedEncoding(_))
+    ));
+}
+
+#[test]
+fn test_serialize_xml_into() {
+    let mut xot = Xot::new();
+    let doc = xot.parse("<doc><a/>text</doc>").unwrap();
+    let doc_el = xot.document_element(doc).unwrap();
+    let a = xot.first_child(doc_el).unwrap();
+    let text = xot.next_sibling(a).unwrap();
+
+    let mut buf = String::new();
+    xot.serialize_xml_into(Default::default(), a, &mut buf)
+        .unwrap();
+    xot.serialize_xml_into(Default::default(), text, &mut buf)
+        .unwrap();
+    assert_eq!(buf, "<a/>text");
+
+    let mut bytes = b"<?xml version=\"1.0\"?>".to_vec();
+    xot.serialize_xml_bytes_into(Default::default(), doc, &mut bytes)
+        .unwrap();
+    assert_eq!(bytes, b"<?xml version=\"1.0\"?><doc><a/>text</doc>");
+
+    // if it fails, the buffer doesn't change
+    let ns = xot.add_namespace("urn:x");
+    let name = xot.add_name_ns("x", ns);
+    let x = xot.new_element(name);
+    xot.append(doc_el, x).unwrap();
+    assert!(matches!(
+        xot.serialize_xml_into(Default::default(), doc, &mut buf),
+        Err(xot::Error::MissingPrefix(_))
+    ));
+    assert_eq!(buf, "<a/>text");
+    assert!(xot
+        .serialize_xml_bytes_into(Default::default(), doc, &mut bytes)
+        .is_err());
+    assert_eq!(bytes, b"<?xml version=\"1.0\"?><doc><a/>text</doc>");
+}
+
+#[test]
+fn test_serialize_xml_split() {
+    use xot::output::xml::{Declaration, DocType, Parameters};
+
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse(r#"<records><record>a</record>text<record>b</record></records>"#)
+        .unwrap();
+    let parameters = Parameters {
+        declaration: Some(Declaration::default()),
+        doctype: Some(DocType::System {
+            system: "record.dtd".to_string(),
+        }),
+        ..Default::default()
+    };
+    let mut pieces = Vec::new();
+    let count = xot
+        .serialize_xml_split(
+            parameters.clone(),
+            doc,
+            |node| xot.is_element(node),
+            |i, node| {
+                assert!(xot.is_element(node));
+                pieces.push(i);
+                Ok(Vec::new())
+            },
+        )
+        .unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(pieces, [0, 1]);
+
+    let mut buffers = [Vec::new(), Vec::new()];
+    let mut writers = buffers.iter_mut();
+    xot.serialize_xml_split(
+        parameters.clone(),
+        doc,
+        |node| xot.is_element(node),
+        |_, _| Ok(writers.next().unwrap()),
+    )
+    .unwrap();
+    // each piece has the prolog
+    let prolog = "<?xml version=\"1.0\"?>\n<!DOCTYPE record SYSTEM \"record.dtd\">\n";
+    assert_eq!(
+        buffers[0],
+        format!("{}<record>a</record>", prolog).as_bytes()
+    );
+    assert_eq!(
+        buffers[1],
+        format!("{}<record>b</record>", prolog).as_bytes()
+    );
+
+    // errors from getting a writer are passed on
+    let result = xot.serialize_xml_split(
+        parameters,
+        doc,
+        |_| true,
+        |_, _| Err::<Vec<u8>, _>(std::io::Error::other("no room")),
+    );
+    assert!(matches!(result, Err(xot::Error::Io(_))));
+}
+
+#[test]
+fn test_serialize_xml_max_output_size() {
+    use xot::output::xml::Parameters;
+
+    let mut xot = Xot::new();
+    let doc = xot.parse("<doc><p>text</p><p>text</p></doc>").unwrap();
+    let limited = |max_output_size| Parameters {
+        max_output_size: Some(max_output_size),
+        ..Default::default()
+    };
+    // the output is 33 bytes
+    assert_eq!(
+        xot.serialize_xml_string(limited(33), doc).unwrap(),
+        "<doc><p>text</p><p>text</p></doc>"
+    );
+    assert!(matches!(
+        xot.serialize_xml_string(limited(32), doc),
+        Err(xot::Error::OutputTooLarge(32))
+    ));
+    // no more than the limit is written
+    let mut buf = Vec::new();
+    assert!(matches!(
+        xot.serialize_xml_write(limited(10), doc, &mut buf),
+        Err(xot::Error::OutputTooLarge(10))
+    ));
+    assert!(buf.len() <= 10);
+    assert!(matches!(
+        xot.serialize_xml_string_with_filter(
+            limited(10),
+            doc,
+            |_: &Xot, _: xot::Node, _: &xot::output::Output| {
+                vec![xot::output::FilteredOutput::Original]
+            }
+        ),
+        Err(xot::Error::OutputTooLarge(10))
+    ));
+}
+
+#[test]
+fn test_serialize_xml_max_output_size_with_encoding() {
+    use xot::output::xml::Parameters;
+
+    let mut xot = Xot::new();
+    let doc = xot.parse("<doc>ééé</doc>").unwrap();
+    let limited = |max_output_size| Parameters {
+        max_output_size: Some(max_output_size),
+        ..Default::default()
+    };
+    // in UTF-16, the output is 2 bytes for the byte order mark and 2 for
+    // each of the 14 characters
+    let mut buf = Vec::new();
+    xot.serialize_xml_write_with_encoding(limited(30), doc, "utf-16le", &mut buf)
+        .unwrap();
+    assert_eq!(buf.len(), 30);
+    let mut buf = Vec::new();
+    assert!(matches!(
+        xot.serialize_xml_write_with_encoding(limited(29), doc, "utf-16le", &mut buf),
+        Err(xot::Error::OutputTooLarge(29))
+    ));
+    assert!(buf.len() <= 29);
+    // in ISO-8859-1, each é is one byte, one less than in UTF-8
+    let bytes = xot
+        .serialize_xml_bytes_with_encoding(limited(58), doc, "iso-8859-1")
+        .unwrap();
+    assert_eq!(bytes.len(), 58);
+    assert!(matches!(
+        xot.serialize_xml_bytes_with_encoding(limited(57), doc, "iso-8859-1"),
+        Err(xot::Error::OutputTooLarge(57))
+    ));
+}
+
+#[test]
+fn test_serialize_fragment_with_context() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse(
+            r#"<doc xmlns="urn:d" xmlns:a="urn:a" xmlns:b="urn:b"><a:p b:x="1"><q/></a:p></doc>"#,
+        )
+        .unwrap();
+    let p = xot.first_child(xot.document_element(doc).unwrap()).unwrap();
+    let mut namespaces = xot::Prefixes::new();
+    namespaces.insert(xot.prefix("a").unwrap(), xot.namespace("urn:a").unwrap());
+    namespaces.insert(xot.empty_prefix(), xot.namespace("urn:d").unwrap());
+    // only the declaration that's not in the context is written
+    assert_eq!(
+        xot.serialize_fragment_with_context(Default::default(), p, &namespaces)
+            .unwrap(),
+        r#"<a:p xmlns:b="urn:b" b:x="1"><q/></a:p>"#
+    );
+
+    // a prefix of the context can be used without a declaration in the tree
+    let ns_a = xot.namespace("urn:a").unwrap();
+    let root_name = xot.add_name_ns("root", ns_a);
+    let child_name = xot.add_name("child");
+    let root = xot.new_element(root_name);
+    let child = xot.new_element(child_name);
+    xot.append(root, child).unwrap();
+    assert_eq!(
+        xot.serialize_fragment_with_context(Default::default(), root, &namespaces)
+            .unwrap(),
+        r#"<a:root><child/></a:root>"#
+    );
+    // a fragment without a namespace undeclares the default namespace of
+    // the context
+    assert_eq!(
+        xot.serialize_fragment_with_context(Default::default(), child, &namespaces)
+            .unwrap(),
+        r#"<child xmlns=""/>"#
+    );
+}
+
+#[test]
+fn test_serialize_xml_with_filter() {
+    use xot::output::{FilteredOutput, Output};
+    use xot::Node;
+
+    let mut xot = Xot::new();
+    let doc = xot.parse(r#"<doc><b>bold</b><i/></doc>"#).unwrap();
+    let b = xot.add_name("b");
+    let strong = xot.add_name("strong");
+    let class = xot.add_name("class");
+    let mut count = 0;
+    let xml = xot
+        .serialize_xml_string_with_filter(
+            xot::output::xml::Parameters {
+                indentation: Some(Default::default()),
+                ..Default::default()
+            },
+            doc,
+            |_xot: &Xot, _node: Node, output: &Output| match output {
+                Output::StartTagOpen(element) if element.name() == b => {
+                    count += 1;
+                    vec![
+                        FilteredOutput::StartTagOpen(strong),
+                        FilteredOutput::Attribute(class, format!("n{}", count)),
+                    ]
+                }
+                Output::EndTag(element) if element.name() == b => {
+                    vec![FilteredOutput::EndTag(strong)]
+                }
+                _ => vec![FilteredOutput::Original],
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        xml,
+        "<doc>\n  <strong class=\"n1\">bold</strong>\n  <i/>\n</doc>\n"
+    );
+    // the tree isn't changed
+    assert_eq!(xot.to_string(doc).unwrap(), "<doc><b>bold</b><i/></doc>");
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn test_serialize_xml_async() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut xot = Xot::new();
+    let xml = format!(
+        r#"<doc xmlns:x="urn:x">{}</doc>"#,
+        r#"<p x:a="1" b="2"><x:q>text &amp; more</x:q><!--c--></p>"#.repeat(5000)
+    );
+    let doc = xot.parse(&xml).unwrap();
+    let parameters = xot::output::xml::Parameters {
+        declaration: Some(Default::default()),
+        indentation: Some(Default::default()),
+        attribute_order: xot::output::xml::AttributeOrder::Name,
+        ..Default::default()
+    };
+    let mut buf = Vec::new();
+    let future = xot.serialize_xml_async(parameters.clone(), doc, &mut buf);
+    fn is_send(_: &impl Send) {}
+    is_send(&future);
+    runtime.block_on(future).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        xot.serialize_xml_string(parameters, doc).unwrap()
+    );
+}
+
+#[cfg(feature = "html5ever")]
+#[test]
+fn test_serialize_html_write_indentation() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse_html(
+            "<html><head><title>T</title></head><body>\n<div>\n  <p>Hello <b>bold</b></p>\n<pre>  a\n   b</pre><ul><li>x</li> <li>y</li></ul>\n<script>if (a < b) {\n}</script></div></body></html>",
+        )
+        .unwrap();
+    let mut buf = Vec::new();
+    xot.serialize_html_write(
+        xot::output::html::Parameters {
+            indentation: Some(Default::default()),
+            ..Default::default()
+        },
+        doc,
+        &mut buf,
+    )
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        r#"<!DOCTYPE html><html xmlns="http://www.w3.org/1999/xhtml">
+  <head>
+    <title>T</title>
+  </head>
+  <body>
+    <div>
+      <p>Hello <b>bold</b></p>
+      <pre>  a
+   b</pre>
+      <ul>
+        <li>x</li>
+        <li>y</li>
+      </ul>
+      <script>if (a < b) {
+}</script>
+    </div>
+  </body>
+</html>
+"#
+    );
+}
+
+#[test]
+fn test_serialize_cdata() {
+    let mut xot = Xot::new();
+    let doc = xot.parse("<p>a</p>").unwrap();
+    let p = xot.document_element(doc).unwrap();
+    let cdata = xot.new_cdata("<b> ]]> &");
+    xot.append(p, cdata).unwrap();
+    let text = xot.new_text("c");
+    xot.append(p, text).unwrap();
+    assert_eq!(xot.children(p).count(), 3);
+    assert_eq!(
+        xot.to_string(p).unwrap(),
+        "<p>a<![CDATA[<b> ]]]]><![CDATA[> &]]>c</p>"
+    );
+    assert_eq!(
+        xot.html_string(p).unwrap(),
+        "<p>a&lt;b> ]]> &amp;c</p>"
+    );
+}
@@ -1,4 +1,5 @@
-use xot::{NodeEdge, Xot};
+use xot::xmlname::{NameStrInfo, RefName};
+use xot::{NodeEdge, ParseOptions, SaxHandler, Xot};
 
 #[test]
 fn test_traverse() {
@@ -108,6 +109,28 @@ fn test_all_reverse_preorder2() {
     assert_eq!(result, vec![g, f, e, d, c, b, a, doc]);
 }
 
+#[test]
+fn test_reverse_descendants() {
+    let mut xot = Xot::new();
+    let doc = xot
+        .parse(r#"<a x="X"><b y="Y">text<c z="Z"/></b><!--d--><e/></a>"#)
+        .unwrap();
+    let a = xot.document_element(doc).unwrap();
+    let mut expected = xot.descendants(doc).collect::<Vec<_>>();
+    expected.reverse();
+    assert_eq!(xot.reverse_descendants(doc).collect::<Vec<_>>(), expected);
+
+    // an element with only attributes, and a sibling after it
+    let b = xot.first_child(a).unwrap();
+    let c = xot.last_child(b).unwrap();
+    assert_eq!(xot.reverse_descendants(c).collect::<Vec<_>>(), vec![c]);
+    let text = xot.first_child(b).unwrap();
+    assert_eq!(
+        xot.reverse_descendants(b).collect::<Vec<_>>(),
+        vec![c, text, b]
+    );
+}
+
 #[test]
 fn test_following() {
     let mut xot = xot::Xot::new();
@@ -201,3 +224,133 @@ fn test_all_following_attribute() {
     let siblings = xot.all_following(c).collect::<Vec<_>>();
     assert_eq!(siblings, vec![d, foo, e, f, g, h]);
 }
+
+#[test]
+fn test_preceding_attribute() {
+    let mut xot = xot::Xot::new();
+    let foo = xot.add_name("foo");
+    let root = xot
+        .parse(r#"<p><a/><b><c bar="BAR"/><d foo="FOO"><x/></d></b></p>"#)
+        .unwrap();
+    let p = xot.document_element(root).unwrap();
+    let a = xot.first_child(p).unwrap();
+    let b = xot.next_sibling(a).unwrap();
+    let c = xot.first_child(b).unwrap();
+    let d = xot.next_sibling(c).unwrap();
+    let foo = xot.attributes(d).get_node(foo).unwrap();
+    let x = xot.first_child(d).unwrap();
+    // the element an attribute belongs to is its ancestor
+    let preceding = xot.preceding(foo).collect::<Vec<_>>();
+    assert_eq!(preceding, vec![c, a]);
+    let preceding = xot.preceding(x).collect::<Vec<_>>();
+    assert_eq!(preceding, vec![c, a]);
+}
+
+#[test]
+fn test_all_preceding() {
+    let mut xot = xot::Xot::new();
+    let bar = xot.add_name("bar");
+    let foo = xot.add_name("foo");
+    let root = xot
+        .parse(r#"<p><a/><b><c bar="BAR"/><d foo="FOO"><x/></d></b></p>"#)
+        .unwrap();
+    let p = xot.document_element(root).unwrap();
+    let a = xot.first_child(p).unwrap();
+    let b = xot.next_sibling(a).unwrap();
+    let c = xot.first_child(b).unwrap();
+    let bar = xot.attributes(c).get_node(bar).unwrap();
+    let d = xot.next_sibling(c).unwrap();
+    let foo = xot.attributes(d).get_node(foo).unwrap();
+    let x = xot.first_child(d).unwrap();
+    let preceding = xot.all_preceding(x).collect::<Vec<_>>();
+    assert_eq!(preceding, vec![foo, bar, c, a]);
+}
+
+#[derive(Default)]
+struct Events(Vec<String>);
+
+impl SaxHandler for Events {
+    fn start_document(&mut self) {
+        self.0.push("start document".to_string());
+    }
+    fn end_document(&mut self) {
+        self.0.push("end document".to_string());
+    }
+    fn start_prefix_mapping(&mut self, prefix: &str, namespace: &str) {
+        self.0.push(format!("xmlns:{}={}", prefix, namespace));
+    }
+    fn end_prefix_mapping(&mut self, prefix: &str) {
+        self.0.push(format!("end xmlns:{}", prefix));
+    }
+    fn start_element(&mut self, name: &RefName, attributes: &[(RefName, &str)]) {
+        let attributes = attributes
+            .iter()
+            .map(|(name, value)| {
+                format!(" {{{}}}{}={}", name.namespace(), name.local_name(), value)
+            })
+            .collect::<String>();
+        self.0.push(format!("<{}{}>", name.full_name(), attributes));
+    }
+    fn end_element(&mut self, name: &RefName) {
+        self.0.push(format!("</{}>", name.full_name()));
+    }
+    fn characters(&mut self, text: &str) {
+        self.0.push(format!("text {}", text));
+    }
+    fn entity_reference(&mut self, name: &str) {
+        self.0.push(format!("&{};", name));
+    }
+    fn comment(&mut self, text: &str) {
+        self.0.push(format!("comment {}", text));
+    }
+    fn processing_instruction(&mut self, target: &RefName, data: Option<&str>) {
+        self.0
+            .push(format!("pi {} {:?}", target.local_name(), data));
+    }
+}
+
+#[test]
+fn test_emit() {
+    let mut xot = Xot::new();
+    let options = ParseOptions {
+        preserve_cdata: true,
+        preserve_entity_references: true,
+        ..Default::default()
+    };
+    let doc = xot
+        .parse_with_options(
+            r#"<?pi data?><x:a xmlns:x="urn:x" b="B" x:c="C"><!--c--><d>text<![CDATA[<cdata>]]>&e;</d></x:a>"#,
+            &options,
+        )
+        .unwrap();
+
+    let mut events = Events::default();
+    xot.emit(doc, &mut events).unwrap();
+    assert_eq!(
+        events.0,
+        [
+            "start document",
+            "pi pi Some(\"data\")",
+            "xmlns:x=urn:x",
+            "<x:a {}b=B {urn:x}c=C>",
+            "comment c",
+            "<d>",
+            "text text",
+            // a CDATA section is text by default
+            "text <cdata>",
+            "&e;",
+            "</d>",
+            "</x:a>",
+            "end xmlns:x",
+            "end document",
+        ]
+    );
+
+    // a subtree has no document events
+    let a = xot.document_element(doc).unwrap();
+    let d = xot.last_child(a).unwrap();
+    let mut events = Events::default();
+    xot.emit(d, &mut events).unwrap();
+    assert_eq!(events.0[0], "<d>");
+    assert_eq!(events.0.last().unwrap(), "</d>");
+}
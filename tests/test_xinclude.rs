@@ -0,0 +1,230 @@
+use std::sync::Arc;
+
+use xot::{Error, XIncludeOptions, Xot};
+
+fn options(resources: &'static [(&'static str, &'static str)]) -> XIncludeOptions {
+    XIncludeOptions {
+        loader: Some(Arc::new(move |uri: &str| {
+            resources
+                .iter()
+                .find(|(name, _)| *name == uri)
+                .map(|(_, content)| content.as_bytes().to_vec())
+        })),
+        fixup_base_uris: false,
+        ..Default::default()
+    }
+}
+
+fn process(xml: &str, options: &XIncludeOptions) -> Result<String, Error> {
+    let mut xot = Xot::new();
+    let root = xot.parse(xml)?;
+    xot.process_xinclude(root, options)?;
+    xot.to_string(root)
+}
+
+#[test]
+fn test_include_xml() {
+    let options = options(&[
+        (
+            "a.xml",
+            "<!--a--><a><xi:include xmlns:xi='http://www.w3.org/2001/XInclude' href='b.xml'/></a>",
+        ),
+        ("b.xml", "<b/>"),
+    ]);
+    assert_eq!(
+        process(
+            r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="a.xml"/></doc>"#,
+            &options
+        )
+        .unwrap(),
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><!--a--><a><b/></a></doc>"#
+    );
+}
+
+#[test]
+fn test_include_resolves_href() {
+    let options = XIncludeOptions {
+        base_uri: Some("http://example.com/doc/main.xml".to_string()),
+        ..options(&[
+            (
+                "http://example.com/doc/sub/a.xml",
+                "<a><xi:include xmlns:xi='http://www.w3.org/2001/XInclude' href='b.xml'/></a>",
+            ),
+            ("http://example.com/doc/sub/b.xml", "<b/>"),
+            ("http://example.com/c.xml", "<c/>"),
+        ])
+    };
+    assert_eq!(
+        process(
+            r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="sub/a.xml"/><p xml:base="/other/"><xi:include href="../c.xml"/></p></doc>"#,
+            &options
+        )
+        .unwrap(),
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><a><b/></a><p xml:base="/other/"><c/></p></doc>"#
+    );
+}
+
+#[test]
+fn test_include_fixup_base_uris() {
+    let options = XIncludeOptions {
+        fixup_base_uris: true,
+        ..options(&[
+            ("sub/a.xml", "<!--a--><a/>"),
+            ("b.xml", "<b xml:base='x/'/>"),
+        ])
+    };
+    assert_eq!(
+        process(
+            r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="sub/a.xml"/><xi:include href="b.xml"/></doc>"#,
+            &options
+        )
+        .unwrap(),
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><!--a--><a xml:base="sub/a.xml"/><b xml:base="x/"/></doc>"#
+    );
+}
+
+#[test]
+fn test_include_xpointer() {
+    let options = options(&[(
+        "a.xml",
+        r#"<a xmlns:p="http://example.com"><p:b xml:id="b"><c/><c>second</c></p:b></a>"#,
+    )]);
+    let include = |xpointer: &str| {
+        process(
+            &format!(
+                r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="a.xml" xpointer="{}"/></doc>"#,
+                xpointer
+            ),
+            &options,
+        )
+    };
+    assert_eq!(
+        include("b").unwrap(),
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><p:b xmlns:p="http://example.com" xml:id="b"><c/><c>second</c></p:b></doc>"#
+    );
+    assert_eq!(
+        include("element(b/2)").unwrap(),
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><c>second</c></doc>"#
+    );
+    assert_eq!(
+        include("element(/1/1/1)").unwrap(),
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><c/></doc>"#
+    );
+    // parts with schemes we don't support are skipped
+    assert_eq!(
+        include("xpointer(//c) element(/1/1/2)").unwrap(),
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><c>second</c></doc>"#
+    );
+    assert!(matches!(
+        include("element(/1/2)"),
+        Err(Error::XIncludeResource(uri)) if uri == "a.xml"
+    ));
+}
+
+#[test]
+fn test_include_same_document() {
+    assert_eq!(
+        process(
+            r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><p xml:id="p">text <xi:include xpointer="q"/></p><q xml:id="q">q</q><xi:include xpointer="p"/></doc>"#,
+            &XIncludeOptions::default()
+        )
+        .unwrap(),
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><p xml:id="p">text <q xml:id="q">q</q></p><q xml:id="q">q</q><p xml:id="p">text <q xml:id="q">q</q></p></doc>"#
+    );
+}
+
+#[test]
+fn test_include_text() {
+    let options = XIncludeOptions {
+        loader: Some(Arc::new(|uri: &str| match uri {
+            "a.txt" => Some(b"a < b".to_vec()),
+            "latin1.txt" => Some(b"caf\xe9".to_vec()),
+            _ => None,
+        })),
+        ..Default::default()
+    };
+    assert_eq!(
+        process(
+            r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude">[<xi:include href="a.txt" parse="text"/>] <xi:include href="latin1.txt" parse="text" encoding="iso-8859-1"/></doc>"#,
+            &options
+        )
+        .unwrap(),
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude">[a &lt; b] café</doc>"#
+    );
+}
+
+#[test]
+fn test_include_fallback() {
+    let options = options(&[("b.xml", "<b/>")]);
+    assert_eq!(
+        process(
+            r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="a.xml"><xi:fallback>no a, <xi:include href="b.xml"/></xi:fallback></xi:include></doc>"#,
+            &options
+        )
+        .unwrap(),
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude">no a, <b/></doc>"#
+    );
+    assert!(matches!(
+        process(
+            r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="a.xml"/></doc>"#,
+            &options
+        ),
+        Err(Error::XIncludeResource(uri)) if uri == "a.xml"
+    ));
+}
+
+#[test]
+fn test_include_loop() {
+    let options = options(&[
+        (
+            "a.xml",
+            "<a><xi:include xmlns:xi='http://www.w3.org/2001/XInclude' href='b.xml'/></a>",
+        ),
+        (
+            "b.xml",
+            "<b><xi:include xmlns:xi='http://www.w3.org/2001/XInclude' href='a.xml'/></b>",
+        ),
+    ]);
+    assert!(matches!(
+        process(
+            r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="a.xml"/></doc>"#,
+            &options
+        ),
+        Err(Error::XIncludeLoop(uri)) if uri == "a.xml"
+    ));
+    assert!(matches!(
+        process(
+            r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><p xml:id="p"><xi:include xpointer="p"/></p></doc>"#,
+            &options
+        ),
+        Err(Error::XIncludeLoop(uri)) if uri == "#p"
+    ));
+}
+
+#[test]
+fn test_include_invalid() {
+    let invalid = |xml: &str| {
+        matches!(
+            process(xml, &XIncludeOptions::default()),
+            Err(Error::InvalidXInclude(_))
+        )
+    };
+    assert!(invalid(
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="a.xml" parse="html"/></doc>"#
+    ));
+    assert!(invalid(
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include/></doc>"#
+    ));
+    assert!(invalid(
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="a.xml#b"/></doc>"#
+    ));
+    assert!(invalid(
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="a.txt" parse="text" xpointer="b"/></doc>"#
+    ));
+    assert!(invalid(
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="a.xml"><xi:fallback/><xi:fallback/></xi:include></doc>"#
+    ));
+    assert!(invalid(
+        r#"<doc xmlns:xi="http://www.w3.org/2001/XInclude"><xi:fallback/></doc>"#
+    ));
+}